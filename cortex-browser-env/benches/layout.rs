@@ -0,0 +1,45 @@
+//! Layout and paint throughput of the synthetic pages `bench` measures,
+//! and how fast their trees can be walked, under criterion: `cargo bench
+//! --bench layout`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use cortex_browser_env::bench;
+
+const WIDTH: f32 = 1280.0;
+const HEIGHT: f32 = 720.0;
+
+fn layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("layout");
+    for case in bench::default_cases() {
+        let mut prepared = case.prepare(WIDTH, HEIGHT);
+        group.throughput(Throughput::Elements(prepared.document.nodes.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(case.name()), |b| b.iter(|| prepared.layout()));
+    }
+    group.finish();
+}
+
+fn paint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("paint");
+    group.sample_size(20);
+    for case in bench::default_cases() {
+        let mut prepared = case.prepare(WIDTH, HEIGHT);
+        prepared.layout();
+        group.throughput(Throughput::Elements(prepared.document.nodes.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(case.name()), |b| b.iter(|| prepared.paint()));
+    }
+    group.finish();
+}
+
+fn walk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("walk");
+    for case in bench::default_cases() {
+        let prepared = case.prepare(WIDTH, HEIGHT);
+        group.throughput(Throughput::Elements(prepared.document.nodes.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(case.name()), |b| b.iter(|| prepared.walk()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, layout, paint, walk);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+// Capture a DOM dump from a real browser for the compatibility harness.
+//
+// Evaluate this script in the page (e.g. Playwright `page.evaluate`, or
+// paste it into the devtools console) after setting the viewport to the
+// size used for the screenshot. It returns the dump as a string; save it
+// next to the screenshot PNG and score the page against both with
+// `cortex-browser-env compat page.html --capture page.png --dom-dump page.dom.txt`.
+//
+// Format: one line per element in document order,
+//   depth tag x y width height
+(() => {
+  const lines = ['# cortex-dom-dump v1'];
+
+  const walk = (element, depth) => {
+    const rect = element.getBoundingClientRect();
+    const round = (n) => Math.round(n * 100) / 100;
+    lines.push([
+      depth,
+      element.tagName.toLowerCase(),
+      round(rect.left + window.scrollX),
+      round(rect.top + window.scrollY),
+      round(rect.width),
+      round(rect.height),
+    ].join(' '));
+
+    for (const child of element.children) {
+      walk(child, depth + 1);
+    }
+  };
+
+  walk(document.documentElement, 0);
+  return lines.join('\n') + '\n';
+})();
@@ -0,0 +1,525 @@
+//! Accessibility Tree
+//! Derives roles, accessible names and states from the DOM, and queries
+//! elements by role the way Testing Library's `getByRole` does
+
+use crate::dom::{Document, NodeData};
+use crate::error::BrowserError;
+use crate::forms::{has_attribute, input_type, is_checkable, is_checked, is_selected, tag_name};
+
+/// ARIA states exposed for an element
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct A11yStates {
+    pub disabled: bool,
+    /// `None` when the role is not checkable
+    pub checked: Option<bool>,
+    /// `None` when the element does not control an expandable region
+    pub expanded: Option<bool>,
+    pub selected: Option<bool>,
+    /// Heading level, for `heading` roles
+    pub level: Option<u32>,
+}
+
+/// One node of the accessibility tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessibleNode {
+    pub node_idx: usize,
+    pub role: String,
+    pub name: String,
+    pub states: A11yStates,
+    pub children: Vec<AccessibleNode>,
+}
+
+/// Roles whose accessible name may come from their text content
+const NAME_FROM_CONTENT: &[&str] = &[
+    "button", "cell", "checkbox", "columnheader", "gridcell", "heading", "link", "menuitem", "option",
+    "radio", "row", "rowheader", "switch", "tab", "tooltip", "treeitem",
+];
+
+fn attribute<'a>(document: &'a Document, idx: usize, name: &str) -> Option<&'a str> {
+    document.get_attribute(idx, name).map(String::as_str)
+}
+
+/// Collapse runs of whitespace and trim, as accessible names are compared
+pub(crate) fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// ============================================================================
+// ROLES
+// ============================================================================
+
+/// The role of an element: its first `role` token, else the implicit role of
+/// its tag. Generic containers such as `div` and `span` have none.
+pub fn role(document: &Document, idx: usize) -> Option<String> {
+    if let Some(explicit) = attribute(document, idx, "role").and_then(|r| r.split_whitespace().next()) {
+        return Some(explicit.to_ascii_lowercase());
+    }
+    implicit_role(document, idx).map(str::to_string)
+}
+
+fn implicit_role(document: &Document, idx: usize) -> Option<&'static str> {
+    let role = match tag_name(document, idx)? {
+        "a" | "area" if has_attribute(document, idx, "href") => "link",
+        "article" => "article",
+        "aside" => "complementary",
+        "button" => "button",
+        "dialog" => "dialog",
+        "fieldset" => "group",
+        "footer" => "contentinfo",
+        "form" => "form",
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+        "header" => "banner",
+        "hr" => "separator",
+        "img" if attribute(document, idx, "alt") == Some("") => "presentation",
+        "img" => "img",
+        "input" => input_role(document, idx)?,
+        "li" => "listitem",
+        "main" => "main",
+        "nav" => "navigation",
+        "ol" | "ul" => "list",
+        "option" => "option",
+        "progress" => "progressbar",
+        "section" => "region",
+        "select" => {
+            let size = attribute(document, idx, "size").and_then(|s| s.trim().parse::<u32>().ok());
+            if has_attribute(document, idx, "multiple") || size.is_some_and(|s| s > 1) {
+                "listbox"
+            } else {
+                "combobox"
+            }
+        }
+        "summary" => "button",
+        "table" => "table",
+        "td" => "cell",
+        "textarea" => "textbox",
+        "th" => "columnheader",
+        "tr" => "row",
+        _ => return None,
+    };
+    Some(role)
+}
+
+fn input_role(document: &Document, idx: usize) -> Option<&'static str> {
+    let role = match input_type(document, idx).as_str() {
+        "button" | "image" | "reset" | "submit" => "button",
+        "checkbox" => "checkbox",
+        "radio" => "radio",
+        "range" => "slider",
+        "number" => "spinbutton",
+        "search" => "searchbox",
+        "hidden" | "file" | "color" | "date" | "datetime-local" | "month" | "time" | "week" => return None,
+        _ => "textbox",
+    };
+    Some(role)
+}
+
+// ============================================================================
+// ACCESSIBLE NAMES
+// ============================================================================
+
+/// Compute the accessible name of an element
+///
+/// Follows the usual precedence: `aria-labelledby`, `aria-label`, native
+/// labelling (`<label>`, `alt`, button values), text content for roles that
+/// take their name from content, then `title` and `placeholder`.
+pub fn accessible_name(document: &Document, idx: usize) -> String {
+    if let Some(ids) = attribute(document, idx, "aria-labelledby") {
+        let name = ids
+            .split_whitespace()
+            .filter_map(|id| find_by_id(document, id))
+            .map(|label| name_from_content(document, label))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !name.trim().is_empty() {
+            return normalize(&name);
+        }
+    }
+    if let Some(label) = attribute(document, idx, "aria-label").filter(|l| !l.trim().is_empty()) {
+        return normalize(label);
+    }
+    if let Some(name) = native_name(document, idx).filter(|n| !n.is_empty()) {
+        return name;
+    }
+    if role(document, idx).is_some_and(|r| NAME_FROM_CONTENT.contains(&r.as_str())) {
+        let name = normalize(&name_from_content(document, idx));
+        if !name.is_empty() {
+            return name;
+        }
+    }
+    ["title", "placeholder"]
+        .iter()
+        .find_map(|attr| attribute(document, idx, attr).filter(|v| !v.trim().is_empty()))
+        .map(normalize)
+        .unwrap_or_default()
+}
+
+/// Names provided by host-language features rather than ARIA
+fn native_name(document: &Document, idx: usize) -> Option<String> {
+    match tag_name(document, idx)? {
+        "img" | "area" => attribute(document, idx, "alt").map(normalize),
+        "input" => match input_type(document, idx).as_str() {
+            "submit" => Some(attribute(document, idx, "value").map_or("Submit".to_string(), normalize)),
+            "reset" => Some(attribute(document, idx, "value").map_or("Reset".to_string(), normalize)),
+            "button" => attribute(document, idx, "value").map(normalize),
+            "image" => attribute(document, idx, "alt").map(normalize),
+            _ => label_text(document, idx),
+        },
+        "select" | "textarea" | "progress" => label_text(document, idx),
+        "fieldset" => child_with_tag(document, idx, "legend").map(|l| normalize(&name_from_content(document, l))),
+        "table" => child_with_tag(document, idx, "caption").map(|c| normalize(&name_from_content(document, c))),
+        _ => None,
+    }
+}
+
+/// Text of the `<label>` elements pointing at a control, by `for` or by
+/// wrapping it
+pub fn label_text(document: &Document, idx: usize) -> Option<String> {
+    let labels = labels_for(document, idx);
+    if labels.is_empty() {
+        return None;
+    }
+    let text = labels
+        .iter()
+        .map(|&label| name_from_content_excluding(document, label, Some(idx)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(normalize(&text))
+}
+
+/// `<label>` elements associated with a control
+pub fn labels_for(document: &Document, idx: usize) -> Vec<usize> {
+    let id = attribute(document, idx, "id");
+    let mut labels: Vec<usize> = (0..document.nodes.len())
+        .filter(|&label| {
+            tag_name(document, label) == Some("label") && id.is_some() && attribute(document, label, "for") == id
+        })
+        .collect();
+
+    let mut current = document.nodes[idx].parent;
+    while let Some(ancestor) = current {
+        if tag_name(document, ancestor) == Some("label")
+            && !has_attribute(document, ancestor, "for")
+            && !labels.contains(&ancestor)
+        {
+            labels.push(ancestor);
+        }
+        current = document.nodes[ancestor].parent;
+    }
+    labels
+}
+
+pub(crate) fn find_by_id(document: &Document, id: &str) -> Option<usize> {
+    (0..document.nodes.len()).find(|&idx| attribute(document, idx, "id") == Some(id))
+}
+
+fn child_with_tag(document: &Document, idx: usize, tag: &str) -> Option<usize> {
+    document.nodes[idx].children.iter().copied().find(|&child| tag_name(document, child) == Some(tag))
+}
+
+/// Text alternative built from an element's subtree
+fn name_from_content(document: &Document, idx: usize) -> String {
+    name_from_content_excluding(document, idx, None)
+}
+
+pub(crate) fn name_from_content_excluding(document: &Document, idx: usize, exclude: Option<usize>) -> String {
+    let mut parts = Vec::new();
+    for &child in &document.nodes[idx].children {
+        if Some(child) == exclude || is_hidden(document, child) {
+            continue;
+        }
+        match &document.nodes[child].data {
+            Some(NodeData::Text(text)) => parts.push(text.clone()),
+            Some(NodeData::Element(_)) => {
+                // Embedded content contributes its own name
+                let embedded = match tag_name(document, child) {
+                    Some("img") => attribute(document, child, "alt").map(str::to_string),
+                    _ => attribute(document, child, "aria-label").map(str::to_string),
+                };
+                parts.push(embedded.unwrap_or_else(|| name_from_content_excluding(document, child, exclude)));
+            }
+            None => {}
+        }
+    }
+    parts.join(" ")
+}
+
+// ============================================================================
+// STATES
+// ============================================================================
+
+/// Check whether an element is excluded from the accessibility tree by
+/// `hidden` or `aria-hidden="true"`
+pub fn is_hidden(document: &Document, idx: usize) -> bool {
+    has_attribute(document, idx, "hidden") || attribute(document, idx, "aria-hidden") == Some("true")
+}
+
+fn is_inaccessible(document: &Document, idx: usize) -> bool {
+    let mut current = Some(idx);
+    while let Some(node) = current {
+        if is_hidden(document, node) {
+            return true;
+        }
+        current = document.nodes[node].parent;
+    }
+    false
+}
+
+fn aria_bool(document: &Document, idx: usize, name: &str) -> Option<bool> {
+    match attribute(document, idx, name)?.trim() {
+        "true" | "mixed" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// ARIA states of an element, from native state or `aria-*` attributes
+pub fn states(document: &Document, idx: usize) -> A11yStates {
+    let role = role(document, idx);
+    let role = role.as_deref();
+
+    let natively_disabled = matches!(
+        tag_name(document, idx),
+        Some("button" | "input" | "select" | "textarea" | "option" | "fieldset")
+    ) && has_attribute(document, idx, "disabled");
+
+    let checked = if is_checkable(document, idx) {
+        Some(is_checked(document, idx))
+    } else if matches!(role, Some("checkbox" | "radio" | "switch" | "menuitemcheckbox" | "menuitemradio")) {
+        Some(aria_bool(document, idx, "aria-checked").unwrap_or(false))
+    } else {
+        None
+    };
+
+    let selected = if tag_name(document, idx) == Some("option") {
+        Some(is_selected(document, idx))
+    } else {
+        aria_bool(document, idx, "aria-selected")
+    };
+
+    let expanded = if tag_name(document, idx) == Some("summary") {
+        document.nodes[idx].parent.map(|details| has_attribute(document, details, "open"))
+    } else {
+        aria_bool(document, idx, "aria-expanded")
+    };
+
+    let level = if role == Some("heading") {
+        attribute(document, idx, "aria-level")
+            .and_then(|l| l.trim().parse().ok())
+            .or_else(|| tag_name(document, idx).and_then(|t| t.strip_prefix('h')).and_then(|n| n.parse().ok()))
+            .or(Some(2))
+    } else {
+        None
+    };
+
+    A11yStates {
+        disabled: natively_disabled || aria_bool(document, idx, "aria-disabled") == Some(true),
+        checked,
+        expanded,
+        selected,
+        level,
+    }
+}
+
+// ============================================================================
+// TREE
+// ============================================================================
+
+/// Build the accessibility tree of a document
+///
+/// The root has the `document` role. Elements without a role, and
+/// `presentation`/`none` elements, are flattened away so their children
+/// attach to the nearest ancestor with a role. Hidden subtrees are omitted.
+pub fn build_accessibility_tree(document: &Document) -> AccessibleNode {
+    let mut root = AccessibleNode {
+        node_idx: document.root,
+        role: "document".to_string(),
+        name: String::new(),
+        states: A11yStates::default(),
+        children: Vec::new(),
+    };
+    root.children = accessible_children(document, document.root);
+    root
+}
+
+fn accessible_children(document: &Document, idx: usize) -> Vec<AccessibleNode> {
+    let mut children = Vec::new();
+    for &child in &document.nodes[idx].children {
+        if tag_name(document, child).is_none() || is_hidden(document, child) {
+            continue;
+        }
+        match role(document, child).filter(|r| r != "presentation" && r != "none") {
+            Some(role) => children.push(AccessibleNode {
+                node_idx: child,
+                role,
+                name: accessible_name(document, child),
+                states: states(document, child),
+                children: accessible_children(document, child),
+            }),
+            None => children.extend(accessible_children(document, child)),
+        }
+    }
+    children
+}
+
+// ============================================================================
+// ROLE QUERIES
+// ============================================================================
+
+/// All accessible elements with `role`, optionally filtered by exact
+/// accessible name, in document order
+pub fn query_all_by_role(document: &Document, role_name: &str, name: Option<&str>) -> Vec<usize> {
+    let wanted_name = name.map(normalize);
+    (0..document.nodes.len())
+        .filter(|&idx| {
+            tag_name(document, idx).is_some()
+                && role(document, idx).as_deref() == Some(role_name)
+                && !is_inaccessible(document, idx)
+        })
+        .filter(|&idx| wanted_name.as_ref().is_none_or(|n| accessible_name(document, idx) == *n))
+        .collect()
+}
+
+fn describe(role_name: &str, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("role \"{}\" and name \"{}\"", role_name, name),
+        None => format!("role \"{}\"", role_name),
+    }
+}
+
+/// The single element with `role` (and name), or `None`; more than one match
+/// is an error, like Testing Library's `queryByRole`
+pub fn query_by_role(document: &Document, role_name: &str, name: Option<&str>) -> Result<Option<usize>, BrowserError> {
+    match query_all_by_role(document, role_name, name).as_slice() {
+        [] => Ok(None),
+        [idx] => Ok(Some(*idx)),
+        matches => Err(BrowserError::QueryError(format!(
+            "Found {} elements with {}",
+            matches.len(),
+            describe(role_name, name)
+        ))),
+    }
+}
+
+/// The single element with `role` (and name); no match or several matches
+/// is an error, like Testing Library's `getByRole`
+pub fn get_by_role(document: &Document, role_name: &str, name: Option<&str>) -> Result<usize, BrowserError> {
+    query_by_role(document, role_name, name)?
+        .ok_or_else(|| BrowserError::NotFoundError(format!("Unable to find an element with {}", describe(role_name, name))))
+}
+
+/// Every element with `role` (and name); no match is an error, like Testing
+/// Library's `getAllByRole`
+pub fn get_all_by_role(document: &Document, role_name: &str, name: Option<&str>) -> Result<Vec<usize>, BrowserError> {
+    let matches = query_all_by_role(document, role_name, name);
+    if matches.is_empty() {
+        return Err(BrowserError::NotFoundError(format!(
+            "Unable to find an element with {}",
+            describe(role_name, name)
+        )));
+    }
+    Ok(matches)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    fn by_id(doc: &Document, id: &str) -> usize {
+        find_by_id(doc, id).unwrap()
+    }
+
+    #[test]
+    fn test_implicit_and_explicit_roles() {
+        let doc = parse_html(
+            r#"<div><a id="home" href="/">Home</a><a id="anchor">x</a><div id="tab" role="tab button">T</div><input id="email" type="email"/><select id="pick"></select><img id="deco" alt=""/></div>"#,
+        );
+
+        assert_eq!(role(&doc, by_id(&doc, "home")).as_deref(), Some("link"));
+        assert_eq!(role(&doc, by_id(&doc, "anchor")), None, "An <a> without href is generic");
+        assert_eq!(role(&doc, by_id(&doc, "tab")).as_deref(), Some("tab"));
+        assert_eq!(role(&doc, by_id(&doc, "email")).as_deref(), Some("textbox"));
+        assert_eq!(role(&doc, by_id(&doc, "pick")).as_deref(), Some("combobox"));
+        assert_eq!(role(&doc, by_id(&doc, "deco")).as_deref(), Some("presentation"));
+    }
+
+    #[test]
+    fn test_accessible_name_precedence() {
+        // Given: Elements named by each mechanism
+        let doc = parse_html(
+            r#"<div>
+                <span id="lbl">Billing address</span>
+                <input id="a" aria-labelledby="lbl" aria-label="ignored"/>
+                <button id="b" aria-label="Close">X</button>
+                <label for="c">Email</label><input id="c"/>
+                <label>Remember me <input id="d" type="checkbox"/></label>
+                <a id="e" href="/"><img src="logo.png" alt="Acme"/> home</a>
+                <input id="f" type="submit"/>
+                <input id="g" title="Search" placeholder="Type here"/>
+            </div>"#,
+        );
+
+        // Then: The highest-precedence source wins
+        assert_eq!(accessible_name(&doc, by_id(&doc, "a")), "Billing address");
+        assert_eq!(accessible_name(&doc, by_id(&doc, "b")), "Close");
+        assert_eq!(accessible_name(&doc, by_id(&doc, "c")), "Email");
+        assert_eq!(accessible_name(&doc, by_id(&doc, "d")), "Remember me");
+        assert_eq!(accessible_name(&doc, by_id(&doc, "e")), "Acme home");
+        assert_eq!(accessible_name(&doc, by_id(&doc, "f")), "Submit");
+        assert_eq!(accessible_name(&doc, by_id(&doc, "g")), "Search");
+    }
+
+    #[test]
+    fn test_states() {
+        let mut doc = parse_html(
+            r#"<div><h3 id="h">Title</h3><button id="menu" aria-expanded="false" aria-disabled="true">Menu</button><input id="agree" type="checkbox"/><div id="sw" role="switch" aria-checked="true"></div></div>"#,
+        );
+        let agree = by_id(&doc, "agree");
+        crate::forms::set_checked(&mut doc, agree, true);
+
+        assert_eq!(states(&doc, by_id(&doc, "h")).level, Some(3));
+        let menu = states(&doc, by_id(&doc, "menu"));
+        assert!(menu.disabled);
+        assert_eq!(menu.expanded, Some(false));
+        assert_eq!(states(&doc, agree).checked, Some(true));
+        assert_eq!(states(&doc, by_id(&doc, "sw")).checked, Some(true));
+        assert_eq!(states(&doc, by_id(&doc, "menu")).checked, None);
+    }
+
+    #[test]
+    fn test_tree_flattens_generic_and_skips_hidden() {
+        // Given: Buttons nested in generic divs, one of them hidden
+        let doc = parse_html(
+            r#"<main><div><div><button>Save</button></div></div><div aria-hidden="true"><button>Ghost</button></div></main>"#,
+        );
+
+        // When: We build the tree
+        let tree = build_accessibility_tree(&doc);
+
+        // Then: The visible button hangs directly off main
+        assert_eq!(tree.role, "document");
+        let main = &tree.children[0];
+        assert_eq!(main.role, "main");
+        assert_eq!(main.children.len(), 1);
+        assert_eq!((main.children[0].role.as_str(), main.children[0].name.as_str()), ("button", "Save"));
+    }
+
+    #[test]
+    fn test_get_by_role() {
+        // Given: A form with two buttons and a hidden one
+        let doc = parse_html(
+            r#"<form><button id="submit">Submit</button><button id="cancel">Cancel</button><button hidden="">Submit</button></form>"#,
+        );
+
+        // Then: Queries behave like Testing Library's
+        assert_eq!(get_by_role(&doc, "button", Some("Submit")).unwrap(), by_id(&doc, "submit"));
+        assert_eq!(get_all_by_role(&doc, "button", None).unwrap().len(), 2);
+        assert!(matches!(get_by_role(&doc, "button", None), Err(BrowserError::QueryError(_))));
+        assert!(matches!(get_by_role(&doc, "link", None), Err(BrowserError::NotFoundError(_))));
+        assert_eq!(query_by_role(&doc, "button", Some("Delete")).unwrap(), None);
+    }
+}
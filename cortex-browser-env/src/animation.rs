@@ -0,0 +1,1008 @@
+//! CSS Animations
+//! `transition`, `animation` and `@keyframes`, run on a timeline the page
+//! advances one frame at a time, plus `requestAnimationFrame`
+//!
+//! Time only moves through `Page::advance_frame` or `Page::advance_time`,
+//! so every intermediate state can be asserted. Each frame writes the
+//! current value of every running transition and animation into the
+//! document's `animated_styles`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::css::{Keyframes, StyleSheet};
+use crate::dom::{Document, NodeType};
+use crate::event_loop::Clock;
+use crate::render::{argb_to_components, try_parse_color};
+use crate::rule_map::RuleMap;
+use crate::style;
+
+/// Time between frames, at 60 frames per second, in milliseconds
+pub const FRAME_INTERVAL_MS: f64 = 1000.0 / 60.0;
+
+/// `transition-timing-function` and `animation-timing-function`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingFunction {
+    CubicBezier(f64, f64, f64, f64),
+    /// `steps(count, jump-end)` when `jump_start` is false
+    Steps { count: u32, jump_start: bool },
+}
+
+impl TimingFunction {
+    pub const LINEAR: TimingFunction = TimingFunction::CubicBezier(0.0, 0.0, 1.0, 1.0);
+    pub const EASE: TimingFunction = TimingFunction::CubicBezier(0.25, 0.1, 0.25, 1.0);
+
+    pub fn parse(value: &str) -> Option<TimingFunction> {
+        let value = value.trim().to_ascii_lowercase();
+        let keyword = match value.as_str() {
+            "linear" => Some(TimingFunction::LINEAR),
+            "ease" => Some(TimingFunction::EASE),
+            "ease-in" => Some(TimingFunction::CubicBezier(0.42, 0.0, 1.0, 1.0)),
+            "ease-out" => Some(TimingFunction::CubicBezier(0.0, 0.0, 0.58, 1.0)),
+            "ease-in-out" => Some(TimingFunction::CubicBezier(0.42, 0.0, 0.58, 1.0)),
+            "step-start" => Some(TimingFunction::Steps { count: 1, jump_start: true }),
+            "step-end" => Some(TimingFunction::Steps { count: 1, jump_start: false }),
+            _ => None,
+        };
+        if keyword.is_some() {
+            return keyword;
+        }
+        let (name, args) = value.strip_suffix(')')?.split_once('(')?;
+        let args: Vec<&str> = args.split(',').map(str::trim).collect();
+        match (name.trim(), args.as_slice()) {
+            ("cubic-bezier", [x1, y1, x2, y2]) => {
+                let [x1, y1, x2, y2] = [x1, y1, x2, y2].map(|n| n.parse::<f64>().ok());
+                let (x1, x2) = (x1.filter(|x| (0.0..=1.0).contains(x))?, x2.filter(|x| (0.0..=1.0).contains(x))?);
+                Some(TimingFunction::CubicBezier(x1, y1?, x2, y2?))
+            }
+            ("steps", [count, position @ ..]) => {
+                let count = count.parse::<u32>().ok().filter(|&c| c > 0)?;
+                let jump_start = match position {
+                    [] | ["end"] | ["jump-end"] => false,
+                    ["start"] | ["jump-start"] => true,
+                    _ => return None,
+                };
+                Some(TimingFunction::Steps { count, jump_start })
+            }
+            _ => None,
+        }
+    }
+
+    /// Eased progress for linear progress `t` in 0..=1
+    pub fn apply(&self, t: f64) -> f64 {
+        match *self {
+            TimingFunction::CubicBezier(x1, y1, x2, y2) => {
+                if t <= 0.0 || t >= 1.0 || (x1 == y1 && x2 == y2) {
+                    return t.clamp(0.0, 1.0);
+                }
+                let bezier = |a: f64, b: f64, s: f64| 3.0 * a * s * (1.0 - s).powi(2) + 3.0 * b * s * s * (1.0 - s) + s.powi(3);
+                // x(s) is monotonic for x1, x2 in 0..=1, so bisect for s
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..50 {
+                    let mid = (low + high) / 2.0;
+                    if bezier(x1, x2, mid) < t {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                bezier(y1, y2, (low + high) / 2.0)
+            }
+            TimingFunction::Steps { count, jump_start } => {
+                let step = (t * count as f64).floor() + if jump_start { 1.0 } else { 0.0 };
+                (step / count as f64).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+/// One entry of a node's `transition` list
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionSpec {
+    /// A property name, or `all`
+    pub property: String,
+    pub duration_ms: f64,
+    pub timing: TimingFunction,
+    pub delay_ms: f64,
+}
+
+/// `animation-direction`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Normal,
+    Reverse,
+    Alternate,
+    AlternateReverse,
+}
+
+/// `animation-fill-mode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FillMode {
+    #[default]
+    None,
+    Forwards,
+    Backwards,
+    Both,
+}
+
+/// One entry of a node's `animation` list
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationSpec {
+    /// The `@keyframes` rule played
+    pub name: String,
+    pub duration_ms: f64,
+    pub timing: TimingFunction,
+    pub delay_ms: f64,
+    /// `f64::INFINITY` for `infinite`
+    pub iterations: f64,
+    pub direction: Direction,
+    pub fill_mode: FillMode,
+    pub paused: bool,
+}
+
+impl Default for AnimationSpec {
+    fn default() -> Self {
+        AnimationSpec {
+            name: "none".to_string(),
+            duration_ms: 0.0,
+            timing: TimingFunction::EASE,
+            delay_ms: 0.0,
+            iterations: 1.0,
+            direction: Direction::Normal,
+            fill_mode: FillMode::None,
+            paused: false,
+        }
+    }
+}
+
+/// A CSS time, `1.5s` or `200ms`, in milliseconds
+pub fn parse_time(value: &str) -> Option<f64> {
+    let value = value.trim().to_ascii_lowercase();
+    match value.strip_suffix("ms") {
+        Some(ms) => ms.parse().ok(),
+        None => value.strip_suffix('s')?.parse::<f64>().ok().map(|s| s * 1000.0),
+    }
+}
+
+/// The transitions `transition` and its longhands declare, one per
+/// property listed; longhands override the shorthand
+pub fn parse_transitions(declared: &HashMap<String, String>) -> Vec<TransitionSpec> {
+    let default = TransitionSpec { property: "all".to_string(), duration_ms: 0.0, timing: TimingFunction::EASE, delay_ms: 0.0 };
+    let mut specs: Vec<TransitionSpec> = match declared.get("transition") {
+        Some(shorthand) => split_list(shorthand)
+            .into_iter()
+            .map(|item| {
+                let mut spec = default.clone();
+                let mut times = 0;
+                for token in split_tokens(item) {
+                    if let Some(ms) = parse_time(token) {
+                        if times == 0 {
+                            spec.duration_ms = ms.max(0.0);
+                        } else {
+                            spec.delay_ms = ms;
+                        }
+                        times += 1;
+                    } else if let Some(timing) = TimingFunction::parse(token) {
+                        spec.timing = timing;
+                    } else {
+                        spec.property = token.to_ascii_lowercase();
+                    }
+                }
+                spec
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let longhand = |name: &str| declared.get(name).map(|value| split_list(value));
+    if let Some(properties) = longhand("transition-property") {
+        specs = properties
+            .iter()
+            .enumerate()
+            .map(|(i, property)| TransitionSpec {
+                property: property.to_ascii_lowercase(),
+                ..specs.get(i % specs.len().max(1)).cloned().unwrap_or_else(|| default.clone())
+            })
+            .collect();
+    }
+    if specs.is_empty() {
+        return specs;
+    }
+    if let Some(durations) = longhand("transition-duration") {
+        for (i, spec) in specs.iter_mut().enumerate() {
+            spec.duration_ms = parse_time(durations[i % durations.len()]).unwrap_or(0.0).max(0.0);
+        }
+    }
+    if let Some(delays) = longhand("transition-delay") {
+        for (i, spec) in specs.iter_mut().enumerate() {
+            spec.delay_ms = parse_time(delays[i % delays.len()]).unwrap_or(0.0);
+        }
+    }
+    if let Some(timings) = longhand("transition-timing-function") {
+        for (i, spec) in specs.iter_mut().enumerate() {
+            spec.timing = TimingFunction::parse(timings[i % timings.len()]).unwrap_or(TimingFunction::EASE);
+        }
+    }
+    specs.retain(|spec| spec.property != "none");
+    specs
+}
+
+/// The animations `animation` and its longhands declare; longhands
+/// override the shorthand, and `none` entries are dropped
+pub fn parse_animations(declared: &HashMap<String, String>) -> Vec<AnimationSpec> {
+    let mut specs: Vec<AnimationSpec> = match declared.get("animation") {
+        Some(shorthand) => split_list(shorthand).into_iter().map(parse_animation_item).collect(),
+        None => Vec::new(),
+    };
+
+    let longhand = |name: &str| declared.get(name).map(|value| split_list(value));
+    if let Some(names) = longhand("animation-name") {
+        specs = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| AnimationSpec {
+                name: name.trim_matches(['"', '\'']).to_string(),
+                ..specs.get(i).cloned().unwrap_or_default()
+            })
+            .collect();
+    }
+    let mut apply = |name: &str, set: &dyn Fn(&mut AnimationSpec, &str)| {
+        if let Some(values) = longhand(name) {
+            for (i, spec) in specs.iter_mut().enumerate() {
+                set(spec, values[i % values.len()]);
+            }
+        }
+    };
+    apply("animation-duration", &|spec, v| spec.duration_ms = parse_time(v).unwrap_or(0.0).max(0.0));
+    apply("animation-delay", &|spec, v| spec.delay_ms = parse_time(v).unwrap_or(0.0));
+    apply("animation-timing-function", &|spec, v| spec.timing = TimingFunction::parse(v).unwrap_or(TimingFunction::EASE));
+    apply("animation-iteration-count", &|spec, v| spec.iterations = parse_iterations(v).unwrap_or(1.0));
+    apply("animation-direction", &|spec, v| spec.direction = parse_direction(v).unwrap_or_default());
+    apply("animation-fill-mode", &|spec, v| spec.fill_mode = parse_fill_mode(v).unwrap_or_default());
+    apply("animation-play-state", &|spec, v| spec.paused = v.eq_ignore_ascii_case("paused"));
+    specs.retain(|spec| spec.name != "none");
+    specs
+}
+
+fn parse_animation_item(item: &str) -> AnimationSpec {
+    let mut spec = AnimationSpec::default();
+    let mut times = 0;
+    for token in split_tokens(item) {
+        if let Some(ms) = parse_time(token) {
+            if times == 0 {
+                spec.duration_ms = ms.max(0.0);
+            } else {
+                spec.delay_ms = ms;
+            }
+            times += 1;
+        } else if let Some(timing) = TimingFunction::parse(token) {
+            spec.timing = timing;
+        } else if let Some(iterations) = parse_iterations(token) {
+            spec.iterations = iterations;
+        } else if let Some(direction) = parse_direction(token) {
+            spec.direction = direction;
+        } else if let Some(fill_mode) = parse_fill_mode(token).filter(|_| token != "none" || spec.name != "none") {
+            spec.fill_mode = fill_mode;
+        } else if token.eq_ignore_ascii_case("paused") || token.eq_ignore_ascii_case("running") {
+            spec.paused = token.eq_ignore_ascii_case("paused");
+        } else {
+            spec.name = token.trim_matches(['"', '\'']).to_string();
+        }
+    }
+    spec
+}
+
+fn parse_iterations(value: &str) -> Option<f64> {
+    match value.trim() {
+        "infinite" => Some(f64::INFINITY),
+        number => number.parse::<f64>().ok().filter(|n| *n >= 0.0),
+    }
+}
+
+fn parse_direction(value: &str) -> Option<Direction> {
+    match value.trim() {
+        "normal" => Some(Direction::Normal),
+        "reverse" => Some(Direction::Reverse),
+        "alternate" => Some(Direction::Alternate),
+        "alternate-reverse" => Some(Direction::AlternateReverse),
+        _ => None,
+    }
+}
+
+fn parse_fill_mode(value: &str) -> Option<FillMode> {
+    match value.trim() {
+        "none" => Some(FillMode::None),
+        "forwards" => Some(FillMode::Forwards),
+        "backwards" => Some(FillMode::Backwards),
+        "both" => Some(FillMode::Both),
+        _ => None,
+    }
+}
+
+/// Split a comma-separated list, leaving commas inside parentheses alone
+fn split_list(value: &str) -> Vec<&str> {
+    split_outside_parens(value, |c| c == ',')
+}
+
+/// Split on whitespace, leaving `cubic-bezier(0, 0, 1, 1)` whole
+fn split_tokens(value: &str) -> Vec<&str> {
+    split_outside_parens(value, char::is_whitespace)
+}
+
+fn split_outside_parens(value: &str, separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if depth == 0 && separator(c) => {
+                parts.push(value[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// A number with its unit, e.g. `-2.5px` or `50%`; unitless for `0.5`
+fn parse_number(token: &str) -> Option<(f64, &str)> {
+    let split = token.find(|c: char| c.is_ascii_alphabetic() || c == '%').unwrap_or(token.len());
+    let (number, unit) = token.split_at(split);
+    number.parse::<f64>().ok().map(|n| (n, unit))
+}
+
+fn format_number(value: f64) -> String {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    if rounded == rounded.trunc() {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+/// Whether `from` and `to` interpolate rather than switch discretely
+pub fn is_interpolable(from: &str, to: &str) -> bool {
+    interpolate_tokens(from, to, 0.0).is_some()
+}
+
+/// The value `progress` of the way from `from` to `to`
+///
+/// Progress may leave 0..=1 for overshooting timing functions. Values that
+/// do not interpolate switch at the halfway point.
+pub fn interpolate(from: &str, to: &str, progress: f64) -> String {
+    interpolate_tokens(from, to, progress).unwrap_or_else(|| if progress < 0.5 { from } else { to }.to_string())
+}
+
+fn interpolate_tokens(from: &str, to: &str, progress: f64) -> Option<String> {
+    let (from, to) = (split_tokens(from), split_tokens(to));
+    if from.len() != to.len() {
+        return None;
+    }
+    let tokens: Option<Vec<String>> = from.iter().zip(&to).map(|(a, b)| interpolate_token(a, b, progress)).collect();
+    Some(tokens?.join(" "))
+}
+
+/// Interpolate equal tokens, numbers of one unit, colors, and functions of
+/// one name such as `rotate(10deg)`, argument by argument
+fn interpolate_token(a: &str, b: &str, progress: f64) -> Option<String> {
+    if a == b {
+        return Some(a.to_string());
+    }
+    if let (Some((x, unit_a)), Some((y, unit_b))) = (parse_number(a), parse_number(b)) {
+        let compatible = unit_a == unit_b || unit_a.is_empty() && a == "0" || unit_b.is_empty() && b == "0";
+        let unit = if unit_a.is_empty() { unit_b } else { unit_a };
+        return compatible.then(|| format!("{}{}", format_number(x + (y - x) * progress), unit));
+    }
+    if let (Some(a), Some(b)) = (try_parse_color(a), try_parse_color(b)) {
+        let ((aa, ar, ag, ab), (ba, br, bg, bb)) = (argb_to_components(a), argb_to_components(b));
+        let mix = |x: u8, y: u8| (x as f64 + (y as f64 - x as f64) * progress).round().clamp(0.0, 255.0) as u8;
+        let alpha = mix(aa, ba);
+        return Some(if alpha == 255 {
+            format!("rgb({}, {}, {})", mix(ar, br), mix(ag, bg), mix(ab, bb))
+        } else {
+            format!("rgba({}, {}, {}, {})", mix(ar, br), mix(ag, bg), mix(ab, bb), format_number(alpha as f64 / 255.0))
+        });
+    }
+    let (name_a, args_a) = a.strip_suffix(')')?.split_once('(')?;
+    let (name_b, args_b) = b.strip_suffix(')')?.split_once('(')?;
+    if !name_a.eq_ignore_ascii_case(name_b) {
+        return None;
+    }
+    let (args_a, args_b) = (split_list(args_a), split_list(args_b));
+    if args_a.len() != args_b.len() {
+        return None;
+    }
+    let args: Option<Vec<String>> = args_a.iter().zip(&args_b).map(|(x, y)| interpolate_tokens(x, y, progress)).collect();
+    Some(format!("{}({})", name_a, args?.join(", ")))
+}
+
+/// An event a frame of the timeline produced, for `Page` to dispatch
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationEvent {
+    pub node: usize,
+    /// `transitionrun`, `animationend`, ...
+    pub event_type: &'static str,
+    /// The transitioned property or the animation's name
+    pub name: String,
+    /// Seconds the transition or animation had run
+    pub elapsed_time: f64,
+}
+
+impl AnimationEvent {
+    fn new(node: usize, event_type: &'static str, name: &str, elapsed_ms: f64) -> Self {
+        AnimationEvent { node, event_type, name: name.to_string(), elapsed_time: elapsed_ms.max(0.0) / 1000.0 }
+    }
+
+    pub fn is_transition(&self) -> bool {
+        self.event_type.starts_with("transition")
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RunningTransition {
+    property: String,
+    from: String,
+    to: String,
+    spec: TransitionSpec,
+    /// Time since the transition was created, delay included
+    elapsed: f64,
+    started: bool,
+}
+
+#[derive(Debug, Clone)]
+struct RunningAnimation {
+    spec: AnimationSpec,
+    /// Time since the animation was applied, delay included
+    elapsed: f64,
+    started: bool,
+    iteration: u64,
+    finished: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct NodeAnimations {
+    declared: HashMap<String, String>,
+    transitions: Vec<RunningTransition>,
+    animations: Vec<RunningAnimation>,
+}
+
+/// Running transitions and animations of a document, and the time
+#[derive(Debug, Clone, Default)]
+pub struct AnimationTimeline {
+    /// Milliseconds since the page opened
+    clock: Clock,
+    nodes: HashMap<usize, NodeAnimations>,
+    /// Events of `start`, held for the next frame
+    pending: Vec<AnimationEvent>,
+}
+
+impl AnimationTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep time on `clock`, shared with the page's timed tasks
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Time since the page opened, as `performance.now()` reports it
+    pub fn now(&self) -> Duration {
+        Duration::from_secs_f64(self.clock.now_ms() / 1000.0)
+    }
+
+    pub fn now_ms(&self) -> f64 {
+        self.clock.now_ms()
+    }
+
+    /// Forget every node, e.g. when the document is replaced; time keeps
+    /// its value
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.pending.clear();
+    }
+
+    /// Start what a newly loaded document declares without moving time
+    ///
+    /// The events wait for the next `update`, as a browser fires them on
+    /// the next animation frame, so scripts run after loading can listen.
+    pub fn start(&mut self, document: &mut Document, stylesheet: &StyleSheet, enabled: bool) {
+        self.pending = self.update(document, stylesheet, 0.0, enabled);
+    }
+
+    /// Number of transitions and animations still running
+    pub fn running(&self) -> usize {
+        self.nodes
+            .values()
+            .map(|node| node.transitions.len() + node.animations.iter().filter(|a| !a.finished).count())
+            .sum()
+    }
+
+    /// Advance time by `advance_ms`, then start transitions for changed
+    /// properties, write every animated value into `animated_styles` and
+    /// return the events that happened
+    ///
+    /// With `enabled` false, transitions and finite animations end now.
+    pub fn update(&mut self, document: &mut Document, stylesheet: &StyleSheet, advance_ms: f64, enabled: bool) -> Vec<AnimationEvent> {
+        self.clock.advance(advance_ms);
+        let mut events = std::mem::take(&mut self.pending);
+        let rules = RuleMap::new(document, stylesheet);
+        for idx in 0..document.nodes.len() {
+            if document.nodes[idx].node_type != NodeType::Element {
+                continue;
+            }
+            let declared = style::declared_values(document, idx, &rules);
+            let state = self.nodes.entry(idx).or_insert_with(|| NodeAnimations { declared: declared.clone(), ..Default::default() });
+            let animated = state.update(idx, declared, stylesheet, advance_ms, enabled, &mut events);
+            document.set_animated_style(idx, animated);
+        }
+        events
+    }
+}
+
+impl NodeAnimations {
+    fn update(
+        &mut self,
+        node: usize,
+        declared: HashMap<String, String>,
+        stylesheet: &StyleSheet,
+        advance_ms: f64,
+        enabled: bool,
+        events: &mut Vec<AnimationEvent>,
+    ) -> HashMap<String, String> {
+        // Time passes for what was already running
+        for transition in &mut self.transitions {
+            transition.elapsed += advance_ms;
+        }
+        for animation in &mut self.animations {
+            if !animation.spec.paused {
+                animation.elapsed += advance_ms;
+            }
+        }
+
+        self.start_transitions(node, &declared, events);
+        self.reconcile_animations(node, &declared, stylesheet, events);
+        self.declared = declared;
+
+        let mut animated = HashMap::new();
+        self.transitions.retain_mut(|transition| {
+            let spec = &transition.spec;
+            let active = transition.elapsed - spec.delay_ms;
+            if !transition.started && (active >= 0.0 || !enabled) {
+                transition.started = true;
+                events.push(AnimationEvent::new(node, "transitionstart", &transition.property, 0.0));
+            }
+            if !enabled || active >= spec.duration_ms {
+                events.push(AnimationEvent::new(node, "transitionend", &transition.property, spec.duration_ms));
+                return false;
+            }
+            let progress = spec.timing.apply((active / spec.duration_ms).max(0.0));
+            animated.insert(transition.property.clone(), interpolate(&transition.from, &transition.to, progress));
+            true
+        });
+
+        for animation in &mut self.animations {
+            let Some(keyframes) = stylesheet.find_keyframes(&animation.spec.name) else { continue };
+            if let Some(progress) = animation.advance(node, enabled, events) {
+                for (property, value) in sample_keyframes(keyframes, &self.declared, progress, animation.spec.timing) {
+                    animated.insert(property, value);
+                }
+            }
+        }
+        animated
+    }
+
+    /// Start, replace or cancel transitions for properties whose declared
+    /// value changed
+    fn start_transitions(&mut self, node: usize, declared: &HashMap<String, String>, events: &mut Vec<AnimationEvent>) {
+        let specs = parse_transitions(declared);
+        let mut changed: Vec<(&String, &String)> = declared
+            .iter()
+            .filter(|(property, value)| self.declared.get(*property).is_some_and(|old| old != *value))
+            .collect();
+        changed.sort();
+        for (property, to) in changed {
+            let running = self.transitions.iter().position(|t| &t.property == property);
+            let from = match running {
+                Some(i) => {
+                    let t = &self.transitions[i];
+                    let active = (t.elapsed - t.spec.delay_ms).max(0.0);
+                    interpolate(&t.from, &t.to, t.spec.timing.apply((active / t.spec.duration_ms).min(1.0)))
+                }
+                None => self.declared[property].clone(),
+            };
+            if let Some(i) = running {
+                let canceled = self.transitions.remove(i);
+                events.push(AnimationEvent::new(node, "transitioncancel", property, canceled.elapsed - canceled.spec.delay_ms));
+            }
+            let spec = specs.iter().rev().find(|spec| spec.property == *property || spec.property == "all");
+            let Some(spec) = spec.filter(|spec| spec.duration_ms > 0.0 && is_interpolable(&from, to)) else { continue };
+            events.push(AnimationEvent::new(node, "transitionrun", property, 0.0));
+            self.transitions.push(RunningTransition {
+                property: property.clone(),
+                from,
+                to: to.clone(),
+                spec: spec.clone(),
+                elapsed: 0.0,
+                started: false,
+            });
+        }
+    }
+
+    /// Start newly named animations, update the specs of running ones and
+    /// cancel those no longer named
+    fn reconcile_animations(
+        &mut self,
+        node: usize,
+        declared: &HashMap<String, String>,
+        stylesheet: &StyleSheet,
+        events: &mut Vec<AnimationEvent>,
+    ) {
+        let specs: Vec<AnimationSpec> =
+            parse_animations(declared).into_iter().filter(|spec| stylesheet.find_keyframes(&spec.name).is_some()).collect();
+        let mut previous = std::mem::take(&mut self.animations);
+        for spec in specs {
+            match previous.iter().position(|a| a.spec.name == spec.name) {
+                Some(i) => {
+                    let mut animation = previous.remove(i);
+                    animation.spec = spec;
+                    self.animations.push(animation);
+                }
+                None => self.animations.push(RunningAnimation { spec, elapsed: 0.0, started: false, iteration: 0, finished: false }),
+            }
+        }
+        for canceled in previous.into_iter().filter(|a| a.started && !a.finished) {
+            events.push(AnimationEvent::new(node, "animationcancel", &canceled.spec.name, canceled.elapsed - canceled.spec.delay_ms));
+        }
+    }
+}
+
+impl RunningAnimation {
+    /// Record the events up to the current time and return the progress
+    /// through the keyframes, or `None` when the animation has no effect
+    fn advance(&mut self, node: usize, enabled: bool, events: &mut Vec<AnimationEvent>) -> Option<f64> {
+        let spec = &self.spec;
+        let name = spec.name.clone();
+        let active_duration = spec.duration_ms * spec.iterations;
+        if !enabled && !active_duration.is_finite() {
+            return None;
+        }
+        let mut local = self.elapsed - spec.delay_ms;
+        if !enabled {
+            local = local.max(active_duration);
+        }
+        if local < 0.0 {
+            let fills = matches!(spec.fill_mode, FillMode::Backwards | FillMode::Both);
+            return fills.then(|| self.progress(0.0));
+        }
+        if !self.started {
+            self.started = true;
+            events.push(AnimationEvent::new(node, "animationstart", &name, 0.0));
+        }
+        if local >= active_duration {
+            if !self.finished {
+                self.finished = true;
+                events.push(AnimationEvent::new(node, "animationend", &name, active_duration));
+            }
+            let fills = matches!(self.spec.fill_mode, FillMode::Forwards | FillMode::Both);
+            return fills.then(|| self.progress(active_duration));
+        }
+        let iteration = if spec.duration_ms > 0.0 { (local / spec.duration_ms).floor() as u64 } else { 0 };
+        while self.iteration < iteration {
+            self.iteration += 1;
+            let at = self.iteration as f64 * self.spec.duration_ms;
+            events.push(AnimationEvent::new(node, "animationiteration", &name, at));
+        }
+        Some(self.progress(local))
+    }
+
+    /// Progress through the keyframes `local` milliseconds into the active
+    /// duration, honoring the direction
+    fn progress(&self, local: f64) -> f64 {
+        let spec = &self.spec;
+        if spec.duration_ms <= 0.0 {
+            return if spec.iterations > 0.0 { 1.0 } else { 0.0 };
+        }
+        let iterations = local / spec.duration_ms;
+        let (mut iteration, mut progress) = (iterations.floor(), iterations.fract());
+        // The end of the last iteration is its progress 1, not the next one's 0
+        if progress == 0.0 && iteration > 0.0 && local >= spec.duration_ms * spec.iterations {
+            iteration -= 1.0;
+            progress = 1.0;
+        }
+        let odd = iteration as u64 % 2 == 1;
+        let reversed = match spec.direction {
+            Direction::Normal => false,
+            Direction::Reverse => true,
+            Direction::Alternate => odd,
+            Direction::AlternateReverse => !odd,
+        };
+        if reversed {
+            1.0 - progress
+        } else {
+            progress
+        }
+    }
+}
+
+/// Every property's value `progress` of the way through `keyframes`
+///
+/// Properties missing from the `from` or `to` keyframe use their declared
+/// value there. Each keyframe's `animation-timing-function` eases the
+/// segment after it, falling back to the animation's.
+fn sample_keyframes(
+    keyframes: &Keyframes,
+    declared: &HashMap<String, String>,
+    progress: f64,
+    timing: TimingFunction,
+) -> Vec<(String, String)> {
+    let mut properties: Vec<&String> = keyframes
+        .frames
+        .iter()
+        .flat_map(|frame| frame.declarations.keys())
+        .filter(|property| !property.starts_with("animation"))
+        .collect();
+    properties.sort();
+    properties.dedup();
+
+    let mut values = Vec::new();
+    for property in properties {
+        let mut stops: Vec<(f64, &str, TimingFunction)> = keyframes
+            .frames
+            .iter()
+            .filter_map(|frame| {
+                let value = frame.declarations.get(property)?;
+                let easing = frame.declarations.get("animation-timing-function").and_then(|t| TimingFunction::parse(t));
+                Some((frame.offset as f64, value.as_str(), easing.unwrap_or(timing)))
+            })
+            .collect();
+        let underlying = declared.get(property).map(String::as_str);
+        if let Some(underlying) = underlying {
+            if stops.first().is_some_and(|stop| stop.0 > 0.0) {
+                stops.insert(0, (0.0, underlying, timing));
+            }
+            if stops.last().is_some_and(|stop| stop.0 < 1.0) {
+                stops.push((1.0, underlying, timing));
+            }
+        }
+        let value = match stops.iter().rposition(|stop| stop.0 <= progress) {
+            None => stops.first().map(|stop| stop.1.to_string()),
+            Some(i) if i + 1 == stops.len() => Some(stops[i].1.to_string()),
+            Some(i) => {
+                let ((start, from, easing), (end, to, _)) = (stops[i], stops[i + 1]);
+                let local = if end > start { (progress - start) / (end - start) } else { 1.0 };
+                Some(interpolate(from, to, easing.apply(local)))
+            }
+        };
+        if let Some(value) = value {
+            values.push((property.clone(), value));
+        }
+    }
+    values
+}
+
+/// `TransitionEvent`, `AnimationEvent`, `requestAnimationFrame` and
+/// `performance.now`, over the natives of `install_animation_bindings`
+const ANIMATION_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexAnimation;
+    let callbacks = new Map();
+    let nextId = 1;
+
+    class TransitionEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.propertyName = String(init.propertyName ?? "");
+            this.elapsedTime = Number(init.elapsedTime ?? 0);
+            this.pseudoElement = String(init.pseudoElement ?? "");
+        }
+    }
+    class AnimationEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.animationName = String(init.animationName ?? "");
+            this.elapsedTime = Number(init.elapsedTime ?? 0);
+            this.pseudoElement = String(init.pseudoElement ?? "");
+        }
+    }
+
+    native.frame = timestamp => {
+        const due = callbacks;
+        callbacks = new Map();
+        for (const callback of due.values()) {
+            try {
+                callback(timestamp);
+            } catch (error) {
+                console.error("Uncaught", error);
+            }
+        }
+    };
+
+    globalThis.TransitionEvent = TransitionEvent;
+    globalThis.AnimationEvent = AnimationEvent;
+    globalThis.requestAnimationFrame = callback => {
+        if (typeof callback !== "function") {
+            throw new TypeError("Failed to execute 'requestAnimationFrame': The callback provided as parameter 1 is not a function.");
+        }
+        const id = nextId++;
+        callbacks.set(id, callback);
+        return id;
+    };
+    globalThis.cancelAnimationFrame = id => { callbacks.delete(id); };
+    globalThis.performance = { now: () => native.now() };
+})();
+"#;
+
+/// Install `TransitionEvent`, `AnimationEvent`, `requestAnimationFrame`,
+/// `cancelAnimationFrame` and `performance.now()` on `timeline`'s clock
+///
+/// Needs the `events` bindings installed first.
+pub fn install_animation_bindings(ctx: &Ctx<'_>, timeline: Rc<RefCell<AnimationTimeline>>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    native.set("now", Function::new(ctx.clone(), move || timeline.borrow().now_ms())?)?;
+    ctx.globals().set("__cortexAnimation", native)?;
+    ctx.eval::<(), _>(ANIMATION_PRELUDE)
+}
+
+/// Run the `requestAnimationFrame` callbacks due at `timestamp_ms`
+pub fn run_frame_callbacks(ctx: &Ctx<'_>, timestamp_ms: f64) -> rquickjs::Result<()> {
+    let native: Object = ctx.globals().get("__cortexAnimation")?;
+    let frame: Function = native.get("frame")?;
+    frame.call((timestamp_ms,))
+}
+
+/// Dispatch a timeline event as a bubbling `TransitionEvent` or
+/// `AnimationEvent` at its node
+pub fn dispatch_animation_event(ctx: &Ctx<'_>, document: &RefCell<Document>, event: &AnimationEvent) -> rquickjs::Result<bool> {
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", true)?;
+    init.set("elapsedTime", event.elapsed_time)?;
+    let constructor = if event.is_transition() {
+        init.set("propertyName", event.name.as_str())?;
+        "TransitionEvent"
+    } else {
+        init.set("animationName", event.name.as_str())?;
+        "AnimationEvent"
+    };
+    let js_event = crate::events::create_event(ctx, constructor, event.event_type, init)?;
+    js_event.set("isTrusted", true)?;
+    crate::events::dispatch_event(ctx, document, event.node, js_event)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::parse_css;
+    use crate::parser::parse_html;
+
+    #[test]
+    fn test_timing_functions() {
+        assert_eq!(TimingFunction::parse("linear").unwrap().apply(0.25), 0.25);
+        let ease_in = TimingFunction::parse("cubic-bezier(0.42, 0, 1, 1)").unwrap();
+        assert_eq!(ease_in, TimingFunction::parse("ease-in").unwrap());
+        assert!(ease_in.apply(0.5) < 0.5 && (ease_in.apply(0.5) - 0.315).abs() < 0.01);
+        assert_eq!(TimingFunction::parse("steps(4)").unwrap().apply(0.3), 0.25);
+        assert_eq!(TimingFunction::parse("steps(4, start)").unwrap().apply(0.3), 0.5);
+        assert_eq!(TimingFunction::parse("cubic-bezier(2, 0, 1, 1)"), None);
+    }
+
+    #[test]
+    fn test_parse_transition_and_animation_lists() {
+        let declared: HashMap<String, String> = [
+            ("transition", "color 200ms ease-out, letter-spacing 1s 0.5s"),
+            ("animation", "spin 2s linear infinite alternate both, fade .5s"),
+            ("animation-play-state", "paused, running"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let transitions = parse_transitions(&declared);
+        assert_eq!(transitions.len(), 2);
+        assert_eq!((transitions[0].property.as_str(), transitions[0].duration_ms), ("color", 200.0));
+        assert_eq!((transitions[1].duration_ms, transitions[1].delay_ms), (1000.0, 500.0));
+        assert_eq!(transitions[1].timing, TimingFunction::EASE);
+
+        let animations = parse_animations(&declared);
+        assert_eq!(animations[0].name, "spin");
+        assert_eq!(animations[0].iterations, f64::INFINITY);
+        assert_eq!((animations[0].direction, animations[0].fill_mode, animations[0].paused), (Direction::Alternate, FillMode::Both, true));
+        assert_eq!((animations[1].name.as_str(), animations[1].duration_ms, animations[1].paused), ("fade", 500.0, false));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq!(interpolate("10px", "20px", 0.25), "12.5px");
+        assert_eq!(interpolate("0", "8px", 0.5), "4px");
+        assert_eq!(interpolate("#000000", "white", 0.5), "rgb(128, 128, 128)");
+        assert_eq!(interpolate("rgba(255, 0, 0, 0)", "red", 0.5), "rgba(255, 0, 0, 0.502)");
+        assert_eq!(interpolate("4px 8px", "8px 16px", 0.5), "6px 12px");
+        assert_eq!(interpolate("uppercase", "lowercase", 0.4), "uppercase");
+        assert_eq!(interpolate("10px", "50%", 0.6), "50%");
+        assert_eq!(interpolate("translateX(0) rotate(0deg)", "translateX(-100%) rotate(90deg)", 0.5), "translateX(-50%) rotate(45deg)");
+        assert_eq!(interpolate("scale(1)", "rotate(1turn)", 0.25), "scale(1)");
+    }
+
+    #[test]
+    fn test_transition_runs_on_declared_change() {
+        // Given: A link whose color transitions when it gets a class
+        let stylesheet = parse_css(".link { color: #000000; transition: color 100ms linear; } .visited { color: #ffffff; }");
+        let mut document = parse_html(r#"<html><body><a class="link">Link</a></body></html>"#);
+        let link = (0..document.nodes.len()).find(|&i| crate::forms::tag_name(&document, i) == Some("a")).unwrap();
+        let mut timeline = AnimationTimeline::new();
+        assert!(timeline.update(&mut document, &stylesheet, 0.0, true).is_empty());
+
+        // When: The class changes and time passes
+        document.set_attribute(link, "class", "link visited");
+        let started = timeline.update(&mut document, &stylesheet, 0.0, true);
+        let halfway = document.animated_style(link).and_then(|style| style.get("color")).cloned();
+        timeline.update(&mut document, &stylesheet, 50.0, true);
+        let later = document.animated_style(link).and_then(|style| style.get("color")).cloned();
+        let ended = timeline.update(&mut document, &stylesheet, 50.0, true);
+
+        // Then: The color moved between the values and the events fired
+        let types: Vec<&str> = started.iter().chain(&ended).map(|e| e.event_type).collect();
+        assert_eq!(types, ["transitionrun", "transitionstart", "transitionend"]);
+        assert_eq!(ended[0].elapsed_time, 0.1);
+        assert_eq!(halfway.as_deref(), Some("rgb(0, 0, 0)"));
+        assert_eq!(later.as_deref(), Some("rgb(128, 128, 128)"));
+        assert!(document.animated_style(link).is_none());
+        assert_eq!(timeline.running(), 0);
+    }
+
+    #[test]
+    fn test_keyframe_animation_iterates_and_fills() {
+        // Given: A two-iteration alternating animation holding its end
+        let stylesheet = parse_css(
+            "@keyframes grow { from { letter-spacing: 0px; } to { letter-spacing: 10px; } }
+             .bar { animation: grow 100ms linear 2 alternate forwards 50ms; }",
+        );
+        let mut document = parse_html(r#"<html><body><div class="bar">Bar</div></body></html>"#);
+        let bar = (0..document.nodes.len()).find(|&i| crate::forms::tag_name(&document, i) == Some("div")).unwrap();
+        let mut timeline = AnimationTimeline::new();
+        let mut spacing = |advance: f64, events: &mut Vec<&'static str>| {
+            events.extend(timeline.update(&mut document, &stylesheet, advance, true).iter().map(|e| e.event_type));
+            document.animated_style(bar).and_then(|style| style.get("letter-spacing")).cloned()
+        };
+
+        // Then: It waits out the delay, plays forwards, then back, then holds
+        let mut events = Vec::new();
+        assert_eq!(spacing(0.0, &mut events), None);
+        assert_eq!(spacing(75.0, &mut events).as_deref(), Some("2.5px"));
+        assert_eq!(spacing(100.0, &mut events).as_deref(), Some("7.5px"));
+        assert_eq!(spacing(100.0, &mut events).as_deref(), Some("0px"));
+        assert_eq!(events, ["animationstart", "animationiteration", "animationend"]);
+    }
+
+    #[test]
+    fn test_disabled_animations_jump_to_the_end() {
+        let stylesheet = parse_css(
+            "@keyframes fade { to { color: #ffffff; } } @keyframes spin { to { letter-spacing: 4px; } }
+             .toast { color: #000000; animation: fade 1s forwards; } .spinner { animation: spin 1s infinite; }",
+        );
+        let mut document = parse_html(r#"<html><body><p class="toast">Saved</p><p class="spinner">...</p></body></html>"#);
+        let paragraphs: Vec<usize> = (0..document.nodes.len()).filter(|&i| crate::forms::tag_name(&document, i) == Some("p")).collect();
+        let mut timeline = AnimationTimeline::new();
+
+        let events = timeline.update(&mut document, &stylesheet, 0.0, false);
+
+        let types: Vec<&str> = events.iter().map(|e| e.event_type).collect();
+        assert_eq!(types, ["animationstart", "animationend"]);
+        assert_eq!(document.animated_style(paragraphs[0]).and_then(|style| style.get("color")).map(String::as_str), Some("#ffffff"));
+        assert!(document.animated_style(paragraphs[1]).is_none());
+    }
+}
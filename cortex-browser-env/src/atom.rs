@@ -0,0 +1,295 @@
+//! Interned Names
+//! Tag and attribute names, lowercased and stored once
+//!
+//! An `Atom` points at the one shared copy of its name, so two atoms are
+//! equal exactly when they point at the same copy. Names are
+//! ASCII-lowercased on the way in, as HTML treats them case-insensitively,
+//! so `DIV` and `div` are the same atom.
+//!
+//! HTML's own tag and attribute names are static and found without a lock.
+//! Other names, such as custom elements and `data-` attributes, are
+//! counted: the table keeps only a weak reference, so a name is freed once
+//! no node or selector holds its atom, and the table only grows with the
+//! names in use.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+/// A lowercased, interned tag or attribute name
+#[derive(Clone)]
+pub struct Atom(Name);
+
+#[derive(Clone)]
+enum Name {
+    Static(&'static str),
+    Dynamic(Arc<str>),
+}
+
+/// HTML's tag and attribute names, sorted for binary search
+const STATIC_NAMES: &[&str] = &[
+    "a", "abbr", "accept", "accept-charset", "accesskey", "action", "address", "align", "alt", "area", "aria-checked",
+    "aria-describedby", "aria-disabled", "aria-expanded", "aria-hidden", "aria-label", "aria-labelledby",
+    "aria-live", "aria-selected", "article", "aside", "async", "audio", "autocomplete", "autofocus", "autoplay", "b",
+    "base", "bdi", "bdo", "blockquote", "body", "br", "button", "canvas", "caption", "charset", "checked", "cite",
+    "class", "code", "col", "colgroup", "cols", "colspan", "content", "contenteditable", "controls", "crossorigin",
+    "data", "datalist", "datetime", "dd", "decoding", "default", "defer", "del", "details", "dfn", "dialog", "dir",
+    "disabled", "div", "dl", "download", "draggable", "dt", "em", "embed", "enctype", "fieldset", "figcaption",
+    "figure", "footer", "for", "form", "formaction", "formmethod", "formnovalidate", "h1", "h2", "h3", "h4", "h5", "h6",
+    "head", "header", "headers", "height", "hgroup", "hidden", "hr", "href", "hreflang", "html", "http-equiv", "i",
+    "id", "iframe", "img", "inert", "input", "inputmode", "ins", "integrity", "is", "kbd", "label", "lang", "legend",
+    "li", "link", "list", "loading", "loop", "main", "map", "mark", "max", "maxlength", "media", "menu", "meta",
+    "meter", "method", "min", "minlength", "multiple", "muted", "name", "nav", "noscript", "novalidate", "object", "ol",
+    "onblur", "onchange", "onclick", "onfocus", "oninput", "onkeydown", "onkeyup", "onload", "onsubmit", "open",
+    "optgroup", "option", "output", "p", "param", "pattern", "picture", "placeholder", "poster", "pre", "preload",
+    "progress", "q", "readonly", "referrerpolicy", "rel", "required", "reversed", "role", "rows", "rowspan", "rp",
+    "rt", "ruby", "s", "samp", "sandbox", "scope", "script", "section", "select", "selected", "sizes", "slot", "small",
+    "source", "span", "spellcheck", "src", "srcdoc", "srclang", "srcset", "start", "step", "strong", "style", "sub",
+    "summary", "sup", "svg", "tabindex", "table", "target", "tbody", "td", "template", "textarea", "tfoot", "th",
+    "thead", "time", "title", "tr", "track", "translate", "type", "u", "ul", "usemap", "value", "var", "video",
+    "viewbox", "wbr", "width", "wrap",
+];
+
+/// Names outside `STATIC_NAMES`, by text, while any atom holds them
+#[derive(Default)]
+struct Table {
+    names: HashMap<Box<str>, Weak<str>>,
+    /// Size after the last sweep of freed names
+    swept: usize,
+}
+
+fn table() -> &'static Mutex<Table> {
+    static TABLE: OnceLock<Mutex<Table>> = OnceLock::new();
+    TABLE.get_or_init(Default::default)
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Table> {
+    table().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn static_name(name: &str) -> Option<&'static str> {
+    STATIC_NAMES.binary_search(&name).ok().map(|i| STATIC_NAMES[i])
+}
+
+impl Atom {
+    /// The atom for `name`, ASCII-lowercased, interning it if no atom holds
+    /// it yet
+    pub fn new(name: &str) -> Self {
+        if name.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Self::intern(&name.to_ascii_lowercase());
+        }
+        Self::intern(name)
+    }
+
+    /// The atom for `name`, ASCII-lowercased, if one is held
+    pub fn lookup(name: &str) -> Option<Self> {
+        if name.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Self::find(&name.to_ascii_lowercase());
+        }
+        Self::find(name)
+    }
+
+    fn find(name: &str) -> Option<Self> {
+        if let Some(name) = static_name(name) {
+            return Some(Atom(Name::Static(name)));
+        }
+        lock().names.get(name).and_then(Weak::upgrade).map(|name| Atom(Name::Dynamic(name)))
+    }
+
+    fn intern(name: &str) -> Self {
+        if let Some(name) = static_name(name) {
+            return Atom(Name::Static(name));
+        }
+        let mut table = lock();
+        if let Some(interned) = table.names.get(name).and_then(Weak::upgrade) {
+            return Atom(Name::Dynamic(interned));
+        }
+        // Drop freed names once they could make up half the table
+        if table.names.len() >= 2 * table.swept.max(32) {
+            table.names.retain(|_, name| name.strong_count() > 0);
+            table.swept = table.names.len();
+        }
+        let interned: Arc<str> = Arc::from(name);
+        table.names.insert(name.into(), Arc::downgrade(&interned));
+        Atom(Name::Dynamic(interned))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Name::Static(name) => name,
+            Name::Dynamic(name) => name,
+        }
+    }
+
+    /// Number of names outside HTML's own held by atoms
+    pub fn interned() -> usize {
+        lock().names.values().filter(|name| name.strong_count() > 0).count()
+    }
+}
+
+impl PartialEq for Atom {
+    fn eq(&self, other: &Atom) -> bool {
+        match (&self.0, &other.0) {
+            (Name::Static(a), Name::Static(b)) => std::ptr::eq(*a, *b),
+            (Name::Dynamic(a), Name::Dynamic(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Atom {}
+
+// Hash the text, not the pointer, so maps keyed by atoms can be looked up
+// with a `&str`
+impl Hash for Atom {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Atom) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Atom {
+    fn cmp(&self, other: &Atom) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialEq<str> for Atom {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Atom {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Atom {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl Deref for Atom {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Atom {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Atom {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(name: &str) -> Self {
+        Atom::new(name)
+    }
+}
+
+impl From<&String> for Atom {
+    fn from(name: &String) -> Self {
+        Atom::new(name)
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_atoms_are_lowercased_and_shared() {
+        // Given: The same name in two cases
+        let lower = Atom::new("section");
+        let upper = Atom::new("SECTION");
+
+        // Then: Both are the one interned copy
+        assert_eq!(lower, upper);
+        assert!(std::ptr::eq(lower.as_str(), upper.as_str()));
+        assert_eq!(upper, "section");
+        assert_ne!(lower, Atom::new("sect"));
+    }
+
+    #[test]
+    fn test_lookup_does_not_intern() {
+        // Given: A name that was interned and one that never was
+        let article = Atom::new("article");
+
+        // When: Both are looked up, in any case
+        let found = Atom::lookup("ARTICLE");
+        let missing = Atom::lookup("x-never-interned-by-any-test");
+
+        // Then: Only the interned one is found, and looking up did not
+        // intern the other
+        assert_eq!(found, Some(article));
+        assert_eq!(missing, None);
+        assert!(Atom::lookup("x-never-interned-by-any-test").is_none());
+    }
+
+    #[test]
+    fn test_names_are_freed_once_no_atom_holds_them() {
+        // Given: A name outside HTML's own, held by two atoms
+        let first = Atom::new("data-held-by-this-test");
+        let second = Atom::lookup("DATA-HELD-BY-THIS-TEST").unwrap();
+        assert_eq!(first, second);
+
+        // When: Both are dropped
+        drop((first, second));
+
+        // Then: The name is gone, and interning it again gives a new copy
+        assert_eq!(Atom::lookup("data-held-by-this-test"), None);
+        assert_eq!(Atom::new("data-held-by-this-test"), "data-held-by-this-test");
+    }
+
+    #[test]
+    fn test_html_names_are_static() {
+        // Given: HTML's own names, sorted for binary search
+        assert!(STATIC_NAMES.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Then: They are found without interning, whatever their case
+        assert!(matches!(Atom::lookup("TextArea"), Some(Atom(Name::Static("textarea")))));
+        assert!(matches!(Atom::new("x-card"), Atom(Name::Dynamic(_))));
+    }
+
+    #[test]
+    fn test_atom_keyed_maps_look_up_by_str() {
+        // Given: A map keyed by atoms
+        let mut attributes = HashMap::new();
+        attributes.insert(Atom::new("data-id"), "7".to_string());
+
+        // Then: A plain string finds the entry
+        assert_eq!(attributes.get("data-id").map(String::as_str), Some("7"));
+    }
+}
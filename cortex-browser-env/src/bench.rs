@@ -0,0 +1,409 @@
+//! Benchmarks
+//! Synthetic documents for measuring layout and paint throughput, and JSON
+//! baselines that later runs are compared against to catch regressions
+//!
+//! Each case is a generated page: nested `<div>`s (deep), siblings (wide),
+//! a large table, or paragraphs of text. A run lays out and paints every
+//! case a number of times and keeps the median of each stage, which is
+//! steadier than the mean on a shared CI machine. `cargo bench` runs the
+//! same cases under criterion.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use raqote::DrawTarget;
+
+use crate::css::{self, StyleSheet};
+use crate::dom::{Document, NodeData};
+use crate::fonts::WebFonts;
+use crate::geometry::Rect;
+use crate::images::ImageCache;
+use crate::json::Json;
+use crate::reporters::json_string;
+use crate::{forms, layout, parser, render, style};
+
+/// Default `--iterations` for `bench`
+pub const DEFAULT_ITERATIONS: usize = 10;
+
+/// Default `--tolerance` for `bench`: how much slower than the baseline a
+/// stage may get, in percent
+pub const DEFAULT_TOLERANCE: f64 = 20.0;
+
+const BENCH_CSS: &str = "
+body { margin: 8px; font-family: sans-serif; }
+div { padding: 2px; border: 1px solid #ccc; }
+td { padding: 4px; border: 1px solid #999; }
+p { margin: 0 0 8px 0; line-height: 1.4; }
+.alt { background-color: #eef; }
+";
+
+/// What `bench` runs and compares against
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+    /// Runs of each case; the median is kept
+    pub iterations: usize,
+    /// Baseline to compare with, failing on regressions
+    pub baseline: Option<PathBuf>,
+    /// Where to write this run as a new baseline
+    pub save_baseline: Option<PathBuf>,
+    /// Percent slower than the baseline a stage may get
+    pub tolerance: f64,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions { iterations: DEFAULT_ITERATIONS, baseline: None, save_baseline: None, tolerance: DEFAULT_TOLERANCE }
+    }
+}
+
+/// The shape of a synthetic document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchDocument {
+    /// `size` `<div>`s, each inside the last
+    Deep,
+    /// `size` sibling `<div>`s
+    Wide,
+    /// A table of `size` rows of 8 cells
+    Table,
+    /// `size` paragraphs of wrapping text
+    Text,
+}
+
+impl BenchDocument {
+    pub const ALL: [BenchDocument; 4] = [BenchDocument::Deep, BenchDocument::Wide, BenchDocument::Table, BenchDocument::Text];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BenchDocument::Deep => "deep",
+            BenchDocument::Wide => "wide",
+            BenchDocument::Table => "table",
+            BenchDocument::Text => "text",
+        }
+    }
+
+    /// The page's markup at `size`
+    pub fn html(&self, size: usize) -> String {
+        let body = match self {
+            BenchDocument::Deep => format!("{}leaf{}", "<div>".repeat(size), "</div>".repeat(size)),
+            BenchDocument::Wide => (0..size).map(|i| format!("<div class=\"{}\">Item {}</div>", if i % 2 == 0 { "alt" } else { "" }, i)).collect(),
+            BenchDocument::Table => {
+                let rows: String = (0..size)
+                    .map(|row| {
+                        let cells: String = (0..8).map(|col| format!("<td>R{}C{}</td>", row, col)).collect();
+                        format!("<tr class=\"{}\">{}</tr>", if row % 2 == 0 { "alt" } else { "" }, cells)
+                    })
+                    .collect();
+                format!("<table><tbody>{}</tbody></table>", rows)
+            }
+            BenchDocument::Text => (0..size)
+                .map(|i| {
+                    let words: Vec<String> = (0..60).map(|w| format!("word{}", (i * 7 + w * 13) % 97)).collect();
+                    format!("<p>{}</p>", words.join(" "))
+                })
+                .collect(),
+        };
+        format!("<html><head><style>{}</style></head><body>{}</body></html>", BENCH_CSS, body)
+    }
+}
+
+/// One synthetic page to measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchCase {
+    pub document: BenchDocument,
+    pub size: usize,
+}
+
+impl BenchCase {
+    pub fn new(document: BenchDocument, size: usize) -> Self {
+        BenchCase { document, size }
+    }
+
+    /// `<shape>-<size>`, the key baselines use
+    pub fn name(&self) -> String {
+        format!("{}-{}", self.document.name(), self.size)
+    }
+
+    /// Parse the page, ready to lay out and paint
+    pub fn prepare(&self, width: f32, height: f32) -> PreparedCase {
+        let html = self.document.html(self.size);
+        let document = parser::parse_html(&html);
+        let css_text: String = (0..document.nodes.len())
+            .filter(|&idx| forms::tag_name(&document, idx) == Some("style"))
+            .map(|idx| document.text_content(idx))
+            .collect();
+        PreparedCase { document, stylesheet: css::parse_css(&css_text), images: ImageCache::new(), width, height, threads: 0 }
+    }
+}
+
+/// The cases `bench` runs by default, small and large of each shape
+pub fn default_cases() -> Vec<BenchCase> {
+    vec![
+        BenchCase::new(BenchDocument::Deep, 50),
+        BenchCase::new(BenchDocument::Deep, 200),
+        BenchCase::new(BenchDocument::Wide, 200),
+        BenchCase::new(BenchDocument::Wide, 1000),
+        BenchCase::new(BenchDocument::Table, 50),
+        BenchCase::new(BenchDocument::Table, 200),
+        BenchCase::new(BenchDocument::Text, 20),
+        BenchCase::new(BenchDocument::Text, 100),
+    ]
+}
+
+/// A parsed synthetic page
+pub struct PreparedCase {
+    pub document: Document,
+    pub stylesheet: StyleSheet,
+    images: ImageCache,
+    width: f32,
+    height: f32,
+    /// Threads for style and layout, 0 for one per core
+    threads: usize,
+}
+
+impl PreparedCase {
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Lay out with only the user agent's styles, as `layout::calculate_layout`
+    pub fn layout(&mut self) {
+        let styles = style::compute_styles_with_threads(&self.document, &StyleSheet::default(), self.threads);
+        layout::calculate_styled_layout_with_threads(&mut self.document, &styles, self.width, self.height, self.threads);
+    }
+
+    /// Walk the tree depth first, reading every element's tag as selector
+    /// matching does; returns the elements seen
+    ///
+    /// Measures how fast nodes can be visited, which depends on how much
+    /// memory each takes.
+    pub fn walk(&self) -> usize {
+        let document = &self.document;
+        let (mut stack, mut elements) = (vec![document.root], 0);
+        while let Some(idx) = stack.pop() {
+            let node = &document.nodes[idx];
+            if let Some(NodeData::Element(element)) = &node.data {
+                elements += usize::from(!element.tag_name.is_empty());
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        elements
+    }
+
+    /// Style and paint the viewport as last laid out
+    pub fn paint(&self) -> DrawTarget {
+        let styles = style::compute_styles_with_threads(&self.document, &self.stylesheet, self.threads);
+        render::render_scaled_region(&self.document, &styles, &self.images, &WebFonts::default(), Rect::new(0.0, 0.0, self.width, self.height), 1.0)
+    }
+}
+
+/// Median times of one case
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: String,
+    pub nodes: usize,
+    pub layout: Duration,
+    pub paint: Duration,
+}
+
+impl BenchResult {
+    /// Nodes laid out per second
+    pub fn layout_throughput(&self) -> f64 {
+        throughput(self.nodes, self.layout)
+    }
+
+    /// Nodes painted per second
+    pub fn paint_throughput(&self) -> f64 {
+        throughput(self.nodes, self.paint)
+    }
+}
+
+fn throughput(nodes: usize, duration: Duration) -> f64 {
+    nodes as f64 / duration.as_secs_f64().max(1e-9)
+}
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples.get(samples.len() / 2).copied().unwrap_or_default()
+}
+
+/// Lay out and paint `case` `iterations` times after a warm-up run, on
+/// `threads` threads, keeping the medians
+pub fn run_case(case: &BenchCase, iterations: usize, width: f32, height: f32, threads: usize) -> BenchResult {
+    let mut prepared = case.prepare(width, height).with_threads(threads);
+    // One untimed run, so loading fonts and filling caches is not counted
+    prepared.layout();
+    prepared.paint();
+    let (mut layouts, mut paints) = (Vec::new(), Vec::new());
+    for _ in 0..iterations.max(1) {
+        let started = Instant::now();
+        prepared.layout();
+        layouts.push(started.elapsed());
+        let started = Instant::now();
+        prepared.paint();
+        paints.push(started.elapsed());
+    }
+    BenchResult { name: case.name(), nodes: prepared.document.nodes.len(), layout: median(layouts), paint: median(paints) }
+}
+
+/// The results of a run, in case order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    /// Run every case on `threads` threads, 0 for one per core
+    pub fn run(cases: &[BenchCase], iterations: usize, width: f32, height: f32, threads: usize) -> Self {
+        BenchReport { results: cases.iter().map(|case| run_case(case, iterations, width, height, threads)).collect() }
+    }
+
+    /// A table of times and throughput, one case per line
+    pub fn format_table(&self) -> String {
+        let mut out = format!("{:<12}{:>8}{:>12}{:>14}{:>12}{:>14}\n", "case", "nodes", "layout ms", "layout n/s", "paint ms", "paint n/s");
+        for result in &self.results {
+            out.push_str(&format!(
+                "{:<12}{:>8}{:>12.3}{:>14.0}{:>12.3}{:>14.0}\n",
+                result.name,
+                result.nodes,
+                result.layout.as_secs_f64() * 1000.0,
+                result.layout_throughput(),
+                result.paint.as_secs_f64() * 1000.0,
+                result.paint_throughput()
+            ));
+        }
+        out
+    }
+
+    /// The report as a baseline file
+    pub fn to_json(&self) -> String {
+        let cases: Vec<String> = self
+            .results
+            .iter()
+            .map(|result| {
+                format!(
+                    "    {{\"name\":{},\"nodes\":{},\"layout_ms\":{:.4},\"paint_ms\":{:.4}}}",
+                    json_string(&result.name),
+                    result.nodes,
+                    result.layout.as_secs_f64() * 1000.0,
+                    result.paint.as_secs_f64() * 1000.0
+                )
+            })
+            .collect();
+        format!("{{\n  \"cases\": [\n{}\n  ]\n}}\n", cases.join(",\n"))
+    }
+
+    /// Read a baseline written by `to_json`
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let invalid = |why: &str| format!("Invalid benchmark baseline: {}", why);
+        let json = Json::parse(text).ok_or_else(|| invalid("not JSON"))?;
+        let Some(Json::Array(cases)) = json.get("cases") else { return Err(invalid("expected a \"cases\" array")) };
+        let ms = |case: &Json, key: &str| case.get(key).and_then(Json::as_number).filter(|n| *n >= 0.0).map(|n| Duration::from_secs_f64(n / 1000.0));
+        let results = cases
+            .iter()
+            .map(|case| {
+                Some(BenchResult {
+                    name: case.get("name")?.as_str()?.to_string(),
+                    nodes: case.get("nodes").and_then(Json::as_number).unwrap_or_default() as usize,
+                    layout: ms(case, "layout_ms")?,
+                    paint: ms(case, "paint_ms")?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("each case needs a name, layout_ms and paint_ms"))?;
+        Ok(BenchReport { results })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read baseline '{}': {}", path.display(), e))?;
+        Self::from_json(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json()).map_err(|e| format!("Cannot write baseline '{}': {}", path.display(), e))
+    }
+
+    /// Stages more than `tolerance` percent slower than in `baseline`;
+    /// cases the baseline lacks are skipped
+    pub fn regressions(&self, baseline: &BenchReport, tolerance: f64) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        for result in &self.results {
+            let Some(base) = baseline.results.iter().find(|base| base.name == result.name) else { continue };
+            for (stage, current, before) in [("layout", result.layout, base.layout), ("paint", result.paint, base.paint)] {
+                if current.as_secs_f64() > before.as_secs_f64() * (1.0 + tolerance / 100.0) {
+                    regressions.push(Regression { name: result.name.clone(), stage, baseline: before, current });
+                }
+            }
+        }
+        regressions
+    }
+}
+
+/// A stage of a case that got slower than its baseline allows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub name: String,
+    pub stage: &'static str,
+    pub baseline: Duration,
+    pub current: Duration,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (before, now) = (self.baseline.as_secs_f64() * 1000.0, self.current.as_secs_f64() * 1000.0);
+        write!(f, "{} {}: {:.3}ms -> {:.3}ms (+{:.0}%)", self.name, self.stage, before, now, (now / before.max(1e-9) - 1.0) * 100.0)
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthetic_documents_have_their_shape() {
+        let deep = BenchCase::new(BenchDocument::Deep, 30).prepare(800.0, 600.0);
+        let depth = (0..deep.document.nodes.len())
+            .map(|idx| std::iter::successors(deep.document.nodes[idx].parent, |&p| deep.document.nodes[p].parent).count())
+            .max();
+        assert!(depth.unwrap() > 30);
+
+        let table = BenchCase::new(BenchDocument::Table, 10).prepare(800.0, 600.0);
+        let cells = (0..table.document.nodes.len()).filter(|&idx| forms::tag_name(&table.document, idx) == Some("td")).count();
+        assert_eq!(cells, 80);
+        assert!(!table.stylesheet.rules.is_empty());
+    }
+
+    #[test]
+    fn test_baseline_round_trips_and_flags_regressions() {
+        // Given: A baseline and a run where wide-10's layout doubled
+        let result = |name: &str, layout: u64, paint: u64| BenchResult {
+            name: name.to_string(),
+            nodes: 40,
+            layout: Duration::from_millis(layout),
+            paint: Duration::from_millis(paint),
+        };
+        let baseline = BenchReport { results: vec![result("deep-10", 10, 20), result("wide-10", 10, 20)] };
+        let current = BenchReport { results: vec![result("deep-10", 11, 19), result("wide-10", 20, 20), result("text-5", 50, 50)] };
+
+        // Then: The baseline survives JSON, and only the doubled stage is
+        // flagged; the new case has nothing to compare against
+        assert_eq!(BenchReport::from_json(&baseline.to_json()).unwrap(), baseline);
+        let regressions = current.regressions(&baseline, 20.0);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].to_string(), "wide-10 layout: 10.000ms -> 20.000ms (+100%)");
+        assert!(BenchReport::from_json("{\"cases\": [{\"name\": \"x\"}]}").is_err());
+    }
+
+    #[test]
+    fn test_run_case_measures_both_stages() {
+        let result = run_case(&BenchCase::new(BenchDocument::Text, 3), 2, 400.0, 300.0, 1);
+        assert_eq!(result.name, "text-3");
+        assert!(result.nodes > 3);
+        assert!(result.layout > Duration::ZERO && result.paint > Duration::ZERO);
+    }
+}
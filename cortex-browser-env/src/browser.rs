@@ -0,0 +1,4196 @@
+//! Browser and Page
+//! Embeddable facade over the engine: load HTML, run scripts, query the DOM,
+//! take screenshots and run tests without re-plumbing parser, style, layout
+//! and render by hand. `PageBuilder` configures a page's environment.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::ffi::CString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use raqote::DrawTarget;
+use rquickjs::convert::Coerced;
+use rquickjs::function::Opt;
+use rquickjs::{qjs, AsyncContext, AsyncRuntime, Ctx, Exception, Function, Module, Object, Value};
+
+use crate::animation::{self, AnimationTimeline, FRAME_INTERVAL_MS};
+use crate::clipboard::{self, Clipboard, ClipboardAction};
+use crate::console::{self, ConsoleBuffer, ConsoleEntry, ConsoleLevel};
+use crate::css::{self, ComputedStyle, StyleSheet};
+use crate::custom_elements::CustomElementRegistry;
+use crate::encoding;
+use crate::device::{Device, LayoutViewport, Navigator, ViewportMeta};
+use crate::display_list::DisplayList;
+use crate::dom::{self, Document, DocumentStats, NodeData, NodeId};
+use crate::element::{self, ElementRef};
+use crate::error::{BrowserError, TestResult, TestSummary};
+use crate::event_loop::{ready, Clock, EventLoop};
+use crate::event_source::{self, MockEventSourceServer, PageEventStreams};
+use crate::failure_capture::{capture_on_failure, FailureCaptureConfig};
+use crate::files::{self, InputFile};
+use crate::fonts::{self, parse_canvas_font, FontFaceLoad, FontManager, WebFonts};
+use crate::geometry::{Point, Rect};
+use crate::golden::{SnapshotConfig, SnapshotOutcome};
+use crate::handles::JsNode;
+use crate::host::{self, HostFunctions};
+use crate::image_diff::Image;
+use crate::frames::{self, Frame, FrameLoad, FrameParent, FrameSource, PageFrames, DEFAULT_FRAME_SIZE, MAX_FRAME_DEPTH};
+use crate::limits::{Limits, Watchdog};
+use crate::inspect::{self, InspectedNode};
+use crate::images::{load_document_images, DecodedImage, ImageCache, ImageLoad};
+pub use crate::media::ColorScheme;
+use crate::media::{self, MediaEnvironment};
+use crate::messaging::{self, PageMessages};
+use crate::modules::{self, LoadedModules, ModuleConfig};
+use crate::navigation::NavigationRequest;
+use crate::network::{self, url_origin, BaseUrlLoader, FileLoader, OfflineLoader, ResourceLoader};
+use crate::pdf::{self, PdfOptions};
+use crate::render::IncrementalRenderer;
+use crate::screenshot::ImageFormat;
+use crate::scripts::{PageScripts, ScriptLoad};
+use crate::seed::{self, RunSeed, DEFAULT_FROZEN_TIME, DETERMINISTIC_SEED};
+use crate::selection::{self, BoundaryPoint, PageSelection, Range};
+use crate::stack_trace::{self, SourceMap, SourceMaps};
+use crate::timers::{self, PageTimers};
+use crate::trace::{TraceStage, Tracer};
+use crate::user_events::{Activation, FormSubmission, KeyboardState, MouseEventInit, MouseState};
+use crate::websocket::{self, MockWebSocketServer, PageSockets};
+use crate::workers::{self, PageWorkers};
+use crate::{
+    debug_overlay, editing, events, forms, head, layout, parser, queries, query, render, screenshot, scripts, snapshot, style, test_runner, transform, transpile, user_events,
+    validation,
+};
+
+/// Page loaded before any HTML is given
+pub const BLANK_PAGE: &str = "<html><head></head><body></body></html>";
+
+/// File name stack frames give scripts run without one
+pub const SCRIPT_FILE_NAME: &str = "<script>";
+
+/// Extends `expect` to nodes, as the query functions return them:
+/// `expect(node).toBeVisible()` and `expect(node).not.toBeVisible()`
+const ELEMENT_MATCHERS: &str = r#"
+(function () {
+    const isVisible = globalThis.__cortexIsVisible;
+    delete globalThis.__cortexIsVisible;
+    const expectConsole = globalThis.expect;
+
+    function matchers(node, negate) {
+        return {
+            toBeVisible() {
+                if (isVisible(node) !== negate) return;
+                throw new Error("Expected node " + node + (negate ? " not to be visible, but it is" : " to be visible, but it is hidden"));
+            },
+        };
+    }
+
+    globalThis.expect = function (actual) {
+        if (typeof actual !== "number") return expectConsole(actual);
+        const result = matchers(actual, false);
+        result.not = matchers(actual, true);
+        return result;
+    };
+})();
+"#;
+
+/// Viewport used unless configured otherwise
+pub const DEFAULT_VIEWPORT: Viewport = Viewport { width: 1280, height: 720 };
+
+/// Initial font size unless configured otherwise
+pub const DEFAULT_ROOT_FONT_SIZE: f32 = 16.0;
+
+/// User agent reported to scripts unless configured otherwise
+pub const DEFAULT_USER_AGENT: &str =
+    concat!("Mozilla/5.0 (compatible; cortex-browser-env/", env!("CARGO_PKG_VERSION"), ")");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Where a page's images, fonts and other resources come from
+///
+/// `data:` URIs always load, whatever the mode.
+#[derive(Clone, Default)]
+pub enum NetworkMode {
+    /// Nothing but `data:` URIs loads
+    #[default]
+    Offline,
+    /// Relative and `file://` URLs are read from this directory
+    FileSystem(PathBuf),
+    /// Any loader, e.g. a shared `MockNetwork` the test inspects afterwards
+    Custom(Rc<dyn ResourceLoader>),
+}
+
+impl NetworkMode {
+    pub(crate) fn loader(&self) -> Rc<dyn ResourceLoader> {
+        match self {
+            NetworkMode::Offline => Rc::new(OfflineLoader),
+            NetworkMode::FileSystem(dir) => Rc::new(FileLoader::new(dir.clone())),
+            NetworkMode::Custom(loader) => loader.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for NetworkMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkMode::Offline => write!(f, "Offline"),
+            NetworkMode::FileSystem(dir) => f.debug_tuple("FileSystem").field(dir).finish(),
+            NetworkMode::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// Settings shared by every page a browser opens
+#[derive(Debug, Clone)]
+pub struct Browser {
+    pub viewport: Viewport,
+    /// Device pixels per CSS pixel in screenshots
+    pub device_pixel_ratio: f32,
+    /// What `navigator` reports
+    pub navigator: Navigator,
+    /// Lay pages out by their `<meta name="viewport">`, as phones do
+    pub mobile: bool,
+    /// The initial font size: the root element's unless the page sets one,
+    /// and what `rem` is relative to
+    pub root_font_size: f32,
+    /// Font files for characters the page's fonts lack, e.g. an emoji font
+    pub fallback_fonts: Vec<Vec<u8>>,
+    pub color_scheme: ColorScheme,
+    /// `prefers-reduced-motion: reduce`
+    pub reduced_motion: bool,
+    /// Run CSS transitions and animations; when off they jump to their end
+    /// state, keeping screenshots deterministic
+    pub animations: bool,
+    /// Run seed; resolved from `CORTEX_SEED` or the clock when unset
+    pub seed: Option<u64>,
+    /// Milliseconds since the Unix epoch `Date` is frozen at; the real
+    /// clock when unset
+    pub frozen_time: Option<f64>,
+    pub failure_capture: FailureCaptureConfig,
+    /// Fail tests that pass but leave new globals or queued tasks behind
+    pub detect_leaks: bool,
+    /// Paint the debug overlay of layout boxes over every render; see
+    /// `debug_overlay`
+    pub debug_boxes: bool,
+    /// Threads for style and layout of large documents, 0 for one per
+    /// core; see `parallel`
+    pub threads: usize,
+    /// Golden masters `expectScreenshot` checks against
+    pub snapshots: SnapshotConfig,
+    /// Where ES modules are loaded from; pages share its source cache
+    pub modules: ModuleConfig,
+    /// Peer of every page's WebSockets
+    pub websockets: MockWebSocketServer,
+    /// Server of every page's EventSources
+    pub event_sources: MockEventSourceServer,
+    /// Clipboard every page copies to and pastes from
+    pub clipboard: Clipboard,
+    /// Where every page records its pipeline spans
+    pub tracer: Tracer,
+    /// What every page's scripts and documents may use
+    pub limits: Limits,
+}
+
+impl Browser {
+    pub fn new() -> Self {
+        Browser {
+            viewport: DEFAULT_VIEWPORT,
+            device_pixel_ratio: 1.0,
+            navigator: Navigator::default(),
+            mobile: false,
+            root_font_size: DEFAULT_ROOT_FONT_SIZE,
+            fallback_fonts: Vec::new(),
+            color_scheme: ColorScheme::default(),
+            reduced_motion: false,
+            animations: true,
+            seed: None,
+            frozen_time: None,
+            failure_capture: FailureCaptureConfig::disabled(),
+            detect_leaks: false,
+            debug_boxes: false,
+            threads: 0,
+            snapshots: SnapshotConfig::default(),
+            modules: ModuleConfig::default(),
+            websockets: MockWebSocketServer::new(),
+            event_sources: MockEventSourceServer::new(),
+            clipboard: Clipboard::new(),
+            tracer: Tracer::disabled(),
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn with_viewport(mut self, width: i32, height: i32) -> Self {
+        self.viewport = Viewport { width, height };
+        self
+    }
+
+    pub fn with_device_pixel_ratio(mut self, ratio: f32) -> Self {
+        self.device_pixel_ratio = ratio;
+        self
+    }
+
+    pub fn with_navigator(mut self, navigator: Navigator) -> Self {
+        self.navigator = navigator;
+        self
+    }
+
+    /// Emulate `device`: its viewport, pixel ratio, navigator and whether
+    /// it honors `<meta name="viewport">`
+    pub fn with_device(self, device: Device) -> Self {
+        self.with_viewport(device.viewport().width, device.viewport().height)
+            .with_device_pixel_ratio(device.device_pixel_ratio())
+            .with_navigator(device.navigator())
+            .with_mobile(device.is_mobile())
+    }
+
+    pub fn with_mobile(mut self, mobile: bool) -> Self {
+        self.mobile = mobile;
+        self
+    }
+
+    pub fn with_root_font_size(mut self, font_size: f32) -> Self {
+        self.root_font_size = font_size;
+        self
+    }
+
+    pub fn with_fallback_fonts(mut self, fonts: Vec<Vec<u8>>) -> Self {
+        self.fallback_fonts = fonts;
+        self
+    }
+
+    pub fn with_color_scheme(mut self, color_scheme: ColorScheme) -> Self {
+        self.color_scheme = color_scheme;
+        self
+    }
+
+    pub fn with_reduced_motion(mut self, reduced_motion: bool) -> Self {
+        self.reduced_motion = reduced_motion;
+        self
+    }
+
+    pub fn with_animations(mut self, animations: bool) -> Self {
+        self.animations = animations;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Freeze `Date` at `time`, in milliseconds since the Unix epoch
+    pub fn with_frozen_time(mut self, time: f64) -> Self {
+        self.frozen_time = Some(time);
+        self
+    }
+
+    /// Deterministic mode: seed with `DETERMINISTIC_SEED` and freeze `Date`
+    /// at `DEFAULT_FROZEN_TIME`, unless a seed or time is already set
+    pub fn with_determinism(mut self) -> Self {
+        self.seed.get_or_insert(DETERMINISTIC_SEED);
+        self.frozen_time.get_or_insert(DEFAULT_FROZEN_TIME);
+        self
+    }
+
+    pub fn with_failure_capture(mut self, config: FailureCaptureConfig) -> Self {
+        self.failure_capture = config;
+        self
+    }
+
+    pub fn with_leak_detection(mut self, detect_leaks: bool) -> Self {
+        self.detect_leaks = detect_leaks;
+        self
+    }
+
+    pub fn with_debug_boxes(mut self, debug_boxes: bool) -> Self {
+        self.debug_boxes = debug_boxes;
+        self
+    }
+
+    /// Style and lay out large documents on `threads` threads: 0 for one
+    /// per core, 1 to run everything on the calling thread
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_snapshots(mut self, config: SnapshotConfig) -> Self {
+        self.snapshots = config;
+        self
+    }
+
+    pub fn with_modules(mut self, config: ModuleConfig) -> Self {
+        self.modules = config;
+        self
+    }
+
+    pub fn with_websockets(mut self, server: MockWebSocketServer) -> Self {
+        self.websockets = server;
+        self
+    }
+
+    pub fn with_event_sources(mut self, server: MockEventSourceServer) -> Self {
+        self.event_sources = server;
+        self
+    }
+
+    pub fn with_clipboard(mut self, clipboard: Clipboard) -> Self {
+        self.clipboard = clipboard;
+        self
+    }
+
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// A page builder starting from this browser's settings
+    pub fn page_builder(&self) -> PageBuilder {
+        PageBuilder {
+            viewport: self.viewport,
+            device_pixel_ratio: self.device_pixel_ratio,
+            navigator: self.navigator.clone(),
+            mobile: self.mobile,
+            root_font_size: self.root_font_size,
+            fallback_fonts: self.fallback_fonts.clone(),
+            color_scheme: self.color_scheme,
+            reduced_motion: self.reduced_motion,
+            animations: self.animations,
+            seed: self.seed,
+            frozen_time: self.frozen_time,
+            failure_capture: self.failure_capture.clone(),
+            detect_leaks: self.detect_leaks,
+            debug_boxes: self.debug_boxes,
+            threads: self.threads,
+            snapshots: self.snapshots.clone(),
+            modules: self.modules.clone(),
+            websockets: self.websockets.clone(),
+            event_sources: self.event_sources.clone(),
+            clipboard: self.clipboard.clone(),
+            tracer: self.tracer.clone(),
+            limits: self.limits,
+            ..PageBuilder::new()
+        }
+    }
+
+    /// Open a page showing `BLANK_PAGE`, with its own JavaScript context
+    pub fn new_page(&self) -> Result<Page, BrowserError> {
+        self.page_builder().build()
+    }
+}
+
+impl Default for Browser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configuration for one page
+#[derive(Debug, Clone)]
+pub struct PageBuilder {
+    pub viewport: Viewport,
+    /// Device pixels per CSS pixel: layout stays in CSS pixels while
+    /// screenshots are rasterized this many times larger;
+    /// `window.devicePixelRatio`
+    pub device_pixel_ratio: f32,
+    /// What `navigator` reports; also whether `(pointer: coarse)` matches
+    pub navigator: Navigator,
+    /// Lay the page out by its `<meta name="viewport">`, zoomed to fit the
+    /// viewport, as phones do
+    pub mobile: bool,
+    /// The initial font size: the root element's unless the page sets one,
+    /// and what `rem` is relative to
+    pub root_font_size: f32,
+    /// URL relative resource URLs resolve against; also `location.href`
+    pub base_url: Option<String>,
+    pub network: NetworkMode,
+    /// Fonts available to the page in addition to `@font-face` rules, as
+    /// (family, font file bytes)
+    pub fonts: Vec<(String, Vec<u8>)>,
+    /// Font files for characters the page's fonts lack, tried in order
+    pub fallback_fonts: Vec<Vec<u8>>,
+    pub color_scheme: ColorScheme,
+    /// `prefers-reduced-motion: reduce`
+    pub reduced_motion: bool,
+    /// Run CSS transitions and animations; when off they jump to their end
+    /// state
+    pub animations: bool,
+    /// Run seed; resolved from `CORTEX_SEED` or the clock when unset
+    pub seed: Option<u64>,
+    /// Milliseconds since the Unix epoch `Date` is frozen at; the real
+    /// clock when unset
+    pub frozen_time: Option<f64>,
+    pub failure_capture: FailureCaptureConfig,
+    /// Fail tests that pass but leave new globals or queued tasks behind
+    pub detect_leaks: bool,
+    /// Paint the debug overlay of layout boxes over every render; see
+    /// `debug_overlay`
+    pub debug_boxes: bool,
+    /// Threads for style and layout of large documents, 0 for one per
+    /// core; see `parallel`
+    pub threads: usize,
+    pub snapshots: SnapshotConfig,
+    /// Where `import` loads ES modules from
+    pub modules: ModuleConfig,
+    /// Peer `new WebSocket(url)` connects to, whatever the URL
+    pub websockets: MockWebSocketServer,
+    /// Streams `new EventSource(url)` reads, before asking the network
+    pub event_sources: MockEventSourceServer,
+    /// Behind `navigator.clipboard` and `Page::copy`/`cut`/`paste`
+    pub clipboard: Clipboard,
+    /// Records how long parsing, style, layout, paint, scripts and encoding
+    /// take; disabled by default
+    pub tracer: Tracer,
+    /// Functions scripts can await as `host.name(...)`
+    pub host_functions: HostFunctions,
+    /// What the page's scripts and document may use; see `limits`
+    pub limits: Limits,
+}
+
+impl PageBuilder {
+    pub fn new() -> Self {
+        PageBuilder {
+            viewport: DEFAULT_VIEWPORT,
+            device_pixel_ratio: 1.0,
+            navigator: Navigator::default(),
+            mobile: false,
+            root_font_size: DEFAULT_ROOT_FONT_SIZE,
+            base_url: None,
+            network: NetworkMode::default(),
+            fonts: Vec::new(),
+            fallback_fonts: Vec::new(),
+            color_scheme: ColorScheme::default(),
+            reduced_motion: false,
+            animations: true,
+            seed: None,
+            frozen_time: None,
+            failure_capture: FailureCaptureConfig::disabled(),
+            detect_leaks: false,
+            debug_boxes: false,
+            threads: 0,
+            snapshots: SnapshotConfig::default(),
+            modules: ModuleConfig::default(),
+            websockets: MockWebSocketServer::new(),
+            event_sources: MockEventSourceServer::new(),
+            clipboard: Clipboard::new(),
+            tracer: Tracer::disabled(),
+            host_functions: HostFunctions::new(),
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn with_viewport(mut self, width: i32, height: i32) -> Self {
+        self.viewport = Viewport { width, height };
+        self
+    }
+
+    pub fn with_device_pixel_ratio(mut self, ratio: f32) -> Self {
+        self.device_pixel_ratio = ratio;
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.navigator.user_agent = user_agent.to_string();
+        self
+    }
+
+    pub fn with_navigator(mut self, navigator: Navigator) -> Self {
+        self.navigator = navigator;
+        self
+    }
+
+    /// Emulate `device`: its viewport, pixel ratio, navigator and whether
+    /// it honors `<meta name="viewport">`
+    pub fn with_device(self, device: Device) -> Self {
+        self.with_viewport(device.viewport().width, device.viewport().height)
+            .with_device_pixel_ratio(device.device_pixel_ratio())
+            .with_navigator(device.navigator())
+            .with_mobile(device.is_mobile())
+    }
+
+    pub fn with_mobile(mut self, mobile: bool) -> Self {
+        self.mobile = mobile;
+        self
+    }
+
+    pub fn with_root_font_size(mut self, font_size: f32) -> Self {
+        self.root_font_size = font_size;
+        self
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_network(mut self, network: NetworkMode) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn with_font(mut self, family: &str, bytes: impl Into<Vec<u8>>) -> Self {
+        self.fonts.push((family.to_string(), bytes.into()));
+        self
+    }
+
+    /// Add a font to draw characters in when the text's own font lacks
+    /// them, e.g. an emoji font
+    pub fn with_fallback_font(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.fallback_fonts.push(bytes.into());
+        self
+    }
+
+    pub fn with_color_scheme(mut self, color_scheme: ColorScheme) -> Self {
+        self.color_scheme = color_scheme;
+        self
+    }
+
+    pub fn with_reduced_motion(mut self, reduced_motion: bool) -> Self {
+        self.reduced_motion = reduced_motion;
+        self
+    }
+
+    pub fn with_animations(mut self, animations: bool) -> Self {
+        self.animations = animations;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Freeze `Date` at `time`, in milliseconds since the Unix epoch
+    pub fn with_frozen_time(mut self, time: f64) -> Self {
+        self.frozen_time = Some(time);
+        self
+    }
+
+    /// Deterministic mode: seed with `DETERMINISTIC_SEED` and freeze `Date`
+    /// at `DEFAULT_FROZEN_TIME`, unless a seed or time is already set
+    pub fn with_determinism(mut self) -> Self {
+        self.seed.get_or_insert(DETERMINISTIC_SEED);
+        self.frozen_time.get_or_insert(DEFAULT_FROZEN_TIME);
+        self
+    }
+
+    pub fn with_failure_capture(mut self, config: FailureCaptureConfig) -> Self {
+        self.failure_capture = config;
+        self
+    }
+
+    pub fn with_leak_detection(mut self, detect_leaks: bool) -> Self {
+        self.detect_leaks = detect_leaks;
+        self
+    }
+
+    pub fn with_debug_boxes(mut self, debug_boxes: bool) -> Self {
+        self.debug_boxes = debug_boxes;
+        self
+    }
+
+    /// Style and lay out large documents on `threads` threads: 0 for one
+    /// per core, 1 to run everything on the calling thread
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_snapshots(mut self, config: SnapshotConfig) -> Self {
+        self.snapshots = config;
+        self
+    }
+
+    pub fn with_modules(mut self, config: ModuleConfig) -> Self {
+        self.modules = config;
+        self
+    }
+
+    pub fn with_websockets(mut self, server: MockWebSocketServer) -> Self {
+        self.websockets = server;
+        self
+    }
+
+    pub fn with_event_sources(mut self, server: MockEventSourceServer) -> Self {
+        self.event_sources = server;
+        self
+    }
+
+    pub fn with_clipboard(mut self, clipboard: Clipboard) -> Self {
+        self.clipboard = clipboard;
+        self
+    }
+
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    pub fn with_host_functions(mut self, functions: HostFunctions) -> Self {
+        self.host_functions = functions;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Open the page, showing `BLANK_PAGE`, with its own JavaScript context
+    pub fn build(self) -> Result<Page, BrowserError> {
+        self.build_frame(None, 0)
+    }
+
+    /// `build` for a page nested `depth` frames deep in `parent`
+    fn build_frame(self, parent: Option<FrameParent>, depth: usize) -> Result<Page, BrowserError> {
+        let settings = self.clone();
+        let js_error = |e: rquickjs::Error| BrowserError::JavaScriptError(e.to_string(), None);
+        let seed = RunSeed::resolve_with(self.seed).map_err(BrowserError::InvalidOperationError)?;
+        if !(self.device_pixel_ratio.is_finite() && self.device_pixel_ratio > 0.0) {
+            return Err(BrowserError::InvalidOperationError(format!(
+                "Device pixel ratio must be positive, got {}",
+                self.device_pixel_ratio
+            )));
+        }
+        if !(self.root_font_size.is_finite() && self.root_font_size > 0.0) {
+            return Err(BrowserError::InvalidOperationError(format!("Root font size must be positive, got {}", self.root_font_size)));
+        }
+        let mut fonts = FontManager::new().map_err(BrowserError::RenderError)?;
+        for (family, bytes) in &self.fonts {
+            fonts.add_font(family, bytes).map_err(BrowserError::RenderError)?;
+        }
+        for bytes in &self.fallback_fonts {
+            fonts.add_fallback_font(bytes).map_err(BrowserError::RenderError)?;
+        }
+        let loader: Rc<dyn ResourceLoader> = match &self.base_url {
+            Some(base_url) => Rc::new(BaseUrlLoader { base_url: base_url.clone(), inner: self.network.loader() }),
+            None => self.network.loader(),
+        };
+
+        let runtime = AsyncRuntime::new().map_err(js_error)?;
+        let loaded_modules = modules::install_module_loader(&runtime, &self.modules);
+        let context = ready(AsyncContext::full(&runtime)).map_err(js_error)?;
+        let clock = Clock::new();
+        let sockets = self.websockets.attach();
+        let streams = self.event_sources.attach(loader.clone(), self.base_url.clone()).with_clock(clock.clone());
+        let media = MediaEnvironment {
+            width: self.viewport.width as f32,
+            height: self.viewport.height as f32,
+            device_pixel_ratio: self.device_pixel_ratio,
+            color_scheme: self.color_scheme,
+            touch: self.navigator.is_touch(),
+            reduced_motion: self.reduced_motion,
+            font_size: self.root_font_size,
+        };
+        let document = Rc::new(RefCell::new(parser::parse_html(BLANK_PAGE)));
+        let selection = PageSelection::new(document.clone());
+        let console = ConsoleBuffer::default();
+        let scripts = PageScripts::new(document.clone(), loader.clone(), console.clone());
+        let origin = url_origin(self.base_url.as_deref().unwrap_or("about:blank"));
+        let messages = match &parent {
+            Some(parent) => parent.messages.for_frame(document.clone(), origin),
+            None => PageMessages::new(document.clone(), origin),
+        };
+        let frames = PageFrames::default();
+        let mut workers = PageWorkers::new(loader.clone(), self.base_url.clone(), console.clone()).with_seed(seed).with_limits(self.limits);
+        if let Some(time) = self.frozen_time {
+            workers = workers.with_frozen_time(time);
+        }
+        let host_functions = self.host_functions;
+        let timers = PageTimers::new(clock.clone());
+        let page = Page {
+            viewport: self.viewport,
+            device_pixel_ratio: self.device_pixel_ratio,
+            navigator: self.navigator,
+            mobile: self.mobile,
+            base_url: self.base_url,
+            media: Rc::new(Cell::new(media)),
+            animations: Cell::new(self.animations),
+            timeline: Rc::new(RefCell::new(AnimationTimeline::new().with_clock(clock.clone()))),
+            mouse: RefCell::new(MouseState::default()),
+            keyboard: RefCell::new(KeyboardState::default()),
+            navigations: RefCell::new(Vec::new()),
+            frames: frames.clone(),
+            messages: messages.clone(),
+            workers: workers.clone(),
+            frame_depth: depth,
+            settings,
+            selection: selection.clone(),
+            seed,
+            frozen_time: self.frozen_time,
+            failure_capture: self.failure_capture.with_viewport(self.viewport.width, self.viewport.height),
+            detect_leaks: self.detect_leaks,
+            debug_boxes: self.debug_boxes,
+            threads: self.threads,
+            snapshots: self.snapshots,
+            modules: self.modules,
+            loaded_modules,
+            loader,
+            fonts: Rc::new(RefCell::new(fonts)),
+            images: Rc::new(RefCell::new(ImageCache::new())),
+            document,
+            stylesheet: Rc::new(RefCell::new(StyleSheet { media, ..StyleSheet::default() })),
+            reported: Rc::new(RefCell::new(Vec::new())),
+            console,
+            source_maps: RefCell::new(SourceMaps::new()),
+            frame: RefCell::new(IncrementalRenderer::new(
+                Rect::new(0.0, 0.0, self.viewport.width as f32, self.viewport.height as f32),
+                self.device_pixel_ratio,
+            )),
+            event_loop: EventLoop::new()
+                .with_clock(clock)
+                .with_source(Rc::new(scripts.clone()))
+                .with_source(Rc::new(sockets.clone()))
+                .with_source(Rc::new(streams.clone()))
+                .with_source(Rc::new(timers.clone()))
+                .with_source(Rc::new(selection))
+                .with_source(Rc::new(messages))
+                .with_source(Rc::new(frames))
+                .with_source(Rc::new(workers)),
+            scripts,
+            sockets,
+            streams,
+            timers,
+            clipboard: self.clipboard,
+            tracer: self.tracer,
+            limits: self.limits,
+            watchdog: Watchdog::new(),
+            context,
+            runtime,
+        };
+        page.with(|ctx| {
+                install_globals(&ctx, &page)?;
+                messaging::install_messaging(&ctx, page.messages.clone())?;
+                workers::install_workers(&ctx, page.workers.clone())?;
+                host::install_host(&ctx, host_functions)?;
+                frames::install_frames(&ctx, page.frames.clone(), &page.messages, parent)
+            })
+            .map_err(js_error)?;
+        page.install_limits();
+        Ok(page)
+    }
+}
+
+impl Default for PageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resources fetched while loading a page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageLoad {
+    pub fonts: Vec<FontFaceLoad>,
+    pub images: Vec<ImageLoad>,
+    pub scripts: Vec<ScriptLoad>,
+    pub frames: Vec<FrameLoad>,
+    /// The limit the document or its scripts ran into while loading; a
+    /// document over the node limit is replaced by `BLANK_PAGE`
+    pub limit_exceeded: Option<BrowserError>,
+}
+
+/// A document with its styles, resources, JavaScript context and job queue
+///
+/// Scripts see the browser globals (`console`, `navigator`, `location`,
+/// `matchMedia`, `requestAnimationFrame`, `customElements`, `WebSocket`,
+/// `EventSource`, form and query bindings, the embedder's `host` functions,
+/// `describe`/`it`, ...). Loading new HTML keeps the context, so globals
+/// defined by earlier scripts survive.
+pub struct Page {
+    viewport: Viewport,
+    device_pixel_ratio: f32,
+    navigator: Navigator,
+    /// Lay out by `<meta name="viewport">`
+    mobile: bool,
+    base_url: Option<String>,
+    /// What media queries are evaluated against; shared with `matchMedia`
+    media: Rc<Cell<MediaEnvironment>>,
+    animations: Cell<bool>,
+    /// Running transitions and animations, and the time
+    /// `requestAnimationFrame` and `performance.now()` report
+    timeline: Rc<RefCell<AnimationTimeline>>,
+    /// Where the simulated pointer is and what it holds down
+    mouse: RefCell<MouseState>,
+    /// Focus, held keys and submitted forms
+    keyboard: RefCell<KeyboardState>,
+    /// Links followed, in order; never loaded
+    navigations: RefCell<Vec<NavigationRequest>>,
+    /// Pages shown by the document's iframes
+    frames: PageFrames,
+    /// Messages posted to the document by its frames or its parent
+    messages: PageMessages,
+    /// Threads running the page's `Worker`s
+    workers: PageWorkers,
+    /// How many frames this page is nested in
+    frame_depth: usize,
+    /// What the page was built with, for opening its frames
+    settings: PageBuilder,
+    /// The selection, shared with `getSelection()`
+    selection: PageSelection,
+    /// Which `<script>` elements ran, and the one running
+    scripts: PageScripts,
+    seed: RunSeed,
+    /// See `PageBuilder::frozen_time`
+    frozen_time: Option<f64>,
+    failure_capture: FailureCaptureConfig,
+    detect_leaks: bool,
+    debug_boxes: bool,
+    /// Threads for style and layout; see `PageBuilder::with_threads`
+    threads: usize,
+    snapshots: SnapshotConfig,
+    modules: ModuleConfig,
+    /// Files `import` has read modules from
+    loaded_modules: LoadedModules,
+    /// Network mode behind the base URL
+    loader: Rc<dyn ResourceLoader>,
+    fonts: Rc<RefCell<FontManager>>,
+    images: Rc<RefCell<ImageCache>>,
+    document: Rc<RefCell<Document>>,
+    stylesheet: Rc<RefCell<StyleSheet>>,
+    /// Results scripts reported with `reportTestResult`
+    reported: Rc<RefCell<Vec<TestResult>>>,
+    /// What scripts logged to the console
+    console: ConsoleBuffer,
+    /// Source maps for the scripts run, by file name
+    source_maps: RefCell<SourceMaps>,
+    /// The last viewport frame, kept for incremental updates
+    frame: RefCell<IncrementalRenderer>,
+    /// This page's WebSocket connections
+    sockets: PageSockets,
+    /// This page's EventSource streams
+    streams: PageEventStreams,
+    /// This page's `setTimeout` and `setInterval` timers
+    timers: PageTimers,
+    clipboard: Clipboard,
+    tracer: Tracer,
+    /// Tasks to run once the job queue is empty
+    event_loop: EventLoop,
+    limits: Limits,
+    /// Times scripts against `limits.script_timeout`
+    watchdog: Rc<Watchdog>,
+    context: AsyncContext,
+    runtime: AsyncRuntime,
+}
+
+impl Page {
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    pub fn device_pixel_ratio(&self) -> f32 {
+        self.device_pixel_ratio
+    }
+
+    /// The size the document is laid out at and its zoom: the viewport,
+    /// unless a mobile page's `<meta name="viewport">` asks otherwise
+    pub fn layout_viewport(&self) -> LayoutViewport {
+        layout_viewport(&self.document.borrow(), self.viewport, self.mobile)
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.navigator.user_agent
+    }
+
+    pub fn navigator(&self) -> &Navigator {
+        &self.navigator
+    }
+
+    /// What the page's media queries are evaluated against
+    pub fn media(&self) -> MediaEnvironment {
+        self.media.get()
+    }
+
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+
+    pub fn color_scheme(&self) -> ColorScheme {
+        self.media.get().color_scheme
+    }
+
+    /// Switch between light and dark mode, as the OS setting would
+    ///
+    /// `@media (prefers-color-scheme)` rules apply from the next render,
+    /// and `matchMedia` lists whose result changed fire `change`.
+    pub fn set_color_scheme(&self, color_scheme: ColorScheme) -> Result<(), BrowserError> {
+        self.set_media(MediaEnvironment { color_scheme, ..self.media.get() })
+    }
+
+    pub fn reduced_motion(&self) -> bool {
+        self.media.get().reduced_motion
+    }
+
+    /// Turn `prefers-reduced-motion: reduce` on or off, as the OS setting
+    /// would; takes effect like `set_color_scheme`
+    pub fn set_reduced_motion(&self, reduced_motion: bool) -> Result<(), BrowserError> {
+        self.set_media(MediaEnvironment { reduced_motion, ..self.media.get() })
+    }
+
+    fn set_media(&self, media: MediaEnvironment) -> Result<(), BrowserError> {
+        self.media.set(media);
+        self.stylesheet.borrow_mut().media = media;
+        self.with(|ctx| media::notify_media_change(&ctx).map_err(|_| pending_exception(&ctx)))?;
+        self.run_until_idle()?;
+        Ok(())
+    }
+
+    /// Whether CSS transitions and animations run, rather than jumping to
+    /// their end state
+    pub fn animations(&self) -> bool {
+        self.animations.get()
+    }
+
+    pub fn set_animations(&self, animations: bool) {
+        self.animations.set(animations);
+    }
+
+    /// Time since the page opened on its clock, which only moves with
+    /// `advance_frame` and `advance_time`
+    pub fn animation_time(&self) -> Duration {
+        self.timeline.borrow().now()
+    }
+
+    /// Transitions and animations still running
+    pub fn running_animations(&self) -> usize {
+        self.timeline.borrow().running()
+    }
+
+    /// Render one frame, 1/60 s after the last: run `requestAnimationFrame`
+    /// callbacks, advance transitions and animations, then dispatch their
+    /// events
+    pub fn advance_frame(&self) -> Result<(), BrowserError> {
+        self.step_animations(FRAME_INTERVAL_MS)
+    }
+
+    /// Advance the page's clock by `duration`, a frame at a time, returning
+    /// how many frames ran
+    ///
+    /// The last frame is shortened so the clock moves by exactly
+    /// `duration`. Timed tasks, such as an EventSource waiting to
+    /// reconnect, run once the frame that reaches their time has.
+    pub fn advance_time(&self, duration: Duration) -> Result<usize, BrowserError> {
+        // Each frame steps to a time measured from the start rather than
+        // summing steps, so the clock lands on the end exactly and timed
+        // tasks due then run
+        let start = self.timeline.borrow().now_ms();
+        let total = duration.as_secs_f64() * 1000.0;
+        let mut frames = 0;
+        while frames as f64 * FRAME_INTERVAL_MS < total - 1e-9 {
+            frames += 1;
+            let reached = start + (frames as f64 * FRAME_INTERVAL_MS).min(total);
+            let step = reached - self.timeline.borrow().now_ms();
+            self.step_animations(step)?;
+        }
+        Ok(frames)
+    }
+
+    fn step_animations(&self, advance_ms: f64) -> Result<(), BrowserError> {
+        let timestamp = self.timeline.borrow().now_ms() + advance_ms;
+        self.with(|ctx| animation::run_frame_callbacks(&ctx, timestamp).map_err(|_| pending_exception(&ctx)))?;
+        self.run_until_idle()?;
+        self.update_animations(advance_ms)
+    }
+
+    /// Move the timeline by `advance_ms` and dispatch what happened
+    fn update_animations(&self, advance_ms: f64) -> Result<(), BrowserError> {
+        let events = self.timeline.borrow_mut().update(
+            &mut self.document.borrow_mut(),
+            &self.stylesheet.borrow(),
+            advance_ms,
+            self.animations.get(),
+        );
+        self.with(|ctx| {
+            events
+                .iter()
+                .try_for_each(|event| animation::dispatch_animation_event(&ctx, &self.document, event).map(|_| ()))
+                .map_err(|_| pending_exception(&ctx))
+        })?;
+        self.run_until_idle()?;
+        Ok(())
+    }
+
+    pub fn seed(&self) -> RunSeed {
+        self.seed
+    }
+
+    /// Fonts available to the page, including loaded `@font-face` rules
+    pub fn fonts(&self) -> Ref<'_, FontManager> {
+        self.fonts.borrow()
+    }
+
+    /// Where the page records its pipeline spans
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    /// Node, attribute and listener counts, estimated heap use and the
+    /// glyph cache size; see `Document::stats`
+    pub fn stats(&self) -> DocumentStats {
+        let (glyphs, bytes) = self.fonts.borrow().cache_stats();
+        self.document.borrow().stats().with_glyph_cache(glyphs, bytes)
+    }
+
+    /// Replace the document, run its scripts and load its resources; its
+    /// `<style>` elements become the page styles
+    ///
+    /// `@font-face` fonts load before scripts run, so `document.fonts.ready`
+    /// has settled when they await it. Scripts that throw are reported on
+    /// the console and in `PageLoad::scripts` without stopping the load.
+    /// `DOMContentLoaded` fires at the document once scripts have run, and
+    /// `load` once images have loaded too, with `document.readyState`
+    /// moving from `loading` through `interactive` to `complete`.
+    pub fn load_html(&self, html: &str) -> PageLoad {
+        let document = self.tracer.span(TraceStage::Parse, "Parse HTML", || parser::parse_html(html));
+        let mut limit_exceeded = self.limits.check_nodes(document.nodes.len()).err();
+        let document = if limit_exceeded.is_some() { parser::parse_html(BLANK_PAGE) } else { document };
+        let mut css_text = String::new();
+        for (idx, node) in document.nodes.iter().enumerate() {
+            if matches!(&node.data, Some(NodeData::Element(elem)) if elem.tag_name == "style") {
+                css_text.push_str(&document.text_content(idx));
+                css_text.push('\n');
+            }
+        }
+        let mut stylesheet = self.tracer.span(TraceStage::Parse, "Parse CSS", || css::parse_css(&css_text));
+        // Media queries see the layout viewport, which the page may size
+        let layout_size = layout_viewport(&document, self.viewport, self.mobile);
+        self.media.set(MediaEnvironment { width: layout_size.width, height: layout_size.height, ..self.media.get() });
+        stylesheet.media = self.media.get();
+        *self.stylesheet.borrow_mut() = stylesheet;
+        *self.document.borrow_mut() = document;
+        let fonts = self.load_fonts();
+        {
+            let mut timeline = self.timeline.borrow_mut();
+            timeline.clear();
+            timeline.start(&mut self.document.borrow_mut(), &self.stylesheet.borrow(), self.animations.get());
+        }
+        *self.mouse.borrow_mut() = MouseState::default();
+        *self.keyboard.borrow_mut() = KeyboardState::default();
+        self.selection.reset();
+        self.scripts.reset();
+        self.set_ready_state("loading", None);
+        match self.tracer.span(TraceStage::Js, "Run scripts", || self.run_until_idle()) {
+            Err(e @ BrowserError::LimitExceeded(..)) => limit_exceeded = Some(e),
+            Err(e) => self.report_uncaught(e),
+            Ok(_) => {}
+        }
+        self.set_ready_state("interactive", Some("DOMContentLoaded"));
+        let frames = self.load_frames();
+        let images = self.load_images();
+        self.set_ready_state("complete", Some("load"));
+        PageLoad { fonts, images, scripts: self.scripts.take_loads(), frames, limit_exceeded }
+    }
+
+    /// Open a page for each iframe, sized to its content box, and fire
+    /// `load` or `error` at the iframe once its document has loaded
+    fn load_frames(&self) -> Vec<FrameLoad> {
+        self.frames.replace(Vec::new());
+        self.document.borrow_mut().clear_frame_images();
+        let iframes: Vec<usize> = {
+            let document = self.document.borrow();
+            (0..document.nodes.len()).filter(|&idx| frames::is_frame(&document, idx)).collect()
+        };
+        if iframes.is_empty() || self.frame_depth >= MAX_FRAME_DEPTH {
+            return Vec::new();
+        }
+        self.layout();
+        let mut loaded = Vec::new();
+        let mut loads = Vec::new();
+        for element in iframes {
+            let (source, size) = {
+                let document = self.document.borrow();
+                let size = document.layout(element).map(|layout| layout.content_box()).map(|rect| (rect.width, rect.height));
+                (FrameSource::of(&document, element, self.base_url.as_deref()), size.unwrap_or(DEFAULT_FRAME_SIZE))
+            };
+            let (url, html) = match &source {
+                FrameSource::SrcDoc(html) => (None, Ok(html.clone())),
+                FrameSource::Url(url) => (Some(url.clone()), network::fetch(&*self.loader, url).map(|bytes| encoding::decode(&bytes, None).0)),
+                FrameSource::Blank => (None, Ok(BLANK_PAGE.to_string())),
+            };
+            let result = html.as_ref().map(|_| ()).map_err(Clone::clone);
+            let mut settings = self.settings.clone().with_viewport(size.0.round() as i32, size.1.round() as i32).with_mobile(false);
+            settings.base_url = url.clone().or(settings.base_url);
+            let parent = FrameParent { messages: self.messages.clone(), element };
+            match settings.build_frame(Some(parent), self.frame_depth + 1) {
+                Ok(page) => {
+                    page.load_html(&html.unwrap_or_else(|_| BLANK_PAGE.to_string()));
+                    loaded.push(Frame { element, page });
+                }
+                Err(e) => self.report_uncaught(e),
+            }
+            loads.push(FrameLoad { element, url, result });
+        }
+        self.frames.replace(loaded);
+        for load in &loads {
+            self.fire_event(load.element, if load.result.is_ok() { "load" } else { "error" });
+        }
+        loads
+    }
+
+    /// Fire a trusted, non-bubbling event of `event_type` at `target`
+    fn fire_event(&self, target: usize, event_type: &str) {
+        let dispatched = self.with(|ctx| {
+            let fire = || -> rquickjs::Result<()> {
+                let event = events::create_event(&ctx, "Event", event_type, Object::new(ctx.clone())?)?;
+                event.set("isTrusted", true)?;
+                events::dispatch_event(&ctx, &self.document, target, event).map(|_| ())
+            };
+            fire().map_err(|_| pending_exception(&ctx))
+        });
+        if let Err(e) = dispatched.and_then(|_| self.run_until_idle().map(|_| ())) {
+            self.report_uncaught(e);
+        }
+    }
+
+    /// The iframes showing a loaded frame, in document order
+    pub fn frames(&self) -> Vec<usize> {
+        self.frames.borrow().iter().map(|frame| frame.element).collect()
+    }
+
+    /// The page the iframe `element` shows, if it has loaded
+    pub fn frame_page(&self, element: usize) -> Option<Ref<'_, Page>> {
+        Ref::filter_map(self.frames.borrow(), |frames| frames.iter().find(|frame| frame.element == element).map(|frame| &frame.page)).ok()
+    }
+
+    pub(crate) fn messages(&self) -> &PageMessages {
+        &self.messages
+    }
+
+    /// Rasterize each frame for the parent to draw; see
+    /// `Document::frame_images`
+    fn paint_frames(&self) {
+        let images: Vec<(usize, DecodedImage)> = self
+            .frames
+            .borrow()
+            .iter()
+            .map(|frame| {
+                let target = frame.page.render();
+                (frame.element, DecodedImage { width: target.width() as u32, height: target.height() as u32, pixels: target.get_data().to_vec() })
+            })
+            .collect();
+        let mut document = self.document.borrow_mut();
+        for (idx, image) in images {
+            document.set_frame_image(idx, image);
+        }
+    }
+
+    /// Fire `unload` at the document, as a browser does before leaving it
+    pub fn unload(&self) {
+        self.set_ready_state("complete", Some("unload"));
+    }
+
+    /// Set `document.readyState` and fire `event_type` at the document,
+    /// which stands in for `window` as the target of `load` and `unload`
+    fn set_ready_state(&self, ready_state: &str, event_type: Option<&str>) {
+        let dispatched = self.with(|ctx| {
+            let fire = || -> rquickjs::Result<()> {
+                let document: Object = ctx.globals().get("document")?;
+                document.set("readyState", ready_state)?;
+                let Some(event_type) = event_type else { return Ok(()) };
+                let init = Object::new(ctx.clone())?;
+                init.set("bubbles", event_type == "DOMContentLoaded")?;
+                let event = events::create_event(&ctx, "Event", event_type, init)?;
+                event.set("isTrusted", true)?;
+                let root = self.document.borrow().root;
+                events::dispatch_event(&ctx, &self.document, root, event).map(|_| ())
+            };
+            fire().map_err(|_| pending_exception(&ctx))
+        });
+        if let Err(e) = dispatched.and_then(|_| self.run_until_idle().map(|_| ())) {
+            self.report_uncaught(e);
+        }
+    }
+
+    pub(crate) fn report_uncaught(&self, error: BrowserError) {
+        self.console.borrow_mut().push(ConsoleEntry { level: ConsoleLevel::Error, message: format!("Uncaught {}", error) });
+    }
+
+    /// `load_html` for raw bytes read from a file or the network, decoded
+    /// as `encoding::decode` sniffs them; `content_type` is the transport's
+    /// `Content-Type`, if any
+    pub fn load_html_bytes(&self, bytes: &[u8], content_type: Option<&str>) -> PageLoad {
+        let (html, _) = encoding::decode(bytes, content_type);
+        self.load_html(&html)
+    }
+
+    /// Load `@font-face` fonts and the document's images through the
+    /// page's network mode
+    ///
+    /// `<img>` elements receive `load` or `error` events, which script
+    /// listeners and inline handlers see. Already loaded URLs are served
+    /// from the page's caches.
+    pub fn load_resources(&self) -> PageLoad {
+        PageLoad { fonts: self.load_fonts(), images: self.load_images(), ..PageLoad::default() }
+    }
+
+    fn load_fonts(&self) -> Vec<FontFaceLoad> {
+        self.fonts.borrow_mut().load_font_faces(&self.stylesheet.borrow(), &self.loader)
+    }
+
+    /// Load the document's images, then fire `load` or `error` at each
+    /// `<img>`
+    fn load_images(&self) -> Vec<ImageLoad> {
+        let loads = {
+            let document = self.document.borrow();
+            let styles = self.compute_styles(&document);
+            load_document_images(&document, &styles, &self.loader, &mut self.images.borrow_mut())
+        };
+        for load in &loads {
+            if let Some(event_type) = load.event_type() {
+                self.fire_event(load.node_idx, event_type);
+            }
+        }
+        loads
+    }
+
+    /// Append rules after the page's own, as a later `<link>` would
+    pub fn add_style(&self, css_text: &str) {
+        let extra = css::parse_css(css_text);
+        self.stylesheet.borrow_mut().rules.extend(extra.rules);
+    }
+
+    /// Evaluate a script, returning its completion value as a string
+    ///
+    /// Promise jobs the script queued run before this returns, as
+    /// microtasks do after each script in a browser.
+    pub fn run_script(&self, source: &str) -> Result<String, BrowserError> {
+        self.run_script_named(source, SCRIPT_FILE_NAME)
+    }
+
+    /// Evaluate a script as the file `file_name`, which stack frames of its
+    /// functions then name
+    ///
+    /// A `//# sourceMappingURL=data:...` comment registers the script's
+    /// inline source map; see `add_source_map`.
+    pub fn run_script_named(&self, source: &str, file_name: &str) -> Result<String, BrowserError> {
+        if let Some(map) = stack_trace::inline_source_map(source) {
+            self.add_source_map(file_name, &map)?;
+        }
+        self.limited(|| {
+            let value = self.tracer.span(TraceStage::Js, file_name, || self.with(|ctx| eval_named(&ctx, source, file_name).map(value_to_string)));
+            let value = value.map_err(|e| self.source_maps.borrow().map_error(e))?;
+            self.run_until_idle()?;
+            Ok(value)
+        })
+    }
+
+    /// Evaluate a script read from `path`, naming it by its path
+    ///
+    /// A `//# sourceMappingURL=` comment naming a file loads that map
+    /// relative to the script, as for a bundle served next to its map. A
+    /// missing or invalid map only leaves frames unmapped. `.ts`, `.tsx`
+    /// and `.jsx` scripts are transpiled first, keeping their line numbers.
+    pub fn run_script_file(&self, source: &str, path: &Path) -> Result<String, BrowserError> {
+        let file_name = path.display().to_string();
+        let source = &transpile::transpile_file(source, path, &self.modules.transpile)?;
+        if let Some(url) = stack_trace::source_map_url(source).filter(|url| !network::is_data_uri(url)) {
+            let map_path = path.parent().unwrap_or(Path::new("")).join(url);
+            if let Ok(map) = std::fs::read_to_string(map_path) {
+                let _ = self.add_source_map(&file_name, &map);
+            }
+        }
+        self.run_script_named(source, &file_name)
+    }
+
+    /// Evaluate `source` as the ES module `name`, a path relative to the
+    /// module root that its relative imports resolve from
+    ///
+    /// Imports load through the page's `ModuleConfig`; a specifier that
+    /// cannot be resolved fails with a `ReferenceError` naming it and the
+    /// importing module.
+    pub fn run_module(&self, source: &str, name: &str) -> Result<(), BrowserError> {
+        if let Some(map) = stack_trace::inline_source_map(source) {
+            self.add_source_map(name, &map)?;
+        }
+        self.limited(|| {
+            let result = self.tracer.span(TraceStage::Js, name, || {
+                self.with(|ctx| Module::evaluate(ctx.clone(), name, source).map(|_| ()).map_err(|_| pending_exception(&ctx)))
+            });
+            result.map_err(|e| self.source_maps.borrow().map_error(e))?;
+            self.run_until_idle()?;
+            Ok(())
+        })
+    }
+
+    /// Evaluate a module read from `path`, which must be under the module
+    /// root; TypeScript and JSX are transpiled as for imported modules
+    pub fn run_module_file(&self, source: &str, path: &Path) -> Result<(), BrowserError> {
+        let name = self.modules.module_name(path)?;
+        self.run_module(&transpile::transpile_file(source, path, &self.modules.transpile)?, &name)
+    }
+
+    /// Every file the page's scripts have imported modules from, in load
+    /// order
+    pub fn module_files(&self) -> Vec<PathBuf> {
+        self.loaded_modules.paths()
+    }
+
+    /// Everything scripts logged to the console, oldest first
+    pub fn console(&self) -> Ref<'_, [ConsoleEntry]> {
+        Ref::map(self.console.borrow(), Vec::as_slice)
+    }
+
+    /// Console entries of one level, e.g. `ConsoleLevel::Error`
+    pub fn console_messages(&self, level: ConsoleLevel) -> Vec<String> {
+        self.console.borrow().iter().filter(|e| e.level == level).map(|e| e.message.clone()).collect()
+    }
+
+    /// Forget what scripts logged so far
+    pub fn clear_console(&self) {
+        self.console.borrow_mut().clear();
+    }
+
+    /// Map stack frames in `file_name` through a source map, so errors
+    /// from bundled scripts point at the original sources
+    pub fn add_source_map(&self, file_name: &str, source_map: &str) -> Result<(), BrowserError> {
+        let map = SourceMap::parse(source_map)?;
+        self.source_maps.borrow_mut().insert(file_name, map);
+        Ok(())
+    }
+
+    /// Run queued jobs, and tasks such as WebSocket events, until none are
+    /// left, returning how many ran
+    pub fn run_until_idle(&self) -> Result<usize, BrowserError> {
+        self.limited(|| self.event_loop.run_until_idle(&self.runtime, &self.context))
+    }
+
+    /// What the page's scripts and document may use
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Cap the runtime's memory and let the watchdog interrupt scripts
+    fn install_limits(&self) {
+        if let Some(bytes) = self.limits.memory {
+            ready(self.runtime.set_memory_limit(bytes));
+        }
+        let watchdog = self.watchdog.clone();
+        ready(self.runtime.set_interrupt_handler(Some(Box::new(move || watchdog.expired()))));
+    }
+
+    /// Run `f` in the page's JavaScript context
+    ///
+    /// Must not be called from a native the page's scripts called, which
+    /// already runs in the context.
+    fn with<R>(&self, f: impl for<'js> FnOnce(Ctx<'js>) -> R) -> R {
+        ready(self.context.with(f))
+    }
+
+    /// Run JavaScript under the script timeout, unless a caller already
+    /// does, turning interrupts, running out of memory and workers running
+    /// into limits into limit errors, then check the node count
+    fn limited<T>(&self, run: impl FnOnce() -> Result<T, BrowserError>) -> Result<T, BrowserError> {
+        let timed = self.limits.script_timeout.is_some_and(|timeout| self.watchdog.arm(timeout));
+        let result = run();
+        if timed && self.watchdog.disarm() {
+            return Err(self.limits.timeout_error());
+        }
+        if let Some(error) = self.workers.take_limit_error() {
+            return Err(error);
+        }
+        let value = result.map_err(|e| self.limits.classify_js_error(e))?;
+        self.limits.check_nodes(self.document.borrow().nodes.len())?;
+        Ok(value)
+    }
+
+    /// Fail when a screenshot of `region`, in CSS pixels, would be larger
+    /// than the limits allow
+    pub fn check_screenshot(&self, region: Rect) -> Result<(), BrowserError> {
+        self.limits.check_screenshot(region, self.device_pixel_ratio)
+    }
+
+    /// The viewport in CSS pixels, the region `render` paints
+    pub fn viewport_region(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.viewport.width as f32, self.viewport.height as f32)
+    }
+
+    /// The peer this page's WebSockets connect to
+    pub fn websockets(&self) -> &MockWebSocketServer {
+        self.sockets.server()
+    }
+
+    /// The server this page's EventSources read
+    pub fn event_sources(&self) -> &MockEventSourceServer {
+        self.streams.server()
+    }
+
+    pub fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    /// Copy from `node` as Ctrl+C would, returning `false` if a `copy`
+    /// listener canceled the default action
+    pub fn copy(&self, node: usize) -> Result<bool, BrowserError> {
+        self.clipboard_action(node, ClipboardAction::Copy)
+    }
+
+    /// Cut from `node` as Ctrl+X would, returning `false` if a `cut`
+    /// listener canceled the default action
+    pub fn cut(&self, node: usize) -> Result<bool, BrowserError> {
+        self.clipboard_action(node, ClipboardAction::Cut)
+    }
+
+    /// Paste into `node` as Ctrl+V would, returning `false` if a `paste`
+    /// listener canceled the default action
+    pub fn paste(&self, node: usize) -> Result<bool, BrowserError> {
+        self.clipboard_action(node, ClipboardAction::Paste)
+    }
+
+    fn clipboard_action(&self, node: usize, action: ClipboardAction) -> Result<bool, BrowserError> {
+        self.document.borrow().check(node)?;
+        let performed = self.with(|ctx| {
+            clipboard::perform(&ctx, &self.document, &self.clipboard, node, action).map_err(|_| pending_exception(&ctx))
+        })?;
+        self.run_until_idle()?;
+        Ok(performed)
+    }
+
+    /// Choose `files` in the file input `node` as the file dialog would,
+    /// firing `input` and `change`
+    ///
+    /// Fails for nodes that are not enabled file inputs, or for more than
+    /// one file without `multiple`. An empty list clears the choice.
+    pub fn set_input_files(&self, node: usize, files: Vec<InputFile>) -> Result<(), BrowserError> {
+        self.document.borrow().check(node)?;
+        files::check_file_input(&self.document.borrow(), node, files.len()).map_err(BrowserError::InvalidOperationError)?;
+        self.with(|ctx| files::choose_files(&ctx, &self.document, node, files).map_err(|_| pending_exception(&ctx)))?;
+        self.run_until_idle()?;
+        Ok(())
+    }
+
+    /// Pick option `index` of the select `node` as a user would, firing
+    /// `input` and `change` if the selection changed
+    ///
+    /// Returns whether it did; disabled selects never change.
+    pub fn select_option(&self, node: usize, index: i32) -> Result<bool, BrowserError> {
+        self.document.borrow().check(node)?;
+        if forms::tag_name(&self.document.borrow(), node) != Some("select") {
+            return Err(BrowserError::InvalidOperationError(format!("Node {} is not a <select>", node)));
+        }
+        let changed = self.with(|ctx| forms::select_option(&ctx, &self.document, node, index).map_err(|_| pending_exception(&ctx)))?;
+        self.run_until_idle()?;
+        Ok(changed)
+    }
+
+    /// Replace the text of the input or textarea `node` as a user would,
+    /// firing `input` and `change` if the value changed
+    ///
+    /// Returns whether it did; disabled fields never change.
+    pub fn fill(&self, node: usize, value: &str) -> Result<bool, BrowserError> {
+        self.document.borrow().check(node)?;
+        if !forms::is_text_field(&self.document.borrow(), node) {
+            return Err(BrowserError::InvalidOperationError(format!("Node {} is not a text field", node)));
+        }
+        let changed = self.with(|ctx| forms::input_value(&ctx, &self.document, node, value).map_err(|_| pending_exception(&ctx)))?;
+        self.run_until_idle()?;
+        Ok(changed)
+    }
+
+    pub fn document(&self) -> Ref<'_, Document> {
+        self.document.borrow()
+    }
+
+    pub fn document_mut(&self) -> RefMut<'_, Document> {
+        self.document.borrow_mut()
+    }
+
+    /// A handle on live node `idx` that fails to resolve once the page
+    /// loads another document; see `dom::NodeId`
+    pub fn node_id(&self, idx: usize) -> Result<NodeId, BrowserError> {
+        Ok(self.document.borrow().node_id(idx)?)
+    }
+
+    /// The index `id` names in the current document, if it was taken from
+    /// it and its node has not been removed
+    pub fn resolve(&self, id: NodeId) -> Result<usize, BrowserError> {
+        Ok(self.document.borrow().resolve(id)?)
+    }
+
+    /// The document shared with the JavaScript bindings
+    pub fn shared_document(&self) -> Rc<RefCell<Document>> {
+        self.document.clone()
+    }
+
+    pub fn stylesheet(&self) -> Ref<'_, StyleSheet> {
+        self.stylesheet.borrow()
+    }
+
+    /// First element matching a CSS selector
+    pub fn query(&self, selector: &str) -> Result<Option<usize>, BrowserError> {
+        Ok(query::query_selector(&self.document.borrow(), selector)?)
+    }
+
+    /// All elements matching a CSS selector, in document order
+    pub fn query_all(&self, selector: &str) -> Result<Vec<usize>, BrowserError> {
+        Ok(query::query_selector_all(&self.document.borrow(), selector)?)
+    }
+
+    /// The document title, as `document.title` gives it
+    pub fn title(&self) -> String {
+        head::title(&self.document.borrow())
+    }
+
+    /// The `content` of the `<meta>` named (or with the `property`) `name`
+    pub fn meta(&self, name: &str) -> Option<String> {
+        head::meta_content(&self.document.borrow(), name)
+    }
+
+    /// The `href`s of the `<link rel=...>` elements for `rel`
+    pub fn links(&self, rel: &str) -> Vec<String> {
+        head::link_hrefs(&self.document.borrow(), rel)
+    }
+
+    /// Lay the document out at the layout viewport size, under the page
+    /// styles
+    pub fn layout(&self) {
+        let styles = self.compute_styles(&self.document.borrow());
+        let size = self.layout_viewport();
+        self.tracer.span(TraceStage::Layout, "Layout", || {
+            layout::calculate_styled_layout_with_threads(&mut self.document.borrow_mut(), &styles, size.width, size.height, self.threads)
+        });
+    }
+
+    /// Every node's computed style under the page styles
+    fn compute_styles(&self, document: &Document) -> Vec<ComputedStyle> {
+        self.tracer.span(TraceStage::Style, "Compute styles", || {
+            style::compute_styles_with_threads(document, &self.stylesheet.borrow(), self.threads)
+        })
+    }
+
+    /// Whether `node` is an element shown under the page styles: not
+    /// `display: none` itself or through an ancestor, and not
+    /// `visibility: hidden`
+    pub fn is_visible(&self, node: usize) -> bool {
+        let document = self.document.borrow();
+        let styles = self.compute_styles(&document);
+        ElementRef::new(node).is_visible(&document, &styles)
+    }
+
+    /// Where `node`'s border box appears in the viewport, after the CSS
+    /// transforms of it and its ancestors, as `getBoundingClientRect`
+    /// reports it; `None` for nodes without a box
+    pub fn bounding_client_rect(&self, node: usize) -> Option<Rect> {
+        self.layout();
+        let document = self.document.borrow();
+        let styles = self.compute_styles(&document);
+        transform::bounding_client_rect(&document, &styles, node)
+    }
+
+    /// The topmost element at (`x`, `y`) in CSS pixels, as
+    /// `document.elementFromPoint` finds it
+    pub fn element_from_point(&self, x: f32, y: f32) -> Option<usize> {
+        self.layout();
+        let document = self.document.borrow();
+        let styles = self.compute_styles(&document);
+        document.element_from_point(&styles, x, y)
+    }
+
+    /// Dispatch a `MouseEvent` of `event_type` at the element under
+    /// (`x`, `y`), as a real pointer there would, returning that element;
+    /// `None` when nothing is under the point
+    pub fn dispatch_mouse_event(&self, event_type: &str, x: f32, y: f32) -> Result<Option<usize>, BrowserError> {
+        let Some(target) = self.element_from_point(x, y) else {
+            return Ok(None);
+        };
+        self.with(|ctx| {
+            user_events::dispatch_mouse_event(&ctx, &self.document, target, event_type, &MouseEventInit::at(Point::new(x, y)))
+                .map_err(|_| pending_exception(&ctx))
+        })?;
+        self.run_until_idle()?;
+        Ok(Some(target))
+    }
+
+    /// Move the simulated pointer to (`x`, `y`), returning the element now
+    /// under it
+    ///
+    /// Crossing onto another element fires `mouseout` and `mouseleave` at
+    /// the old one, then `mouseover` and `mouseenter` at the new one, and
+    /// moves the `:hover` chain; `mousemove` follows at the new target.
+    pub fn move_mouse(&self, x: f32, y: f32) -> Result<Option<usize>, BrowserError> {
+        let target = self.element_from_point(x, y);
+        self.with(|ctx| {
+            user_events::move_to(&ctx, &self.document, &mut self.mouse.borrow_mut(), Point::new(x, y), target)
+                .map_err(|_| pending_exception(&ctx))
+        })?;
+        self.run_until_idle()?;
+        Ok(target)
+    }
+
+    /// Move to (`x`, `y`) and click the primary button there: `mousedown`,
+    /// `mouseup`, then `click`, returning the element clicked
+    ///
+    /// Pressing focuses the innermost focusable element under the pointer,
+    /// unless `mousedown` is canceled, clicking a checkbox or radio button
+    /// checks it, clicking a submit button submits its form, and clicking a
+    /// link records a navigation request; see `navigation_requests`. A `mousedown` listener that moves the page is honored:
+    /// `mouseup` goes to whatever is under the pointer afterwards, and
+    /// `click` to the innermost element holding both targets.
+    pub fn click_at(&self, x: f32, y: f32) -> Result<Option<usize>, BrowserError> {
+        let Some(pressed) = self.move_mouse(x, y)? else {
+            return Ok(None);
+        };
+        let focuses = self.with(|ctx| {
+            user_events::press(&ctx, &self.document, &mut self.mouse.borrow_mut(), pressed).map_err(|_| pending_exception(&ctx))
+        })?;
+        self.run_until_idle()?;
+        if focuses {
+            let target = user_events::focusable_ancestor(&self.document.borrow(), pressed);
+            self.focus(target)?;
+        }
+        let released = self.element_from_point(x, y).unwrap_or(pressed);
+        let activation = self.with(|ctx| {
+            user_events::release(&ctx, &self.document, &mut self.mouse.borrow_mut(), pressed, released)
+                .map_err(|_| pending_exception(&ctx))
+        })?;
+        match activation {
+            Some(Activation::Submitted(submission)) => self.keyboard.borrow_mut().submissions.push(submission),
+            Some(Activation::FollowedLink(link)) => {
+                let request = NavigationRequest::for_link(&self.document.borrow(), link, self.base_url.as_deref());
+                self.navigations.borrow_mut().push(request);
+            }
+            Some(Activation::Toggled(_)) | None => {}
+        }
+        self.run_until_idle()?;
+        Ok(Some(pressed))
+    }
+
+    /// Move to (`x`, `y`) and turn the wheel `delta_y` pixels, positive
+    /// scrolling down, returning `false` if a listener canceled the `wheel`
+    /// event or nothing is under the point
+    pub fn wheel(&self, x: f32, y: f32, delta_y: f64) -> Result<bool, BrowserError> {
+        let Some(target) = self.move_mouse(x, y)? else {
+            return Ok(false);
+        };
+        let not_canceled = self.with(|ctx| {
+            user_events::wheel(&ctx, &self.document, &self.mouse.borrow(), target, delta_y).map_err(|_| pending_exception(&ctx))
+        })?;
+        self.run_until_idle()?;
+        Ok(not_canceled)
+    }
+
+    /// The element under the simulated pointer, the innermost of those
+    /// matching `:hover`
+    pub fn hovered(&self) -> Option<usize> {
+        self.mouse.borrow().target
+    }
+
+    /// The selection, e.g. made by `select` or a script's `getSelection()`
+    pub fn selection(&self) -> selection::Selection {
+        self.selection.get()
+    }
+
+    /// The selected text, as `getSelection().toString()` gives it
+    pub fn selected_text(&self) -> String {
+        self.selection.get().text(&self.document.borrow())
+    }
+
+    /// Select from `anchor` to `focus`, as dragging the mouse between them
+    /// would; `selectionchange` fires once the page runs its tasks
+    pub fn select(&self, anchor: BoundaryPoint, focus: BoundaryPoint) -> Result<(), BrowserError> {
+        self.selection.set(anchor, focus);
+        self.run_until_idle().map(|_| ())
+    }
+
+    /// Select `node`'s contents, as a triple-click on a paragraph does
+    pub fn select_node_contents(&self, node: usize) -> Result<(), BrowserError> {
+        let document = self.document.borrow();
+        let Range { start, end } = Range::node_contents(&document, document.check(node)?);
+        drop(document);
+        self.select(start, end)
+    }
+
+    /// Select nothing
+    pub fn clear_selection(&self) -> Result<(), BrowserError> {
+        self.selection.clear();
+        self.run_until_idle().map(|_| ())
+    }
+
+    /// Move focus to `node`, or back to the body for `None`, firing the
+    /// focus events
+    pub fn focus(&self, node: Option<usize>) -> Result<(), BrowserError> {
+        self.with_keyboard(|ctx, keyboard| user_events::focus(ctx, &self.document, &self.selection, keyboard, node))
+    }
+
+    /// The focused element; `None` when the body has focus
+    pub fn focused(&self) -> Option<usize> {
+        self.keyboard.borrow().focused
+    }
+
+    /// Press a key down, e.g. `a`, `Enter` or `Shift`, running what it does
+    /// unless `keydown` is canceled; returns `false` if it was
+    ///
+    /// Pressing a key that is already down fires a repeat.
+    pub fn key_down(&self, key: &str) -> Result<bool, BrowserError> {
+        self.with_keyboard(|ctx, keyboard| user_events::key_down(ctx, &self.document, &self.selection, keyboard, key))
+    }
+
+    /// Release a key pressed with `key_down`
+    pub fn key_up(&self, key: &str) -> Result<(), BrowserError> {
+        self.with_keyboard(|ctx, keyboard| user_events::key_up(ctx, &self.document, keyboard, key)).map(|_| ())
+    }
+
+    /// Press and release a key combination like `Enter`, `Shift+Tab` or
+    /// `Control+a`, holding the modifiers around the key; returns `false`
+    /// if the key's `keydown` was canceled
+    pub fn press_key(&self, combo: &str) -> Result<bool, BrowserError> {
+        let (modifiers, key) = user_events::parse_key_combo(combo);
+        for modifier in &modifiers {
+            self.key_down(modifier)?;
+        }
+        let not_canceled = self.key_down(&key)?;
+        self.key_up(&key)?;
+        for modifier in modifiers.iter().rev() {
+            self.key_up(modifier)?;
+        }
+        Ok(not_canceled)
+    }
+
+    /// Type `text` into the focused element key by key; uppercase letters
+    /// are typed with Shift and newlines with Enter
+    pub fn type_text(&self, text: &str) -> Result<(), BrowserError> {
+        for c in text.chars() {
+            match c {
+                '\n' => self.press_key("Enter")?,
+                c if c.is_ascii_uppercase() => self.press_key(&format!("Shift+{}", c.to_ascii_lowercase()))?,
+                c => self.press_key(&c.to_string())?,
+            };
+        }
+        Ok(())
+    }
+
+    /// Forms the user submitted, by Enter or a submit button, in order
+    pub fn form_submissions(&self) -> Vec<FormSubmission> {
+        self.keyboard.borrow().submissions.clone()
+    }
+
+    /// Navigations the user asked for by clicking links, in order; the page
+    /// stays on its document
+    pub fn navigation_requests(&self) -> Vec<NavigationRequest> {
+        self.navigations.borrow().clone()
+    }
+
+    fn with_keyboard<T>(
+        &self,
+        f: impl for<'js> FnOnce(&Ctx<'js>, &mut KeyboardState) -> rquickjs::Result<T>,
+    ) -> Result<T, BrowserError> {
+        let result = self.with(|ctx| f(&ctx, &mut self.keyboard.borrow_mut()).map_err(|_| pending_exception(&ctx)))?;
+        self.run_until_idle()?;
+        Ok(result)
+    }
+
+    /// The layout tree as text; see `layout::format_layout_tree`
+    pub fn layout_tree(&self) -> String {
+        self.layout();
+        layout::format_layout_tree(&self.document.borrow())
+    }
+
+    /// The first element matching `selector`, or the body, and what it
+    /// contains, with key attributes, computed style and layout boxes; see
+    /// `inspect`
+    pub fn inspect(&self, selector: Option<&str>) -> Result<InspectedNode, BrowserError> {
+        let root = match selector {
+            Some(selector) => {
+                self.query(selector)?.ok_or_else(|| BrowserError::NotFoundError(format!("No element matches '{}'", selector)))?
+            }
+            None => {
+                let document = self.document.borrow();
+                head::body(&document).unwrap_or(document.root)
+            }
+        };
+        self.layout();
+        let document = self.document.borrow();
+        Ok(inspect::inspect(&document, &self.compute_styles(&document), root))
+    }
+
+    /// Lay out and paint the viewport, in device pixels
+    pub fn render(&self) -> DrawTarget {
+        self.paint_frames();
+        let stylesheet = self.stylesheet.borrow();
+        render_page(
+            &self.document,
+            &stylesheet,
+            &self.images.borrow(),
+            self.fonts.borrow().web_fonts(),
+            self.viewport,
+            self.mobile,
+            self.device_pixel_ratio,
+            self.debug_boxes,
+            self.threads,
+            &self.tracer,
+        )
+    }
+
+    /// Check the rendered viewport against the golden master called `name`
+    ///
+    /// Missing golden masters are recorded and changed ones rewritten when
+    /// the snapshot mode allows it; see `golden::SnapshotConfig::check`.
+    pub fn expect_screenshot(&self, name: &str) -> Result<SnapshotOutcome, BrowserError> {
+        self.check_screenshot(self.viewport_region())?;
+        let image = Image::from_draw_target(&self.render());
+        self.snapshots.check(name, &image).map_err(|e| BrowserError::ScreenshotError(e.to_string()))
+    }
+
+    /// Check `element`'s subtree against the DOM snapshot called `name`,
+    /// stored next to the golden masters
+    ///
+    /// Snapshots are recorded and rewritten as for `expect_screenshot`; a
+    /// mismatch lists the changed lines.
+    pub fn assert_dom_snapshot(&self, name: &str, element: usize) -> Result<SnapshotOutcome, BrowserError> {
+        let document = self.document.borrow();
+        if element >= document.nodes.len() {
+            return Err(BrowserError::NotFoundError(format!("No node {} to snapshot", element)));
+        }
+        self.snapshots.check_dom(name, &snapshot::dom_snapshot(&document, element)).map_err(|e| BrowserError::DOMError(e.to_string()))
+    }
+
+    /// Lay out the page and paint only `region` of it, in device pixels
+    ///
+    /// The region is in CSS pixels and may extend past the viewport, e.g.
+    /// to capture content below the fold.
+    pub fn render_region(&self, region: Rect) -> DrawTarget {
+        self.layout();
+        self.paint_frames();
+        let document = self.document.borrow();
+        let styles = self.compute_styles(&document);
+        self.tracer.span(TraceStage::Paint, "Paint", || {
+            let list = paint_document(&document, &styles, &self.images.borrow(), self.fonts.borrow().web_fonts(), self.debug_boxes);
+            render::render_display_list(&list, region, self.device_pixel_ratio)
+        })
+    }
+
+    /// The whole scrollable page in CSS pixels: at least the viewport, and
+    /// tall or wide enough to include content below or right of it
+    pub fn full_page_region(&self) -> Rect {
+        self.layout();
+        let size = self.layout_viewport();
+        let (width, height) = layout::scroll_size(&self.document.borrow(), size.width, size.height);
+        Rect::new(0.0, 0.0, width, height)
+    }
+
+    /// Lay out and paint the whole scrollable page, in device pixels
+    pub fn render_full_page(&self) -> DrawTarget {
+        self.render_region(self.full_page_region())
+    }
+
+    /// Areas of the viewport, in CSS pixels, that changed since the last
+    /// `update_frame`; everything painted counts before the first one
+    pub fn damage(&self) -> Vec<Rect> {
+        self.frame.borrow().damage(&self.display_list())
+    }
+
+    /// Repaint only the damaged areas of the kept viewport frame, returning
+    /// them
+    ///
+    /// The frame then matches `render()`, at a cost that grows with the
+    /// damage rather than the viewport, which suits watch mode and stepping.
+    pub fn update_frame(&self) -> Vec<Rect> {
+        let list = self.display_list();
+        self.frame.borrow_mut().update(list)
+    }
+
+    /// The viewport frame as of the last `update_frame`, in device pixels
+    pub fn frame(&self) -> Ref<'_, DrawTarget> {
+        Ref::map(self.frame.borrow(), IncrementalRenderer::target)
+    }
+
+    /// Lay out and record the paint commands for the page
+    fn display_list(&self) -> DisplayList {
+        self.layout();
+        self.paint_frames();
+        let document = self.document.borrow();
+        let styles = self.compute_styles(&document);
+        self.tracer.span(TraceStage::Paint, "Display list", || {
+            paint_document(&document, &styles, &self.images.borrow(), self.fonts.borrow().web_fonts(), self.debug_boxes)
+        })
+    }
+
+    /// Render and save the whole scrollable page, as PNG unless the
+    /// extension names another format
+    pub fn screenshot_full_page(&self, path: &Path) -> Result<PathBuf, BrowserError> {
+        let region = self.full_page_region();
+        self.check_screenshot(region)?;
+        save_image(&self.render_region(region), path, &self.tracer)
+    }
+
+    /// Render the whole scrollable page to a PDF, split into pages as
+    /// `options` asks; see `pdf::render_pdf`
+    pub fn render_pdf(&self, options: &PdfOptions) -> Result<Vec<u8>, BrowserError> {
+        if !(options.device_pixel_ratio.is_finite() && options.device_pixel_ratio > 0.0) {
+            return Err(BrowserError::InvalidOperationError(format!(
+                "Device pixel ratio must be positive, got {}",
+                options.device_pixel_ratio
+            )));
+        }
+        let content = self.full_page_region();
+        let document = self.document.borrow();
+        let styles = self.compute_styles(&document);
+        Ok(self.tracer.span(TraceStage::Paint, "Paint PDF", || pdf::render_pdf(&document, &styles, &self.images.borrow(), self.fonts.borrow().web_fonts(), content, options)))
+    }
+
+    /// Render the whole scrollable page and save it as a PDF
+    pub fn save_pdf(&self, path: &Path, options: &PdfOptions) -> Result<PathBuf, BrowserError> {
+        let bytes = self.render_pdf(options)?;
+        pdf::save_pdf(&bytes, path)
+            .map_err(|e| BrowserError::RenderError(format!("Cannot write '{}': {}", path.display(), e)))
+    }
+
+    /// Render and save the viewport, as PNG unless the extension names
+    /// another format
+    pub fn screenshot(&self, path: &Path) -> Result<PathBuf, BrowserError> {
+        self.check_screenshot(self.viewport_region())?;
+        save_image(&self.render(), path, &self.tracer)
+    }
+
+    /// Render the viewport and encode it in memory
+    pub fn encode_screenshot(&self, format: ImageFormat, quality: u8) -> Result<Vec<u8>, BrowserError> {
+        self.check_screenshot(self.viewport_region())?;
+        let draw_target = self.render();
+        self.tracer
+            .span(TraceStage::Encode, "Encode screenshot", || screenshot::encode_to_vec(&draw_target, format, quality))
+            .map_err(|e| BrowserError::ScreenshotError(e.to_string()))
+    }
+
+    /// Render and save a region of the page, as PNG unless the extension
+    /// names another format
+    pub fn screenshot_clip(&self, region: Rect, path: &Path) -> Result<PathBuf, BrowserError> {
+        let region = screenshot::check_region(region).map_err(|e| BrowserError::ScreenshotError(e.to_string()))?;
+        self.check_screenshot(region)?;
+        save_image(&self.render_region(region), path, &self.tracer)
+    }
+
+    /// Render and save the first element matching `selector`, cropped to
+    /// its border box
+    pub fn screenshot_element(&self, selector: &str, path: &Path) -> Result<PathBuf, BrowserError> {
+        let element_idx = self
+            .query(selector)?
+            .ok_or_else(|| BrowserError::NotFoundError(format!("No element matches '{}'", selector)))?;
+        self.layout();
+        let region = screenshot::element_region(&self.document.borrow(), element_idx)
+            .map_err(|e| BrowserError::ScreenshotError(e.to_string()))?;
+        self.screenshot_clip(region, path)
+    }
+
+    /// Run the tests scripts registered with `describe`/`it`
+    ///
+    /// Results scripts reported directly with `reportTestResult` come first.
+    pub fn run_tests(&self) -> TestSummary {
+        let config = test_runner::TestRunnerConfig::new()
+            .with_seed(self.seed.0)
+            .with_stylesheet(Rc::new(self.stylesheet.borrow().clone()))
+            .with_failure_capture(self.failure_capture.clone())
+            .with_leak_detection(self.detect_leaks)
+            .with_event_loop(self.event_loop.clone())
+            .with_console(self.console.clone())
+            .with_tracer(self.tracer.clone());
+        let mut ran = test_runner::run_tests(&self.runtime, &self.context, self.document.clone(), &config);
+        // The runner times tests with its own interrupt handler
+        self.install_limits();
+
+        let mut summary = TestSummary::new().with_seed(self.seed.0);
+        let source_maps = self.source_maps.borrow();
+        for mut result in self.reported.borrow_mut().drain(..).chain(ran.results.drain(..)) {
+            result.error = result.error.map(|error| source_maps.map_error(error));
+            summary.add_result(result);
+        }
+        summary
+    }
+}
+
+/// Save in the format `path`'s extension names, PNG by default
+fn save_image(draw_target: &DrawTarget, path: &Path, tracer: &Tracer) -> Result<PathBuf, BrowserError> {
+    let format = ImageFormat::from_path(path).unwrap_or_default();
+    tracer
+        .span(TraceStage::Encode, "Encode screenshot", || {
+            screenshot::save_screenshot_with_format(draw_target, path, format, screenshot::DEFAULT_QUALITY)
+        })
+        .map_err(|e| BrowserError::ScreenshotError(e.to_string()))
+}
+
+/// The layout viewport of `document` shown in `viewport`
+fn layout_viewport(document: &Document, viewport: Viewport, mobile: bool) -> LayoutViewport {
+    let meta = head::meta_content(document, "viewport").map(|content| ViewportMeta::parse(&content));
+    LayoutViewport::new(viewport, mobile, meta)
+}
+
+/// Lay out the document at its layout viewport size and paint it zoomed to
+/// fill the viewport at the device pixel ratio
+#[allow(clippy::too_many_arguments)]
+fn render_page(
+    document: &RefCell<Document>,
+    stylesheet: &StyleSheet,
+    images: &ImageCache,
+    fonts: &WebFonts,
+    viewport: Viewport,
+    mobile: bool,
+    device_pixel_ratio: f32,
+    debug_boxes: bool,
+    threads: usize,
+    tracer: &Tracer,
+) -> DrawTarget {
+    let size = layout_viewport(&document.borrow(), viewport, mobile);
+    let styles = tracer.span(TraceStage::Style, "Compute styles", || style::compute_styles_with_threads(&document.borrow(), stylesheet, threads));
+    tracer.span(TraceStage::Layout, "Layout", || {
+        layout::calculate_styled_layout_with_threads(&mut document.borrow_mut(), &styles, size.width, size.height, threads)
+    });
+    let document = document.borrow();
+    tracer.span(TraceStage::Paint, "Paint", || {
+        let list = paint_document(&document, &styles, images, fonts, debug_boxes);
+        render::render_display_list(&list, Rect::new(0.0, 0.0, size.width, size.height), device_pixel_ratio * size.scale)
+    })
+}
+
+/// The paint commands of a laid-out document, with the debug overlay on
+/// top when `debug_boxes` is set
+fn paint_document(document: &Document, styles: &[ComputedStyle], images: &ImageCache, fonts: &WebFonts, debug_boxes: bool) -> DisplayList {
+    let mut list = render::build_display_list(document, styles, images, fonts);
+    if debug_boxes {
+        debug_overlay::paint_overlay(&mut list, document);
+    }
+    list
+}
+
+fn value_to_string(value: Value<'_>) -> String {
+    value.get::<Coerced<String>>().map_or_else(|_| format!("{:?}", value), |text| text.0)
+}
+
+/// Evaluate global code as the file `file_name`, taking any exception it
+/// throws
+///
+/// `Ctx::eval` names every script `eval_script`; this passes the real name
+/// on so stack frames can tell scripts apart.
+pub(crate) fn eval_named<'js>(ctx: &Ctx<'js>, source: &str, file_name: &str) -> Result<Value<'js>, BrowserError> {
+    let file = CString::new(file_name.replace('\0', "")).unwrap_or_default();
+    // QuickJS wants the source NUL-terminated but takes its length, so NULs
+    // inside it are fine
+    let mut code = source.as_bytes().to_vec();
+    code.push(0);
+    // SAFETY: Both buffers outlive the call and QuickJS copies what it keeps.
+    // The returned value is owned, as `Value::from_raw` expects.
+    let value = unsafe {
+        let raw = qjs::JS_Eval(
+            ctx.as_raw().as_ptr(),
+            code.as_ptr().cast(),
+            source.len() as _,
+            file.as_ptr(),
+            qjs::JS_EVAL_TYPE_GLOBAL as i32,
+        );
+        Value::from_raw(ctx.clone(), raw)
+    };
+    if value.is_exception() {
+        return Err(pending_exception(ctx));
+    }
+    Ok(value)
+}
+
+/// Take the pending exception as `JavaScriptError("TypeError: message", stack)`
+pub(crate) fn pending_exception(ctx: &Ctx<'_>) -> BrowserError {
+    let value = ctx.catch();
+    let Some(error) = value.as_object() else {
+        return BrowserError::JavaScriptError(value_to_string(value), None);
+    };
+    let name: String = error.get("name").unwrap_or_else(|_| "Error".to_string());
+    let message: String = error.get("message").unwrap_or_default();
+    let stack: Option<String> = error.get("stack").ok();
+    BrowserError::JavaScriptError(
+        format!("{}: {}", name, message),
+        stack.map(|s| s.trim_end().to_string()).filter(|s| !s.is_empty()),
+    )
+}
+
+/// Install the browser globals scripts can use
+fn install_globals<'js>(ctx: &Ctx<'js>, page: &Page) -> rquickjs::Result<()> {
+    let globals = ctx.globals();
+    let document_arc = &page.document;
+
+    // Expose console, recorded into the page's buffer and echoed
+    console::install_console(ctx, page.console.clone(), true)?;
+
+    // Make Math.random replayable from the run seed
+    seed::install_seeded_math_random(ctx, page.seed)?;
+    if let Some(time) = page.frozen_time {
+        seed::install_frozen_date(ctx, time)?;
+    }
+
+    // Expose WebSocket and EventSource, connected to the page's mock
+    // servers
+    websocket::install_websocket(ctx, &page.sockets)?;
+    event_source::install_event_source(ctx, &page.streams)?;
+
+    // Expose setTimeout and setInterval on the page's clock
+    timers::install_timers(ctx, &page.timers)?;
+
+    // Expose the page environment: window, its size and devicePixelRatio,
+    // navigator, location.href and matchMedia
+    globals.set("window", globals.clone())?;
+    globals.set("innerWidth", page.viewport.width)?;
+    globals.set("innerHeight", page.viewport.height)?;
+    globals.set("devicePixelRatio", page.device_pixel_ratio)?;
+    let navigator = Object::new(ctx.clone())?;
+    navigator.set("userAgent", page.navigator.user_agent.as_str())?;
+    navigator.set("language", page.navigator.language())?;
+    navigator.set("languages", page.navigator.languages.clone())?;
+    navigator.set("platform", page.navigator.platform.as_str())?;
+    navigator.set("onLine", page.navigator.on_line)?;
+    navigator.set("hardwareConcurrency", page.navigator.hardware_concurrency)?;
+    navigator.set("maxTouchPoints", page.navigator.max_touch_points)?;
+    globals.set("navigator", navigator)?;
+    let location = Object::new(ctx.clone())?;
+    location.set("href", page.base_url.as_deref().unwrap_or("about:blank"))?;
+    globals.set("location", location)?;
+    media::install_match_media(ctx, page.media.clone())?;
+
+    // Expose expectScreenshot(name): check the rendered page against a
+    // golden master, returning "matched", "recorded" or "updated"
+    let (document, stylesheet, images, fonts) = (page.document.clone(), page.stylesheet.clone(), page.images.clone(), page.fonts.clone());
+    let (snapshots, viewport, mobile, device_pixel_ratio, debug_boxes, threads, tracer) =
+        (page.snapshots.clone(), page.viewport, page.mobile, page.device_pixel_ratio, page.debug_boxes, page.threads, page.tracer.clone());
+    let expect_screenshot_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, name: String| -> rquickjs::Result<&'static str> {
+        let draw_target = render_page(
+            &document,
+            &stylesheet.borrow(),
+            &images.borrow(),
+            fonts.borrow().web_fonts(),
+            viewport,
+            mobile,
+            device_pixel_ratio,
+            debug_boxes,
+            threads,
+            &tracer,
+        );
+        snapshots
+            .check(&name, &Image::from_draw_target(&draw_target))
+            .map(|outcome| outcome.as_str())
+            .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+    })?;
+    globals.set("expectScreenshot", expect_screenshot_fn)?;
+
+    // Expose expectDomSnapshot(name, node): the same for a subtree's DOM
+    let (document, snapshots) = (page.document.clone(), page.snapshots.clone());
+    let expect_dom_snapshot_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, name: String, node: JsNode| -> rquickjs::Result<&'static str> {
+        let document = document.borrow();
+        let node = node.live(&ctx, &document)?;
+        snapshots
+            .check_dom(&name, &snapshot::dom_snapshot(&document, node))
+            .map(|outcome| outcome.as_str())
+            .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+    })?;
+    globals.set("expectDomSnapshot", expect_dom_snapshot_fn)?;
+
+    // Expose expect(node).toBeVisible() under the page styles
+    let (document, stylesheet, threads) = (page.document.clone(), page.stylesheet.clone(), page.threads);
+    let is_visible_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<bool> {
+        let document = document.borrow();
+        let node = node.live(&ctx, &document)?;
+        let styles = style::compute_styles_with_threads(&document, &stylesheet.borrow(), threads);
+        Ok(ElementRef::new(node).is_visible(&document, &styles))
+    })?;
+    globals.set("__cortexIsVisible", is_visible_fn)?;
+    ctx.eval::<(), _>(ELEMENT_MATCHERS)?;
+
+    // Expose measureText(text, font, maxWidth): text metrics in the page's
+    // fonts, with a canvas `font` value and an optional wrapping width
+    let fonts = page.fonts.clone();
+    let measure_text_fn = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, text: Coerced<String>, font: Opt<String>, max_width: Opt<f64>| -> rquickjs::Result<Object<'js>> {
+            let (size, family) = parse_canvas_font(font.0.as_deref().unwrap_or("10px sans-serif"));
+            let metrics = fonts.borrow().measure_text(&text.0, size, &family, max_width.0.map(|width| width as f32));
+            let result = Object::new(ctx)?;
+            result.set("width", metrics.width)?;
+            result.set("ascent", metrics.ascent)?;
+            result.set("descent", metrics.descent)?;
+            result.set("lineCount", metrics.line_count)?;
+            // The names canvas code reads the font's extent by
+            result.set("fontBoundingBoxAscent", metrics.ascent)?;
+            result.set("fontBoundingBoxDescent", metrics.descent)?;
+            Ok(result)
+        },
+    )?;
+    globals.set("measureText", measure_text_fn)?;
+
+    // Expose customElements registry to JavaScript
+    let custom_elements_registry = Arc::new(Mutex::new(CustomElementRegistry::new()));
+    let custom_elements_registry_clone = custom_elements_registry.clone();
+    let custom_elements_obj = Object::new(ctx.clone())?;
+
+    let define_fn = Function::new(ctx.clone(), move |tag_name: String, _constructor_fn: Function| {
+        let mut registry = custom_elements_registry_clone.lock().unwrap();
+        // For now, we just store a dummy index. Later, this will store a reference to the constructor function.
+        registry.define(&tag_name, 0);
+        tracing::debug!(tag_name, "customElements.define");
+    })?;
+    custom_elements_obj.set("define", define_fn)?;
+
+    let custom_elements_registry_clone = custom_elements_registry.clone();
+    let get_fn = Function::new(ctx.clone(), move |tag_name: String| -> Option<u32> {
+        let registry = custom_elements_registry_clone.lock().unwrap();
+        if let Some(idx) = registry.get(&tag_name) {
+            tracing::debug!(tag_name, index = idx, "customElements.get found a definition");
+            Some(*idx as u32)
+        } else {
+            tracing::debug!(tag_name, "customElements.get found no definition");
+            None
+        }
+    })?;
+    custom_elements_obj.set("get", get_fn)?;
+
+    globals.set("customElements", custom_elements_obj)?;
+
+    // Expose attachShadow functionality
+    let document_arc_clone = document_arc.clone();
+    let attach_shadow_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, host: JsNode, mode: String| -> rquickjs::Result<u32> {
+        let mut doc = document_arc_clone.borrow_mut();
+        let host_idx = host.live(&ctx, &doc)?;
+        let shadow_mode = match mode.as_str() {
+            "open" => dom::ShadowRootMode::Open,
+            "closed" => dom::ShadowRootMode::Closed,
+            _ => return Err(rquickjs::Error::Exception),
+        };
+        match doc.attach_shadow(host_idx, shadow_mode) {
+            Ok(idx) => Ok(idx as u32),
+            Err(_) => Err(rquickjs::Error::Exception),
+        }
+    })?;
+    globals.set("attachShadow", attach_shadow_fn)?;
+
+    // Expose cloneNode(node, deep), returning the detached copy's index
+    let document_rc = document_arc.clone();
+    let clone_node_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, deep: Opt<bool>| -> rquickjs::Result<usize> {
+        let mut document = document_rc.borrow_mut();
+        let node = node.live(&ctx, &document)?;
+        document.clone_node(node, deep.0.unwrap_or(false)).map_err(|e| Exception::throw_reference(&ctx, &e.to_string()))
+    })?;
+    globals.set("cloneNode", clone_node_fn.clone())?;
+
+    // Expose document.elementFromPoint(x, y), returning a node index or null
+    let (document_rc, stylesheet_rc, viewport, mobile) = (document_arc.clone(), page.stylesheet.clone(), page.viewport, page.mobile);
+    let element_from_point_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, x: f64, y: f64| -> Value<'js> {
+        let styles = style::compute_styles_with_threads(&document_rc.borrow(), &stylesheet_rc.borrow(), threads);
+        let size = layout_viewport(&document_rc.borrow(), viewport, mobile);
+        layout::calculate_styled_layout_with_threads(&mut document_rc.borrow_mut(), &styles, size.width, size.height, threads);
+        let document = document_rc.borrow();
+        match document.element_from_point(&styles, x as f32, y as f32) {
+            Some(node) => Value::new_number(ctx, node as f64),
+            None => Value::new_null(ctx),
+        }
+    })?;
+    let document_obj = Object::new(ctx.clone())?;
+    document_obj.set("elementFromPoint", element_from_point_fn)?;
+    // One document, so importing a node is cloning it
+    document_obj.set("importNode", clone_node_fn)?;
+    document_obj.set("readyState", "complete")?;
+    globals.set("document", document_obj)?;
+
+    // Expose FontFace and document.fonts, loading into the page's fonts
+    fonts::install_font_loading(ctx, page.fonts.clone(), page.loader.clone())?;
+
+    // Expose getBoundingClientRect(node), laying the page out first;
+    // boxless nodes report an empty rectangle at the origin
+    let (document_rc, stylesheet_rc, tracer) = (document_arc.clone(), page.stylesheet.clone(), page.tracer.clone());
+    let bounding_client_rect_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Object<'js>> {
+        let node = node.live(&ctx, &document_rc.borrow())?;
+        let styles = tracer.span(TraceStage::Style, "Compute styles", || {
+            style::compute_styles_with_threads(&document_rc.borrow(), &stylesheet_rc.borrow(), threads)
+        });
+        let size = layout_viewport(&document_rc.borrow(), viewport, mobile);
+        tracer.span(TraceStage::Layout, "Layout", || {
+            layout::calculate_styled_layout_with_threads(&mut document_rc.borrow_mut(), &styles, size.width, size.height, threads)
+        });
+        let document = document_rc.borrow();
+        let rect = transform::bounding_client_rect(&document, &styles, node).unwrap_or_default();
+        let dom_rect = Object::new(ctx)?;
+        for (name, value) in [
+            ("x", rect.x),
+            ("y", rect.y),
+            ("width", rect.width),
+            ("height", rect.height),
+            ("top", rect.y),
+            ("right", rect.right()),
+            ("bottom", rect.bottom()),
+            ("left", rect.x),
+        ] {
+            dom_rect.set(name, value)?;
+        }
+        Ok(dom_rect)
+    })?;
+    globals.set("getBoundingClientRect", bounding_client_rect_fn)?;
+
+    // Expose addEventListener/dispatchEvent on nodes, calling JavaScript
+    // listeners
+    events::install_events(ctx, document_arc.clone())?;
+
+    // Expose requestAnimationFrame, performance.now() and the
+    // TransitionEvent and AnimationEvent classes on the page's timeline
+    animation::install_animation_bindings(ctx, page.timeline.clone())?;
+
+    // Expose the user event classes: mouse, wheel, focus, keyboard, input
+    // and submit events
+    user_events::install_user_events(ctx)?;
+
+    // Expose Range, getSelection() and document.createRange() over the
+    // page's selection
+    selection::install_selection(ctx, page.selection.clone())?;
+
+    // Expose document.execCommand for contenteditable editors
+    editing::install_editing(ctx, document_arc.clone(), page.selection.clone())?;
+
+    // Expose document.title, document.head and document.body
+    head::install_head(ctx, document_arc.clone())?;
+
+    // Expose document.write and document.currentScript to <script> elements
+    scripts::install_scripts(ctx, page.scripts.clone())?;
+
+    // Expose navigator.clipboard and ClipboardEvent over the page's clipboard
+    clipboard::install_clipboard(ctx, &page.clipboard)?;
+
+    // Expose Blob, File and FileReader, and the files of file inputs
+    files::install_files(ctx, document_arc.clone())?;
+
+    // Expose form control state (checked, selectedIndex, value) and serialization
+    forms::install_form_bindings(ctx, document_arc.clone())?;
+    validation::install_validation_bindings(ctx, document_arc.clone())?;
+    queries::install_query_bindings(ctx, document_arc.clone())?;
+
+    // Expose attributes, and element(node) with reflected properties and dataset
+    element::install_element_bindings(ctx, document_arc.clone())?;
+
+    // Expose describe/it/beforeEach/afterEach; tests run in `Page::run_tests`
+    test_runner::install_test_runner(ctx)?;
+
+    // Expose test reporting function
+    // Failed results capture a screenshot and DOM snapshot of the page
+    let reported = page.reported.clone();
+    let document_arc_clone_report = document_arc.clone();
+    let stylesheet_clone = page.stylesheet.clone();
+    let failure_capture_config = page.failure_capture.clone();
+    let report_test_result_fn = Function::new(ctx.clone(), move |name: String, passed: bool, message: String| {
+        let result = if passed {
+            TestResult::success(&name, &message)
+        } else {
+            TestResult::failure_string(&name, &message)
+        };
+        let doc = document_arc_clone_report.borrow();
+        let styles = if passed { Vec::new() } else { style::compute_styles_with_threads(&doc, &stylesheet_clone.borrow(), threads) };
+        let result = capture_on_failure(result, &doc, &styles, &failure_capture_config);
+        tracing::info!(test = %name, passed, "Test result reported");
+        reported.borrow_mut().push(result);
+    })?;
+    globals.set("reportTestResult", report_test_result_fn)?;
+
+    // Expose customFixture function
+    let document_arc_clone_fixture = document_arc.clone();
+    let custom_fixture_fn = Function::new(ctx.clone(), move |tag_name: String, attributes: Object| -> rquickjs::Result<u32> {
+        let mut doc = document_arc_clone_fixture.borrow_mut();
+        let element_idx = doc.create_element(&tag_name);
+        // Append to document body (for now, assuming body is at index 2)
+        doc.append_child(2, element_idx);
+
+        // Set attributes
+        for item in attributes.into_iter() {
+            let (key, value) = item?;
+            let key_str = key.to_string()?;
+            let value_str = value.as_string().ok_or(rquickjs::Error::Exception)?.to_string()?;
+            doc.set_attribute(element_idx, &key_str, &value_str);
+        }
+        Ok(element_idx as u32)
+    })?;
+    globals.set("customFixture", custom_fixture_fn)?;
+
+    // Note: customExpect, querySelector, and querySelectorAll will be integrated in Phase 5b
+    // after resolving rquickjs Context lifetime constraints
+    Ok(())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::{LimitKind, LIMIT_EXIT_CODE};
+    use crate::modules::ImportMap;
+    use crate::network::MockNetwork;
+    use crate::screenshot::decode_png;
+    use std::fs;
+
+    fn page() -> Page {
+        Browser::new().with_viewport(64, 48).with_seed(1).new_page().unwrap()
+    }
+
+    #[test]
+    fn test_load_html_and_query() {
+        // Given: A page with some markup
+        let page = page();
+        page.load_html(r#"<ul><li class="item">A</li><li class="item">B</li></ul>"#);
+
+        // When: We query it
+        let first = page.query(".item").unwrap().unwrap();
+        let all = page.query_all("li").unwrap();
+
+        // Then: Both queries see the loaded document
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0], first);
+        assert_eq!(page.document().text_content(all[1]), "B");
+        assert_eq!(page.query("table").unwrap(), None);
+    }
+
+    #[test]
+    fn test_pages_lay_out_on_their_own_thread_counts() {
+        // Given: A table large enough to split, on a page using one thread
+        // and a page using four
+        let html = crate::bench::BenchDocument::Table.html(300);
+        let pages: Vec<Page> = [1, 4].into_iter().map(|threads| Browser::new().with_threads(threads).new_page().unwrap()).collect();
+
+        // When: Both load and lay it out
+        for page in &pages {
+            page.load_html(&html);
+            page.layout();
+        }
+
+        // Then: Each kept its own setting, and the boxes are the same
+        assert_eq!((pages[0].threads, pages[1].threads), (1, 4));
+        assert!(pages[0].document().layouts() == pages[1].document().layouts());
+    }
+
+    #[test]
+    fn test_run_script_values_and_errors() {
+        let page = page();
+
+        assert_eq!(page.run_script("globalThis.answer = 6 * 7").unwrap(), "42");
+        assert_eq!(page.run_script("answer + 1").unwrap(), "43", "Globals persist between scripts");
+
+        match page.run_script("function f() { throw new TypeError('boom'); } f()") {
+            Err(BrowserError::JavaScriptError(message, Some(stack))) => {
+                assert_eq!(message, "TypeError: boom");
+                assert!(stack.contains("at f"), "{}", stack);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(page.run_script("throw 5"), Err(BrowserError::JavaScriptError("5".to_string(), None)));
+    }
+
+    #[test]
+    fn test_script_errors_name_their_file() {
+        let page = page();
+        page.run_script_named("function helper() {\n  throw new Error('deep');\n}", "helpers.js").unwrap();
+
+        match page.run_script_named("\nhelper();", "tests.js") {
+            Err(BrowserError::JavaScriptError(message, Some(stack))) => {
+                assert_eq!(message, "Error: deep");
+                let frames = stack_trace::parse_stack(&stack);
+                assert_eq!((frames[0].function.as_deref(), frames[0].file.as_str(), frames[0].line), (Some("helper"), "helpers.js", 2));
+                assert_eq!((frames[1].file.as_str(), frames[1].line), ("tests.js", 2));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_source_maps_apply_to_errors_and_tests() {
+        // Given: A bundle whose line 2 came from line 5 of src/save.ts,
+        // with its map inline
+        let map = r#"{"version":3,"sources":["src/save.ts"],"mappings":";AAIA"}"#;
+        let bundle = format!(
+            "describe('save', () => {{\n it('fails', () => {{ throw new Error('nope'); }}); }});\n//# sourceMappingURL={}",
+            network::encode_data_uri("application/json", map.as_bytes())
+        );
+        let page = page();
+        page.run_script_named(&bundle, "bundle.js").unwrap();
+
+        // When: Its test fails
+        let summary = page.run_tests();
+
+        // Then: The failure's stack points at the original source
+        match &summary.results[0].error {
+            Some(BrowserError::JavaScriptError(_, Some(stack))) => {
+                assert!(stack.contains("(src/save.ts:5:1)"), "{}", stack);
+                assert!(!stack.contains("bundle.js"), "{}", stack);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // And: Scripts without a map keep their own frames
+        match page.run_script_named("throw new Error('x')", "other.js") {
+            Err(BrowserError::JavaScriptError(_, Some(stack))) => assert!(stack.contains("other.js:1"), "{}", stack),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(page.add_source_map("bad.js", "{}").is_err());
+    }
+
+    #[test]
+    fn test_es_modules() {
+        // Given: A module root with a utility module and a mapped package
+        let root = std::env::temp_dir().join(format!("cortex_page_modules_{}", std::process::id()));
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::create_dir_all(root.join("vendor")).unwrap();
+        fs::write(root.join("lib/util.js"), "export const twice = n => n * 2;").unwrap();
+        fs::write(root.join("vendor/greet.js"), "export default name => 'hi ' + name;").unwrap();
+        let modules = ModuleConfig::new(&root).with_import_map(ImportMap::new().with_import("greet", "vendor/greet.js"));
+        let page = PageBuilder::new().with_viewport(64, 48).with_seed(1).with_modules(modules).build().unwrap();
+
+        // When: An entry module imports both
+        let entry = "import { twice } from './lib/util.js';\nimport greet from 'greet';\nglobalThis.out = greet(twice(21));";
+        page.run_module(entry, "main.js").unwrap();
+
+        // Then: Imports resolve, and unresolvable ones name the culprit
+        assert_eq!(page.run_script("out").unwrap(), "hi 42");
+        match page.run_module("import './lib/missing.js';", "lib/entry.js") {
+            Err(BrowserError::JavaScriptError(message, _)) => {
+                assert!(message.starts_with("ReferenceError: Error resolving module './lib/missing.js' from 'lib/entry.js': no file at"), "{}", message)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match page.run_module_file("throw new Error('in module');", &root.join("lib/util.js")) {
+            Err(BrowserError::JavaScriptError(message, _)) => assert_eq!(message, "Error: in module"),
+            other => panic!("unexpected {:?}", other),
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_typescript_and_jsx_run_directly() {
+        // Given: A TSX component and a module root holding it
+        let root = std::env::temp_dir().join(format!("cortex_page_transpile_{}", std::process::id()));
+        fs::create_dir_all(root.join("components")).unwrap();
+        let component = "/** @jsx h */\ninterface Props { label: string; count?: number }\n\
+            export function Badge({ label, count = 0 }: Props): object {\n  return <span class=\"badge\">{label}: {count}</span>;\n}";
+        fs::write(root.join("components/badge.tsx"), component).unwrap();
+        let page = PageBuilder::new().with_viewport(64, 48).with_seed(1).with_modules(ModuleConfig::new(&root)).build().unwrap();
+        page.run_script("globalThis.h = (tag, props, ...children) => ({ tag, props, children });").unwrap();
+
+        // When: A TypeScript test module imports it by its `.js` name
+        let spec = "import { Badge } from './components/badge.js';\n\
+            const badge = Badge({ label: 'Inbox', count: 3 }) as { tag: string, children: unknown[] };\n\
+            globalThis.out = JSON.stringify(badge!);";
+        page.run_module_file(spec, &root.join("spec.ts")).unwrap();
+
+        // Then: Both run, and errors point at the original lines
+        assert_eq!(page.run_script("out").unwrap(), r#"{"tag":"span","props":{"class":"badge"},"children":["Inbox",": ",3]}"#);
+        match page.run_script_file("type N = number;\nconst n: N = 1;\nthrow new Error('typed ' + n);", &root.join("fail.ts")) {
+            Err(BrowserError::JavaScriptError(message, Some(stack))) => {
+                assert_eq!(message, "Error: typed 1");
+                assert!(stack.contains("fail.ts:3"), "{}", stack);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_console_is_captured_per_page() {
+        // Given: A script that logs at several levels and two tests that
+        // check the console
+        let page = page();
+        page.run_script("console.log('count', { n: 2 }); console.error('Save failed');").unwrap();
+        page.run_script(
+            r#"
+            describe("console", () => {
+                it("logs an error", () => {
+                    console.error("boom");
+                    expect(console).toHaveLoggedError("boom");
+                });
+                it("starts clean", () => {
+                    expect(console).not.toHaveLoggedError();
+                });
+            });
+            "#,
+        )
+        .unwrap();
+
+        // When: The tests run
+        let summary = page.run_tests();
+
+        // Then: Earlier errors do not leak into either test, and Rust sees
+        // every entry
+        assert_eq!(summary.failed, 0, "{}", summary.format_summary());
+        assert_eq!(summary.results[0].console, [ConsoleEntry { level: ConsoleLevel::Error, message: "boom".to_string() }]);
+        assert!(summary.results[1].console.is_empty());
+        assert_eq!(page.console()[0], ConsoleEntry { level: ConsoleLevel::Log, message: "count { n: 2 }".to_string() });
+        assert_eq!(page.console_messages(ConsoleLevel::Error), vec!["Save failed", "boom"]);
+        page.clear_console();
+        assert!(page.console().is_empty());
+    }
+
+    #[test]
+    fn test_tracer_records_each_pipeline_stage() {
+        // Given: A traced page that loads, runs a test and saves a screenshot
+        let tracer = Tracer::new();
+        let page = Browser::new().with_viewport(200, 100).with_tracer(tracer.clone()).new_page().unwrap();
+        page.load_html("<html><head><style>p { color: red; }</style></head><body><p>Hi</p></body></html>");
+        page.run_script_named("describe('page', () => { it('lays out', () => { getBoundingClientRect(0); }); });", "spec.js").unwrap();
+        assert_eq!(page.run_tests().failed, 0);
+        let dir = std::env::temp_dir().join(format!("cortex_trace_{}", std::process::id()));
+        page.screenshot(&dir.join("page.png")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Then: Every stage ran, and the test's span holds its layout
+        let stages: Vec<_> = tracer.breakdown().into_iter().map(|(stage, _)| stage).collect();
+        assert_eq!(stages, TraceStage::ALL);
+        let events = tracer.events();
+        let test = events.iter().find(|e| e.stage == TraceStage::Test).unwrap();
+        assert_eq!(test.name, "page > lays out");
+        assert!(events.iter().any(|e| e.stage == TraceStage::Layout && e.start >= test.start && e.start <= test.start + test.duration));
+        assert!(events.iter().any(|e| e.stage == TraceStage::Js && e.name == "spec.js"));
+        assert!(page.tracer().to_chrome_json().contains("\"cat\":\"encode\""));
+    }
+
+    #[test]
+    fn test_stats_count_nodes_listeners_and_detached() {
+        // Given: A list with listeners, one item of which is removed
+        let page = page();
+        page.load_html(r#"<html><body><ul id="list"><li class="a" data-n="1">One</li><li>Two</li></ul></body></html>"#);
+        let list = page.query("#list").unwrap().unwrap();
+        page.run_script(&format!("addEventListener({0}, 'click', () => {{}}); addEventListener({0}, 'keydown', () => {{}});", list)).unwrap();
+        let first = page.query("li").unwrap().unwrap();
+        page.document_mut().remove_child(first);
+
+        // When: The page is laid out and its stats taken
+        page.layout();
+        let stats = page.stats();
+
+        // Then: The removed item and its text still count, as detached
+        assert_eq!(stats.tags.get("li"), Some(&2));
+        assert_eq!(stats.attributes, 3);
+        assert_eq!(stats.listeners, 2);
+        assert_eq!(stats.detached, 2);
+        assert_eq!(stats.elements + stats.text_nodes + 1, stats.nodes);
+        assert!(stats.laid_out > 0 && stats.heap_bytes > stats.nodes * std::mem::size_of::<dom::Node>() / 2);
+        assert!(stats.to_string().contains("Listeners:    2"));
+    }
+
+    #[test]
+    fn test_hidden_elements_and_to_be_visible() {
+        // Given: A page with a display:none menu, an invisible notice and a
+        // visible footer
+        let page = page();
+        page.load_html(
+            r#"<html><head><style>#menu { display: none; } #notice { visibility: hidden; }</style></head>
+            <body><nav id="menu"><a>Home</a></nav><p id="notice">Saved</p><footer>Bye</footer></body></html>"#,
+        );
+        let [menu, link, notice, footer] = ["#menu", "a", "#notice", "footer"].map(|selector| page.query(selector).unwrap().unwrap());
+
+        // When: The page is laid out
+        page.layout();
+
+        // Then: The menu has no boxes, the notice keeps its box, and only
+        // the footer counts as visible
+        assert!(page.document().layout(menu).is_none() && page.document().layout(link).is_none());
+        assert!(page.document().layout(notice).is_some());
+        assert_eq!([menu, link, notice, footer].map(|node| page.is_visible(node)), [false, false, false, true]);
+
+        // And the same holds for tests in the page
+        page.run_script(&format!(
+            r#"describe("visibility", () => {{
+                it("shows the footer", () => {{ expect({footer}).toBeVisible(); expect({notice}).not.toBeVisible(); }});
+                it("fails on the notice", () => {{ expect({notice}).toBeVisible(); }});
+            }});"#
+        ))
+        .unwrap();
+        let summary = page.run_tests();
+        assert_eq!((summary.passed, summary.failed), (1, 1), "{}", summary.format_summary());
+        assert!(summary.format_summary().contains(&format!("Expected node {notice} to be visible, but it is hidden")));
+    }
+
+    #[test]
+    fn test_latin1_page_loads_without_mojibake() {
+        // Given: A latin-1 page declaring its charset, as bytes from disk
+        let page = page();
+        let bytes = b"<html><head><meta charset=\"iso-8859-1\"><title>Men\xfa</title></head><body><p>Cr\xe8me br\xfbl\xe9e</p></body></html>";
+
+        // When: It is loaded from the bytes
+        page.load_html_bytes(bytes, None);
+
+        // Then: The text nodes hold the characters the page meant
+        let paragraph = page.query("p").unwrap().unwrap();
+        assert_eq!(page.document().text_content(paragraph), "Crème brûlée");
+        assert_eq!(page.run_script("document.title").unwrap(), "Menú");
+    }
+
+    #[test]
+    fn test_scripts_see_the_loaded_document() {
+        // Given: A page with a form loaded after the context was created
+        let page = page();
+        page.load_html(r#"<label for="e">Email</label><input id="e" name="email" value="a@b.c">"#);
+
+        // When: A script queries through the bindings
+        let value = page.run_script("getValue(getByLabelText('Email'))").unwrap();
+
+        // Then: It sees the new document
+        assert_eq!(value, "a@b.c");
+    }
+
+    #[test]
+    fn test_run_tests_collects_registered_and_reported() {
+        let page = page();
+        page.run_script(
+            r#"
+            reportTestResult("direct", true, "ok");
+            describe("suite", () => {
+                it("passes", () => {});
+                it("fails", () => { throw new Error("nope"); });
+            });
+            "#,
+        )
+        .unwrap();
+
+        let summary = page.run_tests();
+
+        let names: Vec<&str> = summary.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["direct", "suite > passes", "suite > fails"]);
+        assert_eq!((summary.passed, summary.failed, summary.seed), (2, 1, Some(1)));
+    }
+
+    #[test]
+    fn test_page_styles_and_screenshot() {
+        // Given: A page with a <style> element, plus an added stylesheet
+        let dir = tempfile::tempdir().unwrap();
+        let page = page();
+        page.load_html("<html><head><style>p { color: #ff0000; }</style></head><body><p>Hi</p></body></html>");
+        page.add_style("p { text-transform: uppercase; }");
+
+        // When: We compute styles and take a screenshot
+        let p = page.query("p").unwrap().unwrap();
+        let styles = style::compute_styles(&page.document(), &page.stylesheet());
+        let path = page.screenshot(&dir.path().join("page.png")).unwrap();
+
+        // Then: Both sources apply and the screenshot has the viewport size
+        assert_eq!(styles[p].color.as_deref(), Some("#ff0000"));
+        assert_eq!(styles[p].text_transform, Some(css::TextTransform::Uppercase));
+        let (width, height, _) = decode_png(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!((width, height), (64, 48));
+    }
+
+    // ========================================================================
+    // PageBuilder Tests
+    // ========================================================================
+
+    #[test]
+    fn test_page_builder_environment_globals() {
+        // Given: A page with a custom user agent, base URL and dark scheme
+        let configured = PageBuilder::new()
+            .with_user_agent("TestAgent/1.0")
+            .with_base_url("https://example.com/app/index.html")
+            .with_color_scheme(ColorScheme::Dark)
+            .with_seed(1)
+            .build()
+            .unwrap();
+
+        // Then: Scripts see the configured environment
+        assert_eq!(configured.run_script("navigator.userAgent").unwrap(), "TestAgent/1.0");
+        assert_eq!(configured.run_script("location.href").unwrap(), "https://example.com/app/index.html");
+        assert_eq!(configured.run_script("matchMedia('(prefers-color-scheme: dark)').matches").unwrap(), "true");
+        assert_eq!(configured.run_script("matchMedia('(prefers-color-scheme: light)').matches").unwrap(), "false");
+
+        // And: The defaults apply when nothing is configured
+        let page = page();
+        assert_eq!(page.user_agent(), DEFAULT_USER_AGENT);
+        assert_eq!(page.run_script("location.href").unwrap(), "about:blank");
+        assert_eq!(page.run_script("matchMedia('(prefers-color-scheme: light)').matches").unwrap(), "true");
+    }
+
+    #[test]
+    fn test_dark_mode_across_css_and_js() {
+        // Given: A themed page whose script follows the color scheme
+        let page = page();
+        page.load_html(
+            r#"<style>
+                p { color: #222222; }
+                @media (prefers-color-scheme: dark) { p { color: #eeeeee; } }
+            </style><p id="text">Hello</p>"#,
+        );
+        page.run_script(
+            r#"
+            globalThis.themes = [];
+            const dark = matchMedia("(prefers-color-scheme: dark)");
+            dark.addEventListener("change", e => themes.push(e.matches ? "dark" : "light"));
+            "#,
+        )
+        .unwrap();
+        let text = page.query("#text").unwrap().unwrap();
+        let color = |page: &Page| style::compute_styles(&page.document(), &page.stylesheet())[text].color.clone();
+        let light = color(&page);
+
+        // When: The page switches to dark mode, and back
+        page.set_color_scheme(ColorScheme::Dark).unwrap();
+        let dark = color(&page);
+        assert_eq!(page.run_script("dark.matches").unwrap(), "true");
+        page.set_color_scheme(ColorScheme::Light).unwrap();
+
+        // Then: The CSS and the script both followed
+        assert_eq!((light.as_deref(), dark.as_deref()), (Some("#222222"), Some("#eeeeee")));
+        assert_eq!(page.run_script("themes.join()").unwrap(), "dark,light");
+        assert_eq!(page.color_scheme(), ColorScheme::Light);
+    }
+
+    #[test]
+    fn test_reduced_motion_and_animation_toggle() {
+        // Given: A page asking for reduced motion, with animations off
+        let page = PageBuilder::new().with_reduced_motion(true).with_animations(false).with_seed(1).build().unwrap();
+        page.run_script(
+            r#"
+            const reduce = matchMedia("(prefers-reduced-motion: reduce)");
+            globalThis.duration = () => reduce.matches ? 0 : 300;
+            reduce.onchange = e => { globalThis.changed = e.matches; };
+            "#,
+        )
+        .unwrap();
+        assert_eq!(page.run_script("duration()").unwrap(), "0");
+        assert!(!page.animations());
+
+        // When: The user turns the preference off
+        page.set_reduced_motion(false).unwrap();
+
+        // Then: Motion-sensitive scripts see the change
+        assert_eq!(page.run_script("[duration(), changed].join()").unwrap(), "300,false");
+        assert!(!page.reduced_motion());
+    }
+
+    #[test]
+    fn test_step_transitions_and_keyframes() {
+        // Given: A toast that fades in with keyframes and a button whose
+        // color transitions
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(
+            r#"<html><head><style>
+                @keyframes fade-in { from { color: #ffffff; } to { color: #000000; } }
+                .toast { animation: fade-in 200ms linear; }
+                .button { color: #0000ff; transition: color 100ms linear; }
+                .pressed { color: #ff0000; }
+            </style></head><body><p class="toast">Saved</p><span class="button">Undo</span></body></html>"#,
+        );
+        let find = |class: &str| page.document().nodes.iter().position(|n| matches!(&n.data, Some(NodeData::Element(e)) if e.attributes.get("class").is_some_and(|c| c == class))).unwrap();
+        let (toast, button) = (find("toast"), find("button"));
+        page.with(|ctx| ctx.globals().set("ids", vec![toast, button]).unwrap());
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            for (const id of ids) {
+                for (const type of ["animationstart", "animationend", "transitionrun", "transitionend"]) {
+                    addEventListener(id, type, e => log.push(`${e.type} ${e.animationName ?? e.propertyName} ${e.elapsedTime}`));
+                }
+            }
+            globalThis.frames = [];
+            requestAnimationFrame(time => frames.push(time));
+            "#,
+        )
+        .unwrap();
+        let color = |node: usize| style::compute_styles(&page.document(), &page.stylesheet())[node].color.clone();
+
+        // When: Half the animation's time passes and the button is pressed
+        assert_eq!(page.advance_time(Duration::from_millis(100)).unwrap(), 6);
+        let toast_halfway = color(toast);
+        page.document_mut().set_attribute(button, "class", "button pressed");
+        page.advance_time(Duration::from_millis(50)).unwrap();
+        let button_partway = color(button);
+        page.advance_time(Duration::from_millis(100)).unwrap();
+
+        // Then: Each step shows the in-between values, and the end events fire
+        assert_eq!(toast_halfway.as_deref(), Some("rgb(128, 128, 128)"));
+        // The transition starts on the first frame after the change, so two
+        // of the three frames count
+        assert_eq!(button_partway.as_deref(), Some("rgb(85, 0, 170)"));
+        assert_eq!(color(button).as_deref(), Some("#ff0000"));
+        assert!(page.document().animated_style(toast).is_none(), "The animation no longer applies once ended");
+        let log: Vec<String> = page.run_script("log").unwrap().split(',').map(String::from).collect();
+        assert_eq!(log, ["animationstart fade-in 0", "transitionrun color 0", "animationend fade-in 0.2", "transitionend color 0.1"]);
+        assert_eq!(page.run_script("frames.join()").unwrap(), format!("{}", FRAME_INTERVAL_MS));
+        assert_eq!(page.animation_time(), Duration::from_millis(250));
+        assert_eq!(page.running_animations(), 0);
+    }
+
+    #[test]
+    fn test_bounding_client_rect_follows_transforms() {
+        // Given: A badge moved by a transform inside a scaled card
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(
+            r#"<html><head><style>
+                .card { transform: scale(2); transform-origin: 0 0; }
+                .badge { transform: translate(30px, 10px); }
+            </style></head><body><div class="card"><span class="badge">New</span></div></body></html>"#,
+        );
+        let badge = page.query("span").unwrap().unwrap();
+        page.layout();
+        let untransformed = page.document().layout(badge).unwrap().border_box();
+
+        // When: The page and a script ask where the badge is
+        let rect = page.bounding_client_rect(badge).unwrap();
+        page.with(|ctx| ctx.globals().set("badge", badge).unwrap());
+        let from_script = page.run_script("const r = getBoundingClientRect(badge); [r.left, r.top, r.width, r.right - r.x].join()").unwrap();
+
+        // Then: Both see the badge moved, then scaled from the card's corner
+        let card = page.document().layout(page.query("div").unwrap().unwrap()).unwrap().border_box();
+        let expected = Rect::new(
+            card.x + (untransformed.x + 30.0 - card.x) * 2.0,
+            card.y + (untransformed.y + 10.0 - card.y) * 2.0,
+            untransformed.width * 2.0,
+            untransformed.height * 2.0,
+        );
+        assert!(rect.approx_eq(&expected, 0.01), "{:?} != {:?}", rect, expected);
+        assert_eq!(from_script, format!("{},{},{},{}", rect.x as f64, rect.y as f64, rect.width as f64, rect.width as f64));
+        let unknown = page.run_script("getBoundingClientRect(9999)").unwrap_err();
+        assert!(unknown.to_string().contains("Node 9999 does not exist"), "{}", unknown);
+    }
+
+    #[test]
+    fn test_mouse_events_route_by_position() {
+        // Given: A toast covering a button, and a script tracking clicks
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(
+            r#"<html><head><style>
+                .passive { pointer-events: none; }
+                .slid { transform: translateY(100%); }
+            </style></head><body><button>Buy</button><div class="toast">Saved</div></body></html>"#,
+        );
+        let (button, toast) = (page.query("button").unwrap().unwrap(), page.query("div").unwrap().unwrap());
+        page.with(|ctx| ctx.globals().set("ids", vec![button, toast]).unwrap());
+        page.run_script(
+            r#"
+            globalThis.clicks = [];
+            for (const id of ids) addEventListener(id, "click", e => clicks.push(`${e.target}@${e.clientX},${e.clientY}`));
+            "#,
+        )
+        .unwrap();
+        let button_box = page.bounding_client_rect(button).unwrap();
+        let (x, y) = (button_box.x + 10.0, button_box.y + 10.0);
+        assert!(page.bounding_client_rect(toast).unwrap().contains(Point::new(x, y)));
+
+        // When: The user clicks there, then again once the toast ignores
+        // pointers, and once more after it slid away
+        let covered = page.dispatch_mouse_event("click", x, y).unwrap();
+        page.document_mut().set_attribute(toast, "class", "toast passive");
+        let passed_through = page.dispatch_mouse_event("click", x, y).unwrap();
+        page.document_mut().set_attribute(toast, "class", "toast slid");
+        let from_script = page.run_script(&format!("document.elementFromPoint({}, {})", x, y)).unwrap();
+
+        // Then: Each click reaches the element on top at that point
+        assert_eq!((covered, passed_through), (Some(toast), Some(button)));
+        assert_eq!(from_script, button.to_string());
+        assert_eq!(page.run_script("clicks.join(' ')").unwrap(), format!("{toast}@{x},{y} {button}@{x},{y}"));
+        assert_eq!(page.dispatch_mouse_event("click", -5.0, 10.0).unwrap(), None);
+        assert_eq!(page.run_script("document.elementFromPoint(-5, 10)").unwrap(), "null");
+    }
+
+    #[test]
+    fn test_mouse_moves_hover_and_clicks() {
+        // Given: A buy button inside a card, a panel moved below it, and a
+        // script logging the pointer's events
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(
+            r#"<html><head><style>
+                .buy { color: #0000ff; }
+                .buy:hover { color: #ff0000; }
+                .panel { transform: translateY(400px); }
+            </style></head><body><div class="card"><button class="buy">Buy</button></div><div class="panel">Info</div></body></html>"#,
+        );
+        let (card, button, panel) =
+            (page.query(".card").unwrap().unwrap(), page.query(".buy").unwrap().unwrap(), page.query(".panel").unwrap().unwrap());
+        page.with(|ctx| ctx.globals().set("ids", vec![card, button, panel]).unwrap());
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            for (const id of ids) {
+                for (const type of ["mouseover", "mouseout", "mouseenter", "mouseleave", "mousedown", "mouseup", "click"]) {
+                    addEventListener(id, type, e => {
+                        if (e.target === id) log.push(`${type}:${id}<${e.relatedTarget}>${e.buttons}`);
+                    });
+                }
+                addEventListener(id, "wheel", e => { log.push(`wheel:${id}:${e.deltaY}`); e.preventDefault(); });
+            }
+            "#,
+        )
+        .unwrap();
+        let button_box = page.bounding_client_rect(button).unwrap();
+        let panel_box = page.bounding_client_rect(panel).unwrap();
+        let (bx, by) = (button_box.x + 5.0, button_box.y + 5.0);
+        let (px, py) = (panel_box.x + 5.0, panel_box.y + 5.0);
+        let color = |node: usize| style::compute_styles(&page.document(), &page.stylesheet())[node].color.clone();
+
+        // When: The pointer moves onto the button and clicks it
+        assert_eq!(page.move_mouse(bx, by).unwrap(), Some(button));
+        let hovered_color = color(button);
+        assert_eq!(page.click_at(bx, by).unwrap(), Some(button));
+
+        // Then: It entered the card before the button, and the hover style applies
+        assert_eq!(page.hovered(), Some(button));
+        assert_eq!(hovered_color.as_deref(), Some("#ff0000"));
+        assert!(page.document().is_hovered(card));
+        assert_eq!(
+            page.run_script("log.splice(0).join(' ')").unwrap(),
+            format!(
+                "mouseover:{button}<null>0 mouseenter:{card}<null>0 mouseenter:{button}<null>0 \
+                 mousedown:{button}<null>1 mouseup:{button}<null>0 click:{button}<null>0"
+            )
+        );
+
+        // When: It moves on to the panel and turns the wheel
+        assert_eq!(page.move_mouse(px, py).unwrap(), Some(panel));
+        let canceled = !page.wheel(px, py, 120.0).unwrap();
+
+        // Then: The button and card are left, innermost first, and lose :hover
+        assert_eq!(
+            page.run_script("log.join(' ')").unwrap(),
+            format!(
+                "mouseout:{button}<{panel}>0 mouseleave:{button}<{panel}>0 mouseleave:{card}<{panel}>0 \
+                 mouseover:{panel}<{button}>0 mouseenter:{panel}<{button}>0 wheel:{panel}:120"
+            )
+        );
+        assert!(canceled);
+        assert_eq!(color(button).as_deref(), Some("#0000ff"));
+        assert!(!page.document().is_hovered(card));
+        assert_eq!(page.move_mouse(-5.0, -5.0).unwrap(), None);
+        assert_eq!(page.hovered(), None);
+    }
+
+    #[test]
+    fn test_clicking_links_records_navigation_requests() {
+        // Given: A plain link, a router link whose click listener cancels
+        // the default, and a link opening a new tab
+        let page = PageBuilder::new().with_base_url("https://shop.test/products/42").build().unwrap();
+        page.load_html(
+            r#"<html><body><p><a class="cart" href="/cart"><b>Cart</b></a> <a class="router" href="/orders">Orders</a> <a class="help" href="help" target="_blank">Help</a></p></body></html>"#,
+        );
+        let links = [".cart", ".router", ".help"].map(|selector| page.query(selector).unwrap().unwrap());
+        page.with(|ctx| ctx.globals().set("router", links[1]).unwrap());
+        page.run_script(r#"globalThis.routed = []; addEventListener(router, "click", e => { e.preventDefault(); routed.push("/orders"); });"#)
+            .unwrap();
+        let click = |link: usize| {
+            let rect = page.bounding_client_rect(link).unwrap();
+            page.click_at(rect.x + 2.0, rect.y + 2.0).unwrap()
+        };
+
+        // When: Each link is clicked, the first through the bold text inside it
+        let clicked: Vec<Option<usize>> = links.iter().map(|&link| click(link)).collect();
+
+        // Then: The plain links request navigations, the new tab detected,
+        // and the router link only runs its listener
+        assert_eq!(clicked, vec![page.query("b").unwrap(), Some(links[1]), Some(links[2])]);
+        let requests = page.navigation_requests();
+        assert_eq!(requests.iter().map(|request| request.url.as_str()).collect::<Vec<_>>(), vec!["https://shop.test/cart", "https://shop.test/products/help"]);
+        assert_eq!(requests.iter().map(|request| request.link).collect::<Vec<_>>(), vec![links[0], links[2]]);
+        assert_eq!(requests.iter().map(NavigationRequest::opens_new_window).collect::<Vec<_>>(), vec![false, true]);
+        assert_eq!(page.run_script("routed.join()").unwrap(), "/orders");
+    }
+
+    #[test]
+    fn test_clicking_checkboxes_and_radios_checks_them() {
+        // Given: A checkbox, a checkbox whose click listener cancels, and a
+        // radio group, each logging what script sees
+        let page = Browser::new().with_viewport(480, 120).with_seed(1).new_page().unwrap();
+        page.load_html(
+            r#"<html><head><style>body { display: flex; }</style></head><body><input type="checkbox" id="terms" /><input type="checkbox" id="locked" /><input type="radio" name="size" id="small" checked="" /><input type="radio" name="size" id="large" /></body></html>"#,
+        );
+        let [terms, locked, small, large] = ["#terms", "#locked", "#small", "#large"].map(|selector| page.query(selector).unwrap().unwrap());
+        page.run_script(&format!(
+            r#"
+            globalThis.seen = [];
+            for (const node of [{terms}, {locked}, {small}, {large}]) {{
+                addEventListener(node, "click", () => seen.push(`click:${{node}}:${{getChecked(node)}}`));
+                addEventListener(node, "change", e => seen.push(`change:${{node}}:${{e.isTrusted}}`));
+            }}
+            addEventListener({locked}, "click", e => e.preventDefault());
+            "#
+        ))
+        .unwrap();
+        let click = |node: usize| {
+            let rect = page.bounding_client_rect(node).unwrap();
+            page.click_at(rect.x + 2.0, rect.y + 2.0).unwrap()
+        };
+
+        // When: Each control is clicked, the chosen radio twice
+        for node in [terms, locked, large, large] {
+            assert_eq!(click(node), Some(node));
+        }
+
+        // Then: Listeners saw the new state during click, the canceled
+        // checkbox changed back, and change fired only on real changes
+        let checked = |node: usize| forms::is_checked(&page.document(), node);
+        assert_eq!((checked(terms), checked(locked), checked(small), checked(large)), (true, false, false, true));
+        assert_eq!(
+            page.run_script("seen.join(' ')").unwrap(),
+            format!("click:{terms}:true change:{terms}:true click:{locked}:true click:{large}:true change:{large}:true click:{large}:true")
+        );
+    }
+
+    #[test]
+    fn test_keyboard_types_tabs_and_submits() {
+        // Given: A search form whose field logs keyboard events, and a
+        // listener recording submissions
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(
+            r#"<html><body><form><input name="q"/><input name="page" value="1"/><button>Go</button></form></body></html>"#,
+        );
+        let inputs = page.query_all("input").unwrap();
+        let (form, button) = (page.query("form").unwrap().unwrap(), page.query("button").unwrap().unwrap());
+        page.with(|ctx| ctx.globals().set("ids", vec![inputs[0], form]).unwrap());
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            const [field, form] = ids;
+            for (const type of ["keydown", "keypress", "beforeinput", "input", "keyup", "change", "focus", "blur"]) {
+                addEventListener(field, type, e => log.push(`${type}:${e.key ?? e.inputType ?? ""}${e.shiftKey ? "+shift" : ""}`));
+            }
+            addEventListener(field, "keydown", e => { if (e.key === "!") e.preventDefault(); });
+            addEventListener(form, "submit", e => log.push(`submit:${e.submitter}`));
+            "#,
+        )
+        .unwrap();
+
+        // When: The field is focused and typed into
+        page.focus(Some(inputs[0])).unwrap();
+        page.type_text("Hi").unwrap();
+        page.press_key("!").unwrap();
+
+        // Then: Each key runs keydown, keypress, beforeinput, input and keyup,
+        // and a canceled keydown types nothing
+        assert_eq!(page.run_script("getValue(ids[0])").unwrap(), "Hi");
+        assert_eq!(
+            page.run_script("log.splice(0).join(' ')").unwrap(),
+            "focus: keydown:Shift+shift keydown:H+shift keypress:H+shift beforeinput:insertText input:insertText keyup:H+shift \
+             keyup:Shift keydown:i keypress:i beforeinput:insertText input:insertText keyup:i keydown:! keyup:!"
+        );
+
+        // When: Tab moves on, Shift+Tab comes back, and Enter is pressed
+        page.press_key("Tab").unwrap();
+        let after_tab = page.focused();
+        page.press_key("Backspace").unwrap();
+        page.press_key("Shift+Tab").unwrap();
+        page.press_key("Enter").unwrap();
+
+        // Then: Focus moved through the form, the edit committed on blur, and
+        // Enter submitted through the default button
+        assert_eq!(after_tab, Some(inputs[1]));
+        assert_eq!(page.run_script("getValue(ids[1])").unwrap(), "");
+        assert_eq!(page.focused(), Some(inputs[0]));
+        assert_eq!(
+            page.run_script("log.join(' ')").unwrap(),
+            format!("keydown:Tab change: blur: focus: keyup:Tab+shift keyup:Shift keydown:Enter keypress:Enter submit:{button} keyup:Enter")
+        );
+        let submissions = page.form_submissions();
+        assert_eq!(submissions.len(), 1);
+        assert_eq!((submissions[0].form, submissions[0].submitter), (form, Some(button)));
+        assert_eq!(submissions[0].entries, [("q".to_string(), "Hi".to_string()), ("page".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn test_selection_and_ranges() {
+        // Given: A paragraph with bold text, and a toolbar script showing
+        // the selected text on selectionchange
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html("<html><body><p>Hello <b>bold</b> world</p></body></html>");
+        let p = page.query("p").unwrap().unwrap();
+        let texts = page.document().nodes[p].children.clone();
+        let (hello, world) = (texts[0], texts[2]);
+        page.run_script(
+            r#"
+            globalThis.toolbar = [];
+            document.addEventListener("selectionchange", () => toolbar.push(getSelection().toString()));
+            "#,
+        )
+        .unwrap();
+
+        // When: The page selects backwards from "world" into "Hello"
+        page.select(BoundaryPoint::new(world, 3), BoundaryPoint::new(hello, 2)).unwrap();
+
+        // Then: Scripts see the same selection, and its range in tree order
+        assert_eq!(page.selected_text(), "llo bold wo");
+        assert_eq!(page.run_script("toolbar.join('|')").unwrap(), "llo bold wo");
+        let described = page
+            .run_script(
+                r#"(() => {
+                    const s = getSelection(), r = s.getRangeAt(0);
+                    return [s.type, s.direction, s.anchorNode, s.anchorOffset, r.startContainer, r.startOffset, r === s.getRangeAt(0)].join();
+                })()"#,
+            )
+            .unwrap();
+        assert_eq!(described, format!("Range,backward,{world},3,{hello},2,true"));
+
+        // When: A script builds a range over the bold element and selects it,
+        // changing it twice before the page runs its tasks
+        page.run_script(&format!(
+            r#"
+            const range = document.createRange();
+            range.selectNodeContents({p});
+            range.setStart({p}, 1);
+            range.setEnd({p}, 2);
+            getSelection().removeAllRanges();
+            getSelection().addRange(range);
+            range.collapse(true);
+            range.setEnd({p}, 2);
+            "#
+        ))
+        .unwrap();
+
+        // Then: The selection follows the range, with one event for the batch
+        assert_eq!(page.selected_text(), "bold");
+        assert_eq!(page.selection().anchor, Some(BoundaryPoint::new(p, 1)));
+        assert_eq!(page.run_script("toolbar.join('|')").unwrap(), "llo bold wo|bold");
+        let contains = format!("[getSelection().containsNode({}), getSelection().containsNode({hello})].join()", texts[1]);
+        assert_eq!(page.run_script(&contains).unwrap(), "true,false");
+        page.clear_selection().unwrap();
+        assert_eq!(page.run_script("[getSelection().type, getSelection().rangeCount].join()").unwrap(), "None,0");
+        assert!(page.run_script("document.createRange().setStart(0, 99)").is_err());
+    }
+
+    #[test]
+    fn test_contenteditable_typing_and_commands() {
+        // Given: An editor holding "Hi", logging its input events
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><div class="editor" contenteditable="true">Hi</div></body></html>"#);
+        let editor = page.query(".editor").unwrap().unwrap();
+        page.with(|ctx| ctx.globals().set("editor", editor).unwrap());
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            addEventListener(editor, "beforeinput", e => log.push(`before:${e.inputType}:${e.data}`));
+            addEventListener(editor, "input", e => log.push(`${e.inputType}:${e.data}`));
+            "#,
+        )
+        .unwrap();
+
+        // When: It is focused, typed into, and given a new line
+        page.focus(Some(editor)).unwrap();
+        page.type_text(" you").unwrap();
+        page.press_key("Backspace").unwrap();
+        page.press_key("Enter").unwrap();
+        page.type_text("ok").unwrap();
+
+        // Then: The text nodes changed at the caret, which follows the typing
+        assert_eq!(crate::serialize::inner_html(&page.document(), editor), "Hi yo<br>ok");
+        let caret = page.selection().focus.unwrap();
+        assert_eq!((page.document().text_content(caret.node), caret.offset), ("ok".to_string(), 2));
+        assert_eq!(
+            page.run_script("log.splice(0, 4).join(' ')").unwrap(),
+            "before:insertText:  insertText:  before:insertText:y insertText:y"
+        );
+        assert!(page.run_script("log.join(' ')").unwrap().contains("before:deleteContentBackward:null deleteContentBackward:null before:insertParagraph:null"));
+
+        // When: A script selects "yo" and makes it bold, then types over the
+        // selection
+        let hi = page.document().nodes[editor].children[0];
+        page.select(BoundaryPoint::new(hi, 3), BoundaryPoint::new(hi, 5)).unwrap();
+        let bolded = page.run_script("[document.execCommand('bold'), document.queryCommandState('bold')].join()").unwrap();
+        let html_bold = crate::serialize::inner_html(&page.document(), editor);
+        page.run_script("log.length = 0; document.execCommand('insertText', false, 'there')").unwrap();
+
+        // Then: The command wrapped the selection, and the insertion replaced it
+        assert_eq!(bolded, "true,true");
+        assert_eq!(html_bold, "Hi <b>yo</b><br>ok");
+        assert_eq!(crate::serialize::inner_html(&page.document(), editor), "Hi <b>there</b><br>ok");
+        assert_eq!(page.run_script("log.join(' ')").unwrap(), "insertText:there");
+        assert_eq!(page.run_script("document.execCommand('fly')").unwrap(), "false");
+    }
+
+    #[test]
+    fn test_title_meta_and_head() {
+        // Given: A page with a title and meta tags in its head
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(concat!(
+            r#"<html><head><meta charset="utf-8"><title>Home</title><meta name="description" content="Start here">"#,
+            r#"<link rel="canonical" href="/home"></head><body><p>Hello</p></body></html>"#
+        ));
+
+        // Then: The title and meta tags can be read from Rust and scripts
+        assert_eq!(page.title(), "Home");
+        assert_eq!(page.meta("description").as_deref(), Some("Start here"));
+        assert_eq!(page.links("canonical"), vec!["/home"]);
+        assert_eq!(page.run_script("document.title").unwrap(), "Home");
+        let body = page.query("body").unwrap().unwrap();
+        assert_eq!(page.run_script("document.body").unwrap(), body.to_string());
+
+        // When: A component sets the title
+        page.run_script("document.title = 'Inbox (2)'").unwrap();
+
+        // Then: The page sees the new title
+        assert_eq!(page.title(), "Inbox (2)");
+        assert_eq!(page.run_script("document.title").unwrap(), "Inbox (2)");
+
+        // And: The head takes no part in layout
+        page.layout();
+        let title = page.query("title").unwrap().unwrap();
+        assert!(page.document().layout(title).is_none());
+        assert!(page.document().layout(body).is_some());
+        assert_ne!(page.run_script("document.head").unwrap(), "null");
+    }
+
+    #[test]
+    fn test_script_elements_run_on_load() {
+        // Given: A page whose scripts define a component, write markup, load
+        // a deferred script and throw
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        let load = page.load_html(concat!(
+            r#"<html><head><script src="data:text/javascript,order.push('deferred')" defer=""></script>"#,
+            r#"<script>globalThis.order = ['head']; customElements.define('x-greeting', class {});</script></head>"#,
+            r#"<body><p>Before</p><script>order.push('body:' + document.currentScript); document.write('<i>written</i><script>order.push("written")<\/script>');</script>"#,
+            r#"<p>After</p><script>if (1 < 2) throw new Error('broken widget');</script></body></html>"#
+        ));
+
+        // Then: They ran in document order, deferred last, and the markup
+        // landed right after the writing script
+        let script = page.query_all("script").unwrap()[2];
+        assert_eq!(page.run_script("order.join()").unwrap(), format!("head,body:{},written,deferred", script));
+        assert_eq!(page.run_script("customElements.get('x-greeting') !== undefined").unwrap(), "true");
+        let body = page.query("body").unwrap().unwrap();
+        assert!(crate::serialize::inner_html(&page.document(), body).contains("</script><i>written</i><script>"));
+        assert_eq!(page.run_script("document.currentScript").unwrap(), "null");
+
+        // And: The failing script was reported without stopping the others
+        assert_eq!(load.scripts.len(), 5);
+        let failed: Vec<_> = load.scripts.iter().filter_map(|script| script.result.clone().err()).collect();
+        assert_eq!(failed, ["Error: broken widget"]);
+        assert_eq!(page.console_messages(ConsoleLevel::Error), ["Uncaught Error: broken widget"]);
+
+        // When: A script is added after load
+        let extra = {
+            let mut document = page.document_mut();
+            let nodes = parser::parse_fragment(&mut document, "<script>order.push('late')</script>");
+            document.append_child(body, nodes[0]);
+            nodes[0]
+        };
+        page.run_until_idle().unwrap();
+
+        // Then: It runs once, on the page's next turn
+        assert_eq!(page.run_script("order.join()").unwrap(), format!("head,body:{},written,deferred,late", script));
+        page.run_until_idle().unwrap();
+        assert_eq!(page.run_script("order.length").unwrap(), "5");
+        assert!(page.query_all("script").unwrap().contains(&extra));
+    }
+
+    #[test]
+    fn test_clone_and_import_nodes() {
+        // Given: A card with a shadow root and an edited input
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><div class="card" id="c"><h2>Title</h2><input name="q"/></div></body></html>"#);
+        let card = page.query(".card").unwrap().unwrap();
+        let input = page.query("input").unwrap().unwrap();
+        forms::set_value(&mut page.document_mut(), input, "typed");
+        page.document_mut().attach_shadow(card, dom::ShadowRootMode::Open).unwrap();
+
+        // When: Scripts clone it shallowly and deeply
+        let shallow: usize = page.run_script(&format!("cloneNode({})", card)).unwrap().parse().unwrap();
+        let deep: usize = page.run_script(&format!("document.importNode({}, true)", card)).unwrap().parse().unwrap();
+
+        // Then: Both copies are detached with the same attributes, only the
+        // deep one has copies of the children, and no shadow root is copied
+        let document = page.document();
+        assert_eq!(crate::serialize::to_html(&document, shallow), r#"<div class="card" id="c"></div>"#);
+        assert_eq!(crate::serialize::inner_html(&document, deep), crate::serialize::inner_html(&document, card));
+        assert!(document.nodes[deep].parent.is_none() && document.shadow_root(deep).is_none());
+        let input_copy = document.nodes[deep].children[1];
+        assert_ne!(input_copy, input);
+        assert_eq!(forms::value(&document, input_copy), "typed");
+        drop(document);
+        assert!(page.run_script("cloneNode(9999)").is_err());
+
+        // When: The card is removed for good
+        page.document_mut().remove_node(card);
+
+        // Then: Neither it nor a missing node can be copied
+        let mut other = Document::new();
+        assert_eq!(page.document_mut().clone_node(card, true), Err(dom::NodeError::Removed(card)));
+        assert_eq!(other.import_node(&page.document(), input, false), Err(dom::NodeError::Removed(input)));
+        assert_eq!(other.import_node(&page.document(), 9999, false), Err(dom::NodeError::Missing(9999)));
+    }
+
+    #[test]
+    fn test_removed_and_replaced_nodes_are_not_reused() {
+        // Given: A page with a title and a paragraph scripts listen to
+        let html = r#"<html><head><title>Old</title></head><body><p>Hi</p></body></html>"#;
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(html);
+        let paragraph = page.query("p").unwrap().unwrap();
+        let handle = page.node_id(paragraph).unwrap();
+        let old_title = page.document().nodes[page.query("title").unwrap().unwrap()].children[0];
+        page.run_script(&format!("globalThis.log = []; addEventListener({}, 'ping', () => log.push('old'))", paragraph)).unwrap();
+
+        // When: A script replaces the title's text, then uses the old text node
+        let error = page.run_script(&format!("document.title = 'New'; addEventListener({}, 'ping', () => {{}})", old_title)).unwrap_err();
+
+        // Then: It throws rather than listening on a discarded node
+        assert!(error.to_string().contains(&format!("ReferenceError: Node {} was removed from the document", old_title)), "{}", error);
+        assert!(matches!(page.node_id(old_title), Err(BrowserError::DOMError(_))));
+
+        // When: The page loads the same document again and pings the paragraph
+        page.load_html(html);
+        page.run_script(&format!("dispatchEvent({}, 'ping')", page.query("p").unwrap().unwrap())).unwrap();
+
+        // Then: The old handle and listener do not reach the new paragraph
+        let replaced = page.resolve(handle).unwrap_err();
+        assert_eq!(replaced.to_string(), format!("DOM Error: Node {} belongs to a document that has been replaced", paragraph));
+        assert_eq!(page.run_script("log.length").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_device_emulation() {
+        // Given: A page emulating an iPhone, in German
+        let device = Device::IPhone;
+        let navigator = device.navigator().with_languages(&["de-DE", "de"]).with_on_line(false);
+        let page = PageBuilder::new().with_device(device).with_navigator(navigator).with_seed(1).build().unwrap();
+
+        // Then: The viewport, navigator and media queries all agree
+        assert_eq!(page.viewport(), Viewport { width: 390, height: 844 });
+        assert_eq!(page.run_script("[innerWidth, devicePixelRatio].join()").unwrap(), "390,3");
+        assert!(page.run_script("navigator.userAgent").unwrap().contains("iPhone"));
+        assert_eq!(page.run_script("navigator.language + ' ' + navigator.languages.join()").unwrap(), "de-DE de-DE,de");
+        assert_eq!(page.run_script("[navigator.onLine, navigator.maxTouchPoints, navigator.platform].join()").unwrap(), "false,5,iPhone");
+        assert_eq!(page.run_script("matchMedia('(max-width: 600px) and (pointer: coarse)').matches").unwrap(), "true");
+
+        // And: A desktop page reports a mouse and a wide viewport
+        let desktop = Browser::new().with_device(Device::Desktop).with_seed(1).new_page().unwrap();
+        assert_eq!(desktop.run_script("matchMedia('(hover: hover) and (min-width: 1024px)').matches").unwrap(), "true");
+        assert_eq!(desktop.run_script("navigator.hardwareConcurrency").unwrap(), "4");
+    }
+
+    #[test]
+    fn test_viewport_meta_on_mobile_devices() {
+        // Given: A phone, and a page with a rule for narrow screens
+        let page = PageBuilder::new().with_device(Device::IPhone).with_device_pixel_ratio(1.0).with_seed(1).build().unwrap();
+        let html = |meta: &str| {
+            format!("<html><head>{meta}<style>@media (max-width: 600px) {{ p {{ color: red; }} }}</style></head><body><p>Hi</p></body></html>")
+        };
+        let color = |page: &Page| {
+            let p = page.query("p").unwrap().unwrap();
+            style::compute_styles(&page.document(), &page.stylesheet())[p].color.clone()
+        };
+
+        // When: The page has no viewport meta
+        page.load_html(&html(""));
+
+        // Then: It is laid out at the fallback width, sees that width in
+        // media queries, and is zoomed out into a screen-sized screenshot
+        assert_eq!(page.layout_viewport().width, 980.0);
+        page.layout();
+        assert_eq!(page.document().layout(page.query("html").unwrap().unwrap()).unwrap().rect.width, 980.0);
+        assert_eq!(color(&page), None);
+        assert_eq!((page.render().width(), page.render().height()), (390, 844));
+
+        // When: It asks for the device width
+        page.load_html(&html(r#"<meta name="viewport" content="width=device-width, initial-scale=1">"#));
+
+        // Then: It is laid out at the phone's width and the rule applies
+        assert_eq!(page.layout_viewport(), LayoutViewport { width: 390.0, height: 844.0, scale: 1.0 });
+        assert_eq!(color(&page), Some("red".to_string()));
+
+        // And: Desktops ignore the meta
+        let desktop = PageBuilder::new().with_viewport(800, 600).with_seed(1).build().unwrap();
+        desktop.load_html(&html(r#"<meta name="viewport" content="width=device-width">"#));
+        assert_eq!(desktop.layout_viewport().width, 800.0);
+    }
+
+    #[test]
+    fn test_root_font_size_sets_rem() {
+        // Given: A page with a larger initial font size
+        let page = PageBuilder::new().with_root_font_size(20.0).with_seed(1).build().unwrap();
+        page.load_html("<html><head><style>p { padding: 1rem; }</style></head><body><p>Hi</p></body></html>");
+
+        // When: It is laid out
+        page.layout();
+
+        // Then: rem and the inherited font size follow the setting
+        let p = page.query("p").unwrap().unwrap();
+        let layout = page.document().layout(p).unwrap().clone();
+        assert_eq!((layout.padding.left, layout.font_size), (20.0, 20.0));
+        assert!(PageBuilder::new().with_root_font_size(0.0).build().is_err());
+    }
+
+    #[test]
+    fn test_font_face_and_document_fonts() {
+        // Given: A page whose network serves one font and whose stylesheet
+        // loads another
+        let font = include_bytes!("../assets/DejaVuSansMono.ttf");
+        let network = Rc::new(MockNetwork::new().with_response("https://cdn.test/fonts/icons.ttf", font.to_vec()));
+        let page = PageBuilder::new()
+            .with_base_url("https://cdn.test/index.html")
+            .with_network(NetworkMode::Custom(network.clone()))
+            .with_seed(1)
+            .build()
+            .unwrap();
+        let css = format!("@font-face {{ font-family: Brand; src: url({}); }}", crate::network::encode_data_uri("font/ttf", font));
+        let load = page.load_html(&format!("<style>{}</style><p>Hi</p>", css));
+
+        // When: Scripts load faces from a URL, from bytes and from nowhere
+        let script = r#"
+            globalThis.log = [];
+            const icons = new FontFace("Icons", "url(fonts/icons.ttf) format('truetype')");
+            document.fonts.add(icons);
+            log.push(icons.status, document.fonts.check("16px Icons"));
+            icons.load().then(face => log.push(face.status, document.fonts.check("16px Icons")));
+            const bytes = new FontFace("Inline", new Uint8Array([1, 2, 3]));
+            bytes.loaded.catch(error => log.push(error.name));
+            new FontFace("Gone", "url(missing.ttf)").load().catch(() => log.push("missing"));
+            document.fonts.ready.then(set => log.push(set === document.fonts, set.size, set.status));
+        "#;
+        page.run_script(script).unwrap();
+
+        // Then: Each load settles, and the loaded families are the page's
+        assert_eq!(page.run_script("log.join(' ')").unwrap(), "unloaded false loaded true NetworkError missing true 1 loaded");
+        assert!(load.fonts[0].result.is_ok());
+        assert!(page.fonts().has_font_family("Brand") && page.fonts().has_font_family("Icons"));
+        assert!(!page.fonts().has_font_family("Inline") && !page.fonts().has_font_family("Gone"));
+        assert!(network.requests().contains(&"https://cdn.test/fonts/icons.ttf".to_string()));
+    }
+
+    #[test]
+    fn test_network_mode_and_base_url() {
+        // Given: A page whose network is a mock, behind a base URL
+        let network = Rc::new(MockNetwork::new().with_response("https://cdn.test/img/logo.png", b"not a png".to_vec()));
+        let page = PageBuilder::new()
+            .with_base_url("https://cdn.test/img/page.html")
+            .with_network(NetworkMode::Custom(network.clone()))
+            .with_seed(1)
+            .build()
+            .unwrap();
+
+        // When: The page loads an image by relative URL
+        let load = page.load_html(r#"<img src="logo.png"><img src="missing.png">"#);
+
+        // Then: Both URLs were resolved against the base and fetched
+        assert_eq!(network.requests(), vec!["https://cdn.test/img/logo.png", "https://cdn.test/img/missing.png"]);
+        assert_eq!(load.images.len(), 2);
+        assert!(load.images.iter().all(|image| image.result.is_err()));
+    }
+
+    #[test]
+    fn test_offline_page_loads_nothing() {
+        let page = page();
+
+        let load = page.load_html(r#"<img src="https://example.com/a.png">"#);
+
+        assert_eq!(load.images.len(), 1);
+        assert!(load.images[0].result.is_err());
+    }
+
+    #[test]
+    fn test_page_builder_fonts() {
+        let font = include_bytes!("../assets/DejaVuSansMono.ttf");
+
+        let page = PageBuilder::new().with_font("Brand Sans", font.to_vec()).with_seed(1).build().unwrap();
+
+        assert!(page.fonts().has_font_family("Brand Sans"));
+        assert!(PageBuilder::new().with_font("Broken", b"nope".to_vec()).with_seed(1).build().is_err());
+    }
+
+    #[test]
+    fn test_measure_text_from_scripts() {
+        // Given: A page with a font loaded under a family name
+        let font = include_bytes!("../assets/DejaVuSansMono.ttf");
+        let page = PageBuilder::new().with_font("Brand Sans", font.to_vec()).with_seed(1).build().unwrap();
+        let expected = page.fonts().measure_text("Hello world", 20.0, "'Brand Sans'", Some(100.0));
+
+        // When: A script measures text with a canvas font and a max width
+        let result = page
+            .run_script("const m = measureText('Hello world', \"bold 20px 'Brand Sans'\", 100); [m.width, m.ascent, m.descent, m.lineCount, m.fontBoundingBoxAscent].join(',')")
+            .unwrap();
+
+        // Then: It gets the same metrics as Rust
+        let numbers: Vec<f32> = result.split(',').map(|n| n.parse().unwrap()).collect();
+        assert!((numbers[0] - expected.width).abs() < 1e-3);
+        assert!((numbers[1] - expected.ascent).abs() < 1e-3);
+        assert!((numbers[2] - expected.descent).abs() < 1e-3);
+        assert_eq!(numbers[3], 2.0);
+        assert_eq!(numbers[4], numbers[1]);
+
+        // And the font defaults to 10px sans-serif
+        assert_eq!(page.run_script("measureText('').lineCount").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_run_script_drains_promise_jobs() {
+        let page = page();
+
+        page.run_script("globalThis.done = false; Promise.resolve().then(() => { done = true; })").unwrap();
+
+        assert_eq!(page.run_script("done").unwrap(), "true");
+        assert_eq!(page.run_until_idle().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_expect_screenshot_against_golden_masters() {
+        // Given: A page that records missing golden masters
+        let dir = tempfile::tempdir().unwrap();
+        let snapshots = SnapshotConfig::new(dir.path()).with_mode(crate::golden::SnapshotMode::Record);
+        let page = PageBuilder::new().with_viewport(32, 24).with_seed(1).with_snapshots(snapshots).build().unwrap();
+        page.load_html("<p>Hello</p>");
+
+        // When: A script checks a screenshot twice, then against a golden
+        // master that no longer matches
+        let first = page.run_script("expectScreenshot('hello')").unwrap();
+        let second = page.run_script("expectScreenshot('hello')").unwrap();
+        let mut red = Image::new(32, 24);
+        red.rgba.chunks_mut(4).for_each(|pixel| pixel.copy_from_slice(&[255, 0, 0, 255]));
+        red.save(&dir.path().join("hello.png")).unwrap();
+        let changed = page.run_script("expectScreenshot('hello')");
+
+        // Then: It is recorded, matched, then fails pointing at the diff
+        assert_eq!((first.as_str(), second.as_str()), ("recorded", "matched"));
+        match changed {
+            Err(BrowserError::JavaScriptError(message, _)) => {
+                assert!(message.contains("Screenshot 'hello' does not match"), "{}", message);
+                assert!(message.contains("hello.diff.png"), "{}", message);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(page.expect_screenshot("hello"), Err(BrowserError::ScreenshotError(_))));
+        assert!(dir.path().join(crate::golden::MANIFEST_FILE).exists());
+    }
+
+    #[test]
+    fn test_dom_snapshots_next_to_golden_masters() {
+        // Given: A page that records missing snapshots, showing a list
+        let dir = tempfile::tempdir().unwrap();
+        let snapshots = SnapshotConfig::new(dir.path()).with_mode(crate::golden::SnapshotMode::Record);
+        let page = PageBuilder::new().with_seed(1).with_snapshots(snapshots).build().unwrap();
+        page.load_html(r#"<html><body><ul class="todos"><li data-done="true">Write</li><li>Ship</li></ul></body></html>"#);
+        let list = page.query(".todos").unwrap().unwrap();
+
+        // When: The list is snapshotted, then checked again from a script
+        let recorded = page.assert_dom_snapshot("todos", list).unwrap();
+        let matched = page.run_script(&format!("expectDomSnapshot('todos', {})", list)).unwrap();
+
+        // Then: The snapshot was written as text beside the golden masters
+        assert!(matches!(recorded, SnapshotOutcome::Recorded(_)));
+        assert_eq!(matched, "matched");
+        let stored = std::fs::read_to_string(dir.path().join("todos.dom.txt")).unwrap();
+        assert!(stored.contains("  <li data-done=\"true\">\n    Write\n  </li>\n"), "{}", stored);
+
+        // When: The list changes
+        let ship = page.query_all("li").unwrap()[1];
+        page.document_mut().set_attribute(ship, "data-done", "true");
+
+        // Then: The check fails listing the changed line
+        let error = page.assert_dom_snapshot("todos", list).unwrap_err().to_string();
+        assert!(error.contains("DOM snapshot 'todos' does not match"), "{}", error);
+        assert!(error.contains("-   5   <li>\n+   5   <li data-done=\"true\">"), "{}", error);
+    }
+
+    #[test]
+    fn test_screenshot_element_and_clip() {
+        // Given: A page with a nested element
+        let dir = tempfile::tempdir().unwrap();
+        let page = page();
+        page.load_html(r#"<div><p class="card">Card</p></div>"#);
+        let card = page.query(".card").unwrap().unwrap();
+        page.layout();
+        let border_box = page.document().layout(card).unwrap().border_box();
+
+        // When: We capture the element, and a clip region
+        let element = page.screenshot_element(".card", &dir.path().join("card.png")).unwrap();
+        let clip = page.screenshot_clip(Rect::new(4.0, 4.0, 20.0, 10.0), &dir.path().join("clip.png")).unwrap();
+
+        // Then: Each image is cropped to its region
+        let (width, height, _) = decode_png(&std::fs::read(element).unwrap()).unwrap();
+        let (_, _, expected_width, expected_height) = border_box.round_out();
+        assert_eq!((width as i32, height as i32), (expected_width, expected_height));
+        let (width, height, _) = decode_png(&std::fs::read(clip).unwrap()).unwrap();
+        assert_eq!((width, height), (20, 10));
+
+        // And: Unknown elements and empty regions are errors
+        assert!(matches!(
+            page.screenshot_element(".missing", &dir.path().join("x.png")),
+            Err(BrowserError::NotFoundError(_))
+        ));
+        assert!(page.screenshot_clip(Rect::new(0.0, 0.0, 0.0, 5.0), &dir.path().join("x.png")).is_err());
+    }
+
+    #[test]
+    fn test_screenshot_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = page();
+
+        let jpeg = page.screenshot(&dir.path().join("page.jpg")).unwrap();
+        let webp = page.encode_screenshot(ImageFormat::WebP, 90).unwrap();
+        let raw = page.encode_screenshot(ImageFormat::RawRgba, 90).unwrap();
+
+        assert_eq!(&std::fs::read(jpeg).unwrap()[0..2], &[0xFF, 0xD8]);
+        assert_eq!(&webp[0..4], b"RIFF");
+        assert_eq!(raw.len(), 64 * 48 * 4);
+    }
+
+    #[test]
+    fn test_device_pixel_ratio() {
+        // Given: A 2x page
+        let hidpi = PageBuilder::new().with_viewport(64, 48).with_device_pixel_ratio(2.0).with_seed(1).build().unwrap();
+        hidpi.load_html("<div>Retina</div>");
+
+        // Then: Scripts see the ratio, layout stays in CSS pixels and
+        // screenshots are in device pixels
+        assert_eq!(hidpi.run_script("window.devicePixelRatio").unwrap(), "2");
+        hidpi.layout();
+        let div = hidpi.query("div").unwrap().unwrap();
+        let standard = page();
+        standard.load_html("<div>Retina</div>");
+        standard.layout();
+        let standard_div = standard.query("div").unwrap().unwrap();
+        assert_eq!(
+            hidpi.document().layout(div).unwrap().rect.width,
+            standard.document().layout(standard_div).unwrap().rect.width
+        );
+        let draw_target = hidpi.render();
+        assert_eq!((draw_target.width(), draw_target.height()), (128, 96));
+        let region = hidpi.render_region(Rect::new(0.0, 0.0, 10.0, 5.0));
+        assert_eq!((region.width(), region.height()), (20, 10));
+
+        assert_eq!(standard.run_script("devicePixelRatio").unwrap(), "1");
+        assert!(PageBuilder::new().with_device_pixel_ratio(0.0).build().is_err());
+    }
+
+    #[test]
+    fn test_incremental_frame_updates() {
+        // Given: A page whose first frame has been painted
+        let page = page();
+        page.load_html("<div>Frame</div>");
+        page.add_style("div { box-shadow: 4px 4px red; }");
+        assert!(!page.damage().is_empty());
+        page.update_frame();
+        assert!(page.damage().is_empty());
+
+        // When: A style change repaints the div
+        page.add_style("div { box-shadow: 4px 4px blue; }");
+        let damage = page.update_frame();
+
+        // Then: The damage is reported once and the kept frame matches a
+        // full render
+        assert!(!damage.is_empty());
+        assert!(page.update_frame().is_empty());
+        assert_eq!(page.frame().get_data(), page.render().get_data());
+    }
+
+    #[test]
+    fn test_full_page_screenshot() {
+        // Given: A page taller than its 120x48 viewport
+        let page = PageBuilder::new().with_viewport(120, 48).with_seed(1).build().unwrap();
+        page.load_html("<div>Tall</div>");
+
+        // When: The full page is rendered
+        let region = page.full_page_region();
+        let draw_target = page.render_full_page();
+
+        // Then: The capture keeps the viewport width and covers the
+        // 100px-tall block
+        assert_eq!(region.width, 120.0);
+        assert_eq!(region.height, 100.0);
+        assert_eq!((draw_target.width(), draw_target.height()), (120, 100));
+        assert_eq!(page.render().height(), 48, "Plain screenshots stay clamped to the viewport");
+
+        // And: A short page captures exactly the viewport
+        let short = PageBuilder::new().with_viewport(120, 600).with_seed(1).build().unwrap();
+        short.load_html("<div>One</div>");
+        assert_eq!(short.full_page_region(), Rect::new(0.0, 0.0, 120.0, 600.0));
+    }
+
+    #[test]
+    fn test_save_pdf() {
+        // Given: A page taller than its 120x48 viewport
+        let page = PageBuilder::new().with_viewport(120, 48).with_seed(1).build().unwrap();
+        page.load_html("<div>Printed</div>");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("docs/page.pdf");
+
+        // When: It is saved as a PDF split into 40px pages
+        let saved = page.save_pdf(&path, &PdfOptions::paged(40.0)).unwrap();
+
+        // Then: The whole 100px page is printed across three pages
+        let bytes = std::fs::read(saved).unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+        assert!(String::from_utf8_lossy(&bytes).contains("/Count 3"));
+        assert!(page.render_pdf(&PdfOptions::default().with_device_pixel_ratio(0.0)).is_err());
+    }
+
+    #[test]
+    fn test_websockets_reach_the_mock_server() {
+        // Given: A page whose script opens a socket and renders what arrives
+        let server = MockWebSocketServer::new();
+        let page = PageBuilder::new().with_websockets(server.clone()).with_seed(1).build().unwrap();
+        page.run_script(
+            r#"
+            globalThis.feed = [];
+            const ws = new WebSocket("wss://feed.test/prices");
+            ws.onopen = () => ws.send(JSON.stringify({ subscribe: "ACME" }));
+            ws.onmessage = e => feed.push(JSON.parse(e.data).price);
+            "#,
+        )
+        .unwrap();
+
+        // When: The server answers the subscription it received
+        let socket = server.connection("wss://feed.test/prices").unwrap();
+        assert_eq!(socket.text_messages(), [r#"{"subscribe":"ACME"}"#]);
+        socket.send(r#"{"price": 42}"#);
+        page.run_until_idle().unwrap();
+
+        // Then: The page handled it, and async tests can wait for messages
+        assert_eq!(page.run_script("feed.join()").unwrap(), "42");
+        page.run_script(
+            r#"
+            it("receives a pushed price", async () => {
+                const price = await new Promise(resolve => ws.addEventListener("message", e => resolve(JSON.parse(e.data).price)));
+                if (price !== 43) throw new Error("Expected 43, got " + price);
+            });
+            "#,
+        )
+        .unwrap();
+        socket.send(r#"{"price": 43}"#);
+        let summary = page.run_tests();
+        assert_eq!((summary.passed, summary.failed), (1, 0), "{:?}", summary.results);
+    }
+
+    #[test]
+    fn test_event_source_dashboard() {
+        // Given: A dashboard page subscribed to a feed its network serves
+        let network = Rc::new(MockNetwork::new().with_response("https://dash.test/api/stats", "id: 1\ndata: {\"users\": 10}\n\n"));
+        let page = PageBuilder::new()
+            .with_base_url("https://dash.test/")
+            .with_network(NetworkMode::Custom(network))
+            .with_seed(1)
+            .build()
+            .unwrap();
+        page.run_script(
+            r#"
+            globalThis.users = [];
+            const stats = new EventSource("api/stats");
+            stats.onmessage = e => users.push(JSON.parse(e.data).users);
+            "#,
+        )
+        .unwrap();
+
+        // When: The stream pushes an update, then drops
+        let stream = page.event_sources().stream("https://dash.test/api/stats").unwrap();
+        stream.send(r#"{"users": 12}"#);
+        stream.end();
+        page.run_until_idle().unwrap();
+        assert_eq!(stream.connection_count(), 1, "Reconnecting waits three seconds");
+        page.advance_time(Duration::from_secs(3)).unwrap();
+
+        // Then: The page saw both updates and reconnected, resuming from id 1
+        assert_eq!(page.run_script("users.join()").unwrap(), "10,12,10");
+        assert_eq!(stream.connection_count(), 2);
+        assert_eq!(stream.last_event_id().as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_debounced_search_waits_on_the_page_clock() {
+        // Given: A search box that debounces for 300ms and a polling badge
+        let page = page();
+        page.run_script(
+            r#"
+            globalThis.searches = [];
+            let pending;
+            globalThis.type = (query) => {
+                clearTimeout(pending);
+                pending = setTimeout(q => searches.push(q), 300, query);
+            };
+            globalThis.polls = 0;
+            globalThis.poller = setInterval(() => polls++, 1000);
+            "#,
+        )
+        .unwrap();
+
+        // When: The user types twice, 100ms apart, then waits
+        page.run_script("type('ca')").unwrap();
+        page.advance_time(Duration::from_millis(100)).unwrap();
+        page.run_script("type('cat')").unwrap();
+        page.advance_time(Duration::from_millis(299)).unwrap();
+        assert_eq!(page.run_script("searches.join()").unwrap(), "");
+        page.advance_time(Duration::from_millis(1700)).unwrap();
+
+        // Then: Only the last query was searched, and the poller ran each
+        // second, give or take a frame, until cleared
+        assert_eq!(page.run_script("searches.join()").unwrap(), "cat");
+        assert_eq!(page.run_script("polls").unwrap(), "2");
+        page.run_script("clearInterval(poller)").unwrap();
+        page.advance_time(Duration::from_secs(5)).unwrap();
+        assert_eq!(page.run_script("polls").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_copy_button_and_paste_handler() {
+        // Given: A copy-to-clipboard button and a field that trims pastes
+        let page = page();
+        page.load_html(r#"<button id="share">Share</button><input id="code">"#);
+        let (button, input) = (page.query("#share").unwrap().unwrap(), page.query("#code").unwrap().unwrap());
+        page.run_script(&format!(
+            r#"
+            addEventListener({button}, "click", () => navigator.clipboard.writeText("https://app.test/s/42"));
+            addEventListener({input}, "paste", e => {{
+                e.preventDefault();
+                setValue({input}, e.clipboardData.getData("text/plain").trim());
+            }});
+            dispatchEvent({button}, "click");
+            "#
+        ))
+        .unwrap();
+
+        // When: The user pastes the copied link, padded with spaces
+        assert_eq!(page.clipboard().text().as_deref(), Some("https://app.test/s/42"));
+        page.clipboard().set_text("  https://app.test/s/42\n");
+        let default_ran = page.paste(input).unwrap();
+
+        // Then: The handler replaced the default paste
+        assert!(!default_ran);
+        assert_eq!(page.run_script(&format!("getValue({input})")).unwrap(), "https://app.test/s/42");
+        assert!(page.copy(input).unwrap());
+    }
+
+    #[test]
+    fn test_avatar_upload_preview() {
+        // Given: An upload component previewing the chosen image
+        let page = page();
+        page.load_html(r#"<form><input type="file" id="avatar" accept="image/*" required><img id="preview"></form>"#);
+        let input = page.query("#avatar").unwrap().unwrap();
+        page.run_script(&format!(
+            r#"
+            globalThis.preview = "";
+            addEventListener({input}, "change", () => {{
+                const reader = new FileReader();
+                reader.onload = () => {{ preview = reader.result; }};
+                reader.readAsDataURL(getFiles({input})[0]);
+            }});
+            "#
+        ))
+        .unwrap();
+        assert_eq!(page.run_script(&format!("checkValidity({input})")).unwrap(), "false");
+
+        // When: The test chooses a file instead of the file dialog
+        page.set_input_files(input, vec![InputFile::new("me.png", vec![1, 2, 3])]).unwrap();
+
+        // Then: The component read it and the input is satisfied
+        assert_eq!(page.run_script("preview").unwrap(), "data:image/png;base64,AQID");
+        assert_eq!(page.run_script(&format!("getValue({input})")).unwrap(), "C:\\fakepath\\me.png");
+        assert_eq!(page.run_script(&format!("checkValidity({input})")).unwrap(), "true");
+        let two = vec![InputFile::new("a.png", vec![]), InputFile::new("b.png", vec![])];
+        assert!(matches!(page.set_input_files(input, two), Err(BrowserError::InvalidOperationError(_))));
+    }
+
+    #[test]
+    fn test_filling_and_selecting_fire_change_at_script_listeners() {
+        // Given: A form whose script reacts to input and change
+        let page = page();
+        page.load_html(
+            r#"<form><input id="name" data-testid="name" /><select id="size" data-testid="size"><option>S</option><option>M</option></select></form><script>
+                globalThis.seen = [];
+                for (const id of ["name", "size"]) {
+                    const node = getByTestId(id);
+                    for (const type of ["input", "change"]) {
+                        addEventListener(node, type, e => seen.push(`${type}:${id}:${e.isTrusted}`));
+                    }
+                }
+            </script>"#,
+        );
+        let name = page.query("#name").unwrap().unwrap();
+        let size = page.query("#size").unwrap().unwrap();
+
+        // When: The test fills the field and picks an option, then repeats both
+        assert!(page.fill(name, "Ada").unwrap());
+        assert!(page.select_option(size, 1).unwrap());
+        assert!(!page.fill(name, "Ada").unwrap());
+        assert!(!page.select_option(size, 1).unwrap());
+
+        // Then: Listeners saw trusted events for the real changes only
+        assert_eq!(
+            page.run_script("seen.join(' ')").unwrap(),
+            "input:name:true change:name:true input:size:true change:size:true"
+        );
+        assert_eq!(page.run_script(&format!("getValue({size})")).unwrap(), "M");
+        assert!(matches!(page.fill(size, "M"), Err(BrowserError::InvalidOperationError(_))));
+    }
+
+    #[test]
+    fn test_iframes_render_nested_pages_and_exchange_messages() {
+        // Given: A page framing a red document that greets its parent
+        let page = Browser::new().with_viewport(200, 120).with_seed(1).new_page().unwrap();
+        let load = page.load_html(
+            r#"<html><body><iframe width="80" height="40" srcdoc="<style>div { background-color: red; height: 100px; }</style><div></div><script>parent.postMessage({hello: document.readyState}); addEventListener(0, 'message', e => globalThis.got = e.data.n);</script>"></iframe><script>
+                globalThis.messages = [];
+                addEventListener(0, "message", e => messages.push(e.data.hello));
+            </script></body></html>"#,
+        );
+        let iframe = page.query("iframe").unwrap().unwrap();
+
+        // When: The parent runs its tasks and posts back into the frame
+        page.run_until_idle().unwrap();
+        page.with(|ctx| ctx.globals().set("iframe", iframe).unwrap());
+        page.run_script("contentWindow(iframe).postMessage({n: 7})").unwrap();
+        page.run_until_idle().unwrap();
+
+        // Then: Each realm received the other's message
+        assert_eq!(load.frames.len(), 1);
+        assert_eq!(page.frames(), vec![iframe]);
+        assert_eq!(page.run_script("messages.join()").unwrap(), "loading");
+        assert_eq!(page.frame_page(iframe).unwrap().run_script("got").unwrap(), "7");
+        assert_eq!(page.run_script("typeof got").unwrap(), "undefined");
+
+        // Then: The frame paints red inside the iframe's box only
+        let rect = page.bounding_client_rect(iframe).unwrap();
+        assert_eq!((rect.width, rect.height), (80.0, 40.0));
+        let target = page.render();
+        let pixel = |x: f32, y: f32| target.get_data()[(y as i32 * target.width() + x as i32) as usize];
+        assert_eq!(pixel(rect.x + rect.width / 2.0, rect.y + rect.height / 2.0), 0xffff0000);
+        assert_ne!(pixel(rect.x + rect.width + 20.0, rect.y + rect.height / 2.0), 0xffff0000);
+    }
+
+    #[test]
+    fn test_iframes_fetch_src_and_fire_load_or_error() {
+        // Given: A page framing a fetched document and a missing one
+        let network = MockNetwork::new().with_response("https://shop.test/ad.html", "<p id=ad>Sale</p>");
+        let page = PageBuilder::new().with_base_url("https://shop.test/").with_network(NetworkMode::Custom(Rc::new(network))).build().unwrap();
+
+        // When: It loads, recording the events each iframe receives
+        let load = page.load_html(
+            r#"<iframe data-testid="ad" src="ad.html"></iframe><iframe data-testid="gone" src="gone.html"></iframe><script>
+                globalThis.events = [];
+                for (const id of ["ad", "gone"]) {
+                    addEventListener(getByTestId(id), "load", () => events.push("load " + id));
+                    addEventListener(getByTestId(id), "error", () => events.push("error " + id));
+                }
+            </script>"#,
+        );
+
+        // Then: The fetched frame loaded under its own URL, the missing one
+        // failed and stays blank
+        let iframes = page.query_all("iframe").unwrap();
+        assert_eq!(load.frames.iter().map(|frame| frame.url.as_deref()).collect::<Vec<_>>(), vec![Some("https://shop.test/ad.html"), Some("https://shop.test/gone.html")]);
+        assert!(load.frames[0].result.is_ok() && load.frames[1].result.is_err());
+        assert_eq!(page.run_script("events.join()").unwrap(), "load ad,error gone");
+        let ad = page.frame_page(iframes[0]).unwrap();
+        assert_eq!(ad.base_url(), Some("https://shop.test/ad.html"));
+        assert!(ad.query("#ad").unwrap().is_some());
+        assert_eq!(page.bounding_client_rect(iframes[1]).unwrap().width, DEFAULT_FRAME_SIZE.0);
+    }
+
+    #[test]
+    fn test_images_fire_load_or_error_at_script_listeners() {
+        // Given: A page with an image the network serves and one it lacks
+        let png = crate::screenshot::encode_rgba_png(&[255, 0, 0, 255], 1, 1).unwrap();
+        let network = MockNetwork::new().with_response("https://shop.test/logo.png", png);
+        let page = PageBuilder::new().with_base_url("https://shop.test/").with_network(NetworkMode::Custom(Rc::new(network))).build().unwrap();
+
+        // When: It loads, recording the events each image receives
+        let load = page.load_html(
+            r#"<img data-testid="logo" src="logo.png"><img data-testid="gone" src="gone.png"><script>
+                globalThis.events = [];
+                for (const id of ["logo", "gone"]) {
+                    addEventListener(getByTestId(id), "load", e => events.push("load " + id + " " + e.isTrusted));
+                    addEventListener(getByTestId(id), "error", () => events.push("error " + id));
+                }
+            </script>"#,
+        );
+
+        // Then: Listeners saw the loaded image's load and the missing
+        // one's error, and loading again fires them again from the cache
+        assert!(load.images[0].result.is_ok() && load.images[1].result.is_err());
+        assert_eq!(page.run_script("events.join()").unwrap(), "load logo true,error gone");
+        page.load_resources();
+        assert_eq!(page.run_script("events.join()").unwrap(), "load logo true,error gone,load logo true,error gone");
+    }
+
+    #[test]
+    fn test_frames_reply_through_sources_and_transferred_ports() {
+        // Given: A shop page framing a widget that answers on the port it
+        // is sent and acknowledges the sender
+        let page = PageBuilder::new().with_base_url("https://shop.test/").build().unwrap();
+        page.load_html(
+            r#"<iframe srcdoc="<script>addEventListener(0, 'message', e => {
+                e.ports[0].postMessage('hi ' + e.data + ' from ' + e.origin);
+                e.source.postMessage('ack', '*');
+            });</script>"></iframe>"#,
+        );
+        let iframe = page.query("iframe").unwrap().unwrap();
+        page.with(|ctx| ctx.globals().set("iframe", iframe).unwrap());
+
+        // When: The page posts a port to the frame, and a message for
+        // another origin
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            addEventListener(0, "message", e => log.push(`${e.data} ${e.origin} ${e.source === contentWindow(iframe)}`));
+            const channel = new MessageChannel();
+            channel.port1.onmessage = e => log.push(e.data);
+            contentWindow(iframe).postMessage("elsewhere", "https://other.test");
+            contentWindow(iframe).postMessage("there", "/", [channel.port2]);
+            "#,
+        )
+        .unwrap();
+        page.run_until_idle().unwrap();
+
+        // Then: Only the same-origin message arrived, and both replies made
+        // it back
+        let log = page.run_script("log.join('; ')").unwrap();
+        assert!(log.contains("hi there from https://shop.test"), "{}", log);
+        assert!(log.contains("ack https://shop.test true"), "{}", log);
+        assert!(!log.contains("elsewhere"), "{}", log);
+    }
+
+    #[test]
+    fn test_limits_stop_runaway_scripts_documents_and_screenshots() {
+        // Given: A page with a script timeout, memory limit, node limit and
+        // screenshot limit
+        let limits = Limits::new()
+            .with_script_timeout(Duration::from_millis(50))
+            .with_memory(32 * 1024 * 1024)
+            .with_max_nodes(50)
+            .with_max_screenshot(1280, 1000);
+        let page = PageBuilder::new().with_seed(1).with_limits(limits).build().unwrap();
+        let kind = |result: Result<String, BrowserError>| match result {
+            Err(BrowserError::LimitExceeded(kind, _)) => Some(kind),
+            _ => None,
+        };
+
+        // When: Scripts loop forever, even in a catch, or allocate without
+        // end
+        let looped = page.run_script("for (;;) {}");
+        let caught = page.run_script("try { while (true) {} } catch (e) {} 'escaped'");
+        let allocated = page.run_script("const hoard = []; for (;;) hoard.push(new Array(1e5).fill(1));");
+
+        // Then: Each is stopped with the limit it ran into, and the page
+        // still runs scripts
+        assert_eq!(looped, Err(limits.timeout_error()));
+        assert_eq!(kind(caught), Some(LimitKind::ScriptTimeout));
+        assert_eq!(kind(allocated), Some(LimitKind::Memory));
+        assert_eq!(page.run_script("1 + 1").unwrap(), "2");
+
+        // When: A script adds too many nodes, and a document with too many
+        // loads
+        let added = page.run_script("for (let i = 0; i < 60; i++) cloneNode(0, true);");
+        let load = page.load_html(&format!("<html><body>{}</body></html>", "<p></p>".repeat(60)));
+
+        // Then: Both fail on the node limit, and the document loads blank
+        assert_eq!(kind(added), Some(LimitKind::DomNodes));
+        assert!(matches!(load.limit_exceeded, Some(BrowserError::LimitExceeded(LimitKind::DomNodes, _))));
+        assert_eq!(page.query("p").unwrap(), None);
+
+        // When: Screenshots of the viewport and a taller region are taken
+        let dir = std::env::temp_dir().join("cortex-limits-test");
+        let viewport = page.encode_screenshot(ImageFormat::Png, 90);
+        let tall = page.screenshot_clip(Rect::new(0.0, 0.0, 100.0, 1001.0), &dir.join("tall.png"));
+
+        // Then: Only the one over the size limit fails, before rendering
+        assert!(viewport.is_ok());
+        assert!(matches!(tall, Err(BrowserError::LimitExceeded(LimitKind::ScreenshotSize, _))));
+        assert!(!dir.join("tall.png").exists());
+        assert_eq!(tall.unwrap_err().exit_code(), LIMIT_EXIT_CODE);
+    }
+
+    #[test]
+    fn test_script_timeout_survives_the_test_runner() {
+        // Given: A page with a script timeout whose tests ran
+        let limits = Limits::new().with_script_timeout(Duration::from_millis(50));
+        let page = PageBuilder::new().with_seed(1).with_limits(limits).build().unwrap();
+        page.run_script("it('passes', () => {});").unwrap();
+        assert_eq!(page.run_tests().passed, 1);
+
+        // When: A later script loops forever
+        let result = page.run_script("for (;;) {}");
+
+        // Then: It is still interrupted
+        assert_eq!(result, Err(limits.timeout_error()));
+    }
+}
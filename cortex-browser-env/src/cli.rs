@@ -0,0 +1,1239 @@
+//! Command-Line Interface
+//! Parses `cortex-browser-env <subcommand> [options]` into a `Cli` and reads
+//! the pages and scripts it names from files or stdin
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::bench::BenchOptions;
+use crate::compat::CompatOptions;
+use crate::limits::Limits;
+use crate::logging::{LogConfig, LogFormat};
+use crate::browser::DEFAULT_ROOT_FONT_SIZE;
+pub use crate::browser::{Viewport, DEFAULT_VIEWPORT};
+use crate::device::Device;
+use crate::encoding;
+use crate::media::ColorScheme;
+use crate::geometry::Rect;
+use crate::inspect::InspectFormat;
+use crate::golden::{SnapshotConfig, SnapshotMode, DEFAULT_SNAPSHOT_DIR};
+use crate::modules::{ImportMap, ModuleConfig};
+use crate::transpile::TranspileOptions;
+use crate::webdriver::DEFAULT_PORT;
+use crate::reporters::{ReporterKind, ReporterOptions};
+use crate::screenshot::DEFAULT_QUALITY;
+use crate::seed::{parse_seed, DEFAULT_FROZEN_TIME, DETERMINISTIC_SEED};
+
+pub const USAGE: &str = "\
+Usage: cortex-browser-env <command> [options]
+
+Commands:
+  run <script.js>...       Load the page, run the scripts and any tests they register
+  test <script.js>...      Run the tests the scripts register and report the results
+  watch <script.js>...     Run the tests, then re-run them whenever a file changes
+  render [page.html]       Lay out the page and print the layout tree
+  render pdf [page.html]   Render the whole page to a PDF
+  screenshot [page.html]   Render the page to a PNG
+  bench                    Time layout and paint of synthetic pages, optionally
+                           against a JSON baseline
+  repl [page.html]         Load the page and evaluate JavaScript and commands
+                           (.query, .layout, .screenshot; .help lists them) at a prompt
+  webdriver                Serve the W3C WebDriver protocol over HTTP for test clients,
+                           loading file:// URLs from the working directory
+  compat [page.html]       Score the page's structure, layout and pixels against a real
+                           browser's screenshot and DOM dump (scripts/capture-dom.js)
+
+Screenshots are PNG unless the file extension is .jpg, .webp or .rgba (raw
+pixels). Scripts run in the order given. Any path may be `-` to read it from stdin,
+except under `watch`. TypeScript (.ts, .tsx) and JSX (.jsx) scripts and modules are
+transpiled as they load.
+
+Options:
+  --html <path|->          Page to load (default: an empty document)
+  --js <path|->            Script to run; may be repeated, same as a positional script
+  --css <path|->           Extra stylesheet applied after the page's <style> elements
+  --navigate <url|path>    run, test: open another page in the same tab, fetching
+                           paths from the working directory; scripts given after it
+                           run on the new page (repeatable)
+  --viewport <WxH>         Viewport size (default: 1280x720, or the device's)
+  --device <name>          Emulate desktop, iphone or android: viewport, pixel ratio,
+                           navigator and media queries (default: desktop)
+  --color-scheme <scheme>  prefers-color-scheme: light, dark, or both to save a
+                           screenshot in each as <name>-light and <name>-dark
+  --reduced-motion         Match prefers-reduced-motion: reduce
+  --disable-animations     Jump CSS transitions and animations to their end state
+  --seed <n>               Seed for Math.random and other randomness
+  --deterministic          Seed with 0 unless --seed is given and freeze Date at
+                           2020-01-01T00:00:00Z, so runs render identically
+  --freeze-time <ms>       Freeze Date at this many milliseconds since the Unix epoch
+  --module                 Run scripts as ES modules (always for .mjs and .mts files)
+  --module-root <dir>      Directory ES module imports resolve from (default: .)
+  --import-map <path>      JSON import map mapping bare specifiers to module paths
+  --jsx-factory <fn>       Function JSX compiles to (default: React.createElement);
+                           fragments use <fn>.Fragment, or Fragment for a plain name
+  --device-pixel-ratio <n> Device pixels per CSS pixel in screenshots (default: 1, 2 for PDFs)
+  --root-font-size <px>    Initial font size, which rem is relative to (default: 16)
+  --fallback-font <path>   Font for characters page fonts lack, e.g. emoji (repeatable)
+  --screenshot <path>      run: save a screenshot after the script finishes
+  -o, --output <path>      screenshot, render pdf: output file (default: screenshot.png, page.pdf)
+  --page-height <px>       render pdf: split into pages this tall (default: one page)
+  --clip <x,y,w,h>         screenshot, run --screenshot: capture only this region
+  --full-page              screenshot, run --screenshot: capture the whole scrollable page
+  --quality <1-100>        JPEG screenshot quality (default: 90)
+  --debug-boxes            All but render, render pdf, bench, compat: draw margin, border,
+                           padding and content boxes and node indexes over screenshots
+  --reporter <kind>        test: human, json, junit, tap or html (default: human)
+  --reporter-output <path> test: write the report to a file instead of stdout
+  --interval <ms>          watch: how often to check files for changes (default: 250)
+  --snapshot-dir <path>    Golden masters for expectScreenshot (default: golden_masters)
+  --record                 Create missing golden masters instead of failing
+  --update-snapshots       Rewrite golden masters that are missing or differ
+  --detect-leaks           run, test, watch: fail tests that leave new globals or
+                           queued tasks behind, naming them
+  --iterations <n>         bench: runs of each page, keeping the median (default: 10)
+  --baseline <path>        bench: fail if a stage is slower than in this baseline
+  --save-baseline <path>   bench: write this run's times as a baseline
+  --tolerance <percent>    bench: how much slower than the baseline is allowed (default: 20)
+  --port <n>               webdriver: port to listen on at 127.0.0.1 (default: 4444)
+  --capture <path>         compat: the browser's screenshot PNG, whose size the page
+                           is rendered at
+  --dom-dump <path>        compat: the browser's DOM dump
+  --threshold <percent>    compat: fail unless every score reaches this (default: 95)
+  --trace <path>           All but watch, bench, webdriver, compat: write parse, style, layout, paint, JS,
+                           encode and per-test times as Chrome trace-event JSON
+                           (about:tracing, Perfetto)
+  --stats                  All but watch, bench, webdriver, compat: print node, attribute and listener counts,
+                           estimated memory and glyph cache size when done
+  --inspect <format>       All but watch, bench, repl, webdriver, compat: print the body's tree with key
+                           attributes, computed style and layout boxes when done,
+                           as text or json
+  --threads <n>            Threads for style and layout of large pages (default: one
+                           per core; 1 runs everything on one thread)
+  --script-timeout <ms>    All but bench: stop a script, with the work it queued, that
+                           runs longer than this
+  --memory-limit <MB>      All but bench: memory the JavaScript runtime may allocate
+  --max-nodes <n>          All but bench: nodes a document may hold
+  --max-screenshot <WxH>   All but bench: largest screenshot in device pixels
+  --log <filter>           Engine log level and per-module levels, written to stderr,
+                           e.g. debug or warn,layout=debug (default: $CORTEX_LOG, or
+                           warn,console=info to show what scripts log)
+  --log-format <format>    text or json, one object per line for CI (default: text)
+  -h, --help               Show this help
+
+Exits with 0 on success, 1 when a script or test fails, 2 for invalid arguments
+and 3 when a limit is exceeded.";
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subcommand {
+    Run,
+    Test,
+    Watch,
+    Render,
+    RenderPdf,
+    Screenshot,
+    Bench,
+    Repl,
+    WebDriver,
+    Compat,
+}
+
+impl Subcommand {
+    pub fn parse(name: &str) -> Option<Subcommand> {
+        match name {
+            "run" => Some(Subcommand::Run),
+            "test" => Some(Subcommand::Test),
+            "watch" => Some(Subcommand::Watch),
+            "render" => Some(Subcommand::Render),
+            "screenshot" => Some(Subcommand::Screenshot),
+            "bench" => Some(Subcommand::Bench),
+            "repl" => Some(Subcommand::Repl),
+            "webdriver" => Some(Subcommand::WebDriver),
+            "compat" => Some(Subcommand::Compat),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subcommand::Run => "run",
+            Subcommand::Test => "test",
+            Subcommand::Watch => "watch",
+            Subcommand::Render => "render",
+            Subcommand::RenderPdf => "render pdf",
+            Subcommand::Screenshot => "screenshot",
+            Subcommand::Bench => "bench",
+            Subcommand::Repl => "repl",
+            Subcommand::WebDriver => "webdriver",
+            Subcommand::Compat => "compat",
+        }
+    }
+
+    fn takes_script(&self) -> bool {
+        matches!(self, Subcommand::Run | Subcommand::Test | Subcommand::Watch)
+    }
+}
+
+/// Parse `1280x720`
+pub fn parse_viewport(value: &str) -> Result<Viewport, String> {
+    let invalid = || format!("Invalid viewport '{}': expected WIDTHxHEIGHT, e.g. 1280x720", value);
+    let (width, height) = value.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: i32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: i32 = height.trim().parse().map_err(|_| invalid())?;
+    if width <= 0 || height <= 0 {
+        return Err(invalid());
+    }
+    Ok(Viewport { width, height })
+}
+
+/// Parse `x,y,width,height` in CSS pixels
+pub fn parse_clip(value: &str) -> Result<Rect, String> {
+    let invalid = || format!("Invalid clip '{}': expected x,y,width,height, e.g. 0,0,320,200", value);
+    let numbers = value
+        .split(',')
+        .map(|part| part.trim().parse::<f32>().ok().filter(|n| n.is_finite()))
+        .collect::<Option<Vec<f32>>>()
+        .ok_or_else(invalid)?;
+    match numbers[..] {
+        [x, y, width, height] if width > 0.0 && height > 0.0 => Ok(Rect::new(x, y, width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse a positive device pixel ratio such as `2` or `1.5`
+fn parse_device_pixel_ratio(value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(ratio) if ratio.is_finite() && ratio > 0.0 => Ok(ratio),
+        _ => Err(format!("Invalid device pixel ratio '{}': expected a positive number, e.g. 2", value)),
+    }
+}
+
+/// Parse a positive initial font size in CSS pixels
+fn parse_root_font_size(value: &str) -> Result<f32, String> {
+    match value.trim().trim_end_matches("px").parse::<f32>() {
+        Ok(size) if size.is_finite() && size > 0.0 => Ok(size),
+        _ => Err(format!("Invalid root font size '{}': expected a positive number of pixels, e.g. 20", value)),
+    }
+}
+
+/// Parse a positive PDF page height in CSS pixels
+fn parse_page_height(value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(height) if height.is_finite() && height > 0.0 => Ok(height),
+        _ => Err(format!("Invalid page height '{}': expected a positive number of pixels", value)),
+    }
+}
+
+/// Parse a JPEG quality from 1 to 100
+fn parse_quality(value: &str) -> Result<u8, String> {
+    match value.trim().parse::<u8>() {
+        Ok(quality) if (1..=100).contains(&quality) => Ok(quality),
+        _ => Err(format!("Invalid quality '{}': expected a number from 1 to 100", value)),
+    }
+}
+
+/// Where a page, stylesheet or script is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    Stdin,
+    File(PathBuf),
+}
+
+impl InputSource {
+    /// `-` is stdin, anything else a file path
+    pub fn parse(arg: &str) -> InputSource {
+        if arg == "-" {
+            InputSource::Stdin
+        } else {
+            InputSource::File(PathBuf::from(arg))
+        }
+    }
+
+    /// Read the whole input, decoded as `encoding::decode` sniffs it;
+    /// stdin is read from `stdin`
+    pub fn read_from(&self, stdin: &mut dyn Read) -> Result<String, String> {
+        let mut bytes = Vec::new();
+        let read = match self {
+            InputSource::Stdin => stdin.read_to_end(&mut bytes).map(|_| bytes).map_err(|e| format!("Cannot read stdin: {}", e)),
+            InputSource::File(path) => std::fs::read(path).map_err(|e| format!("Cannot read '{}': {}", path.display(), e)),
+        };
+        read.map(|bytes| encoding::decode(&bytes, None).0)
+    }
+
+    /// Read from the process's stdin or the file system
+    pub fn read(&self) -> Result<String, String> {
+        self.read_from(&mut std::io::stdin())
+    }
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputSource::Stdin => write!(f, "<stdin>"),
+            InputSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Read several inputs, reporting every unreadable one at once rather than
+/// stopping at the first
+pub fn read_all(sources: &[InputSource], stdin: &mut dyn Read) -> Result<Vec<String>, String> {
+    let mut contents = Vec::new();
+    let mut errors = Vec::new();
+    for source in sources {
+        match source.read_from(stdin) {
+            Ok(text) => contents.push(text),
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(contents)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// A parsed command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub command: Subcommand,
+    /// Scripts for `run` and `test`, in execution order
+    pub scripts: Vec<InputSource>,
+    pub html: Option<InputSource>,
+    pub css: Option<InputSource>,
+    /// Pages `--navigate` opens after the first, in order
+    pub navigations: Vec<Navigation>,
+    pub viewport: Viewport,
+    /// Device pixel ratio given on the command line or by the device, if any
+    pub device_pixel_ratio: Option<f32>,
+    /// Initial font size in CSS pixels
+    pub root_font_size: f32,
+    /// Font files for characters the page's fonts lack, tried in order
+    pub fallback_fonts: Vec<PathBuf>,
+    /// Device emulated; `--viewport` and `--device-pixel-ratio` override its
+    pub device: Device,
+    /// Color schemes pages render in, one screenshot each; `both` is light
+    /// then dark
+    pub color_schemes: Vec<ColorScheme>,
+    pub reduced_motion: bool,
+    /// Run CSS transitions and animations rather than jumping to their end
+    pub animations: bool,
+    pub seed: Option<u64>,
+    /// Milliseconds since the Unix epoch `Date` is frozen at
+    pub frozen_time: Option<f64>,
+    /// Run every script as an ES module
+    pub modules: bool,
+    /// Directory ES module imports resolve from
+    pub module_root: Option<PathBuf>,
+    /// Import map file for bare module specifiers
+    pub import_map: Option<PathBuf>,
+    /// Function `.jsx` and `.tsx` files compile JSX to
+    pub jsx_factory: Option<String>,
+    /// `--screenshot` for `run`, `--output` for `screenshot` and `render pdf`
+    pub screenshot: Option<PathBuf>,
+    /// Region the screenshot is cropped to
+    pub clip: Option<Rect>,
+    /// Capture the whole scrollable page instead of the viewport
+    pub full_page: bool,
+    /// JPEG screenshot quality
+    pub quality: u8,
+    /// Draw the debug overlay of layout boxes over screenshots
+    pub debug_boxes: bool,
+    /// `render pdf` page height in CSS pixels; `None` for a single page
+    pub page_height: Option<f32>,
+    pub reporter: ReporterOptions,
+    /// How often `watch` polls its files
+    pub watch_interval: Duration,
+    /// Golden masters scripts check screenshots against
+    pub snapshot_dir: PathBuf,
+    pub snapshot_mode: SnapshotMode,
+    /// Fail tests that leave new globals or queued tasks behind
+    pub detect_leaks: bool,
+    pub bench: BenchOptions,
+    pub compat: CompatOptions,
+    /// Port `webdriver` listens on
+    pub port: u16,
+    /// Where to write the Chrome trace-event JSON of the run
+    pub trace: Option<PathBuf>,
+    /// Print the page's document statistics when done
+    pub stats: bool,
+    /// Print the body's inspector tree when done, in this format
+    pub inspect: Option<InspectFormat>,
+    /// Threads for style and layout; `None` for one per core
+    pub threads: Option<usize>,
+    /// What pages' scripts and documents may use
+    pub limits: Limits,
+    /// `--log` filter; `CORTEX_LOG` or the default when unset
+    pub log: Option<String>,
+    pub log_format: LogFormat,
+}
+
+/// Default `--interval` for `watch`
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+impl Cli {
+    fn new(command: Subcommand) -> Self {
+        Cli {
+            command,
+            scripts: Vec::new(),
+            html: None,
+            css: None,
+            navigations: Vec::new(),
+            viewport: DEFAULT_VIEWPORT,
+            device_pixel_ratio: None,
+            root_font_size: DEFAULT_ROOT_FONT_SIZE,
+            fallback_fonts: Vec::new(),
+            device: Device::default(),
+            color_schemes: vec![ColorScheme::default()],
+            reduced_motion: false,
+            animations: true,
+            seed: None,
+            frozen_time: None,
+            modules: false,
+            module_root: None,
+            import_map: None,
+            jsx_factory: None,
+            screenshot: None,
+            clip: None,
+            full_page: false,
+            quality: DEFAULT_QUALITY,
+            debug_boxes: false,
+            page_height: None,
+            reporter: ReporterOptions::default(),
+            watch_interval: DEFAULT_WATCH_INTERVAL,
+            snapshot_dir: PathBuf::from(DEFAULT_SNAPSHOT_DIR),
+            snapshot_mode: SnapshotMode::default(),
+            detect_leaks: false,
+            bench: BenchOptions::default(),
+            compat: CompatOptions::default(),
+            port: DEFAULT_PORT,
+            trace: None,
+            stats: false,
+            inspect: None,
+            threads: None,
+            limits: Limits::default(),
+            log: None,
+            log_format: LogFormat::default(),
+        }
+    }
+
+    /// Where each color scheme's screenshot goes: `output` itself, or
+    /// `<stem>-<scheme>.<ext>` for each scheme of `--color-scheme both`
+    pub fn screenshot_outputs(&self, output: &Path) -> Vec<(ColorScheme, PathBuf)> {
+        if self.color_schemes.len() == 1 {
+            return vec![(self.color_schemes[0], output.to_path_buf())];
+        }
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        self.color_schemes
+            .iter()
+            .map(|&scheme| {
+                let name = match output.extension() {
+                    Some(ext) => format!("{}-{}.{}", stem, scheme.as_str(), ext.to_string_lossy()),
+                    None => format!("{}-{}", stem, scheme.as_str()),
+                };
+                (scheme, output.with_file_name(name))
+            })
+            .collect()
+    }
+
+    /// Where and how scripts' `expectScreenshot` checks run
+    pub fn snapshots(&self) -> SnapshotConfig {
+        SnapshotConfig::new(&self.snapshot_dir).with_mode(self.snapshot_mode)
+    }
+
+    /// The log filter and format: `--log`, else `CORTEX_LOG`, else the
+    /// default
+    pub fn log_config(&self) -> LogConfig {
+        LogConfig::resolve(self.log.as_deref(), self.log_format)
+    }
+
+    /// Read the `--fallback-font` files
+    pub fn read_fallback_fonts(&self) -> Result<Vec<Vec<u8>>, String> {
+        self.fallback_fonts
+            .iter()
+            .map(|path| std::fs::read(path).map_err(|e| format!("Cannot read font '{}': {}", path.display(), e)))
+            .collect()
+    }
+
+    /// Where ES modules load from, reading the import map if one was given
+    pub fn module_config(&self) -> Result<ModuleConfig, String> {
+        let mut config = ModuleConfig::new(self.module_root.clone().unwrap_or_else(|| PathBuf::from(".")));
+        if let Some(factory) = &self.jsx_factory {
+            let fragment = match factory.rsplit_once('.') {
+                Some((namespace, _)) => format!("{}.Fragment", namespace),
+                None => "Fragment".to_string(),
+            };
+            config = config.with_transpile_options(TranspileOptions::default().with_jsx_factory(factory, &fragment));
+        }
+        match &self.import_map {
+            Some(path) => Ok(config.with_import_map(ImportMap::load(path).map_err(|e| e.to_string())?)),
+            None => Ok(config),
+        }
+    }
+
+    /// Whether a script runs as an ES module: with `--module`, or when it
+    /// is a `.mjs` or `.mts` file
+    pub fn is_module(&self, script: &InputSource) -> bool {
+        self.modules || matches!(script, InputSource::File(path) if is_module_path(path))
+    }
+}
+
+/// A page `--navigate` opens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Navigation {
+    /// URL, or file path relative to the working directory
+    pub target: String,
+    /// How many scripts run before it, on the pages before
+    pub after_scripts: usize,
+}
+
+/// `.mjs` and `.mts` files are always ES modules
+pub fn is_module_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "mjs" || ext == "mts")
+}
+
+/// What the binary should do
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliAction {
+    Execute(Box<Cli>),
+    /// Print the usage text and exit successfully
+    Help,
+}
+
+/// Parse the full argument list, program name included
+pub fn parse_args(args: &[String]) -> Result<CliAction, String> {
+    let rest = args.get(1..).unwrap_or_default();
+    let Some(first) = rest.first() else {
+        return Err("Missing command".to_string());
+    };
+    if first == "-h" || first == "--help" || first == "help" {
+        return Ok(CliAction::Help);
+    }
+    let mut command = Subcommand::parse(first).ok_or_else(|| format!("Unknown command '{}'", first))?;
+    let mut i = 1;
+    if command == Subcommand::Render && rest.get(1).is_some_and(|arg| arg == "pdf") {
+        command = Subcommand::RenderPdf;
+        i = 2;
+    }
+    let mut cli = Cli::new(command);
+    let mut snapshot_modes = Vec::new();
+    let mut viewport = None;
+    let mut deterministic = false;
+
+    while i < rest.len() {
+        let arg = rest[i].as_str();
+        // `--flag=value` and `--flag value` are equivalent
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg, None),
+        };
+        let mut value = || -> Result<String, String> {
+            if let Some(value) = inline_value.clone() {
+                return Ok(value);
+            }
+            i += 1;
+            rest.get(i).cloned().ok_or_else(|| format!("{} requires a value", flag))
+        };
+
+        match flag {
+            "-h" | "--help" => return Ok(CliAction::Help),
+            "--html" => cli.html = Some(InputSource::parse(&value()?)),
+            "--css" => cli.css = Some(InputSource::parse(&value()?)),
+            "--navigate" if matches!(command, Subcommand::Run | Subcommand::Test) => {
+                let target = value()?;
+                cli.navigations.push(Navigation { target, after_scripts: cli.scripts.len() })
+            }
+            "--js" if command.takes_script() => cli.scripts.push(InputSource::parse(&value()?)),
+            "--viewport" => viewport = Some(parse_viewport(&value()?)?),
+            "--device" => cli.device = Device::parse(&value()?)?,
+            "--color-scheme" => cli.color_schemes = parse_color_schemes(&value()?)?,
+            "--reduced-motion" => cli.reduced_motion = true,
+            "--disable-animations" => cli.animations = false,
+            "--seed" => cli.seed = Some(parse_seed(&value()?)?),
+            "--deterministic" => deterministic = true,
+            "--freeze-time" => cli.frozen_time = Some(parse_frozen_time(&value()?)?),
+            "--module" if command.takes_script() => cli.modules = true,
+            "--module-root" if command.takes_script() => cli.module_root = Some(PathBuf::from(value()?)),
+            "--import-map" if command.takes_script() => cli.import_map = Some(PathBuf::from(value()?)),
+            "--jsx-factory" if command.takes_script() => cli.jsx_factory = Some(value()?),
+            "--device-pixel-ratio" => cli.device_pixel_ratio = Some(parse_device_pixel_ratio(&value()?)?),
+            "--root-font-size" => cli.root_font_size = parse_root_font_size(&value()?)?,
+            "--fallback-font" => cli.fallback_fonts.push(PathBuf::from(value()?)),
+            "--screenshot" if command == Subcommand::Run => cli.screenshot = Some(PathBuf::from(value()?)),
+            "-o" | "--output" if matches!(command, Subcommand::Screenshot | Subcommand::RenderPdf) => {
+                cli.screenshot = Some(PathBuf::from(value()?))
+            }
+            "--clip" if matches!(command, Subcommand::Run | Subcommand::Screenshot) => {
+                cli.clip = Some(parse_clip(&value()?)?)
+            }
+            "--page-height" if command == Subcommand::RenderPdf => {
+                cli.page_height = Some(parse_page_height(&value()?)?)
+            }
+            "--full-page" if matches!(command, Subcommand::Run | Subcommand::Screenshot) => cli.full_page = true,
+            "--quality" if matches!(command, Subcommand::Run | Subcommand::Screenshot) => {
+                cli.quality = parse_quality(&value()?)?
+            }
+            "--debug-boxes" if !matches!(command, Subcommand::Render | Subcommand::RenderPdf | Subcommand::Bench | Subcommand::Compat) => {
+                cli.debug_boxes = true
+            }
+            "--reporter" if command == Subcommand::Test => cli.reporter.kind = ReporterKind::parse(&value()?)?,
+            "--reporter-output" if command == Subcommand::Test => {
+                cli.reporter.output = Some(PathBuf::from(value()?))
+            }
+            "--interval" if command == Subcommand::Watch => cli.watch_interval = parse_interval(&value()?)?,
+            "--snapshot-dir" if command.takes_script() => cli.snapshot_dir = PathBuf::from(value()?),
+            "--record" if command.takes_script() => snapshot_modes.push(SnapshotMode::Record),
+            "--update-snapshots" if command.takes_script() => snapshot_modes.push(SnapshotMode::Update),
+            "--detect-leaks" if command.takes_script() => cli.detect_leaks = true,
+            "--iterations" if command == Subcommand::Bench => cli.bench.iterations = parse_iterations(&value()?)?,
+            "--baseline" if command == Subcommand::Bench => cli.bench.baseline = Some(PathBuf::from(value()?)),
+            "--save-baseline" if command == Subcommand::Bench => cli.bench.save_baseline = Some(PathBuf::from(value()?)),
+            "--tolerance" if command == Subcommand::Bench => cli.bench.tolerance = parse_tolerance(&value()?)?,
+            "--port" if command == Subcommand::WebDriver => cli.port = parse_port(&value()?)?,
+            "--capture" if command == Subcommand::Compat => cli.compat.screenshot = Some(PathBuf::from(value()?)),
+            "--dom-dump" if command == Subcommand::Compat => cli.compat.dom_dump = Some(PathBuf::from(value()?)),
+            "--threshold" if command == Subcommand::Compat => cli.compat.threshold = parse_threshold(&value()?)?,
+            "--trace" if !matches!(command, Subcommand::Watch | Subcommand::Bench | Subcommand::WebDriver | Subcommand::Compat) => {
+                cli.trace = Some(PathBuf::from(value()?))
+            }
+            "--stats" if !matches!(command, Subcommand::Watch | Subcommand::Bench | Subcommand::WebDriver | Subcommand::Compat) => {
+                cli.stats = true
+            }
+            "--inspect" if !matches!(command, Subcommand::Watch | Subcommand::Bench | Subcommand::Repl | Subcommand::WebDriver | Subcommand::Compat) => {
+                cli.inspect = Some(value()?.parse()?)
+            }
+            "--threads" => cli.threads = Some(parse_threads(&value()?)?),
+            "--log" => cli.log = Some(parse_log_filter(&value()?)?),
+            "--log-format" => cli.log_format = value()?.parse()?,
+            "--script-timeout" if command != Subcommand::Bench => {
+                cli.limits.script_timeout = Some(parse_script_timeout(&value()?)?)
+            }
+            "--memory-limit" if command != Subcommand::Bench => cli.limits.memory = Some(parse_memory_limit(&value()?)?),
+            "--max-nodes" if command != Subcommand::Bench => cli.limits.max_nodes = Some(parse_max_nodes(&value()?)?),
+            "--max-screenshot" if command != Subcommand::Bench => {
+                cli.limits.max_screenshot = Some(parse_screenshot_size(&value()?)?)
+            }
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("Unknown option '{}' for '{}'", flag, command.name()));
+            }
+            _ => set_positional(&mut cli, arg)?,
+        }
+        i += 1;
+    }
+
+    cli.viewport = viewport.unwrap_or(cli.device.viewport());
+    if deterministic {
+        cli.seed.get_or_insert(DETERMINISTIC_SEED);
+        cli.frozen_time.get_or_insert(DEFAULT_FROZEN_TIME);
+    }
+    if cli.device != Device::Desktop && cli.device_pixel_ratio.is_none() {
+        cli.device_pixel_ratio = Some(cli.device.device_pixel_ratio());
+    }
+
+    if command.takes_script() && cli.scripts.is_empty() {
+        return Err(format!("'{}' requires a script", command.name()));
+    }
+    let stdin_inputs = cli
+        .scripts
+        .iter()
+        .chain(&cli.html)
+        .chain(&cli.css)
+        .filter(|source| **source == InputSource::Stdin)
+        .count();
+    if snapshot_modes.contains(&SnapshotMode::Record) && snapshot_modes.contains(&SnapshotMode::Update) {
+        return Err("--record and --update-snapshots cannot be combined".to_string());
+    }
+    if let Some(&mode) = snapshot_modes.last() {
+        cli.snapshot_mode = mode;
+    }
+    if command == Subcommand::WebDriver && (cli.html.is_some() || cli.css.is_some()) {
+        return Err("'webdriver' loads pages by URL; --html and --css are not used".to_string());
+    }
+    if command == Subcommand::Compat {
+        if cli.html.is_none() || cli.compat.screenshot.is_none() || cli.compat.dom_dump.is_none() {
+            return Err("'compat' requires a page, --capture and --dom-dump".to_string());
+        }
+        if cli.css.is_some() {
+            return Err("'compat' renders the page as the browser saw it; --css is not used".to_string());
+        }
+    }
+    if matches!(command, Subcommand::Watch | Subcommand::Repl) && stdin_inputs > 0 {
+        return Err(format!("'{}' cannot read from stdin ('-')", command.name()));
+    }
+    if stdin_inputs > 1 {
+        return Err("Only one input can be read from stdin ('-')".to_string());
+    }
+    if command == Subcommand::Run && cli.clip.is_some() && cli.screenshot.is_none() {
+        return Err("--clip requires --screenshot".to_string());
+    }
+    if command == Subcommand::Run && cli.full_page && cli.screenshot.is_none() {
+        return Err("--full-page requires --screenshot".to_string());
+    }
+    if cli.full_page && cli.clip.is_some() {
+        return Err("--full-page and --clip cannot be combined".to_string());
+    }
+    let screenshots = command == Subcommand::Screenshot || command == Subcommand::Run && cli.screenshot.is_some();
+    if cli.color_schemes.len() > 1 && !screenshots {
+        return Err("--color-scheme both requires a screenshot".to_string());
+    }
+    if command == Subcommand::Screenshot && cli.screenshot.is_none() {
+        cli.screenshot = Some(PathBuf::from("screenshot.png"));
+    }
+    if command == Subcommand::RenderPdf && cli.screenshot.is_none() {
+        cli.screenshot = Some(PathBuf::from("page.pdf"));
+    }
+    Ok(CliAction::Execute(Box::new(cli)))
+}
+
+/// Parse `light`, `dark` or `both`
+fn parse_color_schemes(value: &str) -> Result<Vec<ColorScheme>, String> {
+    if value.trim().eq_ignore_ascii_case("both") {
+        return Ok(vec![ColorScheme::Light, ColorScheme::Dark]);
+    }
+    ColorScheme::parse(value).map(|scheme| vec![scheme])
+}
+
+/// Parse a positive number of milliseconds
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match value.trim().parse::<u64>() {
+        Ok(ms) if ms > 0 => Ok(Duration::from_millis(ms)),
+        _ => Err(format!("Invalid interval '{}': expected a positive number of milliseconds", value)),
+    }
+}
+
+/// Parse a time in milliseconds since the Unix epoch
+fn parse_frozen_time(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<u64>() {
+        Ok(ms) => Ok(ms as f64),
+        _ => Err(format!("Invalid time '{}': expected milliseconds since the Unix epoch", value)),
+    }
+}
+
+/// Parse a positive number of milliseconds a script may run
+fn parse_script_timeout(value: &str) -> Result<Duration, String> {
+    match value.trim().parse::<u64>() {
+        Ok(ms) if ms > 0 => Ok(Duration::from_millis(ms)),
+        _ => Err(format!("Invalid script timeout '{}': expected a positive number of milliseconds", value)),
+    }
+}
+
+/// Parse a positive number of megabytes into bytes
+fn parse_memory_limit(value: &str) -> Result<usize, String> {
+    match value.trim().trim_end_matches("MB").parse::<usize>() {
+        Ok(mb) if mb > 0 => mb.checked_mul(1024 * 1024).ok_or_else(|| format!("Memory limit '{}' is too large", value)),
+        _ => Err(format!("Invalid memory limit '{}': expected a positive number of megabytes, e.g. 256", value)),
+    }
+}
+
+/// Parse a positive node count
+fn parse_max_nodes(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Invalid node limit '{}': expected a positive number", value)),
+    }
+}
+
+/// Parse a `WIDTHxHEIGHT` screenshot size in device pixels
+fn parse_screenshot_size(value: &str) -> Result<(u32, u32), String> {
+    let size = parse_viewport(value).map_err(|_| format!("Invalid screenshot size '{}': expected WIDTHxHEIGHT, e.g. 4000x4000", value))?;
+    Ok((size.width as u32, size.height as u32))
+}
+
+/// Check a `--log` filter such as `warn,layout=debug`
+fn parse_log_filter(value: &str) -> Result<String, String> {
+    let config = LogConfig { filter: value.to_string(), ..LogConfig::default() };
+    config.env_filter().map(|_| config.filter)
+}
+
+/// Parse a positive number of benchmark runs
+fn parse_iterations(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Invalid iterations '{}': expected a positive number", value)),
+    }
+}
+
+/// Parse a TCP port; 0 picks a free one
+fn parse_port(value: &str) -> Result<u16, String> {
+    value.trim().parse::<u16>().map_err(|_| format!("Invalid port '{}': expected a number from 0 to 65535", value))
+}
+
+/// Parse a positive thread count
+fn parse_threads(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Invalid thread count '{}': expected a positive number", value)),
+    }
+}
+
+/// Parse a percentage of zero or more
+fn parse_tolerance(value: &str) -> Result<f64, String> {
+    match value.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent.is_finite() && percent >= 0.0 => Ok(percent),
+        _ => Err(format!("Invalid tolerance '{}': expected a percentage, e.g. 20", value)),
+    }
+}
+
+/// Parse a percentage from 0 to 100
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    match value.trim().trim_end_matches('%').parse::<f32>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("Invalid threshold '{}': expected a percentage from 0 to 100", value)),
+    }
+}
+
+fn set_positional(cli: &mut Cli, arg: &str) -> Result<(), String> {
+    if matches!(cli.command, Subcommand::Bench | Subcommand::WebDriver) {
+        return Err(format!("Unexpected argument '{}'", arg));
+    }
+    if cli.command.takes_script() {
+        cli.scripts.push(InputSource::parse(arg));
+    } else if cli.html.is_none() {
+        cli.html = Some(InputSource::parse(arg));
+    } else {
+        return Err(format!("Unexpected argument '{}'", arg));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(list: &[&str]) -> Result<CliAction, String> {
+        let args: Vec<String> = std::iter::once("cortex-browser-env").chain(list.iter().copied()).map(String::from).collect();
+        parse_args(&args)
+    }
+
+    fn execute(list: &[&str]) -> Cli {
+        match parse(list) {
+            Ok(CliAction::Execute(cli)) => *cli,
+            other => panic!("expected a command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_with_options() {
+        let cli = execute(&["run", "app.js", "--html", "page.html", "--viewport=800x600", "--screenshot", "out.png", "--seed", "9"]);
+
+        assert_eq!(cli.command, Subcommand::Run);
+        assert_eq!(cli.scripts, vec![InputSource::File(PathBuf::from("app.js"))]);
+        assert_eq!(cli.html, Some(InputSource::File(PathBuf::from("page.html"))));
+        assert_eq!(cli.viewport, Viewport { width: 800, height: 600 });
+        assert_eq!(cli.screenshot, Some(PathBuf::from("out.png")));
+        assert_eq!(cli.seed, Some(9));
+    }
+
+    #[test]
+    fn test_test_with_reporter() {
+        let cli = execute(&["test", "--reporter", "junit", "--reporter-output=report.xml", "spec.js"]);
+
+        assert_eq!(cli.scripts, vec![InputSource::File(PathBuf::from("spec.js"))]);
+        assert_eq!(
+            cli.reporter,
+            ReporterOptions { kind: ReporterKind::Junit, output: Some(PathBuf::from("report.xml")) }
+        );
+        assert_eq!(cli.viewport, DEFAULT_VIEWPORT);
+    }
+
+    #[test]
+    fn test_page_commands_take_html_positionally() {
+        let render = execute(&["render", "page.html", "--css", "site.css"]);
+        let screenshot = execute(&["screenshot", "page.html", "-o", "shot.png"]);
+
+        assert_eq!(render.html, Some(InputSource::File(PathBuf::from("page.html"))));
+        assert_eq!(render.css, Some(InputSource::File(PathBuf::from("site.css"))));
+        assert_eq!(screenshot.screenshot, Some(PathBuf::from("shot.png")));
+        assert_eq!(execute(&["screenshot"]).screenshot, Some(PathBuf::from("screenshot.png")));
+    }
+
+    #[test]
+    fn test_navigate_option() {
+        // Given: Scripts before, between and after two navigations
+        let cli = execute(&["run", "login.js", "--navigate", "account.html", "--js=account.js", "--navigate=https://shop.test/", "checkout.js"]);
+
+        // Then: Each navigation remembers how many scripts run before it
+        assert_eq!(
+            cli.navigations,
+            vec![
+                Navigation { target: "account.html".to_string(), after_scripts: 1 },
+                Navigation { target: "https://shop.test/".to_string(), after_scripts: 2 },
+            ]
+        );
+        assert_eq!(cli.scripts.len(), 3);
+        assert!(parse(&["screenshot", "--navigate", "next.html"]).is_err());
+        assert!(parse(&["watch", "spec.js", "--navigate", "next.html"]).is_err());
+    }
+
+    #[test]
+    fn test_module_options() {
+        let cli = execute(&["test", "spec.js", "--module-root", "src", "--import-map=imports.json"]);
+
+        assert_eq!(cli.module_root, Some(PathBuf::from("src")));
+        assert_eq!(cli.import_map, Some(PathBuf::from("imports.json")));
+        assert!(!cli.is_module(&InputSource::parse("spec.js")));
+        assert!(cli.is_module(&InputSource::parse("spec.mjs")), ".mjs files are always modules");
+        assert!(execute(&["run", "--module", "app.js"]).is_module(&InputSource::parse("app.js")));
+        assert_eq!(parse(&["render", "--module"]), Err("Unknown option '--module' for 'render'".to_string()));
+
+        let missing = execute(&["test", "spec.js", "--import-map", "missing.json"]).module_config().unwrap_err();
+        assert!(missing.starts_with("Not Found: Cannot read import map 'missing.json'"), "{}", missing);
+        assert_eq!(execute(&["test", "spec.js"]).module_config().unwrap().root, PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_jsx_factory_option() {
+        assert!(execute(&["test", "spec.mts"]).is_module(&InputSource::parse("spec.mts")), ".mts files are always modules");
+        assert!(!execute(&["test", "spec.tsx"]).is_module(&InputSource::parse("spec.tsx")));
+
+        let preact = execute(&["test", "spec.tsx", "--jsx-factory", "preact.h"]).module_config().unwrap();
+        assert_eq!(preact.transpile, TranspileOptions::default().with_jsx_factory("preact.h", "preact.Fragment"));
+        let plain = execute(&["run", "--jsx-factory=h", "app.jsx"]).module_config().unwrap();
+        assert_eq!(plain.transpile.jsx_fragment, "Fragment");
+        assert_eq!(execute(&["test", "spec.tsx"]).module_config().unwrap().transpile, TranspileOptions::default());
+    }
+
+    #[test]
+    fn test_device_option() {
+        let phone = execute(&["screenshot", "page.html", "--device", "iphone"]);
+        assert_eq!((phone.device, phone.viewport, phone.device_pixel_ratio), (Device::IPhone, Viewport { width: 390, height: 844 }, Some(3.0)));
+
+        // Explicit sizes win over the device's, whatever the order
+        let landscape = execute(&["test", "spec.js", "--viewport=915x412", "--device=Android", "--device-pixel-ratio", "1"]);
+        assert_eq!((landscape.viewport, landscape.device_pixel_ratio), (Viewport { width: 915, height: 412 }, Some(1.0)));
+        assert_eq!(execute(&["render"]).device_pixel_ratio, None);
+        assert!(parse(&["render", "--device", "fridge"]).unwrap_err().starts_with("Unknown device 'fridge'"));
+    }
+
+    #[test]
+    fn test_color_scheme_option() {
+        assert_eq!(execute(&["test", "spec.js", "--color-scheme", "dark"]).color_schemes, vec![ColorScheme::Dark]);
+        assert_eq!(execute(&["render"]).color_schemes, vec![ColorScheme::Light]);
+
+        // Both schemes save one screenshot each
+        let both = execute(&["screenshot", "page.html", "-o", "shots/theme.png", "--color-scheme=both"]);
+        assert_eq!(
+            both.screenshot_outputs(both.screenshot.as_deref().unwrap()),
+            vec![
+                (ColorScheme::Light, PathBuf::from("shots/theme-light.png")),
+                (ColorScheme::Dark, PathBuf::from("shots/theme-dark.png")),
+            ]
+        );
+        assert_eq!(parse(&["test", "spec.js", "--color-scheme", "both"]), Err("--color-scheme both requires a screenshot".to_string()));
+        assert!(parse(&["render", "--color-scheme", "sepia"]).unwrap_err().starts_with("Unknown color scheme 'sepia'"));
+    }
+
+    #[test]
+    fn test_motion_options() {
+        let cli = execute(&["screenshot", "page.html", "--reduced-motion", "--disable-animations", "--root-font-size=20px"]);
+        assert!(cli.reduced_motion && !cli.animations);
+        assert_eq!(cli.root_font_size, 20.0);
+        let cli = execute(&["screenshot", "chat.html", "--fallback-font", "emoji.ttf", "--fallback-font=symbols.otf"]);
+        assert_eq!(cli.fallback_fonts, vec![PathBuf::from("emoji.ttf"), PathBuf::from("symbols.otf")]);
+        assert!(cli.read_fallback_fonts().unwrap_err().starts_with("Cannot read font 'emoji.ttf'"));
+        assert!(parse(&["render", "--root-font-size", "0"]).unwrap_err().starts_with("Invalid root font size '0'"));
+
+        let defaults = execute(&["test", "spec.js"]);
+        assert!(!defaults.reduced_motion && defaults.animations);
+    }
+
+    #[test]
+    fn test_help_and_errors() {
+        assert_eq!(parse(&["--help"]), Ok(CliAction::Help));
+        assert_eq!(parse(&["test", "spec.js", "-h"]), Ok(CliAction::Help));
+
+        assert_eq!(parse(&[]), Err("Missing command".to_string()));
+        assert_eq!(parse(&["serve"]), Err("Unknown command 'serve'".to_string()));
+        assert_eq!(parse(&["run"]), Err("'run' requires a script".to_string()));
+        assert_eq!(parse(&["render", "a.html", "b.html"]), Err("Unexpected argument 'b.html'".to_string()));
+        assert_eq!(parse(&["run", "a.js", "--html"]), Err("--html requires a value".to_string()));
+        assert_eq!(
+            parse(&["render", "--reporter", "json"]),
+            Err("Unknown option '--reporter' for 'render'".to_string())
+        );
+        assert!(parse(&["run", "a.js", "--viewport", "wide"]).is_err());
+        assert!(parse(&["run", "a.js", "--seed", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_multiple_scripts_and_stdin() {
+        // Given: Positional scripts mixed with --js, one of them from stdin
+        let cli = execute(&["run", "setup.js", "--js", "-", "--js=app.js", "--html", "page.html"]);
+
+        // Then: Scripts keep their command-line order
+        assert_eq!(
+            cli.scripts,
+            vec![
+                InputSource::File(PathBuf::from("setup.js")),
+                InputSource::Stdin,
+                InputSource::File(PathBuf::from("app.js")),
+            ]
+        );
+        assert_eq!(execute(&["screenshot", "-"]).html, Some(InputSource::Stdin));
+        assert_eq!(
+            parse(&["run", "-", "--html", "-"]),
+            Err("Only one input can be read from stdin ('-')".to_string())
+        );
+        assert!(parse(&["render", "--js", "a.js"]).is_err());
+    }
+
+    #[test]
+    fn test_reading_inputs() {
+        // Given: A readable file, a missing file and stdin
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("app.js");
+        std::fs::write(&script, "run()").unwrap();
+        let missing = InputSource::File(dir.path().join("missing.js"));
+        let missing_too = InputSource::File(dir.path().join("gone.js"));
+        let mut stdin = std::io::Cursor::new("<p>piped</p>");
+
+        // When: We read them
+        let ok = read_all(&[InputSource::File(script), InputSource::Stdin], &mut stdin);
+        let err = read_all(&[missing.clone(), missing_too.clone()], &mut std::io::empty()).unwrap_err();
+
+        // Then: Contents come back in order, and every missing file is reported
+        assert_eq!(ok, Ok(vec!["run()".to_string(), "<p>piped</p>".to_string()]));
+        assert_eq!(err.lines().count(), 2);
+        assert!(err.starts_with(&format!("Cannot read '{}': ", missing)));
+        assert!(err.contains(&format!("Cannot read '{}': ", missing_too)));
+        assert_eq!(InputSource::Stdin.to_string(), "<stdin>");
+    }
+
+    #[test]
+    fn test_watch_options() {
+        let cli = execute(&["watch", "spec.js", "--html", "page.html", "--interval=100"]);
+
+        assert_eq!(cli.command, Subcommand::Watch);
+        assert_eq!(cli.scripts, vec![InputSource::File(PathBuf::from("spec.js"))]);
+        assert_eq!(cli.watch_interval, Duration::from_millis(100));
+        assert_eq!(execute(&["watch", "spec.js"]).watch_interval, DEFAULT_WATCH_INTERVAL);
+
+        assert_eq!(parse(&["watch", "-"]), Err("'watch' cannot read from stdin ('-')".to_string()));
+        assert_eq!(parse(&["repl", "-"]), Err("'repl' cannot read from stdin ('-')".to_string()));
+        assert_eq!(execute(&["repl", "page.html"]).html, Some(InputSource::File(PathBuf::from("page.html"))));
+        assert!(parse(&["watch", "spec.js", "--interval", "0"]).is_err());
+        assert!(parse(&["test", "spec.js", "--interval", "100"]).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_options() {
+        let cli = execute(&["test", "spec.js", "--snapshot-dir", "shots", "--update-snapshots"]);
+
+        assert_eq!(cli.snapshots(), SnapshotConfig::new("shots").with_mode(SnapshotMode::Update));
+        assert_eq!(execute(&["run", "a.js", "--record"]).snapshot_mode, SnapshotMode::Record);
+        assert_eq!(execute(&["test", "spec.js"]).snapshots(), SnapshotConfig::default());
+
+        assert_eq!(
+            parse(&["test", "spec.js", "--record", "--update-snapshots"]),
+            Err("--record and --update-snapshots cannot be combined".to_string())
+        );
+        assert!(parse(&["render", "--record"]).is_err());
+        assert!(execute(&["test", "spec.js", "--detect-leaks"]).detect_leaks);
+        assert!(!execute(&["test", "spec.js"]).detect_leaks);
+        assert!(parse(&["screenshot", "--detect-leaks"]).is_err());
+    }
+
+    #[test]
+    fn test_inspect_option() {
+        assert_eq!(execute(&["render", "--inspect", "json"]).inspect, Some(InspectFormat::Json));
+        assert_eq!(execute(&["run", "a.js", "--inspect=text"]).inspect, Some(InspectFormat::Text));
+        assert_eq!(execute(&["run", "a.js"]).inspect, None);
+        assert_eq!(
+            parse(&["render", "--inspect", "yaml"]),
+            Err("Unknown inspect format 'yaml': expected text or json".to_string())
+        );
+        assert!(parse(&["repl", "--inspect", "text"]).is_err());
+        assert!(parse(&["watch", "spec.js", "--inspect", "text"]).is_err());
+    }
+
+    #[test]
+    fn test_bench_options() {
+        let cli = execute(&["bench", "--iterations", "3", "--baseline=base.json", "--tolerance", "15%"]);
+
+        assert_eq!(cli.command, Subcommand::Bench);
+        assert_eq!(cli.bench.iterations, 3);
+        assert_eq!(cli.bench.baseline, Some(PathBuf::from("base.json")));
+        assert_eq!(cli.bench.tolerance, 15.0);
+        assert_eq!(execute(&["bench"]).bench, BenchOptions::default());
+        assert!(parse(&["bench", "--iterations", "0"]).is_err());
+        assert!(parse(&["bench", "page.html"]).is_err());
+        assert!(parse(&["render", "--baseline", "base.json"]).is_err());
+    }
+
+    #[test]
+    fn test_webdriver_options() {
+        let cli = execute(&["webdriver", "--port", "9515", "--viewport", "800x600"]);
+
+        assert_eq!(cli.command, Subcommand::WebDriver);
+        assert_eq!(cli.port, 9515);
+        assert_eq!(cli.viewport, Viewport { width: 800, height: 600 });
+        assert_eq!(execute(&["webdriver"]).port, DEFAULT_PORT);
+        assert!(parse(&["webdriver", "--port", "70000"]).is_err());
+        assert!(parse(&["webdriver", "page.html"]).is_err());
+        assert_eq!(
+            parse(&["webdriver", "--html", "page.html"]),
+            Err("'webdriver' loads pages by URL; --html and --css are not used".to_string())
+        );
+        assert!(parse(&["webdriver", "--stats"]).is_err());
+        assert!(parse(&["render", "--port", "4444"]).is_err());
+    }
+
+    #[test]
+    fn test_compat_options() {
+        let cli = execute(&["compat", "card.html", "--capture", "card.png", "--dom-dump", "card.dom.txt", "--threshold", "90%"]);
+
+        assert_eq!(cli.command, Subcommand::Compat);
+        assert_eq!(cli.html, Some(InputSource::File(PathBuf::from("card.html"))));
+        assert_eq!(cli.compat.screenshot, Some(PathBuf::from("card.png")));
+        assert_eq!(cli.compat.dom_dump, Some(PathBuf::from("card.dom.txt")));
+        assert_eq!(cli.compat.threshold, 90.0);
+        assert_eq!(
+            parse(&["compat", "card.html", "--capture", "card.png"]),
+            Err("'compat' requires a page, --capture and --dom-dump".to_string())
+        );
+        assert!(parse(&["compat", "card.html", "--capture", "c.png", "--dom-dump", "c.txt", "--threshold", "150"]).is_err());
+        assert!(parse(&["screenshot", "--capture", "card.png"]).is_err());
+    }
+
+    #[test]
+    fn test_trace_option() {
+        assert_eq!(execute(&["test", "spec.js", "--trace", "trace.json"]).trace, Some(PathBuf::from("trace.json")));
+        assert_eq!(execute(&["screenshot", "--trace", "t.json"]).trace, Some(PathBuf::from("t.json")));
+        assert_eq!(execute(&["render"]).trace, None);
+        assert!(parse(&["render", "--trace"]).is_err());
+        assert!(parse(&["watch", "spec.js", "--trace", "t.json"]).is_err());
+    }
+
+    #[test]
+    fn test_stats_flag() {
+        assert!(execute(&["run", "app.js", "--stats"]).stats);
+        assert!(!execute(&["render"]).stats);
+        assert!(parse(&["bench", "--stats"]).is_err());
+    }
+
+    #[test]
+    fn test_determinism_options() {
+        let cli = execute(&["screenshot", "page.html", "--deterministic"]);
+        assert_eq!((cli.seed, cli.frozen_time), (Some(DETERMINISTIC_SEED), Some(DEFAULT_FROZEN_TIME)));
+        let cli = execute(&["test", "spec.js", "--seed=7", "--deterministic", "--freeze-time", "86400000"]);
+        assert_eq!((cli.seed, cli.frozen_time), (Some(7), Some(86_400_000.0)));
+        assert_eq!(execute(&["render"]).frozen_time, None);
+        assert!(parse(&["render", "--freeze-time", "yesterday"]).unwrap_err().starts_with("Invalid time 'yesterday'"));
+    }
+
+    #[test]
+    fn test_limit_options() {
+        let cli = execute(&["test", "spec.js", "--script-timeout", "500", "--memory-limit=64", "--max-nodes", "10000", "--max-screenshot", "4000x3000"]);
+        assert_eq!(
+            cli.limits,
+            Limits::new()
+                .with_script_timeout(Duration::from_millis(500))
+                .with_memory(64 * 1024 * 1024)
+                .with_max_nodes(10_000)
+                .with_max_screenshot(4000, 3000)
+        );
+        assert_eq!(execute(&["render"]).limits, Limits::default());
+        assert!(parse(&["run", "a.js", "--script-timeout", "0"]).is_err());
+        assert!(parse(&["screenshot", "--max-screenshot", "huge"]).unwrap_err().starts_with("Invalid screenshot size 'huge'"));
+        assert!(parse(&["bench", "--max-nodes", "10"]).is_err());
+    }
+
+    #[test]
+    fn test_log_options() {
+        let cli = execute(&["test", "spec.js", "--log", "warn,layout=debug", "--log-format=json"]);
+        assert_eq!(cli.log_config(), LogConfig { filter: "warn,layout=debug".to_string(), format: LogFormat::Json });
+        assert_eq!(execute(&["render"]).log_format, LogFormat::Text);
+        assert!(parse(&["render", "--log", "layout=chatty"]).unwrap_err().starts_with("Invalid log filter 'layout=chatty'"));
+        assert!(parse(&["render", "--log-format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_threads_option() {
+        assert_eq!(execute(&["bench", "--threads", "4"]).threads, Some(4));
+        assert_eq!(execute(&["render"]).threads, None);
+        assert!(parse(&["render", "--threads", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_clip_option() {
+        let screenshot = execute(&["screenshot", "page.html", "--clip", "10,20,300,150"]);
+        let run = execute(&["run", "a.js", "--screenshot", "out.png", "--clip=0,0,64,64"]);
+
+        assert_eq!(screenshot.clip, Some(Rect::new(10.0, 20.0, 300.0, 150.0)));
+        assert_eq!(run.clip, Some(Rect::new(0.0, 0.0, 64.0, 64.0)));
+        assert_eq!(parse(&["run", "a.js", "--clip", "0,0,1,1"]), Err("--clip requires --screenshot".to_string()));
+        assert!(parse(&["render", "--clip", "0,0,1,1"]).is_err());
+    }
+
+    #[test]
+    fn test_device_pixel_ratio_option() {
+        assert_eq!(execute(&["screenshot", "--device-pixel-ratio", "2"]).device_pixel_ratio, Some(2.0));
+        assert_eq!(execute(&["run", "spec.js", "--device-pixel-ratio=1.5"]).device_pixel_ratio, Some(1.5));
+        assert_eq!(execute(&["screenshot"]).device_pixel_ratio, None);
+        assert!(parse(&["screenshot", "--device-pixel-ratio", "0"]).is_err());
+        assert!(parse(&["screenshot", "--device-pixel-ratio", "retina"]).is_err());
+    }
+
+    #[test]
+    fn test_full_page_option() {
+        assert!(execute(&["screenshot", "--full-page"]).full_page);
+        assert!(execute(&["run", "spec.js", "--screenshot", "shot.png", "--full-page"]).full_page);
+        assert!(!execute(&["screenshot"]).full_page);
+        assert!(parse(&["run", "spec.js", "--full-page"]).is_err());
+        assert!(parse(&["screenshot", "--full-page", "--clip", "0,0,10,10"]).is_err());
+        assert!(parse(&["test", "spec.js", "--full-page"]).is_err());
+    }
+
+    #[test]
+    fn test_render_pdf_command() {
+        let cli = execute(&["render", "pdf", "page.html", "-o", "out/doc.pdf", "--page-height", "1000"]);
+        assert_eq!(cli.command, Subcommand::RenderPdf);
+        assert_eq!(cli.html, Some(InputSource::File(PathBuf::from("page.html"))));
+        assert_eq!(cli.screenshot, Some(PathBuf::from("out/doc.pdf")));
+        assert_eq!(cli.page_height, Some(1000.0));
+
+        assert_eq!(execute(&["render", "pdf"]).screenshot, Some(PathBuf::from("page.pdf")));
+        assert_eq!(execute(&["render", "pdf.html"]).command, Subcommand::Render);
+        assert!(parse(&["render", "pdf", "--page-height", "0"]).is_err());
+        assert!(parse(&["render", "--page-height", "100"]).is_err());
+    }
+
+    #[test]
+    fn test_quality_option() {
+        assert_eq!(execute(&["screenshot", "-o", "shot.jpg", "--quality", "60"]).quality, 60);
+        assert_eq!(execute(&["screenshot"]).quality, DEFAULT_QUALITY);
+        assert!(parse(&["screenshot", "--quality", "0"]).is_err());
+        assert!(parse(&["screenshot", "--quality", "101"]).is_err());
+    }
+
+    #[test]
+    fn test_debug_boxes_option() {
+        assert!(execute(&["screenshot", "--debug-boxes"]).debug_boxes);
+        assert!(execute(&["test", "spec.js", "--debug-boxes"]).debug_boxes);
+        assert!(!execute(&["screenshot"]).debug_boxes);
+        assert!(parse(&["render", "--debug-boxes"]).is_err());
+        assert!(parse(&["render", "pdf", "--debug-boxes"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_clip() {
+        assert_eq!(parse_clip(" 1.5, 2 ,3,4"), Ok(Rect::new(1.5, 2.0, 3.0, 4.0)));
+        assert!(parse_clip("1,2,3").is_err());
+        assert!(parse_clip("0,0,0,10").is_err());
+        assert!(parse_clip("0,0,a,10").is_err());
+    }
+
+    #[test]
+    fn test_parse_viewport() {
+        assert_eq!(parse_viewport("1280x720"), Ok(Viewport { width: 1280, height: 720 }));
+        assert_eq!(parse_viewport("320X480"), Ok(Viewport { width: 320, height: 480 }));
+        assert!(parse_viewport("0x10").is_err());
+        assert!(parse_viewport("1280").is_err());
+    }
+}
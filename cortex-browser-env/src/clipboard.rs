@@ -0,0 +1,363 @@
+//! Clipboard
+//! In-memory clipboard behind `navigator.clipboard` and the `copy`, `cut`
+//! and `paste` events
+//!
+//! `copy`, `cut` and `paste` act on a node as the keyboard shortcuts would:
+//! a `ClipboardEvent` fires first, and unless a listener cancels it the
+//! default action follows. There is no selection, so the default action
+//! treats the node's whole text, or a text field's whole value, as
+//! selected. A canceled copy or cut puts whatever listeners set on
+//! `clipboardData` on the clipboard instead.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::dom::Document;
+use crate::events;
+use crate::forms;
+
+/// Media type of plain text on the clipboard
+pub const TEXT_PLAIN: &str = "text/plain";
+
+/// Clipboard contents as (media type, data) items
+///
+/// Clones share contents, so a test keeps one clone while the page uses
+/// another; a `Browser` shares its clipboard with all its pages, as an
+/// operating system would.
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    items: Rc<RefCell<Vec<(String, String)>>>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_text(self, text: &str) -> Self {
+        self.set_text(text);
+        self
+    }
+
+    /// The `text/plain` item
+    pub fn text(&self) -> Option<String> {
+        self.get(TEXT_PLAIN)
+    }
+
+    pub fn get(&self, media_type: &str) -> Option<String> {
+        self.items.borrow().iter().find(|(t, _)| t == media_type).map(|(_, data)| data.clone())
+    }
+
+    /// Every item, in the order written
+    pub fn items(&self) -> Vec<(String, String)> {
+        self.items.borrow().clone()
+    }
+
+    /// Replace the contents with `text` alone
+    pub fn set_text(&self, text: &str) {
+        self.write(vec![(TEXT_PLAIN.to_string(), text.to_string())]);
+    }
+
+    /// Replace the contents with `items`
+    pub fn write(&self, items: Vec<(String, String)>) {
+        *self.items.borrow_mut() = items;
+    }
+
+    pub fn clear(&self) {
+        self.items.borrow_mut().clear();
+    }
+}
+
+/// A clipboard shortcut
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardAction {
+    Copy,
+    Cut,
+    Paste,
+}
+
+impl ClipboardAction {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ClipboardAction::Copy => "copy",
+            ClipboardAction::Cut => "cut",
+            ClipboardAction::Paste => "paste",
+        }
+    }
+}
+
+/// Text the default action copies from `node`: a text field's value, or
+/// the node's text
+fn selected_text(document: &Document, node: usize) -> String {
+    if forms::is_text_field(document, node) {
+        forms::value(document, node)
+    } else {
+        document.text_content(node)
+    }
+}
+
+/// Fire `action`'s event at `node` and, unless a listener canceled it, run
+/// its default action; returns whether the default action ran
+///
+/// A cut or paste that changes a text field fires `input` on it, with
+/// `inputType` `deleteByCut` or `insertFromPaste`.
+pub fn perform<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    clipboard: &Clipboard,
+    node: usize,
+    action: ClipboardAction,
+) -> rquickjs::Result<bool> {
+    let native: Object = ctx.globals().get("__cortexClipboard")?;
+    let items = if action == ClipboardAction::Paste { clipboard.items() } else { Vec::new() };
+    let data_transfer: Object = native.get::<_, Function>("dataTransfer")?.call((to_pairs(&items), action != ClipboardAction::Paste))?;
+
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", true)?;
+    init.set("cancelable", true)?;
+    init.set("composed", true)?;
+    init.set("clipboardData", data_transfer.clone())?;
+    let event = events::create_event(ctx, "ClipboardEvent", action.event_type(), init)?;
+    event.set("isTrusted", true)?;
+    if !events::dispatch_event(ctx, document, node, event)? {
+        if action != ClipboardAction::Paste {
+            let pairs: Vec<Vec<String>> = native.get::<_, Function>("items")?.call((data_transfer,))?;
+            let items: Vec<(String, String)> = pairs.into_iter().filter_map(|pair| Some((pair.first()?.clone(), pair.get(1)?.clone()))).collect();
+            if !items.is_empty() {
+                clipboard.write(items);
+            }
+        }
+        return Ok(false);
+    }
+
+    let editable = forms::is_editable_text_field(&document.borrow(), node);
+    match action {
+        ClipboardAction::Copy | ClipboardAction::Cut => {
+            let text = selected_text(&document.borrow(), node);
+            clipboard.set_text(&text);
+            if action == ClipboardAction::Cut && editable && !text.is_empty() {
+                forms::set_value(&mut document.borrow_mut(), node, "");
+                fire_input(ctx, document, node, "deleteByCut", None)?;
+            }
+        }
+        ClipboardAction::Paste => {
+            if let (true, Some(text)) = (editable, clipboard.text()) {
+                forms::set_value(&mut document.borrow_mut(), node, &text);
+                fire_input(ctx, document, node, "insertFromPaste", Some(text))?;
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn to_pairs(items: &[(String, String)]) -> Vec<Vec<String>> {
+    items.iter().map(|(media_type, data)| vec![media_type.clone(), data.clone()]).collect()
+}
+
+fn fire_input<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, node: usize, input_type: &str, data: Option<String>) -> rquickjs::Result<()> {
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", true)?;
+    let event = events::create_event(ctx, "Event", "input", init)?;
+    event.set("isTrusted", true)?;
+    event.set("inputType", input_type)?;
+    match data {
+        Some(data) => event.set("data", data)?,
+        None => event.set("data", rquickjs::Null)?,
+    }
+    events::dispatch_event(ctx, document, node, event).map(|_| ())
+}
+
+/// `DataTransfer`, `ClipboardEvent` and `navigator.clipboard`, over the
+/// natives of `install_clipboard`
+const CLIPBOARD_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexClipboard;
+    const state = new WeakMap();
+
+    function normalize(format) {
+        format = String(format).toLowerCase();
+        if (format === "text") return "text/plain";
+        if (format === "url") return "text/uri-list";
+        return format;
+    }
+
+    class DataTransfer {
+        constructor() {
+            state.set(this, { items: [], writable: true });
+            this.dropEffect = "none";
+            this.effectAllowed = "none";
+        }
+        get types() { return state.get(this).items.map(([type]) => type); }
+        get files() { return []; }
+        getData(format) {
+            const item = state.get(this).items.find(([type]) => type === normalize(format));
+            return item ? item[1] : "";
+        }
+        setData(format, data) {
+            const { items, writable } = state.get(this);
+            if (!writable) return;
+            format = normalize(format);
+            const index = items.findIndex(([type]) => type === format);
+            if (index >= 0) items.splice(index, 1);
+            items.push([format, String(data)]);
+        }
+        clearData(format) {
+            const current = state.get(this);
+            if (!current.writable) return;
+            current.items = format === undefined ? [] : current.items.filter(([type]) => type !== normalize(format));
+        }
+    }
+
+    class ClipboardEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.clipboardData = init.clipboardData || null;
+        }
+    }
+
+    native.dataTransfer = (items, writable) => {
+        const transfer = new DataTransfer();
+        state.get(transfer).items = items.map(([type, data]) => [type, data]);
+        state.get(transfer).writable = writable;
+        return transfer;
+    };
+    native.items = transfer => state.get(transfer).items.map(([type, data]) => [type, data]);
+
+    globalThis.DataTransfer = DataTransfer;
+    globalThis.ClipboardEvent = ClipboardEvent;
+    globalThis.navigator.clipboard = {
+        writeText(text) {
+            return new Promise(resolve => { native.writeText(String(text)); resolve(); });
+        },
+        readText() {
+            return new Promise(resolve => resolve(native.readText()));
+        },
+    };
+})();
+"#;
+
+/// Install `navigator.clipboard`, `DataTransfer` and `ClipboardEvent` over
+/// `clipboard`
+///
+/// Needs `navigator` and the `events` bindings installed first.
+pub fn install_clipboard<'js>(ctx: &Ctx<'js>, clipboard: &Clipboard) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let board = clipboard.clone();
+    native.set("writeText", Function::new(ctx.clone(), move |text: String| board.set_text(&text))?)?;
+    let board = clipboard.clone();
+    native.set("readText", Function::new(ctx.clone(), move || board.text().unwrap_or_default())?)?;
+
+    ctx.globals().set("__cortexClipboard", native)?;
+    ctx.eval::<(), _>(CLIPBOARD_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use rquickjs::{Context, Runtime};
+
+    /// A context over `html` with clipboard and event bindings
+    fn setup(html: &str, clipboard: &Clipboard) -> (Runtime, Context, Rc<RefCell<Document>>) {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        let document = Rc::new(RefCell::new(parse_html(html)));
+        context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            ctx.globals().set("navigator", Object::new(ctx.clone()).unwrap()).unwrap();
+            events::install_events(&ctx, document.clone()).unwrap();
+            install_clipboard(&ctx, clipboard).unwrap();
+        });
+        (runtime, context, document)
+    }
+
+    fn find(document: &RefCell<Document>, tag: &str) -> usize {
+        let doc = document.borrow();
+        (0..doc.nodes.len()).find(|&i| forms::tag_name(&doc, i) == Some(tag)).unwrap()
+    }
+
+    #[test]
+    fn test_navigator_clipboard() {
+        let clipboard = Clipboard::new().with_text("from the system");
+        let (runtime, context, _) = setup("<html><body></body></html>", &clipboard);
+
+        context.with(|ctx| {
+            ctx.eval::<(), _>("navigator.clipboard.readText().then(text => navigator.clipboard.writeText(text.toUpperCase()))")
+                .unwrap()
+        });
+        while runtime.execute_pending_job().unwrap() {}
+
+        assert_eq!(clipboard.text().as_deref(), Some("FROM THE SYSTEM"));
+    }
+
+    #[test]
+    fn test_copy_cut_and_paste_defaults() {
+        let clipboard = Clipboard::new();
+        let (_runtime, context, document) =
+            setup(r#"<html><body><p>Invite code: X7</p><input value="draft"><textarea readonly="">fixed</textarea></body></html>"#, &clipboard);
+        let (p, input, textarea) = (find(&document, "p"), find(&document, "input"), find(&document, "textarea"));
+
+        context.with(|ctx| {
+            ctx.globals().set("input", input).unwrap();
+            ctx.eval::<(), _>("globalThis.inputs = []; addEventListener(input, 'input', e => inputs.push(e.inputType + ' ' + e.data));")
+                .unwrap();
+
+            assert!(perform(&ctx, &document, &clipboard, p, ClipboardAction::Copy).unwrap());
+            assert_eq!(clipboard.text().as_deref(), Some("Invite code: X7"));
+
+            assert!(perform(&ctx, &document, &clipboard, input, ClipboardAction::Cut).unwrap());
+            assert_eq!(clipboard.text().as_deref(), Some("draft"));
+            assert_eq!(forms::value(&document.borrow(), input), "");
+
+            clipboard.set_text("pasted");
+            assert!(perform(&ctx, &document, &clipboard, input, ClipboardAction::Paste).unwrap());
+            perform(&ctx, &document, &clipboard, textarea, ClipboardAction::Paste).unwrap();
+            assert_eq!(forms::value(&document.borrow(), input), "pasted");
+            assert_eq!(forms::value(&document.borrow(), textarea), "fixed", "Read-only fields ignore pastes");
+
+            let inputs: Vec<String> = ctx.eval("inputs").unwrap();
+            assert_eq!(inputs, ["deleteByCut null", "insertFromPaste pasted"]);
+        });
+    }
+
+    #[test]
+    fn test_listeners_replace_clipboard_data() {
+        let clipboard = Clipboard::new().with_text("<b>rich</b>");
+        let (_runtime, context, document) = setup("<html><body><button>Copy link</button><div>Drop here</div></body></html>", &clipboard);
+        let (button, div) = (find(&document, "button"), find(&document, "div"));
+
+        context.with(|ctx| {
+            ctx.globals().set("ids", vec![button, div]).unwrap();
+            ctx.eval::<(), _>(
+                r#"
+                const [button, div] = ids;
+                addEventListener(button, "copy", e => {
+                    e.clipboardData.setData("text", "https://app.test/share/42");
+                    e.clipboardData.setData("text/html", "<a href='/share/42'>link</a>");
+                    e.preventDefault();
+                });
+                addEventListener(div, "paste", e => {
+                    e.clipboardData.setData("text/plain", "ignored: paste data is read-only");
+                    globalThis.pasted = [e instanceof ClipboardEvent, e.isTrusted, e.clipboardData.types.join(), e.clipboardData.getData("Text")];
+                    e.preventDefault();
+                });
+                "#,
+            )
+            .unwrap();
+
+            assert!(!perform(&ctx, &document, &clipboard, div, ClipboardAction::Paste).unwrap());
+            let pasted: Vec<String> = ctx.eval("pasted.map(String)").unwrap();
+            assert_eq!(pasted, ["true", "true", "text/plain", "<b>rich</b>"]);
+
+            assert!(!perform(&ctx, &document, &clipboard, button, ClipboardAction::Copy).unwrap());
+        });
+        assert_eq!(clipboard.text().as_deref(), Some("https://app.test/share/42"));
+        assert_eq!(clipboard.get("text/html").as_deref(), Some("<a href='/share/42'>link</a>"));
+    }
+}
@@ -0,0 +1,480 @@
+//! Browser Compatibility Harness
+//! Compares this engine's output for a fixture against a real browser capture
+//!
+//! A capture is a PNG screenshot plus a DOM dump produced by
+//! `scripts/capture-dom.js` in a real browser at the same viewport size.
+//! The resulting scorecard tells teams which tests are safe to migrate.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dom::{Document, NodeData};
+use crate::error::BrowserError;
+use crate::geometry::Rect;
+use crate::layout;
+use crate::parser;
+use crate::render::render_document;
+use crate::screenshot::{argb_to_rgba, decode_png};
+
+/// Header line written by the capture script
+pub const DOM_DUMP_HEADER: &str = "# cortex-dom-dump v1";
+
+/// Maximum number of mismatch details kept on a scorecard
+const MAX_MISMATCH_DETAILS: usize = 20;
+
+/// Default `--threshold` every score must reach for a fixture to pass, in
+/// percent
+pub const DEFAULT_THRESHOLD: f32 = 95.0;
+
+/// What the `compat` subcommand compares a page against
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompatOptions {
+    /// Browser screenshot PNG
+    pub screenshot: Option<PathBuf>,
+    /// DOM dump from `scripts/capture-dom.js`
+    pub dom_dump: Option<PathBuf>,
+    /// Percent every score must reach
+    pub threshold: f32,
+}
+
+impl Default for CompatOptions {
+    fn default() -> Self {
+        CompatOptions { screenshot: None, dom_dump: None, threshold: DEFAULT_THRESHOLD }
+    }
+}
+
+/// One element box from a DOM dump, in document (pre-)order
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomBox {
+    pub depth: usize,
+    pub tag_name: String,
+    /// Border box in CSS pixels
+    pub rect: Rect,
+}
+
+/// A screenshot and DOM dump captured from a real browser
+#[derive(Debug, Clone)]
+pub struct BrowserCapture {
+    pub width: u32,
+    pub height: u32,
+    /// Screenshot pixels in RGBA order
+    pub pixels: Vec<u8>,
+    pub dom: Vec<DomBox>,
+}
+
+impl BrowserCapture {
+    /// Import a capture from a screenshot PNG and a DOM dump file
+    pub fn import(screenshot_path: &Path, dom_dump_path: &Path) -> Result<Self, BrowserError> {
+        let png_bytes = fs::read(screenshot_path).map_err(|e| {
+            BrowserError::NotFoundError(format!("{}: {}", screenshot_path.display(), e))
+        })?;
+        let (width, height, pixels) = decode_png(&png_bytes)
+            .map_err(|e| BrowserError::ScreenshotError(e.to_string()))?;
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(BrowserError::ScreenshotError(format!(
+                "{}: {} bytes of pixels for a {}x{} image",
+                screenshot_path.display(),
+                pixels.len(),
+                width,
+                height
+            )));
+        }
+
+        let dump = fs::read_to_string(dom_dump_path).map_err(|e| {
+            BrowserError::NotFoundError(format!("{}: {}", dom_dump_path.display(), e))
+        })?;
+        let dom = parse_dom_dump(&dump)?;
+
+        Ok(BrowserCapture { width, height, pixels, dom })
+    }
+}
+
+/// Tolerances used when comparing engine output with a capture
+#[derive(Debug, Clone)]
+pub struct CompatConfig {
+    /// Maximum per-channel difference for two pixels to count as equal
+    pub pixel_tolerance: u8,
+    /// Maximum edge offset in pixels for two boxes to count as equal
+    pub layout_tolerance: f32,
+}
+
+impl CompatConfig {
+    /// Create a configuration with the default tolerances
+    pub fn new() -> Self {
+        CompatConfig {
+            pixel_tolerance: 8,
+            layout_tolerance: 2.0,
+        }
+    }
+
+    /// Set the per-channel pixel tolerance
+    pub fn with_pixel_tolerance(mut self, tolerance: u8) -> Self {
+        self.pixel_tolerance = tolerance;
+        self
+    }
+
+    /// Set the box edge tolerance in pixels
+    pub fn with_layout_tolerance(mut self, tolerance: f32) -> Self {
+        self.layout_tolerance = tolerance;
+        self
+    }
+}
+
+impl Default for CompatConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compatibility scores for a single fixture, each in the range 0.0..=1.0
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompatScorecard {
+    pub fixture: String,
+    /// Share of captured elements whose tag and depth match the engine
+    pub structure_score: f32,
+    /// Share of captured elements whose box matches within tolerance
+    pub layout_score: f32,
+    /// Share of screenshot pixels that match within tolerance
+    pub pixel_score: f32,
+    pub mismatches: Vec<String>,
+}
+
+impl CompatScorecard {
+    /// Overall score, the mean of the structure, layout and pixel scores
+    pub fn overall_score(&self) -> f32 {
+        (self.structure_score + self.layout_score + self.pixel_score) / 3.0
+    }
+
+    /// Check whether every score reaches the given threshold
+    pub fn is_safe_to_migrate(&self, threshold: f32) -> bool {
+        self.structure_score >= threshold
+            && self.layout_score >= threshold
+            && self.pixel_score >= threshold
+    }
+
+    /// Format the scorecard as a human-readable string
+    pub fn format_scorecard(&self) -> String {
+        let mut output = format!(
+            "Compatibility: {} ({:.1}% overall)\n  structure: {:.1}%\n  layout:    {:.1}%\n  pixels:    {:.1}%\n",
+            self.fixture,
+            self.overall_score() * 100.0,
+            self.structure_score * 100.0,
+            self.layout_score * 100.0,
+            self.pixel_score * 100.0,
+        );
+
+        if !self.mismatches.is_empty() {
+            output.push_str("\nMismatches:\n");
+            for mismatch in &self.mismatches {
+                output.push_str(&format!("  - {}\n", mismatch));
+            }
+        }
+
+        output
+    }
+}
+
+/// Parse a DOM dump produced by the capture script
+///
+/// Each non-comment line is `depth tag x y width height`, separated by
+/// whitespace, with elements listed in document order.
+pub fn parse_dom_dump(text: &str) -> Result<Vec<DomBox>, BrowserError> {
+    let mut boxes = Vec::new();
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let invalid = || {
+            BrowserError::ParseError(format!("DOM dump line {}: invalid entry '{}'", line_no + 1, line))
+        };
+        if fields.len() != 6 {
+            return Err(invalid());
+        }
+
+        let number = |s: &str| s.parse::<f32>().map_err(|_| invalid());
+        boxes.push(DomBox {
+            depth: fields[0].parse().map_err(|_| invalid())?,
+            tag_name: fields[1].to_lowercase(),
+            rect: Rect::new(number(fields[2])?, number(fields[3])?, number(fields[4])?, number(fields[5])?),
+        });
+    }
+
+    Ok(boxes)
+}
+
+/// Format element boxes in the DOM dump format
+pub fn format_dom_dump(boxes: &[DomBox]) -> String {
+    let mut output = format!("{}\n", DOM_DUMP_HEADER);
+    for b in boxes {
+        output.push_str(&format!(
+            "{} {} {} {} {} {}\n",
+            b.depth, b.tag_name, b.rect.x, b.rect.y, b.rect.width, b.rect.height
+        ));
+    }
+    output
+}
+
+/// Collect this engine's element boxes in the DOM dump shape
+pub fn engine_dom_dump(document: &Document) -> Vec<DomBox> {
+    fn walk(document: &Document, node_idx: usize, depth: usize, boxes: &mut Vec<DomBox>) {
+        let node = &document.nodes[node_idx];
+        if let Some(NodeData::Element(element)) = &node.data {
+            let layout = document.layout(node_idx).cloned().unwrap_or_default();
+            boxes.push(DomBox {
+                depth,
+                tag_name: element.tag_name.to_lowercase(),
+                rect: layout.border_box(),
+            });
+            for &child_idx in &node.children {
+                walk(document, child_idx, depth + 1, boxes);
+            }
+        }
+    }
+
+    let mut boxes = Vec::new();
+    for &child_idx in &document.nodes[document.root].children {
+        walk(document, child_idx, 0, &mut boxes);
+    }
+    boxes
+}
+
+/// Render a fixture with this engine and score it against a capture
+///
+/// The engine renders at the capture's size, so a capture whose pixels
+/// don't fill `width` x `height` is rejected rather than scored.
+pub fn compare_fixture(
+    name: &str,
+    html: &str,
+    capture: &BrowserCapture,
+    config: &CompatConfig,
+) -> Result<CompatScorecard, BrowserError> {
+    let mut document = parser::parse_html(html);
+    layout::calculate_layout(&mut document, capture.width as f32, capture.height as f32);
+    let draw_target = render_document(&document, capture.width as i32, capture.height as i32);
+    let engine_pixels = argb_to_rgba(draw_target.get_data());
+    let engine_dom = engine_dom_dump(&document);
+
+    let mut mismatches = Vec::new();
+    let (structure_score, layout_score) =
+        compare_dom(&capture.dom, &engine_dom, config.layout_tolerance, &mut mismatches);
+    let pixel_score = compare_pixels(&capture.pixels, &engine_pixels, config.pixel_tolerance)?;
+
+    if capture.dom.len() != engine_dom.len() {
+        mismatches.push(format!(
+            "element count: browser has {}, engine has {}",
+            capture.dom.len(),
+            engine_dom.len()
+        ));
+    }
+
+    Ok(CompatScorecard {
+        fixture: name.to_string(),
+        structure_score,
+        layout_score,
+        pixel_score,
+        mismatches,
+    })
+}
+
+/// Compare DOM dumps element by element, returning (structure, layout) scores
+fn compare_dom(
+    expected: &[DomBox],
+    actual: &[DomBox],
+    tolerance: f32,
+    mismatches: &mut Vec<String>,
+) -> (f32, f32) {
+    if expected.is_empty() {
+        return (1.0, 1.0);
+    }
+
+    let mut structure_matches = 0;
+    let mut layout_matches = 0;
+    let mut note = |message: String| {
+        if mismatches.len() < MAX_MISMATCH_DETAILS {
+            mismatches.push(message);
+        }
+    };
+
+    for (i, want) in expected.iter().enumerate() {
+        let Some(got) = actual.get(i) else {
+            note(format!("element {}: <{}> missing from engine output", i, want.tag_name));
+            continue;
+        };
+
+        if want.tag_name != got.tag_name || want.depth != got.depth {
+            note(format!(
+                "element {}: expected <{}> at depth {}, engine has <{}> at depth {}",
+                i, want.tag_name, want.depth, got.tag_name, got.depth
+            ));
+            continue;
+        }
+        structure_matches += 1;
+
+        if want.rect.approx_eq(&got.rect, tolerance) {
+            layout_matches += 1;
+        } else {
+            note(format!(
+                "element {} <{}>: expected box {}x{} at ({}, {}), engine has {}x{} at ({}, {})",
+                i, want.tag_name, want.rect.width, want.rect.height, want.rect.x, want.rect.y,
+                got.rect.width, got.rect.height, got.rect.x, got.rect.y
+            ));
+        }
+    }
+
+    let total = expected.len() as f32;
+    (structure_matches as f32 / total, layout_matches as f32 / total)
+}
+
+/// Share of RGBA pixels equal within a per-channel tolerance; images of
+/// different sizes can't be compared pixel by pixel
+fn compare_pixels(expected: &[u8], actual: &[u8], tolerance: u8) -> Result<f32, BrowserError> {
+    if expected.len() != actual.len() {
+        return Err(BrowserError::ScreenshotError(format!(
+            "Cannot compare {} pixels with {}",
+            expected.len() / 4,
+            actual.len() / 4
+        )));
+    }
+    let total = expected.len() / 4;
+    if total == 0 {
+        return Ok(1.0);
+    }
+
+    let matching = expected
+        .chunks_exact(4)
+        .zip(actual.chunks_exact(4))
+        .filter(|(a, b)| a.iter().zip(b.iter()).all(|(x, y)| x.abs_diff(*y) <= tolerance))
+        .count();
+
+    Ok(matching as f32 / total as f32)
+}
+
+// ============================================================================
+// TESTS (RED PHASE - TDD)
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screenshot::save_screenshot;
+    use tempfile::tempdir;
+
+    const FIXTURE: &str = "<html><body><div class=\"card\">Card</div></body></html>";
+
+    /// Build a capture from this engine's own output, i.e. a perfect browser
+    fn self_capture(html: &str, width: u32, height: u32) -> BrowserCapture {
+        let mut document = parser::parse_html(html);
+        layout::calculate_layout(&mut document, width as f32, height as f32);
+        let dt = render_document(&document, width as i32, height as i32);
+        BrowserCapture {
+            width,
+            height,
+            pixels: argb_to_rgba(dt.get_data()),
+            dom: engine_dom_dump(&document),
+        }
+    }
+
+    #[test]
+    fn test_parse_dom_dump() {
+        let dump = "# cortex-dom-dump v1\n0 HTML 0 0 800 600\n\n1 body 8 8 784 584.5\n";
+        let boxes = parse_dom_dump(dump).unwrap();
+
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(boxes[0].tag_name, "html");
+        assert_eq!(boxes[1].depth, 1);
+        assert_eq!(boxes[1].rect.height, 584.5);
+    }
+
+    #[test]
+    fn test_parse_dom_dump_reports_line_number() {
+        let result = parse_dom_dump("# cortex-dom-dump v1\n0 html 0 0 800\n");
+        match result {
+            Err(BrowserError::ParseError(msg)) => assert!(msg.contains("line 2")),
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dom_dump_round_trip() {
+        let mut document = parser::parse_html(FIXTURE);
+        layout::calculate_layout(&mut document, 320.0, 200.0);
+        let boxes = engine_dom_dump(&document);
+
+        assert_eq!(parse_dom_dump(&format_dom_dump(&boxes)).unwrap(), boxes);
+        assert_eq!(boxes[0].tag_name, "html");
+        assert_eq!(boxes[2].depth, 2);
+    }
+
+    #[test]
+    fn test_identical_capture_scores_perfectly() {
+        let capture = self_capture(FIXTURE, 320, 200);
+        let scorecard = compare_fixture("card", FIXTURE, &capture, &CompatConfig::new()).unwrap();
+
+        assert_eq!(scorecard.structure_score, 1.0);
+        assert_eq!(scorecard.layout_score, 1.0);
+        assert_eq!(scorecard.pixel_score, 1.0);
+        assert!(scorecard.is_safe_to_migrate(0.99));
+        assert!(scorecard.mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_divergent_capture_lowers_scores() {
+        let mut capture = self_capture(FIXTURE, 320, 200);
+        capture.dom[2].tag_name = "section".to_string();
+        capture.dom[1].rect.width += 50.0;
+        for px in capture.pixels.iter_mut().take(320 * 4 * 100) {
+            *px = 0;
+        }
+
+        let scorecard = compare_fixture("card", FIXTURE, &capture, &CompatConfig::new()).unwrap();
+
+        assert!((scorecard.structure_score - 2.0 / 3.0).abs() < 1e-6);
+        assert!((scorecard.layout_score - 1.0 / 3.0).abs() < 1e-6);
+        assert!((scorecard.pixel_score - 0.5).abs() < 1e-6);
+        assert!(!scorecard.is_safe_to_migrate(0.9));
+        assert_eq!(scorecard.mismatches.len(), 2);
+        assert!(scorecard.format_scorecard().contains("Mismatches:"));
+    }
+
+    #[test]
+    fn test_mismatched_capture_size_is_rejected() {
+        // Given: A capture whose pixels are a row short of its size
+        let mut capture = self_capture(FIXTURE, 320, 200);
+        capture.pixels.truncate(320 * 199 * 4);
+
+        // When: It is compared
+        let result = compare_fixture("card", FIXTURE, &capture, &CompatConfig::new());
+
+        // Then: It is refused instead of scored on the overlap
+        assert!(matches!(result, Err(BrowserError::ScreenshotError(msg)) if msg.contains("63680 pixels with 64000")));
+    }
+
+    #[test]
+    fn test_import_capture_from_files() {
+        let temp_dir = tempdir().unwrap();
+        let png_path = temp_dir.path().join("card.png");
+        let dom_path = temp_dir.path().join("card.dom.txt");
+
+        let mut document = parser::parse_html(FIXTURE);
+        layout::calculate_layout(&mut document, 64.0, 48.0);
+        save_screenshot(&render_document(&document, 64, 48), &png_path).unwrap();
+        fs::write(&dom_path, format_dom_dump(&engine_dom_dump(&document))).unwrap();
+
+        let capture = BrowserCapture::import(&png_path, &dom_path).unwrap();
+
+        assert_eq!((capture.width, capture.height), (64, 48));
+        assert_eq!(capture.dom.len(), 3);
+        assert!(compare_fixture("card", FIXTURE, &capture, &CompatConfig::new()).unwrap().is_safe_to_migrate(1.0));
+    }
+
+    #[test]
+    fn test_import_missing_files() {
+        let temp_dir = tempdir().unwrap();
+        let result = BrowserCapture::import(&temp_dir.path().join("nope.png"), &temp_dir.path().join("nope.txt"));
+        assert!(matches!(result, Err(BrowserError::NotFoundError(_))));
+    }
+}
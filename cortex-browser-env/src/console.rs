@@ -0,0 +1,390 @@
+//! Console
+//! `console.log`/`info`/`warn`/`error`/`debug`/`table` with printf-style
+//! formatting and object inspection, recorded per page so Rust code and
+//! `expect(console)` assertions can check what a script logged
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use rquickjs::{Array, Ctx, Function, Object};
+
+/// Severity of a console entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleLevel {
+    Log,
+    Info,
+    Warn,
+    Error,
+    Debug,
+}
+
+impl ConsoleLevel {
+    pub fn parse(name: &str) -> Option<ConsoleLevel> {
+        match name {
+            "log" => Some(ConsoleLevel::Log),
+            "info" => Some(ConsoleLevel::Info),
+            "warn" => Some(ConsoleLevel::Warn),
+            "error" => Some(ConsoleLevel::Error),
+            "debug" => Some(ConsoleLevel::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsoleLevel::Log => "log",
+            ConsoleLevel::Info => "info",
+            ConsoleLevel::Warn => "warn",
+            ConsoleLevel::Error => "error",
+            ConsoleLevel::Debug => "debug",
+        }
+    }
+}
+
+impl fmt::Display for ConsoleLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// One formatted console call
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleEntry {
+    pub level: ConsoleLevel,
+    pub message: String,
+}
+
+/// Entries a page's scripts logged, oldest first
+pub type ConsoleBuffer = Rc<RefCell<Vec<ConsoleEntry>>>;
+
+/// Formatting, inspection and matchers; calls `__cortexConsole.record` and
+/// `__cortexConsole.entries`, which `install_console` defines natively
+///
+/// Matchers only see entries logged since the current test started (or
+/// since `console.clear()`), so one test's errors cannot satisfy another's
+/// assertions.
+const CONSOLE_PRELUDE: &str = r#"
+(function () {
+    const native = globalThis.__cortexConsole;
+    let since = 0;
+    const MAX_DEPTH = 2;
+
+    function quote(text) {
+        return "'" + text.replace(/\\/g, "\\\\").replace(/'/g, "\\'").replace(/\n/g, "\\n") + "'";
+    }
+
+    function inspect(value, depth, seen) {
+        switch (typeof value) {
+            case "string": return depth === 0 ? value : quote(value);
+            case "function": return "[Function: " + (value.name || "(anonymous)") + "]";
+            case "symbol": return value.toString();
+            case "bigint": return value + "n";
+            case "object": break;
+            default: return Object.is(value, -0) ? "-0" : String(value);
+        }
+        if (value === null) return "null";
+        if (seen.includes(value)) return "[Circular]";
+        if (value instanceof Error) return value.stack ? value.name + ": " + value.message + "\n" + value.stack.trimEnd() : String(value);
+        if (value instanceof Date) return isNaN(value) ? "Invalid Date" : value.toISOString();
+        if (value instanceof RegExp) return String(value);
+
+        const nested = seen.concat([value]);
+        const item = v => inspect(v, depth + 1, nested);
+        const tooDeep = depth > MAX_DEPTH;
+        if (Array.isArray(value)) {
+            if (tooDeep) return "[Array]";
+            return value.length ? "[ " + value.map(item).join(", ") + " ]" : "[]";
+        }
+        if (value instanceof Map) {
+            if (tooDeep) return "[Map]";
+            const entries = Array.from(value, ([k, v]) => item(k) + " => " + item(v));
+            return "Map(" + value.size + ") {" + (entries.length ? " " + entries.join(", ") + " " : "") + "}";
+        }
+        if (value instanceof Set) {
+            if (tooDeep) return "[Set]";
+            const items = Array.from(value, item);
+            return "Set(" + value.size + ") {" + (items.length ? " " + items.join(", ") + " " : "") + "}";
+        }
+
+        const proto = Object.getPrototypeOf(value);
+        const name = proto && proto.constructor && proto.constructor !== Object ? proto.constructor.name : "";
+        const prefix = name ? name + " " : proto === null ? "[Object: null prototype] " : "";
+        if (tooDeep) return "[" + (name || "Object") + "]";
+        const keys = Object.keys(value);
+        if (!keys.length) return prefix + "{}";
+        const key = k => /^[A-Za-z_$][\w$]*$/.test(k) ? k : quote(k);
+        return prefix + "{ " + keys.map(k => key(k) + ": " + item(value[k])).join(", ") + " }";
+    }
+
+    // printf-style substitutions in a leading string, then the remaining
+    // arguments separated by spaces
+    function format(args) {
+        const parts = [];
+        let rest = args;
+        if (typeof args[0] === "string" && args.length > 1) {
+            let i = 1;
+            parts.push(args[0].replace(/%([sdifoOjc%])/g, (match, spec) => {
+                if (spec === "%") return "%";
+                if (i >= args.length) return match;
+                const arg = args[i++];
+                switch (spec) {
+                    case "s": return typeof arg === "string" ? arg : inspect(arg, 1, []);
+                    case "d":
+                    case "i": return typeof arg === "object" ? "NaN" : String(spec === "i" ? parseInt(arg) : Number(arg));
+                    case "f": return String(parseFloat(arg));
+                    case "c": return "";
+                    default: return inspect(arg, 1, []);
+                }
+            }));
+            rest = args.slice(i);
+        } else if (args.length) {
+            parts.push(inspect(args[0], 0, []));
+            rest = args.slice(1);
+        }
+        for (const arg of rest) parts.push(inspect(arg, 0, []));
+        return parts.join(" ");
+    }
+
+    // console.table as box-drawn rows: an index column, the union of the
+    // rows' keys (or `columns`), and a Values column for primitive rows
+    function table(data, columns) {
+        if (data === null || typeof data !== "object") return format([data]);
+        const rows = Array.isArray(data) ? data.map((row, i) => [String(i), row]) : Object.entries(data);
+        const keys = [];
+        let hasValues = false;
+        for (const [, row] of rows) {
+            if (row !== null && typeof row === "object") {
+                for (const k of Object.keys(row)) if (!keys.includes(k)) keys.push(k);
+            } else {
+                hasValues = true;
+            }
+        }
+        const shown = Array.isArray(columns) ? columns.map(String) : keys;
+        const header = ["(index)"].concat(shown, hasValues ? ["Values"] : []);
+        const cell = v => v === undefined ? "" : inspect(v, 1, []);
+        const body = rows.map(([index, row]) => {
+            const isObject = row !== null && typeof row === "object";
+            const cells = shown.map(k => isObject ? cell(row[k]) : "");
+            return [index].concat(cells, hasValues ? [isObject ? "" : cell(row)] : []);
+        });
+        const widths = header.map((h, c) => Math.max(h.length, ...body.map(r => r[c].length)) + 2);
+        const line = (l, m, r) => l + widths.map(w => "─".repeat(w)).join(m) + r;
+        const row = cells => "│" + cells.map((text, c) => {
+            const pad = widths[c] - text.length;
+            return " ".repeat(Math.floor(pad / 2)) + text + " ".repeat(Math.ceil(pad / 2));
+        }).join("│") + "│";
+        return [line("┌", "┬", "┐"), row(header), line("├", "┼", "┤")]
+            .concat(body.map(row), [line("└", "┴", "┘")])
+            .join("\n");
+    }
+
+    const console = {};
+    for (const level of ["log", "info", "warn", "error", "debug"]) {
+        console[level] = function (...args) { native.record(level, format(args)); };
+    }
+    console.table = function (data, columns) { native.record("log", table(data, columns)); };
+    console.clear = function () { since = native.entries().length; };
+    globalThis.console = console;
+
+    function logged(level) {
+        return native.entries().slice(since).filter(e => level === undefined || e.level === level);
+    }
+
+    function matches(message, pattern) {
+        if (pattern === undefined) return true;
+        if (pattern instanceof RegExp) return pattern.test(message);
+        return message.includes(String(pattern));
+    }
+
+    function matcher(level, what, negate) {
+        return function (pattern) {
+            const entries = logged(level);
+            const found = entries.some(e => matches(e.message, pattern));
+            if (found !== negate) return;
+            const expected = (negate ? "not to have logged " : "to have logged ") + what +
+                (pattern === undefined ? "" : " matching " + inspect(pattern, 1, []));
+            const actual = entries.length
+                ? "logged:\n" + entries.map(e => "  [" + e.level + "] " + e.message).join("\n")
+                : "logged nothing";
+            throw new Error("Expected console " + expected + ", but it " + actual);
+        };
+    }
+
+    function matchers(negate) {
+        return {
+            toHaveLogged: matcher(undefined, "anything", negate),
+            toHaveLoggedInfo: matcher("info", "info", negate),
+            toHaveLoggedWarning: matcher("warn", "a warning", negate),
+            toHaveLoggedError: matcher("error", "an error", negate),
+            toHaveLoggedDebug: matcher("debug", "debug output", negate),
+        };
+    }
+
+    globalThis.expect = function (actual) {
+        if (actual !== globalThis.console) {
+            throw new TypeError("expect() only supports console, e.g. expect(console).toHaveLoggedError()");
+        }
+        const result = matchers(false);
+        result.not = matchers(true);
+        return result;
+    };
+
+    native.startTest = function () { since = native.entries().length; };
+})();
+"#;
+
+/// Add `entry` to `buffer`, logging it first with `echo` as an event of
+/// the matching level; `log` is `info`
+pub fn record(buffer: &ConsoleBuffer, entry: ConsoleEntry, echo: bool) {
+    if echo {
+        match entry.level {
+            ConsoleLevel::Log | ConsoleLevel::Info => tracing::info!("{}", entry.message),
+            ConsoleLevel::Warn => tracing::warn!("{}", entry.message),
+            ConsoleLevel::Error => tracing::error!("{}", entry.message),
+            ConsoleLevel::Debug => tracing::debug!("{}", entry.message),
+        }
+    }
+    buffer.borrow_mut().push(entry);
+}
+
+/// Define `console` and `expect(console)`, recording into `buffer`
+///
+/// With `echo`, entries are also logged; see `record`.
+pub fn install_console<'js>(ctx: &Ctx<'js>, buffer: ConsoleBuffer, echo: bool) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let recorded = buffer.clone();
+    let record_fn = Function::new(ctx.clone(), move |level: String, message: String| {
+        let level = ConsoleLevel::parse(&level).unwrap_or(ConsoleLevel::Log);
+        record(&recorded, ConsoleEntry { level, message }, echo);
+    })?;
+    native.set("record", record_fn)?;
+    let entries_fn = Function::new(ctx.clone(), move |ctx: Ctx<'js>| -> rquickjs::Result<Array<'js>> {
+        let entries = Array::new(ctx.clone())?;
+        for (i, entry) in buffer.borrow().iter().enumerate() {
+            let object = Object::new(ctx.clone())?;
+            object.set("level", entry.level.as_str())?;
+            object.set("message", entry.message.as_str())?;
+            entries.set(i, object)?;
+        }
+        Ok(entries)
+    })?;
+    native.set("entries", entries_fn)?;
+    ctx.globals().set("__cortexConsole", native)?;
+    ctx.eval::<(), _>(CONSOLE_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rquickjs::{Context, Runtime};
+
+    /// Evaluate `source` with a console installed, returning what it logged
+    fn logged(source: &str) -> Vec<ConsoleEntry> {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        let buffer = ConsoleBuffer::default();
+        context.with(|ctx| {
+            install_console(&ctx, buffer.clone(), false).unwrap();
+            ctx.eval::<(), _>(source).unwrap();
+        });
+        let entries = buffer.borrow().clone();
+        entries
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        logged(source).into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    fn test_levels_are_recorded() {
+        let entries = logged("console.log('a'); console.info('b'); console.warn('c'); console.error('d'); console.debug('e');");
+        let levels: Vec<ConsoleLevel> = entries.iter().map(|e| e.level).collect();
+        assert_eq!(
+            levels,
+            vec![ConsoleLevel::Log, ConsoleLevel::Info, ConsoleLevel::Warn, ConsoleLevel::Error, ConsoleLevel::Debug]
+        );
+        assert_eq!(entries[3].message, "d");
+    }
+
+    #[test]
+    fn test_multiple_arguments_and_substitutions() {
+        assert_eq!(
+            messages(
+                "console.log('a', 1, true, null, undefined);
+                 console.log('%s has %d items (%i%%)', 'cart', 3, 42.7);
+                 console.log('%o', { a: 'x' }, 'extra');
+                 console.log('%c styled', 'color: red');
+                 console.log('%s missing');"
+            ),
+            vec!["a 1 true null undefined", "cart has 3 items (42%)", "{ a: 'x' } extra", " styled", "%s missing"]
+        );
+    }
+
+    #[test]
+    fn test_object_inspection() {
+        assert_eq!(
+            messages(
+                "console.log({ a: 1, b: [1, 'two'], 'c-d': { e: null } });
+                 class Point { constructor() { this.x = 1; } }
+                 console.log(new Point(), [], {}, function named() {});
+                 const loop = { name: 'loop' }; loop.self = loop; console.log(loop);
+                 console.log({ a: { b: { c: { d: 1 } } } });
+                 console.log(new Map([['k', 1]]), new Set([1]));"
+            ),
+            vec![
+                "{ a: 1, b: [ 1, 'two' ], 'c-d': { e: null } }",
+                "Point { x: 1 } [] {} [Function: named]",
+                "{ name: 'loop', self: [Circular] }",
+                "{ a: { b: { c: [Object] } } }",
+                "Map(1) { 'k' => 1 } Set(1) { 1 }",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_console_table() {
+        let table = messages("console.table([{ a: 1, b: 'x' }, { a: 2 }]);").remove(0);
+        assert_eq!(
+            table,
+            "┌─────────┬───┬─────┐\n\
+             │ (index) │ a │  b  │\n\
+             ├─────────┼───┼─────┤\n\
+             │    0    │ 1 │ 'x' │\n\
+             │    1    │ 2 │     │\n\
+             └─────────┴───┴─────┘"
+        );
+        assert!(messages("console.table(['p']);")[0].contains("Values"));
+    }
+
+    #[test]
+    fn test_expect_console_matchers() {
+        let outcome = |source: &str| -> Result<(), String> {
+            let runtime = Runtime::new().unwrap();
+            let context = Context::full(&runtime).unwrap();
+            context.with(|ctx| {
+                install_console(&ctx, ConsoleBuffer::default(), false).unwrap();
+                ctx.eval::<(), _>(source).map_err(|_| {
+                    let error = ctx.catch();
+                    error.as_object().and_then(|e| e.get::<_, String>("message").ok()).unwrap_or_default()
+                })
+            })
+        };
+
+        assert!(outcome("console.error('Save failed: 500'); expect(console).toHaveLoggedError('Save failed');").is_ok());
+        assert!(outcome("console.error('E42'); expect(console).toHaveLoggedError(/E\\d+/);").is_ok());
+        assert!(outcome("console.log('fine'); expect(console).not.toHaveLoggedError();").is_ok());
+
+        let error = outcome("console.warn('careful'); expect(console).toHaveLoggedError('boom');").unwrap_err();
+        assert_eq!(error, "Expected console to have logged an error matching 'boom', but it logged nothing");
+        let error = outcome("console.error('boom'); expect(console).not.toHaveLoggedError();").unwrap_err();
+        assert_eq!(error, "Expected console not to have logged an error, but it logged:\n  [error] boom");
+
+        assert!(outcome("console.error('old'); console.clear(); expect(console).not.toHaveLoggedError();").is_ok());
+        assert!(outcome("expect(1)").is_err());
+    }
+}
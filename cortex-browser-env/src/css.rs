@@ -1,15 +1,66 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use super::dom::Display;
+use crate::media::MediaEnvironment;
+use crate::text::WordBreaking;
+use crate::transform::TransformFunction;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct StyleSheet {
     pub rules: Vec<Rule>,
+    pub keyframes: Vec<Keyframes>,
+    /// What the rules' `@media` conditions are evaluated against
+    pub media: MediaEnvironment,
 }
 
-#[derive(Debug)]
+impl StyleSheet {
+    /// Rules that apply: those outside `@media` blocks, and those whose
+    /// condition matches the sheet's media
+    pub fn active_rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().filter(|rule| rule.media.as_ref().is_none_or(|query| self.media.matches(query)))
+    }
+
+    /// The `@keyframes` rule named `name` that applies; the last wins
+    pub fn find_keyframes(&self, name: &str) -> Option<&Keyframes> {
+        self.keyframes
+            .iter()
+            .rev()
+            .find(|k| k.name == name && k.media.as_ref().is_none_or(|query| self.media.matches(query)))
+    }
+}
+
+/// A `@keyframes` rule: property values at points through an animation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframes {
+    pub name: String,
+    /// Sorted by offset
+    pub frames: Vec<Keyframe>,
+    /// Condition of the enclosing `@media` blocks, if any
+    pub media: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    /// Point through the animation, from 0 (`from`) to 1 (`to`)
+    pub offset: f32,
+    pub declarations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Rule {
     pub selectors: Vec<String>,
-    pub declarations: HashMap<String, String>,
+    /// In source order, so a shorthand after a longhand resets it; when a
+    /// property repeats, the last one wins
+    pub declarations: Vec<(String, String)>,
+    /// Condition of the enclosing `@media` blocks, if any
+    pub media: Option<String>,
+}
+
+impl Rule {
+    /// The value `property` is last declared with
+    pub fn declaration(&self, property: &str) -> Option<&str> {
+        self.declarations.iter().rev().find(|(name, _)| name == property).map(|(_, value)| value.as_str())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -26,29 +77,628 @@ pub struct ComputedStyle {
     pub margin_left: Option<CSSValue>,
     pub border_width: Option<CSSValue>,
     pub border_color: Option<String>,
+    pub border_top_left_radius: Option<CSSValue>,
+    pub border_top_right_radius: Option<CSSValue>,
+    pub border_bottom_right_radius: Option<CSSValue>,
+    pub border_bottom_left_radius: Option<CSSValue>,
+    pub box_shadows: Vec<BoxShadow>,
+    pub text_decoration: TextDecoration,
+    pub text_transform: Option<TextTransform>,
+    pub letter_spacing: Option<CSSValue>,
+    pub word_spacing: Option<CSSValue>,
+    /// Inherited from the parent when unset
+    pub white_space: Option<WhiteSpace>,
+    /// Inherited from the parent when unset
+    pub overflow_wrap: Option<OverflowWrap>,
+    /// Inherited from the parent when unset
+    pub word_break: Option<WordBreak>,
+    pub overflow: Option<Overflow>,
+    pub text_overflow: Option<TextOverflow>,
+    /// Inherited from the parent when unset
+    pub list_style_type: Option<ListStyleType>,
+    /// Inherited from the parent when unset
+    pub list_style_position: Option<ListStylePosition>,
     pub display: Display,
+    /// Inherited from the parent when unset
+    pub visibility: Option<Visibility>,
+    /// Inherited from the parent when unset; 16px at the root
     pub font_size: Option<CSSValue>,
+    /// 100 to 900; inherited from the parent when unset
+    pub font_weight: Option<u16>,
+    /// The `font-family` list as written; inherited from the parent when unset
+    pub font_family: Option<String>,
     pub color: Option<String>,
     pub background_color: Option<String>,
+    pub background_image: Option<String>,
+    /// Empty for `transform: none`
+    pub transform: Vec<TransformFunction>,
+    /// Offsets into the border box; the center when unset
+    pub transform_origin: Option<[CSSValue; 2]>,
+    /// Paint and hit-test order among siblings; `auto` when unset
+    pub z_index: Option<i32>,
+    /// Inherited from the parent when unset
+    pub pointer_events: Option<PointerEvents>,
+}
+
+/// Whether an element can be the target of pointer events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerEvents {
+    Auto,
+    None,
+}
+
+impl PointerEvents {
+    pub fn parse(value: &str) -> Option<PointerEvents> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(PointerEvents::None),
+            // The SVG values all let events through for HTML boxes
+            "auto" | "all" | "visiblepainted" | "visible" | "painted" | "fill" | "stroke" => Some(PointerEvents::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// A single `box-shadow` layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxShadow {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub blur_radius: f32,
+    pub spread_radius: f32,
+    pub color: String,
+    pub inset: bool,
+}
+
+/// Lines drawn by `text-decoration`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextDecoration {
+    pub underline: bool,
+    pub overline: bool,
+    pub line_through: bool,
+    /// `text-decoration-color`; lines use the text color when unset
+    pub color: Option<String>,
+}
+
+impl TextDecoration {
+    /// Check whether any decoration line is set
+    pub fn is_none(&self) -> bool {
+        !self.underline && !self.overline && !self.line_through
+    }
+}
+
+/// Case mapping applied by `text-transform`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextTransform {
+    #[default]
+    None,
+    Uppercase,
+    Lowercase,
+    Capitalize,
+}
+
+impl TextTransform {
+    /// Parse a `text-transform` keyword
+    pub fn parse(value: &str) -> Option<TextTransform> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Some(TextTransform::None),
+            "uppercase" => Some(TextTransform::Uppercase),
+            "lowercase" => Some(TextTransform::Lowercase),
+            "capitalize" => Some(TextTransform::Capitalize),
+            _ => None,
+        }
+    }
+
+    /// Apply the case mapping to a run of text
+    ///
+    /// `capitalize` uppercases the first letter of each whitespace-separated
+    /// word and leaves the rest untouched, like browsers do.
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            TextTransform::None => text.to_string(),
+            TextTransform::Uppercase => text.to_uppercase(),
+            TextTransform::Lowercase => text.to_lowercase(),
+            TextTransform::Capitalize => {
+                let mut result = String::with_capacity(text.len());
+                let mut at_word_start = true;
+                for ch in text.chars() {
+                    if at_word_start && ch.is_alphanumeric() {
+                        result.extend(ch.to_uppercase());
+                        at_word_start = false;
+                    } else {
+                        result.push(ch);
+                        if ch.is_whitespace() {
+                            at_word_start = true;
+                        }
+                    }
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Whether `visibility` paints a box; hidden boxes still take up space
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    Hidden,
+    /// The same as `hidden` outside tables
+    Collapse,
+}
+
+impl Visibility {
+    /// Parse a `visibility` keyword
+    pub fn parse(value: &str) -> Option<Visibility> {
+        match value.trim().to_lowercase().as_str() {
+            "visible" => Some(Visibility::Visible),
+            "hidden" => Some(Visibility::Hidden),
+            "collapse" => Some(Visibility::Collapse),
+            _ => None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        *self == Visibility::Visible
+    }
+}
+
+/// How `white-space` treats the spaces, tabs and newlines in text
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WhiteSpace {
+    #[default]
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+}
+
+impl WhiteSpace {
+    /// Parse a `white-space` keyword
+    pub fn parse(value: &str) -> Option<WhiteSpace> {
+        match value.trim().to_lowercase().as_str() {
+            "normal" => Some(WhiteSpace::Normal),
+            "pre" => Some(WhiteSpace::Pre),
+            "nowrap" => Some(WhiteSpace::Nowrap),
+            "pre-wrap" => Some(WhiteSpace::PreWrap),
+            _ => None,
+        }
+    }
+
+    /// Whether runs of whitespace, newlines included, collapse to one space
+    pub fn collapses(&self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// Whether lines wrap at the edge of the box
+    pub fn wraps(&self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreWrap)
+    }
+
+    /// The text as it is laid out: with `normal` and `nowrap` each run of
+    /// spaces, tabs and newlines becomes one space; `pre` and `pre-wrap`
+    /// keep it as written. No-break spaces never collapse.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.collapses() {
+            return Cow::Borrowed(text);
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut in_space = false;
+        for ch in text.chars() {
+            if matches!(ch, ' ' | '\t' | '\n' | '\r' | '\x0C') {
+                if !in_space {
+                    result.push(' ');
+                }
+                in_space = true;
+            } else {
+                result.push(ch);
+                in_space = false;
+            }
+        }
+        Cow::Owned(result)
+    }
+}
+
+/// How a line breaks inside a word that is too long for it, from
+/// `overflow-wrap` (or its old name `word-wrap`)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OverflowWrap {
+    /// Overlong words overflow the box
+    #[default]
+    Normal,
+    BreakWord,
+    Anywhere,
+}
+
+impl OverflowWrap {
+    /// Parse an `overflow-wrap` keyword
+    pub fn parse(value: &str) -> Option<OverflowWrap> {
+        match value.trim().to_lowercase().as_str() {
+            "normal" => Some(OverflowWrap::Normal),
+            "break-word" => Some(OverflowWrap::BreakWord),
+            "anywhere" => Some(OverflowWrap::Anywhere),
+            _ => None,
+        }
+    }
+}
+
+/// Where `word-break` allows line breaks inside words
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WordBreak {
+    #[default]
+    Normal,
+    /// Between any two characters
+    BreakAll,
+    /// Never inside CJK words; the same as `normal` here, which has no CJK
+    /// break opportunities
+    KeepAll,
+    /// Deprecated; `normal` with `overflow-wrap: anywhere`
+    BreakWord,
+}
+
+impl WordBreak {
+    /// Parse a `word-break` keyword
+    pub fn parse(value: &str) -> Option<WordBreak> {
+        match value.trim().to_lowercase().as_str() {
+            "normal" => Some(WordBreak::Normal),
+            "break-all" => Some(WordBreak::BreakAll),
+            "keep-all" => Some(WordBreak::KeepAll),
+            "break-word" => Some(WordBreak::BreakWord),
+            _ => None,
+        }
+    }
+
+    /// How `text::break_lines` treats words, combining this with
+    /// `overflow-wrap`
+    pub fn breaking(&self, overflow_wrap: OverflowWrap) -> WordBreaking {
+        match (self, overflow_wrap) {
+            (WordBreak::BreakAll, _) => WordBreaking::Anywhere,
+            (WordBreak::BreakWord, _) | (_, OverflowWrap::BreakWord | OverflowWrap::Anywhere) => WordBreaking::Overlong,
+            _ => WordBreaking::Normal,
+        }
+    }
+}
+
+/// Whether `overflow` clips content that spills out of the padding box
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Overflow {
+    #[default]
+    Visible,
+    Hidden,
+    Clip,
+    /// Clipped like `hidden`; there are no scrollbars to paint
+    Scroll,
+    Auto,
+}
+
+impl Overflow {
+    /// Parse an `overflow` value; with two keywords (x then y) the first
+    /// is used
+    pub fn parse(value: &str) -> Option<Overflow> {
+        match value.split_whitespace().next()?.to_lowercase().as_str() {
+            "visible" => Some(Overflow::Visible),
+            "hidden" => Some(Overflow::Hidden),
+            "clip" => Some(Overflow::Clip),
+            "scroll" => Some(Overflow::Scroll),
+            "auto" => Some(Overflow::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn clips(&self) -> bool {
+        *self != Overflow::Visible
+    }
+}
+
+/// What `text-overflow` shows where a clipped line is cut off
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextOverflow {
+    #[default]
+    Clip,
+    /// An ellipsis (`…`) at the end of the visible text
+    Ellipsis,
+}
+
+impl TextOverflow {
+    /// Parse a `text-overflow` keyword
+    pub fn parse(value: &str) -> Option<TextOverflow> {
+        match value.trim().to_lowercase().as_str() {
+            "clip" => Some(TextOverflow::Clip),
+            "ellipsis" => Some(TextOverflow::Ellipsis),
+            _ => None,
+        }
+    }
+}
+
+/// The marker `list-style-type` puts in front of a list item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListStyleType {
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+    None,
+}
+
+impl ListStyleType {
+    /// Parse a `list-style-type` keyword
+    pub fn parse(value: &str) -> Option<ListStyleType> {
+        match value.trim().to_lowercase().as_str() {
+            "disc" => Some(ListStyleType::Disc),
+            "circle" => Some(ListStyleType::Circle),
+            "square" => Some(ListStyleType::Square),
+            "decimal" => Some(ListStyleType::Decimal),
+            "lower-alpha" | "lower-latin" => Some(ListStyleType::LowerAlpha),
+            "upper-alpha" | "upper-latin" => Some(ListStyleType::UpperAlpha),
+            "lower-roman" => Some(ListStyleType::LowerRoman),
+            "upper-roman" => Some(ListStyleType::UpperRoman),
+            "none" => Some(ListStyleType::None),
+            _ => None,
+        }
+    }
+
+    /// The text of the marker for item number `ordinal`, like `3.` or
+    /// `iv.`; `None` for the bullet shapes and `none`
+    ///
+    /// Alphabetic and roman counters fall back to decimal outside the
+    /// range they can write, as browsers do.
+    pub fn marker_text(&self, ordinal: i64) -> Option<String> {
+        let counter = match self {
+            ListStyleType::Disc | ListStyleType::Circle | ListStyleType::Square | ListStyleType::None => return None,
+            ListStyleType::Decimal => ordinal.to_string(),
+            ListStyleType::LowerAlpha | ListStyleType::UpperAlpha if ordinal >= 1 => {
+                let mut letters = Vec::new();
+                let mut n = ordinal;
+                while n > 0 {
+                    n -= 1;
+                    letters.push((b'a' + (n % 26) as u8) as char);
+                    n /= 26;
+                }
+                let lower: String = letters.into_iter().rev().collect();
+                if *self == ListStyleType::UpperAlpha { lower.to_uppercase() } else { lower }
+            }
+            ListStyleType::LowerRoman | ListStyleType::UpperRoman if (1..4000).contains(&ordinal) => {
+                const NUMERALS: [(i64, &str); 13] = [
+                    (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"), (50, "l"),
+                    (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+                ];
+                let mut roman = String::new();
+                let mut n = ordinal;
+                for (value, numeral) in NUMERALS {
+                    while n >= value {
+                        roman.push_str(numeral);
+                        n -= value;
+                    }
+                }
+                if *self == ListStyleType::UpperRoman { roman.to_uppercase() } else { roman }
+            }
+            _ => ordinal.to_string(),
+        };
+        Some(format!("{}.", counter))
+    }
+}
+
+/// Whether a list item's marker hangs outside its box or starts its content
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ListStylePosition {
+    #[default]
+    Outside,
+    Inside,
+}
+
+impl ListStylePosition {
+    /// Parse a `list-style-position` keyword
+    pub fn parse(value: &str) -> Option<ListStylePosition> {
+        match value.trim().to_lowercase().as_str() {
+            "outside" => Some(ListStylePosition::Outside),
+            "inside" => Some(ListStylePosition::Inside),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the `list-style` shorthand into its type and position, in either
+/// order; `none` is the type. Images are not supported and are skipped.
+pub fn parse_list_style(value: &str) -> (Option<ListStyleType>, Option<ListStylePosition>) {
+    let mut style_type = None;
+    let mut position = None;
+    for token in value.split_whitespace() {
+        if let Some(parsed) = ListStylePosition::parse(token) {
+            position = Some(parsed);
+        } else if let Some(parsed) = ListStyleType::parse(token) {
+            style_type = Some(parsed);
+        }
+    }
+    (style_type, position)
+}
+
+/// Parse a `display` keyword
+pub fn parse_display(value: &str) -> Option<Display> {
+    match value.trim().to_lowercase().as_str() {
+        // List items lay out as blocks; their markers come from the tag
+        "block" | "list-item" => Some(Display::Block),
+        "inline" => Some(Display::Inline),
+        "inline-block" => Some(Display::InlineBlock),
+        "flex" => Some(Display::Flex),
+        "grid" => Some(Display::Grid),
+        "none" => Some(Display::None),
+        _ => None,
+    }
+}
+
+/// Parse `font-size`: a length, a percentage of the parent's size, or an
+/// absolute keyword from `xx-small` to `xx-large`
+pub fn parse_font_size(value: &str) -> Option<CSSValue> {
+    let pixels = match value.trim().to_lowercase().as_str() {
+        "xx-small" => 9.0,
+        "x-small" => 10.0,
+        "small" => 13.0,
+        "medium" => 16.0,
+        "large" => 18.0,
+        "x-large" => 24.0,
+        "xx-large" => 32.0,
+        _ => return CSSValue::parse(value),
+    };
+    Some(CSSValue::Pixels(pixels))
+}
+
+/// Parse `font-weight`: a number from 1 to 1000, `normal` or `bold`
+///
+/// `bolder` and `lighter` are taken as `bold` and `normal`, which is what
+/// they give inside text of normal weight.
+pub fn parse_font_weight(value: &str) -> Option<u16> {
+    match value.trim().to_lowercase().as_str() {
+        "normal" | "lighter" => Some(400),
+        "bold" | "bolder" => Some(700),
+        number => number.parse::<u16>().ok().filter(|weight| (1..=1000).contains(weight)),
+    }
+}
+
+/// Parse a `margin` or `padding` shorthand into top, right, bottom and left
+/// values, expanding one to four values the way browsers do
+pub fn parse_edges(value: &str) -> Option<[CSSValue; 4]> {
+    let values: Vec<CSSValue> = value.split_whitespace().map(CSSValue::parse).collect::<Option<Vec<_>>>()?;
+    match values.as_slice() {
+        [all] => Some([all.clone(), all.clone(), all.clone(), all.clone()]),
+        [vertical, horizontal] => Some([vertical.clone(), horizontal.clone(), vertical.clone(), horizontal.clone()]),
+        [top, horizontal, bottom] => Some([top.clone(), horizontal.clone(), bottom.clone(), horizontal.clone()]),
+        [top, right, bottom, left] => Some([top.clone(), right.clone(), bottom.clone(), left.clone()]),
+        _ => None,
+    }
+}
+
+/// Parse `letter-spacing` / `word-spacing`, where `normal` means no extra space
+pub fn parse_spacing(value: &str) -> Option<CSSValue> {
+    if value.trim().eq_ignore_ascii_case("normal") {
+        return Some(CSSValue::Pixels(0.0));
+    }
+    CSSValue::parse(value)
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum CSSValue {
     Pixels(f32),
     Percentage(f32),
+    /// Multiples of the element's font size; the parent's for `font-size`
+    Em(f32),
+    /// Multiples of the root element's font size
+    Rem(f32),
     Auto,
     Inherit,
 }
 
 impl CSSValue {
+    /// The length in pixels, resolving percentages against `reference`
+    ///
+    /// `style::compute_styles` turns `em` and `rem` into pixels; any left
+    /// over count against the default 16px font.
     pub fn as_pixels(&self, reference: f32) -> f32 {
         match self {
             CSSValue::Pixels(px) => *px,
             CSSValue::Percentage(pct) => reference * (pct / 100.0),
+            CSSValue::Em(em) | CSSValue::Rem(em) => em * 16.0,
             CSSValue::Auto => 0.0,
             CSSValue::Inherit => 0.0,
         }
     }
+
+    /// `em` and `rem` in pixels, for an element with `font_size` on a page
+    /// whose root has `root_font_size`; other values are unchanged
+    pub fn resolve_font_relative(&self, font_size: f32, root_font_size: f32) -> CSSValue {
+        match self {
+            CSSValue::Em(em) => CSSValue::Pixels(em * font_size),
+            CSSValue::Rem(rem) => CSSValue::Pixels(rem * root_font_size),
+            other => other.clone(),
+        }
+    }
+
+    /// Parse a single CSS length (`12px`, `1.5em`, `2rem`, `50%`, `0`,
+    /// `auto`, `inherit`)
+    pub fn parse(value: &str) -> Option<CSSValue> {
+        let value = value.trim().to_lowercase();
+        match value.as_str() {
+            "auto" => return Some(CSSValue::Auto),
+            "inherit" => return Some(CSSValue::Inherit),
+            _ => {}
+        }
+
+        if let Some(px) = value.strip_suffix("px") {
+            return px.trim().parse::<f32>().ok().map(CSSValue::Pixels);
+        }
+        if let Some(rem) = value.strip_suffix("rem") {
+            return rem.trim().parse::<f32>().ok().map(CSSValue::Rem);
+        }
+        if let Some(em) = value.strip_suffix("em") {
+            return em.trim().parse::<f32>().ok().map(CSSValue::Em);
+        }
+        if let Some(pct) = value.strip_suffix('%') {
+            return pct.trim().parse::<f32>().ok().map(CSSValue::Percentage);
+        }
+
+        // Unitless lengths are only valid for zero
+        match value.parse::<f32>() {
+            Ok(0.0) => Some(CSSValue::Pixels(0.0)),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a `border-radius` shorthand into per-corner values
+///
+/// Corners are returned in CSS order: top-left, top-right, bottom-right,
+/// bottom-left. One to four values are accepted and expanded the same way
+/// browsers do. Only the horizontal radii before a `/` are used, since the
+/// renderer draws circular corners.
+pub fn parse_border_radius(value: &str) -> Option<[CSSValue; 4]> {
+    let horizontal = value.split('/').next().unwrap_or("");
+    let values: Vec<CSSValue> = horizontal
+        .split_whitespace()
+        .map(CSSValue::parse)
+        .collect::<Option<Vec<_>>>()?;
+
+    match values.as_slice() {
+        [all] => Some([all.clone(), all.clone(), all.clone(), all.clone()]),
+        [tl_br, tr_bl] => Some([tl_br.clone(), tr_bl.clone(), tl_br.clone(), tr_bl.clone()]),
+        [tl, tr_bl, br] => Some([tl.clone(), tr_bl.clone(), br.clone(), tr_bl.clone()]),
+        [tl, tr, br, bl] => Some([tl.clone(), tr.clone(), br.clone(), bl.clone()]),
+        _ => None,
+    }
+}
+
+impl ComputedStyle {
+    /// Turn the `em` and `rem` lengths other than `font-size` into pixels,
+    /// for an element whose font size came out as `font_size`
+    pub fn resolve_font_relative_lengths(&mut self, font_size: f32, root_font_size: f32) {
+        let lengths = [
+            &mut self.width,
+            &mut self.height,
+            &mut self.padding_top,
+            &mut self.padding_right,
+            &mut self.padding_bottom,
+            &mut self.padding_left,
+            &mut self.margin_top,
+            &mut self.margin_right,
+            &mut self.margin_bottom,
+            &mut self.margin_left,
+            &mut self.border_width,
+            &mut self.border_top_left_radius,
+            &mut self.border_top_right_radius,
+            &mut self.border_bottom_right_radius,
+            &mut self.border_bottom_left_radius,
+            &mut self.letter_spacing,
+            &mut self.word_spacing,
+        ];
+        for value in lengths.into_iter().flatten() {
+            *value = value.resolve_font_relative(font_size, root_font_size);
+        }
+        for value in self.transform_origin.iter_mut().flatten() {
+            *value = value.resolve_font_relative(font_size, root_font_size);
+        }
+    }
 }
 
 impl Default for ComputedStyle {
@@ -66,53 +716,317 @@ impl Default for ComputedStyle {
             margin_left: None,
             border_width: None,
             border_color: None,
+            border_top_left_radius: None,
+            border_top_right_radius: None,
+            border_bottom_right_radius: None,
+            border_bottom_left_radius: None,
+            box_shadows: Vec::new(),
+            text_decoration: TextDecoration::default(),
+            text_transform: None,
+            letter_spacing: None,
+            word_spacing: None,
+            white_space: None,
+            overflow_wrap: None,
+            word_break: None,
+            overflow: None,
+            text_overflow: None,
+            list_style_type: None,
+            list_style_position: None,
             display: Display::Block,
-            font_size: Some(CSSValue::Pixels(16.0)),
+            visibility: None,
+            font_size: None,
+            font_weight: None,
+            font_family: None,
             color: None,
             background_color: None,
+            background_image: None,
+            transform: Vec::new(),
+            transform_origin: None,
+            z_index: None,
+            pointer_events: None,
+        }
+    }
+}
+
+/// Split a value on top-level commas, leaving commas inside `rgb(...)` alone
+fn split_top_level_commas(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Split a value on whitespace, keeping functional notation such as
+/// `rgb(0, 0, 0)` together as one token
+fn split_tokens(value: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' => {
+                depth -= 1;
+                current.push(c);
+            }
+            c if c.is_whitespace() && depth == 0 => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Parse a `box-shadow` value into its comma-separated layers
+///
+/// Each layer is `[inset] <offset-x> <offset-y> [<blur>] [<spread>] [<color>]`
+/// with the color allowed before or after the lengths. A missing color
+/// defaults to black, and `none` yields an empty list.
+pub fn parse_box_shadow(value: &str) -> Option<Vec<BoxShadow>> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Some(Vec::new());
+    }
+
+    split_top_level_commas(value)
+        .into_iter()
+        .map(|layer| {
+            let mut lengths = Vec::new();
+            let mut color = None;
+            let mut inset = false;
+
+            for token in split_tokens(layer) {
+                if token.eq_ignore_ascii_case("inset") {
+                    inset = true;
+                } else if let Some(length) = CSSValue::parse(&token) {
+                    lengths.push(length.as_pixels(0.0));
+                } else if color.is_none() {
+                    color = Some(token);
+                } else {
+                    return None;
+                }
+            }
+
+            if !(2..=4).contains(&lengths.len()) {
+                return None;
+            }
+
+            Some(BoxShadow {
+                offset_x: lengths[0],
+                offset_y: lengths[1],
+                blur_radius: lengths.get(2).copied().unwrap_or(0.0).max(0.0),
+                spread_radius: lengths.get(3).copied().unwrap_or(0.0),
+                color: color.unwrap_or_else(|| "black".to_string()),
+                inset,
+            })
+        })
+        .collect()
+}
+
+/// Parse a `text-decoration` shorthand or `text-decoration-line` value
+///
+/// Line keywords set the lines and any other token that is not a style
+/// keyword or thickness is taken as the decoration color.
+pub fn parse_text_decoration(value: &str) -> TextDecoration {
+    let mut decoration = TextDecoration::default();
+    for token in split_tokens(&value.to_lowercase()) {
+        match token.as_str() {
+            "underline" => decoration.underline = true,
+            "overline" => decoration.overline = true,
+            "line-through" => decoration.line_through = true,
+            "none" | "solid" | "double" | "dotted" | "dashed" | "wavy" | "auto" | "from-font"
+            | "blink" => {}
+            other if CSSValue::parse(other).is_some() => {}
+            other => decoration.color = Some(other.to_string()),
         }
     }
+    decoration
+}
+
+/// Extract the URL from a `url(...)` token
+///
+/// Quotes around the URL are optional. Anything else yields `None`.
+pub fn parse_url(value: &str) -> Option<String> {
+    let value = value.trim();
+    let inner = value
+        .get(..4)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("url("))
+        .and(value[4..].strip_suffix(')'))?
+        .trim();
+    let url = inner
+        .strip_prefix('"')
+        .and_then(|u| u.strip_suffix('"'))
+        .or_else(|| inner.strip_prefix('\'').and_then(|u| u.strip_suffix('\'')))
+        .unwrap_or(inner);
+    if url.is_empty() {
+        None
+    } else {
+        Some(url.to_string())
+    }
+}
+
+/// Extract the URL from a `background-image: url(...)` value
+///
+/// `none` and gradients yield `None`.
+pub fn parse_background_image(value: &str) -> Option<String> {
+    parse_url(value)
+}
+
+/// A `@font-face` rule: a family name and its candidate sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFace {
+    pub family: String,
+    /// URLs from `src`, in order of preference
+    pub sources: Vec<String>,
+}
+
+/// Extract the URLs of a `@font-face` `src` list
+///
+/// `format(...)` hints are ignored and `local(...)` sources are skipped,
+/// since only downloadable fonts can be loaded.
+pub fn parse_font_face_src(value: &str) -> Vec<String> {
+    split_top_level_commas(value)
+        .into_iter()
+        .filter_map(|source| split_tokens(source).first().and_then(|token| parse_url(token)))
+        .collect()
+}
+
+/// Collect the `@font-face` rules of a stylesheet
+///
+/// Rules without a `font-family` or any loadable `src` are dropped.
+pub fn font_faces(stylesheet: &StyleSheet) -> Vec<FontFace> {
+    stylesheet
+        .rules
+        .iter()
+        .filter(|rule| rule.selectors.iter().any(|s| s.eq_ignore_ascii_case("@font-face")))
+        .filter_map(|rule| {
+            let family = rule.declaration("font-family")?.trim().trim_matches(['"', '\'']).to_string();
+            let sources = parse_font_face_src(rule.declaration("src")?);
+            (!family.is_empty() && !sources.is_empty()).then_some(FontFace { family, sources })
+        })
+        .collect()
 }
 
 pub fn parse_css(css: &str) -> StyleSheet {
     // Very basic CSS parser for now
     // This will be expanded as needed
 
-    let mut rules = Vec::new();
+    let mut stylesheet = StyleSheet::default();
     let mut chars = css.chars().peekable();
+    consume_rules(&mut chars, None, &mut stylesheet);
+    stylesheet
+}
 
+/// Parse rules until the end of input or the `}` closing the enclosing
+/// `@media` block, whose condition is `media`
+fn consume_rules(chars: &mut std::iter::Peekable<std::str::Chars>, media: Option<&str>, stylesheet: &mut StyleSheet) {
     while let Some(&c) = chars.peek() {
         if c.is_whitespace() {
             chars.next();
             continue;
         }
+        if c == '}' {
+            chars.next(); // Consume the block's '}'
+            return;
+        }
 
         // Parse selectors
-        let selectors = consume_selectors(&mut chars);
+        let selectors = consume_selectors(chars);
         if selectors.is_empty() {
             break; // No more selectors, end parsing
         }
 
         // Consume '{'
-        consume_until(&mut chars, '{');
+        consume_until(chars, '{');
         chars.next(); // Consume '{'
 
+        // `@media` blocks hold rules rather than declarations; nested
+        // blocks must all match
+        let prelude = selectors.join(", ");
+        if let Some(query) = prelude.strip_prefix("@media").filter(|q| q.starts_with([' ', '('])) {
+            let query = match media {
+                Some(outer) => format!("{} and {}", outer, query.trim()),
+                None => query.trim().to_string(),
+            };
+            consume_rules(chars, Some(&query), stylesheet);
+            continue;
+        }
+        if let Some(name) = prelude.strip_prefix("@keyframes").or_else(|| prelude.strip_prefix("@-webkit-keyframes")) {
+            let name = name.trim().trim_matches(['"', '\'']).to_string();
+            let frames = consume_keyframes(chars);
+            stylesheet.keyframes.push(Keyframes { name, frames, media: media.map(str::to_string) });
+            continue;
+        }
+
         // Parse declarations
-        let declarations = consume_declarations(&mut chars);
+        let declarations = consume_declarations(chars);
 
         // Consume '}'
-        consume_until(&mut chars, '}');
+        consume_until(chars, '}');
         chars.next(); // Consume '}'
 
-        rules.push(Rule {
+        stylesheet.rules.push(Rule {
             selectors,
             declarations,
+            media: media.map(str::to_string),
         });
     }
+}
+
+/// Parse the keyframes of a `@keyframes` block, through its closing `}`
+fn consume_keyframes(chars: &mut std::iter::Peekable<std::str::Chars>) -> Vec<Keyframe> {
+    let mut frames = Vec::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '}' {
+            chars.next(); // Consume the block's '}'
+            break;
+        }
+
+        let selectors = consume_selectors(chars);
+        consume_until(chars, '{');
+        chars.next(); // Consume '{'
+        let declarations = consume_declarations(chars);
+        consume_until(chars, '}');
+        chars.next(); // Consume '}'
 
-    StyleSheet {
-        rules,
+        // `from`, `to` and percentages; anything else drops the keyframe
+        for selector in selectors {
+            let offset = match selector.to_ascii_lowercase().as_str() {
+                "from" => Some(0.0),
+                "to" => Some(1.0),
+                other => other.strip_suffix('%').and_then(|p| p.trim().parse::<f32>().ok()).map(|p| p / 100.0),
+            };
+            if let Some(offset) = offset.filter(|o| (0.0..=1.0).contains(o)) {
+                frames.push(Keyframe { offset, declarations: declarations.iter().cloned().collect() });
+            }
+        }
     }
+    frames.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    frames
 }
 
 fn consume_selectors(chars: &mut std::iter::Peekable<std::str::Chars>) -> Vec<String> {
@@ -137,8 +1051,8 @@ fn consume_selectors(chars: &mut std::iter::Peekable<std::str::Chars>) -> Vec<St
     selectors
 }
 
-fn consume_declarations(chars: &mut std::iter::Peekable<std::str::Chars>) -> HashMap<String, String> {
-    let mut declarations = HashMap::new();
+fn consume_declarations(chars: &mut std::iter::Peekable<std::str::Chars>) -> Vec<(String, String)> {
+    let mut declarations = Vec::new();
     while let Some(&c) = chars.peek() {
         if c.is_whitespace() {
             chars.next();
@@ -155,7 +1069,7 @@ fn consume_declarations(chars: &mut std::iter::Peekable<std::str::Chars>) -> Has
         consume_until(chars, ';');
         chars.next(); // Consume ';'
 
-        declarations.insert(property, value);
+        declarations.push((property, value));
     }
     declarations
 }
@@ -173,9 +1087,17 @@ fn consume_property(chars: &mut std::iter::Peekable<std::str::Chars>) -> String
 
 fn consume_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
     let mut value = String::new();
+    // Semicolons inside `url(...)` or quotes (e.g. data: URIs) belong to the value
+    let mut depth = 0;
+    let mut quote = None;
     while let Some(&c) = chars.peek() {
-        if c == ';' || c == '}' {
-            break;
+        match (c, quote) {
+            (';' | '}', None) if depth == 0 => break,
+            ('"' | '\'', None) => quote = Some(c),
+            (_, Some(q)) if c == q => quote = None,
+            ('(', None) => depth += 1,
+            (')', None) => depth = (depth - 1).max(0),
+            _ => {}
         }
         value.push(chars.next().unwrap());
     }
@@ -194,7 +1116,7 @@ fn consume_until(chars: &mut std::iter::Peekable<std::str::Chars>, target: char)
 #[cfg(test)]
 mod tests {
     use super::*;
-    use maplit::hashmap;
+    use maplit::btreemap;
 
     #[test]
     fn test_parse_simple_css() {
@@ -205,9 +1127,245 @@ mod tests {
         let rule = &stylesheet.rules[0];
 
         assert_eq!(rule.selectors, vec!["h1"]);
-        assert_eq!(rule.declarations, hashmap!{
-            "color".to_string() => "red".to_string(),
-            "font-size".to_string() => "16px".to_string(),
+        assert_eq!(rule.declarations, [("color".to_string(), "red".to_string()), ("font-size".to_string(), "16px".to_string())]);
+    }
+
+    #[test]
+    fn test_media_rules_apply_when_matching() {
+        // Given: A theme with dark overrides, one nested in a width query
+        let mut stylesheet = parse_css(
+            "body { color: black; }
+             @media (prefers-color-scheme: dark) {
+                 body { color: white; }
+                 @media (max-width: 600px) { .nav { display: none; } }
+             }
+             p { margin: 0; }",
+        );
+
+        // Then: The nested rules carry their conditions, combined
+        let media: Vec<_> = stylesheet.rules.iter().map(|rule| rule.media.as_deref()).collect();
+        assert_eq!(
+            media,
+            [None, Some("(prefers-color-scheme: dark)"), Some("(prefers-color-scheme: dark) and (max-width: 600px)"), None]
+        );
+        assert_eq!(stylesheet.active_rules().count(), 2);
+
+        stylesheet.media.color_scheme = crate::media::ColorScheme::Dark;
+        assert_eq!(stylesheet.active_rules().count(), 3);
+    }
+
+    #[test]
+    fn test_parse_keyframes() {
+        let stylesheet = parse_css(
+            "@keyframes pulse {
+                 to { color: red; }
+                 from, 50% { color: blue; letter-spacing: 1px; }
+             }
+             .dot { animation: pulse 1s infinite; }",
+        );
+
+        let pulse = stylesheet.find_keyframes("pulse").unwrap();
+        let offsets: Vec<f32> = pulse.frames.iter().map(|frame| frame.offset).collect();
+        assert_eq!(offsets, [0.0, 0.5, 1.0]);
+        assert_eq!(pulse.frames[2].declarations, btreemap! { "color".to_string() => "red".to_string() });
+        assert_eq!(stylesheet.rules.len(), 1);
+        assert_eq!(stylesheet.rules[0].selectors, vec![".dot"]);
+    }
+
+    #[test]
+    fn test_parse_css_value_lengths() {
+        assert_eq!(CSSValue::parse("12px"), Some(CSSValue::Pixels(12.0)));
+        assert_eq!(CSSValue::parse(" 50% "), Some(CSSValue::Percentage(50.0)));
+        assert_eq!(CSSValue::parse("0"), Some(CSSValue::Pixels(0.0)));
+        assert_eq!(CSSValue::parse("auto"), Some(CSSValue::Auto));
+        assert_eq!(CSSValue::parse("12"), None);
+        assert_eq!(CSSValue::parse("big"), None);
+    }
+
+    #[test]
+    fn test_parse_border_radius_expansion() {
+        let px = CSSValue::Pixels;
+
+        assert_eq!(parse_border_radius("8px"), Some([px(8.0), px(8.0), px(8.0), px(8.0)]));
+        assert_eq!(parse_border_radius("4px 8px"), Some([px(4.0), px(8.0), px(4.0), px(8.0)]));
+        assert_eq!(parse_border_radius("1px 2px 3px"), Some([px(1.0), px(2.0), px(3.0), px(2.0)]));
+        assert_eq!(parse_border_radius("1px 2px 3px 4px"), Some([px(1.0), px(2.0), px(3.0), px(4.0)]));
+        assert_eq!(parse_border_radius("10px / 20px"), Some([px(10.0), px(10.0), px(10.0), px(10.0)]));
+        assert_eq!(parse_border_radius(""), None);
+        assert_eq!(parse_border_radius("1px 2px 3px 4px 5px"), None);
+    }
+
+    #[test]
+    fn test_parse_box_shadow_layers() {
+        let shadows = parse_box_shadow("0 2px 4px rgba(0, 0, 0, 0.5), inset 1px 1px red").unwrap();
+
+        assert_eq!(shadows.len(), 2);
+        assert_eq!(shadows[0], BoxShadow {
+            offset_x: 0.0,
+            offset_y: 2.0,
+            blur_radius: 4.0,
+            spread_radius: 0.0,
+            color: "rgba(0, 0, 0, 0.5)".to_string(),
+            inset: false,
         });
+        assert!(shadows[1].inset);
+        assert_eq!(shadows[1].color, "red");
+    }
+
+    #[test]
+    fn test_parse_box_shadow_color_first_and_spread() {
+        let shadows = parse_box_shadow("#3366ff 0 0 0 3px").unwrap();
+        assert_eq!(shadows[0].spread_radius, 3.0);
+        assert_eq!(shadows[0].color, "#3366ff");
+
+        assert_eq!(parse_box_shadow("none"), Some(Vec::new()));
+        assert_eq!(parse_box_shadow("2px"), None);
+    }
+
+    #[test]
+    fn test_text_transform_apply() {
+        // Given: Each text-transform keyword
+        let text = "buy now-or later";
+
+        // When/Then: The case mapping matches browser behavior
+        assert_eq!(TextTransform::parse("UPPERCASE"), Some(TextTransform::Uppercase));
+        assert_eq!(TextTransform::parse("small-caps"), None);
+        assert_eq!(TextTransform::Uppercase.apply(text), "BUY NOW-OR LATER");
+        assert_eq!(TextTransform::Lowercase.apply("Buy NOW"), "buy now");
+        assert_eq!(TextTransform::Capitalize.apply(text), "Buy Now-or Later");
+        assert_eq!(TextTransform::None.apply(text), text);
+    }
+
+    #[test]
+    fn test_white_space_apply() {
+        // Given: Text with runs of spaces, tabs and newlines
+        let text = "  one \t two\n\nthree\u{A0}\u{A0}four ";
+
+        // When/Then: Only normal and nowrap collapse it
+        assert_eq!(WhiteSpace::parse("Pre-Wrap"), Some(WhiteSpace::PreWrap));
+        assert_eq!(WhiteSpace::parse("break-spaces"), None);
+        assert_eq!(WhiteSpace::Normal.apply(text), " one two three\u{A0}\u{A0}four ");
+        assert_eq!(WhiteSpace::Nowrap.apply(text), WhiteSpace::Normal.apply(text));
+        assert_eq!(WhiteSpace::Pre.apply(text), text);
+        assert!(WhiteSpace::PreWrap.wraps() && !WhiteSpace::Pre.wraps() && !WhiteSpace::Nowrap.wraps());
+    }
+
+    #[test]
+    fn test_word_breaking_and_overflow_keywords() {
+        // Given/When/Then: word-break wins over overflow-wrap, which only
+        // breaks words that do not fit
+        assert_eq!(WordBreak::Normal.breaking(OverflowWrap::Normal), WordBreaking::Normal);
+        assert_eq!(WordBreak::KeepAll.breaking(OverflowWrap::Normal), WordBreaking::Normal);
+        assert_eq!(WordBreak::Normal.breaking(OverflowWrap::Anywhere), WordBreaking::Overlong);
+        assert_eq!(WordBreak::BreakWord.breaking(OverflowWrap::Normal), WordBreaking::Overlong);
+        assert_eq!(WordBreak::BreakAll.breaking(OverflowWrap::BreakWord), WordBreaking::Anywhere);
+        assert_eq!(OverflowWrap::parse("Break-Word"), Some(OverflowWrap::BreakWord));
+        assert_eq!(WordBreak::parse("auto-phrase"), None);
+
+        // And overflow takes the first of two keywords
+        assert_eq!(Overflow::parse("hidden auto"), Some(Overflow::Hidden));
+        assert_eq!(Overflow::parse(""), None);
+        assert!(Overflow::Clip.clips() && !Overflow::Visible.clips());
+        assert_eq!(TextOverflow::parse("ellipsis"), Some(TextOverflow::Ellipsis));
+        assert_eq!(TextOverflow::parse("\"…\""), None);
+    }
+
+    #[test]
+    fn test_list_style_markers() {
+        // Given/When/Then: Counters write each style's numerals
+        assert_eq!(ListStyleType::Decimal.marker_text(3).as_deref(), Some("3."));
+        assert_eq!(ListStyleType::LowerAlpha.marker_text(28).as_deref(), Some("ab."));
+        assert_eq!(ListStyleType::UpperRoman.marker_text(1994).as_deref(), Some("MCMXCIV."));
+        assert_eq!(ListStyleType::LowerRoman.marker_text(0).as_deref(), Some("0."));
+        assert_eq!(ListStyleType::Disc.marker_text(1), None);
+
+        // And the shorthand takes type and position in either order
+        assert_eq!(parse_list_style("inside square"), (Some(ListStyleType::Square), Some(ListStylePosition::Inside)));
+        assert_eq!(parse_list_style("none"), (Some(ListStyleType::None), None));
+    }
+
+    #[test]
+    fn test_parse_box_properties() {
+        // Given/When/Then: Shorthands expand like browsers do
+        assert_eq!(parse_edges("8px"), Some([CSSValue::Pixels(8.0), CSSValue::Pixels(8.0), CSSValue::Pixels(8.0), CSSValue::Pixels(8.0)]));
+        assert_eq!(parse_edges("1px 2px 3px"), Some([CSSValue::Pixels(1.0), CSSValue::Pixels(2.0), CSSValue::Pixels(3.0), CSSValue::Pixels(2.0)]));
+        assert_eq!(parse_edges("1px wide"), None);
+        assert_eq!(parse_display("list-item"), Some(Display::Block));
+        assert_eq!(parse_display("contents"), None);
+        assert_eq!(parse_font_weight("bold"), Some(700));
+        assert_eq!(parse_font_weight("600"), Some(600));
+        assert_eq!(parse_font_weight("heavy"), None);
+        assert_eq!(parse_font_size("x-large"), Some(CSSValue::Pixels(24.0)));
+        assert_eq!(parse_font_size("150%"), Some(CSSValue::Percentage(150.0)));
+    }
+
+    #[test]
+    fn test_parse_spacing() {
+        // Given/When/Then: normal is zero, lengths parse as usual
+        assert_eq!(parse_spacing("normal"), Some(CSSValue::Pixels(0.0)));
+        assert_eq!(parse_spacing("2px"), Some(CSSValue::Pixels(2.0)));
+        assert_eq!(parse_spacing("wide"), None);
+    }
+
+    #[test]
+    fn test_parse_css_keeps_semicolons_inside_url() {
+        let stylesheet = parse_css(".hero { background-image: url(data:image/png;base64,AA==); color: red; }");
+        let rule = &stylesheet.rules[0];
+
+        assert_eq!(rule.declaration("background-image"), Some("url(data:image/png;base64,AA==)"));
+        assert_eq!(rule.declaration("color"), Some("red"));
+    }
+
+    #[test]
+    fn test_parse_background_image() {
+        assert_eq!(parse_background_image("url(hero.png)"), Some("hero.png".to_string()));
+        assert_eq!(parse_background_image("URL( \"a b.jpg\" )"), Some("a b.jpg".to_string()));
+        assert_eq!(parse_background_image("url('data:image/png;base64,AA==')"), Some("data:image/png;base64,AA==".to_string()));
+        assert_eq!(parse_background_image("none"), None);
+        assert_eq!(parse_background_image("linear-gradient(red, blue)"), None);
+    }
+
+    #[test]
+    fn test_font_faces_from_stylesheet() {
+        // Given: A @font-face rule with local, inline and remote sources
+        let stylesheet = parse_css(
+            r#"
+            @font-face {
+                font-family: "Brand Sans";
+                src: local("Brand"), url(data:font/ttf;base64,AAEAAA==) format("truetype"), url('/brand.woff');
+            }
+            @font-face { src: url(nameless.ttf); }
+            p { color: red; }
+            "#,
+        );
+
+        // When: We collect the font faces
+        let faces = font_faces(&stylesheet);
+
+        // Then: Only the complete rule is kept, with its URLs in order
+        assert_eq!(
+            faces,
+            vec![FontFace {
+                family: "Brand Sans".to_string(),
+                sources: vec!["data:font/ttf;base64,AAEAAA==".to_string(), "/brand.woff".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_text_decoration() {
+        assert_eq!(
+            parse_text_decoration("underline"),
+            TextDecoration { underline: true, ..Default::default() }
+        );
+        assert_eq!(
+            parse_text_decoration("underline line-through wavy red"),
+            TextDecoration { underline: true, line_through: true, color: Some("red".to_string()), ..Default::default() }
+        );
+        assert_eq!(
+            parse_text_decoration("overline rgb(0, 0, 255) 2px"),
+            TextDecoration { overline: true, color: Some("rgb(0, 0, 255)".to_string()), ..Default::default() }
+        );
+        assert!(parse_text_decoration("none").is_none());
     }
 }
\ No newline at end of file
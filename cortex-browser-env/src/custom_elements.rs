@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+#[derive(Default)]
 pub struct CustomElementRegistry {
     registry: HashMap<String, usize>, // Map tag name to Node index (for now)
 }
@@ -0,0 +1,158 @@
+//! Debug Overlay
+//! Translucent margin, border, padding and content boxes, with node index
+//! labels, painted on top of a render
+//!
+//! Every laid-out element gets its margin, border and padding drawn as
+//! rings in their own colors, like browser devtools highlight one element,
+//! and its content box outlined rather than filled so nested boxes don't
+//! tint the page over and over. A label with the element's node index sits
+//! on its top-left corner, matching the indexes `inspect`, `.query` and
+//! scripts use. Boxes are drawn where layout put them, ignoring transforms
+//! and clips, and labels go on after all boxes so none is covered.
+
+use crate::display_list::{DisplayList, GlyphStyle, PaintCommand};
+use crate::dom::{Document, NodeData};
+use crate::geometry::Rect;
+use crate::inline;
+
+/// Colors as unpremultiplied ARGB, after the devtools box model
+pub const MARGIN_COLOR: u32 = 0x80F9CC9D;
+pub const BORDER_COLOR: u32 = 0x80FFEEBC;
+pub const PADDING_COLOR: u32 = 0x80C3D08B;
+pub const CONTENT_COLOR: u32 = 0xC08CB6C0;
+const LABEL_BACKGROUND: u32 = 0xCC000000;
+const LABEL_COLOR: u32 = 0xFFFFFFFF;
+
+/// Font size of the node index labels
+const LABEL_FONT_SIZE: f32 = 10.0;
+
+/// Record the overlay for every laid-out element of `document` at the end
+/// of `list`, so it paints over the page
+pub fn paint_overlay(list: &mut DisplayList, document: &Document) {
+    let mut elements = Vec::new();
+    if !document.nodes.is_empty() {
+        collect_elements(document, document.root, &mut elements);
+    }
+    for &idx in &elements {
+        let Some(layout) = document.layout(idx) else {
+            continue;
+        };
+        paint_ring(list, layout.margin_box(), layout.border_box(), MARGIN_COLOR);
+        paint_ring(list, layout.border_box(), layout.padding_box(), BORDER_COLOR);
+        paint_ring(list, layout.padding_box(), layout.content_box(), PADDING_COLOR);
+        let content = layout.content_box();
+        if !content.is_empty() {
+            list.push(PaintCommand::Border { rect: content, width: 1.0, radii: [0.0; 4], color: CONTENT_COLOR });
+        }
+    }
+    for &idx in &elements {
+        if let Some(layout) = document.layout(idx) {
+            paint_label(list, layout.border_box(), idx);
+        }
+    }
+}
+
+/// Elements from `idx` down, in tree order, that have a box
+fn collect_elements(document: &Document, idx: usize, elements: &mut Vec<usize>) {
+    let node = &document.nodes[idx];
+    if matches!(node.data, Some(NodeData::Element(_))) {
+        match document.layout(idx) {
+            Some(layout) if !layout.margin_box().is_empty() => elements.push(idx),
+            // Nothing under an element without a box is laid out either
+            None => return,
+            Some(_) => {}
+        }
+    }
+    for &child in &node.children {
+        collect_elements(document, child, elements);
+    }
+}
+
+/// Fill the area between `outer` and `inner` with one rectangle per side
+fn paint_ring(list: &mut DisplayList, outer: Rect, inner: Rect, color: u32) {
+    let sides = [
+        Rect::new(outer.x, outer.y, outer.width, inner.y - outer.y),
+        Rect::new(outer.x, inner.bottom(), outer.width, outer.bottom() - inner.bottom()),
+        Rect::new(outer.x, inner.y, inner.x - outer.x, inner.height),
+        Rect::new(inner.right(), inner.y, outer.right() - inner.right(), inner.height),
+    ];
+    for rect in sides.into_iter().filter(|side| !side.is_empty()) {
+        list.push(PaintCommand::Rect { rect, radii: [0.0; 4], color });
+    }
+}
+
+/// The node index on a dark tag at the box's top-left corner
+fn paint_label(list: &mut DisplayList, border_box: Rect, idx: usize) {
+    let glyph = GlyphStyle { color: LABEL_COLOR, ..inline::glyph_style(LABEL_FONT_SIZE, 0.0, 0.0) };
+    let text = idx.to_string();
+    let width: f32 = text.chars().map(|ch| glyph.advance(ch)).sum();
+    let (x, y) = (border_box.x.max(0.0), border_box.y.max(0.0));
+    list.push(PaintCommand::Rect { rect: Rect::new(x, y, width + 4.0, glyph.height + 2.0), radii: [0.0; 4], color: LABEL_BACKGROUND });
+    list.push(PaintCommand::Text { x: x + 2.0, y: y + 1.0, text, glyph, font: None });
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::PageBuilder;
+
+    const PAGE: &str = r#"<html><head><style>
+        body { margin: 0; }
+        .box { margin: 20px; border: 6px solid black; padding: 12px; }
+        .gone { display: none; }
+    </style></head><body><div class="box">Box</div><p class="gone">Hidden</p></body></html>"#;
+
+    #[test]
+    fn test_overlay_tints_each_box_area() {
+        // Given: The same page rendered with and without the overlay
+        let plain = PageBuilder::new().with_seed(1).with_viewport(300, 200).build().unwrap();
+        plain.load_html(PAGE);
+        let debug = PageBuilder::new().with_seed(1).with_viewport(300, 200).with_debug_boxes(true).build().unwrap();
+        debug.load_html(PAGE);
+
+        // When: Both are rendered
+        let (plain, debug) = (plain.render(), debug.render());
+        let pixel = |target: &raqote::DrawTarget, x: i32, y: i32| target.get_data()[(y * target.width() + x) as usize];
+
+        // Then: Margin and padding are tinted in their colors, and what
+        // the overlay leaves alone is unchanged
+        assert_eq!(pixel(&plain, 150, 10), 0xFFFFFFFF);
+        let margin = pixel(&debug, 150, 10);
+        assert!(margin != 0xFFFFFFFF && (margin >> 16) & 0xFF > (margin & 0xFF), "margin pixel {:08x}", margin);
+        let padding = pixel(&debug, 150, 30);
+        assert!((padding >> 8) & 0xFF > (padding & 0xFF) && padding != margin, "padding pixel {:08x}", padding);
+        assert_eq!(pixel(&plain, 150, 190), pixel(&debug, 150, 190));
+    }
+
+    #[test]
+    fn test_overlay_labels_laid_out_elements() {
+        // Given: A laid-out page with a hidden paragraph
+        let page = PageBuilder::new().with_seed(1).with_viewport(300, 200).build().unwrap();
+        page.load_html(PAGE);
+        page.layout();
+
+        // When: The overlay is recorded
+        let mut list = DisplayList::new();
+        paint_overlay(&mut list, &page.document());
+
+        // Then: The box is labeled with its index, after every box, and
+        // the hidden paragraph is left out
+        let labels: Vec<(f32, f32, &str)> = list
+            .iter()
+            .filter_map(|command| match command {
+                PaintCommand::Text { x, y, text, .. } => Some((*x, *y, text.as_str())),
+                _ => None,
+            })
+            .collect();
+        let div = page.query(".box").unwrap().unwrap().to_string();
+        let hidden = page.query(".gone").unwrap().unwrap().to_string();
+        assert!(labels.contains(&(22.0, 21.0, div.as_str())), "{:?}", labels);
+        assert!(!labels.iter().any(|(_, _, text)| *text == hidden), "{:?}", labels);
+        let first_label = list.iter().position(|command| matches!(command, PaintCommand::Text { .. })).unwrap();
+        assert!(list.iter().skip(first_label).all(|command| !matches!(command, PaintCommand::Border { .. })));
+    }
+}
@@ -0,0 +1,282 @@
+//! Devices
+//! What a page reports about the browser and machine it runs on:
+//! `Navigator` settings, and `Device` presets pairing them with a viewport
+//! and pixel ratio
+//!
+//! A preset changes everything a responsive component can observe together,
+//! so a page emulating an iPhone has a phone-sized viewport, a mobile Safari
+//! user agent and a touchscreen that `(pointer: coarse)` matches.
+//!
+//! Phones also honor `<meta name="viewport">`: a page without one is laid
+//! out 980px wide and zoomed out to fit the screen, as mobile browsers do,
+//! while `width=device-width` lays it out at the screen's width.
+
+use std::fmt;
+
+use crate::browser::{Viewport, DEFAULT_USER_AGENT, DEFAULT_VIEWPORT};
+
+/// The `navigator` properties scripts read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Navigator {
+    pub user_agent: String,
+    /// Preferred languages, most preferred first; `navigator.language` is
+    /// the first
+    pub languages: Vec<String>,
+    pub platform: String,
+    pub on_line: bool,
+    pub hardware_concurrency: u32,
+    /// Touch points the screen supports; a touchscreen when above 0
+    pub max_touch_points: u32,
+}
+
+impl Navigator {
+    /// `navigator.language`
+    pub fn language(&self) -> &str {
+        self.languages.first().map_or("en-US", String::as_str)
+    }
+
+    pub fn is_touch(&self) -> bool {
+        self.max_touch_points > 0
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Set the preferred languages, most preferred first
+    pub fn with_languages(mut self, languages: &[&str]) -> Self {
+        self.languages = languages.iter().map(|l| l.to_string()).collect();
+        self
+    }
+
+    pub fn with_platform(mut self, platform: &str) -> Self {
+        self.platform = platform.to_string();
+        self
+    }
+
+    pub fn with_on_line(mut self, on_line: bool) -> Self {
+        self.on_line = on_line;
+        self
+    }
+
+    pub fn with_hardware_concurrency(mut self, cores: u32) -> Self {
+        self.hardware_concurrency = cores;
+        self
+    }
+
+    pub fn with_max_touch_points(mut self, points: u32) -> Self {
+        self.max_touch_points = points;
+        self
+    }
+}
+
+impl Default for Navigator {
+    /// A desktop browser, online, in US English
+    fn default() -> Self {
+        Navigator {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            languages: vec!["en-US".to_string(), "en".to_string()],
+            platform: "Linux x86_64".to_string(),
+            on_line: true,
+            hardware_concurrency: 4,
+            max_touch_points: 0,
+        }
+    }
+}
+
+/// Device a page emulates, selected with `--device` or `with_device`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Device {
+    /// The defaults: a 1280x720 desktop browser with a mouse
+    #[default]
+    Desktop,
+    /// An iPhone 13 running Safari
+    IPhone,
+    /// A Pixel 7 running Chrome
+    Android,
+}
+
+impl Device {
+    pub const ALL: [Device; 3] = [Device::Desktop, Device::IPhone, Device::Android];
+
+    /// Parse a `--device` name, ignoring case
+    pub fn parse(name: &str) -> Result<Device, String> {
+        Device::ALL.into_iter().find(|device| device.name().eq_ignore_ascii_case(name.trim())).ok_or_else(|| {
+            let names: Vec<&str> = Device::ALL.iter().map(Device::name).collect();
+            format!("Unknown device '{}', expected one of: {}", name, names.join(", "))
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Device::Desktop => "desktop",
+            Device::IPhone => "iphone",
+            Device::Android => "android",
+        }
+    }
+
+    /// Viewport in CSS pixels, in portrait for phones
+    pub fn viewport(&self) -> Viewport {
+        match self {
+            Device::Desktop => DEFAULT_VIEWPORT,
+            Device::IPhone => Viewport { width: 390, height: 844 },
+            Device::Android => Viewport { width: 412, height: 915 },
+        }
+    }
+
+    /// Whether the device lays pages out by their `<meta name="viewport">`
+    pub fn is_mobile(&self) -> bool {
+        !matches!(self, Device::Desktop)
+    }
+
+    pub fn device_pixel_ratio(&self) -> f32 {
+        match self {
+            Device::Desktop => 1.0,
+            Device::IPhone => 3.0,
+            Device::Android => 2.625,
+        }
+    }
+
+    pub fn navigator(&self) -> Navigator {
+        match self {
+            Device::Desktop => Navigator::default(),
+            Device::IPhone => Navigator::default()
+                .with_user_agent(
+                    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 \
+                     (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1",
+                )
+                .with_platform("iPhone")
+                .with_hardware_concurrency(6)
+                .with_max_touch_points(5),
+            Device::Android => Navigator::default()
+                .with_user_agent(
+                    "Mozilla/5.0 (Linux; Android 13; Pixel 7) AppleWebKit/537.36 \
+                     (KHTML, like Gecko) Chrome/116.0.0.0 Mobile Safari/537.36",
+                )
+                .with_platform("Linux armv8l")
+                .with_hardware_concurrency(8)
+                .with_max_touch_points(5),
+        }
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Width mobile browsers lay out pages without a viewport `<meta>` at
+pub const FALLBACK_LAYOUT_WIDTH: f32 = 980.0;
+
+/// The settings of a `<meta name="viewport" content="...">`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ViewportMeta {
+    /// `width` in pixels, when given as a number
+    pub width: Option<f32>,
+    /// `width=device-width`
+    pub device_width: bool,
+    pub initial_scale: Option<f32>,
+}
+
+impl ViewportMeta {
+    /// Parse the `content`: comma or semicolon separated `key=value`
+    /// pairs, ignoring the keys and values it does not know
+    pub fn parse(content: &str) -> ViewportMeta {
+        let mut meta = ViewportMeta::default();
+        for pair in content.split([',', ';']) {
+            let Some((key, value)) = pair.split_once('=') else { continue };
+            let value = value.trim().to_ascii_lowercase();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "width" if value == "device-width" => meta.device_width = true,
+                "width" => meta.width = value.parse::<f32>().ok().filter(|w| *w > 0.0).map(|w| w.clamp(1.0, 10000.0)),
+                "initial-scale" => meta.initial_scale = value.parse::<f32>().ok().filter(|s| *s > 0.0).map(|s| s.clamp(0.1, 10.0)),
+                _ => {}
+            }
+        }
+        meta
+    }
+
+    /// Width to lay the page out at on a screen `device_width` wide
+    ///
+    /// An explicit width wins, then `device-width`; a scale alone fits the
+    /// screen at that zoom. With neither the page gets the desktop-like
+    /// fallback width.
+    pub fn layout_width(&self, device_width: f32) -> f32 {
+        match (self.width, self.device_width, self.initial_scale) {
+            (Some(width), _, _) => width,
+            (None, true, _) => device_width,
+            (None, false, Some(scale)) => device_width / scale,
+            (None, false, None) => FALLBACK_LAYOUT_WIDTH,
+        }
+    }
+}
+
+/// The size a page is laid out at, in CSS pixels, and how far it is zoomed
+/// to fill a viewport
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutViewport {
+    pub width: f32,
+    pub height: f32,
+    /// Screen pixels per layout pixel: below 1 when zoomed out
+    pub scale: f32,
+}
+
+impl LayoutViewport {
+    /// The layout viewport of a page shown in `viewport`: the viewport
+    /// itself on desktops, and sized by the page's `meta` on mobile devices
+    pub fn new(viewport: Viewport, mobile: bool, meta: Option<ViewportMeta>) -> LayoutViewport {
+        let (device_width, device_height) = (viewport.width as f32, viewport.height as f32);
+        let width = if mobile { meta.unwrap_or_default().layout_width(device_width) } else { device_width };
+        let scale = device_width / width;
+        LayoutViewport { width, height: device_height / scale, scale }
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_presets() {
+        assert_eq!(Device::parse("iPhone"), Ok(Device::IPhone));
+        assert_eq!(Device::parse("watch"), Err("Unknown device 'watch', expected one of: desktop, iphone, android".to_string()));
+
+        let desktop = Device::Desktop;
+        assert_eq!((desktop.viewport(), desktop.device_pixel_ratio()), (DEFAULT_VIEWPORT, 1.0));
+        assert_eq!(desktop.navigator(), Navigator::default());
+        assert!(!desktop.navigator().is_touch());
+
+        assert!(Device::IPhone.is_mobile() && !desktop.is_mobile());
+
+        let android = Device::Android.navigator();
+        assert!(android.user_agent.contains("Android 13") && android.is_touch());
+        assert_eq!(android.language(), "en-US");
+        assert_eq!(Navigator::default().with_languages(&["de-CH", "de"]).language(), "de-CH");
+    }
+
+    #[test]
+    fn test_viewport_meta_sets_the_layout_width() {
+        let phone = Device::IPhone.viewport();
+        let layout = |content: Option<&str>| LayoutViewport::new(phone, true, content.map(ViewportMeta::parse));
+
+        // Pages without the meta are laid out wide and zoomed out to fit
+        let fallback = layout(None);
+        assert_eq!(fallback.width, FALLBACK_LAYOUT_WIDTH);
+        assert_eq!((fallback.scale, fallback.height), (390.0 / 980.0, 844.0 * 980.0 / 390.0));
+
+        // device-width matches the screen; explicit widths and scales win
+        assert_eq!(layout(Some("width=device-width, initial-scale=1")), LayoutViewport { width: 390.0, height: 844.0, scale: 1.0 });
+        assert_eq!(layout(Some("width=600; user-scalable=no")).width, 600.0);
+        assert_eq!(layout(Some("initial-scale=2")).width, 195.0);
+        assert_eq!(layout(Some("width=bogus")).width, FALLBACK_LAYOUT_WIDTH);
+
+        // Desktops ignore it
+        assert_eq!(LayoutViewport::new(DEFAULT_VIEWPORT, false, Some(ViewportMeta::parse("width=600"))).width, 1280.0);
+    }
+}
@@ -0,0 +1,457 @@
+//! Display List
+//! The paint commands a laid-out document produces, in paint order
+//!
+//! `render::build_display_list` records what to draw and
+//! `render::rasterize` plays it onto a raqote target. Coordinates are CSS
+//! pixels in page space, and comparing two frames' lists gives the areas
+//! to repaint.
+
+use std::rc::Rc;
+
+use raqote::{PathOp, Transform};
+
+use crate::css::BoxShadow;
+use crate::emoji::is_emoji_modifier;
+use crate::fonts::WebFont;
+use crate::geometry::{EdgeSizes, Rect};
+use crate::images::DecodedImage;
+use crate::text::NO_BREAK_SPACE;
+use crate::transform::transform_rect;
+
+/// Glyph box, spacing and color of a run of text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphStyle {
+    pub width: f32,
+    pub height: f32,
+    pub letter_spacing: f32,
+    /// Extra advance after spaces, on top of `letter_spacing`
+    pub word_spacing: f32,
+    pub color: u32,
+    /// Drawn twice, a pixel apart, for a heavier weight
+    pub bold: bool,
+}
+
+impl GlyphStyle {
+    /// Horizontal distance from one glyph to the next
+    ///
+    /// Emoji modifiers and joiners draw onto the emoji before them, so they
+    /// take no space.
+    pub fn advance(&self, ch: char) -> f32 {
+        if is_emoji_modifier(ch) {
+            return 0.0;
+        }
+        let word_spacing = if ch == ' ' || ch == NO_BREAK_SPACE { self.word_spacing } else { 0.0 };
+        self.width + self.letter_spacing + word_spacing
+    }
+}
+
+/// One drawing operation
+///
+/// Rounded corners are ordered top-left, top-right, bottom-right,
+/// bottom-left; all-zero radii mean square corners.
+#[derive(Debug, Clone)]
+pub enum PaintCommand {
+    /// A filled rectangle, rounded when any radius is non-zero
+    Rect { rect: Rect, radii: [f32; 4], color: u32 },
+    /// The ring between a box's outer edge and the edge `width` inside it
+    Border { rect: Rect, width: f32, radii: [f32; 4], color: u32 },
+    /// An outer `box-shadow` cast by the border box `rect`
+    BoxShadow { rect: Rect, radii: [f32; 4], shadow: BoxShadow },
+    /// One line of text, its glyph boxes starting at (`x`, `y`), drawn
+    /// in `font` where it has the glyphs
+    Text { x: f32, y: f32, text: String, glyph: GlyphStyle, font: Option<Rc<WebFont>> },
+    /// An image scaled to fill `rect`
+    Image { rect: Rect, image: Rc<DecodedImage> },
+    /// A filled outline, e.g. an SVG shape
+    FillPath { path: raqote::Path, color: u32 },
+    /// A stroked outline
+    StrokePath { path: raqote::Path, width: f32, color: u32 },
+    /// Clip the following commands to `rect` until the matching `PopClip`
+    PushClip { rect: Rect, radii: [f32; 4] },
+    PopClip,
+    /// Map the following commands through `transform`, after any
+    /// transforms already pushed, until the matching `PopTransform`
+    PushTransform { transform: Transform },
+    PopTransform,
+}
+
+/// Anti-aliasing may touch a pixel beyond a shape's exact edge
+const ANTI_ALIAS_MARGIN: f32 = 1.0;
+
+/// raqote's default miter limit; a miter join reaches at most this many
+/// half stroke widths out
+const MITER_LIMIT: f32 = 10.0;
+
+/// Damage split into more rectangles than this is merged into one
+const MAX_DAMAGE_RECTS: usize = 16;
+
+impl PaintCommand {
+    /// The area this command may paint, before any pushed transforms, or
+    /// `None` for clip and transform markers
+    ///
+    /// Bounds are conservative: they include anti-aliased edges, blur and
+    /// stroke joins.
+    pub fn bounds(&self) -> Option<Rect> {
+        let bounds = match self {
+            PaintCommand::Rect { rect, .. } | PaintCommand::Border { rect, .. } | PaintCommand::Image { rect, .. } => *rect,
+            // An inset shadow stays inside its padding box
+            PaintCommand::BoxShadow { rect, shadow, .. } if shadow.inset => *rect,
+            PaintCommand::BoxShadow { rect, shadow, .. } => rect
+                .outset(EdgeSizes::uniform(shadow.spread_radius + shadow.blur_radius.ceil()))
+                .translate(shadow.offset_x, shadow.offset_y),
+            PaintCommand::Text { x, y, text, glyph, .. } => {
+                let width: f32 = text.chars().map(|ch| glyph.advance(ch)).sum();
+                Rect::new(*x, *y, width.max(glyph.width), glyph.height)
+            }
+            PaintCommand::FillPath { path, .. } => path_bounds(path)?,
+            PaintCommand::StrokePath { path, width, .. } => {
+                path_bounds(path)?.outset(EdgeSizes::uniform(width * MITER_LIMIT / 2.0))
+            }
+            PaintCommand::PushClip { .. }
+            | PaintCommand::PopClip
+            | PaintCommand::PushTransform { .. }
+            | PaintCommand::PopTransform => return None,
+        };
+        Some(bounds.outset(EdgeSizes::uniform(ANTI_ALIAS_MARGIN)))
+    }
+}
+
+/// The box around every point of a path; curves stay inside the hull of
+/// their control points
+fn path_bounds(path: &raqote::Path) -> Option<Rect> {
+    let mut points = path.ops.iter().flat_map(|op| match *op {
+        PathOp::MoveTo(p) | PathOp::LineTo(p) => vec![p],
+        PathOp::QuadTo(c, p) => vec![c, p],
+        PathOp::CubicTo(c1, c2, p) => vec![c1, c2, p],
+        PathOp::Close => vec![],
+    });
+    let first = points.next()?;
+    let (mut min, mut max) = (first, first);
+    for p in points {
+        min = min.min(p);
+        max = max.max(p);
+    }
+    Some(Rect::new(min.x, min.y, max.x - min.x, max.y - min.y))
+}
+
+fn same_path(a: &raqote::Path, b: &raqote::Path) -> bool {
+    let same_op = |a: &PathOp, b: &PathOp| match (*a, *b) {
+        (PathOp::MoveTo(a), PathOp::MoveTo(b)) | (PathOp::LineTo(a), PathOp::LineTo(b)) => a == b,
+        (PathOp::QuadTo(a1, a2), PathOp::QuadTo(b1, b2)) => a1 == b1 && a2 == b2,
+        (PathOp::CubicTo(a1, a2, a3), PathOp::CubicTo(b1, b2, b3)) => a1 == b1 && a2 == b2 && a3 == b3,
+        (PathOp::Close, PathOp::Close) => true,
+        _ => false,
+    };
+    a.winding == b.winding && a.ops.len() == b.ops.len() && a.ops.iter().zip(&b.ops).all(|(a, b)| same_op(a, b))
+}
+
+impl PartialEq for PaintCommand {
+    fn eq(&self, other: &Self) -> bool {
+        use PaintCommand::*;
+        match (self, other) {
+            (Rect { rect: a, radii: ar, color: ac }, Rect { rect: b, radii: br, color: bc }) => {
+                a == b && ar == br && ac == bc
+            }
+            (
+                Border { rect: a, width: aw, radii: ar, color: ac },
+                Border { rect: b, width: bw, radii: br, color: bc },
+            ) => a == b && aw == bw && ar == br && ac == bc,
+            (BoxShadow { rect: a, radii: ar, shadow: ash }, BoxShadow { rect: b, radii: br, shadow: bsh }) => {
+                a == b && ar == br && ash == bsh
+            }
+            (Text { x: ax, y: ay, text: at, glyph: ag, font: af }, Text { x: bx, y: by, text: bt, glyph: bg, font: bf }) => {
+                ax == bx && ay == by && at == bt && ag == bg && af == bf
+            }
+            (Image { rect: a, image: ai }, Image { rect: b, image: bi }) => a == b && (Rc::ptr_eq(ai, bi) || ai == bi),
+            (FillPath { path: a, color: ac }, FillPath { path: b, color: bc }) => ac == bc && same_path(a, b),
+            (StrokePath { path: a, width: aw, color: ac }, StrokePath { path: b, width: bw, color: bc }) => {
+                aw == bw && ac == bc && same_path(a, b)
+            }
+            (PushClip { rect: a, radii: ar }, PushClip { rect: b, radii: br }) => a == b && ar == br,
+            (PopClip, PopClip) => true,
+            (PushTransform { transform: a }, PushTransform { transform: b }) => a == b,
+            (PopTransform, PopTransform) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A drawing command with the clip and transform it runs under
+#[derive(Debug, PartialEq)]
+struct Painted<'a> {
+    command: &'a PaintCommand,
+    /// Bounds of the clips in effect, in page coordinates; `None` when
+    /// unclipped
+    clip: Option<Rect>,
+    /// The pushed transforms combined; `None` when untransformed
+    transform: Option<Transform>,
+}
+
+impl Painted<'_> {
+    /// Pixels the command may change, if any
+    fn area(&self) -> Option<Rect> {
+        let bounds = self.command.bounds()?;
+        let bounds = match &self.transform {
+            Some(transform) => transform_rect(transform, bounds),
+            None => bounds,
+        };
+        match self.clip {
+            Some(clip) => bounds.intersection(&clip),
+            None => (!bounds.is_empty()).then_some(bounds),
+        }
+    }
+}
+
+/// Paint commands in the order they are drawn
+#[derive(Debug, Clone, Default)]
+pub struct DisplayList {
+    commands: Vec<PaintCommand>,
+}
+
+impl DisplayList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: PaintCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[PaintCommand] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PaintCommand> {
+        self.commands.iter()
+    }
+
+    /// Areas whose pixels may differ between a render of `previous` and a
+    /// render of this list
+    ///
+    /// Commands are matched in order; any command without a counterpart, or
+    /// drawn under a different clip, damages the area it paints. A pixel
+    /// outside every damaged area is touched by the same commands in the
+    /// same order in both lists, so it renders the same. Overlapping
+    /// rectangles are merged.
+    pub fn damage_since(&self, previous: &DisplayList) -> Vec<Rect> {
+        let before = previous.painted();
+        let after = self.painted();
+
+        // Unchanged commands before and after the edits need no comparison
+        let prefix = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+        let suffix = before[prefix..]
+            .iter()
+            .rev()
+            .zip(after[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let before = &before[prefix..before.len() - suffix];
+        let after = &after[prefix..after.len() - suffix];
+
+        // Greedily match the rest in order
+        let mut damage = Vec::new();
+        let mut cursor = 0;
+        for item in after {
+            match before[cursor..].iter().position(|old| old == item) {
+                Some(offset) => {
+                    damage.extend(before[cursor..cursor + offset].iter().filter_map(Painted::area));
+                    cursor += offset + 1;
+                }
+                None => damage.extend(item.area()),
+            }
+        }
+        damage.extend(before[cursor..].iter().filter_map(Painted::area));
+        merge_rects(damage)
+    }
+
+    /// Drawing commands paired with the clip bounds and transform they
+    /// run under
+    fn painted(&self) -> Vec<Painted<'_>> {
+        let mut clips: Vec<Option<Rect>> = Vec::new();
+        let mut transforms: Vec<Transform> = Vec::new();
+        let mut painted = Vec::new();
+        for command in &self.commands {
+            let clip = clips.last().copied().flatten();
+            let transform = transforms.last().copied();
+            match command {
+                PaintCommand::PushClip { rect, .. } => {
+                    // A transformed clip is bounded by its transformed box
+                    let rect = transform.map_or(*rect, |transform| transform_rect(&transform, *rect));
+                    // Nested clips intersect; an empty one hides everything
+                    let nested = match clip {
+                        Some(clip) => clip.intersection(&rect).unwrap_or_default(),
+                        None => rect,
+                    };
+                    clips.push(Some(nested));
+                }
+                PaintCommand::PopClip => {
+                    clips.pop();
+                }
+                PaintCommand::PushTransform { transform: own } => {
+                    transforms.push(transform.map_or(*own, |outer| own.then(&outer)));
+                }
+                PaintCommand::PopTransform => {
+                    transforms.pop();
+                }
+                _ => painted.push(Painted { command, clip, transform }),
+            }
+        }
+        painted
+    }
+}
+
+/// Merge overlapping rectangles until none overlap, falling back to their
+/// union when there are many
+pub fn merge_rects(rects: Vec<Rect>) -> Vec<Rect> {
+    let mut merged: Vec<Rect> = Vec::new();
+    for mut rect in rects.into_iter().filter(|rect| !rect.is_empty()) {
+        // Absorb every merged rectangle the growing one overlaps
+        while let Some(i) = merged.iter().position(|other| other.intersects(&rect)) {
+            rect = rect.union(&merged.swap_remove(i));
+        }
+        merged.push(rect);
+    }
+    if merged.len() > MAX_DAMAGE_RECTS {
+        let union = merged.iter().fold(Rect::default(), |union, rect| union.union(rect));
+        return vec![union];
+    }
+    merged
+}
+
+impl<'a> IntoIterator for &'a DisplayList {
+    type Item = &'a PaintCommand;
+    type IntoIter = std::slice::Iter<'a, PaintCommand>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glyph_advance_includes_spacing() {
+        let glyph = GlyphStyle { width: 14.0, height: 22.0, letter_spacing: 2.0, word_spacing: 5.0, color: 0xff000000, bold: false };
+
+        assert_eq!(glyph.advance('a'), 16.0);
+        assert_eq!(glyph.advance(' '), 21.0);
+        assert_eq!(glyph.advance(NO_BREAK_SPACE), 21.0);
+    }
+
+    #[test]
+    fn test_display_list_keeps_paint_order() {
+        let mut list = DisplayList::new();
+        assert!(list.is_empty());
+
+        list.push(PaintCommand::PushClip { rect: Rect::new(0.0, 0.0, 10.0, 10.0), radii: [2.0; 4] });
+        list.push(PaintCommand::Rect { rect: Rect::new(0.0, 0.0, 5.0, 5.0), radii: [0.0; 4], color: 0xffff0000 });
+        list.push(PaintCommand::PopClip);
+
+        assert_eq!(list.len(), 3);
+        assert!(matches!(list.commands()[0], PaintCommand::PushClip { .. }));
+        assert!(matches!((&list).into_iter().last(), Some(PaintCommand::PopClip)));
+    }
+
+    fn boxes(colors: &[u32]) -> DisplayList {
+        let mut list = DisplayList::new();
+        for (i, color) in colors.iter().enumerate() {
+            let rect = Rect::new(i as f32 * 20.0, 0.0, 10.0, 10.0);
+            list.push(PaintCommand::Rect { rect, radii: [0.0; 4], color: *color });
+        }
+        list
+    }
+
+    #[test]
+    fn test_damage_covers_only_changed_commands() {
+        let before = boxes(&[0xffff0000, 0xff00ff00, 0xff0000ff]);
+        let after = boxes(&[0xffff0000, 0xff000000, 0xff0000ff]);
+
+        // The middle box plus its anti-aliasing margin
+        assert_eq!(after.damage_since(&before), vec![Rect::new(19.0, -1.0, 12.0, 12.0)]);
+        assert!(after.damage_since(&after.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_damage_of_added_and_removed_commands() {
+        let before = boxes(&[0xffff0000, 0xff00ff00]);
+        let after = boxes(&[0xffff0000]);
+        assert_eq!(after.damage_since(&before), vec![Rect::new(19.0, -1.0, 12.0, 12.0)]);
+        assert_eq!(before.damage_since(&after), vec![Rect::new(19.0, -1.0, 12.0, 12.0)]);
+
+        // Everything painted is damage against an empty list
+        assert_eq!(after.damage_since(&DisplayList::new()), vec![Rect::new(-1.0, -1.0, 12.0, 12.0)]);
+    }
+
+    #[test]
+    fn test_damage_is_clipped() {
+        let clipped = |color| {
+            let mut list = DisplayList::new();
+            list.push(PaintCommand::PushClip { rect: Rect::new(0.0, 0.0, 5.0, 5.0), radii: [0.0; 4] });
+            list.push(PaintCommand::Rect { rect: Rect::new(0.0, 0.0, 50.0, 50.0), radii: [0.0; 4], color });
+            list.push(PaintCommand::PopClip);
+            list
+        };
+
+        assert_eq!(clipped(0xff000000).damage_since(&clipped(0xffffffff)), vec![Rect::new(0.0, 0.0, 5.0, 5.0)]);
+    }
+
+    #[test]
+    fn test_damage_is_transformed() {
+        let moved = |dx: f32| {
+            let mut list = DisplayList::new();
+            list.push(PaintCommand::PushTransform { transform: Transform::translation(dx, 0.0) });
+            list.push(PaintCommand::PushClip { rect: Rect::new(0.0, 0.0, 5.0, 5.0), radii: [0.0; 4] });
+            list.push(PaintCommand::Rect { rect: Rect::new(0.0, 0.0, 50.0, 50.0), radii: [0.0; 4], color: 0xff000000 });
+            list.push(PaintCommand::PopClip);
+            list.push(PaintCommand::PopTransform);
+            list
+        };
+
+        // The clipped box at its old and new places
+        assert_eq!(moved(100.0).damage_since(&moved(10.0)), vec![Rect::new(100.0, 0.0, 5.0, 5.0), Rect::new(10.0, 0.0, 5.0, 5.0)]);
+    }
+
+    #[test]
+    fn test_path_equality_and_bounds() {
+        let path = |x| {
+            let mut pb = raqote::PathBuilder::new();
+            pb.move_to(x, 0.0);
+            pb.cubic_to(x, 10.0, x + 10.0, 20.0, x + 10.0, 0.0);
+            pb.close();
+            PaintCommand::FillPath { path: pb.finish(), color: 0xff000000 }
+        };
+
+        assert_eq!(path(0.0), path(0.0));
+        assert_ne!(path(0.0), path(1.0));
+        assert_eq!(path(0.0).bounds(), Some(Rect::new(-1.0, -1.0, 12.0, 22.0)));
+        assert_eq!(PaintCommand::PopClip.bounds(), None);
+    }
+
+    #[test]
+    fn test_merge_rects_joins_overlaps() {
+        let merged = merge_rects(vec![
+            Rect::new(0.0, 0.0, 10.0, 10.0),
+            Rect::new(50.0, 0.0, 10.0, 10.0),
+            Rect::new(5.0, 5.0, 10.0, 10.0),
+            Rect::new(0.0, 0.0, 0.0, 0.0),
+        ]);
+        assert_eq!(merged, vec![Rect::new(50.0, 0.0, 10.0, 10.0), Rect::new(0.0, 0.0, 15.0, 15.0)]);
+
+        let scattered: Vec<Rect> = (0..20).map(|i| Rect::new(i as f32 * 20.0, 0.0, 10.0, 10.0)).collect();
+        assert_eq!(merge_rects(scattered), vec![Rect::new(0.0, 0.0, 390.0, 10.0)]);
+    }
+}
@@ -1,5 +1,16 @@
-use rquickjs::Function;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU32, Ordering};
+use crate::atom::Atom;
+use crate::css::ComputedStyle;
+use crate::files::InputFile;
+use crate::forms::FormState;
+use crate::geometry::{EdgeSizes, Point, Rect};
+use crate::hit_test;
+use crate::images::DecodedImage;
+use crate::inline::TextFragment;
+use crate::lists::ListMarker;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum NodeType {
@@ -14,22 +25,50 @@ pub enum NodeData {
     Text(String),
 }
 
+/// A node's tree position and content; boxes, listeners, shadow roots,
+/// form state, hover and animated values live in side tables on the
+/// `Document`
 #[derive(Debug, PartialEq, Clone)]
 pub struct Node {
     pub node_type: NodeType,
     pub parent: Option<usize>,
     pub children: Vec<usize>,
     pub data: Option<NodeData>,
-    pub shadow_root: Option<ShadowRoot>,
-    pub event_listeners: std::collections::HashMap<String, Vec<usize>>,
-    pub js_event_listeners: std::collections::HashMap<String, Function<'static>>,
-    pub layout: Option<Layout>,
 }
 
+impl Node {
+    fn new(node_type: NodeType, data: Option<NodeData>) -> Self {
+        Node { node_type, parent: None, children: Vec::new(), data }
+    }
+}
+
+/// An element's tag and attributes; attributes are kept sorted by name, so
+/// anything listing them does so in the same order on every run
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ElementData {
-    pub tag_name: String,
-    pub attributes: HashMap<String, String>,
+    pub tag_name: Atom,
+    pub attributes: BTreeMap<Atom, String>,
+}
+
+/// A listener added to a node, as `Document::listeners` lists it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ListenerInfo {
+    /// Unique within the document; `remove_listener` takes it
+    pub id: usize,
+    pub event_type: String,
+    pub options: ListenerOptions,
+}
+
+/// The options `addEventListener` takes
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ListenerOptions {
+    /// Called in the capture phase, on the way down to the target, rather
+    /// than while the event bubbles
+    pub capture: bool,
+    /// Removed before it is first called
+    pub once: bool,
+    /// Promised not to call `preventDefault`, which is ignored if it does
+    pub passive: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -46,27 +85,43 @@ pub enum ShadowRootMode {
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Layout {
-    pub x: f32,
-    pub y: f32,
-    pub width: f32,
-    pub height: f32,
+    /// The border box: the element's outer edge, excluding margins
+    pub rect: Rect,
     pub content_width: f32,
     pub content_height: f32,
-    pub padding_top: f32,
-    pub padding_right: f32,
-    pub padding_bottom: f32,
-    pub padding_left: f32,
-    pub margin_top: f32,
-    pub margin_right: f32,
-    pub margin_bottom: f32,
-    pub margin_left: f32,
-    pub border_width: f32,
+    pub padding: EdgeSizes,
+    pub margin: EdgeSizes,
+    pub border: EdgeSizes,
     pub font_size: f32,
+    /// Distance from the top of the box to its first baseline
+    pub baseline: f32,
     pub display: Display,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl Layout {
+    pub fn border_box(&self) -> Rect {
+        self.rect
+    }
+
+    /// The padding box: inside the border
+    pub fn padding_box(&self) -> Rect {
+        self.rect.inset(self.border)
+    }
+
+    /// The content box: inside the border and padding
+    pub fn content_box(&self) -> Rect {
+        self.padding_box().inset(self.padding)
+    }
+
+    /// The margin box: the border box grown by the margins
+    pub fn margin_box(&self) -> Rect {
+        self.rect.outset(self.margin)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub enum Display {
+    #[default]
     Block,
     Inline,
     InlineBlock,
@@ -75,80 +130,431 @@ pub enum Display {
     None,
 }
 
-impl Default for Display {
-    fn default() -> Self {
-        Display::Block
-    }
-}
-
-#[derive(Debug)]
+/// The node arena plus side tables keyed by node index
+///
+/// Every node has a slot in `layouts`; the other tables only hold the few
+/// nodes that have a shadow root, listeners, animated values, a list marker,
+/// line fragments, a frame, changed form state or the pointer over them, so
+/// the arena only pays for them where they are used. The tables are private: nodes are only added
+/// through `Document`, which gives each its layout slot, and the tables
+/// are read and written through accessors, so they stay in step with
+/// `nodes`.
+#[derive(Debug, Clone)]
 pub struct Document {
     pub nodes: Vec<Node>,
     pub root: usize,
+    /// Boxes from the last layout, parallel to `nodes`
+    layouts: Vec<Option<Layout>>,
+    shadow_roots: HashMap<usize, ShadowRoot>,
+    /// The listeners added to each node, in the order they were added
+    event_listeners: HashMap<usize, Vec<ListenerInfo>>,
+    /// Id of the next listener added
+    next_listener_id: usize,
+    /// Values running transitions and animations give properties,
+    /// overriding the stylesheet
+    animated_styles: HashMap<usize, HashMap<String, String>>,
+    /// Marker boxes of list items from the last layout
+    list_markers: HashMap<usize, ListMarker>,
+    /// Text nodes' lines from the last layout
+    text_fragments: HashMap<usize, Vec<TextFragment>>,
+    /// Renders of the frames iframes show, as of the last paint; see
+    /// `frames`
+    frame_images: HashMap<usize, DecodedImage>,
+    /// Form controls the user or a script changed
+    form_states: HashMap<usize, FormState>,
+    /// The element under the pointer and its ancestors, which match
+    /// `:hover`
+    hovered: HashSet<usize>,
+    /// Each node's index among its parent's children, parallel to
+    /// `nodes`; kept by `append_child`, `insert_child` and `remove_child`
+    /// so sibling combinators need not search the parent
+    child_positions: Vec<usize>,
+    /// Nodes `remove_node` discarded, with their descendants
+    removed: HashSet<usize>,
+    /// Tells this document's `NodeId`s from those of documents it replaced
+    epoch: u32,
+}
+
+/// Epochs given out to documents so far; see `NodeId`
+static EPOCHS: AtomicU32 = AtomicU32::new(0);
+
+/// A node index tied to the document it was taken from
+///
+/// Indices are only meaningful in their own document: once a page loads
+/// another, the same index names an unrelated node. A `NodeId` resolves
+/// only in the document it came from, and only while its node has not
+/// been removed; see `Document::resolve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    epoch: u32,
+}
+
+impl NodeId {
+    /// The id a script passed back as its parts, e.g. an `Element`
+    /// wrapper's node and epoch; see `handles`
+    pub(crate) fn from_parts(index: usize, epoch: u32) -> Self {
+        NodeId { index, epoch }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// Why an index or `NodeId` does not name a live node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeError {
+    /// No node has this index
+    Missing(usize),
+    /// The node was removed from its document for good
+    Removed(usize),
+    /// The id was taken from a document that has since been replaced
+    Replaced(NodeId),
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeError::Missing(idx) => write!(f, "Node {} does not exist", idx),
+            NodeError::Removed(idx) => write!(f, "Node {} was removed from the document", idx),
+            NodeError::Replaced(id) => write!(f, "Node {} belongs to a document that has been replaced", id.index),
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Document {
     pub fn new() -> Self {
-        let document_node = Node {
-            node_type: NodeType::Document,
-            parent: None,
-            children: Vec::new(),
-            data: None,
-            shadow_root: None,
-            event_listeners: HashMap::new(),
-            js_event_listeners: HashMap::new(),
-            layout: None,
-        };
-        let mut nodes = Vec::new();
-        nodes.push(document_node);
-
         Document {
-            nodes,
+            nodes: vec![Node::new(NodeType::Document, None)],
             root: 0,
+            layouts: vec![None],
+            shadow_roots: HashMap::new(),
+            event_listeners: HashMap::new(),
+            next_listener_id: 0,
+            animated_styles: HashMap::new(),
+            list_markers: HashMap::new(),
+            text_fragments: HashMap::new(),
+            frame_images: HashMap::new(),
+            form_states: HashMap::new(),
+            hovered: HashSet::new(),
+            child_positions: vec![0],
+            removed: HashSet::new(),
+            epoch: EPOCHS.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Add a detached node, with an empty layout slot
+    fn push_node(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.layouts.push(None);
+        self.child_positions.push(0);
+        self.nodes.len() - 1
+    }
+
+    /// `idx`'s box from the last layout
+    pub fn layout(&self, idx: usize) -> Option<&Layout> {
+        self.layouts.get(idx)?.as_ref()
+    }
+
+    pub fn layout_mut(&mut self, idx: usize) -> Option<&mut Layout> {
+        self.layouts.get_mut(idx)?.as_mut()
+    }
+
+    /// Every node's box from the last layout, by node index
+    pub fn layouts(&self) -> &[Option<Layout>] {
+        &self.layouts
+    }
+
+    /// Give `idx` the box `layout`, or none; unknown indices are ignored
+    pub fn set_layout(&mut self, idx: usize, layout: Option<Layout>) {
+        if let Some(slot) = self.layouts.get_mut(idx) {
+            *slot = layout;
+        }
+    }
+
+    /// The marker box of list item `idx` from the last layout
+    pub fn list_marker(&self, idx: usize) -> Option<&ListMarker> {
+        self.list_markers.get(&idx)
+    }
+
+    pub(crate) fn set_list_marker(&mut self, idx: usize, marker: Option<ListMarker>) {
+        match marker {
+            Some(marker) if idx < self.nodes.len() => {
+                self.list_markers.insert(idx, marker);
+            }
+            _ => {
+                self.list_markers.remove(&idx);
+            }
+        }
+    }
+
+    /// The lines text node `idx` was broken into by the last layout
+    pub fn text_fragments(&self, idx: usize) -> Option<&[TextFragment]> {
+        self.text_fragments.get(&idx).map(Vec::as_slice)
+    }
+
+    pub(crate) fn set_text_fragments(&mut self, idx: usize, fragments: Vec<TextFragment>) {
+        if fragments.is_empty() || idx >= self.nodes.len() {
+            self.text_fragments.remove(&idx);
+        } else {
+            self.text_fragments.insert(idx, fragments);
+        }
+    }
+
+    /// The last render of the frame iframe `idx` shows
+    pub fn frame_image(&self, idx: usize) -> Option<&DecodedImage> {
+        self.frame_images.get(&idx)
+    }
+
+    pub(crate) fn set_frame_image(&mut self, idx: usize, image: DecodedImage) {
+        if idx < self.nodes.len() {
+            self.frame_images.insert(idx, image);
+        }
+    }
+
+    pub(crate) fn clear_frame_images(&mut self) {
+        self.frame_images.clear();
+    }
+
+    /// What the user or scripts did to form control `idx`; the default for
+    /// controls still as their markup says
+    pub fn form_state(&self, idx: usize) -> &FormState {
+        static UNCHANGED: FormState = FormState { checked: None, selected: None, value: None, files: Vec::new() };
+        self.form_states.get(&idx).unwrap_or(&UNCHANGED)
+    }
+
+    /// `idx`'s form state, to change; panics past the node table, as
+    /// indexing `nodes` does
+    pub fn form_state_mut(&mut self, idx: usize) -> &mut FormState {
+        assert!(idx < self.nodes.len(), "no node {}", idx);
+        self.form_states.entry(idx).or_default()
+    }
+
+    /// Whether `idx` is under the pointer or an ancestor of the element
+    /// that is; matches `:hover`
+    pub fn is_hovered(&self, idx: usize) -> bool {
+        self.hovered.contains(&idx)
+    }
+
+    /// Put the pointer over exactly `nodes`; unknown indices are ignored
+    pub(crate) fn set_hovered(&mut self, nodes: impl IntoIterator<Item = usize>) {
+        let count = self.nodes.len();
+        self.hovered = nodes.into_iter().filter(|&idx| idx < count).collect();
+    }
+
+    pub fn shadow_root(&self, idx: usize) -> Option<&ShadowRoot> {
+        self.shadow_roots.get(&idx)
+    }
+
+    /// The values animations give `idx`'s properties, if any
+    pub fn animated_style(&self, idx: usize) -> Option<&HashMap<String, String>> {
+        self.animated_styles.get(&idx)
+    }
+
+    /// Set the values animations give `idx`'s properties; none clears them
+    pub(crate) fn set_animated_style(&mut self, idx: usize, values: HashMap<String, String>) {
+        if values.is_empty() || idx >= self.nodes.len() {
+            self.animated_styles.remove(&idx);
+        } else {
+            self.animated_styles.insert(idx, values);
         }
     }
 
     pub fn create_element(&mut self, tag_name: &str) -> usize {
         let element_data = ElementData {
-            tag_name: tag_name.to_string(),
-            attributes: HashMap::new(),
-        };
-        let node = Node {
-            node_type: NodeType::Element,
-            parent: None,
-            children: Vec::new(),
-            data: Some(NodeData::Element(element_data)),
-            shadow_root: None,
-            event_listeners: HashMap::new(),
-            js_event_listeners: HashMap::new(),
-            layout: None,
+            tag_name: Atom::new(tag_name),
+            attributes: BTreeMap::new(),
         };
-        let idx = self.nodes.len();
-        self.nodes.push(node);
-        idx
+        self.push_node(Node::new(NodeType::Element, Some(NodeData::Element(element_data))))
     }
 
     pub fn create_text_node(&mut self, text_content: &str) -> usize {
-        let node = Node {
-            node_type: NodeType::Text,
-            parent: None,
-            children: Vec::new(),
-            data: Some(NodeData::Text(text_content.to_string())),
-            shadow_root: None,
-            event_listeners: HashMap::new(),
-            js_event_listeners: HashMap::new(),
-            layout: None,
-        };
-        let idx = self.nodes.len();
-        self.nodes.push(node);
-        idx
+        self.push_node(Node::new(NodeType::Text, Some(NodeData::Text(text_content.to_string()))))
     }
 
     pub fn append_child(&mut self, parent_idx: usize, child_idx: usize) {
+        self.child_positions[child_idx] = self.nodes[parent_idx].children.len();
         self.nodes[parent_idx].children.push(child_idx);
         self.nodes[child_idx].parent = Some(parent_idx);
     }
 
+    /// Insert `child_idx` as the `index`th child of `parent_idx`, taking it
+    /// from its old parent first
+    pub fn insert_child(&mut self, parent_idx: usize, index: usize, child_idx: usize) {
+        self.remove_child(child_idx);
+        let index = index.min(self.nodes[parent_idx].children.len());
+        self.nodes[parent_idx].children.insert(index, child_idx);
+        self.nodes[child_idx].parent = Some(parent_idx);
+        self.renumber_children(parent_idx, index);
+    }
+
+    /// Detach `child_idx` from its parent; the node stays in the arena
+    pub fn remove_child(&mut self, child_idx: usize) {
+        if let Some(parent_idx) = self.nodes[child_idx].parent.take() {
+            let children = &mut self.nodes[parent_idx].children;
+            let recorded = self.child_positions[child_idx];
+            let Some(position) = (children.get(recorded) == Some(&child_idx))
+                .then_some(recorded)
+                .or_else(|| children.iter().position(|&idx| idx == child_idx))
+            else {
+                return;
+            };
+            children.remove(position);
+            self.child_positions[child_idx] = 0;
+            self.renumber_children(parent_idx, position);
+        }
+    }
+
+    /// Record the positions of `parent_idx`'s children from `from` on
+    fn renumber_children(&mut self, parent_idx: usize, from: usize) {
+        for (position, &child) in self.nodes[parent_idx].children.iter().enumerate().skip(from) {
+            self.child_positions[child] = position;
+        }
+    }
+
+    /// `idx`'s index among its parent's children, or `None` when detached
+    pub fn child_index(&self, idx: usize) -> Option<usize> {
+        self.nodes.get(idx)?.parent.map(|_| self.child_positions[idx])
+    }
+
+    /// The element siblings before `idx`, nearest first
+    pub fn preceding_element_siblings(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        let siblings = self.sibling_slice(idx);
+        let position = self.child_index(idx).unwrap_or(0);
+        siblings[..position].iter().rev().copied().filter(|&sibling| self.nodes[sibling].node_type == NodeType::Element)
+    }
+
+    /// The element siblings after `idx`, in order
+    pub fn following_element_siblings(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        let siblings = self.sibling_slice(idx);
+        let position = self.child_index(idx).map_or(siblings.len(), |position| position + 1);
+        siblings[position..].iter().copied().filter(|&sibling| self.nodes[sibling].node_type == NodeType::Element)
+    }
+
+    /// The element just before `idx` among its siblings
+    pub fn previous_element_sibling(&self, idx: usize) -> Option<usize> {
+        self.preceding_element_siblings(idx).next()
+    }
+
+    /// The children of `idx`'s parent, or none when detached
+    fn sibling_slice(&self, idx: usize) -> &[usize] {
+        match self.nodes.get(idx).and_then(|node| node.parent) {
+            Some(parent) => &self.nodes[parent].children,
+            None => &[],
+        }
+    }
+
+    /// Detach `idx` and discard it with its descendants, as an edit that
+    /// deletes content does; their listeners, boxes and animated values
+    /// are dropped, and `check` and `resolve` report them removed
+    pub fn remove_node(&mut self, idx: usize) {
+        self.remove_child(idx);
+        let mut stack = vec![idx];
+        while let Some(current) = stack.pop() {
+            self.removed.insert(current);
+            self.event_listeners.remove(&current);
+            self.animated_styles.remove(&current);
+            self.hovered.remove(&current);
+            self.layouts[current] = None;
+            stack.extend(&self.nodes[current].children);
+        }
+    }
+
+    /// Whether `remove_node` discarded `idx`; detached nodes are not
+    /// removed, as they can be inserted again
+    pub fn is_removed(&self, idx: usize) -> bool {
+        self.removed.contains(&idx)
+    }
+
+    /// `idx` if it names a node that has not been removed
+    pub fn check(&self, idx: usize) -> Result<usize, NodeError> {
+        if idx >= self.nodes.len() {
+            Err(NodeError::Missing(idx))
+        } else if self.is_removed(idx) {
+            Err(NodeError::Removed(idx))
+        } else {
+            Ok(idx)
+        }
+    }
+
+    /// A handle on live node `idx` that stays tied to this document
+    pub fn node_id(&self, idx: usize) -> Result<NodeId, NodeError> {
+        self.check(idx).map(|index| NodeId { index, epoch: self.epoch })
+    }
+
+    /// The index `id` names, if it was taken from this document and its
+    /// node is still live
+    pub fn resolve(&self, id: NodeId) -> Result<usize, NodeError> {
+        if id.epoch != self.epoch {
+            return Err(NodeError::Replaced(id));
+        }
+        self.check(id.index)
+    }
+
+    /// Go back to `snapshot`, a clone of this document taken earlier, as
+    /// the test runner does between tests; listener ids handed out since
+    /// are not reused, so listeners recorded after the snapshot stay gone
+    pub fn restore(&mut self, snapshot: Document) {
+        let next_listener_id = self.next_listener_id;
+        *self = snapshot;
+        self.next_listener_id = next_listener_id;
+    }
+
+    /// Tells this document from others; see `NodeId`
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Copy `idx` as a new detached node, with copies of its descendants
+    /// when `deep`, as `cloneNode` does
+    ///
+    /// Element data, text and form state are copied. Shadow roots, event
+    /// listeners, layout and animation state are not. Fails for an index
+    /// that is not a live node; see `check`.
+    pub fn clone_node(&mut self, idx: usize, deep: bool) -> Result<usize, NodeError> {
+        let source = self.nodes[self.check(idx)?].clone();
+        let form_state = self.form_state(idx).clone();
+        let copy = self.push_copy(&source, &form_state);
+        if deep {
+            for child in source.children {
+                let child_copy = self.clone_node(child, true)?;
+                self.append_child(copy, child_copy);
+            }
+        }
+        Ok(copy)
+    }
+
+    /// Copy node `idx` of another document into this one, detached, as
+    /// `importNode` does; see `clone_node`
+    pub fn import_node(&mut self, source: &Document, idx: usize, deep: bool) -> Result<usize, NodeError> {
+        let node = &source.nodes[source.check(idx)?];
+        let copy = self.push_copy(node, source.form_state(idx));
+        if deep {
+            for &child in &node.children {
+                let child_copy = self.import_node(source, child, true)?;
+                self.append_child(copy, child_copy);
+            }
+        }
+        Ok(copy)
+    }
+
+    fn push_copy(&mut self, source: &Node, form_state: &FormState) -> usize {
+        let copy = self.push_node(Node::new(source.node_type.clone(), source.data.clone()));
+        if *form_state != FormState::default() {
+            self.form_states.insert(copy, form_state.clone());
+        }
+        copy
+    }
+
     pub fn get_node(&self, idx: usize) -> Option<&Node> {
         self.nodes.get(idx)
     }
@@ -160,7 +566,7 @@ impl Document {
     pub fn set_attribute(&mut self, element_idx: usize, name: &str, value: &str) {
         if let Some(node) = self.nodes.get_mut(element_idx) {
             if let Some(NodeData::Element(element_data)) = &mut node.data {
-                element_data.attributes.insert(name.to_string(), value.to_string());
+                element_data.attributes.insert(Atom::new(name), value.to_string());
             }
         }
     }
@@ -168,23 +574,43 @@ impl Document {
     pub fn get_attribute(&self, element_idx: usize, name: &str) -> Option<&String> {
         if let Some(node) = self.nodes.get(element_idx) {
             if let Some(NodeData::Element(element_data)) = &node.data {
-                return element_data.attributes.get(name);
+                return element_data.attributes.get(&Atom::lookup(name)?);
             }
         }
         None
     }
 
+    /// Concatenated text of all descendant text nodes, in tree order
+    pub fn text_content(&self, idx: usize) -> String {
+        let mut text = String::new();
+        let mut stack = vec![idx];
+        while let Some(current) = stack.pop() {
+            let Some(node) = self.nodes.get(current) else { continue };
+            if let Some(NodeData::Text(content)) = &node.data {
+                text.push_str(content);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        text
+    }
+
+    /// The topmost element at (`x`, `y`) in page coordinates, laid out and
+    /// styled with `styles`; see `hit_test::element_from_point`
+    pub fn element_from_point(&self, styles: &[ComputedStyle], x: f32, y: f32) -> Option<usize> {
+        hit_test::element_from_point(self, styles, Point::new(x, y))
+    }
+
     pub fn attach_shadow(&mut self, host_idx: usize, mode: ShadowRootMode) -> Result<usize, &'static str> {
-        if let Some(node) = self.nodes.get_mut(host_idx) {
+        if let Some(node) = self.nodes.get(host_idx) {
             if node.node_type == NodeType::Element {
-                if node.shadow_root.is_some() {
+                if self.shadow_roots.contains_key(&host_idx) {
                     return Err("Shadow root already exists for this host.");
                 }
                 let shadow_root = ShadowRoot {
                     mode,
                     children: Vec::new(),
                 };
-                node.shadow_root = Some(shadow_root);
+                self.shadow_roots.insert(host_idx, shadow_root);
                 Ok(host_idx)
             } else {
                 Err("Cannot attach shadow root to a non-element node.")
@@ -195,18 +621,46 @@ impl Document {
         }
     }
 
-    pub fn add_event_listener(&mut self, node_idx: usize, event_type: &str, listener_idx: usize) {
-        if let Some(node) = self.nodes.get_mut(node_idx) {
-            node.event_listeners.entry(event_type.to_string()).or_insert_with(Vec::new).push(listener_idx);
+    /// Record a listener for `event_type` on `node_idx`, returning its id;
+    /// the callback itself is kept by whoever calls it, such as `events`
+    pub fn add_event_listener(&mut self, node_idx: usize, event_type: &str, options: ListenerOptions) -> Option<usize> {
+        if node_idx >= self.nodes.len() {
+            return None;
         }
+        let id = self.next_listener_id;
+        self.next_listener_id += 1;
+        self.event_listeners.entry(node_idx).or_default().push(ListenerInfo { id, event_type: event_type.to_string(), options });
+        Some(id)
+    }
+
+    /// The listeners on `node_idx`, in the order they were added
+    pub fn listeners(&self, node_idx: usize) -> Vec<ListenerInfo> {
+        self.event_listeners.get(&node_idx).cloned().unwrap_or_default()
+    }
+
+    /// Forget listener `id`, returning whether it was there
+    pub fn remove_listener(&mut self, id: usize) -> bool {
+        let Some((&node, listeners)) = self.event_listeners.iter_mut().find(|(_, listeners)| listeners.iter().any(|l| l.id == id)) else {
+            return false;
+        };
+        listeners.retain(|listener| listener.id != id);
+        if listeners.is_empty() {
+            self.event_listeners.remove(&node);
+        }
+        true
+    }
+
+    /// Whether listener `id` is still registered on `node_idx`
+    pub fn has_listener(&self, node_idx: usize, id: usize) -> bool {
+        self.event_listeners.get(&node_idx).is_some_and(|listeners| listeners.iter().any(|listener| listener.id == id))
     }
 
     pub fn dispatch_event(&mut self, target_idx: usize, event_type: &str) {
         let mut current_idx = Some(target_idx);
         while let Some(idx) = current_idx {
             if let Some(node) = self.nodes.get(idx) {
-                if let Some(listeners) = node.event_listeners.get(event_type) {
-                    println!("Event '{}' dispatched on node index {}", event_type, idx);
+                if self.event_listeners.get(&idx).is_some_and(|listeners| listeners.iter().any(|l| l.event_type == event_type)) {
+                    tracing::debug!(event_type, node = idx, "Event dispatched");
                 }
                 current_idx = node.parent;
             } else {
@@ -214,4 +668,112 @@ impl Document {
             }
         }
     }
+
+    /// What the document holds and roughly how much memory it takes
+    pub fn stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats {
+            nodes: self.nodes.len(),
+            heap_bytes: self.nodes.capacity() * size_of::<Node>()
+                + self.layouts.capacity() * size_of::<Option<Layout>>()
+                + self.child_positions.capacity() * size_of::<usize>(),
+            ..DocumentStats::default()
+        };
+        for node in &self.nodes {
+            match &node.data {
+                Some(NodeData::Element(elem)) => {
+                    stats.elements += 1;
+                    *stats.tags.entry(elem.tag_name.to_string()).or_default() += 1;
+                    stats.attributes += elem.attributes.len();
+                    stats.heap_bytes += elem.attributes.len() * size_of::<(Atom, String)>()
+                        + elem.attributes.values().map(String::capacity).sum::<usize>();
+                }
+                Some(NodeData::Text(text)) => {
+                    stats.text_nodes += 1;
+                    stats.heap_bytes += text.capacity();
+                }
+                None => {}
+            }
+            stats.heap_bytes += node.children.capacity() * size_of::<usize>();
+        }
+        for root in self.shadow_roots.values() {
+            stats.shadow_roots += 1;
+            stats.heap_bytes += size_of::<(usize, ShadowRoot)>() + root.children.capacity() * size_of::<usize>();
+        }
+        for listeners in self.event_listeners.values() {
+            stats.listeners += listeners.len();
+            stats.heap_bytes += size_of::<(usize, Vec<ListenerInfo>)>()
+                + listeners.capacity() * size_of::<ListenerInfo>()
+                + listeners.iter().map(|listener| listener.event_type.capacity()).sum::<usize>();
+        }
+        for state in self.form_states.values() {
+            stats.heap_bytes += size_of::<(usize, FormState)>()
+                + state.value.as_ref().map_or(0, String::capacity)
+                + state.files.iter().map(|file| size_of::<InputFile>() + file.bytes.capacity()).sum::<usize>();
+        }
+        for animated in self.animated_styles.values() {
+            stats.heap_bytes += size_of::<(usize, HashMap<String, String>)>()
+                + animated.iter().map(|(name, value)| size_of::<(String, String)>() + name.capacity() + value.capacity()).sum::<usize>();
+        }
+        stats.laid_out = self.layouts.iter().flatten().count();
+
+        // Removed nodes stay in the arena; count those no longer reachable
+        let mut connected = vec![false; self.nodes.len()];
+        let mut stack = if self.nodes.is_empty() { Vec::new() } else { vec![self.root] };
+        while let Some(idx) = stack.pop() {
+            if std::mem::replace(&mut connected[idx], true) {
+                continue;
+            }
+            let node = &self.nodes[idx];
+            stack.extend(node.children.iter().chain(self.shadow_roots.get(&idx).iter().flat_map(|root| &root.children)));
+        }
+        stats.detached = connected.iter().filter(|&&c| !c).count();
+        stats
+    }
+}
+
+/// Counts and an estimated heap size for a document; see `Document::stats`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentStats {
+    /// Every node in the arena, detached ones included
+    pub nodes: usize,
+    pub elements: usize,
+    pub text_nodes: usize,
+    /// Nodes removed from the tree whose slots are still held
+    pub detached: usize,
+    /// Element counts by tag name
+    pub tags: BTreeMap<String, usize>,
+    pub attributes: usize,
+    /// Event listeners added with `addEventListener`
+    pub listeners: usize,
+    pub shadow_roots: usize,
+    /// Nodes with a layout box from the last layout
+    pub laid_out: usize,
+    /// Bytes the nodes and their strings, maps and vectors take; an
+    /// estimate, since allocator and hash-table overheads are not counted
+    pub heap_bytes: usize,
+    /// Glyphs rasterized and cached, and their bitmap bytes
+    pub glyph_cache: (usize, usize),
+}
+
+impl DocumentStats {
+    pub fn with_glyph_cache(mut self, glyphs: usize, bytes: usize) -> Self {
+        self.glyph_cache = (glyphs, bytes);
+        self
+    }
+}
+
+impl fmt::Display for DocumentStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Nodes:        {} ({} elements, {} text, {} detached)", self.nodes, self.elements, self.text_nodes, self.detached)?;
+        writeln!(f, "Attributes:   {}", self.attributes)?;
+        writeln!(f, "Listeners:    {}", self.listeners)?;
+        writeln!(f, "Shadow roots: {}", self.shadow_roots)?;
+        writeln!(f, "Laid out:     {}", self.laid_out)?;
+        writeln!(f, "Heap (est.):  {:.1} KiB", self.heap_bytes as f64 / 1024.0)?;
+        writeln!(f, "Glyph cache:  {} glyphs, {:.1} KiB", self.glyph_cache.0, self.glyph_cache.1 as f64 / 1024.0)?;
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        tags.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let tags: Vec<String> = tags.iter().map(|(tag, count)| format!("{} {}", tag, count)).collect();
+        writeln!(f, "Elements:     {}", tags.join(", "))
+    }
 }
@@ -0,0 +1,497 @@
+//! Editing
+//! `contenteditable` elements: what typing, deleting and formatting
+//! commands do to their text nodes, and `document.execCommand` for scripts
+//!
+//! The page's selection is the caret. Formatting commands wrap each
+//! selected piece of text in its own `<b>`, `<i>` or `<u>`, or remove the
+//! formatting when all of the selection already has it.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use rquickjs::function::Opt;
+use rquickjs::{Ctx, Function, Object};
+
+use crate::dom::{Document, NodeData};
+use crate::events;
+use crate::forms;
+use crate::selection::{self, compare_points, BoundaryPoint, PageSelection, Range};
+
+/// An edit the user makes by typing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit<'a> {
+    InsertText(&'a str),
+    /// Enter, in a `contenteditable` element
+    InsertParagraph,
+    /// Enter, in a textarea
+    InsertLineBreak,
+    /// Backspace
+    DeleteBackward,
+}
+
+impl Edit<'_> {
+    /// `InputEvent.inputType`
+    pub fn input_type(&self) -> &'static str {
+        match self {
+            Edit::InsertText(_) => "insertText",
+            Edit::InsertParagraph => "insertParagraph",
+            Edit::InsertLineBreak => "insertLineBreak",
+            Edit::DeleteBackward => "deleteContentBackward",
+        }
+    }
+
+    /// `InputEvent.data`
+    pub fn data(&self) -> Option<&str> {
+        match self {
+            Edit::InsertText(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `idx` has a `contenteditable` that makes it editable
+fn is_editable_value(document: &Document, idx: usize) -> Option<bool> {
+    let value = document.get_attribute(idx, "contenteditable")?.trim().to_ascii_lowercase();
+    Some(matches!(value.as_str(), "" | "true" | "plaintext-only"))
+}
+
+/// Whether `idx` is the root of an editable region: `contenteditable`, in
+/// a parent that is not editable itself
+pub fn is_editing_host(document: &Document, idx: usize) -> bool {
+    is_editable_value(document, idx) == Some(true) && document.nodes[idx].parent.and_then(|parent| editing_host(document, parent)).is_none()
+}
+
+/// The editing host holding `node`: its outermost editable ancestor, up to
+/// a `contenteditable="false"`
+pub fn editing_host(document: &Document, node: usize) -> Option<usize> {
+    let mut host = None;
+    for idx in events::event_path(document, node) {
+        match is_editable_value(document, idx) {
+            Some(true) => host = Some(idx),
+            Some(false) => break,
+            None if host.is_some() => break,
+            None => {}
+        }
+    }
+    host
+}
+
+/// `range` when it lies inside `host`
+pub fn range_in(document: &Document, host: usize, range: Range) -> Option<Range> {
+    let inside = |point: BoundaryPoint| events::event_path(document, point.node).contains(&host);
+    (inside(range.start) && inside(range.end)).then_some(range)
+}
+
+/// The caret position at the end of `host`'s text
+pub fn end_of(document: &Document, host: usize) -> BoundaryPoint {
+    let last_text = selection::tree_order(document, host).into_iter().rev().find(|&idx| is_text(document, idx));
+    match last_text {
+        Some(text) if document.nodes[host].children.last().is_some_and(|&last| events::event_path(document, text).contains(&last)) => {
+            BoundaryPoint::new(text, selection::node_length(document, text))
+        }
+        _ => BoundaryPoint::new(host, document.nodes[host].children.len()),
+    }
+}
+
+fn is_text(document: &Document, idx: usize) -> bool {
+    matches!(document.nodes[idx].data, Some(NodeData::Text(_)))
+}
+
+fn text_mut(document: &mut Document, idx: usize) -> &mut String {
+    match &mut document.nodes[idx].data {
+        Some(NodeData::Text(text)) => text,
+        _ => panic!("node {} is not a text node", idx),
+    }
+}
+
+/// Byte index of character `offset` of `text`
+fn byte_index(text: &str, offset: usize) -> usize {
+    text.char_indices().nth(offset).map_or(text.len(), |(index, _)| index)
+}
+
+/// Split text node `node` at character `offset`, returning the new node
+/// holding the text after it, which follows `node`
+pub fn split_text(document: &mut Document, node: usize, offset: usize) -> usize {
+    let text = text_mut(document, node);
+    let rest = text.split_off(byte_index(text, offset));
+    let after = document.create_text_node(&rest);
+    if let Some(parent) = document.nodes[node].parent {
+        document.insert_child(parent, selection::child_index(document, node) + 1, after);
+    }
+    after
+}
+
+/// Remove what `range` covers: the text it selects and the nodes wholly
+/// inside it, returning where the caret lands
+pub fn delete_contents(document: &mut Document, range: Range) -> BoundaryPoint {
+    let Range { start, end } = range;
+    if start == end {
+        return start;
+    }
+    if start.node == end.node && is_text(document, start.node) {
+        let text = text_mut(document, start.node);
+        let (from, to) = (byte_index(text, start.offset), byte_index(text, end.offset));
+        text.replace_range(from..to, "");
+        return start;
+    }
+    let common = selection::common_ancestor(document, start.node, end.node);
+    let contained = |document: &Document, node: usize| {
+        let Some(parent) = document.nodes[node].parent else { return false };
+        let index = selection::child_index(document, node);
+        compare_points(document, BoundaryPoint::new(parent, index), start) != Ordering::Less
+            && compare_points(document, BoundaryPoint::new(parent, index + 1), end) != Ordering::Greater
+    };
+    // Only the outermost contained nodes; their descendants go with them
+    let doomed: Vec<usize> = selection::tree_order(document, common)
+        .into_iter()
+        .filter(|&node| node != common && contained(document, node))
+        .filter(|&node| document.nodes[node].parent.is_none_or(|parent| parent == common || !contained(document, parent)))
+        .collect();
+    if is_text(document, start.node) {
+        let text = text_mut(document, start.node);
+        text.truncate(byte_index(text, start.offset));
+    }
+    if is_text(document, end.node) {
+        let text = text_mut(document, end.node);
+        text.replace_range(..byte_index(text, end.offset), "");
+    }
+    for node in doomed {
+        document.remove_node(node);
+    }
+    start
+}
+
+/// Insert `text` at `at`, into the text node there or before it when there
+/// is one, returning the point after the text
+pub fn insert_text(document: &mut Document, at: BoundaryPoint, text: &str) -> BoundaryPoint {
+    if is_text(document, at.node) {
+        let content = text_mut(document, at.node);
+        content.insert_str(byte_index(content, at.offset), text);
+        return BoundaryPoint::new(at.node, at.offset + text.chars().count());
+    }
+    let before = at.offset.checked_sub(1).and_then(|index| document.nodes[at.node].children.get(index).copied());
+    if let Some(before) = before.filter(|&before| is_text(document, before)) {
+        let length = selection::node_length(document, before);
+        return insert_text(document, BoundaryPoint::new(before, length), text);
+    }
+    let node = document.create_text_node(text);
+    document.insert_child(at.node, at.offset, node);
+    BoundaryPoint::new(node, text.chars().count())
+}
+
+/// Insert `node` at `at`, splitting a text node there, returning the point
+/// after it
+pub fn insert_node(document: &mut Document, at: BoundaryPoint, node: usize) -> BoundaryPoint {
+    let (parent, index) = if is_text(document, at.node) {
+        let Some(parent) = document.nodes[at.node].parent else { return at };
+        let index = selection::child_index(document, at.node);
+        match at.offset {
+            0 => (parent, index),
+            offset if offset >= selection::node_length(document, at.node) => (parent, index + 1),
+            offset => {
+                split_text(document, at.node, offset);
+                (parent, index + 1)
+            }
+        }
+    } else {
+        (at.node, at.offset)
+    };
+    document.insert_child(parent, index, node);
+    BoundaryPoint::new(parent, index + 1)
+}
+
+/// What Backspace at a collapsed `caret` deletes: the character or `<br>`
+/// before it inside `host`
+pub fn before_caret(document: &Document, host: usize, caret: BoundaryPoint) -> Option<Range> {
+    if is_text(document, caret.node) && caret.offset > 0 {
+        return Some(Range { start: BoundaryPoint::new(caret.node, caret.offset - 1), end: caret });
+    }
+    let after = |node: usize| match document.nodes[node].parent {
+        Some(parent) if !is_text(document, node) => BoundaryPoint::new(parent, selection::child_index(document, node) + 1),
+        _ => BoundaryPoint::new(node, selection::node_length(document, node)),
+    };
+    let previous = selection::tree_order(document, host).into_iter().rev().find(|&node| {
+        let deletable = match &document.nodes[node].data {
+            Some(NodeData::Text(text)) => !text.is_empty(),
+            _ => forms::tag_name(document, node) == Some("br"),
+        };
+        deletable && compare_points(document, after(node), caret) != Ordering::Greater
+    })?;
+    let end = after(previous);
+    let start = if is_text(document, previous) { BoundaryPoint::new(previous, end.offset - 1) } else { BoundaryPoint::new(end.node, end.offset - 1) };
+    Some(Range { start, end })
+}
+
+/// Apply `edit` to `range` inside `host`, returning the caret after it, or
+/// `None` when there was nothing to delete
+pub fn apply_edit(document: &mut Document, host: usize, range: Range, edit: Edit) -> Option<BoundaryPoint> {
+    match edit {
+        Edit::DeleteBackward if range.is_collapsed() => {
+            let range = before_caret(document, host, range.start)?;
+            Some(delete_contents(document, range))
+        }
+        Edit::DeleteBackward => Some(delete_contents(document, range)),
+        Edit::InsertText(text) => {
+            let caret = delete_contents(document, range);
+            Some(insert_text(document, caret, text))
+        }
+        Edit::InsertParagraph | Edit::InsertLineBreak => {
+            let caret = delete_contents(document, range);
+            let br = document.create_element("br");
+            Some(insert_node(document, caret, br))
+        }
+    }
+}
+
+// ============================================================================
+// FORMATTING
+// ============================================================================
+
+/// The text nodes `range` selects part of, with the selected characters
+fn text_pieces(document: &Document, range: Range) -> Vec<(usize, usize, usize)> {
+    let common = selection::common_ancestor(document, range.start.node, range.end.node);
+    selection::tree_order(document, common)
+        .into_iter()
+        .filter(|&idx| is_text(document, idx))
+        .filter_map(|idx| {
+            let length = selection::node_length(document, idx);
+            let from = if idx == range.start.node {
+                range.start.offset
+            } else if compare_points(document, BoundaryPoint::new(idx, 0), range.start) == Ordering::Less {
+                return None;
+            } else {
+                0
+            };
+            let to = if idx == range.end.node {
+                range.end.offset
+            } else if compare_points(document, BoundaryPoint::new(idx, length), range.end) == Ordering::Greater {
+                return None;
+            } else {
+                length
+            };
+            (to > from).then_some((idx, from, to))
+        })
+        .collect()
+}
+
+/// The nearest ancestor of `node` inside `host` whose tag is one of `tags`
+fn formatting_ancestor(document: &Document, host: usize, node: usize, tags: &[&str]) -> Option<usize> {
+    events::event_path(document, node)
+        .into_iter()
+        .take_while(|&idx| idx != host)
+        .find(|&idx| forms::tag_name(document, idx).is_some_and(|tag| tags.contains(&tag)))
+}
+
+/// Whether all of the text `range` selects inside `host` is formatted with
+/// one of `tags`, as `queryCommandState` reports it
+pub fn is_formatted(document: &Document, host: usize, range: Range, tags: &[&str]) -> bool {
+    let pieces = text_pieces(document, range);
+    let start_formatted = || formatting_ancestor(document, host, range.start.node, tags).is_some();
+    if pieces.is_empty() {
+        return start_formatted();
+    }
+    pieces.iter().all(|&(idx, _, _)| formatting_ancestor(document, host, idx, tags).is_some())
+}
+
+/// Toggle formatting over `range` inside `host`: wrap each selected piece
+/// of text in a new `tag`, or, when all of it is formatted with one of
+/// `tags`, unwrap those elements; returns the range over the same text
+pub fn toggle_format(document: &mut Document, host: usize, range: Range, tags: &[&str], tag: &str) -> Range {
+    let pieces = text_pieces(document, range);
+    let (Some(&(first, first_from, _)), Some(&(last, _, last_to))) = (pieces.first(), pieces.last()) else {
+        return range;
+    };
+    if is_formatted(document, host, range, tags) {
+        let mut wrappers: Vec<usize> = pieces.iter().filter_map(|&(idx, _, _)| formatting_ancestor(document, host, idx, tags)).collect();
+        wrappers.dedup();
+        for wrapper in wrappers {
+            let Some(parent) = document.nodes[wrapper].parent else { continue };
+            let index = selection::child_index(document, wrapper);
+            for (offset, child) in document.nodes[wrapper].children.clone().into_iter().enumerate() {
+                document.insert_child(parent, index + offset, child);
+            }
+            document.remove_node(wrapper);
+        }
+        return Range { start: BoundaryPoint::new(first, first_from), end: BoundaryPoint::new(last, last_to) };
+    }
+    let mut wrapped = Vec::new();
+    for (idx, from, to) in pieces {
+        if to < selection::node_length(document, idx) {
+            split_text(document, idx, to);
+        }
+        let piece = if from > 0 { split_text(document, idx, from) } else { idx };
+        let Some(parent) = document.nodes[piece].parent else { continue };
+        let wrapper = document.create_element(tag);
+        document.insert_child(parent, selection::child_index(document, piece), wrapper);
+        document.remove_child(piece);
+        document.append_child(wrapper, piece);
+        wrapped.push(piece);
+    }
+    let (first, last) = (wrapped[0], wrapped[wrapped.len() - 1]);
+    Range { start: BoundaryPoint::new(first, 0), end: BoundaryPoint::new(last, selection::node_length(document, last)) }
+}
+
+/// The tags a formatting command recognizes, and the one it creates
+fn format_tags(command: &str) -> Option<(&'static [&'static str], &'static str)> {
+    match command {
+        "bold" => Some((&["b", "strong"], "b")),
+        "italic" => Some((&["i", "em"], "i")),
+        "underline" => Some((&["u"], "u")),
+        _ => None,
+    }
+}
+
+/// Run an `execCommand` command on `range` inside `host`, returning the
+/// selection afterwards and the `inputType` of the change; `None` for
+/// unsupported commands and deletions with nothing to delete
+pub fn exec_command(document: &mut Document, host: usize, range: Range, command: &str, value: &str) -> Option<(Range, &'static str)> {
+    if let Some((tags, tag)) = format_tags(command) {
+        let input_type = match tag {
+            "b" => "formatBold",
+            "i" => "formatItalic",
+            _ => "formatUnderline",
+        };
+        return Some((toggle_format(document, host, range, tags, tag), input_type));
+    }
+    let edit = match command {
+        "inserttext" => Edit::InsertText(value),
+        "delete" => Edit::DeleteBackward,
+        "insertparagraph" => Edit::InsertParagraph,
+        "insertlinebreak" => Edit::InsertLineBreak,
+        "selectall" => return Some((Range::node_contents(document, host), "")),
+        _ => return None,
+    };
+    let caret = apply_edit(document, host, range, edit)?;
+    Some((Range { start: caret, end: caret }, edit.input_type()))
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// Dispatch the trusted `InputEvent` an edit of `host` fires
+pub fn dispatch_input_event<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    target: usize,
+    event_type: &str,
+    input_type: &str,
+    data: Option<&str>,
+) -> rquickjs::Result<bool> {
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", true)?;
+    init.set("cancelable", event_type == "beforeinput")?;
+    init.set("composed", true)?;
+    init.set("inputType", input_type)?;
+    if let Some(data) = data {
+        init.set("data", data)?;
+    }
+    let event = events::create_event(ctx, "InputEvent", event_type, init)?;
+    event.set("isTrusted", true)?;
+    events::dispatch_event(ctx, document, target, event)
+}
+
+/// Install `document.execCommand(command, showUI, value)` and
+/// `document.queryCommandState(command)` over the page's selection
+///
+/// Commands: `bold`, `italic`, `underline`, `insertText`, `delete`,
+/// `insertParagraph`, `insertLineBreak` and `selectAll`. They act when the
+/// selection is inside an editing host, firing `input` there.
+pub fn install_editing<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>, selection: PageSelection) -> rquickjs::Result<()> {
+    let Ok(document_obj) = ctx.globals().get::<_, Object>("document") else {
+        return Ok(());
+    };
+
+    let (doc, shared) = (document.clone(), selection.clone());
+    let exec_command = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, command: String, _show_ui: Opt<bool>, value: Opt<Option<String>>| -> rquickjs::Result<bool> {
+            let Some(range) = shared.get().range(&doc.borrow()) else { return Ok(false) };
+            let Some(host) = editing_host(&doc.borrow(), range.start.node) else { return Ok(false) };
+            let value = value.0.flatten().unwrap_or_default();
+            let applied = {
+                let mut document = doc.borrow_mut();
+                range_in(&document, host, range).and_then(|range| exec_command(&mut document, host, range, &command.to_ascii_lowercase(), &value))
+            };
+            let Some((range, input_type)) = applied else { return Ok(false) };
+            shared.set(range.start, range.end);
+            if !input_type.is_empty() {
+                let data = (input_type == "insertText").then_some(value.as_str());
+                dispatch_input_event(&ctx, &doc, host, "input", input_type, data)?;
+            }
+            Ok(true)
+        },
+    )?;
+    document_obj.set("execCommand", exec_command)?;
+
+    let query_command_state = Function::new(ctx.clone(), move |command: String| {
+        let document = document.borrow();
+        let Some((tags, _)) = format_tags(&command.to_ascii_lowercase()) else { return false };
+        let Some(range) = selection.get().range(&document) else { return false };
+        editing_host(&document, range.start.node).is_some_and(|host| is_formatted(&document, host, range, tags))
+    })?;
+    document_obj.set("queryCommandState", query_command_state)?;
+    Ok(())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use crate::serialize;
+
+    fn editor(html: &str) -> (Document, usize) {
+        let document = parse_html(&format!("<html><body><div contenteditable=\"\">{}</div></body></html>", html));
+        let host = (0..document.nodes.len()).find(|&i| is_editing_host(&document, i)).unwrap();
+        (document, host)
+    }
+
+    #[test]
+    fn test_typing_and_backspace_edit_text_nodes() {
+        // Given: An editor holding "Hi" and a <br>, the caret at the end
+        let (mut document, host) = editor("Hi<br/>");
+        assert_eq!(editing_host(&document, document.nodes[host].children[0]), Some(host));
+        let caret = end_of(&document, host);
+        assert_eq!(caret, BoundaryPoint::new(host, 2));
+
+        // When: Typing "yo", then Backspace three times
+        let caret = apply_edit(&mut document, host, Range { start: caret, end: caret }, Edit::InsertText("yo")).unwrap();
+        assert_eq!(serialize::inner_html(&document, host), "Hi<br>yo");
+        let mut caret = caret;
+        for _ in 0..3 {
+            caret = apply_edit(&mut document, host, Range { start: caret, end: caret }, Edit::DeleteBackward).unwrap();
+        }
+
+        // Then: The text and the <br> went, and the caret sits after "Hi"
+        assert_eq!(document.text_content(host), "Hi");
+        assert!(!serialize::inner_html(&document, host).contains("<br>"));
+        let caret = apply_edit(&mut document, host, Range { start: caret, end: caret }, Edit::InsertText("!")).unwrap();
+        assert_eq!(document.text_content(host), "Hi!");
+        assert_eq!(caret, BoundaryPoint::new(document.nodes[host].children[0], 3));
+    }
+
+    #[test]
+    fn test_bold_wraps_and_unwraps_the_selection() {
+        // Given: "Hello world" with "lo wo" selected
+        let (mut document, host) = editor("Hello world");
+        let text = document.nodes[host].children[0];
+        let range = Range { start: BoundaryPoint::new(text, 3), end: BoundaryPoint::new(text, 8) };
+
+        // When: Bold is toggled on, then off again
+        let bolded = toggle_format(&mut document, host, range, &["b", "strong"], "b");
+        let after_bold = serialize::inner_html(&document, host);
+        assert!(is_formatted(&document, host, bolded, &["b", "strong"]));
+        let unbolded = toggle_format(&mut document, host, bolded, &["b", "strong"], "b");
+
+        // Then: The selected text was wrapped, then unwrapped, and stays selected
+        assert_eq!(after_bold, "Hel<b>lo wo</b>rld");
+        assert_eq!(serialize::inner_html(&document, host), "Hello world");
+        assert_eq!(unbolded.text(&document), "lo wo");
+        assert_eq!(document.text_content(host), "Hello world");
+    }
+}
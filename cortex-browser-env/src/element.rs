@@ -1,7 +1,96 @@
-/// Element Property and Method API
-/// Provides typed access to element properties and methods
+//! Element Property and Method API
+//! Provides typed access to element properties and methods
+//!
+//! Scripts get the same API through `element(node)`, a wrapper whose
+//! properties reflect the node's attributes as the DOM's do: `id` and
+//! `className` as strings, `disabled` and `hidden` as booleans set by the
+//! attribute's presence, `tabIndex` and `maxLength` as integers, and
+//! `dataset` as the `data-*` attributes under camel-cased names. `value`
+//! and `checked` are the control's live state, as in `forms`. Its
+//! `addEventListener`, `removeEventListener` and `dispatchEvent` are those
+//! of `events` for the node.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rquickjs::{Coerced, Ctx, Exception, FromJs, Function, IntoJs, Object, Value};
+
+use crate::atom::Atom;
+use crate::css::ComputedStyle;
+use crate::dom::{Display, Document, NodeType, NodeData};
+use crate::events;
+use crate::forms;
+use crate::handles::JsNode;
+use crate::style;
+
+/// How a property reflects its content attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reflection {
+    /// The attribute's value, or `""` when it is absent
+    String(&'static str),
+    /// Whether the attribute is present; setting `false` removes it
+    Boolean(&'static str),
+    /// The attribute as an integer, or the default when it is absent or
+    /// not one
+    Number(&'static str, i32),
+    /// The control's live value or checkedness, not an attribute
+    Live,
+}
+
+/// Properties and the attributes they reflect
+pub const REFLECTED_PROPERTIES: &[(&str, Reflection)] = &[
+    ("id", Reflection::String("id")),
+    ("className", Reflection::String("class")),
+    ("title", Reflection::String("title")),
+    ("lang", Reflection::String("lang")),
+    ("name", Reflection::String("name")),
+    ("type", Reflection::String("type")),
+    ("placeholder", Reflection::String("placeholder")),
+    ("href", Reflection::String("href")),
+    ("src", Reflection::String("src")),
+    ("alt", Reflection::String("alt")),
+    ("htmlFor", Reflection::String("for")),
+    ("defaultValue", Reflection::String("value")),
+    ("disabled", Reflection::Boolean("disabled")),
+    ("hidden", Reflection::Boolean("hidden")),
+    ("required", Reflection::Boolean("required")),
+    ("readOnly", Reflection::Boolean("readonly")),
+    ("multiple", Reflection::Boolean("multiple")),
+    ("autofocus", Reflection::Boolean("autofocus")),
+    ("defaultChecked", Reflection::Boolean("checked")),
+    ("tabIndex", Reflection::Number("tabindex", -1)),
+    ("maxLength", Reflection::Number("maxlength", -1)),
+    ("minLength", Reflection::Number("minlength", -1)),
+    ("rows", Reflection::Number("rows", 2)),
+    ("cols", Reflection::Number("cols", 20)),
+    ("colSpan", Reflection::Number("colspan", 1)),
+    ("rowSpan", Reflection::Number("rowspan", 1)),
+    ("value", Reflection::Live),
+    ("checked", Reflection::Live),
+];
+
+/// How `property` reflects, if it is one of `REFLECTED_PROPERTIES`
+pub fn reflection(property: &str) -> Option<Reflection> {
+    REFLECTED_PROPERTIES.iter().find(|(name, _)| *name == property).map(|&(_, reflection)| reflection)
+}
 
-use crate::dom::{Document, NodeType, NodeData};
+/// A reflected property's value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyValue {
+    String(String),
+    Boolean(bool),
+    Number(i32),
+}
+
+impl<'js> IntoJs<'js> for PropertyValue {
+    fn into_js(self, ctx: &Ctx<'js>) -> rquickjs::Result<Value<'js>> {
+        match self {
+            PropertyValue::String(value) => value.into_js(ctx),
+            PropertyValue::Boolean(value) => value.into_js(ctx),
+            PropertyValue::Number(value) => value.into_js(ctx),
+        }
+    }
+}
 
 /// Element reference wrapping a node index
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,8 +117,8 @@ impl ElementRef {
     /// Remove an attribute
     pub fn remove_attribute(&self, document: &mut Document, name: &str) {
         if let Some(node) = document.get_node_mut(self.index) {
-            if let Some(NodeData::Element(element)) = &mut node.data {
-                element.attributes.remove(name);
+            if let (Some(NodeData::Element(element)), Some(name)) = (&mut node.data, Atom::lookup(name)) {
+                element.attributes.remove(&name);
             }
         }
     }
@@ -43,7 +132,7 @@ impl ElementRef {
     pub fn tag_name(&self, document: &Document) -> Option<String> {
         if let Some(node) = document.get_node(self.index) {
             if let Some(NodeData::Element(element)) = &node.data {
-                return Some(element.tag_name.clone());
+                return Some(element.tag_name.to_string());
             }
         }
         None
@@ -125,11 +214,11 @@ impl ElementRef {
         self.set_attribute(document, &attr_name, value);
     }
 
-    /// Get all attributes as a map
-    pub fn attributes(&self, document: &Document) -> Option<std::collections::HashMap<String, String>> {
+    /// Get all attributes as a map, sorted by name
+    pub fn attributes(&self, document: &Document) -> Option<std::collections::BTreeMap<String, String>> {
         if let Some(node) = document.get_node(self.index) {
             if let Some(NodeData::Element(element)) = &node.data {
-                return Some(element.attributes.clone());
+                return Some(element.attributes.iter().map(|(name, value)| (name.to_string(), value.clone())).collect());
             }
         }
         None
@@ -142,6 +231,281 @@ impl ElementRef {
         }
         false
     }
+
+    /// Check if this element is visible under `styles`: neither it nor an
+    /// ancestor is `display: none`, and its `visibility` is `visible`
+    pub fn is_visible(&self, document: &Document, styles: &[ComputedStyle]) -> bool {
+        if !self.is_valid(document) {
+            return false;
+        }
+        let displayed = std::iter::successors(Some(self.index), |&idx| document.nodes[idx].parent)
+            .all(|idx| styles.get(idx).is_none_or(|style| style.display != Display::None));
+        displayed && style::inherited_visibility(document, styles, self.index).is_visible()
+    }
+
+    /// A reflected property, as `element(node)[property]` reads it; `None`
+    /// for properties not in `REFLECTED_PROPERTIES`
+    pub fn property(&self, document: &Document, property: &str) -> Option<PropertyValue> {
+        Some(match reflection(property)? {
+            Reflection::String(attribute) => PropertyValue::String(self.get_attribute(document, attribute).unwrap_or_default()),
+            Reflection::Boolean(attribute) => PropertyValue::Boolean(self.has_attribute(document, attribute)),
+            Reflection::Number(attribute, default) => {
+                let default = if attribute == "tabindex" && self.is_focusable_by_default(document) { 0 } else { default };
+                let parsed = self.get_attribute(document, attribute).and_then(|value| value.trim().parse().ok());
+                PropertyValue::Number(parsed.unwrap_or(default))
+            }
+            Reflection::Live if property == "checked" => PropertyValue::Boolean(forms::is_checked(document, self.index)),
+            Reflection::Live => PropertyValue::String(forms::value(document, self.index)),
+        })
+    }
+
+    /// Set a reflected property, converting `value` to its type first;
+    /// returns whether `property` is one
+    pub fn set_property(&self, document: &mut Document, property: &str, value: PropertyValue) -> bool {
+        let Some(reflection) = reflection(property) else { return false };
+        match (reflection, value) {
+            (Reflection::Boolean(attribute), value) => {
+                if value.truthy() {
+                    self.set_attribute(document, attribute, "");
+                } else {
+                    self.remove_attribute(document, attribute);
+                }
+            }
+            (Reflection::Number(attribute, _), value) => self.set_attribute(document, attribute, &value.as_number().to_string()),
+            (Reflection::String(attribute), value) => self.set_attribute(document, attribute, &value.to_string()),
+            (Reflection::Live, value) if property == "checked" => forms::set_checked(document, self.index, value.truthy()),
+            (Reflection::Live, value) => forms::set_value(document, self.index, &value.to_string()),
+        }
+        true
+    }
+
+    /// Links with an `href` and form controls are in the tab order without
+    /// a `tabindex`
+    fn is_focusable_by_default(&self, document: &Document) -> bool {
+        match self.tag_name(document).as_deref() {
+            Some("a") => self.has_attribute(document, "href"),
+            Some("button" | "input" | "select" | "textarea") => true,
+            _ => false,
+        }
+    }
+}
+
+impl PropertyValue {
+    fn truthy(&self) -> bool {
+        match self {
+            PropertyValue::String(value) => !value.is_empty(),
+            PropertyValue::Boolean(value) => *value,
+            PropertyValue::Number(value) => *value != 0,
+        }
+    }
+
+    fn as_number(&self) -> i32 {
+        match self {
+            PropertyValue::String(value) => value.trim().parse().unwrap_or(0),
+            PropertyValue::Boolean(value) => *value as i32,
+            PropertyValue::Number(value) => *value,
+        }
+    }
+}
+
+impl std::fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PropertyValue::String(value) => f.write_str(value),
+            PropertyValue::Boolean(value) => write!(f, "{}", value),
+            PropertyValue::Number(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// `Element` and `element(node)`, over the natives of `install_element_bindings`
+const ELEMENT_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexElement;
+    delete globalThis.__cortexElement;
+    const toAttribute = key => "data-" + key.replace(/[A-Z]/g, c => "-" + c.toLowerCase());
+    const toKey = name => name.slice(5).replace(/-([a-z])/g, (_, c) => c.toUpperCase());
+    class Element {
+        constructor(node) {
+            // Tie the wrapper to the document it was made in; see `handles`
+            const epoch = native.check(node);
+            Object.defineProperty(this, "node", { value: node, enumerable: true });
+            Object.defineProperty(this, "epoch", { value: epoch });
+        }
+        get tagName() { return native.tagName(this); }
+        getAttribute(name) { return getAttribute(this, name); }
+        setAttribute(name, value) { setAttribute(this, name, value); }
+        removeAttribute(name) { removeAttribute(this, name); }
+        hasAttribute(name) { return hasAttribute(this, name); }
+        getAttributeNames() { return getAttributeNames(this); }
+        addEventListener(type, listener, options) { addEventListener(this, type, listener, options); }
+        removeEventListener(type, listener, options) { removeEventListener(this, type, listener, options); }
+        dispatchEvent(event) { return dispatchEvent(this, event); }
+        get dataset() {
+            const node = this;
+            return new Proxy({}, {
+                get: (_, key) => typeof key === "string" ? (getAttribute(node, toAttribute(key)) ?? undefined) : undefined,
+                set: (_, key, value) => { setAttribute(node, toAttribute(key), value); return true; },
+                has: (_, key) => typeof key === "string" && hasAttribute(node, toAttribute(key)),
+                deleteProperty: (_, key) => { removeAttribute(node, toAttribute(key)); return true; },
+                ownKeys: () => getAttributeNames(node).filter(name => name.startsWith("data-")).map(toKey),
+                getOwnPropertyDescriptor: (_, key) => hasAttribute(node, toAttribute(key))
+                    ? { value: getAttribute(node, toAttribute(key)), enumerable: true, configurable: true, writable: true }
+                    : undefined,
+            });
+        }
+    }
+    for (const property of native.properties) {
+        Object.defineProperty(Element.prototype, property, {
+            get() { return native.get(this, property); },
+            set(value) { native.set(this, property, value); },
+        });
+    }
+    globalThis.Element = Element;
+    // One wrapper per node of the current document, so queries finding the
+    // same node return the same object
+    const wrappers = new Map();
+    let wrappersEpoch = null;
+    globalThis.element = node => {
+        if (node instanceof Element) return node;
+        const epoch = native.check(node);
+        if (epoch !== wrappersEpoch) {
+            wrappers.clear();
+            wrappersEpoch = epoch;
+        }
+        if (!wrappers.has(node)) wrappers.set(node, new Element(node));
+        return wrappers.get(node);
+    };
+})();
+"#;
+
+/// Keep `idx`'s inline handlers in step with attribute `name` when it is
+/// an on* attribute
+fn rebind_if_handler(ctx: &Ctx<'_>, idx: usize, name: &str) -> rquickjs::Result<()> {
+    if name.len() > 2 && name[..2].eq_ignore_ascii_case("on") {
+        events::bind_inline_handlers(ctx, idx)?;
+    }
+    Ok(())
+}
+
+/// Expose attributes and reflected properties to JavaScript
+///
+/// - `getAttribute(idx, name)`, `null` when absent, `setAttribute(idx,
+///   name, value)`, `removeAttribute(idx, name)`, `hasAttribute(idx, name)`
+///   and `getAttributeNames(idx)`
+/// - `element(idx)`, an `Element` with those as methods, the properties of
+///   `REFLECTED_PROPERTIES`, `tagName`, `dataset` and the event methods
+///
+/// Natives taking nodes also take `Element`s; see `handles`. Each throws
+/// for an index that is not a live node.
+pub fn install_element_bindings<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    let globals = ctx.globals();
+
+    let doc = document.clone();
+    globals.set(
+        "getAttribute",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, name: Coerced<String>| -> rquickjs::Result<Option<String>> {
+            let document = doc.borrow();
+            Ok(ElementRef::new(node.live(&ctx, &document)?).get_attribute(&document, &name.0))
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "setAttribute",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, name: Coerced<String>, value: Coerced<String>| -> rquickjs::Result<()> {
+            let idx = {
+                let mut document = doc.borrow_mut();
+                let idx = node.live(&ctx, &document)?;
+                ElementRef::new(idx).set_attribute(&mut document, &name.0, &value.0);
+                idx
+            };
+            rebind_if_handler(&ctx, idx, &name.0)
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "removeAttribute",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, name: Coerced<String>| -> rquickjs::Result<()> {
+            let idx = {
+                let mut document = doc.borrow_mut();
+                let idx = node.live(&ctx, &document)?;
+                ElementRef::new(idx).remove_attribute(&mut document, &name.0);
+                idx
+            };
+            rebind_if_handler(&ctx, idx, &name.0)
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "hasAttribute",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, name: Coerced<String>| -> rquickjs::Result<bool> {
+            let document = doc.borrow();
+            Ok(ElementRef::new(node.live(&ctx, &document)?).has_attribute(&document, &name.0))
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "getAttributeNames",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Vec<String>> {
+            let document = doc.borrow();
+            let attributes = ElementRef::new(node.live(&ctx, &document)?).attributes(&document).unwrap_or_default();
+            Ok(attributes.into_keys().collect())
+        })?,
+    )?;
+
+    let native = Object::new(ctx.clone())?;
+    native.set("properties", REFLECTED_PROPERTIES.iter().map(|(name, _)| *name).collect::<Vec<_>>())?;
+    let doc = document.clone();
+    native.set(
+        "check",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<u32> {
+            let document = doc.borrow();
+            let idx = node.live(&ctx, &document)?;
+            if !ElementRef::new(idx).is_valid(&document) {
+                return Err(Exception::throw_type(&ctx, &format!("Node {} is not an element", idx)));
+            }
+            Ok(document.epoch())
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "tagName",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<String> {
+            let document = doc.borrow();
+            let tag = ElementRef::new(node.live(&ctx, &document)?).tag_name(&document).unwrap_or_default();
+            Ok(tag.to_ascii_uppercase())
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "get",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, property: String| -> rquickjs::Result<Option<PropertyValue>> {
+            let document = doc.borrow();
+            Ok(ElementRef::new(node.live(&ctx, &document)?).property(&document, &property))
+        })?,
+    )?;
+    let doc = document;
+    native.set(
+        "set",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, property: String, value: Value<'js>| -> rquickjs::Result<()> {
+            let value = match reflection(&property) {
+                Some(Reflection::Boolean(_)) => PropertyValue::Boolean(Coerced::<bool>::from_js(&ctx, value)?.0),
+                Some(Reflection::Number(..)) => PropertyValue::Number(Coerced::<f64>::from_js(&ctx, value)?.0 as i32),
+                Some(Reflection::Live) if property == "checked" => PropertyValue::Boolean(Coerced::<bool>::from_js(&ctx, value)?.0),
+                _ => PropertyValue::String(Coerced::<String>::from_js(&ctx, value)?.0),
+            };
+            let mut document = doc.borrow_mut();
+            let idx = node.live(&ctx, &document)?;
+            ElementRef::new(idx).set_property(&mut document, &property, value);
+            Ok(())
+        })?,
+    )?;
+    globals.set("__cortexElement", native)?;
+    ctx.eval::<(), _>(ELEMENT_PRELUDE)
 }
 
 // ============================================================================
@@ -518,6 +882,27 @@ mod tests {
         assert!(!valid);
     }
 
+    #[test]
+    fn test_is_visible() {
+        // Given: Elements hidden by display, by visibility, by the hidden
+        // attribute, and shown again inside a hidden parent
+        let doc = crate::parser::parse_html(
+            r#"<div id="none"><p id="inside">x</p></div><div id="hidden"><span id="shown">y</span></div><p id="attr" hidden>z</p><p id="plain">w</p>"#,
+        );
+        let stylesheet = crate::css::parse_css("#none { display: none; } #hidden { visibility: hidden; } #shown { visibility: visible; }");
+        let styles = style::compute_styles(&doc, &stylesheet);
+        let visible = |id: &str| {
+            let idx = crate::query::query_selector(&doc, &format!("#{}", id)).unwrap().unwrap();
+            ElementRef::new(idx).is_visible(&doc, &styles)
+        };
+
+        // Then: Only elements that are displayed and visible count
+        assert!(!visible("none") && !visible("inside"));
+        assert!(!visible("hidden") && visible("shown"));
+        assert!(!visible("attr"));
+        assert!(visible("plain"));
+    }
+
     // ========================================================================
     // EDGE CASES
     // ========================================================================
@@ -567,7 +952,7 @@ mod tests {
         let elem_ref = ElementRef::new(42);
 
         // When: We clone it
-        let cloned = elem_ref.clone();
+        let cloned = elem_ref;
 
         // Then: Both should refer to the same element
         assert_eq!(elem_ref, cloned);
@@ -594,7 +979,7 @@ mod tests {
     }
 
     #[test]
-    fn test_case_sensitive_attribute_names() {
+    fn test_attribute_names_are_case_insensitive() {
         // Given: An element
         let mut doc = Document::new();
         let html = doc.create_element("html");
@@ -608,8 +993,77 @@ mod tests {
         elem_ref.set_attribute(&mut doc, "dataTest", "value1");
         elem_ref.set_attribute(&mut doc, "datatest", "value2");
 
-        // Then: They should be separate attributes
-        assert_eq!(elem_ref.get_attribute(&doc, "dataTest"), Some("value1".to_string()));
-        assert_eq!(elem_ref.get_attribute(&doc, "datatest"), Some("value2".to_string()));
+        // Then: Both name the same attribute, as in HTML
+        assert_eq!(elem_ref.get_attribute(&doc, "dataTest"), Some("value2".to_string()));
+        assert_eq!(elem_ref.attributes(&doc).unwrap().len(), 1);
+        elem_ref.remove_attribute(&mut doc, "DATATEST");
+        assert!(!elem_ref.has_attribute(&doc, "datatest"));
+    }
+
+    #[test]
+    fn test_properties_reflect_attributes_by_type() {
+        // Given: A text input with a tab index, and a plain div
+        let mut doc = crate::parser::parse_html(r#"<input id="q" tabindex="3" maxlength="x"/><div id="d"></div>"#);
+        let input = ElementRef::new(crate::query::query_selector(&doc, "#q").unwrap().unwrap());
+        let div = ElementRef::new(crate::query::query_selector(&doc, "#d").unwrap().unwrap());
+
+        // Then: Strings, booleans and integers read as their types, with
+        // defaults for missing or invalid numbers
+        assert_eq!(input.property(&doc, "id"), Some(PropertyValue::String("q".to_string())));
+        assert_eq!(input.property(&doc, "disabled"), Some(PropertyValue::Boolean(false)));
+        assert_eq!(input.property(&doc, "tabIndex"), Some(PropertyValue::Number(3)));
+        assert_eq!(input.property(&doc, "maxLength"), Some(PropertyValue::Number(-1)));
+        assert_eq!(div.property(&doc, "tabIndex"), Some(PropertyValue::Number(-1)));
+        assert_eq!(div.property(&doc, "outerHTML"), None);
+
+        // When: Properties are set from other types
+        input.set_property(&mut doc, "disabled", PropertyValue::String("yes".to_string()));
+        input.set_property(&mut doc, "maxLength", PropertyValue::String(" 12 ".to_string()));
+        input.set_property(&mut doc, "value", PropertyValue::Number(7));
+
+        // Then: They are converted, and value changes the live state only
+        assert_eq!(input.get_attribute(&doc, "disabled"), Some(String::new()));
+        assert_eq!(input.get_attribute(&doc, "maxlength"), Some("12".to_string()));
+        assert_eq!(input.property(&doc, "value"), Some(PropertyValue::String("7".to_string())));
+        assert_eq!(input.property(&doc, "defaultValue"), Some(PropertyValue::String(String::new())));
+        input.set_property(&mut doc, "disabled", PropertyValue::Number(0));
+        assert!(!input.disabled(&doc));
+    }
+
+    #[test]
+    fn test_scripts_reflect_properties_and_dataset() {
+        // Given: A page with a button carrying data attributes
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><button data-testid="save" data-user-id="42" tabindex="2">Save</button></body></html>"#);
+
+        // When: A script reads and writes it through element()
+        let result = page
+            .run_script(
+                r#"
+                const button = element(getByTestId("save"));
+                const before = [button.tagName, button.tabIndex, typeof button.tabIndex, button.disabled, button.dataset.userId, "userId" in button.dataset];
+                button.disabled = 1;
+                button.dataset.fooBar = "baz";
+                delete button.dataset.userId;
+                button.setAttribute("title", 5);
+                JSON.stringify([
+                    before,
+                    [button.getAttribute("disabled"), button.getAttribute("data-foo-bar"), button.dataset.userId, button.title],
+                    Object.keys(button.dataset),
+                    getAttribute(button, "missing"),
+                    hasAttribute(button.node, "tabindex"),
+                ])
+                "#,
+            )
+            .unwrap();
+
+        // Then: Properties have their DOM types and write through to attributes
+        assert_eq!(
+            result,
+            r#"[["BUTTON",2,"number",false,"42",true],["","baz",null,"5"],["fooBar","testid"],null,true]"#
+        );
+        let text = page.query("button").unwrap().map(|button| page.document().nodes[button].children[0]).unwrap();
+        let error = page.run_script(&format!("try {{ element({}) }} catch (e) {{ `${{e.name}}: ${{e.message}}` }}", text)).unwrap();
+        assert_eq!(error, format!("TypeError: Node {} is not an element", text));
     }
 }
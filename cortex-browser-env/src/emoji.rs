@@ -0,0 +1,62 @@
+//! Emoji
+//! Telling emoji apart from other text, and the invisible characters that
+//! join them into sequences
+//!
+//! Painting has no shaping, so a sequence like 👍🏽 or 👩‍💻 is drawn as its
+//! visible emoji one after another; the joiners and modifiers between them
+//! take no space and draw nothing.
+
+/// Zero width joiner, which glues emoji into one sequence
+pub const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Whether `ch` is drawn as an emoji by default
+///
+/// Covers the pictograph, emoticon, transport, symbol and flag blocks and
+/// the older dingbats that have emoji presentation; it does not need to be
+/// exact, only to keep emoji from being drawn as missing-glyph boxes.
+pub fn is_emoji(ch: char) -> bool {
+    matches!(
+        ch as u32,
+        0x1F000..=0x1F0FF // Mahjong, domino and playing cards
+            | 0x1F170..=0x1F1FF // Enclosed letters and regional indicators
+            | 0x1F300..=0x1F5FF // Miscellaneous symbols and pictographs
+            | 0x1F600..=0x1F64F // Emoticons
+            | 0x1F680..=0x1F6FF // Transport and map symbols
+            | 0x1F900..=0x1F9FF // Supplemental symbols and pictographs
+            | 0x1FA70..=0x1FAFF // Symbols and pictographs extended-A
+            | 0x2600..=0x27BF // Miscellaneous symbols and dingbats
+            | 0x231A..=0x231B // Watch, hourglass
+            | 0x23E9..=0x23F3 // Media controls, alarm clock
+            | 0x2B50 | 0x2B55 // Star, circle
+    ) && !is_emoji_modifier(ch)
+}
+
+/// Whether `ch` only changes how the emoji before it looks: variation
+/// selectors, skin tones, the keycap mark, tags and the joiner
+pub fn is_emoji_modifier(ch: char) -> bool {
+    matches!(
+        ch as u32,
+        0xFE0E..=0xFE0F // Text and emoji variation selectors
+            | 0x1F3FB..=0x1F3FF // Skin tones
+            | 0x20E3 // Combining enclosing keycap
+            | 0xE0020..=0xE007F // Tags, as in subdivision flags
+    ) || ch == ZERO_WIDTH_JOINER
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emoji_and_their_modifiers() {
+        // Given: A thumbs up with a skin tone, a ZWJ sequence and plain text
+        let thumbs = "👍🏽";
+        let coder = "👩‍💻";
+
+        // Then: The pictographs are emoji and what joins them is not
+        assert_eq!(thumbs.chars().map(is_emoji).collect::<Vec<_>>(), vec![true, false]);
+        assert_eq!(coder.chars().map(is_emoji_modifier).collect::<Vec<_>>(), vec![false, true, false]);
+        assert!(is_emoji('☀') && is_emoji('🇫') && is_emoji('⭐'));
+        assert!(!is_emoji('A') && !is_emoji('é') && !is_emoji_modifier('A'));
+    }
+}
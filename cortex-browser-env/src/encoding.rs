@@ -0,0 +1,151 @@
+//! Character Encodings
+//! Work out how a loaded document's bytes are encoded and decode them
+//!
+//! In order: a byte order mark, the charset a `Content-Type` gives, a
+//! `<meta charset>` or `<meta http-equiv="Content-Type">` in the first
+//! 1024 bytes, then UTF-8 if the bytes are valid UTF-8, and windows-1252
+//! (what browsers use for latin-1) if not.
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// How far into a document to look for a `<meta>` charset, as browsers do
+const PRESCAN_BYTES: usize = 1024;
+
+/// Where a document's encoding came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodingSource {
+    ByteOrderMark,
+    ContentType,
+    Meta,
+    /// No declaration: UTF-8 if valid, else windows-1252
+    Guessed,
+}
+
+/// The encoding of `bytes`, and how it was found; `content_type` is the
+/// transport's header, if any, such as `text/html; charset=latin1`
+pub fn sniff(bytes: &[u8], content_type: Option<&str>) -> (&'static Encoding, EncodingSource) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, EncodingSource::ByteOrderMark);
+    }
+    if let Some(encoding) = content_type.and_then(charset_param).and_then(|label| Encoding::for_label(label.as_bytes())) {
+        return (html_compatible(encoding), EncodingSource::ContentType);
+    }
+    if let Some(encoding) = prescan_meta(&bytes[..bytes.len().min(PRESCAN_BYTES)]) {
+        return (html_compatible(encoding), EncodingSource::Meta);
+    }
+    let encoding = if std::str::from_utf8(bytes).is_ok() { UTF_8 } else { WINDOWS_1252 };
+    (encoding, EncodingSource::Guessed)
+}
+
+/// Decode a document with the encoding `sniff` finds; malformed sequences
+/// become U+FFFD and a byte order mark is dropped
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> (String, &'static Encoding) {
+    let (encoding, _) = sniff(bytes, content_type);
+    let (text, _) = encoding.decode_with_bom_removal(bytes);
+    (text.into_owned(), encoding)
+}
+
+/// A `<meta>` in the markup can only name an ASCII-compatible encoding; one
+/// claiming UTF-16 was read as ASCII, so it means UTF-8
+fn html_compatible(encoding: &'static Encoding) -> &'static Encoding {
+    if encoding.is_ascii_compatible() {
+        encoding
+    } else {
+        UTF_8
+    }
+}
+
+/// The `charset=` parameter of a `Content-Type` value
+fn charset_param(value: &str) -> Option<&str> {
+    let lower = value.to_ascii_lowercase();
+    let start = lower.find("charset")? + "charset".len();
+    let rest = value[start..].trim_start().strip_prefix('=')?.trim_start();
+    let rest = rest.trim_start_matches(['"', '\'']);
+    let end = rest.find(|c: char| c == ';' || c == '"' || c == '\'' || c.is_whitespace()).unwrap_or(rest.len());
+    (end > 0).then(|| &rest[..end])
+}
+
+/// The encoding the first `<meta>` declaring one names
+fn prescan_meta(head: &[u8]) -> Option<&'static Encoding> {
+    // Every encoding a document can declare keeps ASCII as ASCII, so the
+    // markup can be read as latin-1 whatever it turns out to be
+    let text: String = head.iter().map(|&b| b as char).collect();
+    let lower = text.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(offset) = lower[from..].find("<meta") {
+        let start = from + offset + "<meta".len();
+        let end = lower[start..].find('>').map_or(lower.len(), |end| start + end);
+        let tag = &lower[start..end];
+        if let Some(label) = attribute(tag, "charset") {
+            if let Some(encoding) = Encoding::for_label(label.trim().as_bytes()) {
+                return Some(encoding);
+            }
+        }
+        let declares_type = attribute(tag, "http-equiv").is_some_and(|value| value.trim() == "content-type");
+        if let Some(encoding) = attribute(tag, "content")
+            .filter(|_| declares_type)
+            .and_then(charset_param)
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+        {
+            return Some(encoding);
+        }
+        from = end;
+    }
+    None
+}
+
+/// The value of attribute `name` in the inside of a tag, quoted or not
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut from = 0;
+    while let Some(offset) = tag[from..].find(name) {
+        let at = from + offset;
+        from = at + name.len();
+        // Only a whole attribute name counts: `content` is not `tent`
+        if !tag[..at].ends_with(|c: char| c.is_whitespace() || c == '/' || c == '"' || c == '\'') {
+            continue;
+        }
+        let Some(rest) = tag[from..].trim_start().strip_prefix('=') else { continue };
+        let rest = rest.trim_start();
+        return Some(match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => rest[1..].split(quote).next().unwrap_or(""),
+            _ => rest.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or(""),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_order_mark_wins() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("<p>hé</p>".encode_utf16().flat_map(u16::to_le_bytes));
+
+        let (text, encoding) = decode(&bytes, Some("text/html; charset=latin1"));
+        assert_eq!(encoding, encoding_rs::UTF_16LE);
+        assert_eq!(text, "<p>hé</p>");
+    }
+
+    #[test]
+    fn test_meta_charset_and_http_equiv() {
+        let latin1 = b"<html><head><meta charset=\"ISO-8859-1\"></head><body>caf\xe9</body></html>";
+        let (text, encoding) = decode(latin1, None);
+        assert_eq!(encoding, WINDOWS_1252);
+        assert!(text.contains("café"));
+
+        let shift_jis = b"<meta http-equiv='Content-Type' content='text/html; charset=shift_jis'>\x93\xfa\x96\x7b";
+        assert_eq!(decode(shift_jis, None).0, "<meta http-equiv='Content-Type' content='text/html; charset=shift_jis'>日本");
+        assert_eq!(sniff(shift_jis, None).1, EncodingSource::Meta);
+    }
+
+    #[test]
+    fn test_content_type_and_guesses() {
+        assert_eq!(sniff(b"caf\xe9", Some("text/html;charset=\"windows-1252\"")), (WINDOWS_1252, EncodingSource::ContentType));
+        assert_eq!(sniff("café".as_bytes(), None), (UTF_8, EncodingSource::Guessed));
+        assert_eq!(decode(b"caf\xe9", None).0, "café");
+        // A meta claiming UTF-16 was read as ASCII, so it means UTF-8
+        assert_eq!(sniff(b"<meta charset=utf-16>", None).0, UTF_8);
+    }
+}
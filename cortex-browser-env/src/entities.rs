@@ -0,0 +1,123 @@
+//! Character References
+//! Decoding `&amp;`, `&eacute;`, `&#233;` and `&#xE9;` in parsed text and
+//! attribute values, as the HTML tokenizer does
+//!
+//! Named references use the full HTML table, including the legacy ones
+//! like `&copy` that work without a semicolon. Numeric references to
+//! nothing, surrogates or NUL become U+FFFD, and those in the C1 control
+//! range become the windows-1252 characters browsers show. Anything else
+//! after `&` stays as written.
+
+use std::borrow::Cow;
+
+use html5ever::data::{C1_REPLACEMENTS, NAMED_ENTITIES};
+
+/// Longest name in the table, `&CounterClockwiseContourIntegral;`
+const LONGEST_NAME: usize = 32;
+
+/// `text` with its character references decoded
+///
+/// In an attribute value a legacy reference without its semicolon stays
+/// as written when a letter, digit or `=` follows, so query strings like
+/// `?a=1&copy=2` survive.
+pub fn decode(text: &str, in_attribute: bool) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp + 1..];
+        let decoded = if let Some(numeric) = rest.strip_prefix('#') {
+            decode_numeric(numeric).map(|(ch, len)| (vec![ch], len + 1))
+        } else {
+            decode_named(rest, in_attribute)
+        };
+        match decoded {
+            Some((chars, len)) => {
+                out.extend(chars);
+                rest = &rest[len..];
+            }
+            None => out.push('&'),
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// `&#233;` or `&#xE9;` after the `#`: the character and bytes used
+fn decode_numeric(text: &str) -> Option<(char, usize)> {
+    let (radix, prefix) = match text.as_bytes().first() {
+        Some(b'x' | b'X') => (16, 1),
+        _ => (10, 0),
+    };
+    let digits = text[prefix..].bytes().take_while(|b| (*b as char).is_digit(radix)).count();
+    if digits == 0 {
+        return None;
+    }
+    let mut len = prefix + digits;
+    // Too many digits is out of range, not an error
+    let code = u32::from_str_radix(&text[prefix..len], radix).unwrap_or(u32::MAX);
+    if text[len..].starts_with(';') {
+        len += 1;
+    }
+    let ch = match code {
+        0x80..=0x9F => C1_REPLACEMENTS[(code - 0x80) as usize].unwrap_or_else(|| char::from_u32(code).unwrap_or('\u{FFFD}')),
+        0 => '\u{FFFD}',
+        _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+    };
+    Some((ch, len))
+}
+
+/// A named reference after the `&`: its one or two characters and the
+/// bytes used, trying the longest name first
+fn decode_named(text: &str, in_attribute: bool) -> Option<(Vec<char>, usize)> {
+    let name_len = text.bytes().take(LONGEST_NAME).take_while(u8::is_ascii_alphanumeric).count();
+    if name_len == 0 {
+        return None;
+    }
+    if text[name_len..].starts_with(';') {
+        if let Some(&(first @ 1.., second)) = NAMED_ENTITIES.get(&text[..name_len + 1]) {
+            return Some((characters(first, second), name_len + 1));
+        }
+    }
+    // Legacy references: the longest prefix the table has without `;`. The
+    // table also lists every prefix of a name, mapped to 0, to stop at
+    (1..=name_len).rev().find_map(|len| {
+        let &(first @ 1.., second) = NAMED_ENTITIES.get(&text[..len])? else { return None };
+        let next = text[len..].chars().next();
+        if in_attribute && next.is_some_and(|c| c.is_ascii_alphanumeric() || c == '=') {
+            return None;
+        }
+        Some((characters(first, second), len))
+    })
+}
+
+fn characters(first: u32, second: u32) -> Vec<char> {
+    [first, second].into_iter().filter(|&code| code != 0).filter_map(char::from_u32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_and_numeric_references() {
+        assert_eq!(decode("Fish &amp; Chips &lt;3 &#x27;n&#39; caf&eacute; &hearts;", false), "Fish & Chips <3 'n' café ♥");
+        assert_eq!(decode("&NotEqualTilde; &fjlig;", false), "\u{2242}\u{338} fj");
+        assert!(matches!(decode("no references", false), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_malformed_references() {
+        // Unknown names and bare ampersands stay as written
+        assert_eq!(decode("AT&T & &unknown; &#;", false), "AT&T & &unknown; &#;");
+        // Legacy names work without a semicolon, except before more of an
+        // attribute's query string
+        assert_eq!(decode("&copy2024 &ampx", false), "©2024 &x");
+        assert_eq!(decode("/?a=1&copy=2&not", true), "/?a=1&copy=2¬");
+        // Out-of-range, NUL and C1 code points
+        assert_eq!(decode("&#0;&#x110000;&#128;&#x9d;&#99999999999", false), "\u{FFFD}\u{FFFD}€\u{9d}\u{FFFD}");
+    }
+}
@@ -1,7 +1,14 @@
-/// Error Handling and Reporting System
-/// Provides structured error types, stack traces, and exit codes
+//! Error Handling and Reporting System
+//! Provides structured error types, stack traces, and exit codes
 
 use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::console::ConsoleEntry;
+use crate::dom::NodeError;
+use crate::limits::{LimitKind, LIMIT_EXIT_CODE};
+use crate::query::SelectorError;
 
 /// Error type for browser operations
 #[derive(Debug, Clone, PartialEq)]
@@ -16,6 +23,8 @@ pub enum BrowserError {
     JavaScriptError(String, Option<String>), // message, optional stack trace
     InvalidOperationError(String),
     NotFoundError(String),
+    /// A resource limit ran out; see `limits::Limits`
+    LimitExceeded(LimitKind, String),
 }
 
 impl fmt::Display for BrowserError {
@@ -35,12 +44,36 @@ impl fmt::Display for BrowserError {
                 write!(f, "Invalid Operation: {}", msg)
             }
             BrowserError::NotFoundError(msg) => write!(f, "Not Found: {}", msg),
+            BrowserError::LimitExceeded(kind, msg) => write!(f, "Limit Exceeded ({}): {}", kind, msg),
+        }
+    }
+}
+
+impl BrowserError {
+    /// Get the exit code a CLI run failing with this error ends with
+    /// (`LIMIT_EXIT_CODE` for limits, 1 otherwise)
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BrowserError::LimitExceeded(..) => LIMIT_EXIT_CODE,
+            _ => 1,
         }
     }
 }
 
 impl std::error::Error for BrowserError {}
 
+impl From<NodeError> for BrowserError {
+    fn from(error: NodeError) -> Self {
+        BrowserError::DOMError(error.to_string())
+    }
+}
+
+impl From<SelectorError> for BrowserError {
+    fn from(error: SelectorError) -> Self {
+        BrowserError::QueryError(error.to_string())
+    }
+}
+
 /// Test result representing success or failure
 #[derive(Debug, Clone, PartialEq)]
 pub struct TestResult {
@@ -48,6 +81,14 @@ pub struct TestResult {
     pub passed: bool,
     pub message: String,
     pub error: Option<BrowserError>,
+    /// Screenshot captured when the test failed
+    pub screenshot_path: Option<PathBuf>,
+    /// Serialized DOM captured when the test failed
+    pub dom_snapshot_path: Option<PathBuf>,
+    /// How long the test took, when it was timed
+    pub duration: Option<Duration>,
+    /// What the test logged to the console
+    pub console: Vec<ConsoleEntry>,
 }
 
 impl TestResult {
@@ -58,6 +99,10 @@ impl TestResult {
             passed: true,
             message: message.to_string(),
             error: None,
+            screenshot_path: None,
+            dom_snapshot_path: None,
+            duration: None,
+            console: Vec::new(),
         }
     }
 
@@ -68,6 +113,10 @@ impl TestResult {
             passed: false,
             message: message.to_string(),
             error: Some(error),
+            screenshot_path: None,
+            dom_snapshot_path: None,
+            duration: None,
+            console: Vec::new(),
         }
     }
 
@@ -78,9 +127,32 @@ impl TestResult {
             passed: false,
             message: message.to_string(),
             error: Some(BrowserError::InvalidOperationError(message.to_string())),
+            screenshot_path: None,
+            dom_snapshot_path: None,
+            duration: None,
+            console: Vec::new(),
         }
     }
 
+    /// Attach failure artifacts
+    pub fn with_artifacts(mut self, screenshot: Option<PathBuf>, dom_snapshot: Option<PathBuf>) -> Self {
+        self.screenshot_path = screenshot;
+        self.dom_snapshot_path = dom_snapshot;
+        self
+    }
+
+    /// Record how long the test took
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Attach what the test logged
+    pub fn with_console(mut self, console: Vec<ConsoleEntry>) -> Self {
+        self.console = console;
+        self
+    }
+
     /// Get the exit code for this result (0 = success, 1 = failure)
     pub fn exit_code(&self) -> i32 {
         if self.passed { 0 } else { 1 }
@@ -88,12 +160,14 @@ impl TestResult {
 }
 
 /// Test summary for a batch of tests
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct TestSummary {
     pub total: usize,
     pub passed: usize,
     pub failed: usize,
     pub results: Vec<TestResult>,
+    /// Seed the run used, printed so failures can be replayed
+    pub seed: Option<u64>,
 }
 
 impl TestSummary {
@@ -104,9 +178,16 @@ impl TestSummary {
             passed: 0,
             failed: 0,
             results: Vec::new(),
+            seed: None,
         }
     }
 
+    /// Record the run seed to print with the summary
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
     /// Add a test result to the summary
     pub fn add_result(&mut self, result: TestResult) {
         if result.passed {
@@ -129,6 +210,9 @@ impl TestSummary {
             "Test Results: {}/{} passed, {} failed\n",
             self.passed, self.total, self.failed
         );
+        if let Some(seed) = self.seed {
+            output.push_str(&format!("Seed: {} (re-run with --seed {} to reproduce)\n", seed, seed));
+        }
 
         if self.failed > 0 {
             output.push_str("\nFailures:\n");
@@ -137,6 +221,11 @@ impl TestSummary {
                     output.push_str(&format!("  ❌ {}: {}\n", result.name, result.message));
                     if let Some(ref error) = result.error {
                         output.push_str(&format!("     {}\n", error));
+                        if let BrowserError::JavaScriptError(_, Some(stack)) = error {
+                            for frame in stack.lines() {
+                                output.push_str(&format!("       {}\n", frame.trim()));
+                            }
+                        }
                     }
                 }
             }
@@ -145,6 +234,49 @@ impl TestSummary {
         output
     }
 
+    /// Format the summary as a standalone HTML page
+    ///
+    /// Failures link their DOM snapshot and show a thumbnail of the failure
+    /// screenshot; paths are written as recorded, so open the report from the
+    /// directory the tests ran in.
+    pub fn format_html_report(&self) -> String {
+        let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Test Report</title>\n\
+             <style>body{font-family:sans-serif}td{padding:4px 8px;vertical-align:top}\
+             .pass{color:#080}.fail{color:#c00}img{border:1px solid #ccc}</style></head><body>\n",
+        );
+        html.push_str(&format!(
+            "<h1>Test Results: {}/{} passed, {} failed</h1>\n",
+            self.passed, self.total, self.failed
+        ));
+        if let Some(seed) = self.seed {
+            html.push_str(&format!("<p>Seed: {} (re-run with <code>--seed {}</code> to reproduce)</p>\n", seed, seed));
+        }
+        html.push_str("<table>\n");
+        for result in &self.results {
+            let (class, label) = if result.passed { ("pass", "PASS") } else { ("fail", "FAIL") };
+            html.push_str(&format!(
+                "<tr><td class=\"{}\">{}</td><td>{}</td><td>{}",
+                class,
+                label,
+                escape(&result.name),
+                escape(&result.message)
+            ));
+            if let Some(ref path) = result.dom_snapshot_path {
+                html.push_str(&format!(" <a href=\"{}\">DOM</a>", escape(&path.display().to_string())));
+            }
+            html.push_str("</td><td>");
+            if let Some(ref path) = result.screenshot_path {
+                let src = escape(&path.display().to_string());
+                html.push_str(&format!("<a href=\"{0}\"><img src=\"{0}\" width=\"160\" alt=\"Failure screenshot\"></a>", src));
+            }
+            html.push_str("</td></tr>\n");
+        }
+        html.push_str("</table>\n</body></html>\n");
+        html
+    }
+
     /// Get all passed tests
     pub fn passed_tests(&self) -> Vec<&TestResult> {
         self.results.iter().filter(|r| r.passed).collect()
@@ -420,6 +552,58 @@ mod tests {
         assert!(formatted.contains("1 failed"));
     }
 
+    #[test]
+    fn test_summary_format_includes_stack() {
+        // Given: A failure with a stack trace
+        let mut summary = TestSummary::new();
+        let error = BrowserError::JavaScriptError(
+            "Error: nope".to_string(),
+            Some("    at save (src/save.ts:5:1)\n    at <eval> (tests.js:2:1)".to_string()),
+        );
+        summary.add_result(TestResult::failure("saves", "Error: nope", error));
+
+        // When: We format the summary
+        let formatted = summary.format_summary();
+
+        // Then: Each frame is listed under the error
+        assert!(formatted.contains("     JavaScript Error: Error: nope\n       at save (src/save.ts:5:1)\n       at <eval> (tests.js:2:1)\n"));
+    }
+
+    #[test]
+    fn test_summary_format_includes_seed() {
+        // Given: A summary for a seeded run
+        let mut summary = TestSummary::new().with_seed(4242);
+        summary.add_result(TestResult::success("test1", "passed"));
+
+        // When: We format the summary
+        let formatted = summary.format_summary();
+
+        // Then: The seed and how to replay it are printed
+        assert!(formatted.contains("Seed: 4242"));
+        assert!(formatted.contains("--seed 4242"));
+        assert!(!TestSummary::new().format_summary().contains("Seed"));
+    }
+
+    #[test]
+    fn test_html_report_shows_failure_thumbnails() {
+        // Given: A failure with captured artifacts
+        let mut summary = TestSummary::new();
+        summary.add_result(TestResult::success("renders <b>", "ok"));
+        summary.add_result(
+            TestResult::failure_string("submits", "expected 1")
+                .with_artifacts(Some(PathBuf::from("failures/submits.png")), Some(PathBuf::from("failures/submits.html"))),
+        );
+
+        // When: We format the HTML report
+        let html = summary.format_html_report();
+
+        // Then: The screenshot is shown as a thumbnail and names are escaped
+        assert!(html.contains("1/2 passed"));
+        assert!(html.contains(r#"<img src="failures/submits.png" width="160""#));
+        assert!(html.contains(r#"<a href="failures/submits.html">DOM</a>"#));
+        assert!(html.contains("renders &lt;b&gt;"));
+    }
+
     #[test]
     fn test_passed_tests_filter() {
         // Given: A summary with mixed results
@@ -0,0 +1,359 @@
+//! Event Loop
+//! Tasks from outside JavaScript, such as WebSocket messages, run one at a
+//! time whenever the job queue of promise reactions is empty
+//!
+//! Timed tasks wait on the page's `Clock`, which only moves when the page
+//! advances it. Host function futures are polled with the jobs and waited
+//! on, up to `HOST_TIMEOUT`, once nothing else is left to run.
+
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::pin::pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{self, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use rquickjs::{AsyncContext, AsyncRuntime, CatchResultExt, Ctx};
+
+use crate::error::BrowserError;
+
+/// Something that queues tasks for a page, e.g. its WebSocket connections
+pub trait TaskSource {
+    /// Run the oldest ready task in `ctx`; `false` when none is ready
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool>;
+
+    /// Tasks queued and not yet run, for sources that can tell
+    fn pending_tasks(&self) -> usize {
+        0
+    }
+
+    /// Timers set and not yet run or cleared, counted apart from
+    /// `pending_tasks` so leaks can name them
+    fn pending_timers(&self) -> usize {
+        0
+    }
+
+    /// Drop the queued tasks, as the test runner does between tests
+    fn clear_tasks(&self) {}
+
+    /// When, on the page's clock, the earliest task waiting on it is due
+    fn next_due_ms(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Milliseconds since the page opened, shared by everything that waits on
+/// time: animations, timed tasks and `performance.now()`
+///
+/// Clones share the time. Only the page moves it; see `Page::advance_time`.
+#[derive(Debug, Clone, Default)]
+pub struct Clock(Rc<Cell<f64>>);
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn now_ms(&self) -> f64 {
+        self.0.get()
+    }
+
+    pub fn advance(&self, ms: f64) {
+        self.0.set(self.0.get() + ms.max(0.0));
+    }
+}
+
+/// Tasks due at a time on a `Clock`, taken in due order once it arrives;
+/// tasks due at the same time keep the order they were scheduled in
+#[derive(Debug, Clone)]
+pub struct TimerQueue<T> {
+    clock: Clock,
+    /// Due time, then the order scheduled, then the task
+    entries: Vec<(f64, u64, T)>,
+    scheduled: u64,
+}
+
+impl<T> TimerQueue<T> {
+    pub fn new(clock: Clock) -> Self {
+        TimerQueue { clock, entries: Vec::new(), scheduled: 0 }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Run `task` once `delay_ms` has passed; negative or NaN delays mean
+    /// now
+    pub fn schedule(&mut self, delay_ms: f64, task: T) {
+        let delay_ms = if delay_ms > 0.0 { delay_ms } else { 0.0 };
+        self.scheduled += 1;
+        self.entries.push((self.clock.now_ms() + delay_ms, self.scheduled, task));
+    }
+
+    /// The earliest task whose time has come
+    pub fn take_due(&mut self) -> Option<T> {
+        let now = self.clock.now_ms();
+        let (index, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _, _))| *due <= now)
+            .min_by(|(_, a), (_, b)| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))?;
+        Some(self.entries.remove(index).2)
+    }
+
+    /// When the earliest task is due, passed or not
+    pub fn next_due_ms(&self) -> Option<f64> {
+        self.entries.iter().map(|(due, _, _)| *due).min_by(f64::total_cmp)
+    }
+
+    /// Keep only the tasks `keep` accepts, e.g. to cancel one
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.entries.retain(|(_, _, task)| keep(task));
+    }
+
+    /// Tasks scheduled and not yet taken, due or not
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// How long the event loop waits for host functions still running once
+/// nothing else is left to do
+pub const HOST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wakes the thread blocked on a future
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// A waker that unparks this thread
+fn thread_waker() -> Waker {
+    Waker::from(Arc::new(ThreadWaker(thread::current())))
+}
+
+/// Poll `future` on this thread until it is ready or `deadline` passes
+pub(crate) fn block_on_until<F: Future>(future: F, deadline: Instant) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let waker = thread_waker();
+    let mut cx = task::Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+        let left = deadline.checked_duration_since(Instant::now())?;
+        thread::park_timeout(left);
+    }
+}
+
+/// Poll `future` once, `None` if it has to wait
+///
+/// What it waits on wakes this thread, so a `block_on_until` after it
+/// sees the wake-up.
+pub(crate) fn poll_once<F: Future>(future: F) -> Option<F::Output> {
+    let mut future = pin!(future);
+    match future.as_mut().poll(&mut task::Context::from_waker(&thread_waker())) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
+/// The output of a future that needs no waiting, such as entering a
+/// page's context or setting a runtime's limits
+///
+/// A page's runtime is only ever used from the thread that owns it, so
+/// its lock is free unless a native re-enters the context it was called
+/// from, which panics here rather than deadlocking.
+pub fn ready<F: Future>(future: F) -> F::Output {
+    poll_once(future).expect("the page's JavaScript runtime is already in use on this thread")
+}
+
+/// A page's task sources, polled in order
+#[derive(Clone, Default)]
+pub struct EventLoop {
+    sources: Vec<Rc<dyn TaskSource>>,
+    /// The clock timed tasks wait on, moved by `advance_to_next_due`
+    clock: Option<Clock>,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_source(mut self, source: Rc<dyn TaskSource>) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Run one task from the first source with one ready; `false` when the
+    /// page has nothing left to do but wait
+    ///
+    /// Must be called outside `AsyncContext::with`.
+    pub fn run_next_task(&self, context: &AsyncContext) -> Result<bool, BrowserError> {
+        ready(context.with(|ctx| {
+            for source in &self.sources {
+                let ran = source
+                    .run_next_task(&ctx)
+                    .catch(&ctx)
+                    .map_err(|e| BrowserError::JavaScriptError(e.to_string(), None))?;
+                if ran {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }))
+    }
+
+    /// Tasks queued across sources that can tell; see
+    /// `TaskSource::pending_tasks`
+    pub fn pending_tasks(&self) -> usize {
+        self.sources.iter().map(|source| source.pending_tasks()).sum()
+    }
+
+    /// Timers set across sources; see `TaskSource::pending_timers`
+    pub fn pending_timers(&self) -> usize {
+        self.sources.iter().map(|source| source.pending_timers()).sum()
+    }
+
+    /// Drop every source's queued tasks
+    pub fn clear_tasks(&self) {
+        self.sources.iter().for_each(|source| source.clear_tasks());
+    }
+
+    /// When the earliest timed task across sources is due
+    pub fn next_due_ms(&self) -> Option<f64> {
+        self.sources.iter().filter_map(|source| source.next_due_ms()).min_by(f64::total_cmp)
+    }
+
+    /// Move the clock to the earliest timed task still in the future, as
+    /// the test runner does when a test only waits on time; `false` when
+    /// nothing waits or there is no clock
+    pub fn advance_to_next_due(&self) -> bool {
+        let (Some(clock), Some(due)) = (&self.clock, self.next_due_ms()) else {
+            return false;
+        };
+        clock.advance(due - clock.now_ms());
+        true
+    }
+
+    /// Run jobs and finished host functions, and tasks once neither is
+    /// left, until none remain, returning how many ran
+    ///
+    /// Host functions still running are waited for once nothing else can
+    /// run, up to `HOST_TIMEOUT`, after which the loop stops waiting and
+    /// they finish on a later run.
+    pub fn run_until_idle(&self, runtime: &AsyncRuntime, context: &AsyncContext) -> Result<usize, BrowserError> {
+        let mut ran = 0;
+        loop {
+            // `None` when only host functions still running are left
+            let job = poll_once(runtime.execute_pending_job());
+            match job {
+                Some(Ok(true)) => ran += 1,
+                Some(Ok(false)) | None if self.run_next_task(context)? => ran += 1,
+                Some(Ok(false)) => return Ok(ran),
+                None => match block_on_until(runtime.execute_pending_job(), Instant::now() + HOST_TIMEOUT) {
+                    Some(Ok(_)) => ran += 1,
+                    Some(Err(e)) => return Err(BrowserError::JavaScriptError(e.to_string(), None)),
+                    None => return Ok(ran),
+                },
+                Some(Err(e)) => return Err(BrowserError::JavaScriptError(e.to_string(), None)),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EventLoop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EventLoop({} sources)", self.sources.len())
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Runs queued snippets of JavaScript, one per task
+    #[derive(Default)]
+    struct Scripts(RefCell<Vec<&'static str>>);
+
+    impl TaskSource for Scripts {
+        fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+            let next = self.0.borrow_mut().pop();
+            match next {
+                Some(source) => ctx.eval::<(), _>(source).map(|_| true),
+                None => Ok(false),
+            }
+        }
+    }
+
+    #[test]
+    fn test_timer_queue_waits_for_the_clock() {
+        // Given: Tasks due later, due now and due at the same time
+        let clock = Clock::new();
+        let mut queue = TimerQueue::new(clock.clone());
+        queue.schedule(50.0, "late");
+        queue.schedule(10.0, "first at 10");
+        queue.schedule(-5.0, "now");
+        queue.schedule(10.0, "second at 10");
+
+        // When: The clock stands still, then moves to 10ms
+        assert_eq!(queue.take_due(), Some("now"));
+        assert_eq!(queue.take_due(), None);
+        clock.advance(10.0);
+
+        // Then: Only what is due runs, in the order it was scheduled
+        assert_eq!(queue.take_due(), Some("first at 10"));
+        assert_eq!(queue.take_due(), Some("second at 10"));
+        assert_eq!(queue.take_due(), None);
+        assert_eq!(queue.len(), 1);
+        clock.advance(40.0);
+        assert_eq!(queue.take_due(), Some("late"));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_tasks_run_after_jobs() {
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let scripts = Rc::new(Scripts::default());
+        scripts.0.borrow_mut().extend(["order.push('second task')", "order.push('first task'); Promise.resolve().then(() => order.push('its job'))"]);
+        let event_loop = EventLoop::new().with_source(scripts.clone());
+
+        ready(context.with(|ctx| ctx.eval::<(), _>("globalThis.order = []; Promise.resolve().then(() => order.push('job'));").unwrap()));
+        assert_eq!(event_loop.run_until_idle(&runtime, &context).unwrap(), 4);
+
+        let order: Vec<String> = ready(context.with(|ctx| ctx.eval("order").unwrap()));
+        assert_eq!(order, ["job", "first task", "its job", "second task"]);
+        assert!(!event_loop.run_next_task(&context).unwrap());
+
+        scripts.0.borrow_mut().push("throw new Error('in task')");
+        let error = event_loop.run_until_idle(&runtime, &context).unwrap_err();
+        assert!(error.to_string().contains("in task"), "{}", error);
+    }
+}
@@ -0,0 +1,830 @@
+//! EventSource
+//! Server-Sent Events over the network layer: `new EventSource(url)` reads a
+//! `text/event-stream` through a scripted `MockEventSourceServer` or the
+//! page's loader, and reconnects with the last event ID when a stream ends
+//!
+//! A connection opens with the server's canned body for its URL or, failing
+//! that, whatever the page's loader returns for it; a URL neither serves
+//! fails the EventSource for good. After its opening body a connection
+//! stays open: tests push more events, end it to make the page reconnect,
+//! or fail it. Reconnecting waits out the reconnection time, from the last
+//! `retry:` field or three seconds, on the page's clock; see
+//! `Page::advance_time`. Events reach the page as tasks of its event loop.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::event_loop::{Clock, TaskSource, TimerQueue};
+use crate::network::{self, ResourceLoader};
+
+/// How long a stream waits to reconnect until a `retry:` field says
+/// otherwise, as browsers choose it
+pub const DEFAULT_RECONNECTION_TIME: Duration = Duration::from_secs(3);
+
+/// `EventSource.readyState`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadyState {
+    Connecting,
+    Open,
+    Closed,
+}
+
+impl ReadyState {
+    /// The number scripts see, e.g. `EventSource.OPEN` is 1
+    pub fn as_number(&self) -> u8 {
+        match self {
+            ReadyState::Connecting => 0,
+            ReadyState::Open => 1,
+            ReadyState::Closed => 2,
+        }
+    }
+}
+
+/// An event parsed from a stream, as the page receives it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    /// `event:` field, `message` when absent
+    pub event_type: String,
+    pub data: String,
+    pub last_event_id: String,
+}
+
+/// Incremental `text/event-stream` parser; chunks may split lines anywhere
+#[derive(Debug, Default)]
+struct SseParser {
+    started: bool,
+    line: String,
+    after_cr: bool,
+    data: String,
+    event_type: String,
+    id: String,
+    last_event_id: String,
+    retry: Option<u64>,
+}
+
+impl SseParser {
+    fn feed(&mut self, text: &str) -> Vec<SseEvent> {
+        let mut text = text;
+        if !self.started && !text.is_empty() {
+            self.started = true;
+            text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        }
+        let mut events = Vec::new();
+        for ch in text.chars() {
+            match ch {
+                '\n' if self.after_cr => self.after_cr = false,
+                '\r' | '\n' => {
+                    self.after_cr = ch == '\r';
+                    let line = mem::take(&mut self.line);
+                    events.extend(self.process_line(&line));
+                }
+                _ => {
+                    self.after_cr = false;
+                    self.line.push(ch);
+                }
+            }
+        }
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => self.retry = value.parse().ok(),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        self.last_event_id = self.id.clone();
+        let event_type = mem::take(&mut self.event_type);
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut data = mem::take(&mut self.data);
+        data.pop();
+        Some(SseEvent {
+            event_type: if event_type.is_empty() { "message".to_string() } else { event_type },
+            data,
+            last_event_id: self.last_event_id.clone(),
+        })
+    }
+
+    /// Start a new connection: unfinished lines and events are dropped, the
+    /// last event ID is kept
+    fn reconnect(&mut self) {
+        self.started = false;
+        self.line.clear();
+        self.after_cr = false;
+        self.data.clear();
+        self.event_type.clear();
+        self.id = self.last_event_id.clone();
+    }
+}
+
+/// Something that happened to a stream, waiting to reach its page
+#[derive(Debug, Clone, PartialEq)]
+enum StreamTask {
+    Open,
+    Event(SseEvent),
+    /// The connection dropped; `reconnect` is false once the page gives up
+    Error { reconnect: bool },
+    /// Wait out the reconnection time, then connect again sending the last
+    /// event ID
+    Reconnect,
+}
+
+#[derive(Debug)]
+struct Stream {
+    url: String,
+    /// Page that opened it, from `MockEventSourceServer::attach`
+    page: usize,
+    /// As the page last saw it
+    state: ReadyState,
+    /// Whether the server can send on the current connection
+    connected: bool,
+    /// Failed by the server or closed by the page: never reconnects
+    finished: bool,
+    parser: SseParser,
+    /// Last event ID sent with each connection, empty for none
+    connections: Vec<String>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    bodies: HashMap<String, String>,
+    refused: Vec<String>,
+    streams: Vec<Stream>,
+    /// Tasks in the order they happened, by stream
+    tasks: VecDeque<(usize, StreamTask)>,
+    pages: usize,
+}
+
+/// The server behind every EventSource of a page
+///
+/// Clones share streams, so a test keeps one clone while the page uses
+/// another; a `Browser` shares its server with all its pages.
+#[derive(Clone, Default)]
+pub struct MockEventSourceServer {
+    state: Rc<RefCell<ServerState>>,
+}
+
+impl MockEventSourceServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open every connection to `url` with `body`, raw `text/event-stream`
+    /// text, instead of asking the page's loader
+    pub fn with_stream(self, url: &str, body: &str) -> Self {
+        self.state.borrow_mut().bodies.insert(url.to_string(), body.to_string());
+        self
+    }
+
+    /// Fail EventSources for `url` as a non-200 response would
+    pub fn with_refused(self, url: &str) -> Self {
+        self.refuse(url);
+        self
+    }
+
+    pub fn refuse(&self, url: &str) {
+        self.state.borrow_mut().refused.push(url.to_string());
+    }
+
+    /// Every stream opened so far, oldest first
+    pub fn streams(&self) -> Vec<MockEventStream> {
+        (0..self.state.borrow().streams.len()).map(|id| self.stream_handle(id)).collect()
+    }
+
+    /// The latest stream for `url`
+    pub fn stream(&self, url: &str) -> Option<MockEventStream> {
+        let id = self.state.borrow().streams.iter().rposition(|s| s.url == url)?;
+        Some(self.stream_handle(id))
+    }
+
+    /// Streams for a new page, opened through `loader` and relative to
+    /// `base_url`
+    pub fn attach(&self, loader: Rc<dyn ResourceLoader>, base_url: Option<String>) -> PageEventStreams {
+        let mut state = self.state.borrow_mut();
+        state.pages += 1;
+        PageEventStreams {
+            server: self.clone(),
+            page: state.pages,
+            loader,
+            base_url,
+            reconnects: Rc::new(RefCell::new(TimerQueue::new(Clock::new()))),
+        }
+    }
+
+    fn stream_handle(&self, id: usize) -> MockEventStream {
+        MockEventStream { server: self.clone(), id }
+    }
+}
+
+impl fmt::Debug for MockEventSourceServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MockEventSourceServer({} streams)", self.state.borrow().streams.len())
+    }
+}
+
+/// The server's end of one EventSource, across its reconnections
+#[derive(Clone)]
+pub struct MockEventStream {
+    server: MockEventSourceServer,
+    id: usize,
+}
+
+impl MockEventStream {
+    fn with<T>(&self, f: impl FnOnce(&mut Stream) -> T) -> T {
+        f(&mut self.server.state.borrow_mut().streams[self.id])
+    }
+
+    pub fn url(&self) -> String {
+        self.with(|s| s.url.clone())
+    }
+
+    pub fn ready_state(&self) -> ReadyState {
+        self.with(|s| s.state)
+    }
+
+    /// How many times the page connected, the first time included
+    pub fn connection_count(&self) -> usize {
+        self.with(|s| s.connections.len())
+    }
+
+    /// The `Last-Event-ID` the page sent when it last connected, if any
+    pub fn last_event_id(&self) -> Option<String> {
+        self.with(|s| s.connections.last().filter(|id| !id.is_empty()).cloned())
+    }
+
+    /// The reconnection time the stream's last `retry:` field set
+    pub fn reconnection_time(&self) -> Option<Duration> {
+        self.with(|s| s.parser.retry.map(Duration::from_millis))
+    }
+
+    /// Send a `message` event
+    pub fn send(&self, data: &str) {
+        self.send_raw(&format_event(None, data));
+    }
+
+    /// Send an event of type `event_type`, for `addEventListener` listeners
+    pub fn send_event(&self, event_type: &str, data: &str) {
+        self.send_raw(&format_event(Some(event_type), data));
+    }
+
+    /// Send raw `text/event-stream` text; ignored unless connected
+    pub fn send_raw(&self, text: &str) {
+        let mut state = self.server.state.borrow_mut();
+        let stream = &mut state.streams[self.id];
+        if !stream.connected {
+            return;
+        }
+        let events = stream.parser.feed(text);
+        state.tasks.extend(events.into_iter().map(|event| (self.id, StreamTask::Event(event))));
+    }
+
+    /// End the response: the page sees `error` and reconnects
+    pub fn end(&self) {
+        self.disconnect(true);
+    }
+
+    /// Fail the stream: the page sees `error` and gives up
+    pub fn fail(&self) {
+        self.disconnect(false);
+    }
+
+    fn disconnect(&self, reconnect: bool) {
+        let mut state = self.server.state.borrow_mut();
+        let stream = &mut state.streams[self.id];
+        if stream.finished || reconnect && !stream.connected {
+            return;
+        }
+        stream.connected = false;
+        stream.finished = !reconnect;
+        state.tasks.push_back((self.id, StreamTask::Error { reconnect }));
+        if reconnect {
+            state.tasks.push_back((self.id, StreamTask::Reconnect));
+        }
+    }
+}
+
+impl fmt::Debug for MockEventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MockEventStream({}, {:?})", self.url(), self.ready_state())
+    }
+}
+
+/// `data` as one event, a `data:` line per line
+fn format_event(event_type: Option<&str>, data: &str) -> String {
+    let mut text = event_type.map(|t| format!("event: {}\n", t)).unwrap_or_default();
+    for line in data.split('\n') {
+        text.push_str("data: ");
+        text.push_str(line.strip_suffix('\r').unwrap_or(line));
+        text.push('\n');
+    }
+    text.push('\n');
+    text
+}
+
+/// One page's side of a `MockEventSourceServer`
+#[derive(Clone)]
+pub struct PageEventStreams {
+    server: MockEventSourceServer,
+    page: usize,
+    loader: Rc<dyn ResourceLoader>,
+    base_url: Option<String>,
+    /// Streams waiting out their reconnection time
+    reconnects: Rc<RefCell<TimerQueue<usize>>>,
+}
+
+impl PageEventStreams {
+    /// Wait out reconnection times on `clock`, the page's
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.reconnects = Rc::new(RefCell::new(TimerQueue::new(clock)));
+        self
+    }
+
+    pub fn server(&self) -> &MockEventSourceServer {
+        &self.server
+    }
+
+    /// Open a stream as `new EventSource(url)` does
+    fn open(&self, url: &str) -> usize {
+        let url = match &self.base_url {
+            Some(base) => network::resolve_url(base, url),
+            None => url.trim().to_string(),
+        };
+        let id = {
+            let mut state = self.server.state.borrow_mut();
+            state.streams.push(Stream {
+                url,
+                page: self.page,
+                state: ReadyState::Connecting,
+                connected: false,
+                finished: false,
+                parser: SseParser::default(),
+                connections: Vec::new(),
+            });
+            state.streams.len() - 1
+        };
+        self.connect(id);
+        id
+    }
+
+    /// Connect `id`, queueing `open` and its opening body, or its failure
+    fn connect(&self, id: usize) {
+        let (url, canned, refused) = {
+            let state = self.server.state.borrow();
+            let url = state.streams[id].url.clone();
+            let refused = state.refused.contains(&url);
+            (url.clone(), state.bodies.get(&url).cloned(), refused)
+        };
+        let body = match canned {
+            _ if refused => None,
+            Some(body) => Some(body),
+            None => network::fetch(&*self.loader, &url).ok().map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+        };
+
+        let mut state = self.server.state.borrow_mut();
+        let ServerState { streams, tasks, .. } = &mut *state;
+        let stream = &mut streams[id];
+        stream.parser.reconnect();
+        stream.connections.push(stream.parser.last_event_id.clone());
+        match body {
+            Some(body) => {
+                stream.connected = true;
+                tasks.push_back((id, StreamTask::Open));
+                tasks.extend(stream.parser.feed(&body).into_iter().map(|event| (id, StreamTask::Event(event))));
+            }
+            None => {
+                stream.finished = true;
+                tasks.push_back((id, StreamTask::Error { reconnect: false }));
+            }
+        }
+    }
+
+    /// The page's `close()`: nothing more reaches it
+    fn close(&self, id: usize) {
+        let mut state = self.server.state.borrow_mut();
+        let stream = &mut state.streams[id];
+        stream.state = ReadyState::Closed;
+        stream.connected = false;
+        stream.finished = true;
+        state.tasks.retain(|(task_id, _)| *task_id != id);
+        self.reconnects.borrow_mut().retain(|&waiting| waiting != id);
+    }
+
+    /// The next task for this page's streams, applied to their state
+    fn take_task(&self) -> Option<(usize, StreamTask)> {
+        let mut state = self.server.state.borrow_mut();
+        let ServerState { streams, tasks, .. } = &mut *state;
+        let index = tasks.iter().position(|(id, _)| streams[*id].page == self.page)?;
+        let (id, task) = tasks.remove(index)?;
+        let stream = &mut streams[id];
+        match task {
+            StreamTask::Open => stream.state = ReadyState::Open,
+            StreamTask::Error { reconnect: true } => stream.state = ReadyState::Connecting,
+            StreamTask::Error { reconnect: false } => stream.state = ReadyState::Closed,
+            StreamTask::Event(_) | StreamTask::Reconnect => {}
+        }
+        Some((id, task))
+    }
+}
+
+impl fmt::Debug for PageEventStreams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PageEventStreams({})", self.page)
+    }
+}
+
+impl TaskSource for PageEventStreams {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let due = self.reconnects.borrow_mut().take_due();
+        if let Some(id) = due {
+            let finished = self.server.state.borrow().streams[id].finished;
+            if !finished {
+                self.connect(id);
+            }
+            return Ok(true);
+        }
+        let Some((id, task)) = self.take_task() else {
+            return Ok(false);
+        };
+        let deliver = || -> rquickjs::Result<Function> {
+            let native: Object = ctx.globals().get("__cortexEventSource")?;
+            native.get("deliver")
+        };
+        match task {
+            StreamTask::Open => deliver()?.call::<_, ()>((id, "open"))?,
+            StreamTask::Event(event) => {
+                deliver()?.call::<_, ()>((id, "message", event.event_type, event.data, event.last_event_id))?
+            }
+            StreamTask::Error { reconnect } => deliver()?.call::<_, ()>((id, "error", reconnect))?,
+            StreamTask::Reconnect => {
+                let retry = self.server.state.borrow().streams[id].parser.retry;
+                let delay = retry.map_or(DEFAULT_RECONNECTION_TIME, Duration::from_millis);
+                self.reconnects.borrow_mut().schedule(delay.as_secs_f64() * 1000.0, id);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// `EventSource` and the `mockEventSource` controls, over the natives of
+/// `install_event_source`
+const EVENT_SOURCE_PRELUDE: &str = r##"
+(() => {
+    const native = globalThis.__cortexEventSource;
+    const [CONNECTING, OPEN, CLOSED] = [0, 1, 2];
+    const sources = new Map();
+
+    function report(error) {
+        console.error("Uncaught", error);
+    }
+
+    function originOf(url) {
+        const match = url.match(/^[a-z][a-z0-9+.-]*:\/\/[^/?#]*/i);
+        return match ? match[0] : "null";
+    }
+
+    class EventSource {
+        #id;
+        #listeners = new Map();
+
+        constructor(url, init = {}) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to construct 'EventSource': 1 argument required, but only 0 present.");
+            }
+            this.readyState = CONNECTING;
+            this.onopen = null;
+            this.onmessage = null;
+            this.onerror = null;
+            this.#id = native.open(String(url));
+            sources.set(this.#id, this);
+            Object.defineProperty(this, "url", { value: native.info(this.#id).url, enumerable: true });
+            Object.defineProperty(this, "withCredentials", { value: Boolean(init && init.withCredentials), enumerable: true });
+        }
+
+        close() {
+            if (this.readyState === CLOSED) return;
+            this.readyState = CLOSED;
+            sources.delete(this.#id);
+            native.close(this.#id);
+        }
+
+        addEventListener(type, listener) {
+            if (typeof listener !== "function" && !(listener && typeof listener.handleEvent === "function")) return;
+            const listeners = this.#listeners.get(type) || [];
+            if (!listeners.includes(listener)) listeners.push(listener);
+            this.#listeners.set(type, listeners);
+        }
+
+        removeEventListener(type, listener) {
+            const listeners = this.#listeners.get(type) || [];
+            this.#listeners.set(type, listeners.filter(l => l !== listener));
+        }
+
+        dispatchEvent(event) {
+            event.target = this;
+            event.currentTarget = this;
+            const handler = ["open", "message", "error"].includes(event.type) ? this["on" + event.type] : null;
+            const listeners = [...(typeof handler === "function" ? [handler] : []), ...(this.#listeners.get(event.type) || [])];
+            for (const listener of listeners) {
+                try {
+                    if (typeof listener === "function") listener.call(this, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    report(error);
+                }
+            }
+            return true;
+        }
+    }
+    for (const [name, value] of Object.entries({ CONNECTING, OPEN, CLOSED })) {
+        Object.defineProperty(EventSource, name, { value, enumerable: true });
+        Object.defineProperty(EventSource.prototype, name, { value, enumerable: true });
+    }
+
+    native.deliver = (id, type, a, b, c) => {
+        const source = sources.get(id);
+        if (!source) return;
+        if (type === "open") {
+            source.readyState = OPEN;
+            source.dispatchEvent({ type: "open" });
+        } else if (type === "message") {
+            source.dispatchEvent({ type: a, data: b, lastEventId: c, origin: originOf(source.url) });
+        } else {
+            source.readyState = a ? CONNECTING : CLOSED;
+            if (!a) sources.delete(id);
+            source.dispatchEvent({ type: "error" });
+        }
+    };
+
+    function format(data, options) {
+        let text = options.event ? `event: ${options.event}\n` : "";
+        if (options.id !== undefined) text += `id: ${options.id}\n`;
+        for (const line of String(data).split(/\r\n|\r|\n/)) text += `data: ${line}\n`;
+        return text + "\n";
+    }
+
+    class MockEventStream {
+        #id;
+        constructor(id) { this.#id = id; }
+        get url() { return native.info(this.#id).url; }
+        get readyState() { return native.info(this.#id).readyState; }
+        /** How many times the page connected, the first time included */
+        get connections() { return native.info(this.#id).connections; }
+        /** The Last-Event-ID the page sent when it last connected, or null */
+        get lastEventId() { return native.info(this.#id).lastEventId; }
+        /** Send an event; options may give its `event` type and `id` */
+        send(data, options = {}) { native.push(this.#id, format(data, options)); }
+        sendRaw(text) { native.push(this.#id, String(text)); }
+        end() { native.end(this.#id); }
+        error() { native.fail(this.#id); }
+    }
+
+    const handles = new Map();
+    const handle = id => {
+        if (!handles.has(id)) handles.set(id, new MockEventStream(id));
+        return handles.get(id);
+    };
+
+    globalThis.EventSource = EventSource;
+    globalThis.mockEventSource = {
+        streams() { return native.streams().map(handle); },
+        last(url) {
+            const matching = this.streams().filter(s => url === undefined || s.url === url);
+            return matching.length ? matching[matching.length - 1] : null;
+        },
+        refuse(url) { native.refuse(String(url)); },
+    };
+})();
+"##;
+
+/// Install `EventSource` and `mockEventSource`, opened through `streams`
+///
+/// Events only fire when `streams`, as a `TaskSource` of the page's event
+/// loop, runs them.
+pub fn install_event_source<'js>(ctx: &Ctx<'js>, streams: &PageEventStreams) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+
+    // The page's side
+    let page = streams.clone();
+    native.set("open", Function::new(ctx.clone(), move |url: String| page.open(&url))?)?;
+    let page = streams.clone();
+    native.set("close", Function::new(ctx.clone(), move |id: usize| page.close(id))?)?;
+
+    // The mock server's side, limited to this page's streams
+    let page = streams.clone();
+    native.set(
+        "streams",
+        Function::new(ctx.clone(), move || -> Vec<usize> {
+            let state = page.server.state.borrow();
+            (0..state.streams.len()).filter(|&id| state.streams[id].page == page.page).collect()
+        })?,
+    )?;
+    let page = streams.clone();
+    native.set(
+        "info",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, id: usize| -> rquickjs::Result<Object<'js>> {
+            let stream = page.server.stream_handle(id);
+            let info = Object::new(ctx)?;
+            info.set("url", stream.url())?;
+            info.set("readyState", stream.ready_state().as_number())?;
+            info.set("connections", stream.connection_count())?;
+            info.set("lastEventId", stream.last_event_id())?;
+            Ok(info)
+        })?,
+    )?;
+    let page = streams.clone();
+    native.set("push", Function::new(ctx.clone(), move |id: usize, text: String| page.server.stream_handle(id).send_raw(&text))?)?;
+    let page = streams.clone();
+    native.set("end", Function::new(ctx.clone(), move |id: usize| page.server.stream_handle(id).end())?)?;
+    let page = streams.clone();
+    native.set("fail", Function::new(ctx.clone(), move |id: usize| page.server.stream_handle(id).fail())?)?;
+    let page = streams.clone();
+    native.set("refuse", Function::new(ctx.clone(), move |url: String| page.server.refuse(&url))?)?;
+
+    ctx.globals().set("__cortexEventSource", native)?;
+    ctx.eval::<(), _>(EVENT_SOURCE_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_loop::{ready, EventLoop};
+    use crate::network::{MockNetwork, OfflineLoader};
+    use rquickjs::{AsyncContext, AsyncRuntime};
+
+    /// A context with EventSources on `server`, its event loop and clock
+    fn setup(server: &MockEventSourceServer, loader: Rc<dyn ResourceLoader>) -> (AsyncRuntime, AsyncContext, EventLoop, Clock) {
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let clock = Clock::new();
+        let streams = server.attach(loader, Some("https://app.test/dashboard/".to_string())).with_clock(clock.clone());
+        ready(context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            install_event_source(&ctx, &streams).unwrap();
+        }));
+        (runtime, context, EventLoop::new().with_source(Rc::new(streams)), clock)
+    }
+
+    fn eval<T: for<'js> rquickjs::FromJs<'js>>(context: &AsyncContext, source: &str) -> T {
+        ready(context.with(|ctx| ctx.eval(source).unwrap()))
+    }
+
+    #[test]
+    fn test_parser_fields() {
+        let mut parser = SseParser::default();
+        let mut events = parser.feed("\u{feff}: comment\nevent: tick\ndata: a\r\ndata:b\rid: 7\n\nretry: 2500\nretry: soon\ndata");
+        events.extend(parser.feed("\n\ndata: split li"));
+        assert!(parser.feed("ne\n").is_empty(), "Events wait for their blank line");
+        events.extend(parser.feed("\n"));
+
+        let event = |event_type: &str, data: &str| SseEvent {
+            event_type: event_type.to_string(),
+            data: data.to_string(),
+            last_event_id: "7".to_string(),
+        };
+        assert_eq!(events, [event("tick", "a\nb"), event("message", ""), event("message", "split line")]);
+        assert_eq!(parser.retry, Some(2500));
+
+        parser.feed("id: 8\ndata: cut off");
+        parser.reconnect();
+        assert_eq!(parser.feed("\n"), [], "A new connection drops the unfinished event");
+        assert_eq!(parser.last_event_id, "7");
+    }
+
+    #[test]
+    fn test_streams_and_reconnection() {
+        let server = MockEventSourceServer::new().with_stream("https://app.test/dashboard/feed", "retry: 3000\nid: 1\ndata: hello\n\n");
+        let (runtime, context, event_loop, clock) = setup(&server, Rc::new(OfflineLoader));
+        eval::<()>(
+            &context,
+            r#"
+            globalThis.log = [];
+            globalThis.source = new EventSource("feed");
+            source.onopen = () => log.push("open");
+            source.onmessage = e => log.push(`message ${e.data} #${e.lastEventId} from ${e.origin}`);
+            source.addEventListener("alert", e => log.push("alert " + e.data));
+            source.onerror = () => log.push("error " + source.readyState);
+            log.push(source.url);
+            "#,
+        );
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        let stream = server.stream("https://app.test/dashboard/feed").unwrap();
+        assert_eq!(stream.ready_state(), ReadyState::Open);
+        assert_eq!(stream.reconnection_time(), Some(Duration::from_millis(3000)));
+
+        stream.send_raw("id: 2\n");
+        stream.send_event("alert", "disk full");
+        stream.end();
+        stream.send("lost");
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        assert_eq!(stream.connection_count(), 1, "Reconnecting waits for the retry time");
+        clock.advance(2999.0);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        assert_eq!(stream.connection_count(), 1);
+        clock.advance(1.0);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        assert_eq!(stream.connection_count(), 2);
+        assert_eq!(stream.last_event_id().as_deref(), Some("2"));
+
+        stream.fail();
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        let log: Vec<String> = eval(&context, "log");
+        assert_eq!(
+            log,
+            [
+                "https://app.test/dashboard/feed",
+                "open",
+                "message hello #1 from https://app.test",
+                "alert disk full",
+                "error 0",
+                "open",
+                "message hello #1 from https://app.test",
+                "error 2",
+            ]
+        );
+        assert_eq!(stream.ready_state(), ReadyState::Closed);
+    }
+
+    #[test]
+    fn test_streams_from_the_network() {
+        let network = Rc::new(MockNetwork::new().with_response("https://app.test/events", "data: from the loader\n\n"));
+        let server = MockEventSourceServer::new().with_refused("https://app.test/denied");
+        let (runtime, context, event_loop, _) = setup(&server, network.clone());
+        eval::<()>(
+            &context,
+            r#"
+            globalThis.log = [];
+            for (const url of ["/events", "/denied", "/missing"]) {
+                const source = new EventSource(url);
+                source.onmessage = e => log.push(url + " " + e.data);
+                source.onerror = () => log.push(url + " error " + source.readyState);
+            }
+            const closed = new EventSource("/events");
+            closed.onmessage = () => log.push("closed source got a message");
+            closed.close();
+            "#,
+        );
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        let log: Vec<String> = eval(&context, "log");
+        assert_eq!(log, ["/events from the loader", "/denied error 2", "/missing error 2"]);
+        assert_eq!(network.requests(), ["https://app.test/events", "https://app.test/missing", "https://app.test/events"]);
+        assert_eq!(eval::<u8>(&context, "closed.readyState"), 2);
+    }
+
+    #[test]
+    fn test_mock_controls_from_scripts() {
+        let server = MockEventSourceServer::new().with_stream("https://app.test/live", "");
+        let (runtime, context, event_loop, clock) = setup(&server, Rc::new(OfflineLoader));
+        eval::<()>(
+            &context,
+            r#"
+            globalThis.received = [];
+            const source = new EventSource("https://app.test/live");
+            source.onmessage = e => received.push(e.data + "@" + e.lastEventId);
+            const stream = mockEventSource.last("https://app.test/live");
+            stream.send("line 1\nline 2", { id: "a" });
+            stream.send("ignored type", { event: "other" });
+            stream.end();
+            "#,
+        );
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        clock.advance(DEFAULT_RECONNECTION_TIME.as_secs_f64() * 1000.0);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        let received: Vec<String> = eval(&context, "received");
+        assert_eq!(received, ["line 1\nline 2@a"]);
+        assert_eq!(eval::<String>(&context, "mockEventSource.last().lastEventId"), "a");
+        assert_eq!(eval::<u8>(&context, "mockEventSource.streams()[0].connections"), 2);
+    }
+}
@@ -0,0 +1,649 @@
+//! DOM Events
+//! `addEventListener` and `dispatchEvent` over nodes, with capture, target
+//! and bubble phases as in browsers
+//!
+//! Listeners live on the JavaScript side; the document records each one's
+//! id, type and capture flag for `Document::listeners` and
+//! `debug.getEventListeners`. Inline on* handlers are listeners too,
+//! compiled when first called with the node's `element` wrapper as `this`.
+//! Exceptions in listeners go to `console.error` without stopping the
+//! dispatch. Nodes are checked as in `handles`, and listeners are
+//! forgotten when the page loads another document.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::dom::{Document, ListenerInfo, ListenerOptions, NodeData};
+use crate::handles::JsNode;
+
+/// `target` followed by its ancestors, the path an event bubbles along and,
+/// reversed, is captured along
+pub fn event_path(document: &Document, target: usize) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut current = Some(target);
+    while let Some(idx) = current {
+        let Some(node) = document.nodes.get(idx) else { break };
+        path.push(idx);
+        current = node.parent;
+    }
+    path
+}
+
+/// Dispatch a JavaScript `Event` at `target`, returning `false` if a
+/// listener canceled it
+///
+/// The document is only borrowed to find the path, so listeners may use
+/// the bindings that change it.
+pub fn dispatch_event<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, target: usize, event: Object<'js>) -> rquickjs::Result<bool> {
+    let path = event_path(&document.borrow(), target);
+    let native: Object = ctx.globals().get("__cortexEvents")?;
+    let dispatch: Function = native.get("dispatch")?;
+    dispatch.call((path, event))
+}
+
+/// Create `new constructor(event_type, init)` from the page's globals, e.g.
+/// an `Event` or a `ClipboardEvent`
+pub fn create_event<'js>(ctx: &Ctx<'js>, constructor: &str, event_type: &str, init: Object<'js>) -> rquickjs::Result<Object<'js>> {
+    let native: Object = ctx.globals().get("__cortexEvents")?;
+    let create: Function = native.get("create")?;
+    create.call((constructor, event_type, init))
+}
+
+/// Event types with an inline handler attribute, `on` followed by the
+/// type, as HTML defines them; sorted for `binary_search`
+const HANDLER_EVENT_TYPES: &[&str] = &[
+    "abort", "afterprint", "animationcancel", "animationend", "animationiteration", "animationstart",
+    "auxclick", "beforeinput", "beforeprint", "beforetoggle", "beforeunload", "blur", "cancel", "canplay",
+    "canplaythrough", "change", "click", "close", "contextlost", "contextmenu", "contextrestored", "copy",
+    "cuechange", "cut", "dblclick", "drag", "dragend", "dragenter", "dragleave", "dragover", "dragstart",
+    "drop", "durationchange", "emptied", "ended", "error", "focus", "formdata", "hashchange", "input",
+    "invalid", "keydown", "keypress", "keyup", "languagechange", "load", "loadeddata", "loadedmetadata",
+    "loadstart", "message", "messageerror", "mousedown", "mouseenter", "mouseleave", "mousemove", "mouseout",
+    "mouseover", "mouseup", "offline", "online", "pagehide", "pageshow", "paste", "pause", "play", "playing",
+    "pointercancel", "pointerdown", "pointerenter", "pointerleave", "pointermove", "pointerout",
+    "pointerover", "pointerup", "popstate", "progress", "ratechange", "readystatechange", "rejectionhandled",
+    "reset", "resize", "scroll", "scrollend", "securitypolicyviolation", "seeked", "seeking", "select",
+    "selectionchange", "selectstart", "slotchange", "stalled", "storage", "submit", "suspend", "timeupdate",
+    "toggle", "touchcancel", "touchend", "touchmove", "touchstart", "transitioncancel", "transitionend",
+    "transitionrun", "transitionstart", "unhandledrejection", "unload", "visibilitychange", "volumechange",
+    "waiting", "wheel",
+];
+
+/// The (event type, code) of each event handler attribute of live element
+/// `idx`, e.g. `("click", "save()")` for `onclick="save()"`; other
+/// attributes starting with "on", such as `open`, are not handlers
+pub fn inline_handlers(document: &Document, idx: usize) -> Vec<(String, String)> {
+    if document.check(idx).is_err() {
+        return Vec::new();
+    }
+    match document.nodes[idx].data.as_ref() {
+        Some(NodeData::Element(element)) => element
+            .attributes
+            .iter()
+            .filter_map(|(name, code)| {
+                let event_type = name.strip_prefix("on").filter(|event_type| HANDLER_EVENT_TYPES.binary_search(event_type).is_ok())?;
+                Some((event_type.to_string(), code.clone()))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Bind, rebind or unbind `idx`'s inline handlers after its on* attributes
+/// changed
+pub fn bind_inline_handlers(ctx: &Ctx<'_>, idx: usize) -> rquickjs::Result<()> {
+    let native: Object = ctx.globals().get("__cortexEvents")?;
+    let bind: Function = native.get("bindInline")?;
+    bind.call((idx,))
+}
+
+/// `Event`, the listener registry and its dispatch, over the natives of
+/// `install_events`
+const EVENTS_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexEvents;
+    // Each node's listeners, as { id, type, listener, capture, once, passive }
+    const listeners = new Map();
+    // Each node's inline handlers by event type, as { code, compiled, listener }
+    const inline = new Map();
+    const flags = new WeakMap();
+    let epoch = null;
+
+    const callable = listener => typeof listener === "function" || (listener && typeof listener.handleEvent === "function");
+    // An `element(node)` wrapper stands for its node, while its document
+    // is the page's
+    const nodeOf = target => target !== null && typeof target === "object" && typeof target.node === "number" ? native.node(target) : target;
+    const captures = options => typeof options === "boolean" ? options : Boolean(options && options.capture);
+    const optionsOf = options => ({
+        capture: captures(options),
+        once: Boolean(options && typeof options === "object" && options.once),
+        passive: Boolean(options && typeof options === "object" && options.passive),
+    });
+
+    // The listeners of the current document's nodes
+    function registry() {
+        if (native.epoch() !== epoch) {
+            listeners.clear();
+            inline.clear();
+            epoch = native.epoch();
+            for (const idx of native.inlineNodes()) bindInline(idx);
+        }
+        return listeners;
+    }
+
+    // Match `idx`'s listeners for inline handlers to its on* attributes: a
+    // handler keeps its place among the listeners while its code changes,
+    // and is compiled when first called
+    function bindInline(idx) {
+        const current = new Map(native.inline(idx));
+        const handlers = inline.get(idx) || new Map();
+        for (const [type, handler] of handlers) {
+            if (current.has(type)) continue;
+            removeEventListener(idx, type, handler.listener);
+            handlers.delete(type);
+        }
+        for (const [type, code] of current) {
+            const known = handlers.get(type);
+            if (known) {
+                if (known.code !== code) Object.assign(known, { code, compiled: null });
+                continue;
+            }
+            const handler = { code, compiled: null };
+            handler.listener = function (event) {
+                handler.compiled ||= new Function("event", handler.code);
+                if (handler.compiled.call(element(idx), event) === false) event.preventDefault();
+            };
+            Object.defineProperty(handler.listener, "name", { value: "on" + type });
+            handlers.set(type, handler);
+            addEventListener(idx, type, handler.listener);
+        }
+        if (handlers.size) inline.set(idx, handlers);
+        else inline.delete(idx);
+    }
+
+    class Event {
+        constructor(type, init = {}) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to construct 'Event': 1 argument required, but only 0 present.");
+            }
+            flags.set(this, { stopped: false, stoppedImmediately: false, passive: false });
+            this.type = String(type);
+            this.bubbles = Boolean(init.bubbles);
+            this.cancelable = Boolean(init.cancelable);
+            this.composed = Boolean(init.composed);
+            this.defaultPrevented = false;
+            this.isTrusted = false;
+            this.target = null;
+            this.currentTarget = null;
+            this.eventPhase = Event.NONE;
+            this.timeStamp = 0;
+        }
+        preventDefault() {
+            if (this.cancelable && !flags.get(this).passive) this.defaultPrevented = true;
+        }
+        stopPropagation() {
+            flags.get(this).stopped = true;
+        }
+        stopImmediatePropagation() {
+            const state = flags.get(this);
+            state.stopped = true;
+            state.stoppedImmediately = true;
+        }
+    }
+    for (const [name, value] of Object.entries({ NONE: 0, CAPTURING_PHASE: 1, AT_TARGET: 2, BUBBLING_PHASE: 3 })) {
+        Object.defineProperty(Event, name, { value, enumerable: true });
+        Object.defineProperty(Event.prototype, name, { value, enumerable: true });
+    }
+
+    class CustomEvent extends Event {
+        constructor(type, init = {}) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to construct 'CustomEvent': 1 argument required, but only 0 present.");
+            }
+            super(type, init);
+            this.detail = init.detail === undefined ? null : init.detail;
+        }
+    }
+
+    function dispatch(path, event) {
+        const state = flags.get(event);
+        if (!state) throw new TypeError("Failed to execute 'dispatchEvent': parameter 2 is not of type 'Event'.");
+        registry();
+        for (const node of path) bindInline(node);
+        state.stopped = false;
+        state.stoppedImmediately = false;
+        event.target = path[0];
+        const steps = path.slice(1).reverse().map(node => [node, Event.CAPTURING_PHASE]);
+        steps.push([path[0], Event.AT_TARGET]);
+        if (event.bubbles) steps.push(...path.slice(1).map(node => [node, Event.BUBBLING_PHASE]));
+        for (const [node, phase] of steps) {
+            event.currentTarget = node;
+            event.eventPhase = phase;
+            const entries = (registry().get(node) || []).filter(entry => entry.type === event.type);
+            const due = phase === Event.AT_TARGET
+                ? [...entries.filter(entry => entry.capture), ...entries.filter(entry => !entry.capture)]
+                : entries.filter(entry => entry.capture === (phase === Event.CAPTURING_PHASE));
+            for (const entry of due) {
+                if (!native.has(node, entry.id)) {
+                    forget(node, entry);
+                    continue;
+                }
+                if (entry.once) {
+                    native.forget(entry.id);
+                    forget(node, entry);
+                }
+                const listener = entry.listener;
+                state.passive = entry.passive;
+                try {
+                    if (typeof listener === "function") listener.call(undefined, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    console.error("Uncaught", error);
+                }
+                state.passive = false;
+                if (state.stoppedImmediately) break;
+            }
+            if (state.stopped) break;
+        }
+        event.currentTarget = null;
+        event.eventPhase = Event.NONE;
+        return !event.defaultPrevented;
+    }
+
+    function forget(idx, entry) {
+        const entries = registry().get(idx);
+        if (!entries) return;
+        const remaining = entries.filter(e => e !== entry);
+        if (remaining.length) listeners.set(idx, remaining);
+        else listeners.delete(idx);
+    }
+
+    native.dispatch = dispatch;
+    native.bindInline = idx => { registry(); bindInline(idx); };
+    native.create = (constructor, type, init) => new globalThis[constructor](type, init);
+
+    globalThis.Event = Event;
+    globalThis.CustomEvent = CustomEvent;
+    const find = (idx, type, listener, capture) =>
+        (registry().get(idx) || []).find(e => e.type === type && e.listener === listener && e.capture === capture);
+
+    globalThis.addEventListener = (target, type, listener, options) => {
+        if (!callable(listener)) return;
+        const idx = nodeOf(target);
+        const { capture, once, passive } = optionsOf(options);
+        type = String(type);
+        if (find(idx, type, listener, capture)) return;
+        const id = native.record(idx, type, capture, once, passive);
+        registry().set(idx, [...(registry().get(idx) || []), { id, type, listener, capture, once, passive }]);
+    };
+    globalThis.removeEventListener = (target, type, listener, options) => {
+        const idx = nodeOf(target);
+        const entry = find(idx, String(type), listener, captures(options));
+        if (!entry) return;
+        native.forget(entry.id);
+        forget(idx, entry);
+    };
+    globalThis.dispatchEvent = (target, event) => dispatch(native.path(nodeOf(target)), typeof event === "string" ? new Event(event) : event);
+
+    // Introspection for tests, shaped like devtools' getEventListeners
+    globalThis.debug = Object.assign(globalThis.debug || {}, {
+        getEventListeners(target) {
+            const idx = nodeOf(target);
+            const entries = registry().get(idx) || [];
+            const byType = {};
+            for (const { id, type, capture, once, passive } of native.listeners(idx)) {
+                const entry = entries.find(e => e.id === id);
+                if (entry) (byType[type] ||= []).push({ type, listener: entry.listener, useCapture: capture, once, passive, id });
+            }
+            return byType;
+        },
+    });
+})();
+"#;
+
+/// `{ id, type, capture, once, passive }` for `native.listeners`
+fn listener_object<'js>(ctx: &Ctx<'js>, listener: ListenerInfo) -> rquickjs::Result<Object<'js>> {
+    let object = Object::new(ctx.clone())?;
+    object.set("id", listener.id)?;
+    object.set("type", listener.event_type)?;
+    object.set("capture", listener.options.capture)?;
+    object.set("once", listener.options.once)?;
+    object.set("passive", listener.options.passive)?;
+    Ok(object)
+}
+
+/// Install `Event`, `addEventListener`, `removeEventListener`,
+/// `dispatchEvent` and `debug.getEventListeners` for the nodes of
+/// `document`
+pub fn install_events<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+
+    let doc = document.clone();
+    native.set(
+        "record",
+        Function::new(
+            ctx.clone(),
+            move |ctx: Ctx<'js>, node: JsNode, event_type: String, capture: bool, once: bool, passive: bool| -> rquickjs::Result<Option<usize>> {
+                let mut document = doc.borrow_mut();
+                let idx = node.live(&ctx, &document)?;
+                Ok(document.add_event_listener(idx, &event_type, ListenerOptions { capture, once, passive }))
+            },
+        )?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "node",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<usize> { node.live(&ctx, &doc.borrow()) })?,
+    )?;
+    let doc = document.clone();
+    native.set("forget", Function::new(ctx.clone(), move |id: usize| doc.borrow_mut().remove_listener(id))?)?;
+    let doc = document.clone();
+    native.set("has", Function::new(ctx.clone(), move |idx: usize, id: usize| doc.borrow().has_listener(idx, id))?)?;
+    let doc = document.clone();
+    native.set(
+        "listeners",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Vec<Object<'js>>> {
+            let document = doc.borrow();
+            let idx = node.live(&ctx, &document)?;
+            document.listeners(idx).into_iter().map(|listener| listener_object(&ctx, listener)).collect()
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "path",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Vec<usize>> {
+            let document = doc.borrow();
+            Ok(event_path(&document, node.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set("inline", Function::new(ctx.clone(), move |idx: usize| {
+        inline_handlers(&doc.borrow(), idx).into_iter().map(|(event_type, code)| vec![event_type, code]).collect::<Vec<_>>()
+    })?)?;
+    let doc = document.clone();
+    native.set(
+        "inlineNodes",
+        Function::new(ctx.clone(), move || {
+            let document = doc.borrow();
+            (0..document.nodes.len()).filter(|&idx| !inline_handlers(&document, idx).is_empty()).collect::<Vec<_>>()
+        })?,
+    )?;
+    let doc = document;
+    native.set("epoch", Function::new(ctx.clone(), move || doc.borrow().epoch())?)?;
+
+    ctx.globals().set("__cortexEvents", native)?;
+    ctx.eval::<(), _>(EVENTS_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use rquickjs::{Context, Runtime};
+
+    #[test]
+    fn test_listeners_bubble_and_cancel() {
+        // Given: Listeners on a button and its ancestors
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        let document = Rc::new(RefCell::new(parse_html("<html><body><form><button>Save</button></form></body></html>")));
+        let (form, button) = {
+            let doc = document.borrow();
+            let find = |tag: &str| (0..doc.nodes.len()).find(|&i| crate::forms::tag_name(&doc, i) == Some(tag)).unwrap();
+            (find("form"), find("button"))
+        };
+        let body = document.borrow().nodes[form].parent.unwrap();
+
+        let (log, results): (Vec<String>, Vec<bool>) = context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            install_events(&ctx, document.clone()).unwrap();
+            ctx.globals().set("ids", vec![button, form, body]).unwrap();
+            ctx.eval::<(), _>(
+                    r#"
+                    const [button, form, body] = ids;
+                    globalThis.log = [];
+                    addEventListener(button, "save", e => log.push(`button ${e.target} ${e.eventPhase}`));
+                    addEventListener(button, "save", () => { throw new Error("listener failed"); });
+                    addEventListener(form, "save", e => { log.push("form " + (e.currentTarget === form)); e.preventDefault(); });
+                    addEventListener(body, "save", () => log.push("body"));
+                    addEventListener(form, "stop", e => { e.stopImmediatePropagation(); log.push("first"); });
+                    addEventListener(form, "stop", () => log.push("second"));
+                    addEventListener(body, "stop", () => log.push("never"));
+                    globalThis.results = [
+                        dispatchEvent(button, new Event("save", { bubbles: true, cancelable: true })),
+                        dispatchEvent(button, new Event("save")),
+                        dispatchEvent(form, new Event("stop", { bubbles: true })),
+                    ];
+                    "#,
+                )
+                .unwrap();
+            let log = (ctx.eval("log").unwrap(), ctx.eval("results").unwrap());
+            let event = create_event(&ctx, "Event", "save", Object::new(ctx.clone()).unwrap()).unwrap();
+            assert!(dispatch_event(&ctx, &document, button, event).unwrap());
+            log
+        });
+
+        // Then: Bubbling events reach ancestors and report cancellation
+        let button_entry = format!("button {} 2", button);
+        assert_eq!(log, [button_entry.as_str(), "form true", "body", button_entry.as_str(), "first"]);
+        assert_eq!(results, [false, true, true]);
+        assert!(document.borrow().listeners(form).iter().any(|listener| listener.event_type == "stop"));
+    }
+
+    #[test]
+    fn test_listeners_are_listed_and_removed_like_browsers() {
+        // Given: A page with a button
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><button data-testid="save">Save</button></body></html>"#);
+        let button = page.query("button").unwrap().unwrap();
+
+        // When: A script adds listeners, one twice, one for capture, and
+        // removes some by type, callback and capture flag
+        page.run_script(
+            r#"
+            globalThis.calls = [];
+            const button = getByTestId("save");
+            function onSave() { calls.push("save"); }
+            function onCapture() { calls.push("capture"); }
+            addEventListener(button, "click", onSave);
+            addEventListener(button, "click", onSave);
+            addEventListener(element(button), "click", onCapture, { capture: true });
+            addEventListener(button, "focus", onSave);
+            removeEventListener(button, "click", onCapture);
+            removeEventListener(button, "focus", onSave, false);
+            "#,
+        )
+        .unwrap();
+
+        // Then: Rust and scripts see the same listeners, once each
+        let listeners = page.document().listeners(button);
+        let summary: Vec<(&str, bool)> = listeners.iter().map(|l| (l.event_type.as_str(), l.options.capture)).collect();
+        assert_eq!(summary, [("click", false), ("click", true)]);
+        let listed = page
+            .run_script("JSON.stringify(Object.entries(debug.getEventListeners(getByTestId('save'))).map(([type, ls]) => [type, ls.map(l => [l.listener.name, l.useCapture])]))")
+            .unwrap();
+        assert_eq!(listed, r#"[["click",[["onSave",false],["onCapture",true]]]]"#);
+
+        // When: Rust removes the capture listener by id and the button is clicked
+        assert!(page.document_mut().remove_listener(listeners[1].id));
+        assert!(!page.document_mut().remove_listener(listeners[1].id));
+        page.run_script("dispatchEvent(getByTestId('save'), 'click')").unwrap();
+
+        // Then: Only the remaining listener runs
+        assert_eq!(page.run_script("calls.join()").unwrap(), "save");
+        assert_eq!(page.run_script("debug.getEventListeners(getByTestId('save')).click.length").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_capture_once_and_passive_options() {
+        // Given: A page with a button
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><button data-testid="save">Save</button></body></html>"#);
+
+        // When: Listeners are added in each phase, once, and passively, and
+        // two cancelable clicks are dispatched
+        let result = page
+            .run_script(
+                r#"
+                const button = getByTestId("save");
+                const log = [];
+                const at = name => e => log.push(`${name}:${e.eventPhase}`);
+                addEventListener(document.body, "click", at("body-bubble"));
+                addEventListener(document.body, "click", at("body-capture"), true);
+                addEventListener(button, "click", at("target"));
+                addEventListener(button, "click", at("target-capture"), { capture: true });
+                addEventListener(button, "click", at("once"), { once: true });
+                addEventListener(button, "click", e => e.preventDefault(), { passive: true });
+                const first = dispatchEvent(button, new Event("click", { bubbles: true, cancelable: true }));
+                log.push("|");
+                dispatchEvent(button, new Event("click", { cancelable: true }));
+                JSON.stringify([log, first, debug.getEventListeners(button).click.map(l => l.passive)])
+                "#,
+            )
+            .unwrap();
+
+        // Then: Capture runs top-down before the target, bubbling bottom-up
+        // after it; once runs once, and passive cannot cancel
+        assert_eq!(
+            result,
+            r#"[["body-capture:1","target-capture:2","target:2","once:2","body-bubble:3","|","body-capture:1","target-capture:2","target:2"],true,[false,false,true]]"#
+        );
+        let button = page.query("button").unwrap().unwrap();
+        assert!(page.document().listeners(button).iter().all(|listener| !listener.options.once));
+    }
+
+    #[test]
+    fn test_custom_events_carry_detail_between_components() {
+        // Given: A cart listening on its list for items picked by a product
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><ul data-testid="cart"><li><button data-testid="mug">Mug</button></li></ul></body></html>"#);
+
+        // When: The product dispatches a bubbling custom event with an object
+        // as its detail, through its element wrapper
+        let result = page
+            .run_script(
+                r#"
+                const cart = element(getByTestId("cart"));
+                const mug = element(getByTestId("mug"));
+                const payload = { sku: "mug", quantity: 2 };
+                let received;
+                cart.addEventListener("item-picked", e => { received = e; });
+                const event = new CustomEvent("item-picked", { bubbles: true, detail: payload });
+                mug.dispatchEvent(event);
+                JSON.stringify([
+                    received === event && received.detail === payload,
+                    received.detail,
+                    received instanceof Event,
+                    received.target === mug.node,
+                    new CustomEvent("plain").detail,
+                ])
+                "#,
+            )
+            .unwrap();
+
+        // Then: The cart got the same event with its detail intact
+        assert_eq!(result, r#"[true,{"sku":"mug","quantity":2},true,true,null]"#);
+        assert_eq!(page.run_script("try { new CustomEvent(); } catch (e) { e.name }").unwrap(), "TypeError");
+    }
+
+    #[test]
+    fn test_inline_handler_attributes_run_as_listeners() {
+        // Given: Markup with inline handlers, and a listener added after load
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(
+            r#"<html><body>
+                <button data-testid="save" onclick="log.push(event.type + ':' + this.tagName)">Save</button>
+                <a data-testid="link" href="/away" onclick="log.push('link'); return false">Away</a>
+                <input data-testid="name"/>
+            </body></html>"#,
+        );
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            addEventListener(getByTestId("save"), "click", () => log.push("listener"));
+            "#,
+        )
+        .unwrap();
+
+        // When: Both are clicked, and the handler's code is changed
+        let followed = page
+            .run_script(
+                r#"
+                dispatchEvent(getByTestId("save"), "click");
+                const followed = dispatchEvent(getByTestId("link"), new Event("click", { cancelable: true }));
+                setAttribute(getByTestId("save"), "onclick", "log.push('changed')");
+                dispatchEvent(getByTestId("save"), "click");
+                followed
+                "#,
+            )
+            .unwrap();
+
+        // Then: The handler ran first with the element as `this`, kept its
+        // place when changed, and `return false` canceled the link's click
+        assert_eq!(followed, "false");
+        assert_eq!(page.run_script("log.join()").unwrap(), "click:BUTTON,listener,link,changed,listener");
+        assert_eq!(page.run_script("debug.getEventListeners(getByTestId('save')).click[0].listener.name").unwrap(), "onclick");
+
+        // When: The handler is removed, and one is set from Rust
+        let input = page.query("input").unwrap().unwrap();
+        page.document_mut().set_attribute(input, "oninput", "log.push('typed ' + this.value)");
+        page.run_script(
+            r#"
+            log.length = 0;
+            removeAttribute(getByTestId("save"), "onclick");
+            dispatchEvent(getByTestId("save"), "click");
+            dispatchEvent(getByTestId("name"), "input");
+            "#,
+        )
+        .unwrap();
+
+        // Then: Only the listener and the new handler run
+        assert_eq!(page.run_script("log.join()").unwrap(), "listener,typed ");
+        assert_eq!(page.run_script("Object.keys(debug.getEventListeners(getByTestId('save'))).join()").unwrap(), "click");
+    }
+
+    #[test]
+    fn test_inline_handlers_see_events_the_page_fires() {
+        // Given: Inline handlers for an image's load and error, a field's
+        // change and invalid, and attributes that only start with "on"
+        let png = crate::screenshot::encode_rgba_png(&[255, 0, 0, 255], 1, 1).unwrap();
+        let network = crate::network::MockNetwork::new().with_response("https://shop.test/logo.png", png);
+        let page = crate::browser::PageBuilder::new()
+            .with_base_url("https://shop.test/")
+            .with_network(crate::browser::NetworkMode::Custom(Rc::new(network)))
+            .build()
+            .unwrap();
+        page.run_script("globalThis.log = [];").unwrap();
+        page.load_html(
+            r#"<html><body>
+                <img src="logo.png" onload="log.push('load ' + event.isTrusted)"/>
+                <img src="gone.png" onerror="log.push('error')"/>
+                <input data-testid="email" required onchange="log.push('change ' + this.value)" oninvalid="log.push('invalid')"/>
+                <details data-testid="faq" open one="x" onsave="log.push('save')"></details>
+            </body></html>"#,
+        );
+
+        // When: The field is filled, then emptied and checked, and the
+        // details get a custom event
+        let email = page.query("input").unwrap().unwrap();
+        page.fill(email, "ada@example.com").unwrap();
+        page.fill(email, "").unwrap();
+        let valid = page
+            .run_script(r#"const valid = checkValidity(getByTestId("email")); dispatchEvent(getByTestId("faq"), "save"); valid"#)
+            .unwrap();
+
+        // Then: Each event the page fired reached its handler, and `open`,
+        // `one` and `onsave` bound nothing
+        assert_eq!(valid, "false");
+        assert_eq!(page.run_script("log.join()").unwrap(), "load true,error,change ada@example.com,change ,invalid");
+        let details = page.query("details").unwrap().unwrap();
+        assert!(inline_handlers(&page.document(), details).is_empty());
+        assert_eq!(page.run_script("Object.keys(debug.getEventListeners(getByTestId('faq'))).length").unwrap(), "0");
+    }
+}
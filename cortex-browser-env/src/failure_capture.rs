@@ -0,0 +1,231 @@
+//! Failure Capture
+//! Saves a screenshot and DOM snapshot when a test fails, so headless
+//! failures can be inspected after the run
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::css::ComputedStyle;
+use crate::dom::Document;
+use crate::error::{BrowserError, TestResult};
+use crate::images::ImageCache;
+use crate::layout;
+use crate::render::render_styled_document;
+use crate::screenshot::save_screenshot;
+use crate::serialize::to_html;
+
+/// Environment variable read by `FailureCaptureConfig::from_env`
+pub const FAILURE_ARTIFACTS_ENV_VAR: &str = "CORTEX_FAILURE_ARTIFACTS";
+
+/// Where and whether to capture failure artifacts
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureCaptureConfig {
+    pub enabled: bool,
+    pub output_dir: PathBuf,
+    pub viewport_width: i32,
+    pub viewport_height: i32,
+    /// Also save the serialized DOM next to the screenshot
+    pub capture_dom: bool,
+}
+
+impl FailureCaptureConfig {
+    /// Capture screenshots and DOM snapshots into `test-failures/`
+    pub fn new() -> Self {
+        FailureCaptureConfig {
+            enabled: true,
+            output_dir: PathBuf::from("test-failures"),
+            viewport_width: 1280,
+            viewport_height: 720,
+            capture_dom: true,
+        }
+    }
+
+    /// Configure from `CORTEX_FAILURE_ARTIFACTS`: unset for the default
+    /// directory, `off` to disable, or any other value as the output directory
+    pub fn from_env() -> Self {
+        match std::env::var(FAILURE_ARTIFACTS_ENV_VAR) {
+            Ok(value) if matches!(value.trim(), "off" | "0" | "false") => Self::disabled(),
+            Ok(value) if !value.trim().is_empty() => Self::new().with_output_dir(value.trim()),
+            _ => Self::new(),
+        }
+    }
+
+    /// Never capture anything
+    pub fn disabled() -> Self {
+        FailureCaptureConfig { enabled: false, ..Self::new() }
+    }
+
+    pub fn with_output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = dir.into();
+        self
+    }
+
+    pub fn with_viewport(mut self, width: i32, height: i32) -> Self {
+        self.viewport_width = width;
+        self.viewport_height = height;
+        self
+    }
+
+    pub fn with_dom_snapshot(mut self, capture_dom: bool) -> Self {
+        self.capture_dom = capture_dom;
+        self
+    }
+}
+
+impl Default for FailureCaptureConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Files written for one failure
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FailureArtifacts {
+    pub screenshot: Option<PathBuf>,
+    pub dom_snapshot: Option<PathBuf>,
+}
+
+/// Turn a test name into a safe file stem, e.g. `Login form > submits` to
+/// `login-form-submits`
+pub fn artifact_stem(test_name: &str) -> String {
+    let mut stem = String::new();
+    for ch in test_name.chars() {
+        if ch.is_ascii_alphanumeric() {
+            stem.push(ch.to_ascii_lowercase());
+        } else if !stem.ends_with('-') && !stem.is_empty() {
+            stem.push('-');
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    if stem.is_empty() { "test".to_string() } else { stem.to_string() }
+}
+
+/// Capture the document as it is right now
+///
+/// The document is laid out at the configured viewport on a copy, so the
+/// caller's layout is left untouched. Missing styles fall back to defaults.
+pub fn capture_failure(
+    document: &Document,
+    styles: &[ComputedStyle],
+    test_name: &str,
+    config: &FailureCaptureConfig,
+) -> Result<FailureArtifacts, BrowserError> {
+    let mut artifacts = FailureArtifacts::default();
+    if !config.enabled {
+        return Ok(artifacts);
+    }
+
+    let stem = artifact_stem(test_name);
+    let mut snapshot = document.clone();
+    layout::calculate_layout(&mut snapshot, config.viewport_width as f32, config.viewport_height as f32);
+    let mut full_styles = styles.to_vec();
+    full_styles.resize(snapshot.nodes.len(), ComputedStyle::default());
+
+    let dt = render_styled_document(
+        &snapshot,
+        &full_styles,
+        &ImageCache::new(),
+        config.viewport_width,
+        config.viewport_height,
+    );
+    let screenshot_path = config.output_dir.join(format!("{}.png", stem));
+    save_screenshot(&dt, &screenshot_path).map_err(|e| BrowserError::ScreenshotError(e.to_string()))?;
+    artifacts.screenshot = Some(screenshot_path);
+
+    if config.capture_dom {
+        let dom_path = config.output_dir.join(format!("{}.html", stem));
+        write_file(&dom_path, &to_html(document, document.root))?;
+        artifacts.dom_snapshot = Some(dom_path);
+    }
+
+    Ok(artifacts)
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), BrowserError> {
+    fs::write(path, contents)
+        .map_err(|e| BrowserError::InvalidOperationError(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Attach failure artifacts to a failed result; passing results are returned
+/// unchanged
+///
+/// A capture error never hides the original failure: it is appended to the
+/// message instead.
+pub fn capture_on_failure(
+    result: TestResult,
+    document: &Document,
+    styles: &[ComputedStyle],
+    config: &FailureCaptureConfig,
+) -> TestResult {
+    if result.passed || !config.enabled {
+        return result;
+    }
+    match capture_failure(document, styles, &result.name, config) {
+        Ok(artifacts) => result.with_artifacts(artifacts.screenshot, artifacts.dom_snapshot),
+        Err(e) => {
+            let message = format!("{} (failure capture failed: {})", result.message, e);
+            TestResult { message, ..result }
+        }
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use crate::screenshot::decode_png;
+
+    #[test]
+    fn test_artifact_stem() {
+        assert_eq!(artifact_stem("Login form > submits"), "login-form-submits");
+        assert_eq!(artifact_stem("  ??  "), "test");
+    }
+
+    #[test]
+    fn test_failed_result_gets_screenshot_and_dom() {
+        // Given: A failing test over a small document
+        let dir = tempfile::tempdir().unwrap();
+        let config = FailureCaptureConfig::new().with_output_dir(dir.path()).with_viewport(64, 48);
+        let doc = parse_html("<div><p>Oops</p></div>");
+        let result = TestResult::failure_string("button shows label", "expected 'Save'");
+
+        // When: We capture on failure
+        let result = capture_on_failure(result, &doc, &[], &config);
+
+        // Then: Both artifacts exist and are attached to the result
+        let screenshot = result.screenshot_path.clone().unwrap();
+        let dom = result.dom_snapshot_path.clone().unwrap();
+        assert_eq!(screenshot, dir.path().join("button-shows-label.png"));
+        let (width, height, _) = decode_png(&fs::read(&screenshot).unwrap()).unwrap();
+        assert_eq!((width, height), (64, 48));
+        assert_eq!(fs::read_to_string(dom).unwrap(), "<div><p>Oops</p></div>");
+        assert!(doc.layouts().iter().all(Option::is_none), "Caller's document is not laid out");
+    }
+
+    #[test]
+    fn test_passing_and_disabled_capture_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = parse_html("<p>Fine</p>");
+
+        let passed = capture_on_failure(
+            TestResult::success("ok", "fine"),
+            &doc,
+            &[],
+            &FailureCaptureConfig::new().with_output_dir(dir.path()),
+        );
+        let disabled = capture_on_failure(
+            TestResult::failure_string("bad", "broken"),
+            &doc,
+            &[],
+            &FailureCaptureConfig::disabled().with_output_dir(dir.path()),
+        );
+
+        assert_eq!(passed.screenshot_path, None);
+        assert_eq!(disabled.screenshot_path, None);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,488 @@
+//! Files
+//! `Blob`, `File`, `FileList` and `FileReader` for scripts, and choosing the
+//! files of an `<input type="file">` from test code in place of the file
+//! dialog
+//!
+//! Chosen files are form state of their input, read by scripts through
+//! `getFiles(idx)`. Choosing fires `input` and `change` as the dialog
+//! would; like Playwright's `setInputFiles`, it ignores `accept`.
+//! `FileReader` results arrive in promise jobs rather than tasks, so they
+//! are ready once the page is idle.
+
+use std::cell::RefCell;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use rquickjs::{Array, ArrayBuffer, Ctx, Exception, Function, Object, TypedArray};
+
+use crate::dom::Document;
+use crate::events;
+use crate::forms;
+use crate::handles::JsNode;
+use crate::network;
+
+/// A file chosen in a file input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFile {
+    pub name: String,
+    /// `File.type`, guessed from the name's extension unless given
+    pub media_type: String,
+    pub bytes: Vec<u8>,
+    /// `File.lastModified` in milliseconds since the epoch; 0 unless given,
+    /// keeping runs reproducible
+    pub last_modified: u64,
+}
+
+impl InputFile {
+    pub fn new(name: &str, bytes: impl Into<Vec<u8>>) -> Self {
+        InputFile {
+            name: name.to_string(),
+            media_type: guess_media_type(name).to_string(),
+            bytes: bytes.into(),
+            last_modified: 0,
+        }
+    }
+
+    /// Read a file from disk, keeping its name and modification time
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let modified = std::fs::metadata(path)?.modified()?;
+        let last_modified = modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        Ok(InputFile::new(&name, bytes).with_last_modified(last_modified))
+    }
+
+    pub fn with_type(mut self, media_type: &str) -> Self {
+        self.media_type = media_type.to_string();
+        self
+    }
+
+    pub fn with_last_modified(mut self, millis: u64) -> Self {
+        self.last_modified = millis;
+        self
+    }
+}
+
+/// Media type for a file name's extension, empty when unknown as in
+/// browsers
+pub fn guess_media_type(name: &str) -> &'static str {
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "",
+    }
+}
+
+/// Check that `count` files can be chosen in `idx`: an enabled file input,
+/// with `multiple` for more than one
+pub fn check_file_input(document: &Document, idx: usize, count: usize) -> Result<(), String> {
+    if forms::tag_name(document, idx) != Some("input") || forms::input_type(document, idx) != "file" {
+        return Err(format!("Node {} is not an <input type=\"file\">", idx));
+    }
+    if forms::has_attribute(document, idx, "disabled") {
+        return Err(format!("File input {} is disabled", idx));
+    }
+    if count > 1 && !forms::has_attribute(document, idx, "multiple") {
+        return Err(format!("File input {} takes one file, not {}", idx, count));
+    }
+    Ok(())
+}
+
+/// Choose `files` in the file input `idx` as the file dialog would, firing
+/// `input` and `change`
+///
+/// Call `check_file_input` first; the choice is made whatever the input.
+pub fn choose_files<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, idx: usize, files: Vec<InputFile>) -> rquickjs::Result<()> {
+    document.borrow_mut().form_state_mut(idx).files = files;
+    for (event_type, composed) in [("input", true), ("change", false)] {
+        let init = Object::new(ctx.clone())?;
+        init.set("bubbles", true)?;
+        init.set("composed", composed)?;
+        let event = events::create_event(ctx, "Event", event_type, init)?;
+        event.set("isTrusted", true)?;
+        events::dispatch_event(ctx, document, idx, event)?;
+    }
+    Ok(())
+}
+
+/// `Blob`, `File`, `FileList`, `FileReader` and `getFiles`, over the
+/// natives of `install_files`
+const FILES_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexFiles;
+    const contents = new WeakMap();
+
+    function bytesOf(part) {
+        if (part instanceof Blob) return contents.get(part);
+        if (part instanceof ArrayBuffer) return new Uint8Array(part.slice(0));
+        if (ArrayBuffer.isView(part)) return new Uint8Array(part.buffer.slice(part.byteOffset, part.byteOffset + part.byteLength));
+        return new Uint8Array(native.encode(String(part)));
+    }
+
+    function normalizeType(type) {
+        type = type === undefined ? "" : String(type);
+        return /^[\x20-\x7e]*$/.test(type) ? type.toLowerCase() : "";
+    }
+
+    class Blob {
+        constructor(parts = [], options = {}) {
+            if (parts === null || typeof parts !== "object" || typeof parts[Symbol.iterator] !== "function") {
+                throw new TypeError("Failed to construct 'Blob': The provided value cannot be converted to a sequence.");
+            }
+            const chunks = Array.from(parts, bytesOf);
+            const bytes = new Uint8Array(chunks.reduce((length, chunk) => length + chunk.length, 0));
+            let offset = 0;
+            for (const chunk of chunks) {
+                bytes.set(chunk, offset);
+                offset += chunk.length;
+            }
+            contents.set(this, bytes);
+            this.type = normalizeType(options && options.type);
+        }
+        get size() { return contents.get(this).length; }
+        slice(start = 0, end = this.size, type = "") {
+            const bytes = contents.get(this).slice(start, end);
+            return new Blob([bytes], { type });
+        }
+        arrayBuffer() { return Promise.resolve(contents.get(this).slice().buffer); }
+        bytes() { return Promise.resolve(contents.get(this).slice()); }
+        text() { return Promise.resolve(native.decode(contents.get(this))); }
+        get [Symbol.toStringTag]() { return "Blob"; }
+    }
+
+    class File extends Blob {
+        constructor(bits, name, options = {}) {
+            if (arguments.length < 2) {
+                throw new TypeError(`Failed to construct 'File': 2 arguments required, but only ${arguments.length} present.`);
+            }
+            super(bits, options);
+            this.name = String(name);
+            this.lastModified = options && options.lastModified !== undefined ? Number(options.lastModified) : Date.now();
+            this.webkitRelativePath = "";
+        }
+        get [Symbol.toStringTag]() { return "File"; }
+    }
+
+    class FileList {
+        constructor(files) {
+            files.forEach((file, i) => { this[i] = file; });
+            Object.defineProperty(this, "length", { value: files.length });
+        }
+        item(index) { return this[index] || null; }
+        *[Symbol.iterator]() {
+            for (let i = 0; i < this.length; i++) yield this[i];
+        }
+    }
+
+    class FileReader {
+        #listeners = new Map();
+        #read = 0;
+
+        constructor() {
+            this.readyState = FileReader.EMPTY;
+            this.result = null;
+            this.error = null;
+            for (const type of ["loadstart", "progress", "load", "loadend", "error", "abort"]) this["on" + type] = null;
+        }
+        readAsArrayBuffer(blob) { this.#start(blob, bytes => bytes.slice().buffer); }
+        readAsText(blob) { this.#start(blob, bytes => native.decode(bytes)); }
+        readAsDataURL(blob) { this.#start(blob, bytes => native.dataUrl(blob.type || "application/octet-stream", bytes)); }
+        readAsBinaryString(blob) { this.#start(blob, bytes => Array.from(bytes, b => String.fromCharCode(b)).join("")); }
+        abort() {
+            if (this.readyState !== FileReader.LOADING) return;
+            this.#read++;
+            this.readyState = FileReader.DONE;
+            this.result = null;
+            this.#fire("abort");
+            this.#fire("loadend");
+        }
+        #start(blob, convert) {
+            if (!(blob instanceof Blob)) {
+                throw new TypeError("Failed to execute 'read' on 'FileReader': parameter 1 is not of type 'Blob'.");
+            }
+            if (this.readyState === FileReader.LOADING) {
+                const error = new Error("Failed to execute 'read' on 'FileReader': The object is already busy reading Blobs.");
+                error.name = "InvalidStateError";
+                throw error;
+            }
+            const read = ++this.#read;
+            this.readyState = FileReader.LOADING;
+            this.result = null;
+            this.error = null;
+            const bytes = contents.get(blob);
+            Promise.resolve().then(() => {
+                if (read !== this.#read) return;
+                this.#fire("loadstart");
+                this.#fire("progress", bytes.length);
+                if (read !== this.#read) return;
+                this.result = convert(bytes);
+                this.readyState = FileReader.DONE;
+                this.#fire("load", bytes.length);
+                this.#fire("loadend", bytes.length);
+            });
+        }
+        #fire(type, loaded = 0) {
+            const event = new Event(type);
+            Object.assign(event, { target: this, currentTarget: this, lengthComputable: true, loaded, total: loaded });
+            const handler = this["on" + type];
+            for (const listener of [...(typeof handler === "function" ? [handler] : []), ...(this.#listeners.get(type) || [])]) {
+                try {
+                    if (typeof listener === "function") listener.call(this, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    console.error("Uncaught", error);
+                }
+            }
+        }
+        addEventListener(type, listener) {
+            const listeners = this.#listeners.get(type) || [];
+            if (!listeners.includes(listener)) this.#listeners.set(type, [...listeners, listener]);
+        }
+        removeEventListener(type, listener) {
+            this.#listeners.set(type, (this.#listeners.get(type) || []).filter(l => l !== listener));
+        }
+    }
+    for (const [name, value] of Object.entries({ EMPTY: 0, LOADING: 1, DONE: 2 })) {
+        Object.defineProperty(FileReader, name, { value, enumerable: true });
+        Object.defineProperty(FileReader.prototype, name, { value, enumerable: true });
+    }
+
+    globalThis.Blob = Blob;
+    globalThis.File = File;
+    globalThis.FileList = FileList;
+    globalThis.FileReader = FileReader;
+    globalThis.getFiles = idx => new FileList(native.files(idx).map(([name, type, lastModified, buffer]) =>
+        new File([buffer], name, { type, lastModified })));
+    globalThis.setFiles = (idx, files) => native.choose(idx, Array.from(files, file => {
+        if (!(file instanceof File)) throw new TypeError("setFiles takes File objects");
+        return [file.name, file.type, file.lastModified, contents.get(file)];
+    }));
+})();
+"#;
+
+/// Install `Blob`, `File`, `FileList` and `FileReader`, plus index-based
+/// `getFiles(idx)` and `setFiles(idx, files)` for file inputs of `document`
+///
+/// Needs the `events` bindings installed first.
+pub fn install_files<'js>(ctx: &Ctx<'js>, document: std::rc::Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+
+    native.set(
+        "encode",
+        Function::new(ctx.clone(), |ctx: Ctx<'js>, text: String| ArrayBuffer::new(ctx, text.into_bytes()))?,
+    )?;
+    native.set(
+        "decode",
+        Function::new(ctx.clone(), |bytes: TypedArray<'js, u8>| {
+            String::from_utf8_lossy(bytes.as_bytes().unwrap_or_default()).into_owned()
+        })?,
+    )?;
+    native.set(
+        "dataUrl",
+        Function::new(ctx.clone(), |media_type: String, bytes: TypedArray<'js, u8>| {
+            network::encode_data_uri(&media_type, bytes.as_bytes().unwrap_or_default())
+        })?,
+    )?;
+
+    let doc = document.clone();
+    native.set(
+        "files",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Array<'js>> {
+            let files = {
+                let document = doc.borrow();
+                document.form_state(node.live(&ctx, &document)?).files.clone()
+            };
+            let list = Array::new(ctx.clone())?;
+            for (i, file) in files.into_iter().enumerate() {
+                let entry = Array::new(ctx.clone())?;
+                entry.set(0, file.name)?;
+                entry.set(1, file.media_type)?;
+                entry.set(2, file.last_modified as f64)?;
+                entry.set(3, ArrayBuffer::new(ctx.clone(), file.bytes)?)?;
+                list.set(i, entry)?;
+            }
+            Ok(list)
+        })?,
+    )?;
+    let doc = document;
+    native.set(
+        "choose",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, entries: Vec<Array<'js>>| -> rquickjs::Result<()> {
+            let idx = node.live(&ctx, &doc.borrow())?;
+            let mut files = Vec::new();
+            for entry in entries {
+                let bytes: TypedArray<u8> = entry.get(3)?;
+                let name: String = entry.get(0)?;
+                let file = InputFile::new(&name, bytes.as_bytes().unwrap_or_default())
+                    .with_type(&entry.get::<String>(1)?)
+                    .with_last_modified(entry.get::<f64>(2)?.max(0.0) as u64);
+                files.push(file);
+            }
+            let checked = check_file_input(&doc.borrow(), idx, files.len());
+            checked.map_err(|message| Exception::throw_type(&ctx, &message))?;
+            choose_files(&ctx, &doc, idx, files)
+        })?,
+    )?;
+
+    ctx.globals().set("__cortexFiles", native)?;
+    ctx.eval::<(), _>(FILES_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use rquickjs::{Context, Runtime};
+    use std::rc::Rc;
+
+    /// A context over `html` with file and event bindings
+    fn setup(html: &str) -> (Runtime, Context, Rc<RefCell<Document>>) {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        let document = Rc::new(RefCell::new(parse_html(html)));
+        context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            events::install_events(&ctx, document.clone()).unwrap();
+            install_files(&ctx, document.clone()).unwrap();
+            forms::install_form_bindings(&ctx, document.clone()).unwrap();
+        });
+        (runtime, context, document)
+    }
+
+    fn eval<T: for<'js> rquickjs::FromJs<'js>>(runtime: &Runtime, context: &Context, source: &str) -> T {
+        context.with(|ctx| ctx.eval::<(), _>(source).unwrap());
+        while runtime.execute_pending_job().unwrap() {}
+        context.with(|ctx| ctx.eval("result").unwrap())
+    }
+
+    #[test]
+    fn test_blob_and_file() {
+        let (runtime, context, _) = setup("<html><body></body></html>");
+
+        let result: Vec<String> = eval(
+            &runtime,
+            &context,
+            r#"
+            const blob = new Blob(["héllo ", new Uint8Array([119, 111]), new Blob(["rld"])], { type: "Text/Plain" });
+            const file = new File([blob.slice(0, 6)], "greeting.txt", { lastModified: 42 });
+            globalThis.result = [blob.size, blob.type, file.name, file.size, file.lastModified, file instanceof Blob, String(file)].map(String);
+            blob.text().then(text => result.push(text));
+            file.arrayBuffer().then(buffer => result.push(new Uint8Array(buffer).join()));
+            "#,
+        );
+
+        assert_eq!(
+            result,
+            ["12", "text/plain", "greeting.txt", "6", "42", "true", "[object File]", "héllo world", "104,195,169,108,108,111"]
+        );
+    }
+
+    #[test]
+    fn test_file_reader() {
+        let (runtime, context, _) = setup("<html><body></body></html>");
+
+        let result: Vec<String> = eval(
+            &runtime,
+            &context,
+            r#"
+            globalThis.result = [];
+            const file = new File(["hi"], "a.txt", { type: "text/plain" });
+            const reader = new FileReader();
+            reader.addEventListener("loadstart", () => result.push("loadstart " + reader.readyState));
+            reader.onload = e => {
+                result.push(`load ${e.loaded} ${reader.result}`);
+                const second = new FileReader();
+                second.onloadend = () => result.push("data " + second.result);
+                second.readAsDataURL(file);
+            };
+            reader.readAsText(file);
+            result.push("reading " + reader.readyState);
+            try { reader.readAsText(file); } catch (e) { result.push(e.name); }
+
+            const aborted = new FileReader();
+            aborted.onload = () => result.push("never");
+            aborted.onabort = () => result.push("abort " + aborted.result);
+            aborted.readAsArrayBuffer(file);
+            aborted.abort();
+            "#,
+        );
+
+        assert_eq!(
+            result,
+            ["reading 1", "InvalidStateError", "abort null", "loadstart 1", "load 2 hi", "data data:text/plain;base64,aGk="]
+        );
+    }
+
+    #[test]
+    fn test_choosing_files() {
+        let (runtime, context, document) = setup(
+            r#"<html><body><form><input type="file" id="one" /><input type="file" multiple="" id="many" /><input id="text" /></form></body></html>"#,
+        );
+        let inputs: Vec<usize> = {
+            let doc = document.borrow();
+            (0..doc.nodes.len()).filter(|&i| forms::tag_name(&doc, i) == Some("input")).collect()
+        };
+        let (one, many, text) = (inputs[0], inputs[1], inputs[2]);
+
+        // Given: Rust chooses a file, as a test would
+        let file = InputFile::new("avatar.PNG", vec![137, 80, 78, 71]).with_last_modified(1000);
+        assert_eq!(file.media_type, "image/png");
+        assert!(check_file_input(&document.borrow(), one, 2).is_err());
+        assert!(check_file_input(&document.borrow(), text, 1).is_err());
+        check_file_input(&document.borrow(), one, 1).unwrap();
+
+        let result: Vec<String> = context.with(|ctx| {
+            ctx.globals().set("ids", vec![one, many]).unwrap();
+            ctx.eval::<(), _>(
+                r#"
+                const [one, many] = ids;
+                globalThis.result = [];
+                addEventListener(one, "input", () => result.push("input"));
+                addEventListener(one, "change", () => {
+                    const [file] = getFiles(one);
+                    result.push(`change ${file.name} ${file.type} ${file.size} ${file.lastModified} ${getValue(one)}`);
+                });
+                "#,
+            )
+            .unwrap();
+            choose_files(&ctx, &document, one, vec![file]).unwrap();
+
+            // When: Scripts choose files themselves
+            ctx.eval::<(), _>(
+                r#"
+                setFiles(many, [new File(["a"], "a.csv"), new File(["b"], "b.csv", { type: "text/csv" })]);
+                result.push(Array.from(getFiles(many), f => f.name + ":" + f.type).join());
+                try { setFiles(one, [new File([], "1"), new File([], "2")]); } catch (e) { result.push(e.message); }
+                "#,
+            )
+            .unwrap();
+            ctx.eval("result").unwrap()
+        });
+        while runtime.execute_pending_job().unwrap() {}
+
+        // Then: Both reached the inputs' state and listeners
+        let one_text = format!("File input {} takes one file, not 2", one);
+        assert_eq!(result, ["input", "change avatar.PNG image/png 4 1000 C:\\fakepath\\avatar.PNG", "a.csv:,b.csv:text/csv", one_text.as_str()]);
+        assert_eq!(document.borrow().form_state(many).files.len(), 2);
+    }
+}
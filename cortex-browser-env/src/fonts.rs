@@ -1,10 +1,148 @@
-/// Font rendering module for Cortex browser engine
+//! Font rendering module for Cortex browser engine
+//!
+//! Provides font management, glyph rasterization, and caching
+//! using the fontdue library for pure Rust font rendering.
+//!
+//! Fonts from `@font-face` rules and the `FontFace` script API load
+//! synchronously through the page's network mode, so `document.fonts.ready`
+//! is settled by the time scripts can await it. Text in a loaded family is
+//! painted with the font's own glyphs, and characters it lacks come from
+//! the fallback fonts, e.g. an emoji font, before the built-in glyphs.
+//! Layout keeps measuring with the default metrics, so a font arriving
+//! never moves boxes.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::OnceLock;
+use fontdue::{Font, Metrics};
+use rquickjs::{ArrayBuffer, Ctx, Function, Object};
+
+use crate::css::{font_faces, parse_font_face_src, StyleSheet};
+use crate::network::{fetch, ResourceLoader};
+use crate::text::{break_lines, WordBreaking};
+
+/// Embedded default font data (DejaVu Sans Mono)
+const DEFAULT_FONT_DATA: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+
+/// Vertical font metrics used to build line boxes
 ///
-/// Provides font management, glyph rasterization, and caching
-/// using the fontdue library for pure Rust font rendering.
+/// All values are positive distances in pixels at a given font size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineMetrics {
+    /// Distance from the baseline up to the top of the tallest glyph
+    pub ascent: f32,
+    /// Distance from the baseline down to the bottom of the lowest glyph
+    pub descent: f32,
+    /// Extra space the font designer recommends between lines
+    pub line_gap: f32,
+}
 
-use std::collections::HashMap;
-use fontdue::Font;
+impl LineMetrics {
+    /// Height of a line box using the font's normal line height
+    pub fn line_height(&self) -> f32 {
+        self.ascent + self.descent + self.line_gap
+    }
+
+    /// Offset of the baseline from the top of a line box of the given height
+    ///
+    /// The leading (line height minus ascent and descent) is split evenly
+    /// above and below the glyphs, as CSS does.
+    pub fn baseline_in(&self, line_height: f32) -> f32 {
+        let half_leading = (line_height - self.ascent - self.descent) / 2.0;
+        half_leading + self.ascent
+    }
+
+    /// Scale metrics measured at one size to another size
+    pub fn scale(&self, factor: f32) -> Self {
+        LineMetrics {
+            ascent: self.ascent * factor,
+            descent: self.descent * factor,
+            line_gap: self.line_gap * factor,
+        }
+    }
+}
+
+/// Line metrics of the embedded default font at `size_px`
+///
+/// Metrics scale linearly with size, so the font is parsed once and the
+/// per-pixel metrics are cached for layout, which has no FontManager.
+pub fn default_line_metrics(size_px: f32) -> LineMetrics {
+    static UNIT_METRICS: OnceLock<LineMetrics> = OnceLock::new();
+    let unit = UNIT_METRICS.get_or_init(|| {
+        Font::from_bytes(DEFAULT_FONT_DATA, Default::default())
+            .ok()
+            .and_then(|font| font_line_metrics(&font, 1.0))
+            // Typical sans-serif proportions if the font lacks hhea metrics
+            .unwrap_or(LineMetrics { ascent: 0.8, descent: 0.2, line_gap: 0.0 })
+    });
+    unit.scale(size_px)
+}
+
+/// Placement of text decoration lines relative to the baseline
+///
+/// Offsets are in pixels, measured to the top edge of each line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecorationMetrics {
+    /// Distance below the baseline to the top of the underline
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    /// Distance above the baseline to the top of the line-through
+    pub strikeout_offset: f32,
+    pub strikeout_thickness: f32,
+}
+
+impl DecorationMetrics {
+    /// Scale metrics measured at one size to another size
+    pub fn scale(&self, factor: f32) -> Self {
+        DecorationMetrics {
+            underline_offset: self.underline_offset * factor,
+            underline_thickness: self.underline_thickness * factor,
+            strikeout_offset: self.strikeout_offset * factor,
+            strikeout_thickness: self.strikeout_thickness * factor,
+        }
+    }
+}
+
+/// Decoration metrics of the embedded default font at `size_px`
+///
+/// Read from the font's `post` (underline) and `OS/2` (strikeout) tables,
+/// which fontdue does not expose.
+pub fn default_decoration_metrics(size_px: f32) -> DecorationMetrics {
+    static UNIT_METRICS: OnceLock<DecorationMetrics> = OnceLock::new();
+    let unit = UNIT_METRICS.get_or_init(|| {
+        // Typical proportions if the font lacks the tables
+        let fallback = DecorationMetrics {
+            underline_offset: 0.1,
+            underline_thickness: 0.05,
+            strikeout_offset: 0.3,
+            strikeout_thickness: 0.05,
+        };
+        let Ok(face) = ttf_parser::Face::parse(DEFAULT_FONT_DATA, 0) else {
+            return fallback;
+        };
+        let units = face.units_per_em() as f32;
+        let underline = face.underline_metrics();
+        let strikeout = face.strikeout_metrics();
+        DecorationMetrics {
+            underline_offset: underline.map_or(fallback.underline_offset, |m| -m.position as f32 / units),
+            underline_thickness: underline.map_or(fallback.underline_thickness, |m| m.thickness as f32 / units),
+            strikeout_offset: strikeout.map_or(fallback.strikeout_offset, |m| m.position as f32 / units),
+            strikeout_thickness: strikeout.map_or(fallback.strikeout_thickness, |m| m.thickness as f32 / units),
+        }
+    });
+    unit.scale(size_px)
+}
+
+/// Read horizontal line metrics from a font at `size_px`
+fn font_line_metrics(font: &Font, size_px: f32) -> Option<LineMetrics> {
+    font.horizontal_line_metrics(size_px).map(|m| LineMetrics {
+        ascent: m.ascent,
+        descent: -m.descent,
+        line_gap: m.line_gap,
+    })
+}
 
 /// Represents a rasterized glyph bitmap
 #[derive(Debug, Clone)]
@@ -19,6 +157,113 @@ pub struct GlyphBitmap {
     pub advance_width: f32,
 }
 
+/// Size of a run of text in a font, as `measureText` reports it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextMetrics {
+    /// Advance width of the widest line
+    pub width: f32,
+    /// Distance from the baseline up to the top of the font's glyphs
+    pub ascent: f32,
+    /// Distance from the baseline down to the bottom of the font's glyphs
+    pub descent: f32,
+    /// Number of lines the text breaks into
+    pub line_count: usize,
+}
+
+/// Split a canvas `font` value like `bold 20px "Brand Sans", serif` into
+/// its size in pixels and its family list
+///
+/// Style and weight keywords before the size are skipped. A value without
+/// a pixel size falls back to 10px sans-serif, as canvas does.
+pub fn parse_canvas_font(font: &str) -> (f32, String) {
+    let mut words = font.split_whitespace();
+    for word in words.by_ref() {
+        // A `size/line-height` pair keeps only the size
+        let size = word.split('/').next().unwrap_or(word);
+        if let Some(size) = size.strip_suffix("px").and_then(|px| px.parse::<f32>().ok()).filter(|px| px.is_finite() && *px > 0.0) {
+            let family = words.collect::<Vec<_>>().join(" ");
+            return (size, if family.is_empty() { "sans-serif".to_string() } else { family });
+        }
+    }
+    (10.0, "sans-serif".to_string())
+}
+
+/// A font loaded for a family by `@font-face` or `FontFace`
+pub struct WebFont {
+    pub family: String,
+    font: Font,
+}
+
+impl WebFont {
+    /// Whether the font has a glyph for `ch`, rather than its missing-glyph box
+    pub fn has_glyph(&self, ch: char) -> bool {
+        self.font.lookup_glyph_index(ch) != 0
+    }
+
+    /// Rasterize `ch` at `size_px` into a coverage bitmap, one byte per pixel
+    pub fn rasterize(&self, ch: char, size_px: f32) -> (Metrics, Vec<u8>) {
+        self.font.rasterize(ch, size_px)
+    }
+}
+
+impl fmt::Debug for WebFont {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebFont").field("family", &self.family).finish_non_exhaustive()
+    }
+}
+
+/// Fonts are equal only to themselves: reloading a family gives a new font
+/// even when the name stays the same
+impl PartialEq for WebFont {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// The loaded web fonts, keyed by lowercased family, and the fallback
+/// fonts characters missing from them are looked up in
+#[derive(Debug, Clone, Default)]
+pub struct WebFonts {
+    fonts: HashMap<String, Rc<WebFont>>,
+    fallbacks: Vec<Rc<WebFont>>,
+}
+
+impl WebFonts {
+    fn insert(&mut self, family: &str, font: Font) {
+        let family = unquote_family(family);
+        self.fonts.insert(family.to_lowercase(), Rc::new(WebFont { family: family.to_string(), font }));
+    }
+
+    /// Check whether a family has been loaded
+    pub fn contains(&self, family: &str) -> bool {
+        self.fonts.contains_key(&unquote_family(family).to_lowercase())
+    }
+
+    /// The first loaded family in a `font-family` list, if any
+    pub fn get(&self, families: &str) -> Option<Rc<WebFont>> {
+        families.split(',').find_map(|family| self.fonts.get(&unquote_family(family).to_lowercase())).cloned()
+    }
+
+    /// The fonts text in a `font-family` list looks each character up in:
+    /// its first loaded family, then the fallback fonts
+    ///
+    /// Characters none of them have are left to the built-in glyphs.
+    pub fn fallback_chain(&self, families: Option<&str>) -> Vec<Rc<WebFont>> {
+        families.and_then(|families| self.get(families)).into_iter().chain(self.fallbacks.iter().cloned()).collect()
+    }
+}
+
+/// The embedded default font, parsed once, for characters that the
+/// built-in glyphs and the page's fonts lack
+pub fn default_font() -> Option<&'static Font> {
+    static DEFAULT_FONT: OnceLock<Option<Font>> = OnceLock::new();
+    DEFAULT_FONT.get_or_init(|| Font::from_bytes(DEFAULT_FONT_DATA, Default::default()).ok()).as_ref()
+}
+
+fn unquote_family(family: &str) -> &str {
+    family.trim().trim_matches(['"', '\''])
+}
+
 /// Manages fonts and glyph rasterization
 ///
 /// The FontManager loads a default embedded font and provides
@@ -26,6 +271,16 @@ pub struct GlyphBitmap {
 pub struct FontManager {
     default_font: Font,
     glyph_cache: HashMap<(char, u32), GlyphBitmap>,
+    /// Fonts loaded from `@font-face` rules and `FontFace`
+    web_fonts: WebFonts,
+}
+
+/// Outcome of loading one `@font-face` rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFaceLoad {
+    pub family: String,
+    /// The source that loaded, or why every source failed
+    pub result: Result<String, String>,
 }
 
 impl FontManager {
@@ -35,16 +290,124 @@ impl FontManager {
     /// A new FontManager instance or an error if font loading fails
     pub fn new() -> Result<Self, String> {
         // Load embedded DejaVu Sans Mono font
-        let font_data = include_bytes!("../assets/DejaVuSansMono.ttf");
-        let font = Font::from_bytes(font_data.to_vec(), Default::default())
+        let font = Font::from_bytes(DEFAULT_FONT_DATA, Default::default())
             .map_err(|e| format!("Failed to load embedded font: {}", e))?;
 
         Ok(FontManager {
             default_font: font,
             glyph_cache: HashMap::new(),
+            web_fonts: WebFonts::default(),
         })
     }
 
+    /// Load the fonts declared by a stylesheet's `@font-face` rules
+    ///
+    /// Sources are tried in order and the first that fetches and parses
+    /// wins. `data:` URIs are decoded inline without touching the loader.
+    ///
+    /// # Arguments
+    /// * `stylesheet` - Stylesheet containing `@font-face` rules
+    /// * `loader` - Source for non-inline font files
+    ///
+    /// # Returns
+    /// One entry per rule, recording the source used or the last error
+    pub fn load_font_faces(&mut self, stylesheet: &StyleSheet, loader: &dyn ResourceLoader) -> Vec<FontFaceLoad> {
+        font_faces(stylesheet)
+            .into_iter()
+            .map(|face| {
+                let result = self.load_font(&face.family, &face.sources, loader);
+                FontFaceLoad { family: face.family, result }
+            })
+            .collect()
+    }
+
+    /// Load a family from the first of `sources` that fetches and parses
+    ///
+    /// # Returns
+    /// The source used, or the last source's error
+    pub fn load_font(&mut self, family: &str, sources: &[String], loader: &dyn ResourceLoader) -> Result<String, String> {
+        let mut result = Err(format!("No sources for font family '{}'", family));
+        for url in sources {
+            let loaded = fetch(loader, url)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    Font::from_bytes(bytes, Default::default())
+                        .map_err(|e| format!("Failed to parse font {}: {}", url, e))
+                });
+            match loaded {
+                Ok(font) => {
+                    self.web_fonts.insert(family, font);
+                    return Ok(url.clone());
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+
+    /// Register a font under a family name, as if loaded by `@font-face`
+    pub fn add_font(&mut self, family: &str, bytes: &[u8]) -> Result<(), String> {
+        let font = Font::from_bytes(bytes, Default::default())
+            .map_err(|e| format!("Failed to parse font for '{}': {}", family, e))?;
+        self.web_fonts.insert(family, font);
+        Ok(())
+    }
+
+    /// Add a font to look up characters in when the text's own font lacks
+    /// them, e.g. an emoji font; fallbacks are tried in the order added
+    pub fn add_fallback_font(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let family = format!("fallback {}", self.web_fonts.fallbacks.len() + 1);
+        let font = Font::from_bytes(bytes, Default::default())
+            .map_err(|e| format!("Failed to parse {} font: {}", family, e))?;
+        self.web_fonts.fallbacks.push(Rc::new(WebFont { family, font }));
+        Ok(())
+    }
+
+    /// Check whether a `@font-face` family has been loaded
+    pub fn has_font_family(&self, family: &str) -> bool {
+        self.web_fonts.contains(family)
+    }
+
+    /// The loaded web fonts, for painting text in them
+    pub fn web_fonts(&self) -> &WebFonts {
+        &self.web_fonts
+    }
+
+    /// The font for a `font-family` list: the first loaded `@font-face`
+    /// family in it, or the default font
+    fn font_for_family(&self, family: &str) -> &Font {
+        family
+            .split(',')
+            .find_map(|name| self.web_fonts.fonts.get(&unquote_family(name).to_lowercase()))
+            .map_or(&self.default_font, |web_font| &web_font.font)
+    }
+
+    /// Measure text the way layout would set it
+    ///
+    /// # Arguments
+    /// * `text` - Text to measure; newlines always start a new line
+    /// * `size_px` - Font size in pixels
+    /// * `family` - A `font-family` list; families not loaded fall back to
+    ///   the default font
+    /// * `max_width` - Width to wrap lines at, or `None` to only break at
+    ///   newlines; words too long for a line break between characters
+    ///
+    /// # Returns
+    /// The widest line's width, the font's ascent and descent, and the
+    /// number of lines
+    pub fn measure_text(&self, text: &str, size_px: f32, family: &str, max_width: Option<f32>) -> TextMetrics {
+        let font = self.font_for_family(family);
+        let advance = |ch: char| font.metrics(ch, size_px).advance_width;
+        let lines = break_lines(text, max_width.unwrap_or(f32::INFINITY), WordBreaking::Overlong, advance);
+        let metrics = font_line_metrics(font, size_px).unwrap_or_else(|| default_line_metrics(size_px));
+        TextMetrics {
+            width: lines.iter().map(|line| line.chars().map(advance).sum::<f32>()).fold(0.0, f32::max),
+            ascent: metrics.ascent,
+            descent: metrics.descent,
+            line_count: lines.len(),
+        }
+    }
+
     /// Rasterize a glyph to a bitmap
     ///
     /// # Arguments
@@ -90,15 +453,27 @@ impl FontManager {
         metrics.advance_width
     }
 
-    /// Get the height of a character
+    /// Get the height of a line of text
     ///
     /// # Arguments
     /// * `size_px` - Font size in pixels
     ///
     /// # Returns
-    /// The height in pixels (typically same as font size)
+    /// The normal line height in pixels (ascent + descent + line gap)
     pub fn line_height(&self, size_px: u32) -> f32 {
-        size_px as f32 * 1.2  // Approximate line height (120% of font size)
+        self.line_metrics(size_px as f32).line_height()
+    }
+
+    /// Get the vertical line metrics of the default font
+    ///
+    /// # Arguments
+    /// * `size_px` - Font size in pixels
+    ///
+    /// # Returns
+    /// Ascent, descent and line gap in pixels
+    pub fn line_metrics(&self, size_px: f32) -> LineMetrics {
+        font_line_metrics(&self.default_font, size_px)
+            .unwrap_or_else(|| default_line_metrics(size_px))
     }
 
     /// Clear the glyph cache to free memory
@@ -127,6 +502,107 @@ impl Default for FontManager {
     }
 }
 
+/// `FontFace` and `document.fonts` over the natives in `__cortexFonts`
+///
+/// Loads finish synchronously, so `ready` is always settled and `status`
+/// is always `"loaded"`. A face's family is usable as soon as it loads;
+/// adding it to `document.fonts` only lists it there.
+const FONT_LOADING_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexFonts;
+    delete globalThis.__cortexFonts;
+    const faces = new Set();
+
+    class FontFace {
+        #source;
+        #loaded;
+        #settle;
+
+        constructor(family, source, descriptors = {}) {
+            this.family = String(family).trim().replace(/^["']|["']$/g, "");
+            this.style = descriptors.style ?? "normal";
+            this.weight = descriptors.weight ?? "normal";
+            this.display = descriptors.display ?? "auto";
+            this.status = "unloaded";
+            this.#source = source;
+            this.#loaded = new Promise((resolve, reject) => { this.#settle = { resolve, reject }; });
+            // Faces made from bytes have nothing to fetch, so they load right away
+            if (typeof source !== "string") this.load();
+        }
+        get loaded() { return this.#loaded; }
+        load() {
+            if (this.status !== "unloaded") return this.#loaded;
+            this.status = "loading";
+            const source = this.#source;
+            const error = typeof source === "string"
+                ? native.loadUrls(this.family, source)
+                : native.loadBytes(this.family, ArrayBuffer.isView(source) ? source.buffer.slice(source.byteOffset, source.byteOffset + source.byteLength) : source);
+            if (error == null) {
+                this.status = "loaded";
+                this.#settle.resolve(this);
+            } else {
+                this.status = "error";
+                this.#settle.reject(Object.assign(new Error(error), { name: "NetworkError" }));
+            }
+            return this.#loaded;
+        }
+    }
+
+    class FontFaceSet {
+        get status() { return "loaded"; }
+        get ready() { return Promise.resolve(this); }
+        get size() { return faces.size; }
+        add(face) { faces.add(face); return this; }
+        delete(face) { return faces.delete(face); }
+        has(face) { return faces.has(face); }
+        clear() { faces.clear(); }
+        forEach(callback, thisArg) { faces.forEach(face => callback.call(thisArg, face, face, this)); }
+        values() { return faces.values(); }
+        [Symbol.iterator]() { return faces.values(); }
+        #matching(font) {
+            const families = native.families(String(font));
+            return [...faces].filter(face => families.includes(face.family.toLowerCase()));
+        }
+        check(font) { return this.#matching(font).every(face => face.status === "loaded"); }
+        load(font) { return Promise.all(this.#matching(font).map(face => face.load())); }
+    }
+
+    globalThis.FontFace = FontFace;
+    globalThis.FontFaceSet = FontFaceSet;
+    globalThis.document.fonts = new FontFaceSet();
+})();
+"#;
+
+/// Install `FontFace` and `document.fonts`, loading into `fonts` through
+/// `loader`
+///
+/// Needs the `document` global to exist.
+pub fn install_font_loading(ctx: &Ctx<'_>, fonts: Rc<RefCell<FontManager>>, loader: Rc<dyn ResourceLoader>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let url_fonts = fonts.clone();
+    native.set(
+        "loadUrls",
+        Function::new(ctx.clone(), move |family: String, source: String| {
+            url_fonts.borrow_mut().load_font(&family, &parse_font_face_src(&source), &*loader).err()
+        })?,
+    )?;
+    native.set(
+        "loadBytes",
+        Function::new(ctx.clone(), move |family: String, bytes: ArrayBuffer<'_>| match bytes.as_bytes() {
+            Some(bytes) => fonts.borrow_mut().add_font(&family, bytes).err(),
+            None => Some("The font data was detached".to_string()),
+        })?,
+    )?;
+    native.set(
+        "families",
+        Function::new(ctx.clone(), |font: String| -> Vec<String> {
+            parse_canvas_font(&font).1.split(',').map(|family| unquote_family(family).to_lowercase()).collect()
+        })?,
+    )?;
+    ctx.globals().set("__cortexFonts", native)?;
+    ctx.eval::<(), _>(FONT_LOADING_PRELUDE)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,6 +658,156 @@ mod tests {
         assert!(height > 16.0, "Line height should be greater than font size");
     }
 
+    #[test]
+    fn test_line_metrics_from_font() {
+        let fm = FontManager::new().expect("Failed to create FontManager");
+        let metrics = fm.line_metrics(16.0);
+
+        assert!(metrics.ascent > 0.0, "Ascent should be positive");
+        assert!(metrics.descent > 0.0, "Descent should be a positive distance");
+        assert!(metrics.ascent > metrics.descent, "Ascent should exceed descent");
+        assert_eq!(fm.line_height(16), metrics.line_height());
+    }
+
+    #[test]
+    fn test_default_line_metrics_scale_linearly() {
+        let small = default_line_metrics(10.0);
+        let large = default_line_metrics(20.0);
+
+        assert!((large.ascent - small.ascent * 2.0).abs() < 1e-3);
+        assert!((large.line_height() - small.line_height() * 2.0).abs() < 1e-3);
+
+        let fm = FontManager::new().expect("Failed to create FontManager");
+        assert!((fm.line_metrics(20.0).ascent - large.ascent).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_baseline_splits_leading() {
+        let metrics = LineMetrics { ascent: 12.0, descent: 4.0, line_gap: 0.0 };
+
+        assert_eq!(metrics.baseline_in(16.0), 12.0);
+        assert_eq!(metrics.baseline_in(24.0), 16.0); // 4px half-leading above
+    }
+
+    #[test]
+    fn test_decoration_metrics_from_font() {
+        let metrics = default_decoration_metrics(20.0);
+        let line = default_line_metrics(20.0);
+
+        // Underline sits just below the baseline, line-through inside the ascent
+        assert!(metrics.underline_offset > 0.0 && metrics.underline_offset < line.descent);
+        assert!(metrics.strikeout_offset > 0.0 && metrics.strikeout_offset < line.ascent);
+        assert!(metrics.underline_thickness > 0.0 && metrics.strikeout_thickness > 0.0);
+        assert_eq!(default_decoration_metrics(40.0), metrics.scale(2.0));
+    }
+
+    #[test]
+    fn test_load_font_face_from_data_uri() {
+        use crate::css::parse_css;
+        use crate::network::{encode_data_uri, MockNetwork};
+
+        // Given: A @font-face whose first source is missing and second is inline
+        let css = format!(
+            "@font-face {{ font-family: 'Inline Mono'; src: url(/missing.ttf), url({}); }}",
+            encode_data_uri("font/ttf", DEFAULT_FONT_DATA)
+        );
+        let network = MockNetwork::new();
+        let mut fm = FontManager::new().unwrap();
+
+        // When: We load the stylesheet's fonts
+        let loads = fm.load_font_faces(&parse_css(&css), &network);
+
+        // Then: The inline source is used after the missing one fails
+        assert_eq!(loads.len(), 1);
+        assert!(loads[0].result.as_ref().unwrap().starts_with("data:font/ttf;base64,"));
+        assert!(fm.has_font_family("inline mono"));
+        assert!(!fm.has_font_family("Other"));
+        assert_eq!(network.requests(), vec!["/missing.ttf"]);
+    }
+
+    #[test]
+    fn test_load_font_face_reports_failure() {
+        use crate::css::parse_css;
+        use crate::network::MockNetwork;
+
+        let css = "@font-face { font-family: Broken; src: url('data:font/ttf,not%20a%20font'); }";
+        let mut fm = FontManager::new().unwrap();
+
+        let loads = fm.load_font_faces(&parse_css(css), &MockNetwork::new());
+
+        assert!(loads[0].result.is_err());
+        assert!(!fm.has_font_family("Broken"));
+    }
+
+    #[test]
+    fn test_add_font() {
+        let mut fm = FontManager::new().unwrap();
+
+        fm.add_font("Brand Sans", DEFAULT_FONT_DATA).unwrap();
+
+        assert!(fm.has_font_family("'Brand Sans'"));
+        assert!(fm.add_font("Broken", b"not a font").is_err());
+        assert!(!fm.has_font_family("Broken"));
+    }
+
+    #[test]
+    fn test_measure_text() {
+        // Given: The default font, which is monospaced
+        let fm = FontManager::new().unwrap();
+        let advance = fm.char_advance('a', 20);
+
+        // When: We measure a line, then the same text wrapped
+        let line = fm.measure_text("aaa bbb", 20.0, "monospace", None);
+        let wrapped = fm.measure_text("aaa bbb\nc", 20.0, "monospace", Some(advance * 5.0));
+
+        // Then: One line is as wide as its characters, and wrapping at five
+        // characters gives three lines as wide as the longest word
+        assert!((line.width - advance * 7.0).abs() < 1e-3);
+        assert_eq!(line.line_count, 1);
+        assert_eq!(line.ascent, fm.line_metrics(20.0).ascent);
+        assert_eq!(wrapped.line_count, 3);
+        assert!((wrapped.width - advance * 3.0).abs() < 1e-3);
+        assert_eq!(fm.measure_text("", 20.0, "monospace", None).line_count, 0);
+    }
+
+    #[test]
+    fn test_measure_text_uses_loaded_families() {
+        // Given: A family loaded under a name, listed after one that is not
+        let mut fm = FontManager::new().unwrap();
+        fm.add_font("Brand Sans", DEFAULT_FONT_DATA).unwrap();
+
+        // Then: The loaded family measures like the font it was loaded from
+        let loaded = fm.measure_text("Hello", 16.0, "Missing, 'Brand Sans', serif", None);
+        assert_eq!(loaded, fm.measure_text("Hello", 16.0, "sans-serif", None));
+        assert!(loaded.width > 0.0);
+    }
+
+    #[test]
+    fn test_web_fonts_pick_the_first_loaded_family() {
+        // Given: Two families loaded from the same file
+        let mut fm = FontManager::new().unwrap();
+        fm.add_font("\"Brand Sans\"", DEFAULT_FONT_DATA).unwrap();
+        fm.add_font("Icons", DEFAULT_FONT_DATA).unwrap();
+        let fonts = fm.web_fonts();
+
+        // Then: A family list resolves to the first one loaded, by its
+        // unquoted name, and each load is a font of its own
+        let family = |list: &str| fonts.get(list).map(|font| font.family.clone());
+        assert_eq!(family("Missing, 'brand sans', Icons"), Some("Brand Sans".to_string()));
+        assert_eq!(family("serif"), None);
+        assert_ne!(fonts.get("Icons"), fonts.get("Brand Sans"));
+        assert_eq!(fonts.get("icons"), fonts.get("Icons"));
+        assert!(fonts.get("Icons").unwrap().has_glyph('A'));
+    }
+
+    #[test]
+    fn test_parse_canvas_font() {
+        assert_eq!(parse_canvas_font("bold 20px \"Brand Sans\", serif"), (20.0, "\"Brand Sans\", serif".to_string()));
+        assert_eq!(parse_canvas_font("12.5px/1.5 monospace"), (12.5, "monospace".to_string()));
+        assert_eq!(parse_canvas_font("16px"), (16.0, "sans-serif".to_string()));
+        assert_eq!(parse_canvas_font("large serif"), (10.0, "sans-serif".to_string()));
+    }
+
     #[test]
     fn test_cache_clear() {
         let mut fm = FontManager::new().expect("Failed to create FontManager");
@@ -0,0 +1,784 @@
+//! Form Controls
+//! Interactive state for checkboxes, radios, selects and text fields, the
+//! matching IDL properties for JavaScript, and form serialization
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::dom::{Document, NodeData};
+use crate::events;
+use crate::files::InputFile;
+use crate::handles::JsNode;
+
+/// Dirty state of a form control, overriding its content attributes
+///
+/// A browser only reads `checked`, `selected` and `value` attributes until
+/// the user (or script) changes the control; after that the live state wins.
+/// `None` means the control is still reflecting its attribute.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormState {
+    pub checked: Option<bool>,
+    pub selected: Option<bool>,
+    pub value: Option<String>,
+    /// Files chosen in a file input
+    pub files: Vec<InputFile>,
+}
+
+pub(crate) fn tag_name(document: &Document, idx: usize) -> Option<&str> {
+    match document.nodes.get(idx).and_then(|n| n.data.as_ref()) {
+        Some(NodeData::Element(elem)) => Some(elem.tag_name.as_str()),
+        _ => None,
+    }
+}
+
+pub(crate) fn has_attribute(document: &Document, idx: usize, name: &str) -> bool {
+    document.get_attribute(idx, name).is_some()
+}
+
+/// Lowercased `type` of an `<input>`, defaulting to `text`
+pub(crate) fn input_type(document: &Document, idx: usize) -> String {
+    document
+        .get_attribute(idx, "type")
+        .map(|t| t.trim().to_ascii_lowercase())
+        .unwrap_or_else(|| "text".to_string())
+}
+
+pub(crate) fn is_checkable(document: &Document, idx: usize) -> bool {
+    tag_name(document, idx) == Some("input") && matches!(input_type(document, idx).as_str(), "checkbox" | "radio")
+}
+
+pub(crate) fn is_radio(document: &Document, idx: usize) -> bool {
+    tag_name(document, idx) == Some("input") && input_type(document, idx) == "radio"
+}
+
+/// A textarea or an input holding text, i.e. neither a checkbox nor a radio
+pub(crate) fn is_text_field(document: &Document, idx: usize) -> bool {
+    matches!(tag_name(document, idx), Some("input") | Some("textarea")) && !is_checkable(document, idx)
+}
+
+/// A text field the user can type into: neither disabled nor read-only
+pub(crate) fn is_editable_text_field(document: &Document, idx: usize) -> bool {
+    is_text_field(document, idx) && !has_attribute(document, idx, "disabled") && !has_attribute(document, idx, "readonly")
+}
+
+/// Element indices below `idx` in tree order, excluding `idx` itself
+pub(crate) fn descendants(document: &Document, idx: usize) -> Vec<usize> {
+    let mut result = Vec::new();
+    let mut stack: Vec<usize> = document.nodes[idx].children.iter().rev().copied().collect();
+    while let Some(current) = stack.pop() {
+        result.push(current);
+        stack.extend(document.nodes[current].children.iter().rev());
+    }
+    result
+}
+
+/// The `<form>` a control belongs to: its nearest form ancestor
+pub fn form_owner(document: &Document, idx: usize) -> Option<usize> {
+    let mut current = document.nodes.get(idx)?.parent;
+    while let Some(ancestor) = current {
+        if tag_name(document, ancestor) == Some("form") {
+            return Some(ancestor);
+        }
+        current = document.nodes[ancestor].parent;
+    }
+    None
+}
+
+/// Topmost ancestor, used as the radio group scope for controls outside a form
+fn tree_root(document: &Document, idx: usize) -> usize {
+    let mut current = idx;
+    while let Some(parent) = document.nodes[current].parent {
+        current = parent;
+    }
+    current
+}
+
+// ============================================================================
+// CHECKBOXES AND RADIOS
+// ============================================================================
+
+/// Checkedness of a checkbox or radio button
+pub fn is_checked(document: &Document, idx: usize) -> bool {
+    if !is_checkable(document, idx) {
+        return false;
+    }
+    document
+        .form_state(idx)
+        .checked
+        .unwrap_or_else(|| has_attribute(document, idx, "checked"))
+}
+
+/// Set checkedness like the `checked` IDL property; does not fire events
+///
+/// Checking a radio button unchecks the other buttons in its group: radios
+/// with the same `name` in the same form (or outside any form).
+pub fn set_checked(document: &mut Document, idx: usize, checked: bool) {
+    if !is_checkable(document, idx) {
+        return;
+    }
+    if checked && is_radio(document, idx) {
+        for other in radio_group(document, idx) {
+            if other != idx {
+                document.form_state_mut(other).checked = Some(false);
+            }
+        }
+    }
+    document.form_state_mut(idx).checked = Some(checked);
+}
+
+/// Radio buttons sharing a group with `idx`, including itself
+pub(crate) fn radio_group(document: &Document, idx: usize) -> Vec<usize> {
+    let Some(name) = document.get_attribute(idx, "name").filter(|n| !n.is_empty()) else {
+        return vec![idx];
+    };
+    let owner = form_owner(document, idx);
+    let scope = owner.unwrap_or_else(|| tree_root(document, idx));
+    descendants(document, scope)
+        .into_iter()
+        .filter(|&other| {
+            is_radio(document, other)
+                && document.get_attribute(other, "name") == Some(name)
+                && form_owner(document, other) == owner
+        })
+        .collect()
+}
+
+// ============================================================================
+// SELECTS
+// ============================================================================
+
+/// `<option>` elements of a select, including those inside `<optgroup>`
+pub fn options(document: &Document, select_idx: usize) -> Vec<usize> {
+    if tag_name(document, select_idx) != Some("select") {
+        return Vec::new();
+    }
+    descendants(document, select_idx)
+        .into_iter()
+        .filter(|&idx| tag_name(document, idx) == Some("option"))
+        .collect()
+}
+
+fn option_selectedness(document: &Document, option_idx: usize) -> bool {
+    document
+        .form_state(option_idx)
+        .selected
+        .unwrap_or_else(|| has_attribute(document, option_idx, "selected"))
+}
+
+/// Check whether an `<option>` is selected, accounting for a single-select's
+/// fallback to its first enabled option
+pub fn is_selected(document: &Document, option_idx: usize) -> bool {
+    let mut current = document.nodes.get(option_idx).and_then(|n| n.parent);
+    while let Some(ancestor) = current {
+        if tag_name(document, ancestor) == Some("select") {
+            return selected_options(document, ancestor).contains(&option_idx);
+        }
+        current = document.nodes[ancestor].parent;
+    }
+    option_selectedness(document, option_idx)
+}
+
+/// Selected options of a select, in tree order
+///
+/// A single-select shows at most one option: the last one marked selected.
+/// Until script or the user changes the selection, a single-select with no
+/// `selected` attribute falls back to its first option that is not disabled.
+pub fn selected_options(document: &Document, select_idx: usize) -> Vec<usize> {
+    let options = options(document, select_idx);
+    let selected: Vec<usize> = options.iter().copied().filter(|&o| option_selectedness(document, o)).collect();
+    if has_attribute(document, select_idx, "multiple") {
+        return selected;
+    }
+    if let Some(&last) = selected.last() {
+        return vec![last];
+    }
+    if options.iter().any(|&o| document.form_state(o).selected.is_some()) {
+        return Vec::new();
+    }
+    options
+        .into_iter()
+        .find(|&o| !has_attribute(document, o, "disabled"))
+        .into_iter()
+        .collect()
+}
+
+/// The `selectedIndex` IDL property: index of the first selected option, or -1
+pub fn selected_index(document: &Document, select_idx: usize) -> i32 {
+    let options = options(document, select_idx);
+    selected_options(document, select_idx)
+        .first()
+        .and_then(|selected| options.iter().position(|o| o == selected))
+        .map_or(-1, |i| i as i32)
+}
+
+/// Set `selectedIndex`; any out-of-range index deselects every option.
+/// Does not fire events.
+pub fn set_selected_index(document: &mut Document, select_idx: usize, index: i32) {
+    for (i, option) in options(document, select_idx).into_iter().enumerate() {
+        document.form_state_mut(option).selected = Some(i as i32 == index);
+    }
+}
+
+/// Value of an `<option>`: its `value` attribute, or its whitespace-collapsed text
+fn option_value(document: &Document, option_idx: usize) -> String {
+    match document.get_attribute(option_idx, "value") {
+        Some(value) => value.clone(),
+        None => document
+            .text_content(option_idx)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+// ============================================================================
+// VALUES
+// ============================================================================
+
+/// The `value` IDL property of an input, textarea, select or option
+pub fn value(document: &Document, idx: usize) -> String {
+    let state = document.form_state(idx);
+    match tag_name(document, idx) {
+        Some("input") if is_checkable(document, idx) => document
+            .get_attribute(idx, "value")
+            .cloned()
+            .unwrap_or_else(|| "on".to_string()),
+        Some("input") if input_type(document, idx) == "file" => state
+            .files
+            .first()
+            .map(|file| format!("C:\\fakepath\\{}", file.name))
+            .unwrap_or_default(),
+        Some("input") => state
+            .value
+            .clone()
+            .or_else(|| document.get_attribute(idx, "value").cloned())
+            .unwrap_or_default(),
+        Some("textarea") => state.value.clone().unwrap_or_else(|| document.text_content(idx)),
+        Some("select") => selected_options(document, idx)
+            .first()
+            .map(|&option| option_value(document, option))
+            .unwrap_or_default(),
+        Some("option") => option_value(document, idx),
+        _ => String::new(),
+    }
+}
+
+/// Set the `value` IDL property; does not fire events
+///
+/// For a select this selects the first option with a matching value, or
+/// none. Checkbox and radio values are their `value` attribute. A file
+/// input's value can only be cleared, which drops its files.
+pub fn set_value(document: &mut Document, idx: usize, new_value: &str) {
+    match tag_name(document, idx) {
+        Some("input") if is_checkable(document, idx) => document.set_attribute(idx, "value", new_value),
+        Some("input") if input_type(document, idx) == "file" && new_value.is_empty() => {
+            document.form_state_mut(idx).files.clear();
+        }
+        Some("input") if input_type(document, idx) == "file" => {}
+        Some("input") | Some("textarea") => {
+            document.form_state_mut(idx).value = Some(new_value.to_string());
+        }
+        Some("select") => {
+            let options = options(document, idx);
+            let index = options
+                .iter()
+                .position(|&o| option_value(document, o) == new_value)
+                .map_or(-1, |i| i as i32);
+            set_selected_index(document, idx, index);
+        }
+        Some("option") => document.set_attribute(idx, "value", new_value),
+        _ => {}
+    }
+}
+
+// ============================================================================
+// USER INTERACTION
+// ============================================================================
+
+/// Fire trusted `input` then `change` at `idx`, as a user edit would
+fn fire_input_and_change<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, idx: usize) -> rquickjs::Result<()> {
+    for (event_type, composed) in [("input", true), ("change", false)] {
+        let init = Object::new(ctx.clone())?;
+        init.set("bubbles", true)?;
+        init.set("composed", composed)?;
+        let event = events::create_event(ctx, "Event", event_type, init)?;
+        event.set("isTrusted", true)?;
+        events::dispatch_event(ctx, document, idx, event)?;
+    }
+    Ok(())
+}
+
+/// Checkedness a click set before dispatching `click`, kept so that a
+/// canceled click can put it back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PreActivation {
+    target: usize,
+    changed: bool,
+    /// The dirty checkedness of the target and, for a radio, its group
+    saved: Vec<(usize, Option<bool>)>,
+}
+
+/// Run the legacy-pre-activation behavior of a checkbox or radio button:
+/// checkboxes toggle and radios become checked before `click` is
+/// dispatched, so listeners see the new state
+///
+/// `None` for other elements and disabled controls.
+pub(crate) fn pre_activate(document: &mut Document, idx: usize) -> Option<PreActivation> {
+    if !is_checkable(document, idx) || has_attribute(document, idx, "disabled") {
+        return None;
+    }
+    let group = if is_radio(document, idx) { radio_group(document, idx) } else { vec![idx] };
+    let saved = group.into_iter().map(|node| (node, document.form_state(node).checked)).collect();
+    let was_checked = is_checked(document, idx);
+    let checked = if is_radio(document, idx) { true } else { !was_checked };
+    set_checked(document, idx, checked);
+    Some(PreActivation { target: idx, changed: checked != was_checked, saved })
+}
+
+/// Undo `pre_activate` after a listener canceled the click
+pub(crate) fn cancel_activation(document: &mut Document, pre_activation: PreActivation) {
+    for (node, checked) in pre_activation.saved {
+        document.form_state_mut(node).checked = checked;
+    }
+}
+
+/// Finish a click that was not canceled, firing `input` and `change` if
+/// the checkedness changed. Returns whether it did.
+pub(crate) fn finish_activation<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, pre_activation: PreActivation) -> rquickjs::Result<bool> {
+    if pre_activation.changed {
+        fire_input_and_change(ctx, document, pre_activation.target)?;
+    }
+    Ok(pre_activation.changed)
+}
+
+/// Click a checkbox or radio button as a user would, without dispatching
+/// `click` itself
+///
+/// Checkboxes toggle and radios become checked. `input` and `change` fire
+/// only when the checkedness actually changed. Returns whether it did.
+pub fn activate<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, idx: usize) -> rquickjs::Result<bool> {
+    let pre_activation = pre_activate(&mut document.borrow_mut(), idx);
+    match pre_activation {
+        Some(pre_activation) => finish_activation(ctx, document, pre_activation),
+        None => Ok(false),
+    }
+}
+
+/// Pick an option of a select as a user would, firing `input` and `change`
+/// if the selection changed. Returns whether it did.
+pub fn select_option<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, select_idx: usize, index: i32) -> rquickjs::Result<bool> {
+    let changed = {
+        let mut document = document.borrow_mut();
+        if has_attribute(&document, select_idx, "disabled") {
+            return Ok(false);
+        }
+        let before = selected_options(&document, select_idx);
+        set_selected_index(&mut document, select_idx, index);
+        selected_options(&document, select_idx) != before
+    };
+    if changed {
+        fire_input_and_change(ctx, document, select_idx)?;
+    }
+    Ok(changed)
+}
+
+/// Replace the text of an input or textarea as a user would, firing `input`
+/// and then `change` on commit. Returns whether the value changed.
+pub fn input_value<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, idx: usize, new_value: &str) -> rquickjs::Result<bool> {
+    let changed = {
+        let mut document = document.borrow_mut();
+        if !is_text_field(&document, idx) || has_attribute(&document, idx, "disabled") {
+            return Ok(false);
+        }
+        let changed = value(&document, idx) != new_value;
+        set_value(&mut document, idx, new_value);
+        changed
+    };
+    if changed {
+        fire_input_and_change(ctx, document, idx)?;
+    }
+    Ok(changed)
+}
+
+// ============================================================================
+// SERIALIZATION
+// ============================================================================
+
+/// Name/value pairs a `<form>` would submit, in tree order, like `FormData`
+///
+/// Disabled and unnamed controls are skipped, as are unchecked checkboxes and
+/// radios, buttons (there is no submitter) and file inputs.
+pub fn serialize_form(document: &Document, form_idx: usize) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    if tag_name(document, form_idx) != Some("form") {
+        return entries;
+    }
+
+    for idx in descendants(document, form_idx) {
+        let Some(tag) = tag_name(document, idx) else { continue };
+        if !matches!(tag, "input" | "select" | "textarea") || has_attribute(document, idx, "disabled") {
+            continue;
+        }
+        let Some(name) = document.get_attribute(idx, "name").filter(|n| !n.is_empty()).cloned() else {
+            continue;
+        };
+
+        match tag {
+            "input" => {
+                let kind = input_type(document, idx);
+                if matches!(kind.as_str(), "submit" | "button" | "reset" | "image" | "file") {
+                    continue;
+                }
+                if is_checkable(document, idx) && !is_checked(document, idx) {
+                    continue;
+                }
+                entries.push((name, value(document, idx)));
+            }
+            "select" => {
+                for option in selected_options(document, idx) {
+                    if !has_attribute(document, option, "disabled") {
+                        entries.push((name.clone(), option_value(document, option)));
+                    }
+                }
+            }
+            _ => entries.push((name, value(document, idx))),
+        }
+    }
+    entries
+}
+
+/// Encode entries as `application/x-www-form-urlencoded`
+pub fn to_url_encoded(entries: &[(String, String)]) -> String {
+    fn encode(text: &str) -> String {
+        let mut out = String::new();
+        for byte in text.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => out.push(byte as char),
+                b' ' => out.push('+'),
+                _ => out.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        out
+    }
+
+    entries
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// Expose form control properties to JavaScript as index-based globals
+///
+/// - `getChecked(idx)` / `setChecked(idx, checked)`
+/// - `getSelectedIndex(idx)` / `setSelectedIndex(idx, index)`
+/// - `getValue(idx)` / `setValue(idx, value)`
+/// - `serializeForm(idx)`, returning `[name, value]` pairs
+///
+/// Like their IDL counterparts, the setters do not fire `change` events.
+/// Each throws for an index that is not a live node; see `handles`.
+pub fn install_form_bindings<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    let globals = ctx.globals();
+
+    let doc = document.clone();
+    globals.set(
+        "getChecked",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<bool> {
+            let document = doc.borrow();
+            Ok(is_checked(&document, node.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "setChecked",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, checked: bool| -> rquickjs::Result<()> {
+            let mut document = doc.borrow_mut();
+            let idx = node.live(&ctx, &document)?;
+            set_checked(&mut document, idx, checked);
+            Ok(())
+        })?,
+    )?;
+
+    let doc = document.clone();
+    globals.set(
+        "getSelectedIndex",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<i32> {
+            let document = doc.borrow();
+            Ok(selected_index(&document, node.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "setSelectedIndex",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, index: i32| -> rquickjs::Result<()> {
+            let mut document = doc.borrow_mut();
+            let idx = node.live(&ctx, &document)?;
+            set_selected_index(&mut document, idx, index);
+            Ok(())
+        })?,
+    )?;
+
+    let doc = document.clone();
+    globals.set(
+        "getValue",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<String> {
+            let document = doc.borrow();
+            Ok(value(&document, node.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    globals.set(
+        "setValue",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode, new_value: String| -> rquickjs::Result<()> {
+            let mut document = doc.borrow_mut();
+            let idx = node.live(&ctx, &document)?;
+            set_value(&mut document, idx, &new_value);
+            Ok(())
+        })?,
+    )?;
+
+    let doc = document;
+    globals.set(
+        "serializeForm",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Vec<Vec<String>>> {
+            let document = doc.borrow();
+            let fields = serialize_form(&document, node.live(&ctx, &document)?);
+            Ok(fields.into_iter().map(|(name, value)| vec![name, value]).collect())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Append a new element with attributes to `parent`
+    fn add(doc: &mut Document, parent: usize, tag: &str, attrs: &[(&str, &str)]) -> usize {
+        let idx = doc.create_element(tag);
+        for (name, value) in attrs {
+            doc.set_attribute(idx, name, value);
+        }
+        doc.append_child(parent, idx);
+        idx
+    }
+
+    /// Run `f` over `doc` in a context whose events reach script, handing the
+    /// document back afterwards
+    fn with_events(doc: Document, f: impl FnOnce(&Ctx<'_>, &RefCell<Document>)) -> Document {
+        let document = Rc::new(RefCell::new(doc));
+        let runtime = rquickjs::Runtime::new().unwrap();
+        let context = rquickjs::Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            events::install_events(&ctx, document.clone()).unwrap();
+            f(&ctx, &document);
+        });
+        document.take()
+    }
+
+    fn add_option(doc: &mut Document, select: usize, text: &str, attrs: &[(&str, &str)]) -> usize {
+        let option = add(doc, select, "option", attrs);
+        let text_idx = doc.create_text_node(text);
+        doc.append_child(option, text_idx);
+        option
+    }
+
+    #[test]
+    fn test_checkbox_reflects_attribute_until_changed() {
+        // Given: A checkbox checked by attribute
+        let mut doc = Document::new();
+        let root = doc.root;
+        let checkbox = add(&mut doc, root, "input", &[("type", "checkbox"), ("checked", "")]);
+        assert!(is_checked(&doc, checkbox));
+
+        // When: The user clicks it
+        let doc = with_events(doc, |ctx, document| assert!(activate(ctx, document, checkbox).unwrap()));
+
+        // Then: The live state wins over the attribute
+        assert!(!is_checked(&doc, checkbox));
+        assert_eq!(doc.get_attribute(checkbox, "checked"), Some(&String::new()));
+    }
+
+    #[test]
+    fn test_form_state_is_kept_off_untouched_nodes_and_copied_by_clones() {
+        // Given: A checkbox the user checked, and one left alone
+        let mut doc = Document::new();
+        let root = doc.root;
+        let changed = add(&mut doc, root, "input", &[("type", "checkbox")]);
+        let untouched = add(&mut doc, root, "input", &[("type", "checkbox")]);
+        doc.form_state_mut(changed).checked = Some(true);
+
+        // When: Both are cloned, and one imported into another document
+        let changed_copy = doc.clone_node(changed, false).unwrap();
+        let untouched_copy = doc.clone_node(untouched, false).unwrap();
+        let mut other = Document::new();
+        let imported = other.import_node(&doc, changed, false).unwrap();
+
+        // Then: The copies keep the live state, and untouched controls
+        // read the default without an entry of their own
+        assert!(is_checked(&doc, changed_copy) && is_checked(&other, imported));
+        assert!(!is_checked(&doc, untouched_copy));
+        assert_eq!(doc.form_state(untouched), &FormState::default());
+    }
+
+    #[test]
+    fn test_radio_group_is_exclusive_per_form() {
+        // Given: Two forms, each with a "size" radio group
+        let mut doc = Document::new();
+        let root = doc.root;
+        let form_a = add(&mut doc, root, "form", &[]);
+        let form_b = add(&mut doc, root, "form", &[]);
+        let small = add(&mut doc, form_a, "input", &[("type", "radio"), ("name", "size"), ("checked", "")]);
+        let large = add(&mut doc, form_a, "input", &[("type", "radio"), ("name", "size")]);
+        let other = add(&mut doc, form_b, "input", &[("type", "radio"), ("name", "size"), ("checked", "")]);
+
+        // When: The user picks "large", then picks it again
+        let doc = with_events(doc, |ctx, document| {
+            assert!(activate(ctx, document, large).unwrap());
+            assert!(!activate(ctx, document, large).unwrap(), "Clicking a checked radio changes nothing");
+        });
+
+        // Then: Only the radio in the same form is unchecked
+        assert!(!is_checked(&doc, small));
+        assert!(is_checked(&doc, large));
+        assert!(is_checked(&doc, other));
+    }
+
+    #[test]
+    fn test_select_index_and_value() {
+        // Given: A select with a pre-selected second option
+        let mut doc = Document::new();
+        let root = doc.root;
+        let select = add(&mut doc, root, "select", &[]);
+        add_option(&mut doc, select, "Red", &[("value", "r")]);
+        add_option(&mut doc, select, "Green", &[("value", "g"), ("selected", "")]);
+        let blue = add_option(&mut doc, select, "  Blue\n ", &[]);
+
+        assert_eq!(selected_index(&doc, select), 1);
+        assert_eq!(value(&doc, select), "g");
+
+        // When: Script selects by value, then by index
+        set_value(&mut doc, select, "Blue");
+        assert_eq!(selected_index(&doc, select), 2);
+        assert!(is_selected(&doc, blue));
+
+        set_selected_index(&mut doc, select, -1);
+
+        // Then: Nothing is selected
+        assert_eq!(selected_index(&doc, select), -1);
+        assert_eq!(value(&doc, select), "");
+    }
+
+    #[test]
+    fn test_single_select_defaults_to_first_enabled_option() {
+        let mut doc = Document::new();
+        let root = doc.root;
+        let select = add(&mut doc, root, "select", &[]);
+        add_option(&mut doc, select, "Choose", &[("disabled", "")]);
+        add_option(&mut doc, select, "One", &[]);
+
+        assert_eq!(selected_index(&doc, select), 1);
+        assert_eq!(value(&doc, select), "One");
+    }
+
+    #[test]
+    fn test_text_values() {
+        // Given: An input with a default value and a textarea with text
+        let mut doc = Document::new();
+        let root = doc.root;
+        let input = add(&mut doc, root, "input", &[("value", "default")]);
+        let textarea = add(&mut doc, root, "textarea", &[]);
+        let text = doc.create_text_node("Hello");
+        doc.append_child(textarea, text);
+
+        assert_eq!(value(&doc, input), "default");
+        assert_eq!(value(&doc, textarea), "Hello");
+
+        // When: The user types into both
+        let doc = with_events(doc, |ctx, document| {
+            assert!(input_value(ctx, document, input, "typed").unwrap());
+            assert!(input_value(ctx, document, textarea, "Bye").unwrap());
+            assert!(!input_value(ctx, document, textarea, "Bye").unwrap(), "Same value is not a change");
+        });
+
+        // Then: Values change but the default value attribute does not
+        assert_eq!(value(&doc, input), "typed");
+        assert_eq!(value(&doc, textarea), "Bye");
+        assert_eq!(doc.get_attribute(input, "value"), Some(&"default".to_string()));
+    }
+
+    #[test]
+    fn test_serialize_form() {
+        // Given: A form with assorted controls
+        let mut doc = Document::new();
+        let root = doc.root;
+        let form = add(&mut doc, root, "form", &[]);
+        add(&mut doc, form, "input", &[("name", "q"), ("value", "rust lang")]);
+        add(&mut doc, form, "input", &[("type", "checkbox"), ("name", "news"), ("checked", "")]);
+        add(&mut doc, form, "input", &[("type", "checkbox"), ("name", "spam")]);
+        add(&mut doc, form, "input", &[("name", "off"), ("value", "x"), ("disabled", "")]);
+        add(&mut doc, form, "input", &[("value", "unnamed")]);
+        add(&mut doc, form, "input", &[("type", "submit"), ("name", "go"), ("value", "Go")]);
+        let select = add(&mut doc, form, "select", &[("name", "tags"), ("multiple", "")]);
+        add_option(&mut doc, select, "a", &[("selected", "")]);
+        add_option(&mut doc, select, "b", &[]);
+        add_option(&mut doc, select, "c", &[("selected", "")]);
+        let textarea = add(&mut doc, form, "textarea", &[("name", "note")]);
+        let text = doc.create_text_node("a&b");
+        doc.append_child(textarea, text);
+
+        // When: We serialize it
+        let entries = serialize_form(&doc, form);
+
+        // Then: Only successful controls are included, in tree order
+        let pairs: Vec<(&str, &str)> = entries.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+        assert_eq!(
+            pairs,
+            vec![("q", "rust lang"), ("news", "on"), ("tags", "a"), ("tags", "c"), ("note", "a&b")]
+        );
+        assert_eq!(to_url_encoded(&entries), "q=rust+lang&news=on&tags=a&tags=c&note=a%26b");
+    }
+
+    #[test]
+    fn test_js_bindings_read_and_write_state() {
+        // Given: A document with a checkbox and a select, shared with JS
+        let mut doc = Document::new();
+        let root = doc.root;
+        let form = add(&mut doc, root, "form", &[]);
+        let checkbox = add(&mut doc, form, "input", &[("type", "checkbox"), ("name", "ok")]);
+        let select = add(&mut doc, form, "select", &[("name", "n")]);
+        add_option(&mut doc, select, "1", &[]);
+        add_option(&mut doc, select, "2", &[]);
+        let document = Rc::new(RefCell::new(doc));
+
+        let runtime = rquickjs::Runtime::new().unwrap();
+        let context = rquickjs::Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            install_form_bindings(&ctx, document.clone()).unwrap();
+
+            // When: Script flips the checkbox and picks the second option
+            let script = format!(
+                "setChecked({c}, true); setSelectedIndex({s}, 1); \
+                 [getChecked({c}), getSelectedIndex({s}), getValue({s}), \
+                  serializeForm({f}).map(p => p.join('=')).join('&')].join(',')",
+                c = checkbox, s = select, f = form
+            );
+            let result: String = ctx.eval(script).unwrap();
+
+            // Then: Rust and JS see the same state
+            assert_eq!(result, "true,1,2,ok=on&n=2");
+        });
+        assert!(is_checked(&document.borrow(), checkbox));
+    }
+}
@@ -0,0 +1,193 @@
+//! Frames
+//! `<iframe>` elements, each showing a nested document in a page of its own
+//!
+//! A frame is a child `Page` with the parent's settings and the iframe's
+//! content box as its viewport. `srcdoc` wins over `src`, and frames load
+//! once the parent's scripts have run. The parent paints each frame into
+//! its iframe, and the two talk through `postMessage`; see `messaging`.
+
+use std::cell::{Ref, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::atom::Atom;
+use crate::browser::Page;
+use crate::dom::{Document, Node, NodeData};
+use crate::event_loop::TaskSource;
+use crate::messaging::{Message, MessageSource, PageMessages};
+use crate::network::{resolve_url, NetworkError};
+
+/// Size of an iframe without `width` and `height`, in CSS pixels
+pub const DEFAULT_FRAME_SIZE: (f32, f32) = (300.0, 150.0);
+
+/// Frames nested deeper than this stay blank, so a page framing itself
+/// terminates
+pub const MAX_FRAME_DEPTH: usize = 8;
+
+/// Whether `idx` is an `<iframe>`
+pub fn is_frame(document: &Document, idx: usize) -> bool {
+    document.nodes.get(idx).is_some_and(|node| frame_attributes(node).is_some())
+}
+
+fn frame_attributes(node: &Node) -> Option<&BTreeMap<Atom, String>> {
+    match &node.data {
+        Some(NodeData::Element(elem)) if elem.tag_name == "iframe" => Some(&elem.attributes),
+        _ => None,
+    }
+}
+
+/// An iframe's size by its `width` and `height` attributes, defaulting to
+/// `DEFAULT_FRAME_SIZE`; `None` for other nodes
+pub fn intrinsic_size(node: &Node) -> Option<(f32, f32)> {
+    let attributes = frame_attributes(node)?;
+    let dimension = |name: &str, default: f32| {
+        attributes
+            .get(name)
+            .and_then(|value| value.trim().trim_end_matches("px").parse::<f32>().ok())
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .unwrap_or(default)
+    };
+    Some((dimension("width", DEFAULT_FRAME_SIZE.0), dimension("height", DEFAULT_FRAME_SIZE.1)))
+}
+
+/// What an iframe shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameSource {
+    /// Its `srcdoc` markup
+    SrcDoc(String),
+    /// The document at its `src`, resolved against the parent's base URL
+    Url(String),
+    /// `about:blank`
+    Blank,
+}
+
+impl FrameSource {
+    pub fn of(document: &Document, iframe: usize, base_url: Option<&str>) -> FrameSource {
+        if let Some(html) = document.get_attribute(iframe, "srcdoc") {
+            return FrameSource::SrcDoc(html.clone());
+        }
+        match document.get_attribute(iframe, "src").map(|src| src.trim()).filter(|src| !src.is_empty() && *src != "about:blank") {
+            Some(src) => FrameSource::Url(base_url.map_or_else(|| src.to_string(), |base| resolve_url(base, src))),
+            None => FrameSource::Blank,
+        }
+    }
+}
+
+/// Result of loading one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameLoad {
+    pub element: usize,
+    /// The URL fetched; `None` for `srcdoc` and blank frames
+    pub url: Option<String>,
+    pub result: Result<(), NetworkError>,
+}
+
+/// A loaded frame: the iframe element and the page it shows
+pub struct Frame {
+    pub element: usize,
+    pub page: Page,
+}
+
+/// A page's frames, in document order
+///
+/// As a task source it runs each frame's jobs and tasks, so work a frame
+/// queued, such as a posted message, goes on while the parent is driven.
+/// Exceptions are reported on the frame's console.
+#[derive(Clone, Default)]
+pub struct PageFrames {
+    frames: Rc<RefCell<Vec<Frame>>>,
+}
+
+impl PageFrames {
+    pub fn borrow(&self) -> Ref<'_, Vec<Frame>> {
+        self.frames.borrow()
+    }
+
+    pub(crate) fn replace(&self, frames: Vec<Frame>) {
+        // Dropped outside the borrow, as dropping a page runs its finalizers
+        let old = std::mem::replace(&mut *self.frames.borrow_mut(), frames);
+        drop(old);
+    }
+
+    /// The messages of the frame shown by `iframe`, if it has loaded
+    fn messages(&self, iframe: usize) -> Option<PageMessages> {
+        self.frames.borrow().iter().find(|frame| frame.element == iframe).map(|frame| frame.page.messages().clone())
+    }
+}
+
+impl TaskSource for PageFrames {
+    fn run_next_task(&self, _ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let frames = self.frames.borrow();
+        let mut ran = false;
+        for frame in frames.iter() {
+            match frame.page.run_until_idle() {
+                Ok(count) => ran |= count > 0,
+                Err(e) => {
+                    frame.page.report_uncaught(e);
+                    ran = true;
+                }
+            }
+        }
+        Ok(ran)
+    }
+}
+
+/// The page a frame is shown in, and the iframe showing it
+#[derive(Clone)]
+pub struct FrameParent {
+    pub messages: PageMessages,
+    pub element: usize,
+}
+
+/// `contentWindow(iframe)` and `parent`, over the natives of
+/// `install_frames`
+const FRAMES_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexFrames;
+    delete globalThis.__cortexFrames;
+    const { postWith } = globalThis.__cortexMessaging;
+    const windows = new Map();
+    globalThis.contentWindow = iframe => {
+        if (!native.isFrame(iframe)) return null;
+        if (!windows.has(iframe)) {
+            windows.set(iframe, { postMessage: postWith((data, ports, targetOrigin) => native.postToFrame(iframe, data, ports, targetOrigin)) });
+        }
+        return windows.get(iframe);
+    };
+    globalThis.parent = native.hasParent ? { postMessage: postWith(native.postToParent) } : globalThis;
+})();
+"#;
+
+/// Install `contentWindow(iframe)`, whose `postMessage` reaches the frame
+/// `iframe` shows, and `parent`, whose `postMessage` reaches `parent`'s
+/// page; a page that is not a frame is its own `parent`. Messages are sent
+/// from `own`'s origin
+pub fn install_frames<'js>(ctx: &Ctx<'js>, frames: PageFrames, own: &PageMessages, parent: Option<FrameParent>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let loaded = frames.clone();
+    native.set("isFrame", Function::new(ctx.clone(), move |iframe: usize| loaded.messages(iframe).is_some())?)?;
+    let origin = own.origin().to_string();
+    native.set(
+        "postToFrame",
+        Function::new(ctx.clone(), move |iframe: usize, data: String, ports: Vec<usize>, target_origin: String| {
+            if let Some(messages) = frames.messages(iframe) {
+                messages.post(Message { data, origin: origin.clone(), ports, source: MessageSource::Parent }, &target_origin);
+            }
+        })?,
+    )?;
+    native.set("hasParent", parent.is_some())?;
+    let origin = own.origin().to_string();
+    native.set(
+        "postToParent",
+        Function::new(ctx.clone(), move |data: String, ports: Vec<usize>, target_origin: String| {
+            if let Some(parent) = &parent {
+                let message = Message { data, origin: origin.clone(), ports, source: MessageSource::Frame(parent.element) };
+                parent.messages.post(message, &target_origin);
+            }
+        })?,
+    )?;
+    ctx.globals().set("__cortexFrames", native)?;
+    ctx.eval::<(), _>(FRAMES_PRELUDE)
+}
@@ -0,0 +1,269 @@
+//! Geometry Primitives
+//! Points, sizes, rectangles and box edges shared by layout, painting,
+//! screenshots and comparison code
+
+/// A position in CSS pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub fn new(x: f32, y: f32) -> Self {
+        Point { x, y }
+    }
+}
+
+/// A width and height in CSS pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Size {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Size {
+    pub fn new(width: f32, height: f32) -> Self {
+        Size { width, height }
+    }
+
+    /// Check whether the size encloses no area
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+}
+
+/// Widths of the four sides of a box edge (padding, border or margin)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EdgeSizes {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl EdgeSizes {
+    pub fn new(top: f32, right: f32, bottom: f32, left: f32) -> Self {
+        EdgeSizes { top, right, bottom, left }
+    }
+
+    /// The same width on all four sides
+    pub fn uniform(size: f32) -> Self {
+        EdgeSizes::new(size, size, size, size)
+    }
+
+    /// Combined left and right widths
+    pub fn horizontal(&self) -> f32 {
+        self.left + self.right
+    }
+
+    /// Combined top and bottom widths
+    pub fn vertical(&self) -> f32 {
+        self.top + self.bottom
+    }
+}
+
+impl std::ops::Add for EdgeSizes {
+    type Output = EdgeSizes;
+
+    fn add(self, other: EdgeSizes) -> EdgeSizes {
+        EdgeSizes::new(
+            self.top + other.top,
+            self.right + other.right,
+            self.bottom + other.bottom,
+            self.left + other.left,
+        )
+    }
+}
+
+/// An axis-aligned rectangle in CSS pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    pub fn from_origin_size(origin: Point, size: Size) -> Self {
+        Rect::new(origin.x, origin.y, size.width, size.height)
+    }
+
+    pub fn origin(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    /// Check whether the rectangle encloses no area
+    pub fn is_empty(&self) -> bool {
+        self.size().is_empty()
+    }
+
+    /// Check whether a point lies inside, counting the top and left edges
+    /// but not the bottom and right ones
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.x && point.x < self.right() && point.y >= self.y && point.y < self.bottom()
+    }
+
+    /// Check whether two rectangles share any area
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// The overlapping area of two rectangles, if any
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        let rect = Rect::new(x, y, right - x, bottom - y);
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// The smallest rectangle containing both; empty rectangles are ignored
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(x, y, self.right().max(other.right()) - x, self.bottom().max(other.bottom()) - y)
+    }
+
+    /// Shrink by edge widths, never below zero size
+    pub fn inset(&self, edges: EdgeSizes) -> Rect {
+        Rect::new(
+            self.x + edges.left,
+            self.y + edges.top,
+            (self.width - edges.horizontal()).max(0.0),
+            (self.height - edges.vertical()).max(0.0),
+        )
+    }
+
+    /// Grow by edge widths
+    pub fn outset(&self, edges: EdgeSizes) -> Rect {
+        Rect::new(
+            self.x - edges.left,
+            self.y - edges.top,
+            self.width + edges.horizontal(),
+            self.height + edges.vertical(),
+        )
+    }
+
+    /// Move by an offset
+    pub fn translate(&self, dx: f32, dy: f32) -> Rect {
+        Rect::new(self.x + dx, self.y + dy, self.width, self.height)
+    }
+
+    /// Scale position and size, e.g. from CSS to device pixels
+    pub fn scale(&self, factor: f32) -> Rect {
+        Rect::new(self.x * factor, self.y * factor, self.width * factor, self.height * factor)
+    }
+
+    /// Smallest whole-pixel rectangle covering this one, as
+    /// `(x, y, width, height)`
+    pub fn round_out(&self) -> (i32, i32, i32, i32) {
+        let x = self.x.floor() as i32;
+        let y = self.y.floor() as i32;
+        let right = self.right().ceil() as i32;
+        let bottom = self.bottom().ceil() as i32;
+        (x, y, right - x, bottom - y)
+    }
+
+    /// Check whether every edge is within `tolerance` of the other's
+    pub fn approx_eq(&self, other: &Rect, tolerance: f32) -> bool {
+        let within = |a: f32, b: f32| (a - b).abs() <= tolerance;
+        within(self.x, other.x)
+            && within(self.y, other.y)
+            && within(self.width, other.width)
+            && within(self.height, other.height)
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::Layout;
+
+    #[test]
+    fn test_rect_contains_is_half_open() {
+        let rect = Rect::new(10.0, 10.0, 20.0, 10.0);
+
+        assert!(rect.contains(Point::new(10.0, 10.0)));
+        assert!(rect.contains(Point::new(29.9, 19.9)));
+        assert!(!rect.contains(Point::new(30.0, 15.0)));
+        assert!(!rect.contains(Point::new(15.0, 20.0)));
+    }
+
+    #[test]
+    fn test_rect_intersection_and_union() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(5.0, 5.0, 10.0, 10.0);
+        let far = Rect::new(50.0, 50.0, 1.0, 1.0);
+
+        assert_eq!(a.intersection(&b), Some(Rect::new(5.0, 5.0, 5.0, 5.0)));
+        assert_eq!(a.intersection(&far), None);
+        assert!(!a.intersects(&Rect::new(10.0, 0.0, 5.0, 5.0)), "Touching edges do not overlap");
+        assert_eq!(a.union(&b), Rect::new(0.0, 0.0, 15.0, 15.0));
+        assert_eq!(a.union(&Rect::default()), a);
+    }
+
+    #[test]
+    fn test_rect_inset_and_outset() {
+        let rect = Rect::new(0.0, 0.0, 100.0, 50.0);
+        let edges = EdgeSizes::new(1.0, 2.0, 3.0, 4.0);
+
+        assert_eq!(rect.inset(edges), Rect::new(4.0, 1.0, 94.0, 46.0));
+        assert_eq!(rect.inset(edges).outset(edges), rect);
+        assert_eq!(rect.inset(EdgeSizes::uniform(60.0)).size(), Size::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_rect_round_out_covers_fractional_edges() {
+        let rect = Rect::new(1.5, 2.25, 3.0, 1.0);
+
+        assert_eq!(rect.round_out(), (1, 2, 4, 2));
+        assert_eq!(rect.scale(2.0), Rect::new(3.0, 4.5, 6.0, 2.0));
+    }
+
+    #[test]
+    fn test_layout_boxes_nest() {
+        // Given: A box with border, padding and margin
+        let layout = Layout {
+            rect: Rect::new(10.0, 10.0, 100.0, 60.0),
+            padding: EdgeSizes::new(5.0, 5.0, 5.0, 10.0),
+            margin: EdgeSizes::new(8.0, 0.0, 0.0, 8.0),
+            border: EdgeSizes::uniform(2.0),
+            ..Default::default()
+        };
+
+        // Then: Each box is inset from the one outside it
+        assert_eq!(layout.border_box(), Rect::new(10.0, 10.0, 100.0, 60.0));
+        assert_eq!(layout.padding_box(), Rect::new(12.0, 12.0, 96.0, 56.0));
+        assert_eq!(layout.content_box(), Rect::new(22.0, 17.0, 81.0, 46.0));
+        assert_eq!(layout.margin_box(), Rect::new(2.0, 2.0, 108.0, 68.0));
+    }
+}
@@ -0,0 +1,465 @@
+//! Golden Masters
+//! Checks screenshots against stored golden master PNGs, records missing
+//! ones and rewrites changed ones on request
+//!
+//! DOM snapshots live in the same directory as `<name>.dom.txt` and follow
+//! the same modes, compared as exact text.
+//!
+//! Each snapshot directory has a manifest recording the viewport and engine
+//! version every golden master was rendered with, so a mismatch can say
+//! whether the golden master predates an engine or viewport change.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::image_diff::{compare, compare_with_file, DiffOptions, DiffResult, Image, ImageDiffError};
+use crate::snapshot::line_diff;
+
+/// Engine version recorded with new golden masters
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Manifest file name inside the snapshot directory
+pub const MANIFEST_FILE: &str = "manifest.txt";
+
+/// Default snapshot directory, relative to the working directory
+pub const DEFAULT_SNAPSHOT_DIR: &str = "golden_masters";
+
+/// What to do with golden masters that are missing or differ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotMode {
+    /// Missing and differing golden masters fail
+    #[default]
+    Compare,
+    /// `--record`: missing golden masters are created; differing ones fail
+    Record,
+    /// `--update-snapshots`: missing and differing golden masters are
+    /// rewritten
+    Update,
+}
+
+/// What a snapshot check did
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotOutcome {
+    Matched(DiffResult),
+    /// A DOM snapshot was the same as the stored one
+    MatchedText,
+    Recorded(PathBuf),
+    Updated(PathBuf),
+}
+
+impl SnapshotOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnapshotOutcome::Matched(_) | SnapshotOutcome::MatchedText => "matched",
+            SnapshotOutcome::Recorded(_) => "recorded",
+            SnapshotOutcome::Updated(_) => "updated",
+        }
+    }
+}
+
+/// How a golden master was rendered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub width: u32,
+    pub height: u32,
+    pub engine_version: String,
+}
+
+/// Metadata for every golden master in a directory
+///
+/// Stored one snapshot per line as `<name> <width>x<height> <engine version>`,
+/// sorted by name so updates produce small diffs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, SnapshotEntry>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut entries = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Invalid manifest line {}: '{}'", number + 1, line);
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(size), Some(engine_version), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+            let entry = SnapshotEntry {
+                width: width.parse().map_err(|_| invalid())?,
+                height: height.parse().map_err(|_| invalid())?,
+                engine_version: engine_version.to_string(),
+            };
+            entries.insert(name.to_string(), entry);
+        }
+        Ok(Manifest { entries })
+    }
+
+    pub fn format(&self) -> String {
+        let mut text = String::from("# Golden masters: name, viewport, engine version\n");
+        for (name, entry) in &self.entries {
+            text.push_str(&format!("{} {}x{} {}\n", name, entry.width, entry.height, entry.engine_version));
+        }
+        text
+    }
+
+    /// Read the manifest in `dir`; a missing manifest is empty
+    pub fn load(dir: &Path) -> Result<Manifest, SnapshotError> {
+        let path = dir.join(MANIFEST_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => Manifest::parse(&text).map_err(|e| SnapshotError::Io(format!("{}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(SnapshotError::Io(format!("Cannot read '{}': {}", path.display(), e))),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), SnapshotError> {
+        let path = dir.join(MANIFEST_FILE);
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, self.format()))
+            .map_err(|e| SnapshotError::Io(format!("Cannot write '{}': {}", path.display(), e)))
+    }
+}
+
+/// Where golden masters live and how screenshots are checked against them
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotConfig {
+    pub dir: PathBuf,
+    pub mode: SnapshotMode,
+    pub options: DiffOptions,
+    /// Where diff and side-by-side images of failed checks go
+    pub artifacts_dir: PathBuf,
+}
+
+impl SnapshotConfig {
+    /// Golden masters in `dir`, with failure artifacts in `dir/diffs`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        SnapshotConfig {
+            artifacts_dir: dir.join("diffs"),
+            dir,
+            mode: SnapshotMode::default(),
+            options: DiffOptions::default(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: SnapshotMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_options(mut self, options: DiffOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_artifacts_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.artifacts_dir = dir.into();
+        self
+    }
+
+    /// Path of the golden master called `name`
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", name))
+    }
+
+    /// Path of the DOM snapshot called `name`
+    pub fn dom_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.dom.txt", name))
+    }
+
+    /// Check `actual` against the golden master called `name`, recording or
+    /// updating it as the mode allows
+    pub fn check(&self, name: &str, actual: &Image) -> Result<SnapshotOutcome, SnapshotError> {
+        validate_name(name)?;
+        let path = self.path(name);
+
+        if !path.exists() {
+            return match self.mode {
+                SnapshotMode::Compare => Err(SnapshotError::Missing { name: name.to_string(), path }),
+                SnapshotMode::Record | SnapshotMode::Update => {
+                    self.write(name, actual)?;
+                    Ok(SnapshotOutcome::Recorded(path))
+                }
+            };
+        }
+
+        if self.mode == SnapshotMode::Update {
+            let expected = Image::load(&path).map_err(SnapshotError::Diff)?;
+            return match compare(&expected, actual, &self.options) {
+                Ok(result) if result.passed => Ok(SnapshotOutcome::Matched(result)),
+                _ => {
+                    self.write(name, actual)?;
+                    Ok(SnapshotOutcome::Updated(path))
+                }
+            };
+        }
+
+        compare_with_file(actual, &path, &self.options, &self.artifacts_dir)
+            .map(SnapshotOutcome::Matched)
+            .map_err(|error| match error {
+                ImageDiffError::Mismatch { .. } | ImageDiffError::SizeMismatch { .. } => SnapshotError::Mismatch {
+                    name: name.to_string(),
+                    path: path.clone(),
+                    recorded: Manifest::load(&self.dir).ok().and_then(|manifest| manifest.entries.get(name).cloned()),
+                    error: Box::new(error),
+                },
+                error => SnapshotError::Diff(error),
+            })
+    }
+
+    /// Check a DOM snapshot against the stored one called `name`, recording
+    /// or updating it as the mode allows
+    pub fn check_dom(&self, name: &str, actual: &str) -> Result<SnapshotOutcome, SnapshotError> {
+        validate_name(name)?;
+        let path = self.dom_path(name);
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => Some(expected),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(SnapshotError::Io(format!("Cannot read '{}': {}", path.display(), e))),
+        };
+        let write = || {
+            fs::create_dir_all(&self.dir)
+                .and_then(|_| fs::write(&path, actual))
+                .map_err(|e| SnapshotError::Io(format!("Cannot write '{}': {}", path.display(), e)))
+        };
+        match (expected, self.mode) {
+            (Some(expected), _) if expected == actual => Ok(SnapshotOutcome::MatchedText),
+            (None, SnapshotMode::Compare) => Err(SnapshotError::Missing { name: name.to_string(), path }),
+            (None, _) => write().map(|_| SnapshotOutcome::Recorded(path.clone())),
+            (Some(_), SnapshotMode::Update) => write().map(|_| SnapshotOutcome::Updated(path.clone())),
+            (Some(expected), _) => {
+                Err(SnapshotError::DomMismatch { name: name.to_string(), path: path.clone(), diff: line_diff(&expected, actual) })
+            }
+        }
+    }
+
+    /// Save a golden master and its manifest entry
+    fn write(&self, name: &str, image: &Image) -> Result<(), SnapshotError> {
+        image.save(&self.path(name)).map_err(SnapshotError::Diff)?;
+        let mut manifest = Manifest::load(&self.dir)?;
+        let entry = SnapshotEntry {
+            width: image.width,
+            height: image.height,
+            engine_version: ENGINE_VERSION.to_string(),
+        };
+        manifest.entries.insert(name.to_string(), entry);
+        manifest.save(&self.dir)
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), SnapshotError> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') || name.contains(char::is_whitespace) {
+        return Err(SnapshotError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_SNAPSHOT_DIR)
+    }
+}
+
+/// Error types for golden master checks
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    InvalidName(String),
+    Missing {
+        name: String,
+        path: PathBuf,
+    },
+    Mismatch {
+        name: String,
+        path: PathBuf,
+        /// Manifest entry of the golden master, if it has one
+        recorded: Option<SnapshotEntry>,
+        error: Box<ImageDiffError>,
+    },
+    DomMismatch {
+        name: String,
+        path: PathBuf,
+        /// The changed lines, as `snapshot::line_diff` gives them
+        diff: String,
+    },
+    Diff(ImageDiffError),
+    Io(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidName(name) => {
+                write!(f, "Invalid snapshot name '{}': use a file name without spaces or slashes", name)
+            }
+            SnapshotError::Missing { name, path } => write!(
+                f,
+                "No golden master for '{}' (expected {}); run with --record to create it",
+                name,
+                path.display()
+            ),
+            SnapshotError::Mismatch { name, path, recorded, error } => {
+                write!(f, "Screenshot '{}' does not match {}: {}", name, path.display(), error)?;
+                if let Some(entry) = recorded {
+                    if entry.engine_version != ENGINE_VERSION {
+                        write!(
+                            f,
+                            ". The golden master was recorded with engine {} (now {})",
+                            entry.engine_version, ENGINE_VERSION
+                        )?;
+                    }
+                }
+                write!(f, ". Run with --update-snapshots to accept the new rendering")
+            }
+            SnapshotError::DomMismatch { name, path, diff } => write!(
+                f,
+                "DOM snapshot '{}' does not match {}:\n{}Run with --update-snapshots to accept the new DOM",
+                name,
+                path.display(),
+                diff
+            ),
+            SnapshotError::Diff(e) => write!(f, "{}", e),
+            SnapshotError::Io(e) => write!(f, "IO Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(color: [u8; 4]) -> Image {
+        let mut image = Image::new(4, 3);
+        for y in 0..3 {
+            for x in 0..4 {
+                image.set_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    #[test]
+    fn test_missing_golden_master_fails_unless_recording() {
+        // Given: An empty snapshot directory
+        let dir = tempfile::tempdir().unwrap();
+        let compare = SnapshotConfig::new(dir.path());
+        let record = compare.clone().with_mode(SnapshotMode::Record);
+
+        // When: A screenshot is checked, then recorded
+        let missing = compare.check("button", &image(BLUE)).unwrap_err();
+        let recorded = record.check("button", &image(BLUE)).unwrap();
+
+        // Then: Comparing fails with a hint, recording writes the golden master
+        assert!(missing.to_string().contains("run with --record to create it"), "{}", missing);
+        assert_eq!(recorded, SnapshotOutcome::Recorded(dir.path().join("button.png")));
+        assert_eq!(Image::load(&dir.path().join("button.png")).unwrap(), image(BLUE));
+        assert!(matches!(compare.check("button", &image(BLUE)), Ok(SnapshotOutcome::Matched(_))));
+    }
+
+    #[test]
+    fn test_mismatch_points_at_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SnapshotConfig::new(dir.path());
+        config.clone().with_mode(SnapshotMode::Record).check("card", &image(BLUE)).unwrap();
+
+        // Record mode does not overwrite a differing golden master either
+        let error = config.clone().with_mode(SnapshotMode::Record).check("card", &image(RED)).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.starts_with("Screenshot 'card' does not match "), "{}", message);
+        assert!(message.contains(&dir.path().join("diffs/card.diff.png").display().to_string()), "{}", message);
+        assert!(message.ends_with("Run with --update-snapshots to accept the new rendering"), "{}", message);
+        assert!(dir.path().join("diffs/card.side-by-side.png").exists());
+        assert_eq!(Image::load(&config.path("card")).unwrap(), image(BLUE));
+    }
+
+    #[test]
+    fn test_update_rewrites_changed_golden_masters() {
+        let dir = tempfile::tempdir().unwrap();
+        let update = SnapshotConfig::new(dir.path()).with_mode(SnapshotMode::Update);
+        update.check("card", &image(BLUE)).unwrap();
+
+        let unchanged = update.check("card", &image(BLUE)).unwrap();
+        let changed = update.check("card", &image(RED)).unwrap();
+
+        assert_eq!(unchanged.as_str(), "matched");
+        assert_eq!(changed, SnapshotOutcome::Updated(dir.path().join("card.png")));
+        assert_eq!(Image::load(&update.path("card")).unwrap(), image(RED));
+    }
+
+    #[test]
+    fn test_manifest_tracks_viewport_and_engine() {
+        // Given: Two recorded golden masters
+        let dir = tempfile::tempdir().unwrap();
+        let record = SnapshotConfig::new(dir.path()).with_mode(SnapshotMode::Record);
+        record.check("b", &image(BLUE)).unwrap();
+        record.check("a", &Image::new(2, 2)).unwrap();
+
+        // Then: The manifest lists both, sorted
+        let manifest = Manifest::load(dir.path()).unwrap();
+        let names: Vec<&String> = manifest.entries.keys().collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(
+            manifest.entries["b"],
+            SnapshotEntry { width: 4, height: 3, engine_version: ENGINE_VERSION.to_string() }
+        );
+        assert_eq!(Manifest::parse(&manifest.format()), Ok(manifest));
+    }
+
+    #[test]
+    fn test_mismatch_mentions_older_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SnapshotConfig::new(dir.path());
+        image(BLUE).save(&config.path("card")).unwrap();
+        fs::write(dir.path().join(MANIFEST_FILE), "card 4x3 0.0.1\n").unwrap();
+
+        let message = config.check("card", &image(RED)).unwrap_err().to_string();
+
+        assert!(message.contains("recorded with engine 0.0.1"), "{}", message);
+    }
+
+    #[test]
+    fn test_invalid_names_and_manifest_lines() {
+        let config = SnapshotConfig::new("unused");
+
+        assert_eq!(
+            config.check("../escape", &image(BLUE)),
+            Err(SnapshotError::InvalidName("../escape".to_string()))
+        );
+        assert!(config.check("two words", &image(BLUE)).is_err());
+        assert!(Manifest::parse("card 4x3").is_err());
+        assert!(Manifest::parse("card wide 0.1.0").is_err());
+        assert_eq!(Manifest::parse("# comment\n\n"), Ok(Manifest::default()));
+    }
+
+    #[test]
+    fn test_dom_snapshots_follow_the_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let compare = SnapshotConfig::new(dir.path());
+
+        let missing = compare.check_dom("menu", "<nav></nav>\n").unwrap_err();
+        assert!(missing.to_string().contains("run with --record to create it"), "{}", missing);
+
+        let update = compare.clone().with_mode(SnapshotMode::Update);
+        assert_eq!(update.check_dom("menu", "<nav></nav>\n").unwrap(), SnapshotOutcome::Recorded(dir.path().join("menu.dom.txt")));
+        assert_eq!(compare.check_dom("menu", "<nav></nav>\n").unwrap(), SnapshotOutcome::MatchedText);
+        assert_eq!(update.check_dom("menu", "<nav>\n</nav>\n").unwrap(), SnapshotOutcome::Updated(dir.path().join("menu.dom.txt")));
+        assert!(matches!(compare.check_dom("menu", "<nav></nav>\n"), Err(SnapshotError::DomMismatch { .. })));
+    }
+}
@@ -0,0 +1,185 @@
+//! Node Handles
+//! Checking the nodes scripts name before natives touch them
+//!
+//! Natives take a bare index or an `Element` wrapper as a `JsNode` and
+//! resolve it with `JsNode::live`, which throws for anything but a live
+//! node. Queries return wrappers, which carry their document's epoch, so
+//! one kept across a page load throws `NodeError::Replaced`.
+
+use rquickjs::{Ctx, Exception, FromJs, Value};
+
+use crate::dom::{Document, NodeId};
+
+/// A node as a script passed it: a bare index, or an `Element` wrapper's
+/// index and epoch; see `live`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsNode {
+    pub index: usize,
+    /// The epoch of the document a wrapper was made in, `None` for a bare
+    /// index
+    pub epoch: Option<u32>,
+}
+
+impl JsNode {
+    /// The index, if it names a live node of `document`, and for a
+    /// wrapper, if `document` is the one it was made in; otherwise throw
+    /// a `ReferenceError` saying why it does not
+    pub fn live(self, ctx: &Ctx<'_>, document: &Document) -> rquickjs::Result<usize> {
+        let checked = match self.epoch {
+            Some(epoch) => document.resolve(NodeId::from_parts(self.index, epoch)),
+            None => document.check(self.index),
+        };
+        checked.map_err(|e| Exception::throw_reference(ctx, &e.to_string()))
+    }
+}
+
+impl<'js> FromJs<'js> for JsNode {
+    fn from_js(ctx: &Ctx<'js>, value: Value<'js>) -> rquickjs::Result<Self> {
+        // An `Element` from `element(node)` passes as its node and epoch
+        let wrapped = value.as_object().and_then(|object| Some((object.get::<_, Value>("node").ok()?, object.get::<_, Value>("epoch").ok()?)));
+        let (value, epoch) = match wrapped {
+            Some((node, epoch)) if node.is_number() => (node, epoch.as_number().map(|epoch| epoch as u32)),
+            _ => (value, None),
+        };
+        match value.as_number() {
+            Some(number) if number >= 0.0 && number.fract() == 0.0 && number <= u32::MAX as f64 => Ok(JsNode { index: number as usize, epoch }),
+            _ => Err(Exception::throw_type(ctx, &format!("Expected a node, got {}", describe(&value)))),
+        }
+    }
+}
+
+fn describe(value: &Value<'_>) -> String {
+    match value.as_number() {
+        Some(number) => number.to_string(),
+        None => value.type_name().to_string(),
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::NodeError;
+    use crate::parser::parse_html;
+    use rquickjs::{Context, Function, Runtime};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_node_ids_resolve_only_in_their_document_while_live() {
+        // Given: A list and handles on its items
+        let mut document = parse_html("<ul><li>One <b>bold</b></li><li>Two</li></ul>");
+        let items: Vec<usize> = (0..document.nodes.len()).filter(|&idx| crate::forms::tag_name(&document, idx) == Some("li")).collect();
+        let (first, second) = (document.node_id(items[0]).unwrap(), document.node_id(items[1]).unwrap());
+        let bold = document.nodes[items[0]].children[1];
+
+        // When: The first item is removed for good and the second detached
+        document.remove_node(items[0]);
+        document.remove_child(items[1]);
+
+        // Then: The removed subtree no longer resolves; the detached item does
+        assert_eq!(document.resolve(first), Err(NodeError::Removed(items[0])));
+        assert_eq!(document.check(bold), Err(NodeError::Removed(bold)));
+        assert_eq!(document.resolve(second), Ok(items[1]));
+        assert_eq!(document.check(document.nodes.len()), Err(NodeError::Missing(document.nodes.len())));
+
+        // When: Another document is parsed with the same shape
+        let replacement = parse_html("<ul><li>One <b>bold</b></li><li>Two</li></ul>");
+
+        // Then: Handles from the first do not resolve in it
+        assert_eq!(replacement.resolve(second), Err(NodeError::Replaced(second)));
+        assert_eq!(NodeError::Replaced(second).to_string(), format!("Node {} belongs to a document that has been replaced", items[1]));
+    }
+
+    #[test]
+    fn test_natives_throw_for_values_that_are_not_live_nodes() {
+        // Given: A native taking a node, over a document with a removed node
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        let document = Rc::new(RefCell::new(parse_html("<p>Kept</p><p>Gone</p>")));
+        let gone = document.borrow().nodes.len() - 2;
+        document.borrow_mut().remove_node(gone);
+
+        let errors: Vec<String> = context.with(|ctx| {
+            let doc = document.clone();
+            let text = Function::new(ctx.clone(), move |ctx: Ctx<'_>, node: JsNode| -> rquickjs::Result<String> {
+                let document = doc.borrow();
+                Ok(document.text_content(node.live(&ctx, &document)?))
+            })
+            .unwrap();
+            ctx.globals().set("text", text).unwrap();
+            ctx.globals().set("gone", gone).unwrap();
+
+            // When: Scripts pass a removed node, a missing one and non-indices
+            ctx.eval(
+                r#"["gone", "1e6", "-1", "'p'", "undefined"].map(arg => {
+                    try { return text(eval(arg)); } catch (e) { return `${e.name}: ${e.message}`; }
+                })"#,
+            )
+            .unwrap()
+        });
+
+        // Then: Each throws saying what was wrong
+        assert_eq!(
+            errors,
+            [
+                format!("ReferenceError: Node {} was removed from the document", gone),
+                "ReferenceError: Node 1000000 does not exist".to_string(),
+                "TypeError: Expected a node, got -1".to_string(),
+                "TypeError: Expected a node, got string".to_string(),
+                "TypeError: Expected a node, got undefined".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_results_are_wrappers_tied_to_their_document() {
+        // Given: A script keeping what a query found
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<button data-testid="save">Save</button>"#);
+        page.run_script("globalThis.found = getByTestId('save');").unwrap();
+
+        // Then: It is the node's one wrapper, whichever query found it
+        assert_eq!(page.run_script("found instanceof Element && found === getByText('Save') && found === element(found.node)").unwrap(), "true");
+
+        // When: The page loads another document with a node at that index
+        page.load_html(r#"<p data-testid="save">Other</p>"#);
+
+        // Then: The kept result throws, and a new query finds the new node
+        let error = page.run_script("try { found.getAttribute('data-testid'); } catch (e) { `${e.name}: ${e.message}`; }").unwrap();
+        assert!(error.ends_with("belongs to a document that has been replaced"), "{}", error);
+        assert_eq!(page.run_script("getByTestId('save').tagName").unwrap(), "P");
+    }
+
+    #[test]
+    fn test_element_wrappers_throw_once_their_document_is_replaced() {
+        // Given: A script keeping a wrapper and a bare index for a button
+        let page = crate::browser::PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<button data-testid="save" title="Save">Save</button>"#);
+        page.run_script("globalThis.kept = element(getByTestId('save')); globalThis.index = kept.node;").unwrap();
+        assert_eq!(page.run_script("kept.getAttribute('title')").unwrap(), "Save");
+
+        // When: The page loads another document with a node at that index
+        page.load_html(r#"<p data-testid="other" title="Other">Other</p>"#);
+
+        // Then: Natives reject the wrapper as replaced, through methods,
+        // globals and listeners alike, while the bare index names the new node
+        let errors = page
+            .run_script(
+                r#"[
+                    () => kept.getAttribute("title"),
+                    () => getAttribute(kept, "title"),
+                    () => kept.addEventListener("click", () => {}),
+                    () => dispatchEvent(kept, "click"),
+                ].map(f => { try { f(); return "ok"; } catch (e) { return `${e.name}: ${e.message}`; } }).join("\n")"#,
+            )
+            .unwrap();
+        let replaced = page.run_script("`ReferenceError: Node ${index} belongs to a document that has been replaced`").unwrap();
+        assert_eq!(errors, [replaced.as_str(); 4].join("\n"));
+        assert_eq!(page.run_script("getAttribute(index, 'title')").unwrap(), "Other");
+        assert_eq!(page.run_script("element(index).getAttribute('title')").unwrap(), "Other");
+    }
+}
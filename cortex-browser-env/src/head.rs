@@ -0,0 +1,200 @@
+//! Document Head
+//! The `<head>` and `<body>`, the document title, and the `<meta>` and
+//! `<link>` tags that pages and components put in the head, with
+//! `document.title`, `document.head` and `document.body` for scripts
+//!
+//! Head content is metadata, not part of the page's visual flow: layout
+//! gives no boxes to the head, nor to `<title>`, `<meta>`, `<link>`,
+//! `<base>`, `<script>` or `<style>` elements wherever they appear.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object, Value};
+
+use crate::dom::Document;
+use crate::forms;
+
+/// Elements that hold metadata or code and are never rendered
+const METADATA_TAGS: &[&str] = &["head", "title", "meta", "link", "base", "script", "style"];
+
+/// Whether `idx` is an element that takes no part in the visual flow
+pub fn is_metadata(document: &Document, idx: usize) -> bool {
+    forms::tag_name(document, idx).is_some_and(|tag| METADATA_TAGS.contains(&tag))
+}
+
+/// The first element named `tag`, in tree order
+fn first_element(document: &Document, tag: &str) -> Option<usize> {
+    if document.nodes.is_empty() {
+        return None;
+    }
+    forms::descendants(document, document.root).into_iter().find(|&idx| forms::tag_name(document, idx) == Some(tag))
+}
+
+/// The `<head>` element
+pub fn head(document: &Document) -> Option<usize> {
+    first_element(document, "head")
+}
+
+/// The `<body>` element
+pub fn body(document: &Document) -> Option<usize> {
+    first_element(document, "body")
+}
+
+/// The document title, as `document.title` gives it: the first `<title>`'s
+/// text with whitespace collapsed and trimmed
+pub fn title(document: &Document) -> String {
+    first_element(document, "title")
+        .map(|idx| document.text_content(idx).split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default()
+}
+
+/// Replace the title's text, adding a `<title>` to the head when there is
+/// none; a document without a head is left alone
+pub fn set_title(document: &mut Document, title: &str) {
+    let element = match first_element(document, "title") {
+        Some(idx) => idx,
+        None => {
+            let Some(head) = head(document) else { return };
+            let idx = document.create_element("title");
+            document.append_child(head, idx);
+            idx
+        }
+    };
+    for child in document.nodes[element].children.clone() {
+        document.remove_node(child);
+    }
+    if !title.is_empty() {
+        let text = document.create_text_node(title);
+        document.append_child(element, text);
+    }
+}
+
+/// All `<meta>` elements, in tree order
+pub fn meta_tags(document: &Document) -> Vec<usize> {
+    if document.nodes.is_empty() {
+        return Vec::new();
+    }
+    forms::descendants(document, document.root).into_iter().filter(|&idx| forms::tag_name(document, idx) == Some("meta")).collect()
+}
+
+/// The `content` of the first `<meta>` whose `name` or `property` is
+/// `name`, so both `description` and `og:title` are found
+pub fn meta_content(document: &Document, name: &str) -> Option<String> {
+    meta_tags(document)
+        .into_iter()
+        .find(|&idx| ["name", "property"].iter().any(|attr| document.get_attribute(idx, attr).is_some_and(|value| value == name)))
+        .and_then(|idx| document.get_attribute(idx, "content").cloned())
+}
+
+/// The `href`s of the `<link>` elements whose `rel` lists `rel`, in tree order
+pub fn link_hrefs(document: &Document, rel: &str) -> Vec<String> {
+    if document.nodes.is_empty() {
+        return Vec::new();
+    }
+    forms::descendants(document, document.root)
+        .into_iter()
+        .filter(|&idx| forms::tag_name(document, idx) == Some("link"))
+        .filter(|&idx| document.get_attribute(idx, "rel").is_some_and(|rels| rels.split_whitespace().any(|r| r.eq_ignore_ascii_case(rel))))
+        .filter_map(|idx| document.get_attribute(idx, "href").cloned())
+        .collect()
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// Expose `document.title`, `document.head` and `document.body`, the
+/// elements as node indices
+pub fn install_head<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    if ctx.globals().get::<_, Object>("document").is_err() {
+        return Ok(());
+    }
+
+    let native = Object::new(ctx.clone())?;
+    let doc = document.clone();
+    native.set("title", Function::new(ctx.clone(), move || title(&doc.borrow()))?)?;
+    let doc = document.clone();
+    native.set("setTitle", Function::new(ctx.clone(), move |value: String| set_title(&mut doc.borrow_mut(), &value))?)?;
+    for (name, find) in [("head", head as fn(&Document) -> Option<usize>), ("body", body)] {
+        let doc = document.clone();
+        native.set(
+            name,
+            Function::new(ctx.clone(), move |ctx: Ctx<'js>| -> Value<'js> {
+                match find(&doc.borrow()) {
+                    Some(idx) => Value::new_number(ctx, idx as f64),
+                    None => Value::new_null(ctx),
+                }
+            })?,
+        )?;
+    }
+
+    ctx.globals().set("__cortexHead", native)?;
+    ctx.eval::<(), _>(HEAD_PRELUDE)
+}
+
+const HEAD_PRELUDE: &str = r#"
+(function() {
+    const native = globalThis.__cortexHead;
+    Object.defineProperties(document, {
+        title: { get() { return native.title(); }, set(value) { native.setTitle(String(value)); }, configurable: true },
+        head: { get() { return native.head(); }, configurable: true },
+        body: { get() { return native.body(); }, configurable: true },
+    });
+})();
+"#;
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    #[test]
+    fn test_title_head_and_body() {
+        // Given: A page with a spaced-out title
+        let mut document = parse_html("<html><head><title>  My\n  Page </title></head><body><p>Hi</p></body></html>");
+        let head_idx = head(&document).unwrap();
+
+        // Then: The title is collapsed and the head and body are found
+        assert_eq!(title(&document), "My Page");
+        assert_eq!(forms::tag_name(&document, body(&document).unwrap()), Some("body"));
+        assert!(is_metadata(&document, head_idx));
+
+        // When: The title is replaced
+        set_title(&mut document, "Inbox (3)");
+        assert_eq!(title(&document), "Inbox (3)");
+        assert_eq!(document.nodes[head_idx].children.len(), 1);
+    }
+
+    #[test]
+    fn test_set_title_adds_element_to_head() {
+        let mut document = parse_html("<html><head></head><body></body></html>");
+        assert_eq!(title(&document), "");
+
+        set_title(&mut document, "Fresh");
+        let head_idx = head(&document).unwrap();
+        let title_idx = document.nodes[head_idx].children[0];
+        assert_eq!(forms::tag_name(&document, title_idx), Some("title"));
+        assert_eq!(title(&document), "Fresh");
+    }
+
+    #[test]
+    fn test_meta_and_link_queries() {
+        let document = parse_html(concat!(
+            "<html><head><meta charset=\"utf-8\"><meta name=\"description\" content=\"A demo\">",
+            "<meta property=\"og:title\" content=\"Demo\"><link rel=\"icon stylesheet\" href=\"/a.css\">",
+            "<link rel=\"canonical\" href=\"https://example.com/\"></head><body></body></html>"
+        ));
+
+        assert_eq!(meta_tags(&document).len(), 3);
+        assert_eq!(meta_content(&document, "description").as_deref(), Some("A demo"));
+        assert_eq!(meta_content(&document, "og:title").as_deref(), Some("Demo"));
+        assert_eq!(meta_content(&document, "keywords"), None);
+        assert_eq!(link_hrefs(&document, "stylesheet"), vec!["/a.css"]);
+        assert_eq!(link_hrefs(&document, "canonical"), vec!["https://example.com/"]);
+    }
+}
@@ -0,0 +1,124 @@
+//! Hit Testing
+//! Which element is under a point of the page, as `elementFromPoint` and
+//! position-based mouse events see it
+//!
+//! A hit test walks the boxes in reverse paint order, so the element drawn
+//! on top wins. Siblings paint in `z-index` order, ties in tree order, and
+//! each element's descendants stay with it, as if every element formed a
+//! stacking context. Transformed boxes are hit where they appear, and
+//! elements under `pointer-events: none` or `visibility: hidden` are passed
+//! through to what lies beneath them.
+
+use crate::css::{ComputedStyle, PointerEvents};
+use crate::dom::{Document, NodeType};
+use crate::geometry::Point;
+use crate::style;
+use crate::transform;
+
+/// `node`'s children in paint order: by `z-index`, ties in tree order
+pub fn paint_order(document: &Document, styles: &[ComputedStyle], node: usize) -> Vec<usize> {
+    let mut children = document.nodes[node].children.clone();
+    children.sort_by_key(|&child| styles.get(child).and_then(|style| style.z_index).unwrap_or(0));
+    children
+}
+
+/// Whether `node` receives pointer events: the nearest `pointer-events`
+/// on it or an ancestor is not `none`
+pub fn receives_pointer_events(document: &Document, styles: &[ComputedStyle], node: usize) -> bool {
+    let mut current = Some(node);
+    while let Some(idx) = current {
+        if let Some(pointer_events) = styles.get(idx).and_then(|style| style.pointer_events) {
+            return pointer_events == PointerEvents::Auto;
+        }
+        current = document.nodes[idx].parent;
+    }
+    true
+}
+
+/// The topmost element whose border box contains `point`, in page
+/// coordinates, skipping elements that do not receive pointer events
+pub fn element_from_point(document: &Document, styles: &[ComputedStyle], point: Point) -> Option<usize> {
+    if document.nodes.is_empty() {
+        return None;
+    }
+    hit(document, styles, document.root, point)
+}
+
+fn hit(document: &Document, styles: &[ComputedStyle], node: usize, point: Point) -> Option<usize> {
+    if let Some(child) = paint_order(document, styles, node).into_iter().rev().find_map(|child| hit(document, styles, child, point)) {
+        return Some(child);
+    }
+    let element = &document.nodes[node];
+    if element.node_type != NodeType::Element
+        || !receives_pointer_events(document, styles, node)
+        || !style::inherited_visibility(document, styles, node).is_visible()
+    {
+        return None;
+    }
+    let border_box = document.layout(node)?.border_box();
+    // Map the point back into the untransformed box
+    let local = transform::accumulated_matrix(document, styles, node).inverse()?.transform_point(raqote::Point::new(point.x, point.y));
+    border_box.contains(Point::new(local.x, local.y)).then_some(node)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::Layout;
+    use crate::geometry::Rect;
+
+    /// A body with three 100x50 boxes stacked at the top left, overlapping
+    /// where they share the body's area
+    fn overlapping_boxes() -> (Document, Vec<ComputedStyle>, [usize; 3]) {
+        let mut document = Document::new();
+        let body = document.create_element("body");
+        document.append_child(document.root, body);
+        document.set_layout(body, Some(Layout { rect: Rect::new(0.0, 0.0, 400.0, 300.0), ..Default::default() }));
+        let boxes = [0, 1, 2].map(|i| {
+            let div = document.create_element("div");
+            document.append_child(body, div);
+            document.set_layout(div, Some(Layout { rect: Rect::new(i as f32 * 50.0, 0.0, 100.0, 50.0), ..Default::default() }));
+            div
+        });
+        let styles = vec![ComputedStyle::default(); document.nodes.len()];
+        (document, styles, boxes)
+    }
+
+    #[test]
+    fn test_later_siblings_are_on_top() {
+        let (document, styles, [first, second, third]) = overlapping_boxes();
+
+        assert_eq!(document.element_from_point(&styles, 10.0, 10.0), Some(first));
+        assert_eq!(document.element_from_point(&styles, 60.0, 10.0), Some(second));
+        assert_eq!(document.element_from_point(&styles, 110.0, 10.0), Some(third));
+        assert_eq!(document.element_from_point(&styles, 300.0, 10.0), document.nodes[first].parent, "The body is under the boxes");
+        assert_eq!(document.element_from_point(&styles, 500.0, 10.0), None);
+    }
+
+    #[test]
+    fn test_z_index_and_pointer_events() {
+        // Given: The first box raised above the others, the third ignoring pointers
+        let (document, mut styles, [first, second, third]) = overlapping_boxes();
+        styles[first].z_index = Some(1);
+        styles[third].pointer_events = Some(PointerEvents::None);
+
+        // Then: The raised box wins, and pointers pass through the third
+        assert_eq!(document.element_from_point(&styles, 60.0, 10.0), Some(first));
+        assert_eq!(document.element_from_point(&styles, 110.0, 10.0), Some(second));
+        assert_eq!(paint_order(&document, &styles, document.nodes[first].parent.unwrap()), [second, third, first]);
+    }
+
+    #[test]
+    fn test_transformed_boxes_are_hit_where_they_appear() {
+        let (document, mut styles, [first, _, third]) = overlapping_boxes();
+        styles[first].transform = transform::parse_transform("translateY(100px)").unwrap();
+
+        assert_eq!(document.element_from_point(&styles, 10.0, 120.0), Some(first));
+        assert_ne!(document.element_from_point(&styles, 10.0, 10.0), Some(first));
+        assert_eq!(document.element_from_point(&styles, 140.0, 10.0), Some(third));
+    }
+}
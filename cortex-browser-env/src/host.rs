@@ -0,0 +1,248 @@
+//! Host Functions
+//! Async functions the embedder defines for scripts, awaited as promises
+//!
+//! `host.name(...args)` returns a promise settled by the function's future,
+//! which the page's event loop polls alongside the job queue. Natives drop
+//! their document borrow before running script, since a nested native
+//! would otherwise hit the held `RefCell`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use rquickjs::function::Async;
+use rquickjs::{Ctx, Exception, Function, Object};
+
+/// What a host function's future yields: its result or error message
+pub type HostResult = Pin<Box<dyn Future<Output = Result<String, String>>>>;
+
+/// A host function: its arguments in, a future of its result out
+pub type HostFunction = Rc<dyn Fn(Vec<String>) -> HostResult>;
+
+/// Host functions by the name scripts call them under, as `host.name`
+#[derive(Clone, Default)]
+pub struct HostFunctions {
+    functions: BTreeMap<String, HostFunction>,
+}
+
+impl HostFunctions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<F, Fut>(mut self, name: &str, function: F) -> Self
+    where
+        F: Fn(Vec<String>) -> Fut + 'static,
+        Fut: Future<Output = Result<String, String>> + 'static,
+    {
+        self.functions.insert(name.to_string(), Rc::new(move |args| Box::pin(function(args))));
+        self
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+}
+
+impl fmt::Debug for HostFunctions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostFunctions({:?})", self.names())
+    }
+}
+
+/// `host`, with a method per host function, over the natives of
+/// `install_host`
+const HOST_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexHost;
+    const host = {};
+    for (const name of native.names) {
+        host[name] = (...args) => native.call(name, args.map(String));
+    }
+    globalThis.host = Object.freeze(host);
+    delete globalThis.__cortexHost;
+})();
+"#;
+
+/// Install `host`, whose methods return promises of `functions`' results
+pub fn install_host<'js>(ctx: &Ctx<'js>, functions: HostFunctions) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    native.set("names", functions.names())?;
+    let call = move |ctx: Ctx<'js>, name: String, args: Vec<String>| {
+        let function = functions.functions.get(&name).cloned();
+        // Deferred into the future so the function runs after the script
+        async move {
+            let result = match function {
+                Some(function) => function(args).await,
+                None => Err(format!("host.{} is not a host function", name)),
+            };
+            result.map_err(|message| Exception::throw_message(&ctx, &message))
+        }
+    };
+    native.set("call", Function::new(ctx.clone(), Async(call))?)?;
+    ctx.globals().set("__cortexHost", native)?;
+    ctx.eval::<(), _>(HOST_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::browser::PageBuilder;
+
+    /// Whether the sleep is over, and who to wake when it is
+    type Sleep = Arc<Mutex<(bool, Option<Waker>)>>;
+
+    /// Ready once another thread has slept for `delay`
+    struct Later {
+        delay: Duration,
+        started: Option<Sleep>,
+    }
+
+    impl Future for Later {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if let Some(state) = &self.started {
+                let mut state = state.lock().unwrap();
+                if state.0 {
+                    return Poll::Ready(());
+                }
+                state.1 = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let state = Arc::new(Mutex::new((false, Some(cx.waker().clone()))));
+            let (shared, delay) = (state.clone(), self.delay);
+            thread::spawn(move || {
+                thread::sleep(delay);
+                let mut state = shared.lock().unwrap();
+                state.0 = true;
+                if let Some(waker) = state.1.take() {
+                    waker.wake();
+                }
+            });
+            self.started = Some(state);
+            Poll::Pending
+        }
+    }
+
+    fn prices() -> HostFunctions {
+        let calls = Rc::new(Cell::new(0));
+        HostFunctions::new().with("price", move |args| {
+            calls.set(calls.get() + 1);
+            let call = calls.get();
+            async move {
+                match args.as_slice() {
+                    [sku] if sku == "mug" => Ok(format!("12.50 (call {})", call)),
+                    [sku] => Err(format!("No price for {}", sku)),
+                    _ => Err("Expected one SKU".to_string()),
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_host_functions_settle_promises_after_the_script() {
+        // Given: A page whose host prices products
+        let page = PageBuilder::new().with_seed(1).with_host_functions(prices()).build().unwrap();
+        page.load_html(r#"<html><body><input data-testid="price"/></body></html>"#);
+
+        // When: A script awaits a price and a missing one, writing into the page
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            const input = getByTestId("price");
+            (async () => {
+                const pending = host.price("mug");
+                log.push("called");
+                setValue(input, await pending);
+                try { await host.price("teapot"); } catch (e) { log.push(e.message); }
+            })();
+            log.push("returned");
+            "#,
+        )
+        .unwrap();
+
+        // Then: The calls ran after the script, in order
+        assert_eq!(page.run_script("log.join()").unwrap(), "called,returned,No price for teapot");
+        assert_eq!(page.run_script("getValue(getByTestId('price'))").unwrap(), "12.50 (call 1)");
+        assert_eq!(page.run_script("Object.keys(host).join()").unwrap(), "price");
+    }
+
+    #[test]
+    fn test_host_functions_are_awaited_until_their_futures_finish() {
+        // Given: A host function waiting on another thread, quicker for
+        // later calls
+        let host = HostFunctions::new().with("fetch", |args| async move {
+            let ms: u64 = args[0].parse().map_err(|_| "Expected a delay".to_string())?;
+            Later { delay: Duration::from_millis(ms), started: None }.await;
+            Ok(format!("waited {}", ms))
+        });
+        let page = PageBuilder::new().with_seed(1).with_host_functions(host).build().unwrap();
+
+        // When: A script starts two calls and awaits both
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            host.fetch(40).then(v => log.push(v));
+            host.fetch(10).then(v => log.push(v));
+            host.fetch("soon").catch(e => log.push(e.message));
+            "#,
+        )
+        .unwrap();
+        page.run_until_idle().unwrap();
+
+        // Then: The page waited for both, settling each as it finished
+        assert_eq!(page.run_script("log.join()").unwrap(), "Expected a delay,waited 10,waited 40");
+    }
+
+    #[test]
+    fn test_scripts_reentering_natives_do_not_hit_a_held_borrow() {
+        // Given: A page whose script re-enters natives from inside calls
+        // to natives: argument conversions, listeners of dispatched and
+        // page-fired events, handlers rebound mid-dispatch, host results
+        let page = PageBuilder::new().with_seed(1).with_host_functions(prices()).build().unwrap();
+        page.load_html(r#"<html><body><input data-testid="price"/><button data-testid="save">Save</button></body></html>"#);
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            const input = element(getByTestId("price"));
+            const save = element(getByTestId("save"));
+            input.title = { toString() { input.setAttribute("data-seen", getAttribute(save, "data-testid")); return "Price"; } };
+            setAttribute(save, "data-label", { toString() { return getValue(input) || "empty"; } });
+            input.addEventListener("input", () => {
+                log.push("input " + getValue(input));
+                setAttribute(save, "onclick", "log.push('inline ' + this.getAttribute('data-label'))");
+                save.dispatchEvent("click");
+            });
+            save.addEventListener("click", () => {
+                save.dataset.clicks = Number(save.dataset.clicks || 0) + 1;
+                if (save.dataset.clicks === "1") dispatchEvent(save, "click");
+            });
+            host.price("mug").then(price => { setValue(getByTestId("price"), price); log.push("priced " + input.value); });
+            "#,
+        )
+        .unwrap();
+
+        // When: The page fires input from Rust, then idles
+        page.fill(page.run_script("getByTestId('price').node").unwrap().parse().unwrap(), "3").unwrap();
+        page.run_until_idle().unwrap();
+
+        // Then: Every nested call ran against the document, the inline
+        // handler once per click
+        assert_eq!(page.run_script("[input.title, input.dataset.seen, getAttribute(save, 'data-label'), save.dataset.clicks].join()").unwrap(), "Price,save,empty,2");
+        assert_eq!(page.run_script("log.join()").unwrap(), "priced 12.50 (call 1),input 3,inline empty,inline empty");
+    }
+}
+
@@ -0,0 +1,579 @@
+//! Image Diffing
+//! Compares rendered output with expected images pixel by pixel or
+//! perceptually, and draws diff images that show where they differ
+//!
+//! Perceptual comparison lets each channel drift by a threshold and ignores
+//! anti-aliasing: a differing pixel whose colour also appears in, or blends
+//! between, the other image's neighbouring pixels is an edge drawn slightly
+//! differently, not a change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use raqote::DrawTarget;
+
+use crate::screenshot::{argb_to_rgba, decode_png, encode_rgba_png};
+
+/// Colour of mismatched pixels in diff images
+const MISMATCH_COLOR: [u8; 4] = [255, 0, 0, 255];
+/// Colour of pixels ignored as anti-aliasing in diff images
+const ANTI_ALIASED_COLOR: [u8; 4] = [255, 255, 0, 255];
+/// How much of the expected image shows through behind the highlights
+const BACKGROUND_OPACITY: f32 = 0.1;
+
+/// An 8-bit RGBA image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    /// A fully transparent image
+    pub fn new(width: u32, height: u32) -> Self {
+        Image { width, height, rgba: vec![0; (width * height * 4) as usize] }
+    }
+
+    pub fn from_draw_target(draw_target: &DrawTarget) -> Self {
+        Image {
+            width: draw_target.width() as u32,
+            height: draw_target.height() as u32,
+            rgba: argb_to_rgba(draw_target.get_data()),
+        }
+    }
+
+    pub fn from_png(bytes: &[u8]) -> Result<Self, ImageDiffError> {
+        let (width, height, rgba) = decode_png(bytes).map_err(|e| ImageDiffError::Decode(e.to_string()))?;
+        Ok(Image { width, height, rgba })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ImageDiffError> {
+        let bytes = fs::read(path).map_err(|e| ImageDiffError::Io(format!("Cannot read '{}': {}", path.display(), e)))?;
+        Self::from_png(&bytes)
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, ImageDiffError> {
+        encode_rgba_png(&self.rgba, self.width, self.height).map_err(ImageDiffError::Decode)
+    }
+
+    /// Save as a PNG, creating parent directories
+    pub fn save(&self, path: &Path) -> Result<PathBuf, ImageDiffError> {
+        let io_error = |e: std::io::Error| ImageDiffError::Io(format!("Cannot write '{}': {}", path.display(), e));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(path, self.to_png()?).map_err(io_error)?;
+        Ok(path.to_path_buf())
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.rgba[i], self.rgba[i + 1], self.rgba[i + 2], self.rgba[i + 3]]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.rgba[i..i + 4].copy_from_slice(&color);
+    }
+
+    /// Copy `other` into this image with its top-left corner at (x, y),
+    /// clipping whatever falls outside
+    fn draw(&mut self, other: &Image, x: u32, y: u32) {
+        for oy in 0..other.height.min(self.height.saturating_sub(y)) {
+            for ox in 0..other.width.min(self.width.saturating_sub(x)) {
+                self.set_pixel(x + ox, y + oy, other.pixel(ox, oy));
+            }
+        }
+    }
+}
+
+/// How pixels are compared
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareMode {
+    /// Every channel must be identical
+    Pixel,
+    /// Channels may differ by up to `channel_threshold`; with
+    /// `anti_aliasing`, differences that look like anti-aliased edges are
+    /// ignored
+    Perceptual { channel_threshold: u8, anti_aliasing: bool },
+}
+
+/// Comparison settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffOptions {
+    pub mode: CompareMode,
+    /// Largest share of mismatched pixels, in percent, that still passes
+    pub max_mismatch_percent: f64,
+}
+
+impl DiffOptions {
+    /// Exact comparison; any differing pixel fails
+    pub fn pixel() -> Self {
+        DiffOptions { mode: CompareMode::Pixel, max_mismatch_percent: 0.0 }
+    }
+
+    /// Tolerates small colour drift and anti-aliasing; any other differing
+    /// pixel fails
+    pub fn perceptual() -> Self {
+        DiffOptions {
+            mode: CompareMode::Perceptual { channel_threshold: 16, anti_aliasing: true },
+            max_mismatch_percent: 0.0,
+        }
+    }
+
+    pub fn with_max_mismatch_percent(mut self, percent: f64) -> Self {
+        self.max_mismatch_percent = percent;
+        self
+    }
+
+    pub fn with_channel_threshold(mut self, threshold: u8) -> Self {
+        let anti_aliasing = match self.mode {
+            CompareMode::Perceptual { anti_aliasing, .. } => anti_aliasing,
+            CompareMode::Pixel => false,
+        };
+        self.mode = CompareMode::Perceptual { channel_threshold: threshold, anti_aliasing };
+        self
+    }
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self::perceptual()
+    }
+}
+
+/// How one pixel compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelDiff {
+    Same,
+    AntiAliased,
+    Mismatch,
+}
+
+/// Outcome of comparing two images of the same size
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffResult {
+    pub width: u32,
+    pub height: u32,
+    pub mismatched_pixels: usize,
+    pub anti_aliased_pixels: usize,
+    /// Whether the mismatch stayed within `max_mismatch_percent`
+    pub passed: bool,
+    pixels: Vec<PixelDiff>,
+}
+
+impl DiffResult {
+    pub fn total_pixels(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn mismatch_percent(&self) -> f64 {
+        if self.pixels.is_empty() {
+            return 0.0;
+        }
+        self.mismatched_pixels as f64 * 100.0 / self.pixels.len() as f64
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> PixelDiff {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// The expected image faded out, with mismatched pixels in red and
+    /// ignored anti-aliasing in yellow
+    pub fn diff_image(&self, expected: &Image) -> Image {
+        let mut diff = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = match self.pixel(x, y) {
+                    PixelDiff::Mismatch => MISMATCH_COLOR,
+                    PixelDiff::AntiAliased => ANTI_ALIASED_COLOR,
+                    PixelDiff::Same => faded(expected.pixel(x, y)),
+                };
+                diff.set_pixel(x, y, color);
+            }
+        }
+        diff
+    }
+}
+
+/// Compare `actual` with `expected`
+///
+/// Images of different sizes cannot be compared and are an error.
+pub fn compare(expected: &Image, actual: &Image, options: &DiffOptions) -> Result<DiffResult, ImageDiffError> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(ImageDiffError::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (actual.width, actual.height),
+            side_by_side: None,
+        });
+    }
+
+    let (threshold, anti_aliasing) = match options.mode {
+        CompareMode::Pixel => (0, false),
+        CompareMode::Perceptual { channel_threshold, anti_aliasing } => (channel_threshold, anti_aliasing),
+    };
+    let mut pixels = Vec::with_capacity((expected.width * expected.height) as usize);
+    for y in 0..expected.height {
+        for x in 0..expected.width {
+            let pixel = if close(expected.pixel(x, y), actual.pixel(x, y), threshold) {
+                PixelDiff::Same
+            } else if anti_aliasing
+                && is_anti_aliased(expected, actual, x, y, threshold)
+                && is_anti_aliased(actual, expected, x, y, threshold)
+            {
+                PixelDiff::AntiAliased
+            } else {
+                PixelDiff::Mismatch
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    let mismatched_pixels = pixels.iter().filter(|&&p| p == PixelDiff::Mismatch).count();
+    let anti_aliased_pixels = pixels.iter().filter(|&&p| p == PixelDiff::AntiAliased).count();
+    let mut result = DiffResult {
+        width: expected.width,
+        height: expected.height,
+        mismatched_pixels,
+        anti_aliased_pixels,
+        passed: false,
+        pixels,
+    };
+    result.passed = result.mismatch_percent() <= options.max_mismatch_percent;
+    Ok(result)
+}
+
+/// Expected, actual and diff next to each other, left to right
+///
+/// Smaller images are padded with transparency, so images of different
+/// sizes can be shown together.
+pub fn side_by_side(images: &[&Image]) -> Image {
+    let width = images.iter().map(|image| image.width).sum();
+    let height = images.iter().map(|image| image.height).max().unwrap_or(0);
+    let mut combined = Image::new(width, height);
+    let mut x = 0;
+    for image in images {
+        combined.draw(image, x, 0);
+        x += image.width;
+    }
+    combined
+}
+
+/// Compare `actual` with the PNG at `expected_path`
+///
+/// On failure, `<name>.diff.png` and `<name>.side-by-side.png` (expected,
+/// actual, diff) are written to `artifacts_dir`, where `<name>` is the
+/// expected file's stem, and the error names them.
+pub fn compare_with_file(
+    actual: &Image,
+    expected_path: &Path,
+    options: &DiffOptions,
+    artifacts_dir: &Path,
+) -> Result<DiffResult, ImageDiffError> {
+    let expected = Image::load(expected_path)?;
+    let name = expected_path.file_stem().map_or("image".into(), |stem| stem.to_string_lossy());
+    let side_by_side_path = artifacts_dir.join(format!("{}.side-by-side.png", name));
+
+    let result = match compare(&expected, actual, options) {
+        Ok(result) => result,
+        Err(ImageDiffError::SizeMismatch { expected: expected_size, actual: actual_size, .. }) => {
+            side_by_side(&[&expected, actual]).save(&side_by_side_path)?;
+            return Err(ImageDiffError::SizeMismatch {
+                expected: expected_size,
+                actual: actual_size,
+                side_by_side: Some(side_by_side_path),
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    if result.passed {
+        return Ok(result);
+    }
+
+    let diff = result.diff_image(&expected);
+    let diff_path = diff.save(&artifacts_dir.join(format!("{}.diff.png", name)))?;
+    side_by_side(&[&expected, actual, &diff]).save(&side_by_side_path)?;
+    Err(ImageDiffError::Mismatch {
+        mismatch_percent: result.mismatch_percent(),
+        max_mismatch_percent: options.max_mismatch_percent,
+        diff: diff_path,
+        side_by_side: side_by_side_path,
+    })
+}
+
+fn close(a: [u8; 4], b: [u8; 4], threshold: u8) -> bool {
+    a.iter().zip(&b).all(|(a, b)| a.abs_diff(*b) <= threshold)
+}
+
+/// Whether `image`'s pixel at (x, y) looks like an anti-aliased version of
+/// `other`'s neighbourhood there: it matches a neighbouring pixel, or every
+/// channel lies between the neighbours' smallest and largest values
+fn is_anti_aliased(image: &Image, other: &Image, x: u32, y: u32, threshold: u8) -> bool {
+    let color = image.pixel(x, y);
+    let mut low = [u8::MAX; 4];
+    let mut high = [u8::MIN; 4];
+    for ny in y.saturating_sub(1)..=(y + 1).min(other.height - 1) {
+        for nx in x.saturating_sub(1)..=(x + 1).min(other.width - 1) {
+            let neighbour = other.pixel(nx, ny);
+            if (nx, ny) != (x, y) && close(color, neighbour, threshold) {
+                return true;
+            }
+            for c in 0..4 {
+                low[c] = low[c].min(neighbour[c]);
+                high[c] = high[c].max(neighbour[c]);
+            }
+        }
+    }
+    // A flat neighbourhood has no edge to anti-alias
+    let has_edge = (0..4).any(|c| high[c] - low[c] > threshold);
+    has_edge && (0..4).all(|c| color[c] >= low[c].saturating_sub(threshold) && color[c] <= high[c].saturating_add(threshold))
+}
+
+/// A pixel as a light grey that keeps the picture recognisable behind the
+/// highlights
+fn faded(color: [u8; 4]) -> [u8; 4] {
+    let luma = 0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32;
+    let alpha = color[3] as f32 / 255.0 * BACKGROUND_OPACITY;
+    let value = (255.0 + (luma - 255.0) * alpha) as u8;
+    [value, value, value, 255]
+}
+
+/// Error types for image comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageDiffError {
+    Io(String),
+    Decode(String),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+        side_by_side: Option<PathBuf>,
+    },
+    Mismatch {
+        mismatch_percent: f64,
+        max_mismatch_percent: f64,
+        diff: PathBuf,
+        side_by_side: PathBuf,
+    },
+}
+
+impl std::fmt::Display for ImageDiffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImageDiffError::Io(e) => write!(f, "IO Error: {}", e),
+            ImageDiffError::Decode(e) => write!(f, "Decode Error: {}", e),
+            ImageDiffError::SizeMismatch { expected, actual, side_by_side } => {
+                write!(f, "Image size {}x{} does not match expected {}x{}", actual.0, actual.1, expected.0, expected.1)?;
+                if let Some(path) = side_by_side {
+                    write!(f, " (side by side: {})", path.display())?;
+                }
+                Ok(())
+            }
+            ImageDiffError::Mismatch { mismatch_percent, max_mismatch_percent, diff, side_by_side } => write!(
+                f,
+                "{:.2}% of pixels differ (allowed: {:.2}%); diff: {}, side by side: {}",
+                mismatch_percent,
+                max_mismatch_percent,
+                diff.display(),
+                side_by_side.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageDiffError {}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    /// A white image with a black square from (2, 2) to (5, 5)
+    fn square(offset: u32) -> Image {
+        let mut image = Image::new(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                let inside = (2 + offset..6 + offset).contains(&x) && (2..6).contains(&y);
+                image.set_pixel(x, y, if inside { BLACK } else { WHITE });
+            }
+        }
+        image
+    }
+
+    // ========================================================================
+    // Comparison Tests
+    // ========================================================================
+
+    #[test]
+    fn test_identical_images_match() {
+        let result = compare(&square(0), &square(0), &DiffOptions::pixel()).unwrap();
+
+        assert!(result.passed);
+        assert_eq!(result.mismatched_pixels, 0);
+        assert_eq!(result.total_pixels(), 64);
+    }
+
+    #[test]
+    fn test_pixel_mode_fails_on_any_change_perceptual_tolerates_drift() {
+        // Given: An image with one pixel slightly off
+        let expected = square(0);
+        let mut actual = square(0);
+        actual.set_pixel(0, 0, [250, 252, 255, 255]);
+
+        // When: We compare exactly and perceptually
+        let exact = compare(&expected, &actual, &DiffOptions::pixel()).unwrap();
+        let perceptual = compare(&expected, &actual, &DiffOptions::perceptual()).unwrap();
+
+        // Then: Only the exact comparison fails
+        assert!(!exact.passed);
+        assert_eq!(exact.mismatched_pixels, 1);
+        assert_eq!(exact.pixel(0, 0), PixelDiff::Mismatch);
+        assert!(perceptual.passed);
+    }
+
+    #[test]
+    fn test_anti_aliased_edges_are_ignored() {
+        // Given: A square whose left edge is drawn half-covered
+        let expected = square(0);
+        let mut actual = square(0);
+        for y in 2..6 {
+            actual.set_pixel(2, y, [128, 128, 128, 255]);
+        }
+
+        // When: We compare perceptually, with and without anti-aliasing
+        let tolerant = compare(&expected, &actual, &DiffOptions::perceptual()).unwrap();
+        let strict = compare(&expected, &actual, &DiffOptions::pixel().with_channel_threshold(16)).unwrap();
+
+        // Then: The blended edge only counts when anti-aliasing is not ignored
+        assert!(tolerant.passed, "{:?}", tolerant);
+        assert_eq!(tolerant.anti_aliased_pixels, 4);
+        assert!(!strict.passed);
+        assert_eq!(strict.mismatched_pixels, 4);
+    }
+
+    #[test]
+    fn test_real_changes_are_mismatches() {
+        // Given: A square that turned red
+        let expected = square(0);
+        let mut actual = square(0);
+        for y in 2..6 {
+            for x in 2..6 {
+                actual.set_pixel(x, y, [255, 0, 0, 255]);
+            }
+        }
+
+        let result = compare(&expected, &actual, &DiffOptions::perceptual()).unwrap();
+
+        assert!(!result.passed);
+        assert_eq!(result.mismatched_pixels, 16);
+        assert_eq!(result.mismatch_percent(), 25.0);
+    }
+
+    #[test]
+    fn test_mismatch_threshold() {
+        let expected = square(0);
+        let mut actual = square(0);
+        actual.set_pixel(0, 0, [255, 0, 0, 255]);
+
+        let strict = compare(&expected, &actual, &DiffOptions::perceptual()).unwrap();
+        let lenient = compare(&expected, &actual, &DiffOptions::perceptual().with_max_mismatch_percent(2.0)).unwrap();
+
+        assert!(!strict.passed);
+        assert!(lenient.passed, "1 of 64 pixels is under 2%");
+    }
+
+    #[test]
+    fn test_size_mismatch_is_an_error() {
+        let result = compare(&Image::new(4, 4), &Image::new(4, 5), &DiffOptions::pixel());
+
+        assert_eq!(
+            result,
+            Err(ImageDiffError::SizeMismatch { expected: (4, 4), actual: (4, 5), side_by_side: None })
+        );
+    }
+
+    // ========================================================================
+    // Diff Image Tests
+    // ========================================================================
+
+    #[test]
+    fn test_diff_image_highlights_mismatches() {
+        let expected = square(0);
+        let mut actual = square(0);
+        actual.set_pixel(0, 0, BLACK);
+
+        let result = compare(&expected, &actual, &DiffOptions::perceptual()).unwrap();
+        let diff = result.diff_image(&expected);
+
+        assert_eq!(diff.pixel(0, 0), MISMATCH_COLOR);
+        let background = diff.pixel(7, 7);
+        assert_eq!(background, [255, 255, 255, 255]);
+        let faded_square = diff.pixel(3, 3);
+        assert!(faded_square[0] < 255 && faded_square[0] > 200, "{:?}", faded_square);
+    }
+
+    #[test]
+    fn test_side_by_side_pads_smaller_images() {
+        let mut left = Image::new(2, 2);
+        left.set_pixel(1, 1, BLACK);
+        let mut right = Image::new(3, 1);
+        right.set_pixel(0, 0, WHITE);
+
+        let combined = side_by_side(&[&left, &right]);
+
+        assert_eq!((combined.width, combined.height), (5, 2));
+        assert_eq!(combined.pixel(1, 1), BLACK);
+        assert_eq!(combined.pixel(2, 0), WHITE);
+        assert_eq!(combined.pixel(2, 1), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_compare_with_file_writes_artifacts_on_failure() {
+        // Given: An expected image on disk
+        let dir = tempfile::tempdir().unwrap();
+        let expected_path = square(0).save(&dir.path().join("button.png")).unwrap();
+        let artifacts = dir.path().join("artifacts");
+
+        // When: A matching image and a changed one are compared
+        let matching = compare_with_file(&square(0), &expected_path, &DiffOptions::perceptual(), &artifacts);
+        let changed = compare_with_file(&square(2), &expected_path, &DiffOptions::perceptual(), &artifacts);
+
+        // Then: Only the failure writes artifacts, and the error points at them
+        assert!(matching.unwrap().passed);
+        match changed {
+            Err(ImageDiffError::Mismatch { diff, side_by_side, .. }) => {
+                assert_eq!(diff, artifacts.join("button.diff.png"));
+                let combined = Image::load(&side_by_side).unwrap();
+                assert_eq!((combined.width, combined.height), (24, 8));
+                assert_eq!(Image::load(&diff).unwrap().pixel(6, 3), MISMATCH_COLOR);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // When: An image of another size is compared
+        let resized = compare_with_file(&Image::new(4, 4), &expected_path, &DiffOptions::perceptual(), &artifacts);
+
+        // Then: Only a side-by-side image is written
+        let message = resized.unwrap_err().to_string();
+        assert!(message.starts_with("Image size 4x4 does not match expected 8x8"), "{}", message);
+        assert!(message.contains("button.side-by-side.png"), "{}", message);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/square.png");
+
+        square(1).save(&path).unwrap();
+
+        assert_eq!(Image::load(&path).unwrap(), square(1));
+        assert!(matches!(Image::from_png(b"not a png"), Err(ImageDiffError::Decode(_))));
+        assert!(matches!(Image::load(&dir.path().join("missing.png")), Err(ImageDiffError::Io(_))));
+    }
+}
@@ -0,0 +1,328 @@
+//! Image Loading
+//! Decodes PNG and JPEG images referenced by `<img src>` and
+//! `background-image`, caching the bitmaps for the render pass
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::css::ComputedStyle;
+use crate::dom::{Document, NodeData};
+use crate::network::{fetch, NetworkError, ResourceLoader};
+use crate::screenshot::decode_png;
+
+/// Error type for image loading
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageError {
+    NetworkError(NetworkError),
+    DecodeError(String),
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::NetworkError(e) => write!(f, "Network Error: {}", e),
+            ImageError::DecodeError(msg) => write!(f, "Decode Error: {}", msg),
+            ImageError::UnsupportedFormat(msg) => write!(f, "Unsupported Format: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// A decoded bitmap in raqote's premultiplied ARGB layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+impl DecodedImage {
+    /// Build an image from straight (non-premultiplied) RGBA bytes
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8]) -> Self {
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|p| {
+                let a = p[3] as u32;
+                let premultiply = |c: u8| (c as u32 * a + 127) / 255;
+                (a << 24) | (premultiply(p[0]) << 16) | (premultiply(p[1]) << 8) | premultiply(p[2])
+            })
+            .collect();
+        DecodedImage { width, height, pixels }
+    }
+}
+
+/// Decode PNG or JPEG bytes, detected from the file signature
+pub fn decode_image(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let (width, height, rgba) =
+            decode_png(bytes).map_err(|e| ImageError::DecodeError(e.to_string()))?;
+        Ok(DecodedImage::from_rgba(width, height, &rgba))
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        decode_jpeg(bytes)
+    } else {
+        Err(ImageError::UnsupportedFormat("Not a PNG or JPEG image".to_string()))
+    }
+}
+
+fn decode_jpeg(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    use jpeg_decoder::{Decoder, PixelFormat};
+
+    let mut decoder = Decoder::new(bytes);
+    let data = decoder
+        .decode()
+        .map_err(|e| ImageError::DecodeError(format!("JPEG error: {}", e)))?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| ImageError::DecodeError("JPEG missing header".to_string()))?;
+
+    let rgba: Vec<u8> = match info.pixel_format {
+        PixelFormat::RGB24 => data.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        PixelFormat::L8 => data.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        PixelFormat::L16 => data.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], 255]).collect(),
+        PixelFormat::CMYK32 => data
+            .chunks_exact(4)
+            .flat_map(|p| {
+                // Adobe JPEGs store inverted CMYK
+                let k = p[3] as u32;
+                let channel = |c: u8| (c as u32 * k / 255) as u8;
+                [channel(p[0]), channel(p[1]), channel(p[2]), 255]
+            })
+            .collect(),
+    };
+
+    Ok(DecodedImage::from_rgba(info.width as u32, info.height as u32, &rgba))
+}
+
+/// Cache of decoded images keyed by URL
+///
+/// Failures are cached too, so a broken URL is only fetched once per page.
+#[derive(Debug, Default)]
+pub struct ImageCache {
+    entries: HashMap<String, Result<Rc<DecodedImage>, ImageError>>,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load an image, fetching and decoding it on first use
+    pub fn load(&mut self, loader: &dyn ResourceLoader, url: &str) -> Result<Rc<DecodedImage>, ImageError> {
+        self.entries
+            .entry(url.to_string())
+            .or_insert_with(|| {
+                let bytes = fetch(loader, url).map_err(ImageError::NetworkError)?;
+                decode_image(&bytes).map(Rc::new)
+            })
+            .clone()
+    }
+
+    /// Get a successfully decoded image, if it has been loaded
+    pub fn get(&self, url: &str) -> Option<Rc<DecodedImage>> {
+        self.entries.get(url).and_then(|entry| entry.as_ref().ok()).cloned()
+    }
+
+    /// Number of cached entries, including failures
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Image a node paints: `<img src>` for replaced elements, otherwise its
+/// `background-image`
+pub fn image_source(document: &Document, node_idx: usize, style: Option<&ComputedStyle>) -> Option<String> {
+    if let Some(NodeData::Element(elem)) = document.nodes.get(node_idx).and_then(|n| n.data.as_ref()) {
+        if elem.tag_name == "img" {
+            return elem.attributes.get("src").filter(|src| !src.trim().is_empty()).cloned();
+        }
+    }
+    style.and_then(|s| s.background_image.clone())
+}
+
+/// Result of loading one node's image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageLoad {
+    pub node_idx: usize,
+    pub url: String,
+    pub result: Result<(), ImageError>,
+    /// Whether the node is an `<img>`, rather than a background image
+    pub img: bool,
+}
+
+impl ImageLoad {
+    /// The event the node receives: `load` or `error` for an `<img>`,
+    /// nothing for a background image
+    pub fn event_type(&self) -> Option<&'static str> {
+        self.img.then_some(if self.result.is_ok() { "load" } else { "error" })
+    }
+}
+
+/// Load every image referenced by the document into the cache
+///
+/// The page fires `load` or `error` at each `<img>` in the result once its
+/// image has been fetched and decoded, like in a browser; see
+/// `ImageLoad::event_type`. Background images load silently.
+pub fn load_document_images(
+    document: &Document,
+    styles: &[ComputedStyle],
+    loader: &dyn ResourceLoader,
+    cache: &mut ImageCache,
+) -> Vec<ImageLoad> {
+    let mut loads = Vec::new();
+    for node_idx in 0..document.nodes.len() {
+        let Some(url) = image_source(document, node_idx, styles.get(node_idx)) else {
+            continue;
+        };
+
+        let result = cache.load(loader, &url).map(|_| ());
+        loads.push(ImageLoad { node_idx, url, result, img: is_img_element(document, node_idx) });
+    }
+    loads
+}
+
+fn is_img_element(document: &Document, node_idx: usize) -> bool {
+    matches!(
+        document.nodes.get(node_idx).and_then(|n| n.data.as_ref()),
+        Some(NodeData::Element(elem)) if elem.tag_name == "img"
+    )
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{encode_data_uri, MockNetwork};
+
+    /// Encode a solid-color RGBA PNG
+    fn solid_png(width: u32, height: u32, rgba: [u8; 4]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        let data: Vec<u8> = (0..width * height).flat_map(|_| rgba).collect();
+        writer.write_image_data(&data).unwrap();
+        drop(writer);
+        bytes
+    }
+
+    #[test]
+    fn test_decode_png_premultiplies() {
+        // Given: A half-transparent red PNG
+        let bytes = solid_png(2, 1, [255, 0, 0, 128]);
+
+        // When: We decode it
+        let image = decode_image(&bytes).unwrap();
+
+        // Then: Pixels are premultiplied ARGB
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, vec![0x80800000, 0x80800000]);
+    }
+
+    #[test]
+    fn test_decode_rejects_unknown_format() {
+        assert!(matches!(decode_image(b"GIF89a"), Err(ImageError::UnsupportedFormat(_))));
+        assert!(matches!(decode_image(&[0xff, 0xd8, 0x00]), Err(ImageError::DecodeError(_))));
+    }
+
+    #[test]
+    fn test_cache_fetches_each_url_once() {
+        // Given: A mock network serving one image
+        let network = MockNetwork::new().with_response("/logo.png", solid_png(1, 1, [0, 0, 255, 255]));
+        let mut cache = ImageCache::new();
+
+        // When: We load it twice and a missing image twice
+        cache.load(&network, "/logo.png").unwrap();
+        cache.load(&network, "/logo.png").unwrap();
+        assert!(cache.load(&network, "/missing.png").is_err());
+        assert!(cache.load(&network, "/missing.png").is_err());
+
+        // Then: Each URL hit the network once
+        assert_eq!(network.requests(), vec!["/logo.png", "/missing.png"]);
+        assert!(cache.get("/logo.png").is_some());
+        assert!(cache.get("/missing.png").is_none());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_load_document_images_from_img_and_background() {
+        // Given: An <img>, a broken <img> and a div with a background image
+        let mut doc = Document::new();
+        let img_idx = doc.create_element("img");
+        let broken_idx = doc.create_element("img");
+        let hero_idx = doc.create_element("div");
+        doc.set_attribute(img_idx, "src", "/logo.png");
+        doc.set_attribute(broken_idx, "src", "/gone.png");
+        for idx in [img_idx, broken_idx, hero_idx] {
+            doc.append_child(doc.root, idx);
+        }
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[hero_idx].background_image = Some("/hero.png".to_string());
+
+        let network = MockNetwork::new()
+            .with_response("/logo.png", solid_png(1, 1, [0, 0, 0, 255]))
+            .with_response("/hero.png", solid_png(1, 1, [0, 0, 0, 255]));
+        let mut cache = ImageCache::new();
+
+        // When: We load the document's images
+        let loads = load_document_images(&doc, &styles, &network, &mut cache);
+
+        // Then: Each reference is reported with its outcome
+        assert_eq!(loads.len(), 3);
+        assert_eq!(loads[0], ImageLoad { node_idx: img_idx, url: "/logo.png".to_string(), result: Ok(()), img: true });
+        assert!(matches!(loads[1].result, Err(ImageError::NetworkError(NetworkError::NotFound(_)))));
+        assert_eq!(loads[2].node_idx, hero_idx);
+        assert!(loads[2].result.is_ok());
+
+        // And only the <img>s receive events
+        let events: Vec<_> = loads.iter().map(ImageLoad::event_type).collect();
+        assert_eq!(events, vec![Some("load"), Some("error"), None]);
+    }
+
+    #[test]
+    fn test_load_image_from_data_uri() {
+        // Given: An <img> whose source is an inline PNG
+        let png = solid_png(1, 1, [0, 255, 0, 255]);
+        let url = encode_data_uri("image/png", &png);
+        let network = MockNetwork::new();
+        let mut cache = ImageCache::new();
+
+        // When: We load it
+        let image = cache.load(&network, &url).unwrap();
+
+        // Then: It decodes without touching the network
+        assert_eq!(image.pixels, vec![0xff00ff00]);
+        assert!(network.requests().is_empty());
+    }
+
+    #[test]
+    fn test_background_image_data_uri_from_stylesheet() {
+        // Given: A stylesheet inlining a percent-encoded PNG background
+        let png = solid_png(1, 1, [255, 0, 0, 255]);
+        let encoded: String = png.iter().map(|b| format!("%{:02X}", b)).collect();
+        let css = format!(".hero {{ background-image: url(\"data:image/png,{}\"); }}", encoded);
+        let doc = crate::parser::parse_html(r#"<div class="hero"></div>"#);
+        let styles = crate::style::compute_styles(&doc, &crate::css::parse_css(&css));
+        let network = MockNetwork::new();
+        let mut cache = ImageCache::new();
+
+        // When: We load the document's images
+        let loads = load_document_images(&doc, &styles, &network, &mut cache);
+
+        // Then: The inline image decodes natively
+        assert_eq!(loads.len(), 1);
+        assert!(loads[0].result.is_ok());
+        assert_eq!(cache.get(&loads[0].url).unwrap().pixels, vec![0xffff0000]);
+        assert!(network.requests().is_empty());
+    }
+}
@@ -0,0 +1,612 @@
+//! Inline Formatting
+//! Flows text and inline boxes into line boxes that share a baseline
+//!
+//! A block's run of inline-level children (text, inline elements and inline
+//! blocks) is cut into pieces at break opportunities and laid out left to
+//! right, wrapping at the block's content edge. A line box is as tall as the
+//! block's own strut and everything on it, and all of it sits on one
+//! baseline. Text nodes get a box around their fragments plus one
+//! `TextFragment` per line; inline elements get the box around their
+//! content, widened by the padding, border and margin at their start and
+//! end. Text is measured on the fixed-width glyph grid paint draws with, so
+//! glyphs land in the fragments made for them.
+
+use std::collections::HashMap;
+use crate::css::ComputedStyle;
+use crate::display_list::GlyphStyle;
+use crate::dom::{Display, Document, NodeData};
+use crate::fonts::default_line_metrics;
+use crate::geometry::Rect;
+use crate::layout::MeasuredBox;
+use crate::text::{WordBreaking, SOFT_HYPHEN};
+
+/// One line's worth of a text node, from the last layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFragment {
+    /// The node's part of the line box, relative to the node's box
+    pub rect: Rect,
+    /// Distance from the top of `rect` down to the baseline
+    pub baseline: f32,
+    /// The text on this line, with whitespace already collapsed
+    pub text: String,
+}
+
+/// Advance of one glyph at `font_size`
+pub fn glyph_width(font_size: f32) -> f32 {
+    font_size * 0.6
+}
+
+/// The glyph boxes text at `font_size` is measured and painted with: one
+/// em tall, standing on the baseline
+pub fn glyph_style(font_size: f32, letter_spacing: f32, word_spacing: f32) -> GlyphStyle {
+    GlyphStyle { width: glyph_width(font_size), height: font_size, letter_spacing, word_spacing, color: 0xff000000, bold: false }
+}
+
+/// Room a line of text at `font_size` needs above and below its baseline,
+/// half the leading on each side
+fn strut(font_size: f32) -> (f32, f32) {
+    let metrics = default_line_metrics(font_size);
+    let line_height = metrics.line_height();
+    let above = metrics.baseline_in(line_height);
+    (above, line_height - above)
+}
+
+/// The nearest value `get` finds on `idx` or an ancestor
+fn inherited<T>(document: &Document, styles: &[ComputedStyle], idx: usize, get: impl Fn(&ComputedStyle) -> Option<T>) -> Option<T> {
+    std::iter::successors(Some(idx), |&idx| document.nodes[idx].parent).find_map(|idx| styles.get(idx).and_then(&get))
+}
+
+/// What a piece of inline content is
+#[derive(Debug, Clone)]
+enum Kind {
+    /// Text up to a break opportunity, including the spaces before it
+    Text { text: String, glyph: GlyphStyle, collapses: bool },
+    /// The start of an inline element: its left margin, border and padding
+    Open { width: f32, margin: f32 },
+    /// The end of an inline element: its right padding, border and margin
+    Close { width: f32, margin: f32 },
+    /// An inline block, placed whole by its margin box
+    Atomic { width: f32 },
+}
+
+/// A piece of inline content with the room it needs around the baseline
+#[derive(Debug, Clone)]
+struct Item {
+    node: usize,
+    kind: Kind,
+    ascent: f32,
+    descent: f32,
+    /// Whether the line may wrap after this piece
+    soft_break: bool,
+    /// Whether the line must end after this piece
+    forced_break: bool,
+}
+
+impl Item {
+    fn width(&self) -> f32 {
+        match &self.kind {
+            Kind::Text { text, glyph, .. } => text.chars().map(|ch| glyph.advance(ch)).sum(),
+            Kind::Open { width, .. } | Kind::Close { width, .. } | Kind::Atomic { width } => *width,
+        }
+    }
+
+    /// Width of the spaces that end a text piece
+    fn trailing_space(&self) -> f32 {
+        match &self.kind {
+            Kind::Text { text, glyph, .. } => (text.len() - text.trim_end_matches(' ').len()) as f32 * glyph.advance(' '),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Cut a run of measured boxes into pieces, in document order
+fn collect_items(document: &Document, styles: &[ComputedStyle], measured: &MeasuredBox, available: f32, items: &mut Vec<Item>) {
+    let Some(layout) = &measured.layout else { return };
+    let idx = measured.idx;
+    if let Some(NodeData::Text(text)) = &document.nodes[idx].data {
+        text_items(document, styles, idx, text, layout.font_size, available, items);
+        return;
+    }
+    if layout.display != Display::Inline {
+        let margin_box = layout.margin_box();
+        let (width, height) = (margin_box.width, margin_box.height);
+        let ascent = layout.margin.top + layout.baseline;
+        items.push(Item { node: idx, kind: Kind::Atomic { width }, ascent, descent: height - ascent, soft_break: true, forced_break: false });
+        return;
+    }
+
+    let (ascent, descent) = strut(layout.font_size);
+    let edge = |kind| Item { node: idx, kind, ascent, descent, soft_break: false, forced_break: false };
+    items.push(edge(Kind::Open { width: layout.margin.left + layout.border.left + layout.padding.left, margin: layout.margin.left }));
+    for child in &measured.children {
+        collect_items(document, styles, child, available, items);
+    }
+    items.push(edge(Kind::Close { width: layout.padding.right + layout.border.right + layout.margin.right, margin: layout.margin.right }));
+}
+
+/// Cut a text node into pieces at its break opportunities
+///
+/// Whitespace collapses by `white-space` and the text is case-mapped by
+/// `text-transform` before it is measured, so fragments hold what paint
+/// draws; lines may break after spaces when it wraps, must break at
+/// newlines it keeps, and break inside words as `word-break` and
+/// `overflow-wrap` allow.
+fn text_items(document: &Document, styles: &[ComputedStyle], idx: usize, text: &str, font_size: f32, available: f32, items: &mut Vec<Item>) {
+    let white_space = inherited(document, styles, idx, |style| style.white_space).unwrap_or_default();
+    let word_break = inherited(document, styles, idx, |style| style.word_break).unwrap_or_default();
+    let breaking = word_break.breaking(inherited(document, styles, idx, |style| style.overflow_wrap).unwrap_or_default());
+    let letter_spacing = inherited(document, styles, idx, |style| style.letter_spacing.as_ref().map(|v| v.as_pixels(0.0)));
+    let word_spacing = inherited(document, styles, idx, |style| style.word_spacing.as_ref().map(|v| v.as_pixels(0.0)));
+    let glyph = glyph_style(font_size, letter_spacing.unwrap_or(0.0), word_spacing.unwrap_or(0.0));
+    let transform = inherited(document, styles, idx, |style| style.text_transform).unwrap_or_default();
+    let (ascent, descent) = strut(font_size);
+    let wraps = white_space.wraps();
+    let collapses = white_space.collapses();
+
+    let mut pieces: Vec<(String, bool)> = Vec::new();
+    let mut current = String::new();
+    for ch in transform.apply(&white_space.apply(text)).chars().filter(|&ch| ch != SOFT_HYPHEN) {
+        if ch == '\n' && !collapses {
+            pieces.push((std::mem::take(&mut current), true));
+            continue;
+        }
+        if wraps && ch != ' ' && !current.is_empty() && (current.ends_with(' ') || breaking == WordBreaking::Anywhere) {
+            pieces.push((std::mem::take(&mut current), false));
+        }
+        current.push(ch);
+    }
+    if !current.is_empty() {
+        pieces.push((current, false));
+    }
+
+    for (text, forced_break) in pieces {
+        let soft_break = wraps && (text.ends_with(' ') || breaking == WordBreaking::Anywhere);
+        let chunks = if wraps && breaking == WordBreaking::Overlong { split_overlong(&text, available, &glyph) } else { vec![text] };
+        let last = chunks.len() - 1;
+        for (i, text) in chunks.into_iter().enumerate() {
+            items.push(Item {
+                node: idx,
+                kind: Kind::Text { text, glyph, collapses },
+                ascent,
+                descent,
+                soft_break: i < last || soft_break,
+                forced_break: i == last && forced_break,
+            });
+        }
+    }
+}
+
+/// Split a word too wide for a line of its own into chunks that fit
+fn split_overlong(text: &str, available: f32, glyph: &GlyphStyle) -> Vec<String> {
+    let mut chunks = vec![String::new()];
+    let mut width = 0.0;
+    for ch in text.chars() {
+        let advance = glyph.advance(ch);
+        let chunk = chunks.last_mut().expect("always one chunk");
+        if !chunk.is_empty() && width + advance > available && ch != ' ' {
+            chunks.push(String::new());
+            width = 0.0;
+        }
+        chunks.last_mut().expect("always one chunk").push(ch);
+        width += advance;
+    }
+    chunks
+}
+
+/// A piece at its place on a line
+#[derive(Debug, Clone)]
+struct Placed {
+    item: Item,
+    x: f32,
+    width: f32,
+}
+
+/// Fills lines with the pieces between one break opportunity and the next
+struct LineBreaker {
+    lines: Vec<Vec<Placed>>,
+    x: f32,
+    available: f32,
+}
+
+impl LineBreaker {
+    fn current(&self) -> &[Placed] {
+        self.lines.last().map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether the line holds anything visible yet, not just element edges
+    fn has_content(&self) -> bool {
+        self.current().iter().any(|placed| match &placed.item.kind {
+            Kind::Text { text, .. } => !text.is_empty(),
+            Kind::Atomic { .. } => true,
+            _ => false,
+        })
+    }
+
+    fn ends_with_space(&self) -> bool {
+        self.current().iter().rev().find_map(|placed| match &placed.item.kind {
+            Kind::Text { text, .. } if !text.is_empty() => Some(text.ends_with(' ')),
+            Kind::Atomic { .. } => Some(false),
+            _ => None,
+        }) == Some(true)
+    }
+
+    /// Place the pending pieces, on a new line if they do not fit on this
+    /// one; spaces at their end may hang past the edge
+    fn commit(&mut self, pending: &mut Vec<Item>) {
+        let width: f32 = pending.iter().map(Item::width).sum::<f32>() - pending.last().map_or(0.0, Item::trailing_space);
+        if self.has_content() && self.x + width > self.available {
+            self.new_line();
+        }
+        for item in pending.drain(..) {
+            self.place(item);
+        }
+    }
+
+    fn place(&mut self, mut item: Item) {
+        let at_line_start = !self.has_content() || self.ends_with_space();
+        if let Kind::Text { text, collapses: true, .. } = &mut item.kind {
+            if at_line_start {
+                *text = text.trim_start_matches(' ').to_string();
+            }
+        }
+        let width = item.width();
+        let x = self.x;
+        self.x += width;
+        self.lines.last_mut().expect("always one line").push(Placed { item, x, width });
+    }
+
+    fn new_line(&mut self) {
+        self.trim_line_end();
+        self.lines.push(Vec::new());
+        self.x = 0.0;
+    }
+
+    /// Drop collapsible spaces from the end of the line, moving the element
+    /// edges after them back
+    fn trim_line_end(&mut self) {
+        let Some(line) = self.lines.last_mut() else { return };
+        let Some(last) = line.iter().rposition(|placed| matches!(placed.item.kind, Kind::Text { .. } | Kind::Atomic { .. })) else { return };
+        let trimmed = match &mut line[last].item.kind {
+            Kind::Text { text, glyph, collapses: true } => {
+                let spaces = text.len() - text.trim_end_matches(' ').len();
+                text.truncate(text.len() - spaces);
+                spaces as f32 * glyph.advance(' ')
+            }
+            _ => return,
+        };
+        line[last].width -= trimmed;
+        for placed in &mut line[last + 1..] {
+            placed.x -= trimmed;
+        }
+        self.x -= trimmed;
+    }
+}
+
+/// Break pieces into lines no wider than `available` where possible
+fn break_into_lines(items: Vec<Item>, available: f32) -> Vec<Vec<Placed>> {
+    let mut breaker = LineBreaker { lines: vec![Vec::new()], x: 0.0, available };
+    let mut pending = Vec::new();
+    for item in items {
+        if matches!(item.kind, Kind::Atomic { .. }) {
+            breaker.commit(&mut pending);
+        }
+        let (soft_break, forced_break) = (item.soft_break, item.forced_break);
+        pending.push(item);
+        if soft_break || forced_break {
+            breaker.commit(&mut pending);
+        }
+        if forced_break {
+            breaker.new_line();
+        }
+    }
+    breaker.commit(&mut pending);
+    breaker.trim_line_end();
+    breaker.lines
+}
+
+/// Extent of an inline element's content across the lines it is on
+#[derive(Debug, Clone, Copy)]
+struct InlineExtent {
+    left: f32,
+    right: f32,
+    top: f32,
+    bottom: f32,
+    /// The baseline of its first line
+    baseline: f32,
+}
+
+impl InlineExtent {
+    fn new(baseline: f32) -> Self {
+        InlineExtent { left: f32::INFINITY, right: f32::NEG_INFINITY, top: f32::INFINITY, bottom: f32::NEG_INFINITY, baseline }
+    }
+
+    fn extend(&mut self, left: f32, right: f32, top: f32, bottom: f32) {
+        self.left = self.left.min(left);
+        self.right = self.right.max(right);
+        self.top = self.top.min(top);
+        self.bottom = self.bottom.max(bottom);
+    }
+}
+
+/// Where the flow put each node, relative to the block's content box
+#[derive(Debug, Default)]
+struct Placements {
+    texts: HashMap<usize, Vec<TextFragment>>,
+    /// Top left of each inline block's margin box
+    atomics: HashMap<usize, (f32, f32)>,
+    inlines: HashMap<usize, InlineExtent>,
+}
+
+/// Lay out a run of inline-level siblings in a block whose content box is
+/// `available` wide, with the block's `font_size` setting the minimum line
+/// height
+///
+/// Lines start at the top of the content box. The boxes of the run and of
+/// everything inside its inline elements are moved and resized in place.
+pub(crate) fn flow(document: &Document, styles: &[ComputedStyle], run: &mut [MeasuredBox], available: f32, font_size: f32) {
+    let mut items = Vec::new();
+    for measured in run.iter() {
+        collect_items(document, styles, measured, available, &mut items);
+    }
+    if items.is_empty() {
+        return;
+    }
+    let lines = break_into_lines(items, available);
+
+    // Each line is tall enough for the block's strut, everything on it,
+    // and the struts of inline elements that continue from the line above
+    let block_strut = strut(font_size);
+    let mut open: Vec<(usize, f32, f32)> = Vec::new();
+    let mut baselines = Vec::with_capacity(lines.len());
+    let mut top = 0.0;
+    for line in &lines {
+        let (mut above, mut below) = open.iter().fold(block_strut, |(a, d), &(_, ascent, descent)| (a.max(ascent), d.max(descent)));
+        for placed in line {
+            above = above.max(placed.item.ascent);
+            below = below.max(placed.item.descent);
+            match placed.item.kind {
+                Kind::Open { .. } => open.push((placed.item.node, placed.item.ascent, placed.item.descent)),
+                Kind::Close { .. } => {
+                    open.pop();
+                }
+                _ => {}
+            }
+        }
+        baselines.push(top + above);
+        top += above + below;
+    }
+
+    let mut placements = Placements::default();
+    let mut open: Vec<(usize, f32, f32)> = Vec::new();
+    for (line, &baseline) in lines.into_iter().zip(&baselines) {
+        for Placed { item, x, width } in line {
+            let (left, right) = match item.kind {
+                Kind::Open { margin, .. } => {
+                    open.push((item.node, item.ascent, item.descent));
+                    placements.inlines.entry(item.node).or_insert_with(|| InlineExtent::new(baseline));
+                    (x + margin, x + width)
+                }
+                Kind::Close { margin, .. } => (x, x + width - margin),
+                _ => (x, x + width),
+            };
+            for &(node, ascent, descent) in &open {
+                if let Some(extent) = placements.inlines.get_mut(&node) {
+                    extent.extend(left, right, baseline - ascent, baseline + descent);
+                }
+            }
+            match item.kind {
+                Kind::Text { text, .. } => {
+                    // Pieces of one node next to each other make one fragment
+                    let rect = Rect::new(x, baseline - item.ascent, width, item.ascent + item.descent);
+                    let fragments = placements.texts.entry(item.node).or_default();
+                    match fragments.last_mut() {
+                        Some(last) if last.rect.y == rect.y && (last.rect.right() - x).abs() < 1e-3 => {
+                            last.text.push_str(&text);
+                            last.rect.width += width;
+                        }
+                        _ => fragments.push(TextFragment { rect, baseline: item.ascent, text }),
+                    }
+                }
+                Kind::Atomic { .. } => {
+                    placements.atomics.insert(item.node, (x, baseline - item.ascent));
+                }
+                Kind::Close { .. } => {
+                    open.pop();
+                }
+                Kind::Open { .. } => {}
+            }
+        }
+    }
+
+    for measured in run.iter_mut() {
+        place(measured, (0.0, 0.0), &placements);
+    }
+}
+
+/// Move a measured box to where the flow put it; `origin` is the top left
+/// of its parent's content box, relative to the block's
+fn place(measured: &mut MeasuredBox, origin: (f32, f32), placements: &Placements) {
+    let Some(layout) = measured.layout.as_mut() else { return };
+    let idx = measured.idx;
+    if let Some(fragments) = placements.texts.get(&idx) {
+        let bounds = fragments.iter().map(|fragment| fragment.rect).reduce(|a, b| a.union(&b)).unwrap_or_default();
+        layout.rect.x = bounds.x - origin.0;
+        layout.rect.y = bounds.y - origin.1;
+        layout.rect.width = bounds.width;
+        layout.rect.height = bounds.height;
+        layout.content_width = bounds.width;
+        layout.content_height = bounds.height;
+        layout.baseline = fragments[0].rect.y + fragments[0].baseline - bounds.y;
+        measured.fragments = fragments
+            .iter()
+            .filter(|fragment| !fragment.text.is_empty())
+            .map(|fragment| TextFragment { rect: fragment.rect.translate(-bounds.x, -bounds.y), ..fragment.clone() })
+            .collect();
+    } else if let Some(&(x, y)) = placements.atomics.get(&idx) {
+        layout.rect.x = x + layout.margin.left - origin.0;
+        layout.rect.y = y + layout.margin.top - origin.1;
+    } else if let Some(extent) = placements.inlines.get(&idx) {
+        // Vertical padding and borders do not move the line, they stick out
+        let border_top = extent.top - layout.padding.top - layout.border.top;
+        layout.rect.x = extent.left - origin.0;
+        layout.rect.y = border_top - origin.1;
+        layout.rect.width = extent.right - extent.left;
+        layout.content_width = (layout.rect.width - layout.padding.horizontal() - layout.border.horizontal()).max(0.0);
+        layout.content_height = extent.bottom - extent.top;
+        layout.rect.height = layout.content_height + layout.padding.vertical() + layout.border.vertical();
+        layout.baseline = extent.baseline - border_top;
+        let content_origin = (extent.left + layout.border.left + layout.padding.left, extent.top);
+        for child in &mut measured.children {
+            place(child, content_origin, placements);
+        }
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::parse_css;
+    use crate::forms::tag_name;
+    use crate::layout::calculate_styled_layout;
+    use crate::parser::parse_html;
+    use crate::style::compute_styles;
+
+    /// Lay out `html` with `css` in a viewport `width` wide
+    fn laid_out(html: &str, css: &str, width: f32) -> Document {
+        let mut document = parse_html(html);
+        let styles = compute_styles(&document, &parse_css(css));
+        calculate_styled_layout(&mut document, &styles, width, 600.0);
+        document
+    }
+
+    fn find_text(document: &Document, needle: &str) -> usize {
+        (0..document.nodes.len())
+            .find(|&idx| matches!(&document.nodes[idx].data, Some(NodeData::Text(text)) if text.contains(needle)))
+            .unwrap()
+    }
+
+    fn find_element(document: &Document, tag: &str) -> usize {
+        (0..document.nodes.len()).find(|&idx| tag_name(document, idx) == Some(tag)).unwrap()
+    }
+
+    fn fragment_texts(document: &Document, idx: usize) -> Vec<String> {
+        document.text_fragments(idx).unwrap_or_default().iter().map(|fragment| fragment.text.clone()).collect()
+    }
+
+    /// `idx`'s border box, relative to the content box of `block`
+    fn relative(document: &Document, idx: usize, block: usize) -> Rect {
+        let content = document.layout(block).unwrap().content_box();
+        document.layout(idx).unwrap().border_box().translate(-content.x, -content.y)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_text_and_inline_elements_share_a_line() {
+        // Given: A paragraph with a span in the middle of its text
+        let document = laid_out("<p>Hello <span>big</span> world</p>", "", 800.0);
+        let p = find_element(&document, "p");
+        let [hello, big, world] = ["Hello", "big", "world"].map(|text| find_text(&document, text));
+        let span = document.nodes[big].parent.unwrap();
+
+        // Then: Each piece follows the last on one line
+        let glyph = glyph_width(16.0);
+        assert_close(relative(&document, hello, p).x, 0.0);
+        assert_close(relative(&document, span, p).x, 6.0 * glyph);
+        assert_close(relative(&document, big, p).x, 6.0 * glyph);
+        assert_close(relative(&document, world, p).x, 9.0 * glyph);
+        assert_close(relative(&document, span, p).width, 3.0 * glyph);
+        assert_eq!(fragment_texts(&document, world), vec![" world"]);
+
+        // And all of it sits on one baseline
+        let baseline = |idx: usize| document.layout(idx).unwrap().rect.y + document.layout(idx).unwrap().baseline;
+        assert_close(baseline(big), baseline(hello));
+        assert_close(baseline(world), baseline(hello));
+    }
+
+    #[test]
+    fn test_lines_wrap_across_nodes_and_trim_spaces() {
+        // Given: Text and a strong element in a block ten glyphs wide
+        let document = laid_out("<div>aaaa bbbb cccc <strong>dddd eeee</strong></div>", "", 100.0);
+        let div = find_element(&document, "div");
+        let [first, strong_text] = ["aaaa", "dddd"].map(|text| find_text(&document, text));
+        let strong = document.nodes[strong_text].parent.unwrap();
+
+        // Then: Words move to the next line when they do not fit, and
+        // spaces where lines break are dropped
+        assert_eq!(fragment_texts(&document, first), vec!["aaaa bbbb", "cccc "]);
+        assert_eq!(fragment_texts(&document, strong_text), vec!["dddd", "eeee"]);
+
+        // And the strong element's box spans its two lines
+        let line_height = document.text_fragments(first).unwrap()[0].rect.height;
+        let strong_box = relative(&document, strong, div);
+        assert_close(strong_box.x, 0.0);
+        assert_close(strong_box.y, line_height);
+        assert_close(strong_box.height, 2.0 * line_height);
+        assert_close(document.text_fragments(strong_text).unwrap()[1].rect.y, line_height);
+    }
+
+    #[test]
+    fn test_inline_boxes_add_their_edges_and_blocks_sit_on_the_baseline() {
+        // Given: A padded span followed by an empty inline block
+        let css = "span { padding: 0 5px; margin: 0 3px; } button { margin: 0; padding: 0; }";
+        let document = laid_out("<p>a<span>b</span>c<button></button></p>", css, 800.0);
+        let p = find_element(&document, "p");
+        let [a, b, c] = ["a", "b", "c"].map(|text| find_text(&document, text));
+        let span = document.nodes[b].parent.unwrap();
+        let button = find_element(&document, "button");
+        let glyph = glyph_width(16.0);
+        let rect = |idx: usize| relative(&document, idx, p);
+
+        // Then: The span's margin and padding push its text and the text
+        // after it along
+        assert_close(rect(span).x, glyph + 3.0);
+        assert_close(rect(span).width, glyph + 10.0);
+        assert_close(rect(b).x, rect(span).x + 5.0);
+        assert_close(rect(c).x, rect(span).right() + 3.0);
+
+        // And the inline block's bottom edge sits on the baseline
+        let baseline = rect(a).y + document.layout(a).unwrap().baseline;
+        assert_close(rect(button).x, rect(c).x + glyph);
+        assert_close(rect(button).bottom(), baseline);
+    }
+
+    #[test]
+    fn test_spacing_and_text_transform_widen_text() {
+        // Given: Spaced-out text, and uppercased text whose ß maps to SS
+        let css = ".spaced { letter-spacing: 2px; word-spacing: 5px; } .upper { text-transform: uppercase; }";
+        let document = laid_out("<p><span class=\"spaced\">ab cd</span> <span class=\"upper\">straße</span></p>", css, 800.0);
+        let p = find_element(&document, "p");
+        let [spaced, upper] = ["ab cd", "straße"].map(|text| find_text(&document, text));
+        let glyph = glyph_width(16.0);
+
+        // Then: Every glyph is 2px wider and the space 5px more, and the
+        // uppercased text is measured as the seven glyphs paint draws
+        assert_close(relative(&document, spaced, p).width, 5.0 * (glyph + 2.0) + 5.0);
+        assert_close(relative(&document, upper, p).width, 7.0 * glyph);
+        assert_eq!(fragment_texts(&document, upper), vec!["STRASSE"]);
+    }
+
+    #[test]
+    fn test_preformatted_and_unbreakable_text() {
+        // Given: Preformatted text, then text too long for a line ten
+        // glyphs wide that may not wrap, or may break anywhere
+        let pre = laid_out("<pre>one\ntwo</pre>", "", 100.0);
+        let nowrap = laid_out("<p>aaa bbb ccc dd</p>", "p { white-space: nowrap; }", 100.0);
+        let anywhere = laid_out("<p>abcdefghijklmn</p>", "p { overflow-wrap: anywhere; }", 100.0);
+        let normal = laid_out("<p>abcdefghijklmn</p>", "", 100.0);
+
+        // Then: Newlines start lines, nowrap keeps one and long words split
+        // only when overflow-wrap allows it
+        assert_eq!(fragment_texts(&pre, find_text(&pre, "one")), vec!["one", "two"]);
+        assert_eq!(fragment_texts(&nowrap, find_text(&nowrap, "aaa")), vec!["aaa bbb ccc dd"]);
+        assert_eq!(fragment_texts(&anywhere, find_text(&anywhere, "abc")), vec!["abcdefghij", "klmn"]);
+        assert_eq!(fragment_texts(&normal, find_text(&normal, "abc")), vec!["abcdefghijklmn"]);
+    }
+}
@@ -0,0 +1,325 @@
+//! Inspector
+//! A dump of the document tree with each element's key attributes,
+//! computed style and layout box, as text or JSON
+//!
+//! Each element shows the attributes that identify it (`KEY_ATTRIBUTES`),
+//! the computed style properties of `STYLE_PROPERTIES` it sets, `display`
+//! always, and its border box from the last layout. Text nodes show their
+//! collapsed text and box; whitespace-only text, comments and elements'
+//! other attributes are left out. Elements without a box, such as those
+//! under `display: none`, say so, which is often the answer to why
+//! something is missing from a screenshot.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::css::{CSSValue, ComputedStyle};
+use crate::dom::{Display, Document, NodeData};
+use crate::geometry::Rect;
+use crate::reporters::json_string;
+
+/// Attributes shown for elements that have them, in this order
+pub const KEY_ATTRIBUTES: &[&str] =
+    &["id", "class", "data-testid", "role", "name", "type", "href", "src", "aria-label", "hidden", "disabled"];
+
+/// Computed style properties shown for elements that set them
+pub const STYLE_PROPERTIES: &[&str] = &[
+    "display",
+    "visibility",
+    "width",
+    "height",
+    "margin-top",
+    "margin-right",
+    "margin-bottom",
+    "margin-left",
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+    "border-width",
+    "overflow",
+    "z-index",
+    "font-size",
+    "font-weight",
+    "color",
+    "background-color",
+];
+
+/// How `--inspect` writes the tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InspectFormat {
+    /// One indented line per node
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for InspectFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "text" => Ok(InspectFormat::Text),
+            "json" => Ok(InspectFormat::Json),
+            other => Err(format!("Unknown inspect format '{}': expected text or json", other)),
+        }
+    }
+}
+
+/// What a node is
+#[derive(Debug, Clone, PartialEq)]
+pub enum InspectedKind {
+    /// An element, with its key attributes and computed style as
+    /// (property, value)
+    Element { tag: String, attributes: Vec<(String, String)>, style: Vec<(&'static str, String)> },
+    /// A text node's text, whitespace collapsed
+    Text(String),
+}
+
+/// A node of the inspector tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectedNode {
+    /// Index in the document
+    pub node: usize,
+    pub kind: InspectedKind,
+    /// Border box from the last layout; `None` for nodes without one
+    pub layout: Option<Rect>,
+    pub children: Vec<InspectedNode>,
+}
+
+impl InspectedNode {
+    /// The tree as JSON: each node an object with `node`, `tag`,
+    /// `attributes` and `style`, or `text`, then `box` and `children`
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(0, &mut out);
+        out.push('\n');
+        out
+    }
+
+    fn write_json(&self, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        out.push_str(&format!("{}{{\"node\":{}", indent, self.node));
+        match &self.kind {
+            InspectedKind::Element { tag, attributes, style } => {
+                let pairs = |pairs: Vec<(&str, &str)>| {
+                    pairs.iter().map(|(key, value)| format!("{}:{}", json_string(key), json_string(value))).collect::<Vec<_>>().join(",")
+                };
+                out.push_str(&format!(
+                    ",\"tag\":{},\"attributes\":{{{}}},\"style\":{{{}}}",
+                    json_string(tag),
+                    pairs(attributes.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()),
+                    pairs(style.iter().map(|(property, value)| (*property, value.as_str())).collect())
+                ));
+            }
+            InspectedKind::Text(text) => out.push_str(&format!(",\"text\":{}", json_string(text))),
+        }
+        match self.layout {
+            Some(rect) => out.push_str(&format!(
+                ",\"box\":{{\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+                rect.x, rect.y, rect.width, rect.height
+            )),
+            None => out.push_str(",\"box\":null"),
+        }
+        if self.children.is_empty() {
+            out.push_str(",\"children\":[]}");
+            return;
+        }
+        out.push_str(",\"children\":[\n");
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                out.push_str(",\n");
+            }
+            child.write_json(depth + 1, out);
+        }
+        out.push_str(&format!("\n{}]}}", indent));
+    }
+
+    /// The tree in `format`
+    pub fn format(&self, format: InspectFormat) -> String {
+        match format {
+            InspectFormat::Text => self.to_string(),
+            InspectFormat::Json => self.to_json(),
+        }
+    }
+
+    fn write_text(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", "  ".repeat(depth))?;
+        match &self.kind {
+            InspectedKind::Element { tag, attributes, .. } => {
+                write!(f, "<{}", tag)?;
+                for (name, value) in attributes {
+                    write!(f, " {}={}", name, json_string(value))?;
+                }
+                write!(f, ">")?;
+            }
+            InspectedKind::Text(text) => write!(f, "{}", json_string(text))?,
+        }
+        match self.layout {
+            Some(rect) => write!(f, " {},{} {}x{}", rect.x, rect.y, rect.width, rect.height)?,
+            None => write!(f, " (no box)")?,
+        }
+        if let InspectedKind::Element { style, .. } = &self.kind {
+            for (property, value) in style {
+                write!(f, " {}={}", property, value)?;
+            }
+        }
+        writeln!(f)?;
+        self.children.iter().try_for_each(|child| child.write_text(depth + 1, f))
+    }
+}
+
+impl fmt::Display for InspectedNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(0, f)
+    }
+}
+
+/// The tree from `root` down, styled with `styles` and laid out by the
+/// document's last layout
+pub fn inspect(document: &Document, styles: &[ComputedStyle], root: usize) -> InspectedNode {
+    let node = &document.nodes[root];
+    let kind = match &node.data {
+        Some(NodeData::Element(element)) => InspectedKind::Element {
+            tag: element.tag_name.to_string(),
+            attributes: KEY_ATTRIBUTES
+                .iter()
+                .filter_map(|&name| Some((name.to_string(), document.get_attribute(root, name)?.clone())))
+                .collect(),
+            style: styles.get(root).map(style_subset).unwrap_or_default(),
+        },
+        Some(NodeData::Text(text)) => InspectedKind::Text(text.split_whitespace().collect::<Vec<_>>().join(" ")),
+        _ => InspectedKind::Text(String::new()),
+    };
+    let layout = document.layout(root).map(|layout| layout.rect);
+    let children = node
+        .children
+        .iter()
+        .filter(|&&child| match &document.nodes[child].data {
+            Some(NodeData::Element(_)) => true,
+            Some(NodeData::Text(text)) => !text.trim().is_empty(),
+            _ => false,
+        })
+        .map(|&child| inspect(document, styles, child))
+        .collect();
+    InspectedNode { node: root, kind, layout, children }
+}
+
+/// The properties of `STYLE_PROPERTIES` that `style` sets, as CSS text
+fn style_subset(style: &ComputedStyle) -> Vec<(&'static str, String)> {
+    STYLE_PROPERTIES.iter().filter_map(|&property| Some((property, style_value(style, property)?))).collect()
+}
+
+fn style_value(style: &ComputedStyle, property: &str) -> Option<String> {
+    let length = |value: &Option<CSSValue>| value.as_ref().map(css_length);
+    match property {
+        "display" => Some(display_keyword(&style.display).to_string()),
+        "visibility" => style.visibility.map(keyword),
+        "width" => length(&style.width),
+        "height" => length(&style.height),
+        "margin-top" => length(&style.margin_top),
+        "margin-right" => length(&style.margin_right),
+        "margin-bottom" => length(&style.margin_bottom),
+        "margin-left" => length(&style.margin_left),
+        "padding-top" => length(&style.padding_top),
+        "padding-right" => length(&style.padding_right),
+        "padding-bottom" => length(&style.padding_bottom),
+        "padding-left" => length(&style.padding_left),
+        "border-width" => length(&style.border_width),
+        "overflow" => style.overflow.map(keyword),
+        "z-index" => style.z_index.map(|z| z.to_string()),
+        "font-size" => length(&style.font_size),
+        "font-weight" => style.font_weight.map(|weight| weight.to_string()),
+        "color" => style.color.clone(),
+        "background-color" => style.background_color.clone(),
+        _ => None,
+    }
+}
+
+fn css_length(value: &CSSValue) -> String {
+    match value {
+        CSSValue::Pixels(px) => format!("{}px", px),
+        CSSValue::Percentage(percent) => format!("{}%", percent),
+        CSSValue::Em(em) => format!("{}em", em),
+        CSSValue::Rem(rem) => format!("{}rem", rem),
+        CSSValue::Auto => "auto".to_string(),
+        CSSValue::Inherit => "inherit".to_string(),
+    }
+}
+
+fn display_keyword(display: &Display) -> &'static str {
+    match display {
+        Display::Block => "block",
+        Display::Inline => "inline",
+        Display::InlineBlock => "inline-block",
+        Display::Flex => "flex",
+        Display::Grid => "grid",
+        Display::None => "none",
+    }
+}
+
+/// A keyword enum's CSS name, e.g. `Hidden` as `hidden`
+fn keyword(value: impl fmt::Debug) -> String {
+    format!("{:?}", value).to_ascii_lowercase()
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::PageBuilder;
+
+    const PAGE: &str = r#"<html><head><style>
+        body { margin: 0; }
+        .card { display: flex; padding: 10px; color: #333; }
+        .gone { display: none; }
+    </style></head><body><div id="app" class="card" data-testid="card" title="ignored"><span>Hi   there</span><p class="gone">Hidden</p></div></body></html>"#;
+
+    #[test]
+    fn test_inspect_tree_as_text() {
+        // Given: A page with a flex card holding text and a hidden paragraph
+        let page = PageBuilder::new().with_seed(1).with_viewport(400, 300).build().unwrap();
+        page.load_html(PAGE);
+
+        // When: The body is inspected
+        let text = page.inspect(None).unwrap().to_string();
+
+        // Then: Each node shows its key attributes, box and set styles
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("<body> 0,0 400x"), "{}", text);
+        assert!(lines[0].ends_with("display=block margin-top=0px margin-right=0px margin-bottom=0px margin-left=0px"), "{}", text);
+        assert!(
+            lines[1].starts_with(r#"  <div id="app" class="card" data-testid="card"> 0,0 "#)
+                && lines[1].contains("display=flex padding-top=10px")
+                && lines[1].ends_with("color=#333"),
+            "{}",
+            text
+        );
+        assert!(lines[3].starts_with(r#"      "Hi there" "#), "{}", text);
+        assert!(lines[4].starts_with(r#"    <p class="gone"> (no box) display=none margin-top=16px"#), "{}", text);
+        assert_eq!(lines[5], r#"      "Hidden" (no box)"#);
+    }
+
+    #[test]
+    fn test_inspect_subtree_as_json() {
+        // Given: The same page
+        let page = PageBuilder::new().with_seed(1).with_viewport(400, 300).build().unwrap();
+        page.load_html(PAGE);
+
+        // When: The hidden paragraph is inspected as JSON
+        let json = page.inspect(Some(".gone")).unwrap().format(InspectFormat::Json);
+
+        // Then: It parses, with attributes, style, a null box and children
+        let parsed = crate::json::Json::parse(&json).unwrap();
+        assert_eq!(parsed.get("tag").and_then(crate::json::Json::as_str), Some("p"));
+        assert_eq!(parsed.get("box"), Some(&crate::json::Json::Null));
+        assert_eq!(parsed.get("style").and_then(|style| style.get("display")).and_then(crate::json::Json::as_str), Some("none"));
+        assert!(json.contains(r#"{"node":"#) && json.contains(r#""text":"Hidden""#), "{}", json);
+        assert!(page.inspect(Some("table")).is_err());
+        assert_eq!("json".parse::<InspectFormat>(), Ok(InspectFormat::Json));
+        assert!("yaml".parse::<InspectFormat>().is_err());
+    }
+}
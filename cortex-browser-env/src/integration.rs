@@ -1,19 +1,17 @@
-/// Phase 7: Integration Testing - Component Testing with Rust Browser
-///
-/// This module implements integration tests for verifying that UI components
-/// render correctly in the Rust headless browser environment. It tests:
-/// - Component rendering and DOM structure
-/// - Property access and manipulation
-/// - Element querying with CSS selectors
-/// - Visual regression testing (screenshots)
-/// - Error handling and edge cases
-
-use crate::parser;
-use crate::layout;
-use crate::render::render_document;
-use crate::query::query_selector;
+//! Phase 7: Integration Testing - Component Testing with Rust Browser
+//!
+//! This module implements integration tests for verifying that UI components
+//! render correctly in the Rust headless browser environment. It tests:
+//! - Component rendering and DOM structure
+//! - Property access and manipulation
+//! - Element querying with CSS selectors
+//! - Visual regression testing (screenshots)
+//! - Error handling and edge cases
+
+use crate::browser::{Page, PageBuilder};
 use crate::element::ElementRef;
-use crate::error::{TestResult, TestSummary};
+use crate::error::TestResult;
+use crate::failure_capture::{capture_on_failure, FailureCaptureConfig};
 
 /// Test configuration for component integration testing
 #[derive(Debug, Clone)]
@@ -24,6 +22,8 @@ pub struct ComponentTestConfig {
     pub expected_classes: Vec<String>,
     pub viewport_width: f64,
     pub viewport_height: f64,
+    /// Artifacts to save when the component test fails
+    pub failure_capture: FailureCaptureConfig,
 }
 
 impl ComponentTestConfig {
@@ -36,6 +36,7 @@ impl ComponentTestConfig {
             expected_classes: Vec::new(),
             viewport_width: 1280.0,
             viewport_height: 720.0,
+            failure_capture: FailureCaptureConfig::disabled(),
         }
     }
 
@@ -51,25 +52,43 @@ impl ComponentTestConfig {
         self.viewport_height = height;
         self
     }
+
+    /// Save a screenshot and DOM snapshot if the test fails
+    pub fn with_failure_capture(mut self, config: FailureCaptureConfig) -> Self {
+        self.failure_capture = config;
+        self
+    }
 }
 
 /// Render and test a component in the browser
+///
+/// Failures carry screenshot and DOM snapshot paths when the config enables
+/// failure capture.
 pub fn test_component(config: ComponentTestConfig) -> TestResult {
-    // Parse the component HTML
-    let mut document = parser::parse_html(&config.html);
-
-    // Calculate layout
-    layout::calculate_layout(&mut document, config.viewport_width as f32, config.viewport_height as f32);
+    let page = match PageBuilder::new()
+        .with_viewport(config.viewport_width as i32, config.viewport_height as i32)
+        .build()
+    {
+        Ok(page) => page,
+        Err(e) => return TestResult::failure(&config.name, "Could not open page", e),
+    };
+    page.load_html(&config.html);
+    let result = check_component(&config, &page);
+    let capture = config
+        .failure_capture
+        .clone()
+        .with_viewport(config.viewport_width as i32, config.viewport_height as i32);
+    let document = page.document();
+    capture_on_failure(result, &document, &[], &capture)
+}
 
-    // Render the component to a DrawTarget
-    let _draw_target = render_document(
-        &document,
-        config.viewport_width as i32,
-        config.viewport_height as i32,
-    );
+fn check_component(config: &ComponentTestConfig, page: &Page) -> TestResult {
+    // Lay out and render the component
+    let _draw_target = page.render();
+    let document = page.document();
 
     // Query for the expected element
-    match query_selector(&document, &config.expected_element) {
+    match page.query(&config.expected_element) {
         Ok(Some(element_idx)) => {
             // Verify element exists
             let element_ref = ElementRef {
@@ -108,10 +127,7 @@ pub fn test_component(config: ComponentTestConfig) -> TestResult {
             &config.name,
             &format!("Expected element '{}' not found", config.expected_element),
         ),
-        Err(e) => TestResult::failure_string(
-            &config.name,
-            &format!("Query failed: {}", e),
-        ),
+        Err(e) => TestResult::failure_string(&config.name, &e.to_string()),
     }
 }
 
@@ -122,6 +138,7 @@ pub fn test_component(config: ComponentTestConfig) -> TestResult {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::TestSummary;
 
     // ========================================================================
     // TEXT INPUT COMPONENT TESTS
@@ -401,6 +418,25 @@ mod tests {
         assert!(result.passed);
     }
 
+    #[test]
+    fn test_failed_component_captures_artifacts() {
+        // Given: A component test expecting an element that is not there
+        let dir = tempfile::tempdir().unwrap();
+        let html = r#"<html><body><div id="present"></div></body></html>"#;
+        let config = ComponentTestConfig::new("missing element", html, "#absent")
+            .with_viewport(320.0, 200.0)
+            .with_failure_capture(FailureCaptureConfig::new().with_output_dir(dir.path()));
+
+        // When: The test fails
+        let result = test_component(config);
+
+        // Then: The screenshot and DOM snapshot paths are attached
+        assert!(!result.passed);
+        assert_eq!(result.screenshot_path, Some(dir.path().join("missing-element.png")));
+        assert!(result.screenshot_path.unwrap().exists());
+        assert!(result.dom_snapshot_path.unwrap().exists());
+    }
+
     #[test]
     fn test_nested_components() {
         let html = r#"
@@ -1,12 +1,83 @@
+pub mod a11y;
+pub mod animation;
+pub mod atom;
+pub mod bench;
+pub mod browser;
+pub mod cli;
+pub mod clipboard;
+pub mod compat;
+pub mod console;
 pub mod css;
+pub mod custom_elements;
+pub mod debug_overlay;
+pub mod device;
+pub mod display_list;
 pub mod dom;
+pub mod editing;
 pub mod element;
+pub mod emoji;
+pub mod encoding;
+pub mod entities;
 pub mod error;
+pub mod event_loop;
+pub mod event_source;
+pub mod events;
+pub mod failure_capture;
+pub mod files;
 pub mod fonts;
+pub mod forms;
+pub mod frames;
+pub mod geometry;
+pub mod golden;
+pub mod handles;
+pub mod head;
+pub mod hit_test;
+pub mod host;
+pub mod image_diff;
+pub mod images;
+pub mod inline;
+pub mod inspect;
 pub mod integration;
+pub mod json;
 pub mod layout;
+pub mod limits;
+pub mod lists;
+pub mod logging;
+pub mod media;
+pub mod messaging;
+pub mod modules;
+pub mod navigation;
+pub mod network;
+pub mod parallel;
 pub mod parser;
+pub mod pdf;
+pub mod queries;
 pub mod query;
 pub mod render;
+pub mod repl;
+pub mod rule_map;
+pub mod reporters;
 pub mod screenshot;
+pub mod scripts;
+pub mod seed;
+pub mod selection;
+pub mod selector_conformance;
+pub mod serialize;
+pub mod session;
+pub mod snapshot;
+pub mod stack_trace;
 pub mod style;
+pub mod svg;
+pub mod test_runner;
+pub mod text;
+pub mod timers;
+pub mod trace;
+pub mod transform;
+pub mod transpile;
+pub mod user_agent;
+pub mod user_events;
+pub mod validation;
+pub mod watch;
+pub mod webdriver;
+pub mod websocket;
+pub mod workers;
@@ -0,0 +1,214 @@
+//! Resource Limits
+//! Bounds on what untrusted pages and test scripts may use
+//!
+//! A page's `Limits` cap how long a script may run, how much memory its
+//! JavaScript runtime may allocate, how many nodes its document may hold
+//! and how large a screenshot of it may be. None are set by default.
+//! Exceeding one fails the operation with `BrowserError::LimitExceeded`,
+//! naming the limit, and the CLI exits with `LIMIT_EXIT_CODE`.
+//!
+//! The script timeout covers one script or module with the jobs and tasks
+//! it queued, or one run of the event loop; a loop that never returns is
+//! interrupted once it runs out. Nodes are counted once a document has
+//! been parsed and after each script, so a script may briefly go over.
+
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::error::BrowserError;
+use crate::geometry::Rect;
+
+/// Exit code of a CLI run stopped by a limit
+pub const LIMIT_EXIT_CODE: i32 = 3;
+
+/// The limit an operation ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    ScriptTimeout,
+    Memory,
+    DomNodes,
+    ScreenshotSize,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LimitKind::ScriptTimeout => "script timeout",
+            LimitKind::Memory => "memory limit",
+            LimitKind::DomNodes => "DOM node limit",
+            LimitKind::ScreenshotSize => "screenshot size limit",
+        })
+    }
+}
+
+/// What a page may use; unlimited when unset
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Wall-clock time one script and the work it queued may take
+    pub script_timeout: Option<Duration>,
+    /// Bytes the JavaScript runtime may allocate
+    pub memory: Option<usize>,
+    /// Nodes the document may hold, including detached ones
+    pub max_nodes: Option<usize>,
+    /// Largest screenshot, as (width, height) in device pixels
+    pub max_screenshot: Option<(u32, u32)>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_script_timeout(mut self, timeout: Duration) -> Self {
+        self.script_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_memory(mut self, bytes: usize) -> Self {
+        self.memory = Some(bytes);
+        self
+    }
+
+    pub fn with_max_nodes(mut self, nodes: usize) -> Self {
+        self.max_nodes = Some(nodes);
+        self
+    }
+
+    pub fn with_max_screenshot(mut self, width: u32, height: u32) -> Self {
+        self.max_screenshot = Some((width, height));
+        self
+    }
+
+    /// Fail when a document holds more than `max_nodes` nodes
+    pub fn check_nodes(&self, nodes: usize) -> Result<(), BrowserError> {
+        match self.max_nodes {
+            Some(max) if nodes > max => {
+                Err(BrowserError::LimitExceeded(LimitKind::DomNodes, format!("Document has {} nodes, more than the limit of {}", nodes, max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail when `region`, in CSS pixels, would be wider or taller than
+    /// `max_screenshot` once scaled by `device_pixel_ratio`
+    pub fn check_screenshot(&self, region: Rect, device_pixel_ratio: f32) -> Result<(), BrowserError> {
+        let Some((max_width, max_height)) = self.max_screenshot else {
+            return Ok(());
+        };
+        let width = (region.width * device_pixel_ratio).ceil() as u64;
+        let height = (region.height * device_pixel_ratio).ceil() as u64;
+        if width > max_width as u64 || height > max_height as u64 {
+            return Err(BrowserError::LimitExceeded(
+                LimitKind::ScreenshotSize,
+                format!("Screenshot would be {}x{} pixels, larger than the limit of {}x{}", width, height, max_width, max_height),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The error for a script interrupted by `script_timeout`
+    pub fn timeout_error(&self) -> BrowserError {
+        let millis = self.script_timeout.unwrap_or_default().as_millis();
+        BrowserError::LimitExceeded(LimitKind::ScriptTimeout, format!("Script ran for more than {}ms", millis))
+    }
+
+    /// `error`, or the memory limit's error when it is the runtime running
+    /// out of memory under one
+    pub fn classify_js_error(&self, error: BrowserError) -> BrowserError {
+        match (&error, self.memory) {
+            (BrowserError::JavaScriptError(message, _), Some(bytes)) if message.contains("out of memory") => {
+                BrowserError::LimitExceeded(LimitKind::Memory, format!("Scripts allocated more than the limit of {} bytes", bytes))
+            }
+            _ => error,
+        }
+    }
+}
+
+/// The deadline of the script running, shared with the runtime's interrupt
+/// handler
+#[derive(Debug, Default)]
+pub struct Watchdog {
+    deadline: Cell<Option<Instant>>,
+    fired: Cell<bool>,
+}
+
+impl Watchdog {
+    pub fn new() -> Rc<Self> {
+        Rc::default()
+    }
+
+    /// Start timing a script unless one is already timed, returning whether
+    /// this call did
+    pub fn arm(&self, timeout: Duration) -> bool {
+        if self.deadline.get().is_some() {
+            return false;
+        }
+        self.deadline.set(Some(Instant::now() + timeout));
+        self.fired.set(false);
+        true
+    }
+
+    /// Stop timing, returning whether the script was interrupted
+    pub fn disarm(&self) -> bool {
+        self.deadline.set(None);
+        self.fired.replace(false)
+    }
+
+    /// Whether the runtime should interrupt the script, as its interrupt
+    /// handler asks
+    pub fn expired(&self) -> bool {
+        let expired = self.deadline.get().is_some_and(|deadline| Instant::now() > deadline);
+        if expired {
+            self.fired.set(true);
+        }
+        expired
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_check_nodes_and_screenshot_sizes() {
+        // Given: Limits of 100 nodes and 800x600 screenshots
+        let limits = Limits::new().with_max_nodes(100).with_max_screenshot(800, 600);
+
+        // Then: Sizes up to the limits pass and larger ones name the limit
+        assert_eq!(limits.check_nodes(100), Ok(()));
+        assert!(matches!(limits.check_nodes(101), Err(BrowserError::LimitExceeded(LimitKind::DomNodes, _))));
+        assert_eq!(limits.check_screenshot(Rect::new(0.0, 0.0, 400.0, 300.0), 2.0), Ok(()));
+        assert_eq!(
+            limits.check_screenshot(Rect::new(0.0, 0.0, 400.0, 300.5), 2.0),
+            Err(BrowserError::LimitExceeded(
+                LimitKind::ScreenshotSize,
+                "Screenshot would be 800x601 pixels, larger than the limit of 800x600".to_string()
+            ))
+        );
+        assert_eq!(Limits::new().check_nodes(usize::MAX), Ok(()));
+    }
+
+    #[test]
+    fn test_watchdog_times_the_outermost_script() {
+        // Given: A watchdog timing a script with no time to run
+        let watchdog = Watchdog::new();
+        assert!(watchdog.arm(Duration::ZERO));
+
+        // When: A nested script starts and the handler is polled
+        let nested = watchdog.arm(Duration::from_secs(60));
+        std::thread::sleep(Duration::from_millis(1));
+
+        // Then: The outer deadline holds, and disarming reports it fired once
+        assert!(!nested);
+        assert!(watchdog.expired());
+        assert!(watchdog.disarm());
+        assert!(!watchdog.expired());
+        assert!(!watchdog.disarm());
+    }
+}
@@ -0,0 +1,195 @@
+//! List Markers
+//! The bullets and numbers layout puts in front of `<li>` items
+//!
+//! Lists are indented by the user agent stylesheet. A marker hangs to the
+//! left of its item by default, or starts the item's content with
+//! `list-style-position: inside`, moving the content over to make room.
+//! `<ol>` items count up from the list's `start`, or down with
+//! `reversed`, and `<li value>` renumbers an item and those after it.
+//! Until a stylesheet says otherwise, `<ol>` items are numbered and `<ul>`
+//! items get a disc, or a circle and then a square inside other lists.
+
+use crate::css::{ComputedStyle, ListStylePosition, ListStyleType};
+use crate::dom::{Document, Layout, NodeData};
+use crate::fonts::default_line_metrics;
+use crate::geometry::Rect;
+use crate::inline::glyph_width;
+
+/// What a marker shows
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkerKind {
+    Disc,
+    Circle,
+    Square,
+    /// A counter like `3.` or `iv.`
+    Text(String),
+}
+
+/// A list item's marker box, from the last layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListMarker {
+    pub kind: MarkerKind,
+    pub position: ListStylePosition,
+    /// The bullet, or the glyph boxes of the counter
+    pub rect: Rect,
+}
+
+impl ListMarker {
+    /// The marker for list item `idx`, sized for `font_size` but not yet
+    /// placed; `None` if it is not a list item or has `list-style-type: none`
+    pub fn for_item(document: &Document, idx: usize, styles: &[ComputedStyle], font_size: f32) -> Option<ListMarker> {
+        if tag_name(document, idx) != Some("li") {
+            return None;
+        }
+        let (style_type, position) = list_style(document, idx, styles);
+        let kind = match style_type {
+            ListStyleType::None => return None,
+            ListStyleType::Disc => MarkerKind::Disc,
+            ListStyleType::Circle => MarkerKind::Circle,
+            ListStyleType::Square => MarkerKind::Square,
+            counter => MarkerKind::Text(counter.marker_text(ordinal(document, idx))?),
+        };
+        let (width, height) = match &kind {
+            MarkerKind::Text(text) => (text.chars().count() as f32 * glyph_width(font_size), font_size),
+            _ => (bullet_size(font_size), bullet_size(font_size)),
+        };
+        Some(ListMarker { kind, position, rect: Rect::new(0.0, 0.0, width, height) })
+    }
+
+    /// Room an inside marker takes at the start of the content
+    pub fn inline_size(&self, font_size: f32) -> f32 {
+        self.rect.width + gap(font_size)
+    }
+
+    /// Move the marker next to the first line of the item laid out as
+    /// `layout`: left of its border box, or at the start of its content
+    pub fn place(&mut self, layout: &Layout) {
+        let font_size = layout.font_size;
+        let content_left = layout.content_box().x;
+        self.rect.x = match self.position {
+            ListStylePosition::Outside => layout.rect.x - gap(font_size) - self.rect.width,
+            ListStylePosition::Inside => content_left - self.inline_size(font_size),
+        };
+        let metrics = default_line_metrics(font_size);
+        let baseline = layout.content_box().y + metrics.baseline_in(metrics.line_height());
+        self.rect.y = match self.kind {
+            // Glyph boxes sit on the baseline
+            MarkerKind::Text(_) => baseline - self.rect.height,
+            // Bullets are centered on the middle of the lowercase letters
+            _ => baseline - 0.3 * font_size - self.rect.height / 2.0,
+        };
+    }
+}
+
+/// The number of list item `idx` in its list
+pub fn ordinal(document: &Document, idx: usize) -> i64 {
+    let Some(list_idx) = document.nodes[idx].parent else { return 1 };
+    let items: Vec<usize> = document.nodes[list_idx].children.iter().copied().filter(|&child| tag_name(document, child) == Some("li")).collect();
+    let attribute = |idx: usize, name: &str| document.get_attribute(idx, name).and_then(|value| value.trim().parse::<i64>().ok());
+    let reversed = tag_name(document, list_idx) == Some("ol") && document.get_attribute(list_idx, "reversed").is_some();
+    let step = if reversed { -1 } else { 1 };
+    let mut next = attribute(list_idx, "start").unwrap_or(if reversed { items.len() as i64 } else { 1 });
+    for item in items {
+        let number = attribute(item, "value").unwrap_or(next);
+        if item == idx {
+            return number;
+        }
+        next = number + step;
+    }
+    next
+}
+
+/// `list-style-type` and `list-style-position` for list item `idx`
+///
+/// Both are inherited, so the nearest node (self first) that sets each
+/// wins; a list that sets neither supplies the default for its kind.
+fn list_style(document: &Document, idx: usize, styles: &[ComputedStyle]) -> (ListStyleType, ListStylePosition) {
+    let mut style_type = None;
+    let mut position = None;
+    let mut current = Some(idx);
+    while let Some(node_idx) = current {
+        if let Some(style) = styles.get(node_idx) {
+            style_type = style_type.or(style.list_style_type);
+            position = position.or(style.list_style_position);
+        }
+        style_type = style_type.or_else(|| default_type(document, node_idx));
+        current = document.nodes[node_idx].parent;
+    }
+    (style_type.unwrap_or(ListStyleType::Disc), position.unwrap_or_default())
+}
+
+/// The marker a list's items get by default: numbers for `<ol>`, and
+/// bullets for `<ul>` that change with how deeply it is nested
+fn default_type(document: &Document, idx: usize) -> Option<ListStyleType> {
+    match tag_name(document, idx)? {
+        "ol" => Some(ListStyleType::Decimal),
+        "ul" | "menu" => {
+            let depth = std::iter::successors(document.nodes[idx].parent, |&ancestor| document.nodes[ancestor].parent)
+                .filter(|&ancestor| matches!(tag_name(document, ancestor), Some("ul" | "ol" | "menu")))
+                .count();
+            Some(match depth {
+                0 => ListStyleType::Disc,
+                1 => ListStyleType::Circle,
+                _ => ListStyleType::Square,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn tag_name(document: &Document, idx: usize) -> Option<&str> {
+    match &document.nodes[idx].data {
+        Some(NodeData::Element(elem)) => Some(elem.tag_name.as_str()),
+        _ => None,
+    }
+}
+
+fn bullet_size(font_size: f32) -> f32 {
+    (font_size * 0.35).round().max(3.0)
+}
+
+/// Space between a marker and the item's content
+fn gap(font_size: f32) -> f32 {
+    font_size * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    fn items(document: &Document) -> Vec<usize> {
+        (0..document.nodes.len()).filter(|&idx| tag_name(document, idx) == Some("li")).collect()
+    }
+
+    #[test]
+    fn test_ordinals_follow_start_reversed_and_value() {
+        // Given: A list starting at 5, one renumbered item, and a reversed list
+        let document = parse_html(r#"<ol start="5"><li>a</li><li value="10">b</li><li>c</li></ol><ol reversed><li>x</li><li>y</li><li>z</li></ol>"#);
+
+        // Then: Items count on from the last number given
+        let ordinals: Vec<i64> = items(&document).into_iter().map(|idx| ordinal(&document, idx)).collect();
+        assert_eq!(ordinals, vec![5, 10, 11, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_marker_types_default_by_list_and_nesting() {
+        // Given: Nested unordered lists inside an ordered one
+        let document = parse_html("<ol><li>1<ul><li>a<ul><li>b<ul><li>c</li></ul></li></ul></li></ul></li></ol>");
+        let mut styles = vec![ComputedStyle::default(); document.nodes.len()];
+
+        // When: We make each item's marker
+        let kinds = |styles: &[ComputedStyle]| -> Vec<MarkerKind> {
+            items(&document).into_iter().map(|idx| ListMarker::for_item(&document, idx, styles, 16.0).unwrap().kind).collect()
+        };
+
+        // Then: Numbers, then circles and squares as the bullets nest
+        assert_eq!(kinds(&styles), vec![MarkerKind::Text("1.".into()), MarkerKind::Circle, MarkerKind::Square, MarkerKind::Square]);
+
+        // And a list-style-type set on an item wins over its list's, while
+        // the lists nested in it keep their own defaults
+        styles[items(&document)[0]].list_style_type = Some(ListStyleType::UpperRoman);
+        assert_eq!(kinds(&styles)[0], MarkerKind::Text("I.".into()));
+        assert_eq!(kinds(&styles)[1], MarkerKind::Circle);
+    }
+}
@@ -0,0 +1,216 @@
+//! Logging
+//! Engine diagnostics through `tracing`, with levels, per-module filters
+//! and JSON output for CI
+//!
+//! The engine reports what it does as `tracing` events targeted at the
+//! module emitting them: `console` for what scripts log, `dom` for event
+//! dispatch, `layout`, `style`, `network` and so on. The CLI installs a
+//! subscriber writing them to stderr, so stdout only carries results and
+//! reports, filtered by `--log` or `CORTEX_LOG`: a default level and
+//! `module=level` overrides, e.g. `warn,layout=debug`. Embedders install
+//! their own subscriber, or none to keep the engine silent.
+
+use std::str::FromStr;
+
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable consulted when `--log` is not given
+pub const LOG_ENV_VAR: &str = "CORTEX_LOG";
+
+/// Filter used when neither `--log` nor `CORTEX_LOG` is given: warnings and
+/// errors, and everything scripts log to the console
+pub const DEFAULT_LOG_FILTER: &str = "warn,console=info";
+
+/// Prefix of the targets of this crate's events
+const CRATE_TARGET: &str = "cortex_browser_env";
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `LEVEL module: message key=value`
+    #[default]
+    Text,
+    /// One JSON object per line, for CI log collectors
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("Unknown log format '{}': expected text or json", other)),
+        }
+    }
+}
+
+/// Which events to write, and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    /// Directives as `--log` takes them, e.g. `warn,layout=debug`
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig { filter: DEFAULT_LOG_FILTER.to_string(), format: LogFormat::Text }
+    }
+}
+
+impl LogConfig {
+    /// Use `filter` if given, else `CORTEX_LOG`, else `DEFAULT_LOG_FILTER`
+    pub fn resolve(filter: Option<&str>, format: LogFormat) -> Self {
+        let filter = match filter {
+            Some(filter) => filter.to_string(),
+            None => std::env::var(LOG_ENV_VAR).unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string()),
+        };
+        LogConfig { filter, format }
+    }
+
+    /// The filter, with module names qualified by the crate's; fails for
+    /// directives `tracing` cannot parse
+    pub fn env_filter(&self) -> Result<EnvFilter, String> {
+        EnvFilter::try_new(qualify_directives(&self.filter))
+            .map_err(|e| format!("Invalid log filter '{}': {}", self.filter, e))
+    }
+}
+
+/// Qualify each `module=level` directive's module with the crate name, so
+/// `layout=debug` applies to `cortex_browser_env::layout`; bare levels and
+/// paths naming another crate are kept
+pub fn qualify_directives(filter: &str) -> String {
+    filter
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((module, level)) if !module.contains("::") && module != CRATE_TARGET => {
+                format!("{}::{}={}", CRATE_TARGET, module, level)
+            }
+            _ if !directive.contains('=') && !is_level(directive) && !directive.contains("::") => {
+                format!("{}::{}", CRATE_TARGET, directive)
+            }
+            _ => directive.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn is_level(name: &str) -> bool {
+    matches!(name.to_ascii_lowercase().as_str(), "trace" | "debug" | "info" | "warn" | "error" | "off")
+}
+
+/// A subscriber writing the events `config` lets through to `writer`
+pub fn subscriber<W>(config: &LogConfig, writer: W) -> Result<Box<dyn Subscriber + Send + Sync>, String>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt().with_env_filter(config.env_filter()?).with_writer(writer).without_time();
+    Ok(match config.format {
+        LogFormat::Text => Box::new(builder.with_ansi(false).finish()),
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+    })
+}
+
+/// Write the events `config` lets through to stderr for the rest of the
+/// process
+pub fn init(config: &LogConfig) -> Result<(), String> {
+    tracing::subscriber::set_global_default(subscriber(config, std::io::stderr)?)
+        .map_err(|e| format!("Cannot install logger: {}", e))
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what a subscriber writes
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for Captured {
+        type Writer = Captured;
+
+        fn make_writer(&'w self) -> Captured {
+            self.clone()
+        }
+    }
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_directives_name_modules_of_the_crate() {
+        assert_eq!(qualify_directives("warn, layout=debug"), "warn,cortex_browser_env::layout=debug");
+        assert_eq!(qualify_directives("style"), "cortex_browser_env::style");
+        assert_eq!(qualify_directives("info,html5ever::tree_builder=trace,cortex_browser_env::dom=debug"), "info,html5ever::tree_builder=trace,cortex_browser_env::dom=debug");
+        assert!(LogConfig::resolve(Some("layout=loud"), LogFormat::Text).env_filter().is_err());
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_module_filters_pick_events() {
+        // Given: A text subscriber showing warnings, and layout debugging
+        let captured = Captured::default();
+        let config = LogConfig::resolve(Some("warn,layout=debug"), LogFormat::Text);
+        let subscriber = subscriber(&config, captured.clone()).unwrap();
+
+        // When: Modules log at several levels
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "cortex_browser_env::layout", nodes = 3, "Laid out");
+            tracing::debug!(target: "cortex_browser_env::style", "Styled");
+            tracing::warn!(target: "cortex_browser_env::style", "Unknown property");
+        });
+
+        // Then: Only the layout debugging and the warning are written
+        let text = captured.text();
+        assert!(text.contains("DEBUG cortex_browser_env::layout: Laid out nodes=3"), "{}", text);
+        assert!(text.contains("WARN cortex_browser_env::style: Unknown property"), "{}", text);
+        assert!(!text.contains("Styled"), "{}", text);
+    }
+
+    #[test]
+    fn test_json_format_writes_one_object_per_event() {
+        // Given: A JSON subscriber
+        let captured = Captured::default();
+        let subscriber = subscriber(&LogConfig { format: LogFormat::Json, ..LogConfig::default() }, captured.clone()).unwrap();
+
+        // When: A script logs to the console
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "cortex_browser_env::console", "hello");
+        });
+
+        // Then: The line is a JSON object with the level, target and message
+        let text = captured.text();
+        let line = text.lines().next().unwrap();
+        assert!(line.starts_with('{') && line.ends_with('}'), "{}", line);
+        assert!(line.contains(r#""level":"INFO""#), "{}", line);
+        assert!(line.contains(r#""message":"hello""#), "{}", line);
+        assert!(line.contains(r#""target":"cortex_browser_env::console""#), "{}", line);
+    }
+}
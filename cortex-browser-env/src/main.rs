@@ -1,204 +1,374 @@
-mod dom;
-mod parser;
-mod custom_elements;
-mod css;
-mod layout;
-mod query;
-mod element;
-mod error;
-
-use std::sync::{Arc, Mutex};
-use raqote::{DrawTarget, SolidSource, Source, StrokeStyle, LineCap, LineJoin, PathBuilder};
-use rquickjs::{Runtime, Context, Function, Value, Object};
-
-// Struct to hold test results
-#[derive(Debug, Clone)]
-pub struct TestResult {
-    pub name: String,
-    pub passed: bool,
-    pub message: String,
-}
+use cortex_browser_env::bench::{self, BenchReport};
+use cortex_browser_env::browser::{Browser, NetworkMode, Page, PageBuilder};
+use cortex_browser_env::cli::{self, Cli, CliAction, InputSource, Subcommand};
+use cortex_browser_env::compat::{self, BrowserCapture, CompatConfig};
+use cortex_browser_env::error::BrowserError;
+use cortex_browser_env::failure_capture::FailureCaptureConfig;
+use cortex_browser_env::logging;
+use cortex_browser_env::pdf::PdfOptions;
+use cortex_browser_env::repl;
+use cortex_browser_env::reporters;
+use cortex_browser_env::screenshot::{self, ImageFormat};
+use cortex_browser_env::seed::RunSeed;
+use cortex_browser_env::session::Session;
+use cortex_browser_env::trace::{TraceStage, Tracer};
+use cortex_browser_env::watch::{FileWatcher, WatchSession, WatchSet};
+use cortex_browser_env::webdriver::WebDriver;
+
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    let js_code_arg = if args.len() > 1 {
-        &args[1]
-    } else {
-        eprintln!("Usage: cortex-browser-env <javascript_code>");
-        std::process::exit(1);
+    let cli = match cli::parse_args(&args) {
+        Ok(CliAction::Execute(cli)) => cli,
+        Ok(CliAction::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
     };
 
-    println!("Hello, cortex-browser-env!");
-    let html_content = "<html><body><h1>Hello, World!</h1></body></html>";
-    let document = parser::parse_html(html_content);
-    println!("Parsed document: {:#?}", document);
+    let exit_code = match execute(&cli) {
+        Ok(code) => code,
+        Err(failure) => {
+            eprintln!("{}", failure.message);
+            failure.exit_code
+        }
+    };
+    std::process::exit(exit_code);
+}
 
-    let custom_elements_registry = Arc::new(Mutex::new(custom_elements::CustomElementRegistry::new()));
-    println!("Custom Element Registry created.");
+/// Why a command stopped, and the exit code reporting it: 1, or
+/// `LIMIT_EXIT_CODE` when a page ran into one of its limits
+struct Failure {
+    message: String,
+    exit_code: i32,
+}
 
-    // Store test results
-    let test_results = Arc::new(Mutex::new(Vec::<TestResult>::new()));
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure { message, exit_code: 1 }
+    }
+}
+
+impl From<BrowserError> for Failure {
+    fn from(error: BrowserError) -> Self {
+        Failure { message: error.to_string(), exit_code: error.exit_code() }
+    }
+}
+
+fn execute(cli: &Cli) -> Result<i32, Failure> {
+    logging::init(&cli.log_config())?;
+    if cli.command == Subcommand::Watch {
+        return Ok(watch(cli)?);
+    }
+    if cli.command == Subcommand::Bench {
+        return Ok(run_bench(cli)?);
+    }
+
+    // Every input is read before anything runs, so all missing files are
+    // reported together
+    let inputs: Vec<_> = cli.html.iter().chain(&cli.css).chain(&cli.scripts).cloned().collect();
+    let mut contents = cli::read_all(&inputs, &mut std::io::stdin())?.into_iter();
+    if cli.command == Subcommand::Compat {
+        return run_compat(cli, &contents.next().unwrap_or_default());
+    }
 
-    // Parse some CSS
-    let css_content = r#"
-        body {
-            background-color: #f0f0f0;
-            margin: 0;
+    let tracer = if cli.trace.is_some() { Tracer::new() } else { Tracer::disabled() };
+    let mut browser = Browser::new()
+        .with_device(cli.device)
+        .with_color_scheme(cli.color_schemes[0])
+        .with_reduced_motion(cli.reduced_motion)
+        .with_root_font_size(cli.root_font_size)
+        .with_fallback_fonts(cli.read_fallback_fonts()?)
+        .with_animations(cli.animations)
+        .with_viewport(cli.viewport.width, cli.viewport.height)
+        .with_device_pixel_ratio(cli.device_pixel_ratio.unwrap_or(1.0))
+        .with_failure_capture(FailureCaptureConfig::from_env())
+        .with_leak_detection(cli.detect_leaks)
+        .with_debug_boxes(cli.debug_boxes)
+        .with_threads(cli.threads.unwrap_or(0))
+        .with_snapshots(cli.snapshots())
+        .with_modules(cli.module_config()?)
+        .with_tracer(tracer.clone())
+        .with_limits(cli.limits);
+    if let Some(seed) = cli.seed {
+        browser = browser.with_seed(seed);
+    }
+    if let Some(time) = cli.frozen_time {
+        browser = browser.with_frozen_time(time);
+    }
+    let mut builder = browser.page_builder();
+    if !cli.navigations.is_empty() || cli.command == Subcommand::WebDriver {
+        builder = builder.with_network(NetworkMode::FileSystem(PathBuf::from(".")));
+    }
+    if cli.command == Subcommand::WebDriver {
+        return Ok(serve_webdriver(cli, builder)?);
+    }
+    let mut session = Session::new(builder)?;
+    if cli.html.is_some() {
+        let load = session.load_html(&contents.next().unwrap_or_default())?;
+        if let Some(e) = load.limit_exceeded {
+            return Err(e.into());
         }
-        h1 {
-            color: blue;
-            font-size: 24px;
+    }
+    if cli.css.is_some() {
+        session.page().add_style(&contents.next().unwrap_or_default());
+    }
+    let scripts: Vec<String> = contents.collect();
+
+    let exit_code = match cli.command {
+        Subcommand::Run | Subcommand::Test => run_scripts(cli, &mut session, &scripts),
+        _ => run_page_command(cli, session.page()),
+    }?;
+    let page = session.page();
+    if let Some(format) = cli.inspect {
+        print!("{}", page.inspect(None)?.format(format));
+    }
+    if cli.stats {
+        eprint!("\nDocument stats:\n{}", page.stats());
+    }
+    if let Some(path) = &cli.trace {
+        tracer.save(path).map_err(|e| format!("Cannot write trace '{}': {}", path.display(), e))?;
+        eprint!("Saved trace to {}\n{}", path.display(), tracer.format_breakdown());
+    }
+    Ok(exit_code)
+}
+
+/// `render`, `render pdf`, `screenshot` and `repl` on the loaded page
+fn run_page_command(cli: &Cli, page: &Page) -> Result<i32, Failure> {
+    match cli.command {
+        Subcommand::Render => {
+            print!("{}", page.layout_tree());
+            Ok(0)
         }
-    "#;
-    let stylesheet = css::parse_css(css_content);
-    println!("Parsed stylesheet: {:#?}", stylesheet);
-
-    // Initialize rquickjs runtime and context
-    let runtime = Runtime::new().unwrap();
-    let context = Context::full(&runtime).unwrap();
-    let document_arc = Arc::new(Mutex::new(document)); // Wrap document in Arc<Mutex> here
-
-    context.with(|ctx| {
-        let globals = ctx.globals();
-
-        // Expose a simple 'console.log' to the JavaScript environment
-        let console_obj = Object::new(ctx.clone()).unwrap();
-        let log_fn = Function::new(ctx.clone(), |msg: String| {
-            println!("JS Console: {}", msg);
-        }).unwrap();
-        console_obj.set("log", log_fn).unwrap();
-        globals.set("console", console_obj).unwrap();
-
-        // Expose customElements registry to JavaScript
-        let custom_elements_registry_clone = custom_elements_registry.clone();
-        let custom_elements_obj = Object::new(ctx.clone()).unwrap();
-
-        let define_fn = Function::new(ctx.clone(), move |tag_name: String, _constructor_fn: Function| {
-            let mut registry = custom_elements_registry_clone.lock().unwrap();
-            // For now, we just store a dummy index. Later, this will store a reference to the constructor function.
-            registry.define(&tag_name, 0);
-            println!("JS: customElements.define('{}', constructor_name)", tag_name);
-        }).unwrap();
-        custom_elements_obj.set("define", define_fn).unwrap();
-
-        let custom_elements_registry_clone = custom_elements_registry.clone();
-        let get_fn = Function::new(ctx.clone(), move |tag_name: String| -> Option<u32> {
-            let registry = custom_elements_registry_clone.lock().unwrap();
-            if let Some(idx) = registry.get(&tag_name) {
-                println!("JS: customElements.get('{}') -> Found index {}", tag_name, idx);
-                Some(*idx as u32)
-            } else {
-                println!("JS: customElements.get('{}') -> Not found", tag_name);
-                None
-            }
-        }).unwrap();
-        custom_elements_obj.set("get", get_fn).unwrap();
-
-        globals.set("customElements", custom_elements_obj).unwrap();
-
-        // Expose attachShadow functionality
-        let document_arc_clone = document_arc.clone();
-        let attach_shadow_fn = Function::new(ctx.clone(), move |host_idx: u32, mode: String| -> rquickjs::Result<u32> {
-            let mut doc = document_arc_clone.lock().unwrap();
-            let shadow_mode = match mode.as_str() {
-                "open" => dom::ShadowRootMode::Open,
-                "closed" => dom::ShadowRootMode::Closed,
-                _ => return Err(rquickjs::Error::Exception),
+        Subcommand::RenderPdf => {
+            let output = cli.screenshot.as_deref().unwrap_or(Path::new("page.pdf"));
+            let options = PdfOptions { page_height: cli.page_height, ..PdfOptions::default() };
+            let options = match cli.device_pixel_ratio {
+                Some(ratio) => options.with_device_pixel_ratio(ratio),
+                None => options,
             };
-            match doc.attach_shadow(host_idx as usize, shadow_mode) {
-                Ok(idx) => Ok(idx as u32),
-                Err(_) => Err(rquickjs::Error::Exception),
+            page.save_pdf(output, &options)?;
+            println!("Saved PDF to {}", output.display());
+            Ok(0)
+        }
+        Subcommand::Screenshot => {
+            let output = cli.screenshot.as_deref().unwrap_or(Path::new("screenshot.png"));
+            save_screenshot(cli, page, output)?;
+            Ok(0)
+        }
+        Subcommand::Repl => {
+            repl::run(page).map_err(|e| format!("Cannot read input: {}", e))?;
+            Ok(0)
+        }
+        Subcommand::Run | Subcommand::Test | Subcommand::Watch | Subcommand::Bench | Subcommand::WebDriver | Subcommand::Compat => {
+            unreachable!("{} is not a page command", cli.command.name())
+        }
+    }
+}
+
+/// `watch`: run every script's tests, then poll the files and re-run the
+/// tests each change affects until interrupted
+fn watch(cli: &Cli) -> Result<i32, String> {
+    // One seed for the whole session, so re-runs are comparable
+    let seed = RunSeed::resolve_with(cli.seed)?;
+    let mut browser = Browser::new()
+        .with_device(cli.device)
+        .with_color_scheme(cli.color_schemes[0])
+        .with_reduced_motion(cli.reduced_motion)
+        .with_root_font_size(cli.root_font_size)
+        .with_fallback_fonts(cli.read_fallback_fonts()?)
+        .with_animations(cli.animations)
+        .with_viewport(cli.viewport.width, cli.viewport.height)
+        .with_device_pixel_ratio(cli.device_pixel_ratio.unwrap_or(1.0))
+        .with_seed(seed.0)
+        .with_failure_capture(FailureCaptureConfig::from_env())
+        .with_leak_detection(cli.detect_leaks)
+        .with_debug_boxes(cli.debug_boxes)
+        .with_threads(cli.threads.unwrap_or(0))
+        .with_snapshots(cli.snapshots())
+        .with_modules(cli.module_config()?)
+        .with_limits(cli.limits);
+    if let Some(time) = cli.frozen_time {
+        browser = browser.with_frozen_time(time);
+    }
+    let files = WatchSet::from_cli(cli)?;
+    let mut watcher = FileWatcher::new(files.paths());
+    let mut session = WatchSession::new(browser, files);
+
+    let all: Vec<usize> = (0..session.files().scripts.len()).collect();
+    print!("{}", session.run(&all));
+    watcher.update(session.files().paths());
+    println!("\nWatching {} files for changes (Ctrl-C to stop)", watcher.paths().count());
+    loop {
+        std::thread::sleep(cli.watch_interval);
+        let changed = watcher.poll();
+        if changed.is_empty() {
+            continue;
+        }
+        let names: Vec<String> = changed.iter().map(|path| path.display().to_string()).collect();
+        println!("\nChanged: {}", names.join(", "));
+        print!("{}", session.run(&session.files().affected_scripts(&changed)));
+        watcher.update(session.files().paths());
+    }
+}
+
+/// `webdriver`: answer WebDriver clients on the port until interrupted
+fn serve_webdriver(cli: &Cli, builder: PageBuilder) -> Result<i32, String> {
+    let listener = TcpListener::bind(("127.0.0.1", cli.port)).map_err(|e| format!("Cannot listen on port {}: {}", cli.port, e))?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    println!("WebDriver listening on http://{}", address);
+    WebDriver::new(builder).serve(&listener).map_err(|e| format!("WebDriver server stopped: {}", e))?;
+    Ok(0)
+}
+
+/// `bench`: lay out and paint the synthetic pages, then save the times as
+/// a baseline or compare them with one
+fn run_bench(cli: &Cli) -> Result<i32, String> {
+    let options = &cli.bench;
+    let baseline = options.baseline.as_deref().map(BenchReport::load).transpose()?;
+    let (width, height) = (cli.viewport.width as f32, cli.viewport.height as f32);
+    let report = BenchReport::run(&bench::default_cases(), options.iterations, width, height, cli.threads.unwrap_or(0));
+    print!("{}", report.format_table());
+
+    if let Some(path) = &options.save_baseline {
+        report.save(path)?;
+        println!("Saved baseline to {}", path.display());
+    }
+    let Some(baseline) = baseline else { return Ok(0) };
+    let regressions = report.regressions(&baseline, options.tolerance);
+    if regressions.is_empty() {
+        println!("No regressions against the baseline (tolerance {}%)", options.tolerance);
+        return Ok(0);
+    }
+    eprintln!("{} regressions against the baseline (tolerance {}%):", regressions.len(), options.tolerance);
+    for regression in &regressions {
+        eprintln!("  {}", regression);
+    }
+    Ok(1)
+}
+
+/// `compat`: score the page against a real browser's capture, failing when
+/// any score is below the threshold
+fn run_compat(cli: &Cli, html: &str) -> Result<i32, Failure> {
+    let (Some(screenshot), Some(dom_dump)) = (&cli.compat.screenshot, &cli.compat.dom_dump) else {
+        return Err("'compat' requires --capture and --dom-dump".to_string().into());
+    };
+    let capture = BrowserCapture::import(screenshot, dom_dump)?;
+    let name = match &cli.html {
+        Some(InputSource::File(path)) => path.display().to_string(),
+        _ => "stdin".to_string(),
+    };
+    let scorecard = compat::compare_fixture(&name, html, &capture, &CompatConfig::new())?;
+    print!("{}", scorecard.format_scorecard());
+    if scorecard.is_safe_to_migrate(cli.compat.threshold / 100.0) {
+        println!("Safe to migrate (every score at least {}%)", cli.compat.threshold);
+        Ok(0)
+    } else {
+        println!("Not safe to migrate (a score is below {}%)", cli.compat.threshold);
+        Ok(1)
+    }
+}
+
+/// `run` and `test`: evaluate the scripts in order, opening each
+/// `--navigate` page when its turn comes, then run the tests they
+/// registered on the last page
+///
+/// As in a browser, a script that throws does not stop the ones after it.
+fn run_scripts(cli: &Cli, session: &mut Session, scripts: &[String]) -> Result<i32, Failure> {
+    let mut exit_code = 0;
+    for (index, (script, source)) in cli.scripts.iter().zip(scripts).enumerate() {
+        navigate_before(cli, session, index)?;
+        let page = session.page();
+        let result = match script {
+            InputSource::File(path) if cli.is_module(script) => page.run_module_file(source, path).map(|_| None),
+            InputSource::Stdin if cli.is_module(script) => page.run_module(source, &script.to_string()).map(|_| None),
+            InputSource::File(path) => page.run_script_file(source, path).map(Some),
+            InputSource::Stdin => page.run_script_named(source, &script.to_string()).map(Some),
+        };
+        match result {
+            Ok(value) => {
+                // Modules have no completion value to print
+                if let (Subcommand::Run, Some(value)) = (cli.command, value) {
+                    println!("JS Result ({}): {}", script, value);
+                }
             }
-        }).unwrap();
-        globals.set("attachShadow", attach_shadow_fn).unwrap();
-
-        // Expose event system functionality
-        let document_arc_clone_add_listener = document_arc.clone();
-        let add_event_listener_fn = Function::new(ctx.clone(), move |node_idx: u32, event_type: String, _listener_fn: Function| {
-            let mut doc = document_arc_clone_add_listener.lock().unwrap();
-            doc.add_event_listener(node_idx as usize, &event_type, 0); // Dummy index for now
-            println!("JS: addEventListener on node {} for event '{}'", node_idx, event_type);
-        }).unwrap();
-        globals.set("addEventListener", add_event_listener_fn).unwrap();
-
-        let document_arc_clone_dispatch_event = document_arc.clone();
-        let dispatch_event_fn = Function::new(ctx.clone(), move |node_idx: u32, event_type: String| {
-            let mut doc = document_arc_clone_dispatch_event.lock().unwrap();
-            doc.dispatch_event(node_idx as usize, &event_type);
-        }).unwrap();
-        globals.set("dispatchEvent", dispatch_event_fn).unwrap();
-
-        // Expose test reporting function
-        let test_results_clone = test_results.clone();
-        let report_test_result_fn = Function::new(ctx.clone(), move |name: String, passed: bool, message: String| {
-            let mut results = test_results_clone.lock().unwrap();
-            results.push(TestResult { name: name.clone(), passed, message: message.clone() });
-            println!("Test Result: {} - {}", name, if passed { "PASSED" } else { "FAILED" });
-        }).unwrap();
-        globals.set("reportTestResult", report_test_result_fn).unwrap();
-
-        // Expose customFixture function
-        let document_arc_clone_fixture = document_arc.clone();
-        let custom_fixture_fn = Function::new(ctx.clone(), move |tag_name: String, attributes: Object| -> rquickjs::Result<u32> {
-            let mut doc = document_arc_clone_fixture.lock().unwrap();
-            let element_idx = doc.create_element(&tag_name);
-            // Append to document body (for now, assuming body is at index 2)
-            doc.append_child(2, element_idx);
-
-            // Set attributes
-            for item in attributes.into_iter() {
-                let (key, value) = item?;
-                let key_str = key.to_string()?;
-                let value_str = value.as_string().ok_or(rquickjs::Error::Exception)?.to_string()?;
-                doc.set_attribute(element_idx, &key_str, &value_str);
+            Err(BrowserError::JavaScriptError(message, stack)) => {
+                eprintln!("JS Error in {}: {}", script, message);
+                if let Some(stack) = stack {
+                    eprintln!("{}", stack);
+                }
+                exit_code = 1;
             }
-            Ok(element_idx as u32)
-        }).unwrap();
-        globals.set("customFixture", custom_fixture_fn).unwrap();
-
-        // Note: customExpect, querySelector, and querySelectorAll will be integrated in Phase 5b
-        // after resolving rquickjs Context lifetime constraints
-
-        // Calculate layout with viewport dimensions
-        let mut document_for_layout = document_arc.lock().unwrap();
-        layout::calculate_layout(&mut document_for_layout, 256.0, 256.0);
-        drop(document_for_layout); // Release the lock
-
-        // Rendering to image
-        let mut dt = DrawTarget::new(256, 256);
-        dt.clear(SolidSource::from_unpremultiplied_argb(0xff, 0xff, 0xff, 0xff)); // White background
-
-        let mut pb = PathBuilder::new();
-        pb.rect(10.0, 10.0, 100.0, 50.0);
-        let path = pb.finish();
-
-        dt.fill(&path, &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0xff, 0x00, 0x00)), &raqote::DrawOptions::new());
-
-        dt.stroke(&path, &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0x00, 0x00, 0x00)),
-                  &StrokeStyle {
-                      width: 2.0,
-                      cap: LineCap::Round,
-                      join: LineJoin::Round,
-                      miter_limit: 2.0,
-                      dash_array: Vec::new(),
-                      dash_offset: 0.0,
-                  },
-                  &raqote::DrawOptions::new());
-
-        dt.write_png("output.png").expect("Failed to write PNG");
-        println!("Rendered image to output.png");
-
-        // Execute JavaScript code from command-line argument
-        match ctx.eval::<Value, _>(js_code_arg.as_str()) {
-            Ok(value) => println!("JS Result: {:#?}", value),
-            Err(e) => eprintln!("JS Error: {}", e),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    navigate_before(cli, session, scripts.len())?;
+
+    // Run tests registered with describe/it, each against its own DOM snapshot
+    let page = session.page();
+    let summary = page.run_tests();
+    if summary.exit_code() != 0 {
+        exit_code = 1;
+    }
+    if cli.command == Subcommand::Test || summary.total > 0 {
+        if let Err(e) = reporters::write_report(&summary, &cli.reporter) {
+            eprintln!("{}", e);
+            exit_code = 1;
         }
-    });
+    }
+
+    if cli.command == Subcommand::Run {
+        if let Some(output) = &cli.screenshot {
+            save_screenshot(cli, page, output)?;
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Open the `--navigate` pages due before script `index`, file paths as
+/// `file://` URLs
+fn navigate_before(cli: &Cli, session: &mut Session, index: usize) -> Result<(), Failure> {
+    for navigation in cli.navigations.iter().filter(|navigation| navigation.after_scripts == index) {
+        let url = match std::path::absolute(&navigation.target) {
+            Ok(path) if !navigation.target.contains("://") => format!("file://{}", path.display()),
+            _ => navigation.target.clone(),
+        };
+        let load = session.navigate(&url)?;
+        if let Some(e) = load.limit_exceeded {
+            return Err(e.into());
+        }
+        println!("Navigated to {}", url);
+    }
+    Ok(())
+}
 
-    // Print final test results
-    println!("\n--- Test Summary ---");
-    let final_results = test_results.lock().unwrap();
-    for result in final_results.iter() {
-        println!("{} [{}] - {}", result.name, if result.passed { "PASSED" } else { "FAILED" }, result.message);
+/// Save the viewport, the clip region or the full page in the format the
+/// output's extension names, once per color scheme
+fn save_screenshot(cli: &Cli, page: &Page, output: &Path) -> Result<(), Failure> {
+    for (color_scheme, output) in cli.screenshot_outputs(output) {
+        page.set_color_scheme(color_scheme)?;
+        let region = match cli.clip {
+            Some(clip) => Some(screenshot::check_region(clip).map_err(|e| e.to_string())?),
+            None if cli.full_page => Some(page.full_page_region()),
+            None => None,
+        };
+        page.check_screenshot(region.unwrap_or(page.viewport_region()))?;
+        let draw_target = match region {
+            Some(region) => page.render_region(region),
+            None => page.render(),
+        };
+        let format = ImageFormat::from_path(&output).unwrap_or_default();
+        page.tracer()
+            .span(TraceStage::Encode, "Encode screenshot", || screenshot::save_screenshot_with_format(&draw_target, &output, format, cli.quality))
+            .map_err(|e| e.to_string())?;
+        println!("Saved screenshot to {}", output.display());
     }
+    Ok(())
 }
@@ -0,0 +1,309 @@
+//! Media Queries
+//! Evaluating queries like `(min-width: 600px) and (pointer: coarse)`
+//! against the page's viewport, device and preferences
+//!
+//! Supports comma-separated lists, `not` and `only`, the `all`, `screen`
+//! and `print` media types (pages are screens), and the width, height,
+//! orientation, resolution, pointer, hover, prefers-color-scheme and
+//! prefers-reduced-motion features. Unknown features never match, as in
+//! browsers.
+//!
+//! `matchMedia` returns live `MediaQueryList`s whose `change` listeners run
+//! when the page's environment changes, e.g. on `Page::set_color_scheme`.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object};
+
+/// Value of the `prefers-color-scheme` media feature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    pub fn parse(name: &str) -> Result<ColorScheme, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "light" => Ok(ColorScheme::Light),
+            "dark" => Ok(ColorScheme::Dark),
+            _ => Err(format!("Unknown color scheme '{}', expected light or dark", name)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }
+    }
+}
+
+/// What media queries are evaluated against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaEnvironment {
+    /// Viewport size in CSS pixels
+    pub width: f32,
+    pub height: f32,
+    pub device_pixel_ratio: f32,
+    pub color_scheme: ColorScheme,
+    /// A touchscreen is the primary pointer: `pointer: coarse`,
+    /// `hover: none`
+    pub touch: bool,
+    /// The user asked for less motion: `prefers-reduced-motion: reduce`
+    pub reduced_motion: bool,
+    /// The initial font size: what `em` and `rem` are relative to in
+    /// queries, and the size the root element inherits
+    pub font_size: f32,
+}
+
+impl Default for MediaEnvironment {
+    /// A light-themed desktop browser at the default viewport
+    fn default() -> Self {
+        MediaEnvironment {
+            width: 1280.0,
+            height: 720.0,
+            device_pixel_ratio: 1.0,
+            color_scheme: ColorScheme::Light,
+            touch: false,
+            reduced_motion: false,
+            font_size: 16.0,
+        }
+    }
+}
+
+impl MediaEnvironment {
+    /// Whether `query` matches; an empty query matches everything
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_ascii_lowercase();
+        query.is_empty() || query.split(',').any(|part| self.matches_one(part.trim()))
+    }
+
+    fn matches_one(&self, query: &str) -> bool {
+        let (negated, rest) = match query.strip_prefix("not ") {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, query.strip_prefix("only ").map_or(query, str::trim_start)),
+        };
+        let mut matched = true;
+        let mut first = true;
+        for condition in split_and(rest) {
+            let condition = condition.trim();
+            matched &= if let Some(feature) = condition.strip_prefix('(').and_then(|c| c.strip_suffix(')')) {
+                self.matches_feature(feature)
+            } else if first {
+                matches!(condition, "all" | "screen")
+            } else {
+                false
+            };
+            first = false;
+        }
+        matched != negated
+    }
+
+    fn matches_feature(&self, feature: &str) -> bool {
+        let Some((name, value)) = feature.split_once(':') else {
+            // `(hover)` and friends match unless the feature's value is none
+            return match feature.trim() {
+                "hover" | "any-hover" => !self.touch,
+                "pointer" | "any-pointer" | "color" | "width" | "height" | "orientation" => true,
+                "prefers-color-scheme" => true,
+                "prefers-reduced-motion" => self.reduced_motion,
+                _ => false,
+            };
+        };
+        let (name, value) = (name.trim(), value.trim());
+        let (prefix, base) = match name.split_once('-') {
+            Some((prefix @ ("min" | "max"), base)) => (Some(prefix), base),
+            _ => (None, name),
+        };
+        let compare = |actual: f32, wanted: Option<f32>| {
+            wanted.is_some_and(|wanted| match prefix {
+                Some("min") => actual >= wanted,
+                Some(_) => actual <= wanted,
+                None => (actual - wanted).abs() < 0.001,
+            })
+        };
+        match base {
+            "width" => compare(self.width, parse_length(value, self.font_size)),
+            "height" => compare(self.height, parse_length(value, self.font_size)),
+            "resolution" => compare(self.device_pixel_ratio, parse_resolution(value)),
+            "device-pixel-ratio" | "-webkit-device-pixel-ratio" => compare(self.device_pixel_ratio, value.parse().ok()),
+            _ if prefix.is_some() => false,
+            "orientation" => value == if self.height >= self.width { "portrait" } else { "landscape" },
+            "pointer" | "any-pointer" => value == if self.touch { "coarse" } else { "fine" },
+            "hover" | "any-hover" => value == if self.touch { "none" } else { "hover" },
+            "prefers-color-scheme" => value == self.color_scheme.as_str(),
+            "prefers-reduced-motion" => value == if self.reduced_motion { "reduce" } else { "no-preference" },
+            _ => false,
+        }
+    }
+}
+
+/// `MediaQueryList` and `matchMedia`, over the natives of
+/// `install_match_media`
+const MEDIA_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexMedia;
+    const lists = [];
+
+    class MediaQueryList {
+        #listeners = [];
+        #matches;
+
+        constructor(media) {
+            this.media = media;
+            this.onchange = null;
+            this.#matches = native.matches(media);
+        }
+        get matches() { return native.matches(this.media); }
+        addEventListener(type, listener) {
+            if (type === "change" && listener && !this.#listeners.includes(listener)) this.#listeners.push(listener);
+        }
+        removeEventListener(type, listener) {
+            if (type === "change") this.#listeners = this.#listeners.filter(l => l !== listener);
+        }
+        addListener(listener) { this.addEventListener("change", listener); }
+        removeListener(listener) { this.removeEventListener("change", listener); }
+        update() {
+            const matches = this.matches;
+            if (matches === this.#matches) return;
+            this.#matches = matches;
+            const event = new Event("change");
+            Object.assign(event, { matches, media: this.media, target: this, currentTarget: this });
+            const handlers = typeof this.onchange === "function" ? [this.onchange, ...this.#listeners] : [...this.#listeners];
+            for (const listener of handlers) {
+                try {
+                    if (typeof listener === "function") listener.call(this, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    console.error("Uncaught", error);
+                }
+            }
+        }
+    }
+
+    native.update = () => lists.forEach(list => list.update());
+    globalThis.MediaQueryList = MediaQueryList;
+    globalThis.matchMedia = query => {
+        const list = new MediaQueryList(String(query));
+        lists.push(list);
+        return list;
+    };
+})();
+"#;
+
+/// Install `matchMedia`, evaluating queries against `media` as it is when
+/// they are read
+///
+/// Call `notify_media_change` after changing `media` to run the `change`
+/// listeners of lists whose result changed.
+pub fn install_match_media(ctx: &Ctx<'_>, media: Rc<Cell<MediaEnvironment>>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    native.set("matches", Function::new(ctx.clone(), move |query: String| media.get().matches(&query))?)?;
+    ctx.globals().set("__cortexMedia", native)?;
+    ctx.eval::<(), _>(MEDIA_PRELUDE)
+}
+
+/// Fire `change` at the `MediaQueryList`s whose result changed
+pub fn notify_media_change(ctx: &Ctx<'_>) -> rquickjs::Result<()> {
+    let native: Object = ctx.globals().get("__cortexMedia")?;
+    let update: Function = native.get("update")?;
+    update.call(())
+}
+
+/// Split on the `and` keyword, outside parentheses
+fn split_and(query: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    let bytes = query.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            _ if depth == 0 && query[i..].starts_with("and") => {
+                let before = i == 0 || bytes[i - 1].is_ascii_whitespace() || bytes[i - 1] == b')';
+                let after = bytes.get(i + 3).is_none_or(|&c| c.is_ascii_whitespace() || c == b'(');
+                if before && after && i >= start {
+                    parts.push(&query[start..i]);
+                    start = i + 3;
+                }
+            }
+            _ => {}
+        }
+    }
+    parts.push(&query[start..]);
+    parts
+}
+
+/// A length in CSS pixels: `px`, `em`/`rem` at the initial `font_size`, or a bare 0
+fn parse_length(value: &str, font_size: f32) -> Option<f32> {
+    let number = |unit: &str| value.strip_suffix(unit).and_then(|n| n.trim().parse::<f32>().ok());
+    number("px")
+        .or_else(|| number("rem").map(|n| n * font_size))
+        .or_else(|| number("em").map(|n| n * font_size))
+        .or_else(|| (value == "0").then_some(0.0))
+}
+
+/// A resolution in dots per CSS pixel: `dppx`, `x` or `dpi`
+fn parse_resolution(value: &str) -> Option<f32> {
+    let number = |unit: &str| value.strip_suffix(unit).and_then(|n| n.trim().parse::<f32>().ok());
+    number("dppx").or_else(|| number("dpi").map(|n| n / 96.0)).or_else(|| number("x"))
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAPTOP: MediaEnvironment = MediaEnvironment {
+        width: 1280.0,
+        height: 720.0,
+        device_pixel_ratio: 1.0,
+        color_scheme: ColorScheme::Light,
+        touch: false,
+        reduced_motion: false,
+        font_size: 16.0,
+    };
+
+    #[test]
+    fn test_color_scheme() {
+        let dark = MediaEnvironment { color_scheme: ColorScheme::Dark, ..LAPTOP };
+
+        assert!(dark.matches("(prefers-color-scheme: dark)"));
+        assert!(LAPTOP.matches("( Prefers-Color-Scheme:LIGHT )"));
+        assert!(!LAPTOP.matches("(prefers-color-scheme: dark)"));
+        assert!(!LAPTOP.matches("(prefers-contrast: more)"));
+    }
+
+    #[test]
+    fn test_reduced_motion() {
+        let reduced = MediaEnvironment { reduced_motion: true, ..LAPTOP };
+
+        assert!(reduced.matches("(prefers-reduced-motion: reduce)"));
+        assert!(reduced.matches("(prefers-reduced-motion)"));
+        assert!(!reduced.matches("(prefers-reduced-motion: no-preference)"));
+        assert!(LAPTOP.matches("(prefers-reduced-motion: no-preference)"));
+        assert!(!LAPTOP.matches("(prefers-reduced-motion)"));
+    }
+
+    #[test]
+    fn test_viewport_and_device_features() {
+        let phone = MediaEnvironment { width: 390.0, height: 844.0, device_pixel_ratio: 3.0, touch: true, ..LAPTOP };
+
+        assert!(LAPTOP.matches("(min-width: 100px)"));
+        assert!(LAPTOP.matches("screen and (min-width: 1024px) and (hover: hover)"));
+        assert!(!phone.matches("screen and (min-width: 1024px)"));
+        assert!(phone.matches("(max-width: 40em) and (orientation: portrait)"));
+        assert!(phone.matches("(pointer: coarse) and (min-resolution: 2dppx)"));
+        assert!(!phone.matches("(hover)"));
+        assert!(phone.matches("print, (any-hover: none)"));
+        assert!(phone.matches("not all and (min-width: 800px)"));
+        assert!(!LAPTOP.matches("print"));
+        assert!(LAPTOP.matches(""));
+    }
+}
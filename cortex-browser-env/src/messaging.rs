@@ -0,0 +1,710 @@
+//! Messaging
+//! `postMessage`, `MessageEvent`, `MessageChannel` and `structuredClone`
+//!
+//! Data is copied with the structured clone algorithm through JSON, and
+//! values it cannot clone throw a `DataCloneError`. Ports live in a table
+//! shared by a page and its frames, so a transferred port keeps talking to
+//! its peer.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object, Value};
+
+use crate::dom::Document;
+use crate::event_loop::TaskSource;
+use crate::events;
+use crate::network::url_origin;
+
+/// A message on its way to a window or a port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The data, as serialized by the sending realm
+    pub data: String,
+    /// The sender's origin; empty for port messages
+    pub origin: String,
+    /// Ports transferred with the message, in transfer order
+    pub ports: Vec<usize>,
+    pub source: MessageSource,
+}
+
+/// Which window sent a message, as its receiver sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageSource {
+    /// The receiving window itself
+    Window,
+    /// The receiving frame's parent
+    Parent,
+    /// The frame shown by this iframe of the receiver's
+    Frame(usize),
+    /// A `MessagePort`, which has no source window
+    Port,
+}
+
+#[derive(Debug, Default)]
+struct Port {
+    peer: Option<usize>,
+    /// The realm that may receive on the port; `None` while in transit
+    owner: Option<usize>,
+    started: bool,
+    queue: VecDeque<Message>,
+}
+
+#[derive(Debug, Default)]
+struct PortTable {
+    ports: Vec<Port>,
+    realms: usize,
+}
+
+/// The message ports of a page and its frames, by id
+#[derive(Debug, Clone, Default)]
+pub struct MessagePorts {
+    table: Rc<RefCell<PortTable>>,
+}
+
+impl MessagePorts {
+    /// A new realm id for the ports to be owned by
+    fn realm(&self) -> usize {
+        let mut table = self.table.borrow_mut();
+        table.realms += 1;
+        table.realms
+    }
+
+    /// Create two entangled ports owned by `realm`
+    pub fn entangle(&self, realm: usize) -> (usize, usize) {
+        let mut table = self.table.borrow_mut();
+        let first = table.ports.len();
+        table.ports.push(Port { peer: Some(first + 1), owner: Some(realm), ..Port::default() });
+        table.ports.push(Port { peer: Some(first), owner: Some(realm), ..Port::default() });
+        (first, first + 1)
+    }
+
+    /// Queue `message` at the peer of `port`; dropped once they are
+    /// disentangled
+    pub fn post(&self, port: usize, message: Message) {
+        self.send(&message.ports);
+        let mut table = self.table.borrow_mut();
+        if let Some(peer) = table.ports.get(port).and_then(|port| port.peer) {
+            table.ports[peer].queue.push_back(message);
+        }
+    }
+
+    /// Let `port` deliver its queued messages
+    pub fn start(&self, port: usize) {
+        if let Some(port) = self.table.borrow_mut().ports.get_mut(port) {
+            port.started = true;
+        }
+    }
+
+    /// Disentangle `port` from its peer
+    pub fn close(&self, port: usize) {
+        let mut table = self.table.borrow_mut();
+        if let Some(peer) = table.ports.get_mut(port).and_then(|port| port.peer.take()) {
+            table.ports[peer].peer = None;
+        }
+    }
+
+    /// Take `ports` from their realm as they are transferred
+    fn send(&self, ports: &[usize]) {
+        let mut table = self.table.borrow_mut();
+        for &port in ports {
+            if let Some(port) = table.ports.get_mut(port) {
+                port.owner = None;
+            }
+        }
+    }
+
+    /// Give transferred `ports` to the realm receiving them
+    fn adopt(&self, ports: &[usize], realm: usize) {
+        let mut table = self.table.borrow_mut();
+        for &port in ports {
+            if let Some(port) = table.ports.get_mut(port) {
+                port.owner = Some(realm);
+            }
+        }
+    }
+
+    /// The oldest message a started port of `realm` has queued
+    fn next_for(&self, realm: usize) -> Option<(usize, Message)> {
+        let mut table = self.table.borrow_mut();
+        let (id, port) = table.ports.iter_mut().enumerate().find(|(_, port)| port.owner == Some(realm) && port.started && !port.queue.is_empty())?;
+        Some((id, port.queue.pop_front()?))
+    }
+}
+
+/// Messages posted to a page's window and ports, oldest first
+///
+/// Each runs as a task dispatching a `MessageEvent`: at the document for
+/// window messages, which go first, or at the receiving `MessagePort`.
+#[derive(Clone)]
+pub struct PageMessages {
+    document: Rc<RefCell<Document>>,
+    origin: String,
+    realm: usize,
+    queue: Rc<RefCell<VecDeque<Message>>>,
+    ports: MessagePorts,
+}
+
+impl PageMessages {
+    /// Messages for a top-level page at `origin`
+    pub fn new(document: Rc<RefCell<Document>>, origin: String) -> Self {
+        Self::with_ports(document, origin, MessagePorts::default())
+    }
+
+    /// Messages for a frame of this page, sharing its ports
+    pub fn for_frame(&self, document: Rc<RefCell<Document>>, origin: String) -> Self {
+        Self::with_ports(document, origin, self.ports.clone())
+    }
+
+    fn with_ports(document: Rc<RefCell<Document>>, origin: String, ports: MessagePorts) -> Self {
+        let realm = ports.realm();
+        PageMessages { document, origin, realm, queue: Rc::new(RefCell::new(VecDeque::new())), ports }
+    }
+
+    /// The origin of the page, e.g. `https://shop.test`, or `null`
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Queue `message` unless `target_origin` does not match this page's
+    /// origin; `/` stands for the sender's origin and `*` for any.
+    /// Returns whether it was queued
+    pub fn post(&self, message: Message, target_origin: &str) -> bool {
+        let matches = match target_origin {
+            "*" => true,
+            "/" => message.origin == self.origin,
+            url => url_origin(url) == self.origin,
+        };
+        self.ports.send(&message.ports);
+        if matches {
+            self.queue.borrow_mut().push_back(message);
+        } else {
+            message.ports.iter().for_each(|&port| self.ports.close(port));
+        }
+        matches
+    }
+}
+
+impl TaskSource for PageMessages {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let native: Object = ctx.globals().get("__cortexMessaging")?;
+        let window_message = self.queue.borrow_mut().pop_front();
+        if let Some(message) = window_message {
+            self.ports.adopt(&message.ports, self.realm);
+            let source: Value = match message.source {
+                MessageSource::Window => ctx.globals().into_value(),
+                MessageSource::Parent => ctx.globals().get("parent")?,
+                MessageSource::Frame(iframe) => ctx.globals().get::<_, Function>("contentWindow")?.call((iframe,))?,
+                MessageSource::Port => Value::new_null(ctx.clone()),
+            };
+            let create: Function = native.get("messageEvent")?;
+            let event: Object = create.call((message.data, message.origin, message.ports, source))?;
+            let root = self.document.borrow().root;
+            events::dispatch_event(ctx, &self.document, root, event)?;
+            return Ok(true);
+        }
+        let Some((port, message)) = self.ports.next_for(self.realm) else {
+            return Ok(false);
+        };
+        self.ports.adopt(&message.ports, self.realm);
+        let deliver: Function = native.get("deliverPort")?;
+        deliver.call::<_, ()>((port, message.data, message.ports))?;
+        Ok(true)
+    }
+
+    fn pending_tasks(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    fn clear_tasks(&self) {
+        self.queue.borrow_mut().clear();
+    }
+}
+
+/// The structured clone algorithm, as a function taking the realm's
+/// transferable ports (`isPort`, `isDetached` and `detach`) and returning
+/// `{ serialize, deserialize }`; see the module docs
+const STRUCTURED_CLONE: &str = r#"
+(transferables) => {
+    const uncloneables = [Promise, WeakMap, WeakSet, globalThis.WeakRef].filter(Boolean);
+    const errors = { Error, EvalError, RangeError, ReferenceError, SyntaxError, TypeError, URIError };
+
+    function domError(name, message) {
+        const error = new Error(message);
+        error.name = name;
+        return error;
+    }
+
+    function uncloneable(value) {
+        const name = typeof value === "function" ? value.toString() : typeof value === "symbol" ? value.toString() : `#<${value.constructor?.name ?? "Object"}>`;
+        return domError("DataCloneError", `Failed to execute 'postMessage': ${name} could not be cloned.`);
+    }
+
+    /** Serialize `value` to JSON, returning it with the transferred port ids */
+    function serialize(value, transfer = []) {
+        const transferred = [...transfer];
+        transferred.forEach((item, index) => {
+            if (item instanceof ArrayBuffer) return;
+            if (!transferables.isPort(item)) {
+                throw domError("DataCloneError", `Failed to execute 'postMessage': Value at index ${index} does not have a transferable type.`);
+            }
+            if (transferables.isDetached(item) || transferred.indexOf(item) !== index) {
+                throw domError("DataCloneError", `Failed to execute 'postMessage': Port at index ${index} is already neutered or duplicated.`);
+            }
+        });
+        const transferredPorts = transferred.filter(item => transferables.isPort(item));
+        const seen = new Map();
+        const encode = value => {
+            switch (typeof value) {
+                case "undefined": return ["u"];
+                case "boolean":
+                case "string": return value;
+                case "number": return Number.isFinite(value) && !Object.is(value, -0) ? value : ["n", String(Object.is(value, -0) ? "-0" : value)];
+                case "bigint": return ["i", String(value)];
+                case "symbol":
+                case "function": throw uncloneable(value);
+            }
+            if (value === null) return null;
+            if (seen.has(value)) return ["ref", seen.get(value)];
+            if (transferables.isPort(value)) {
+                const index = transferredPorts.indexOf(value);
+                if (index < 0) throw domError("DataCloneError", "Failed to execute 'postMessage': A MessagePort could not be cloned because it was not transferred.");
+                return ["p", index];
+            }
+            if (uncloneables.some(type => value instanceof type)) throw uncloneable(value);
+            const id = seen.size;
+            seen.set(value, id);
+            const entries = object => Object.keys(object).map(key => [key, encode(object[key])]);
+            if (Array.isArray(value)) return ["a", id, value.length, entries(value)];
+            if (value instanceof Date) return ["d", id, encode(value.getTime())];
+            if (value instanceof RegExp) return ["r", id, value.source, value.flags];
+            if (value instanceof Map) return ["m", id, [...value].map(([key, item]) => [encode(key), encode(item)])];
+            if (value instanceof Set) return ["S", id, [...value].map(encode)];
+            if (value instanceof Error) return ["e", id, value.name in errors ? value.name : "Error", String(value.message), value.stack === undefined ? null : String(value.stack)];
+            if (value instanceof ArrayBuffer) return ["b", id, [...new Uint8Array(value)]];
+            if (ArrayBuffer.isView(value)) {
+                const length = value instanceof DataView ? value.byteLength : value.length;
+                return ["v", id, value.constructor.name, encode(value.buffer), value.byteOffset, length];
+            }
+            if (value instanceof Boolean || value instanceof Number || value instanceof String || (typeof BigInt === "function" && value instanceof BigInt)) {
+                return ["B", id, encode(value.valueOf())];
+            }
+            return ["o", id, entries(value)];
+        };
+        const data = JSON.stringify(encode(value));
+        return { data, ports: transferredPorts.map(port => transferables.detach(port)) };
+    }
+
+    /** Rebuild serialized `data` in this realm, `ports` standing for the transferred ones */
+    function deserialize(data, ports) {
+        const built = [];
+        const decode = value => {
+            if (value === null || typeof value !== "object") return value;
+            const [tag, id] = value;
+            const keep = object => (built[id] = object);
+            switch (tag) {
+                case "u": return undefined;
+                case "n": return Number(value[1]);
+                case "i": return BigInt(value[1]);
+                case "ref": return built[id];
+                case "p": return ports[id];
+                case "a": {
+                    const array = keep(new Array(value[2]));
+                    for (const [key, item] of value[3]) array[key] = decode(item);
+                    return array;
+                }
+                case "d": return keep(new Date(decode(value[2])));
+                case "r": return keep(new RegExp(value[2], value[3]));
+                case "m": {
+                    const map = keep(new Map());
+                    for (const [key, item] of value[2]) map.set(decode(key), decode(item));
+                    return map;
+                }
+                case "S": {
+                    const set = keep(new Set());
+                    for (const item of value[2]) set.add(decode(item));
+                    return set;
+                }
+                case "e": {
+                    const error = keep(new errors[value[2]](value[3]));
+                    if (value[4] !== null) error.stack = value[4];
+                    return error;
+                }
+                case "b": return keep(new Uint8Array(value[2]).buffer);
+                case "v": {
+                    const buffer = decode(value[3]);
+                    return keep(new globalThis[value[2]](buffer, value[4], value[5]));
+                }
+                case "B": return keep(Object(decode(value[2])));
+                case "o": {
+                    const object = keep({});
+                    for (const [key, item] of value[2]) object[key] = decode(item);
+                    return object;
+                }
+            }
+            throw domError("DataCloneError", `Unknown serialized value '${tag}'.`);
+        };
+        return decode(JSON.parse(data));
+    }
+
+    return { serialize, deserialize };
+}
+"#;
+
+/// Evaluate `STRUCTURED_CLONE` in `ctx`, for this module and workers
+pub(crate) fn structured_clone<'js>(ctx: &Ctx<'js>) -> rquickjs::Result<Function<'js>> {
+    ctx.eval(STRUCTURED_CLONE)
+}
+
+/// `structuredClone`, `MessageEvent`, `MessageChannel`, `MessagePort` and
+/// `postMessage`, over the natives of `install_messaging`
+///
+/// `__cortexMessaging` stays for the event loop and the frames and workers
+/// preludes: `postWith(post)` builds a window's `postMessage` around a
+/// native `post`, and `serialize` and `deserialize` clone data.
+const MESSAGING_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexMessaging;
+    const ports = new Map();
+    const state = new WeakMap();
+    const token = Symbol("MessagePort");
+    const { serialize, deserialize } = native.cloning({
+        isPort: value => value instanceof MessagePort,
+        isDetached: port => state.get(port).detached,
+        detach,
+    });
+
+    function domError(name, message) {
+        const error = new Error(message);
+        error.name = name;
+        return error;
+    }
+
+    /** Hand a port over to a message, returning its id */
+    function detach(port) {
+        const { id } = state.get(port);
+        state.get(port).detached = true;
+        ports.delete(id);
+        return id;
+    }
+
+    function portFor(id) {
+        if (!ports.has(id)) ports.set(id, new MessagePort(token, id));
+        return ports.get(id);
+    }
+
+    /** `postMessage(message, targetOrigin, transfer)` or `postMessage(message, { targetOrigin, transfer })` */
+    function options(targetOrigin, transfer) {
+        if (targetOrigin !== null && typeof targetOrigin === "object") {
+            return { targetOrigin: String(targetOrigin.targetOrigin ?? "/"), transfer: targetOrigin.transfer ?? [] };
+        }
+        return { targetOrigin: targetOrigin === undefined ? "/" : String(targetOrigin), transfer: transfer ?? [] };
+    }
+
+    function postWith(post) {
+        return function postMessage(message, targetOrigin, transfer) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to execute 'postMessage' on 'Window': 1 argument required, but only 0 present.");
+            }
+            const settings = options(targetOrigin, transfer);
+            if (settings.targetOrigin !== "*" && settings.targetOrigin !== "/" && !/^[a-z][a-z0-9+.-]*:/i.test(settings.targetOrigin)) {
+                throw domError("SyntaxError", `Failed to execute 'postMessage' on 'Window': Invalid target origin '${settings.targetOrigin}' in a call to 'postMessage'.`);
+            }
+            const { data, ports } = serialize(message, settings.transfer);
+            post(data, ports, settings.targetOrigin);
+        };
+    }
+
+    class MessageEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.data = init.data === undefined ? null : init.data;
+            this.origin = String(init.origin ?? "");
+            this.lastEventId = String(init.lastEventId ?? "");
+            this.source = init.source ?? null;
+            this.ports = Object.freeze([...(init.ports ?? [])]);
+        }
+    }
+
+    class MessagePort {
+        #onmessage = null;
+        #listeners = new Map();
+
+        constructor(key, id) {
+            if (key !== token) throw new TypeError("Illegal constructor");
+            state.set(this, { id, detached: false });
+            this.onmessageerror = null;
+        }
+
+        get onmessage() { return this.#onmessage; }
+        set onmessage(handler) {
+            this.#onmessage = typeof handler === "function" ? handler : null;
+            this.start();
+        }
+
+        postMessage(message, transfer) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to execute 'postMessage' on 'MessagePort': 1 argument required, but only 0 present.");
+            }
+            const list = transfer !== null && typeof transfer === "object" && !Array.isArray(transfer) && !(Symbol.iterator in transfer) ? transfer.transfer ?? [] : transfer ?? [];
+            if ([...list].includes(this)) {
+                throw domError("DataCloneError", "Failed to execute 'postMessage' on 'MessagePort': Port at index 0 contains the source port.");
+            }
+            const { data, ports } = serialize(message, list);
+            const { id, detached } = state.get(this);
+            if (!detached) native.postPort(id, data, ports);
+        }
+
+        start() {
+            const { id, detached } = state.get(this);
+            if (!detached) native.start(id);
+        }
+
+        close() {
+            const { id, detached } = state.get(this);
+            if (!detached) native.close(id);
+        }
+
+        addEventListener(type, listener) {
+            if (typeof listener !== "function" && !(listener && typeof listener.handleEvent === "function")) return;
+            const listeners = this.#listeners.get(type) || [];
+            if (!listeners.includes(listener)) listeners.push(listener);
+            this.#listeners.set(type, listeners);
+        }
+
+        removeEventListener(type, listener) {
+            const listeners = this.#listeners.get(type) || [];
+            this.#listeners.set(type, listeners.filter(l => l !== listener));
+        }
+
+        dispatchEvent(event) {
+            event.target = this;
+            event.currentTarget = this;
+            const handler = event.type === "message" ? this.#onmessage : this["on" + event.type];
+            const listeners = [...(typeof handler === "function" ? [handler] : []), ...(this.#listeners.get(event.type) || [])];
+            for (const listener of listeners) {
+                try {
+                    if (typeof listener === "function") listener.call(this, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    console.error("Uncaught", error);
+                }
+            }
+            return !event.defaultPrevented;
+        }
+    }
+
+    class MessageChannel {
+        constructor() {
+            const [first, second] = native.entangle();
+            this.port1 = portFor(first);
+            this.port2 = portFor(second);
+        }
+    }
+
+    native.postWith = postWith;
+    native.serialize = serialize;
+    native.deserialize = deserialize;
+    native.messageEvent = (data, origin, portIds, source) => {
+        const received = portIds.map(portFor);
+        const event = new MessageEvent("message", { data: deserialize(data, received), origin, source, ports: received });
+        event.isTrusted = true;
+        return event;
+    };
+    native.deliverPort = (id, data, portIds) => {
+        const port = ports.get(id);
+        if (!port) return;
+        const received = portIds.map(portFor);
+        const event = new MessageEvent("message", { data: deserialize(data, received), ports: received });
+        event.isTrusted = true;
+        port.dispatchEvent(event);
+    };
+
+    globalThis.MessageEvent = MessageEvent;
+    globalThis.MessagePort = MessagePort;
+    globalThis.MessageChannel = MessageChannel;
+    globalThis.structuredClone = function structuredClone(value, options = {}) {
+        if (arguments.length === 0) {
+            throw new TypeError("Failed to execute 'structuredClone' on 'Window': 1 argument required, but only 0 present.");
+        }
+        const { data, ports } = serialize(value, options?.transfer ?? []);
+        return deserialize(data, ports.map(portFor));
+    };
+    globalThis.postMessage = postWith(native.postToSelf);
+})();
+"#;
+
+/// Install `structuredClone`, `MessageEvent`, `MessageChannel`,
+/// `MessagePort` and the window's own `postMessage`, receiving through
+/// `messages`
+pub fn install_messaging<'js>(ctx: &Ctx<'js>, messages: PageMessages) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+
+    let (ports, realm) = (messages.ports.clone(), messages.realm);
+    native.set(
+        "entangle",
+        Function::new(ctx.clone(), move || {
+            let (first, second) = ports.entangle(realm);
+            vec![first, second]
+        })?,
+    )?;
+    let ports = messages.ports.clone();
+    native.set(
+        "postPort",
+        Function::new(ctx.clone(), move |port: usize, data: String, transferred: Vec<usize>| {
+            ports.post(port, Message { data, origin: String::new(), ports: transferred, source: MessageSource::Port });
+        })?,
+    )?;
+    let ports = messages.ports.clone();
+    native.set("start", Function::new(ctx.clone(), move |port: usize| ports.start(port))?)?;
+    let ports = messages.ports.clone();
+    native.set("close", Function::new(ctx.clone(), move |port: usize| ports.close(port))?)?;
+    native.set(
+        "postToSelf",
+        Function::new(ctx.clone(), move |data: String, ports: Vec<usize>, target_origin: String| {
+            let origin = messages.origin.clone();
+            messages.post(Message { data, origin, ports, source: MessageSource::Window }, &target_origin);
+        })?,
+    )?;
+
+    native.set("cloning", structured_clone(ctx)?)?;
+    ctx.globals().set("__cortexMessaging", native)?;
+    ctx.eval::<(), _>(MESSAGING_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_loop::{ready, EventLoop};
+    use crate::parser::parse_html;
+    use rquickjs::{AsyncContext, AsyncRuntime};
+
+    fn eval(context: &AsyncContext, script: &str) -> String {
+        ready(context.with(|ctx| ctx.eval::<rquickjs::Coerced<String>, _>(script).map(|s| s.0))).unwrap()
+    }
+
+    /// A realm with events and messaging over an empty document at `origin`
+    fn realm(runtime: &AsyncRuntime, origin: &str) -> (AsyncContext, EventLoop) {
+        let context = ready(AsyncContext::full(runtime)).unwrap();
+        let document = Rc::new(RefCell::new(parse_html("<html><body></body></html>")));
+        let messages = PageMessages::new(document.clone(), origin.to_string());
+        ready(context.with(|ctx| {
+            ctx.eval::<(), _>("globalThis.console = { error: (...args) => { throw new Error(args.join(' ')); } };")?;
+            events::install_events(&ctx, document)?;
+            install_messaging(&ctx, messages.clone())
+        }))
+        .unwrap();
+        (context, EventLoop::new().with_source(Rc::new(messages)))
+    }
+
+    #[test]
+    fn test_structured_clone_keeps_types_and_references() {
+        // Given: A value with special numbers, collections and a cycle
+        let runtime = AsyncRuntime::new().unwrap();
+        let (context, _) = realm(&runtime, "https://shop.test");
+        eval(
+            &context,
+            r#"
+            const shared = { sku: 42 };
+            globalThis.original = {
+                nothing: undefined, nan: NaN, negativeZero: -0, big: 10n ** 20n,
+                when: new Date(0), pattern: /a+/gi, map: new Map([[shared, "first"]]), set: new Set([shared]),
+                error: new RangeError("too far"), bytes: new Uint16Array([1, 2, 3]).subarray(1), sparse: [1, , 3],
+            };
+            original.self = original;
+            globalThis.copy = structuredClone(original);
+            "#,
+        );
+
+        // When: We inspect the copy
+        let checks = eval(
+            &context,
+            r#"[
+                copy !== original, copy.self === copy, "nothing" in copy && copy.nothing === undefined,
+                Number.isNaN(copy.nan), Object.is(copy.negativeZero, -0), copy.big === 10n ** 20n,
+                copy.when instanceof Date && copy.when.getTime() === 0, copy.pattern.flags === "gi",
+                [...copy.map.keys()][0] === [...copy.set][0], copy.error instanceof RangeError && copy.error.message === "too far",
+                copy.bytes instanceof Uint16Array && copy.bytes.join() === "2,3" && copy.bytes.byteOffset === 2, !(1 in copy.sparse),
+            ].join()"#,
+        );
+
+        // Then: Everything survives, and functions cannot be cloned
+        assert_eq!(checks, ["true"; 12].join(","));
+        assert_eq!(eval(&context, "try { structuredClone({ f() {} }); } catch (e) { e.name }"), "DataCloneError");
+    }
+
+    #[test]
+    fn test_window_messages_check_the_target_origin() {
+        // Given: A page logging the messages it receives
+        let runtime = AsyncRuntime::new().unwrap();
+        let (context, event_loop) = realm(&runtime, "https://shop.test");
+        eval(&context, "globalThis.log = []; addEventListener(0, 'message', e => log.push(`${e.data.n} from ${e.origin} ${e.source === globalThis} ${e.isTrusted}`));");
+
+        // When: It posts to itself with matching and mismatched origins
+        eval(
+            &context,
+            r#"postMessage({ n: 1 }); postMessage({ n: 2 }, "https://evil.test"); postMessage({ n: 3 }, "*");
+            postMessage({ n: 4 }, { targetOrigin: "https://shop.test/cart" });"#,
+        );
+        let before = eval(&context, "log.length");
+        while event_loop.run_next_task(&context).unwrap() {}
+
+        // Then: Matching messages arrive asynchronously, in order
+        assert_eq!(before, "0");
+        assert_eq!(eval(&context, "log.join('; ')"), "1 from https://shop.test true true; 3 from https://shop.test true true; 4 from https://shop.test true true");
+        assert_eq!(eval(&context, "try { postMessage(1, 'shop.test'); } catch (e) { e.name }"), "SyntaxError");
+    }
+
+    #[test]
+    fn test_message_channels_queue_until_started_and_transfer_ports() {
+        // Given: A channel whose second port receives before being started
+        let runtime = AsyncRuntime::new().unwrap();
+        let (context, event_loop) = realm(&runtime, "null");
+        let drain = || while event_loop.run_next_task(&context).unwrap() {};
+        eval(
+            &context,
+            r#"
+            globalThis.log = [];
+            globalThis.channel = new MessageChannel();
+            channel.port1.postMessage("early");
+            channel.port2.addEventListener("message", e => log.push("listener " + e.data));
+            "#,
+        );
+        drain();
+        assert_eq!(eval(&context, "log.length"), "0");
+
+        // When: The port is started, then a second channel's port is
+        // transferred through it and used to reply
+        eval(
+            &context,
+            r#"
+            channel.port2.start();
+            channel.port2.onmessage = e => {
+                log.push("onmessage " + (typeof e.data === "string" ? e.data : e.data.reply === e.ports[0]));
+                if (e.ports.length) e.ports[0].postMessage("pong");
+            };
+            const reply = new MessageChannel();
+            reply.port1.onmessage = e => log.push("reply " + e.data);
+            channel.port1.postMessage({ reply: reply.port2 }, [reply.port2]);
+            try { channel.port1.postMessage(null, [reply.port2]); } catch (e) { globalThis.resent = e.name; }
+            "#,
+        );
+        drain();
+
+        // Then: Queued and transferred messages arrive in order
+        assert_eq!(eval(&context, "log.join()"), "onmessage early,listener early,onmessage true,listener [object Object],reply pong");
+        assert_eq!(eval(&context, "resent"), "DataCloneError");
+
+        // When: The channel is closed
+        eval(&context, "channel.port1.close(); channel.port2.postMessage('late');");
+        drain();
+
+        // Then: Nothing more arrives
+        assert_eq!(eval(&context, "log.length"), "5");
+    }
+}
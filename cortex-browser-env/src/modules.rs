@@ -0,0 +1,427 @@
+//! ES Modules
+//! Resolves `import` specifiers against a module root and an import map,
+//! and loads module sources for the QuickJS runtime
+//!
+//! Modules are named by their path relative to the root, with `/`
+//! separators, e.g. `components/button.js`; stack frames show these names.
+//! Relative specifiers (`./util.js`) resolve from the importing module,
+//! root-relative ones (`/lib/util.js`) from the root, and bare ones
+//! (`lit`, `lib/util.js`) only through the import map.
+//!
+//! TypeScript and JSX modules (`.ts`, `.tsx`, `.jsx`) are transpiled as
+//! they load; as with `tsc`, `./util.js` also finds `util.ts`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use rquickjs::loader::{Loader, Resolver};
+use rquickjs::module::ModuleData;
+use rquickjs::{AsyncRuntime, Ctx};
+
+use crate::event_loop::ready;
+
+use crate::error::BrowserError;
+use crate::json::Json;
+use crate::transpile::{self, TranspileOptions};
+
+/// Extensions tried, in order, for specifiers that name no existing file
+const EXTENSIONS: [&str; 5] = ["js", "mjs", "ts", "tsx", "jsx"];
+
+/// Bare specifier mappings, as in the `imports` of an HTML import map
+///
+/// A key ending in `/` maps every specifier starting with it; otherwise the
+/// key must match exactly. The longest matching key wins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportMap {
+    imports: Vec<(String, String)>,
+}
+
+impl ImportMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map `specifier` to `target`, a path relative to the module root
+    pub fn with_import(mut self, specifier: &str, target: &str) -> Self {
+        self.imports.retain(|(key, _)| key != specifier);
+        self.imports.push((specifier.to_string(), target.to_string()));
+        // Longest keys first, so the most specific prefix matches
+        self.imports.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        self
+    }
+
+    /// Parse `{"imports": {"specifier": "target", ...}}`
+    pub fn parse(json: &str) -> Result<ImportMap, BrowserError> {
+        let invalid = |reason: &str| BrowserError::ParseError(format!("Invalid import map: {}", reason));
+        let map = Json::parse(json).ok_or_else(|| invalid("malformed JSON"))?;
+        let entries = match map.get("imports") {
+            Some(Json::Object(entries)) => entries,
+            None => return Ok(ImportMap::new()),
+            Some(_) => return Err(invalid("\"imports\" must be an object")),
+        };
+        entries.iter().try_fold(ImportMap::new(), |map, (specifier, target)| {
+            let target = target
+                .as_str()
+                .ok_or_else(|| invalid(&format!("the target of \"{}\" must be a string", specifier)))?;
+            if specifier.ends_with('/') != target.ends_with('/') {
+                return Err(invalid(&format!("\"{}\" and its target must both end in '/' or neither", specifier)));
+            }
+            Ok(map.with_import(specifier, target))
+        })
+    }
+
+    /// Read and parse an import map file
+    pub fn load(path: &Path) -> Result<ImportMap, BrowserError> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| BrowserError::NotFoundError(format!("Cannot read import map '{}': {}", path.display(), e)))?;
+        ImportMap::parse(&json)
+    }
+
+    /// The target a bare specifier maps to
+    pub fn map(&self, specifier: &str) -> Option<String> {
+        self.imports.iter().find_map(|(key, target)| {
+            if key.ends_with('/') {
+                specifier.strip_prefix(key.as_str()).map(|rest| format!("{}{}", target, rest))
+            } else {
+                (key == specifier).then(|| target.clone())
+            }
+        })
+    }
+}
+
+/// A source read from disk, transpiled if need be, kept until the file
+/// changes
+#[derive(Debug, Clone)]
+struct CachedSource {
+    modified: Option<SystemTime>,
+    source: Rc<str>,
+}
+
+/// Module sources shared by every page a browser opens
+///
+/// Each page's runtime compiles a module once however often it is imported;
+/// the cache saves re-reading files across pages, e.g. between `watch`
+/// re-runs, and re-reads a file when its modification time changes.
+#[derive(Clone, Default)]
+pub struct ModuleCache {
+    sources: Rc<RefCell<HashMap<PathBuf, CachedSource>>>,
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of cached sources
+    pub fn len(&self) -> usize {
+        self.sources.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.borrow().is_empty()
+    }
+
+    fn read(&self, path: &Path, options: &TranspileOptions) -> Result<Rc<str>, BrowserError> {
+        let unreadable = |e: io::Error| BrowserError::NotFoundError(format!("{}: {}", path.display(), e));
+        let modified = fs::metadata(path).map_err(unreadable)?.modified().ok();
+        if let Some(cached) = self.sources.borrow().get(path) {
+            if cached.modified.is_some() && cached.modified == modified {
+                return Ok(cached.source.clone());
+            }
+        }
+        let source = fs::read_to_string(path).map_err(unreadable)?;
+        let source: Rc<str> = transpile::transpile_file(&source, path, options)?.into();
+        self.sources.borrow_mut().insert(path.to_path_buf(), CachedSource { modified, source: source.clone() });
+        Ok(source)
+    }
+}
+
+impl fmt::Debug for ModuleCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ModuleCache({} sources)", self.len())
+    }
+}
+
+/// Where modules are loaded from
+#[derive(Debug, Clone)]
+pub struct ModuleConfig {
+    /// Directory module names are relative to
+    pub root: PathBuf,
+    pub import_map: ImportMap,
+    pub cache: ModuleCache,
+    /// How TypeScript and JSX modules compile
+    pub transpile: TranspileOptions,
+}
+
+impl ModuleConfig {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ModuleConfig {
+            root: root.into(),
+            import_map: ImportMap::new(),
+            cache: ModuleCache::new(),
+            transpile: TranspileOptions::default(),
+        }
+    }
+
+    pub fn with_import_map(mut self, import_map: ImportMap) -> Self {
+        self.import_map = import_map;
+        self
+    }
+
+    pub fn with_transpile_options(mut self, options: TranspileOptions) -> Self {
+        self.transpile = options;
+        self
+    }
+
+    /// The name of the module at `path`, relative to the root
+    ///
+    /// Paths outside the root are an error, since imports could not name
+    /// them either.
+    pub fn module_name(&self, path: &Path) -> Result<String, BrowserError> {
+        let outside = || {
+            BrowserError::NotFoundError(format!(
+                "Module '{}' is outside the module root '{}'",
+                path.display(),
+                self.root.display()
+            ))
+        };
+        let absolute = |p: &Path| p.canonicalize().or_else(|_| std::env::current_dir().map(|dir| dir.join(p)));
+        let (path, root) = (absolute(path).map_err(|_| outside())?, absolute(&self.root).map_err(|_| outside())?);
+        let relative = path.strip_prefix(&root).map_err(|_| outside())?;
+        let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+        Ok(parts.join("/"))
+    }
+
+    /// Resolve `specifier` imported from the module named `base` to a module
+    /// name, or explain why it cannot be
+    pub fn resolve(&self, base: &str, specifier: &str) -> Result<String, String> {
+        let candidate = if specifier.starts_with("./") || specifier.starts_with("../") {
+            let dir = base.rsplit_once('/').map_or("", |(dir, _)| dir);
+            join_module_path(dir, specifier)
+        } else if let Some(rooted) = specifier.strip_prefix('/') {
+            join_module_path("", rooted)
+        } else {
+            let target = self.import_map.map(specifier).ok_or_else(|| {
+                format!("bare specifier '{}' is not in the import map; use a relative path or map it", specifier)
+            })?;
+            join_module_path("", target.trim_start_matches('/'))
+        }
+        .ok_or_else(|| format!("'{}' points outside the module root", specifier))?;
+
+        self.find(&candidate)
+            .ok_or_else(|| format!("no file at '{}'", self.root.join(&candidate).display()))
+    }
+
+    /// The module name of the first existing file among `name`, `name` with
+    /// an extension, the TypeScript file a `.js` name stands for, and
+    /// `name/index` with an extension
+    fn find(&self, name: &str) -> Option<String> {
+        let with_extensions = EXTENSIONS.iter().map(|ext| format!("{}.{}", name, ext));
+        let typescript = name.strip_suffix(".js").into_iter().flat_map(|stem| ["ts", "tsx"].map(|ext| format!("{}.{}", stem, ext)));
+        let dir = name.trim_end_matches('/');
+        let index = EXTENSIONS.iter().map(move |ext| format!("{}/index.{}", dir, ext));
+        std::iter::once(name.to_string())
+            .chain(with_extensions)
+            .chain(typescript)
+            .chain(index)
+            .find(|candidate| !candidate.is_empty() && self.root.join(candidate).is_file())
+    }
+
+    /// Read a module by name, through the cache, transpiling TypeScript and
+    /// JSX
+    pub fn source(&self, name: &str) -> Result<Rc<str>, BrowserError> {
+        self.cache.read(&self.root.join(name), &self.transpile)
+    }
+}
+
+impl Default for ModuleConfig {
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+/// Join `path` onto the module directory `dir`, resolving `.` and `..`;
+/// `None` if it climbs above the root
+fn join_module_path(dir: &str, path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    let mut joined = parts.join("/");
+    if path.ends_with('/') {
+        joined.push('/');
+    }
+    Some(joined)
+}
+
+struct ModuleResolver(ModuleConfig);
+
+impl Resolver for ModuleResolver {
+    fn resolve<'js>(&mut self, _ctx: &Ctx<'js>, base: &str, name: &str) -> rquickjs::Result<String> {
+        self.0.resolve(base, name).map_err(|message| rquickjs::Error::new_resolving_message(base, name, message))
+    }
+}
+
+/// The files a runtime has loaded modules from, in load order
+///
+/// Files that failed to read or compile are listed too, so `watch` re-runs
+/// once they are fixed.
+#[derive(Debug, Clone, Default)]
+pub struct LoadedModules(Rc<RefCell<Vec<PathBuf>>>);
+
+impl LoadedModules {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.0.borrow().clone()
+    }
+
+    fn record(&self, path: PathBuf) {
+        let mut paths = self.0.borrow_mut();
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+}
+
+struct ModuleLoader {
+    config: ModuleConfig,
+    loaded: LoadedModules,
+}
+
+impl Loader for ModuleLoader {
+    fn load<'js>(&mut self, _ctx: &Ctx<'js>, name: &str) -> rquickjs::Result<ModuleData> {
+        self.loaded.record(self.config.root.join(name));
+        let source = self.config.source(name).map_err(|e| rquickjs::Error::new_loading_message(name, e.to_string()))?;
+        Ok(ModuleData::source(name, source.as_bytes()))
+    }
+}
+
+/// Let `import` statements and `import()` in `runtime` load modules as
+/// `config` says, recording the files they load
+pub fn install_module_loader(runtime: &AsyncRuntime, config: &ModuleConfig) -> LoadedModules {
+    let loaded = LoadedModules::default();
+    ready(runtime.set_loader(ModuleResolver(config.clone()), ModuleLoader { config: config.clone(), loaded: loaded.clone() }));
+    loaded
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module root with `main.js`, `lib/util.js`, `lib/index.js` and
+    /// `vendor/lit/core.js`
+    fn module_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cortex_modules_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, source) in [
+            ("main.js", "import { twice } from './lib/util.js'; export const four = twice(2);"),
+            ("lib/util.js", "export function twice(n) { return n * 2; }"),
+            ("lib/index.js", "export * from './util.js';"),
+            ("vendor/lit/core.js", "export const lit = 'lit';"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_join_module_path() {
+        assert_eq!(join_module_path("lib", "./util.js").as_deref(), Some("lib/util.js"));
+        assert_eq!(join_module_path("lib/deep", "../../main.js").as_deref(), Some("main.js"));
+        assert_eq!(join_module_path("", "vendor/"), Some("vendor/".to_string()));
+        assert_eq!(join_module_path("lib", "../../escape.js"), None);
+    }
+
+    #[test]
+    fn test_import_map() {
+        let map = ImportMap::parse(r#"{"imports": {"lit": "vendor/lit/core.js", "lit/": "vendor/lit/", "app/": "src/"}}"#)
+            .unwrap();
+
+        assert_eq!(map.map("lit").as_deref(), Some("vendor/lit/core.js"));
+        assert_eq!(map.map("lit/core.js").as_deref(), Some("vendor/lit/core.js"));
+        assert_eq!(map.map("app/ui/button.js").as_deref(), Some("src/ui/button.js"));
+        assert_eq!(map.map("react"), None);
+
+        assert_eq!(ImportMap::parse("{}"), Ok(ImportMap::new()));
+        assert!(ImportMap::parse(r#"{"imports": {"a/": "b.js"}}"#).is_err());
+        assert!(ImportMap::parse(r#"{"imports": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_resolve_specifiers() {
+        let root = module_root("resolve");
+        let config = ModuleConfig::new(&root).with_import_map(ImportMap::new().with_import("lit", "/vendor/lit/core.js"));
+
+        assert_eq!(config.resolve("main.js", "./lib/util.js"), Ok("lib/util.js".to_string()));
+        assert_eq!(config.resolve("lib/index.js", "./util"), Ok("lib/util.js".to_string()), "Extension is optional");
+        assert_eq!(config.resolve("main.js", "./lib"), Ok("lib/index.js".to_string()));
+        assert_eq!(config.resolve("lib/util.js", "/main.js"), Ok("main.js".to_string()));
+        assert_eq!(config.resolve("main.js", "lit"), Ok("vendor/lit/core.js".to_string()));
+
+        let missing = config.resolve("main.js", "./nope.js").unwrap_err();
+        assert!(missing.starts_with("no file at "), "{}", missing);
+        assert_eq!(
+            config.resolve("main.js", "react").unwrap_err(),
+            "bare specifier 'react' is not in the import map; use a relative path or map it"
+        );
+        assert_eq!(config.resolve("main.js", "../up.js").unwrap_err(), "'../up.js' points outside the module root");
+
+        assert_eq!(config.module_name(&root.join("lib/util.js")), Ok("lib/util.js".to_string()));
+        assert!(config.module_name(&std::env::temp_dir()).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_cache_rereads_changed_files() {
+        let root = module_root("cache");
+        let config = ModuleConfig::new(&root);
+
+        assert_eq!(&*config.source("lib/util.js").unwrap(), "export function twice(n) { return n * 2; }");
+        let shared = config.clone();
+        assert_eq!(shared.cache.len(), 1, "Clones share the cache");
+
+        // A changed modification time invalidates the entry
+        let path = root.join("lib/util.js");
+        fs::write(&path, "export const changed = true;").unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert_eq!(&*shared.source("lib/util.js").unwrap(), "export const changed = true;");
+        assert!(config.source("missing.js").is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn test_typescript_modules_are_transpiled() {
+        let root = module_root("typescript");
+        fs::write(root.join("lib/math.ts"), "export const add = (a: number, b: number): number => a + b;").unwrap();
+        fs::write(root.join("lib/view.tsx"), "/** @jsx h */\nexport const view = <p>{1}</p>;").unwrap();
+        fs::write(root.join("lib/broken.tsx"), "let a = 1;\nconst b = <div>;").unwrap();
+        let config = ModuleConfig::new(&root);
+
+        assert_eq!(config.resolve("main.js", "./lib/math"), Ok("lib/math.ts".to_string()));
+        assert_eq!(config.resolve("main.js", "./lib/math.js"), Ok("lib/math.ts".to_string()), "`.js` finds the TypeScript source");
+        assert_eq!(config.resolve("main.js", "./lib/view.js"), Ok("lib/view.tsx".to_string()));
+
+        assert_eq!(&*config.source("lib/math.ts").unwrap(), "export const add = (a, b) => a + b;");
+        assert_eq!(&*config.source("lib/view.tsx").unwrap(), "/** @jsx h */\nexport const view = h(\"p\", null, 1);");
+        let error = config.source("lib/broken.tsx").unwrap_err().to_string();
+        assert!(error.contains("broken.tsx") && error.contains("line 2"), "{}", error);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+//! Navigation
+//! Where following a link would take the page, recorded instead of loaded
+//!
+//! Clicking an `<a href>` (or `<area href>`) runs its activation behavior
+//! unless a `click` listener canceled the event, as a client-side router's
+//! links do. The page never leaves its document: the request is recorded on
+//! the `Page` for tests to check, with the URL resolved against the page's
+//! base URL and whether the link's `target` opens another window.
+
+use crate::dom::Document;
+use crate::forms;
+use crate::network::resolve_url;
+
+/// A navigation a followed link asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavigationRequest {
+    /// The `<a>` or `<area>` element followed
+    pub link: usize,
+    /// The `href` resolved against the base URL
+    pub url: String,
+    /// The `target` attribute, e.g. `_blank`; `None` when unset
+    pub target: Option<String>,
+}
+
+impl NavigationRequest {
+    /// Build the request following `link` asks for, resolving its `href`
+    /// against `base_url`
+    pub fn for_link(document: &Document, link: usize, base_url: Option<&str>) -> NavigationRequest {
+        let href = document.get_attribute(link, "href").map_or("", String::as_str);
+        let target = document.get_attribute(link, "target").map(|target| target.trim().to_string()).filter(|target| !target.is_empty());
+        NavigationRequest { link, url: resolve_href(base_url.unwrap_or("about:blank"), href), target }
+    }
+
+    /// Whether the link opens in another window or tab instead of this
+    /// page: `_blank`, or a named target other than `_self`, `_parent` and
+    /// `_top`
+    pub fn opens_new_window(&self) -> bool {
+        self.target.as_deref().is_some_and(|target| {
+            !["_self", "_parent", "_top"].iter().any(|keyword| target.eq_ignore_ascii_case(keyword))
+        })
+    }
+}
+
+/// Whether `idx` is a link: an `<a>` or `<area>` with an `href`
+pub fn is_link(document: &Document, idx: usize) -> bool {
+    matches!(forms::tag_name(document, idx), Some("a" | "area")) && document.get_attribute(idx, "href").is_some()
+}
+
+/// Resolve an `href`; a fragment-only one like `#top` replaces the base's
+/// fragment
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.trim();
+    if href.starts_with('#') {
+        let document_url = base.split_once('#').map_or(base, |(url, _)| url);
+        return format!("{}{}", document_url, href);
+    }
+    resolve_url(base, href)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    #[test]
+    fn test_links_resolve_against_the_base_and_detect_new_windows() {
+        // Given: Relative, fragment and new-window links, and an anchor
+        // without an href
+        let document = parse_html(r##"<a href="../cart?x=1">a</a><a href="#reviews" target="_SELF">b</a><a href="/help" target="_blank">c</a><a name="top">d</a>"##);
+        let anchors: Vec<usize> = (0..document.nodes.len()).filter(|&idx| forms::tag_name(&document, idx) == Some("a")).collect();
+        let base = Some("https://shop.test/products/42#top");
+
+        // When: We build the request each link would make
+        let requests: Vec<NavigationRequest> = anchors[..3].iter().map(|&link| NavigationRequest::for_link(&document, link, base)).collect();
+
+        // Then: URLs resolve as a browser's would and only _blank opens a window
+        assert_eq!(requests[0].url, "https://shop.test/products/../cart?x=1");
+        assert_eq!(requests[1].url, "https://shop.test/products/42#reviews");
+        assert_eq!(requests[2].url, "https://shop.test/help");
+        assert_eq!(requests.iter().map(NavigationRequest::opens_new_window).collect::<Vec<_>>(), vec![false, false, true]);
+        assert!(anchors[..3].iter().all(|&link| is_link(&document, link)));
+        assert!(!is_link(&document, anchors[3]));
+    }
+}
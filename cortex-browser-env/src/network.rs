@@ -0,0 +1,462 @@
+//! Network Layer
+//! Loads resources referenced by documents (images, fonts, scripts) through a
+//! pluggable loader, so tests can serve fixtures without touching the network
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Error type for resource loading
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkError {
+    InvalidUrl(String),
+    NotFound(String),
+    IoError(String),
+    Unsupported(String),
+    /// A path outside what the loader may read
+    Forbidden(String),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::InvalidUrl(url) => write!(f, "Invalid URL: {}", url),
+            NetworkError::NotFound(url) => write!(f, "Resource not found: {}", url),
+            NetworkError::IoError(msg) => write!(f, "IO Error: {}", msg),
+            NetworkError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+            NetworkError::Forbidden(url) => write!(f, "Access denied: {}", url),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+/// Source of resource bytes for a URL
+///
+/// `data:` URIs never reach a loader; `fetch` decodes them directly.
+pub trait ResourceLoader {
+    fn load(&self, url: &str) -> Result<Vec<u8>, NetworkError>;
+}
+
+impl<L: ResourceLoader + ?Sized> ResourceLoader for std::rc::Rc<L> {
+    fn load(&self, url: &str) -> Result<Vec<u8>, NetworkError> {
+        (**self).load(url)
+    }
+}
+
+/// Fetch a resource, decoding `data:` URIs inline and delegating everything
+/// else to the loader
+pub fn fetch(loader: &dyn ResourceLoader, url: &str) -> Result<Vec<u8>, NetworkError> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err(NetworkError::InvalidUrl(url.to_string()));
+    }
+    if is_data_uri(url) {
+        return decode_data_uri(url).map(|(_, body)| body);
+    }
+    let result = loader.load(url);
+    match &result {
+        Ok(body) => tracing::debug!(url, bytes = body.len(), "Fetched"),
+        Err(e) => tracing::debug!(url, error = %e, "Fetch failed"),
+    }
+    result
+}
+
+/// Check whether a URL uses the `data:` scheme (case-insensitively)
+pub fn is_data_uri(url: &str) -> bool {
+    url.trim_start().get(..5).is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Decode a `data:` URI into its media type and body
+///
+/// Both base64 (`data:image/png;base64,...`) and percent-encoded bodies are
+/// accepted. A missing media type defaults to `text/plain`.
+pub fn decode_data_uri(url: &str) -> Result<(String, Vec<u8>), NetworkError> {
+    let url = url.trim();
+    if !is_data_uri(url) {
+        return Err(NetworkError::InvalidUrl(url.to_string()));
+    }
+    let rest = &url[5..];
+    let (header, payload) = rest
+        .split_once(',')
+        .ok_or_else(|| NetworkError::InvalidUrl(url.to_string()))?;
+
+    let mut params = header.split(';');
+    let media_type = match params.next().map(str::trim) {
+        Some(mime) if !mime.is_empty() => mime.to_lowercase(),
+        _ => "text/plain".to_string(),
+    };
+    let is_base64 = params.any(|p| p.trim().eq_ignore_ascii_case("base64"));
+
+    let body = if is_base64 {
+        decode_base64(&percent_decode(payload))
+            .ok_or_else(|| NetworkError::InvalidUrl("Malformed base64 in data: URI".to_string()))?
+    } else {
+        percent_decode(payload)
+    };
+
+    Ok((media_type, body))
+}
+
+/// Build a base64 `data:` URI, for inlining fixtures into self-contained pages
+pub fn encode_data_uri(media_type: &str, body: &[u8]) -> String {
+    format!("data:{};base64,{}", media_type, encode_base64(body))
+}
+
+/// Standard base64 with padding
+pub fn encode_base64(body: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(body.len().div_ceil(3) * 4);
+    for chunk in body.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode `%XX` escapes, leaving malformed escapes as-is
+fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(value) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(value);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Decode standard or URL-safe base64, ignoring whitespace and padding
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    fn sextet(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' | b'-' => Some(62),
+            b'/' | b'_' => Some(63),
+            _ => None,
+        }
+    }
+
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &c in input {
+        if c.is_ascii_whitespace() || c == b'=' {
+            continue;
+        }
+        buffer = (buffer << 6) | sextet(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// In-memory loader serving canned responses, for hermetic tests
+///
+/// Every requested URL is recorded so tests can assert what was fetched.
+#[derive(Debug, Default)]
+pub struct MockNetwork {
+    responses: HashMap<String, Vec<u8>>,
+    requests: RefCell<Vec<String>>,
+}
+
+impl MockNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `body` for requests to `url`
+    pub fn with_response(mut self, url: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.responses.insert(url.to_string(), body.into());
+        self
+    }
+
+    /// URLs requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl ResourceLoader for MockNetwork {
+    fn load(&self, url: &str) -> Result<Vec<u8>, NetworkError> {
+        self.requests.borrow_mut().push(url.to_string());
+        self.responses
+            .get(url)
+            .cloned()
+            .ok_or_else(|| NetworkError::NotFound(url.to_string()))
+    }
+}
+
+/// Loader reading `file://` URLs and relative paths from disk
+///
+/// Only files inside `base_dir` load: paths are resolved, following `..`
+/// and symlinks, and anything that ends up outside it, like
+/// `file:///etc/passwd` or `../../secret`, is refused.
+#[derive(Debug, Clone, Default)]
+pub struct FileLoader {
+    base_dir: PathBuf,
+}
+
+impl FileLoader {
+    /// Resolve relative URLs against `base_dir`
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        FileLoader { base_dir: base_dir.into() }
+    }
+}
+
+impl ResourceLoader for FileLoader {
+    fn load(&self, url: &str) -> Result<Vec<u8>, NetworkError> {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Err(NetworkError::Unsupported(format!("Remote fetch of {}", url)));
+        }
+
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        let path = self.base_dir.join(path);
+        let failed = |e: std::io::Error, path: &Path| match e.kind() {
+            std::io::ErrorKind::NotFound => NetworkError::NotFound(url.to_string()),
+            _ => NetworkError::IoError(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let base = self.base_dir.canonicalize().map_err(|e| failed(e, &self.base_dir))?;
+        let path = path.canonicalize().map_err(|e| failed(e, &path))?;
+        if !path.starts_with(&base) {
+            return Err(NetworkError::Forbidden(url.to_string()));
+        }
+        fs::read(&path).map_err(|e| failed(e, &path))
+    }
+}
+
+/// Loader for pages with networking disabled; only `data:` URIs load
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OfflineLoader;
+
+impl ResourceLoader for OfflineLoader {
+    fn load(&self, url: &str) -> Result<Vec<u8>, NetworkError> {
+        Err(NetworkError::Unsupported(format!("Network is offline: {}", url)))
+    }
+}
+
+/// Whether `url` is absolute, starting with a scheme such as `https:`
+pub fn has_scheme(url: &str) -> bool {
+    url.split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)))
+}
+
+/// Resolve a possibly relative URL against a base URL
+///
+/// URLs with a scheme are returned as-is, `/path` is resolved against the
+/// base's origin and anything else against the base's directory. Dot
+/// segments are not normalized.
+pub fn resolve_url(base: &str, url: &str) -> String {
+    let url = url.trim();
+    if has_scheme(url) || base.is_empty() {
+        return url.to_string();
+    }
+
+    // Origin: scheme and authority, e.g. `https://example.com`
+    let authority_start = base.find("://").map_or(0, |i| i + 3);
+    let origin_end = base[authority_start..].find('/').map_or(base.len(), |i| authority_start + i);
+    if let Some(rest) = url.strip_prefix("//") {
+        let scheme = base.split_once("://").map_or("", |(scheme, _)| scheme);
+        return format!("{}://{}", scheme, rest);
+    }
+    if url.starts_with('/') {
+        return format!("{}{}", &base[..origin_end], url);
+    }
+    let path = &base[origin_end..];
+    let directory = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+    let directory = if directory.is_empty() { "/" } else { directory };
+    format!("{}{}{}", &base[..origin_end], directory, url)
+}
+
+/// The origin of `url`: its scheme, host and any non-default port for
+/// http(s) and ws(s) URLs, e.g. `https://example.test`; `null` otherwise
+pub fn url_origin(url: &str) -> String {
+    let Some((scheme, rest)) = url.trim().split_once("://") else {
+        return "null".to_string();
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" | "ws" => "80",
+        "https" | "wss" => "443",
+        _ => return "null".to_string(),
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host).to_ascii_lowercase();
+    match host.rsplit_once(':') {
+        Some((name, port)) if port == default_port || port.is_empty() => format!("{}://{}", scheme, name),
+        _ => format!("{}://{}", scheme, host),
+    }
+}
+
+/// Loader resolving relative URLs against a base URL before delegating
+pub struct BaseUrlLoader<L> {
+    pub base_url: String,
+    pub inner: L,
+}
+
+impl<L: ResourceLoader> ResourceLoader for BaseUrlLoader<L> {
+    fn load(&self, url: &str) -> Result<Vec<u8>, NetworkError> {
+        self.inner.load(&resolve_url(&self.base_url, url))
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_base64_data_uri() {
+        // Given: A base64 data URI
+        let (mime, body) = decode_data_uri("data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==").unwrap();
+
+        // Then: The media type and bytes are decoded
+        assert_eq!(mime, "text/plain");
+        assert_eq!(body, b"Hello, World!");
+    }
+
+    #[test]
+    fn test_decode_percent_encoded_data_uri() {
+        // Given: A percent-encoded data URI without a media type
+        let (mime, body) = decode_data_uri("data:,a%20b%2Cc").unwrap();
+
+        // Then: Escapes are decoded and the media type defaults to text/plain
+        assert_eq!(mime, "text/plain");
+        assert_eq!(body, b"a b,c");
+    }
+
+    #[test]
+    fn test_encode_data_uri_round_trips() {
+        for body in [&b""[..], b"a", b"ab", b"abc", &[0, 255, 128, 7]] {
+            let uri = encode_data_uri("application/octet-stream", body);
+            assert_eq!(decode_data_uri(&uri).unwrap(), ("application/octet-stream".to_string(), body.to_vec()));
+        }
+        assert_eq!(encode_data_uri("text/plain", b"Hello, World!"), "data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==");
+    }
+
+    #[test]
+    fn test_data_scheme_is_case_insensitive() {
+        let network = MockNetwork::new();
+
+        assert_eq!(fetch(&network, "DATA:,hi"), Ok(b"hi".to_vec()));
+        assert_eq!(decode_data_uri(" Data:text/css,a%7Bb%7D ").unwrap().1, b"a{b}");
+        assert!(network.requests().is_empty());
+    }
+
+    #[test]
+    fn test_decode_data_uri_rejects_malformed() {
+        assert!(decode_data_uri("data:image/png;base64").is_err());
+        assert!(decode_data_uri("data:;base64,@@@").is_err());
+    }
+
+    #[test]
+    fn test_mock_network_serves_and_records() {
+        // Given: A mock with one canned response
+        let network = MockNetwork::new().with_response("https://cdn.test/a.png", vec![1, 2, 3]);
+
+        // When: We fetch a known, an unknown and a data: URL
+        let known = fetch(&network, "https://cdn.test/a.png");
+        let missing = fetch(&network, "https://cdn.test/b.png");
+        let inline = fetch(&network, "data:,x");
+
+        // Then: Only network URLs reach the loader
+        assert_eq!(known, Ok(vec![1, 2, 3]));
+        assert_eq!(missing, Err(NetworkError::NotFound("https://cdn.test/b.png".to_string())));
+        assert_eq!(inline, Ok(b"x".to_vec()));
+        assert_eq!(network.requests(), vec!["https://cdn.test/a.png", "https://cdn.test/b.png"]);
+    }
+
+    #[test]
+    fn test_file_loader_resolves_relative_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("logo.png"), b"png").unwrap();
+        let loader = FileLoader::new(dir.path());
+
+        assert_eq!(loader.load("logo.png"), Ok(b"png".to_vec()));
+        assert!(matches!(loader.load("missing.png"), Err(NetworkError::NotFound(_))));
+        assert!(matches!(loader.load("https://example.com/x"), Err(NetworkError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_file_loader_stays_inside_its_base_dir() {
+        // Given: A site directory with a page, next to a secret
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("site/css")).unwrap();
+        fs::write(dir.path().join("site/index.html"), b"<p>Hi</p>").unwrap();
+        fs::write(dir.path().join("secret.txt"), b"key").unwrap();
+        let loader = FileLoader::new(dir.path().join("site"));
+        let secret = dir.path().join("secret.txt").canonicalize().unwrap();
+
+        // Then: Paths that stay inside load, however they are written
+        assert_eq!(loader.load("css/../index.html"), Ok(b"<p>Hi</p>".to_vec()));
+        let inside = format!("file://{}", dir.path().join("site/index.html").canonicalize().unwrap().display());
+        assert_eq!(loader.load(&inside), Ok(b"<p>Hi</p>".to_vec()));
+
+        // And: Paths leading out are refused, absolute or relative
+        for url in ["../secret.txt", "file://../secret.txt", &format!("file://{}", secret.display()), &secret.display().to_string()] {
+            assert_eq!(loader.load(url), Err(NetworkError::Forbidden(url.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "https://example.test/app/index.html";
+
+        assert_eq!(resolve_url(base, "img/logo.png"), "https://example.test/app/img/logo.png");
+        assert_eq!(resolve_url(base, "/fonts/a.woff"), "https://example.test/fonts/a.woff");
+        assert_eq!(resolve_url(base, "//cdn.test/x.js"), "https://cdn.test/x.js");
+        assert_eq!(resolve_url(base, "data:,hi"), "data:,hi");
+        assert_eq!(resolve_url(base, "file:///tmp/a.png"), "file:///tmp/a.png");
+        assert_eq!(resolve_url("https://example.test", "a.png"), "https://example.test/a.png");
+        assert_eq!(resolve_url("", "a.png"), "a.png");
+    }
+
+    #[test]
+    fn test_url_origin() {
+        assert_eq!(url_origin("https://Example.test/app/index.html?q=1"), "https://example.test");
+        assert_eq!(url_origin("http://example.test:80/"), "http://example.test");
+        assert_eq!(url_origin("http://user@example.test:8080#top"), "http://example.test:8080");
+        assert_eq!(url_origin("file:///tmp/index.html"), "null");
+        assert_eq!(url_origin("about:blank"), "null");
+    }
+
+    #[test]
+    fn test_offline_and_base_url_loaders() {
+        // Given: A mock network behind a base URL, and an offline loader
+        let loader = BaseUrlLoader {
+            base_url: "https://example.test/app/".to_string(),
+            inner: MockNetwork::new().with_response("https://example.test/app/a.txt", "A"),
+        };
+
+        // Then: Relative URLs reach the mock resolved, and offline loads fail
+        assert_eq!(fetch(&loader, "a.txt"), Ok(b"A".to_vec()));
+        assert_eq!(loader.inner.requests(), vec!["https://example.test/app/a.txt"]);
+        assert!(matches!(fetch(&OfflineLoader, "https://example.test/"), Err(NetworkError::Unsupported(_))));
+        assert_eq!(fetch(&OfflineLoader, "data:,ok"), Ok(b"ok".to_vec()));
+    }
+}
@@ -0,0 +1,88 @@
+//! Parallel Work
+//! The thread pool style resolution and layout spread large documents
+//! over
+//!
+//! Work only goes to the pool for documents of at least
+//! `MIN_PARALLEL_NODES` nodes; smaller ones run on the calling thread,
+//! where splitting would cost more than it saves. Results never depend on
+//! the thread count: each node's style and box are computed from the same
+//! inputs either way and collected in node order.
+//!
+//! The thread count is a setting of each page, `PageBuilder::with_threads`,
+//! passed down to `style::compute_styles_with_threads` and
+//! `layout::calculate_styled_layout_with_threads`; the plain versions use
+//! one thread per core.
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{Arc, Mutex};
+
+/// Documents smaller than this are styled and laid out serially
+pub const MIN_PARALLEL_NODES: usize = 2048;
+
+/// Pools built so far, by thread count, shared by every page asking for
+/// that many
+static POOLS: Mutex<Vec<(usize, Arc<ThreadPool>)>> = Mutex::new(Vec::new());
+
+/// The threads a `threads` setting means: 0 for one per core, otherwise
+/// that many
+pub fn resolve_threads(threads: usize) -> usize {
+    match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Whether a document of `nodes` nodes is worth splitting across
+/// `threads` threads
+pub fn worth_splitting(nodes: usize, threads: usize) -> bool {
+    nodes >= MIN_PARALLEL_NODES && resolve_threads(threads) > 1
+}
+
+/// Run `work` in a pool of `threads` threads, 0 for one per core, so the
+/// rayon iterators inside it use that many
+pub fn install<R: Send>(threads: usize, work: impl FnOnce() -> R + Send) -> R {
+    let threads = resolve_threads(threads);
+    let pool = {
+        let mut pools = POOLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match pools.iter().find(|(size, _)| *size == threads) {
+            Some((_, pool)) => pool.clone(),
+            None => {
+                let built = match ThreadPoolBuilder::new().num_threads(threads).build() {
+                    Ok(built) => Arc::new(built),
+                    // No threads to be had: run where we are
+                    Err(_) => return work(),
+                };
+                pools.push((threads, built.clone()));
+                built
+            }
+        }
+    };
+    pool.install(work)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::BenchDocument;
+    use crate::{css, layout, parser, style};
+
+    #[test]
+    fn test_parallel_results_match_serial() {
+        // Given: A table large enough to split, and its stylesheet
+        let mut document = parser::parse_html(&BenchDocument::Table.html(300));
+        assert!(document.nodes.len() >= MIN_PARALLEL_NODES);
+        let stylesheet = css::parse_css("td { padding: 4px; } .alt { color: #336699; }");
+
+        // When: It is styled and laid out on one thread, then on four
+        let mut run = |threads: usize| {
+            let styles = style::compute_styles_with_threads(&document, &stylesheet, threads);
+            layout::calculate_styled_layout_with_threads(&mut document, &styles, 800.0, 600.0, threads);
+            (styles, document.layouts().to_vec())
+        };
+        let serial = run(1);
+        let split = run(4);
+
+        // Then: Every style and box is the same
+        assert!(split == serial);
+    }
+}
@@ -1,104 +1,309 @@
+//! HTML Parser
+//! Markup to a `Document`, all at once with `parse_html` or pushed a chunk
+//! at a time through a `Parser`
+//!
+//! Both build the same tree: a chunk may end anywhere, even inside a tag or
+//! a character, and whatever is incomplete waits for the next chunk.
+
+use super::atom::Atom;
 use super::dom::{Document, Node, NodeType, ElementData, NodeData};
+use super::entities;
+use super::serialize::VOID_ELEMENTS;
 use std::collections::HashMap;
-use std::iter::Peekable;
-use std::str::Chars;
+use std::io::{self, Read};
 
 pub fn parse_html(html: &str) -> Document {
-    let mut document = Document::new();
-    let mut current_parent_idx: Option<usize> = Some(document.root);
-
-    let mut chars = html.chars().peekable();
-
-    while let Some(&c) = chars.peek() {
-        match c {
-            '<' => {
-                chars.next(); // Consume '<'
-                // Start of a tag
-                if chars.peek() == Some(&'/') {
-                    // End tag
-                    chars.next(); // Consume '/'
-                    let tag_name = consume_tag_name(&mut chars);
-                    // Pop current_parent_idx if it matches the end tag
-                    if let Some(parent_idx) = current_parent_idx {
-                        if let Some(Node { node_type: NodeType::Element, data: Some(NodeData::Element(ElementData { tag_name: current_tag, .. })), .. }) = document.get_node(parent_idx) {
-                            if current_tag == &tag_name {
-                                current_parent_idx = document.get_node(parent_idx).unwrap().parent;
-                            }
+    let mut parser = Parser::new();
+    parser.feed(html);
+    let document = parser.finish();
+    tracing::debug!(bytes = html.len(), nodes = document.nodes.len(), "Parsed HTML");
+    document
+}
+
+/// Parse `html` into detached nodes of `document`, as for `document.write`,
+/// returning the top-level ones in order
+pub fn parse_fragment(document: &mut Document, html: &str) -> Vec<usize> {
+    let holder = document.create_element("template");
+    let mut builder = TreeBuilder { current_parent_idx: Some(holder), after_pre: false };
+    builder.build(document, html, true);
+    let nodes = document.nodes[holder].children.clone();
+    for &idx in &nodes {
+        document.remove_child(idx);
+    }
+    nodes
+}
+
+/// Parse everything `reader` yields, a chunk at a time; bytes that are not
+/// UTF-8 become U+FFFD
+pub fn parse_reader(mut reader: impl Read) -> io::Result<Document> {
+    let mut parser = Parser::new();
+    let mut chunk = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => parser.feed_bytes(&chunk[..read]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(parser.finish())
+}
+
+/// A push parser: `feed` it chunks of markup as they arrive, then `finish`
+#[derive(Debug)]
+pub struct Parser {
+    document: Document,
+    builder: TreeBuilder,
+    /// Markup fed but not yet parsed: the start of an unfinished tag or
+    /// text run
+    pending: String,
+    /// Trailing bytes of a character split across `feed_bytes` chunks
+    partial_char: Vec<u8>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        let document = Document::new();
+        let builder = TreeBuilder { current_parent_idx: Some(document.root), after_pre: false };
+        Parser { document, builder, pending: String::new(), partial_char: Vec::new() }
+    }
+
+    /// Parse as much of the markup so far as is complete
+    pub fn feed(&mut self, chunk: &str) {
+        self.pending.push_str(chunk);
+        let consumed = self.builder.build(&mut self.document, &self.pending, false);
+        self.pending.drain(..consumed);
+    }
+
+    /// Feed UTF-8 bytes, holding back a character split at the end
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        self.partial_char.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut rest = &self.partial_char[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    text.push_str(std::str::from_utf8(&rest[..e.valid_up_to()]).unwrap_or_default());
+                    match e.error_len() {
+                        Some(invalid) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &rest[e.valid_up_to() + invalid..];
+                        }
+                        // Cut off mid-character: wait for the rest
+                        None => {
+                            rest = &rest[e.valid_up_to()..];
+                            break;
                         }
                     }
-                    consume_until(&mut chars, '>');
-                    chars.next(); // Consume '>'
-                } else {
-                    // Start tag
-                    let tag_name = consume_tag_name(&mut chars);
-                    let attributes = consume_attributes(&mut chars);
-                    consume_until(&mut chars, '>');
-                    chars.next(); // Consume '>'
-
-                    let new_element_idx = document.create_element(&tag_name);
-                    for (attr_name, attr_value) in attributes {
-                        document.set_attribute(new_element_idx, &attr_name, &attr_value);
-                    }
+                }
+            }
+        }
+        self.partial_char = rest.to_vec();
+        self.feed(&text);
+    }
 
-                    if let Some(parent_idx) = current_parent_idx {
-                        document.append_child(parent_idx, new_element_idx);
-                    }
-                    current_parent_idx = Some(new_element_idx);
+    /// The tree built so far; unfinished elements are open at the end
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// Parse whatever is left, as if the markup ended there
+    pub fn finish(mut self) -> Document {
+        if !self.partial_char.is_empty() {
+            self.pending.push(char::REPLACEMENT_CHARACTER);
+        }
+        self.builder.build(&mut self.document, &self.pending, true);
+        self.document
+    }
+}
+
+/// Where parsing is in the tree: the element new nodes go into
+#[derive(Debug)]
+struct TreeBuilder {
+    current_parent_idx: Option<usize>,
+    /// Just opened a `<pre>`, `<listing>` or `<textarea>`, whose first
+    /// newline is dropped so the content can start on the next line
+    after_pre: bool,
+}
+
+impl TreeBuilder {
+    /// Add the nodes in `html` to `document`, returning the bytes used; an
+    /// unfinished tag or text run at the end is left unless `at_end`
+    fn build(&mut self, document: &mut Document, html: &str, at_end: bool) -> usize {
+        let mut chars = Cursor::new(html);
+        loop {
+            let before = chars.clone();
+            if chars.peek().is_none() || !self.step(document, &mut chars, at_end) {
+                return html.len() - before.rest.len();
+            }
+        }
+    }
+
+    /// Parse one tag or text run, returning false, with nothing added, if
+    /// it runs off the end of the input before `at_end`
+    fn step(&mut self, document: &mut Document, chars: &mut Cursor, at_end: bool) -> bool {
+        let complete = |chars: &Cursor| at_end || chars.peek().is_some();
+        if chars.peek() != Some('<') {
+            // Text content
+            let text_content = consume_text(chars);
+            if !complete(chars) {
+                return false;
+            }
+            let text_content = if std::mem::take(&mut self.after_pre) { text_content.strip_prefix('\n').unwrap_or(&text_content) } else { &text_content };
+            // Whitespace is kept, even between tags; layout collapses it
+            if !text_content.is_empty() {
+                let new_text_node_idx = document.create_text_node(&entities::decode(text_content, false));
+
+                if let Some(parent_idx) = self.current_parent_idx {
+                    document.append_child(parent_idx, new_text_node_idx);
                 }
             }
-            _ => {
-                // Text content
-                let text_content = consume_text(&mut chars);
-                if !text_content.trim().is_empty() {
-                    let new_text_node_idx = document.create_text_node(&text_content);
+            return true;
+        }
 
-                    if let Some(parent_idx) = current_parent_idx {
-                        document.append_child(parent_idx, new_text_node_idx);
+        chars.next(); // Consume '<'
+        // Start of a tag
+        if chars.peek() == Some('/') {
+            // End tag
+            chars.next(); // Consume '/'
+            let tag_name = Atom::new(&consume_tag_name(chars));
+            consume_until(chars, '>');
+            if !complete(chars) {
+                return false;
+            }
+            chars.next(); // Consume '>'
+            self.after_pre = false;
+            // Close the innermost open element with this name, and any left
+            // open inside it; an end tag matching none is ignored
+            let mut open = self.current_parent_idx;
+            while let Some(idx) = open {
+                let node = document.get_node(idx).unwrap();
+                if let Node { node_type: NodeType::Element, data: Some(NodeData::Element(ElementData { tag_name: current_tag, .. })), .. } = node {
+                    if *current_tag == tag_name {
+                        self.current_parent_idx = node.parent;
+                        break;
                     }
                 }
+                open = node.parent;
             }
+            return true;
         }
-    }
 
-    document
-}
+        // Start tag
+        let tag_name = consume_tag_name(chars);
+        let attributes = consume_attributes(chars);
+        let self_closing = chars.peek() == Some('/');
+        consume_until(chars, '>');
+        if !complete(chars) {
+            return false;
+        }
+        chars.next(); // Consume '>'
 
-fn consume_tag_name(chars: &mut Peekable<Chars>) -> String {
-    let mut name = String::new();
-    while let Some(&c) = chars.peek() {
-        // Allow alphanumeric, hyphens, underscores, and colons for custom elements
-        // Examples: ui-text-input, my:component, custom-element_v2
-        if c.is_alphanumeric() || c == '-' || c == '_' || c == ':' {
-            name.push(chars.next().unwrap());
+        // Script and style text is raw: a `<` in it starts no tag
+        let opens = !self_closing && !VOID_ELEMENTS.contains(&tag_name.to_ascii_lowercase().as_str());
+        let raw_text = if opens && RAW_TEXT_TAGS.contains(&tag_name.as_str()) {
+            let text_content = consume_raw_text(chars, &tag_name);
+            if !complete(chars) {
+                return false;
+            }
+            Some(text_content)
         } else {
-            break;
+            None
+        };
+
+        let new_element_idx = document.create_element(&tag_name);
+        for (attr_name, attr_value) in attributes {
+            document.set_attribute(new_element_idx, &attr_name, &entities::decode(&attr_value, true));
+        }
+
+        if let Some(parent_idx) = self.current_parent_idx {
+            let parent_idx = head_for_metadata(document, parent_idx, &tag_name).unwrap_or(parent_idx);
+            document.append_child(parent_idx, new_element_idx);
         }
+        // `<rect />` and friends have no children or end tag,
+        // and neither do void elements like `<input>` and `<br>`
+        self.after_pre = opens && matches!(tag_name.as_str(), "pre" | "listing" | "textarea");
+        if opens {
+            self.current_parent_idx = Some(new_element_idx);
+            if let Some(text_content) = raw_text.filter(|text| !text.is_empty()) {
+                let new_text_node_idx = document.create_text_node(&text_content);
+                document.append_child(new_element_idx, new_text_node_idx);
+            }
+        }
+        true
     }
-    name
 }
 
-fn consume_attributes(chars: &mut Peekable<Chars>) -> HashMap<String, String> {
-    let mut attributes = HashMap::new();
-    while let Some(&c) = chars.peek() {
-        if c.is_whitespace() {
-            chars.next();
-        } else if c == '>' || c == '/' {
-            break;
-        } else {
-            let attr_name = consume_attr_name(chars);
-            consume_until(chars, '=');
-            chars.next(); // Consume '=' 
-            let attr_value = consume_attr_value(chars);
-            attributes.insert(attr_name, attr_value);
+/// Position in the markup being parsed
+#[derive(Debug, Clone)]
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Cursor { rest: input }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.rest = &self.rest[c.len_utf8()..];
+        Some(c)
+    }
+}
+
+/// Elements whose content runs to their end tag without parsing markup
+const RAW_TEXT_TAGS: &[&str] = &["script", "style"];
+
+/// The `<head>` to move a metadata element into when it turns up outside
+/// the head and body, e.g. after `</head>`, so it stays out of the page flow
+fn head_for_metadata(document: &Document, parent_idx: usize, tag_name: &str) -> Option<usize> {
+    if !matches!(tag_name, "title" | "meta" | "link" | "base") {
+        return None;
+    }
+    let parent_tag = match &document.nodes[parent_idx].data {
+        Some(NodeData::Element(elem)) => Some(elem.tag_name.as_str()),
+        _ => None,
+    };
+    if !matches!(parent_tag, None | Some("html")) {
+        return None;
+    }
+    let mut stack = vec![document.root];
+    while let Some(idx) = stack.pop() {
+        match &document.nodes[idx].data {
+            Some(NodeData::Element(elem)) if elem.tag_name == "head" => return Some(idx),
+            Some(NodeData::Element(elem)) if elem.tag_name == "body" => continue,
+            _ => stack.extend(document.nodes[idx].children.iter().rev()),
         }
     }
-    attributes
+    None
 }
 
-fn consume_attr_name(chars: &mut Peekable<Chars>) -> String {
+fn consume_tag_name(chars: &mut Cursor) -> String {
     let mut name = String::new();
-    while let Some(&c) = chars.peek() {
-        if c.is_alphanumeric() || c == '-' {
+    while let Some(c) = chars.peek() {
+        // Allow alphanumeric, hyphens, underscores, and colons for custom elements
+        // Examples: ui-text-input, my:component, custom-element_v2
+        if c.is_alphanumeric() || c == '-' || c == '_' || c == ':' {
             name.push(chars.next().unwrap());
         } else {
             break;
@@ -107,40 +312,125 @@ fn consume_attr_name(chars: &mut Peekable<Chars>) -> String {
     name
 }
 
-fn consume_attr_value(chars: &mut Peekable<Chars>) -> String {
+/// Where `consume_attributes` is in a start tag, after the tag name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttributeState {
+    BeforeName,
+    Name,
+    AfterName,
+    BeforeValue,
+    /// In a value, closed by its quote or, unquoted, by whitespace or `>`
+    Value(Option<char>),
+}
+
+/// The attributes of a start tag, up to the `>` or `/>` that ends it
+///
+/// An attribute without `=`, like `disabled` or `data-x`, has an empty
+/// value. Names run to whitespace, `/`, `>` or `=`, so `@click` and
+/// `:value` work too. When a name repeats, the first value wins.
+fn consume_attributes(chars: &mut Cursor) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut state = AttributeState::BeforeName;
+    let mut name = String::new();
     let mut value = String::new();
-    let quote_char = if chars.peek() == Some(&'\'') || chars.peek() == Some(&'"') {
-        chars.next().unwrap()
-    } else {
-        '\0' // No quote
-    };
+    while let Some(c) = chars.peek() {
+        state = match (state, c) {
+            (AttributeState::BeforeName | AttributeState::AfterName, '>') => break,
+            (AttributeState::BeforeName | AttributeState::AfterName, '/') => {
+                // `/>` ends a self-closing tag; a stray `/` is skipped
+                if chars.rest.starts_with("/>") {
+                    break;
+                }
+                if !name.is_empty() {
+                    attributes.entry(std::mem::take(&mut name)).or_default();
+                }
+                chars.next();
+                AttributeState::BeforeName
+            }
+            (AttributeState::BeforeName, c) if c.is_whitespace() => {
+                chars.next();
+                AttributeState::BeforeName
+            }
+            (AttributeState::BeforeName, _) => {
+                name.push(chars.next().unwrap());
+                AttributeState::Name
+            }
+            (AttributeState::Name, c) if c.is_whitespace() || c == '/' || c == '>' => AttributeState::AfterName,
+            (AttributeState::Name | AttributeState::AfterName, '=') => {
+                chars.next();
+                AttributeState::BeforeValue
+            }
+            (AttributeState::Name, _) => {
+                name.push(chars.next().unwrap());
+                AttributeState::Name
+            }
+            (AttributeState::AfterName, c) if c.is_whitespace() => {
+                chars.next();
+                AttributeState::AfterName
+            }
+            // Another name: the last one had no value
+            (AttributeState::AfterName, _) => {
+                attributes.entry(std::mem::take(&mut name)).or_default();
+                AttributeState::BeforeName
+            }
+            (AttributeState::BeforeValue, c) if c.is_whitespace() => {
+                chars.next();
+                AttributeState::BeforeValue
+            }
+            (AttributeState::BeforeValue, '"' | '\'') => AttributeState::Value(chars.next()),
+            // Unquoted; `<a href=>` has an empty value
+            (AttributeState::BeforeValue, _) => AttributeState::Value(None),
+            (AttributeState::Value(quote), c) if Some(c) == quote || (quote.is_none() && (c.is_whitespace() || c == '>')) => {
+                if quote.is_some() {
+                    chars.next(); // Consume closing quote
+                }
+                attributes.entry(std::mem::take(&mut name)).or_insert(std::mem::take(&mut value));
+                AttributeState::BeforeName
+            }
+            (AttributeState::Value(quote), _) => {
+                value.push(chars.next().unwrap());
+                AttributeState::Value(quote)
+            }
+        };
+    }
+    // The tag, or the input, ended partway through an attribute
+    if !name.is_empty() {
+        attributes.entry(name).or_insert(value);
+    }
+    attributes
+}
 
-    while let Some(&c) = chars.peek() {
-        if quote_char != '\0' && c == quote_char {
-            chars.next(); // Consume closing quote
-            break;
-        } else if quote_char == '\0' && (c.is_whitespace() || c == '>') {
+fn consume_text(chars: &mut Cursor) -> String {
+    let mut text = String::new();
+    while let Some(c) = chars.peek() {
+        if c == '<' {
             break;
-        } else {
-            value.push(chars.next().unwrap());
         }
+        text.push(chars.next().unwrap());
     }
-    value
+    text
 }
 
-fn consume_text(chars: &mut Peekable<Chars>) -> String {
+/// Text up to the `</tag_name` that ends a raw text element, matched
+/// ignoring case; the end tag is left for the main loop to close the element
+fn consume_raw_text(chars: &mut Cursor, tag_name: &str) -> String {
+    let end_tag = format!("</{}", tag_name);
     let mut text = String::new();
-    while let Some(&c) = chars.peek() {
+    while let Some(c) = chars.peek() {
         if c == '<' {
-            break;
+            let ahead: String = chars.clone().take(end_tag.chars().count()).collect();
+            if ahead.eq_ignore_ascii_case(&end_tag) {
+                break;
+            }
         }
-        text.push(chars.next().unwrap());
+        text.push(c);
+        chars.next();
     }
     text
 }
 
-fn consume_until(chars: &mut Peekable<Chars>, target: char) {
-    while let Some(&c) = chars.peek() {
+fn consume_until(chars: &mut Cursor, target: char) {
+    while let Some(c) = chars.peek() {
         if c == target {
             break;
         }
@@ -151,7 +441,7 @@ fn consume_until(chars: &mut Peekable<Chars>, target: char) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::dom::{NodeData, NodeType};
+    use crate::dom::NodeData;
 
     #[test]
     fn test_parse_simple_html() {
@@ -206,4 +496,162 @@ mod tests {
         assert_eq!(text_node.children.len(), 0);
         assert_eq!(text_node.parent, Some(h1_node_idx));
     }
+
+    #[test]
+    fn test_parse_self_closing_tags_as_siblings() {
+        let html = r#"<svg viewBox="0 0 24 24"><rect width="4" /><circle r="2"/></svg><p>After</p>"#;
+        let document = parse_html(html);
+
+        let svg_idx = document.nodes[document.root].children[0];
+        let svg = document.get_node(svg_idx).unwrap();
+        assert_eq!(svg.children.len(), 2);
+        assert_eq!(document.get_attribute(svg_idx, "viewBox"), Some(&"0 0 24 24".to_string()));
+        assert!(document.get_node(svg.children[0]).unwrap().children.is_empty());
+
+        // The paragraph after the svg is a sibling, not a descendant
+        assert_eq!(document.nodes[document.root].children.len(), 2);
+    }
+
+    #[test]
+    fn test_head_content_stays_in_head() {
+        let html = r#"<html><head><meta charset="utf-8"><title>T</title></head><link rel="icon" href="/i.png"><body><p>Hi</p></body></html>"#;
+        let document = parse_html(html);
+
+        let html_idx = document.nodes[document.root].children[0];
+        let [head_idx, body_idx] = document.nodes[html_idx].children[..] else { panic!("expected head and body") };
+        // The unclosed <meta> does not swallow the title, and the stray
+        // <link> after </head> joins the head
+        let head_tags: Vec<_> = document.nodes[head_idx]
+            .children
+            .iter()
+            .map(|&idx| match &document.nodes[idx].data {
+                Some(NodeData::Element(elem)) => elem.tag_name.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(head_tags, ["meta", "title", "link"]);
+        assert_eq!(document.nodes[body_idx].children.len(), 1);
+    }
+
+    #[test]
+    fn test_tag_and_attribute_names_are_case_insensitive() {
+        let html = r#"<DIV ID="outer"><P>One</p></Div><p>Two</p>"#;
+        let document = parse_html(html);
+
+        // Mismatched-case end tags still close their elements
+        let [div_idx, p_idx] = document.nodes[document.root].children[..] else { panic!("expected div and p") };
+        let Some(NodeData::Element(div)) = &document.nodes[div_idx].data else { panic!("expected an element") };
+        assert_eq!(div.tag_name, "div");
+        assert_eq!(document.get_attribute(div_idx, "id"), Some(&"outer".to_string()));
+        assert_eq!(document.text_content(p_idx), "Two");
+    }
+
+    #[test]
+    fn test_boolean_and_unquoted_attributes() {
+        let html = r#"<input disabled><div data-x>A</div><img src=foo><input type=checkbox checked required class = 'a b'/><p title="x" title="y" @click=go>B</p>"#;
+        let document = parse_html(html);
+        let elements: Vec<usize> = (0..document.nodes.len()).filter(|&idx| document.nodes[idx].node_type == NodeType::Element).collect();
+        let [input_idx, div_idx, img_idx, checkbox_idx, p_idx] = elements[..] else { panic!("expected five elements") };
+        let attribute = |idx: usize, name: &str| document.get_attribute(idx, name).map(String::as_str);
+
+        // Boolean attributes are present and empty, even right before `>`
+        assert_eq!(attribute(input_idx, "disabled"), Some(""));
+        assert_eq!(attribute(div_idx, "data-x"), Some(""));
+        assert_eq!(document.text_content(div_idx), "A");
+        assert_eq!(attribute(img_idx, "src"), Some("foo"));
+        // A valueless attribute does not swallow the next one
+        assert_eq!(attribute(checkbox_idx, "type"), Some("checkbox"));
+        assert_eq!(attribute(checkbox_idx, "checked"), Some(""));
+        assert_eq!(attribute(checkbox_idx, "required"), Some(""));
+        assert_eq!(attribute(checkbox_idx, "class"), Some("a b"));
+        // The first of a repeated name wins
+        assert_eq!(attribute(p_idx, "title"), Some("x"));
+        assert_eq!(attribute(p_idx, "@click"), Some("go"));
+        assert_eq!(document.text_content(p_idx), "B");
+        // Void elements written without `/>` stay empty, so what follows
+        // them are their siblings
+        assert_eq!(document.nodes[document.root].children, [input_idx, div_idx, img_idx, checkbox_idx, p_idx]);
+    }
+
+    #[test]
+    fn test_void_elements_close_and_end_tags_pop_to_their_element() {
+        // Given: Unslashed void elements among siblings, and an end tag
+        // closing an element with one still open inside it
+        let document = parse_html("<form><input><input><button>Go</button></form><p>a<br>b</p><p>c</p><div><span>d</div><i>e</i>");
+        let tag = |idx: usize| crate::forms::tag_name(&document, idx).unwrap_or("#text");
+        let children = |idx: usize| document.nodes[idx].children.iter().map(|&child| tag(child)).collect::<Vec<_>>();
+
+        // Then: Each void element is a sibling of what follows it, and the
+        // unclosed span ends with its div
+        let top = document.nodes[document.root].children.clone();
+        assert_eq!(top.iter().map(|&idx| tag(idx)).collect::<Vec<_>>(), ["form", "p", "p", "div", "i"]);
+        assert_eq!(children(top[0]), ["input", "input", "button"]);
+        assert_eq!(children(top[1]), ["#text", "br", "#text"]);
+        assert_eq!(document.text_content(top[2]), "c");
+        assert_eq!(children(top[3]), ["span"]);
+        assert_eq!(document.text_content(top[4]), "e");
+    }
+
+    #[test]
+    fn test_whitespace_is_kept_in_the_dom() {
+        let html = "<div>\n  <b>a</b> <i>b</i>\n</div><pre>\n  x\n    y\n</pre>";
+        let document = parse_html(html);
+
+        let [div_idx, pre_idx] = document.nodes[document.root].children[..] else { panic!("expected div and pre") };
+        assert_eq!(document.nodes[div_idx].children.len(), 5);
+        assert_eq!(document.text_content(div_idx), "\n  a b\n");
+        // Only the newline straight after `<pre>` is dropped
+        assert_eq!(document.text_content(pre_idx), "  x\n    y\n");
+    }
+
+    #[test]
+    fn test_character_references_are_decoded() {
+        let html = r#"<p title="Tom &amp; Jerry&#x27;s">&lt;b&gt; caf&eacute; &copy 2024</p><a href="/?a=1&copy=2">x</a><script>a &amp;&amp; b</script>"#;
+        let document = parse_html(html);
+
+        let [p_idx, a_idx, script_idx] = document.nodes[document.root].children[..] else { panic!("expected p, a and script") };
+        assert_eq!(document.get_attribute(p_idx, "title"), Some(&"Tom & Jerry's".to_string()));
+        assert_eq!(document.text_content(p_idx), "<b> café © 2024");
+        // A query string is not a reference, and script text is raw
+        assert_eq!(document.get_attribute(a_idx, "href"), Some(&"/?a=1&copy=2".to_string()));
+        assert_eq!(document.text_content(script_idx), "a &amp;&amp; b");
+    }
+
+    #[test]
+    fn test_chunked_parse_matches_whole_parse() {
+        let html = "<html><head><title>Ünïcode ✓</title><style>p > a { color: red; }</style></head>\
+            <body><p class='a b' id=\"x\" title='&quot;&#x2713;'>Hello &amp; <b>w&ouml;rld</b></p><img src=\"a>b.png\"/><script>if (a < b) {}</script></body></html>";
+        let whole = parse_html(html);
+
+        // Every split point, including inside tags, attributes and characters
+        for size in [1, 2, 3, 7, 64] {
+            let mut parser = Parser::new();
+            for chunk in html.as_bytes().chunks(size) {
+                parser.feed_bytes(chunk);
+            }
+            let chunked = parser.finish();
+            assert_eq!(chunked.nodes, whole.nodes, "chunks of {} bytes", size);
+        }
+    }
+
+    #[test]
+    fn test_parser_exposes_the_partial_tree() {
+        let mut parser = Parser::new();
+        parser.feed("<ul><li>One</li><li>Tw");
+
+        // The unfinished text waits for more input
+        assert_eq!(parser.document().text_content(parser.document().root), "One");
+
+        parser.feed("o</li></ul>");
+        let document = parser.finish();
+        assert_eq!(document.text_content(document.root), "OneTwo");
+    }
+
+    #[test]
+    fn test_parse_reader_streams_from_a_source() {
+        let html = "<div><p>Streamed</p></div>".repeat(5000);
+        let document = parse_reader(html.as_bytes()).unwrap();
+
+        assert_eq!(document.nodes, parse_html(&html).nodes);
+    }
 }
@@ -0,0 +1,323 @@
+//! PDF Export
+//! Renders a laid-out document into a single or multi-page PDF
+//!
+//! Each page is the same region a screenshot of it would capture, painted
+//! by the usual rasterizer and embedded as a compressed RGB image, so a PDF
+//! shows exactly what screenshots show. CSS pixels map to PDF points at
+//! 96 DPI, the CSS reference resolution.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use raqote::DrawTarget;
+
+use crate::css::ComputedStyle;
+use crate::dom::Document;
+use crate::fonts::WebFonts;
+use crate::geometry::Rect;
+use crate::images::ImageCache;
+use crate::render::render_scaled_region;
+
+/// PDF points per CSS pixel: 72 points per inch over 96 pixels per inch
+pub const POINTS_PER_CSS_PIXEL: f32 = 0.75;
+
+/// Device pixel ratio pages are rasterized at unless one is given; 2 keeps
+/// text legible when printed
+pub const DEFAULT_PDF_PIXEL_RATIO: f32 = 2.0;
+
+/// How a document is split into pages and how finely they are rasterized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PdfOptions {
+    /// Height of each page in CSS pixels; `None` puts the whole document on
+    /// one page
+    pub page_height: Option<f32>,
+    /// Device pixels per CSS pixel in the embedded page images
+    pub device_pixel_ratio: f32,
+}
+
+impl PdfOptions {
+    /// The whole document on a single page
+    pub fn single_page() -> Self {
+        PdfOptions { page_height: None, device_pixel_ratio: DEFAULT_PDF_PIXEL_RATIO }
+    }
+
+    /// Split the document into pages `page_height` CSS pixels tall
+    pub fn paged(page_height: f32) -> Self {
+        PdfOptions { page_height: Some(page_height), ..PdfOptions::single_page() }
+    }
+
+    pub fn with_device_pixel_ratio(mut self, ratio: f32) -> Self {
+        self.device_pixel_ratio = ratio;
+        self
+    }
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions::single_page()
+    }
+}
+
+/// One PDF page: its size in points and the image covering it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfPage {
+    pub width: f32,
+    pub height: f32,
+    pub image_width: u32,
+    pub image_height: u32,
+    /// 8-bit RGB pixels, row by row
+    pub rgb: Vec<u8>,
+}
+
+impl PdfPage {
+    /// A page `region` CSS pixels in size showing a rendering of it
+    pub fn from_draw_target(draw_target: &DrawTarget, region: Rect) -> Self {
+        // Pixels are premultiplied over the opaque white background, so
+        // alpha can be dropped
+        let rgb = draw_target
+            .get_data()
+            .iter()
+            .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+            .collect();
+        PdfPage {
+            width: region.width * POINTS_PER_CSS_PIXEL,
+            height: region.height * POINTS_PER_CSS_PIXEL,
+            image_width: draw_target.width() as u32,
+            image_height: draw_target.height() as u32,
+            rgb,
+        }
+    }
+}
+
+/// Split `content` into page regions from top to bottom; the last page is
+/// only as tall as what is left
+pub fn page_regions(content: Rect, page_height: Option<f32>) -> Vec<Rect> {
+    let page_height = match page_height {
+        Some(height) if height > 0.0 && height < content.height => height,
+        _ => return vec![content],
+    };
+    let mut regions = Vec::new();
+    let mut y = content.y;
+    while y < content.y + content.height {
+        let height = page_height.min(content.y + content.height - y);
+        regions.push(Rect::new(content.x, y, content.width, height));
+        y += page_height;
+    }
+    regions
+}
+
+/// Render the part of a laid-out document inside `content` to a PDF
+pub fn render_pdf(
+    document: &Document,
+    styles: &[ComputedStyle],
+    images: &ImageCache,
+    fonts: &WebFonts,
+    content: Rect,
+    options: &PdfOptions,
+) -> Vec<u8> {
+    let pages: Vec<PdfPage> = page_regions(content, options.page_height)
+        .into_iter()
+        .map(|region| {
+            let draw_target = render_scaled_region(document, styles, images, fonts, region, options.device_pixel_ratio);
+            PdfPage::from_draw_target(&draw_target, region)
+        })
+        .collect();
+    encode_pdf(&pages)
+}
+
+/// Write pages as a PDF 1.4 file, one full-page image per page
+pub fn encode_pdf(pages: &[PdfPage]) -> Vec<u8> {
+    // Objects 1 and 2 are the catalog and page tree; each page then takes
+    // three: the page, its content stream and its image
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 3 + i * 3).collect();
+    let mut writer = PdfWriter::new();
+    writer.object(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+    writer.object(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).into_bytes());
+
+    for (page, &id) in pages.iter().zip(&page_ids) {
+        writer.object(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                number(page.width),
+                number(page.height),
+                id + 2,
+                id + 1
+            )
+            .into_bytes(),
+        );
+        // Scale the unit-square image to the page
+        let content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", number(page.width), number(page.height));
+        writer.stream("", content.as_bytes());
+        let pixels = miniz_oxide::deflate::compress_to_vec_zlib(&page.rgb, 6);
+        writer.stream(
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Filter /FlateDecode",
+                page.image_width, page.image_height
+            ),
+            &pixels,
+        );
+    }
+    writer.finish()
+}
+
+/// Write PDF bytes to `path`
+pub fn save_pdf(bytes: &[u8], path: &Path) -> std::io::Result<PathBuf> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, bytes)?;
+    Ok(path.to_path_buf())
+}
+
+/// A number as PDF syntax: no exponent, at most two decimals
+fn number(value: f32) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Numbers objects in the order they are written and builds the
+/// cross-reference table that locates them
+struct PdfWriter {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        // The comment's high bytes mark the file as binary
+        PdfWriter { out: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(), offsets: Vec::new() }
+    }
+
+    fn object(&mut self, body: Vec<u8>) {
+        self.begin();
+        self.out.extend_from_slice(&body);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    /// A stream object; `dictionary` holds entries besides `/Length`
+    fn stream(&mut self, dictionary: &str, data: &[u8]) {
+        self.begin();
+        let separator = if dictionary.is_empty() { "" } else { " " };
+        self.out
+            .extend_from_slice(format!("<< {}{}/Length {} >>\nstream\n", dictionary, separator, data.len()).as_bytes());
+        self.out.extend_from_slice(data);
+        self.out.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn begin(&mut self) {
+        self.offsets.push(self.out.len());
+        self.out.extend_from_slice(format!("{} 0 obj\n", self.offsets.len()).as_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            xref
+        ));
+        self.out.extend_from_slice(table.as_bytes());
+        self.out
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::calculate_layout;
+
+    fn text(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    #[test]
+    fn test_page_regions() {
+        let content = Rect::new(0.0, 0.0, 100.0, 250.0);
+
+        assert_eq!(page_regions(content, None), vec![content]);
+        assert_eq!(page_regions(content, Some(300.0)), vec![content]);
+        assert_eq!(
+            page_regions(content, Some(100.0)),
+            vec![
+                Rect::new(0.0, 0.0, 100.0, 100.0),
+                Rect::new(0.0, 100.0, 100.0, 100.0),
+                Rect::new(0.0, 200.0, 100.0, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_pdf_structure() {
+        // Given: Two small pages
+        let page = PdfPage { width: 75.0, height: 37.5, image_width: 2, image_height: 1, rgb: vec![255, 0, 0, 0, 0, 255] };
+
+        // When: They are encoded
+        let bytes = encode_pdf(&[page.clone(), page]);
+        let pdf = text(&bytes);
+
+        // Then: The file has a header, two sized pages and a trailer
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/Type /Pages /Kids [3 0 R 6 0 R] /Count 2"), "{}", pdf);
+        assert_eq!(pdf.matches("/MediaBox [0 0 75 37.5]").count(), 2);
+        assert!(pdf.contains("q 75 0 0 37.5 0 0 cm /Im0 Do Q"));
+        assert!(pdf.ends_with("%%EOF\n"));
+
+        // And: Every cross-reference entry points at its object
+        let startxref: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert!(bytes[startxref..].starts_with(b"xref\n"));
+        let table = text(&bytes[startxref..]);
+        let entries: Vec<&str> = table.lines().skip(3).take_while(|line| line.ends_with(" n ")).collect();
+        assert_eq!(entries.len(), 8);
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            let object = format!("{} 0 obj\n", i + 1);
+            assert!(bytes[offset..].starts_with(object.as_bytes()), "Object {} at {}", i + 1, offset);
+        }
+    }
+
+    #[test]
+    fn test_render_pdf_embeds_rasterized_pages() {
+        // Given: A laid-out document 100px tall
+        let mut doc = Document::new();
+        let div = doc.create_element("div");
+        doc.append_child(doc.root, div);
+        calculate_layout(&mut doc, 80.0, 100.0);
+        let styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        let content = Rect::new(0.0, 0.0, 80.0, 100.0);
+
+        // When: It is split into 40px pages at 1x
+        let options = PdfOptions::paged(40.0).with_device_pixel_ratio(1.0);
+        let pdf = text(&render_pdf(&doc, &styles, &ImageCache::new(), &WebFonts::default(), content, &options));
+
+        // Then: There are three pages, the last one shorter, with images at
+        // the rendered size
+        assert!(pdf.contains("/Count 3"));
+        assert_eq!(pdf.matches("/MediaBox [0 0 60 30]").count(), 2);
+        assert_eq!(pdf.matches("/MediaBox [0 0 60 15]").count(), 1);
+        assert_eq!(pdf.matches("/Width 80 /Height 40").count(), 2);
+        assert!(pdf.contains("/Width 80 /Height 20"));
+    }
+
+    #[test]
+    fn test_page_pixels_compress_losslessly() {
+        let mut draw_target = DrawTarget::new(3, 2);
+        draw_target.get_data_mut().copy_from_slice(&[0xFFFF0000, 0xFF00FF00, 0xFF0000FF, 0xFFFFFFFF, 0xFF000000, 0xFF808080]);
+        let page = PdfPage::from_draw_target(&draw_target, Rect::new(0.0, 0.0, 3.0, 2.0));
+
+        assert_eq!(page.rgb, vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 128, 128, 128]);
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&page.rgb, 6);
+        assert_eq!(miniz_oxide::inflate::decompress_to_vec_zlib(&compressed).unwrap(), page.rgb);
+        assert_eq!((page.width, page.height), (2.25, 1.5));
+    }
+}
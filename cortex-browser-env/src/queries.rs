@@ -0,0 +1,417 @@
+//! Testing Library Queries
+//! Find elements the way users perceive them (by text, label, placeholder or
+//! test id) instead of by CSS selector
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use regex::Regex;
+use rquickjs::function::Opt;
+use rquickjs::{Ctx, Exception, Function, Object, Value};
+
+use crate::a11y::{self, find_by_id, labels_for, name_from_content_excluding, normalize};
+use crate::dom::{Document, NodeData};
+use crate::element::ElementRef;
+use crate::error::BrowserError;
+use crate::forms::tag_name;
+
+/// How query text is compared with element text
+///
+/// Element text is whitespace-normalized (trimmed, runs collapsed) first.
+#[derive(Debug, Clone)]
+pub enum TextMatch {
+    /// The whole text, case-sensitive
+    Exact(String),
+    /// Part of the text, case-insensitive (Testing Library's `exact: false`)
+    Substring(String),
+    Regex(Regex),
+}
+
+impl TextMatch {
+    pub fn exact(text: &str) -> Self {
+        TextMatch::Exact(normalize(text))
+    }
+
+    pub fn substring(text: &str) -> Self {
+        TextMatch::Substring(normalize(text).to_lowercase())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, BrowserError> {
+        Regex::new(pattern)
+            .map(TextMatch::Regex)
+            .map_err(|e| BrowserError::QueryError(format!("Invalid pattern /{}/: {}", pattern, e)))
+    }
+
+    /// Check normalized element text against the matcher
+    pub fn matches(&self, text: &str) -> bool {
+        let text = normalize(text);
+        match self {
+            TextMatch::Exact(expected) => text == *expected,
+            TextMatch::Substring(part) => text.to_lowercase().contains(part.as_str()),
+            TextMatch::Regex(regex) => regex.is_match(&text),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            TextMatch::Exact(text) | TextMatch::Substring(text) => format!("\"{}\"", text),
+            TextMatch::Regex(regex) => format!("/{}/", regex.as_str()),
+        }
+    }
+}
+
+impl From<&str> for TextMatch {
+    fn from(text: &str) -> Self {
+        TextMatch::exact(text)
+    }
+}
+
+/// What a query matches against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum By {
+    /// The element's own text: its direct text children
+    Text,
+    /// `<label>` text, `aria-label` or `aria-labelledby` text of a control
+    LabelText,
+    PlaceholderText,
+    /// The `data-testid` attribute
+    TestId,
+}
+
+impl By {
+    fn describe(&self) -> &'static str {
+        match self {
+            By::Text => "text",
+            By::LabelText => "label",
+            By::PlaceholderText => "placeholder",
+            By::TestId => "data-testid",
+        }
+    }
+}
+
+/// Text of an element's direct text children, as Testing Library matches it,
+/// so a parent does not also match its child's text
+fn own_text(document: &Document, idx: usize) -> String {
+    document.nodes[idx]
+        .children
+        .iter()
+        .filter_map(|&child| match &document.nodes[child].data {
+            Some(NodeData::Text(text)) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn matches_label(document: &Document, idx: usize, matcher: &TextMatch) -> bool {
+    if let Some(label) = document.get_attribute(idx, "aria-label") {
+        if matcher.matches(label) {
+            return true;
+        }
+    }
+    if let Some(ids) = document.get_attribute(idx, "aria-labelledby") {
+        let text = ids
+            .split_whitespace()
+            .filter_map(|id| find_by_id(document, id))
+            .map(|label| document.text_content(label))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if matcher.matches(&text) {
+            return true;
+        }
+    }
+    labels_for(document, idx)
+        .into_iter()
+        .any(|label| matcher.matches(&name_from_content_excluding(document, label, Some(idx))))
+}
+
+/// All elements matching, in document order
+pub fn query_all(document: &Document, by: By, matcher: &TextMatch) -> Vec<ElementRef> {
+    (0..document.nodes.len())
+        .filter(|&idx| match tag_name(document, idx) {
+            None => false,
+            Some("script" | "style") => false,
+            Some("label") if by == By::LabelText => false,
+            Some(_) => match by {
+                By::Text => matcher.matches(&own_text(document, idx)),
+                By::LabelText => matches_label(document, idx, matcher),
+                By::PlaceholderText => document.get_attribute(idx, "placeholder").is_some_and(|p| matcher.matches(p)),
+                By::TestId => document.get_attribute(idx, "data-testid").is_some_and(|t| matcher.matches(t)),
+            },
+        })
+        .map(ElementRef::new)
+        .collect()
+}
+
+/// The single match or `None`; several matches is an error
+pub fn query(document: &Document, by: By, matcher: &TextMatch) -> Result<Option<ElementRef>, BrowserError> {
+    match query_all(document, by, matcher).as_slice() {
+        [] => Ok(None),
+        [element] => Ok(Some(*element)),
+        matches => Err(BrowserError::QueryError(format!(
+            "Found {} elements with {} {}",
+            matches.len(),
+            by.describe(),
+            matcher.describe()
+        ))),
+    }
+}
+
+/// The single match; none or several is an error
+pub fn get(document: &Document, by: By, matcher: &TextMatch) -> Result<ElementRef, BrowserError> {
+    query(document, by, matcher)?.ok_or_else(|| {
+        BrowserError::NotFoundError(format!(
+            "Unable to find an element with {} {}",
+            by.describe(),
+            matcher.describe()
+        ))
+    })
+}
+
+/// Every match; none is an error
+pub fn get_all(document: &Document, by: By, matcher: &TextMatch) -> Result<Vec<ElementRef>, BrowserError> {
+    let matches = query_all(document, by, matcher);
+    if matches.is_empty() {
+        return Err(BrowserError::NotFoundError(format!(
+            "Unable to find an element with {} {}",
+            by.describe(),
+            matcher.describe()
+        )));
+    }
+    Ok(matches)
+}
+
+pub fn get_by_text(document: &Document, text: impl Into<TextMatch>) -> Result<ElementRef, BrowserError> {
+    get(document, By::Text, &text.into())
+}
+
+pub fn query_by_text(document: &Document, text: impl Into<TextMatch>) -> Result<Option<ElementRef>, BrowserError> {
+    query(document, By::Text, &text.into())
+}
+
+pub fn query_all_by_text(document: &Document, text: impl Into<TextMatch>) -> Vec<ElementRef> {
+    query_all(document, By::Text, &text.into())
+}
+
+pub fn get_all_by_text(document: &Document, text: impl Into<TextMatch>) -> Result<Vec<ElementRef>, BrowserError> {
+    get_all(document, By::Text, &text.into())
+}
+
+pub fn get_by_label_text(document: &Document, text: impl Into<TextMatch>) -> Result<ElementRef, BrowserError> {
+    get(document, By::LabelText, &text.into())
+}
+
+pub fn get_by_placeholder_text(document: &Document, text: impl Into<TextMatch>) -> Result<ElementRef, BrowserError> {
+    get(document, By::PlaceholderText, &text.into())
+}
+
+pub fn get_by_test_id(document: &Document, test_id: &str) -> Result<ElementRef, BrowserError> {
+    get(document, By::TestId, &TextMatch::exact(test_id))
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// Build a matcher from a JS string or RegExp, honouring `{ exact: false }`
+fn js_matcher<'js>(ctx: &Ctx<'js>, value: Value<'js>, options: Option<Object<'js>>) -> rquickjs::Result<TextMatch> {
+    if let Some(text) = value.as_string() {
+        let text = text.to_string()?;
+        let exact = options.and_then(|o| o.get::<_, Option<bool>>("exact").ok().flatten()).unwrap_or(true);
+        return Ok(if exact { TextMatch::exact(&text) } else { TextMatch::substring(&text) });
+    }
+    if let Some(regexp) = value.as_object() {
+        let source: String = regexp.get("source")?;
+        let flags: String = regexp.get("flags").unwrap_or_default();
+        let pattern = if flags.contains('i') { format!("(?i){}", source) } else { source };
+        return TextMatch::regex(&pattern).map_err(|e| Exception::throw_message(ctx, &e.to_string()));
+    }
+    Err(Exception::throw_message(ctx, "Expected a string or RegExp to match"))
+}
+
+/// The query globals, over the natives of `install_query_bindings`
+const QUERIES_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexQueries;
+    delete globalThis.__cortexQueries;
+    const wrap = found => Array.isArray(found) ? found.map(wrap) : found == null ? found : element(found);
+    for (const name of Object.keys(native)) {
+        globalThis[name] = (...args) => wrap(native[name](...args));
+    }
+})();
+"#;
+
+/// Expose queries to JavaScript as globals returning `Element` wrappers
+///
+/// `getByText`, `queryByText`, `queryAllByText`, `getAllByText`,
+/// `getByLabelText`, `getByPlaceholderText`, `getByTestId` and
+/// `getByRole(role, name?)`. Text matchers take a string or RegExp plus an
+/// optional `{ exact: false }`. Failed `get*` queries throw. Found nodes
+/// come back as `element(node)` does, tied to the document they were found
+/// in; see `handles`.
+pub fn install_query_bindings<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+
+    for (name, by) in [
+        ("getByText", By::Text),
+        ("getByLabelText", By::LabelText),
+        ("getByPlaceholderText", By::PlaceholderText),
+        ("getByTestId", By::TestId),
+    ] {
+        let doc = document.clone();
+        native.set(
+            name,
+            Function::new(ctx.clone(), move |ctx: Ctx<'js>, value: Value<'js>, options: Opt<Object<'js>>| {
+                let matcher = js_matcher(&ctx, value, options.0)?;
+                get(&doc.borrow(), by, &matcher)
+                    .map(|element| element.index as u32)
+                    .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+            })?,
+        )?;
+    }
+
+    let doc = document.clone();
+    native.set(
+        "queryByText",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, value: Value<'js>, options: Opt<Object<'js>>| {
+            let matcher = js_matcher(&ctx, value, options.0)?;
+            query(&doc.borrow(), By::Text, &matcher)
+                .map(|element| element.map(|e| e.index as u32))
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+        })?,
+    )?;
+
+    let doc = document.clone();
+    native.set(
+        "queryAllByText",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, value: Value<'js>, options: Opt<Object<'js>>| {
+            let matcher = js_matcher(&ctx, value, options.0)?;
+            Ok::<_, rquickjs::Error>(
+                query_all(&doc.borrow(), By::Text, &matcher).iter().map(|e| e.index as u32).collect::<Vec<_>>(),
+            )
+        })?,
+    )?;
+
+    let doc = document.clone();
+    native.set(
+        "getAllByText",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, value: Value<'js>, options: Opt<Object<'js>>| {
+            let matcher = js_matcher(&ctx, value, options.0)?;
+            get_all(&doc.borrow(), By::Text, &matcher)
+                .map(|elements| elements.iter().map(|e| e.index as u32).collect::<Vec<_>>())
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+        })?,
+    )?;
+
+    let doc = document;
+    native.set(
+        "getByRole",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, role: String, name: Opt<String>| {
+            a11y::get_by_role(&doc.borrow(), &role, name.0.as_deref())
+                .map(|idx| idx as u32)
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+        })?,
+    )?;
+
+    ctx.globals().set("__cortexQueries", native)?;
+    ctx.eval::<(), _>(QUERIES_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    fn fixture() -> Document {
+        parse_html(
+            r#"<form>
+                <h2 id="title">Create   account</h2>
+                <p>Already registered? <a href="/login">Sign in</a></p>
+                <label for="email">Email address</label>
+                <input id="email" placeholder="you@example.com"/>
+                <label>Password <input id="password" type="password"/></label>
+                <input id="search" aria-label="Search"/>
+                <button data-testid="submit-btn">Create account</button>
+                <script>var title = "Create account";</script>
+            </form>"#,
+        )
+    }
+
+    fn id_of(doc: &Document, element: ElementRef) -> String {
+        element.get_attribute(doc, "id").unwrap_or_default()
+    }
+
+    #[test]
+    fn test_text_queries_normalize_whitespace() {
+        let doc = fixture();
+
+        // Both the heading and the button read "Create account"; script text is ignored
+        let matches = query_all_by_text(&doc, "Create account");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(id_of(&doc, matches[0]), "title");
+        assert!(get_by_text(&doc, "Create account").is_err(), "Ambiguous get is an error");
+
+        // Only the element owning the text matches, not its ancestors
+        let link = get_by_text(&doc, "Sign in").unwrap();
+        assert_eq!(link.tag_name(&doc).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_substring_and_regex_matching() {
+        let doc = fixture();
+
+        assert_eq!(query_all_by_text(&doc, TextMatch::substring("already REGISTERED")).len(), 1);
+        assert_eq!(query_all_by_text(&doc, TextMatch::regex("^Create").unwrap()).len(), 2);
+        assert!(query_by_text(&doc, "Already").unwrap().is_none(), "Exact match needs the whole text");
+        assert!(TextMatch::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_label_placeholder_and_test_id() {
+        let doc = fixture();
+
+        assert_eq!(id_of(&doc, get_by_label_text(&doc, "Email address").unwrap()), "email");
+        assert_eq!(id_of(&doc, get_by_label_text(&doc, "Password").unwrap()), "password");
+        assert_eq!(id_of(&doc, get_by_label_text(&doc, "Search").unwrap()), "search");
+        assert_eq!(id_of(&doc, get_by_placeholder_text(&doc, "you@example.com").unwrap()), "email");
+        let button = get_by_test_id(&doc, "submit-btn").unwrap();
+        assert_eq!(button.tag_name(&doc).as_deref(), Some("button"));
+        assert!(matches!(get_by_test_id(&doc, "missing"), Err(BrowserError::NotFoundError(_))));
+    }
+
+    #[test]
+    fn test_js_bindings() {
+        let document = Rc::new(RefCell::new(fixture()));
+        let email = get_by_label_text(&document.borrow(), "Email address").unwrap().index;
+
+        let runtime = rquickjs::Runtime::new().unwrap();
+        let context = rquickjs::Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            install_query_bindings(&ctx, document.clone()).unwrap();
+            crate::element::install_element_bindings(&ctx, document.clone()).unwrap();
+
+            let result: String = ctx
+                .eval(
+                    r#"
+                    let threw = false;
+                    try { getByText("Nope"); } catch (e) { threw = e.message.includes("Unable to find"); }
+                    [
+                        getByLabelText("Email address").node,
+                        getByLabelText("Email address") instanceof Element,
+                        queryAllByText(/create/i).length,
+                        queryAllByText("sign", { exact: false }).length,
+                        queryByText("Nope"),
+                        getByRole("link", "Sign in") === getByText("Sign in"),
+                        threw,
+                    ].join(",")
+                    "#,
+                )
+                .unwrap();
+
+            assert_eq!(result, format!("{},true,2,1,,true,true", email));
+        });
+    }
+}
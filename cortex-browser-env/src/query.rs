@@ -1,70 +1,415 @@
-/// DOM Query Methods - querySelector and querySelectorAll
-/// Implements CSS selector matching for DOM elements
-
+//! DOM Query Methods - querySelector and querySelectorAll
+//! Implements CSS selector parsing and matching for DOM elements
+//!
+//! Supported: type selectors and `*`, `#id`, `.class` and attribute
+//! selectors (`[attr]`, and `=`, `~=`, `|=`, `^=`, `$=` and `*=` with an
+//! identifier or quoted string) and `:has()`, combined into compounds like
+//! `input.wide[type=text]`, joined by descendant (space), child (`>`),
+//! next-sibling (`+`) and subsequent-sibling (`~`) combinators, in
+//! comma-separated lists.
+//!
+//! `:has()` takes relative selectors, each starting with an implied
+//! descendant combinator or an explicit `>`, `+` or `~`: `li:has(> a)` is
+//! an item with a link child, `h2:has(+ p)` a heading followed by a
+//! paragraph. They match against the element `:has()` is on, which stands
+//! at their start as `Selector::Scope`.
+//!
+//! Stylesheet rules are parsed with `parse_style_selector`, which also
+//! takes `:hover`, matching the element under the simulated pointer and
+//! its ancestors.
+//!
+//! Tag and attribute names in selectors are atoms, so matching them is a
+//! pointer compare; a name no node has is freed with the selector.
+//!
+//! A selector that does not parse fails with a `SelectorError` giving the
+//! position, counted in characters from the start of the selector, what
+//! the parser expected there and what it found instead. The corpus in
+//! `selector_conformance` pins down both what parses and how it matches.
+
+use std::fmt;
+
+use crate::atom::Atom;
 use crate::dom::{Document, NodeType, NodeData};
 
-/// Simple CSS Selector representation
+/// Parsed CSS selector
 #[derive(Debug, Clone, PartialEq)]
 pub enum Selector {
-    Element(String),                    // div, span, p, etc.
+    Universal,                          // *
+    Element(Atom),                      // div, span, p, etc.
     Id(String),                         // #myid
     Class(String),                      // .myclass
-    Attribute(String, String),          // [attr="value"]
-    AttributeExists(String),            // [attr]
+    Attribute(Atom, String),            // [attr="value"]
+    AttributeExists(Atom),              // [attr]
+    AttributeMatch(Atom, AttributeOperator, String), // [attr^="value"], ...
+    Compound(Vec<Selector>),            // input.wide[type=text]
     Descendant(Box<Selector>, Box<Selector>), // parent descendant
     Child(Box<Selector>, Box<Selector>), // parent > child
+    NextSibling(Box<Selector>, Box<Selector>), // previous + next
+    SubsequentSibling(Box<Selector>, Box<Selector>), // earlier ~ later
+    List(Vec<Selector>),                // a, b
+    Has(Vec<Selector>),                 // :has(> img, + p)
+    Scope,                              // the element :has() is on
+    Hover,                              // :hover, in stylesheets
+}
+
+/// How an attribute selector compares the attribute's value, besides `=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `~=`: one of the whitespace-separated words
+    Includes,
+    /// `|=`: the value, or the value followed by `-`
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+impl AttributeOperator {
+    fn matches(self, actual: &str, value: &str) -> bool {
+        match self {
+            AttributeOperator::Includes => !value.is_empty() && actual.split_whitespace().any(|word| word == value),
+            AttributeOperator::DashMatch => actual == value || actual.strip_prefix(value).is_some_and(|rest| rest.starts_with('-')),
+            // An empty value never matches, as in browsers
+            AttributeOperator::Prefix => !value.is_empty() && actual.starts_with(value),
+            AttributeOperator::Suffix => !value.is_empty() && actual.ends_with(value),
+            AttributeOperator::Substring => !value.is_empty() && actual.contains(value),
+        }
+    }
 }
 
+/// Why a selector did not parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorError {
+    pub selector: String,
+    /// Characters from the start of the selector to the problem
+    pub position: usize,
+    /// What would have been valid there, e.g. `an attribute value`
+    pub expected: String,
+    /// What was there instead, e.g. `']'` or `end of selector`
+    pub found: String,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid selector '{}' at position {}: expected {}, found {}", self.selector, self.position, self.expected, self.found)
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
 /// Query result for a single element
 pub struct QueryResult {
     pub node_index: usize,
 }
 
-/// Parse a simple CSS selector (basic support for tag, #id, .class, [attr])
-pub fn parse_selector(selector: &str) -> Result<Selector, String> {
-    let selector = selector.trim();
+/// Parse a CSS selector or comma-separated selector list
+///
+/// Surrounding whitespace is ignored; positions in errors count from the
+/// first character of `selector` as given.
+pub fn parse_selector(selector: &str) -> Result<Selector, SelectorError> {
+    SelectorParser { source: selector, chars: selector.chars().collect(), pos: 0, hover: false }.list()
+}
+
+/// Parse a stylesheet rule's selector, which may also use `:hover`
+pub fn parse_style_selector(selector: &str) -> Result<Selector, SelectorError> {
+    SelectorParser { source: selector, chars: selector.chars().collect(), pos: 0, hover: true }.list()
+}
+
+/// What a compound starts with, for errors
+const COMPOUND_START: &str = "a tag, '*', '#id', '.class', '[attribute]' or ':has()'";
+
+/// Joins the selector so far to the compound after a combinator
+type Combinator = fn(Box<Selector>, Box<Selector>) -> Selector;
+
+struct SelectorParser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    /// Whether `:hover` is allowed
+    hover: bool,
+}
 
-    if selector.is_empty() {
-        return Err("Empty selector".to_string());
+impl SelectorParser<'_> {
+    /// A selector list, through the end of the source
+    fn list(mut self) -> Result<Selector, SelectorError> {
+        self.skip_whitespace();
+        let mut list = vec![self.complex()?];
+        while self.eat(',') {
+            self.skip_whitespace();
+            if self.at_end() {
+                return Err(self.error("a selector after ','"));
+            }
+            list.push(self.complex()?);
+        }
+        if !self.at_end() {
+            return Err(self.error("a combinator, ',' or end of selector"));
+        }
+        Ok(if list.len() == 1 { list.remove(0) } else { Selector::List(list) })
     }
 
-    // Handle ID selector (#id)
-    if selector.starts_with('#') {
-        let id = selector[1..].to_string();
-        return Ok(Selector::Id(id));
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
     }
 
-    // Handle class selector (.class)
-    if selector.starts_with('.') {
-        let class = selector[1..].to_string();
-        return Ok(Selector::Class(class));
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
     }
 
-    // Handle attribute selector ([attr="value"] or [attr])
-    if selector.starts_with('[') && selector.ends_with(']') {
-        let content = &selector[1..selector.len()-1];
-        if let Some(eq_pos) = content.find('=') {
-            let attr = content[..eq_pos].trim().to_string();
-            let value_part = &content[eq_pos+1..].trim();
-            let value = if value_part.starts_with('"') && value_part.ends_with('"') {
-                value_part[1..value_part.len()-1].to_string()
-            } else if value_part.starts_with('\'') && value_part.ends_with('\'') {
-                value_part[1..value_part.len()-1].to_string()
-            } else {
-                value_part.to_string()
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Skip whitespace, returning whether there was any
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn error(&self, expected: &str) -> SelectorError {
+        SelectorError { selector: self.source.to_string(), position: self.pos, expected: expected.to_string(), found: self.found() }
+    }
+
+    /// The character at the parser's position, quoted, or what it starts
+    /// when that reads better
+    fn found(&self) -> String {
+        match self.peek() {
+            None => "end of selector".to_string(),
+            Some(':') => {
+                let name: String = self.chars[self.pos..].iter().take_while(|&&c| c == ':' || is_name_char(c)).collect();
+                format!("pseudo-class '{}', which is not supported", name)
+            }
+            Some(c) if c.is_whitespace() => "whitespace".to_string(),
+            Some(c) => format!("'{}'", c),
+        }
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    /// Compounds joined by combinators, left to right
+    fn complex(&mut self) -> Result<Selector, SelectorError> {
+        let first = self.compound()?;
+        self.combinators(first)
+    }
+
+    /// Compounds joined to `selector` by combinators, up to the end of the
+    /// selector, a `,` or a `)`
+    fn combinators(&mut self, mut selector: Selector) -> Result<Selector, SelectorError> {
+        loop {
+            let spaced = self.skip_whitespace();
+            let combinator = match self.peek() {
+                Some(',' | ')') | None => return Ok(selector),
+                Some(_) => match self.combinator() {
+                    Some(combinator) => combinator?,
+                    None if spaced => Selector::Descendant,
+                    None => return Err(self.error("a combinator, ',' or end of selector")),
+                },
             };
-            return Ok(Selector::Attribute(attr, value));
-        } else {
-            return Ok(Selector::AttributeExists(content.to_string()));
+            selector = combinator(Box::new(selector), Box::new(self.compound()?));
+        }
+    }
+
+    /// An explicit combinator and the whitespace after it, if there is one
+    /// here; fails when no selector follows it
+    fn combinator(&mut self) -> Option<Result<Combinator, SelectorError>> {
+        let symbol = self.peek()?;
+        let combinator: Combinator = match symbol {
+            '>' => Selector::Child,
+            '+' => Selector::NextSibling,
+            '~' => Selector::SubsequentSibling,
+            _ => return None,
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+        if matches!(self.peek(), Some(',' | ')') | None) {
+            return Some(Err(self.error(&format!("a selector after '{}'", symbol))));
+        }
+        Some(Ok(combinator))
+    }
+
+    /// The relative selectors of a `:has(`, through its `)`
+    fn has(&mut self) -> Result<Selector, SelectorError> {
+        let mut list = vec![self.relative()?];
+        while self.eat(',') {
+            list.push(self.relative()?);
+        }
+        if !self.eat(')') {
+            return Err(self.error("')' to close ':has('"));
+        }
+        Ok(Selector::Has(list))
+    }
+
+    /// A selector starting from the scope, with an implied descendant
+    /// combinator unless it starts with its own
+    fn relative(&mut self) -> Result<Selector, SelectorError> {
+        self.skip_whitespace();
+        let combinator = match self.combinator() {
+            Some(combinator) => combinator?,
+            None => Selector::Descendant,
+        };
+        let first = combinator(Box::new(Selector::Scope), Box::new(self.compound()?));
+        self.combinators(first)
+    }
+
+    fn compound(&mut self) -> Result<Selector, SelectorError> {
+        let mut parts = Vec::new();
+        if self.eat('*') {
+            parts.push(Selector::Universal);
+        } else if self.peek().is_some_and(is_name_start) {
+            parts.push(Selector::Element(Atom::new(&self.identifier("a tag name")?)));
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    parts.push(Selector::Id(self.identifier("an identifier after '#'")?));
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    parts.push(Selector::Class(self.identifier("a class name after '.'")?));
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    parts.push(self.attribute()?);
+                }
+                Some(':') if self.starts_with(":has(") => {
+                    self.pos += ":has(".len();
+                    parts.push(self.has()?);
+                }
+                Some(':') if self.hover && self.starts_with(":hover") && !self.chars.get(self.pos + 6).is_some_and(|&c| is_name_char(c)) => {
+                    self.pos += ":hover".len();
+                    parts.push(Selector::Hover);
+                }
+                _ => break,
+            }
+        }
+        match parts.len() {
+            0 => Err(self.error(COMPOUND_START)),
+            1 => Ok(parts.remove(0)),
+            _ => Ok(Selector::Compound(parts)),
         }
     }
 
-    // Handle element/tag selector
-    Ok(Selector::Element(selector.to_string()))
+    /// The rest of an attribute selector, after its `[`
+    fn attribute(&mut self) -> Result<Selector, SelectorError> {
+        self.skip_whitespace();
+        let name = Atom::new(&self.identifier("an attribute name")?);
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Selector::AttributeExists(name));
+        }
+        let operator = match self.peek() {
+            Some('=') => None,
+            Some('~') => Some(AttributeOperator::Includes),
+            Some('|') => Some(AttributeOperator::DashMatch),
+            Some('^') => Some(AttributeOperator::Prefix),
+            Some('$') => Some(AttributeOperator::Suffix),
+            Some('*') => Some(AttributeOperator::Substring),
+            _ => return Err(self.error("']' or an operator (=, ~=, |=, ^=, $=, *=)")),
+        };
+        self.pos += 1;
+        if operator.is_some() && !self.eat('=') {
+            return Err(self.error("'=' to complete the operator"));
+        }
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => self.string(quote)?,
+            _ => self.identifier("an attribute value")?,
+        };
+        self.skip_whitespace();
+        if !self.eat(']') {
+            return Err(self.error("']'"));
+        }
+        Ok(match operator {
+            None => Selector::Attribute(name, value),
+            Some(operator) => Selector::AttributeMatch(name, operator, value),
+        })
+    }
+
+    /// A name that may start a CSS identifier, or fail expecting `expected`
+    fn identifier(&mut self, expected: &str) -> Result<String, SelectorError> {
+        let starts_name = |c: Option<char>| c.is_some_and(|c| is_name_start(c) || c == '\\');
+        let valid = match self.peek() {
+            Some('-') => matches!(self.chars.get(self.pos + 1), Some('-')) || starts_name(self.chars.get(self.pos + 1).copied()),
+            c => starts_name(c),
+        };
+        if !valid {
+            return Err(self.error(expected));
+        }
+        self.name()
+    }
+
+    /// Name characters, with `\` escaping the character after it
+    fn name(&mut self) -> Result<String, SelectorError> {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                let Some(&escaped) = self.chars.get(self.pos + 1) else {
+                    self.pos += 1;
+                    return Err(self.error("a character after '\\'"));
+                };
+                name.push(escaped);
+                self.pos += 2;
+            } else if is_name_char(c) {
+                name.push(c);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(name)
+    }
+
+    /// A quoted string, from its opening quote
+    fn string(&mut self, quote: char) -> Result<String, SelectorError> {
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error(&format!("a closing {}", quote))),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some('\\') if self.pos + 1 < self.chars.len() => {
+                    value.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
 }
 
 /// Check if a node matches a selector
-fn matches_selector(document: &Document, node_idx: usize, selector: &Selector) -> bool {
+pub fn matches_selector(document: &Document, node_idx: usize, selector: &Selector) -> bool {
+    matches_in_scope(document, node_idx, selector, None)
+}
+
+/// `matches_selector`, with `scope` as the element `Selector::Scope` is
+fn matches_in_scope(document: &Document, node_idx: usize, selector: &Selector, scope: Option<usize>) -> bool {
     let node = match document.get_node(node_idx) {
         Some(n) => n,
         None => return false,
@@ -81,8 +426,9 @@ fn matches_selector(document: &Document, node_idx: usize, selector: &Selector) -
     };
 
     match selector {
+        Selector::Universal => true,
         Selector::Element(tag) => {
-            element_data.tag_name.to_lowercase() == tag.to_lowercase()
+            element_data.tag_name == *tag
         },
         Selector::Id(id) => {
             element_data.attributes.get("id").map(|v| v == id).unwrap_or(false)
@@ -100,15 +446,48 @@ fn matches_selector(document: &Document, node_idx: usize, selector: &Selector) -
         Selector::AttributeExists(attr) => {
             element_data.attributes.contains_key(attr)
         },
-        Selector::Descendant(_, _) | Selector::Child(_, _) => {
-            // Handled in the tree traversal
+        Selector::AttributeMatch(attr, operator, value) => {
+            element_data.attributes.get(attr).is_some_and(|actual| operator.matches(actual, value))
+        },
+        Selector::Compound(parts) => parts.iter().all(|part| matches_in_scope(document, node_idx, part, scope)),
+        Selector::Descendant(ancestor, subject) => {
+            matches_in_scope(document, node_idx, subject, scope)
+                && std::iter::successors(node.parent, |&idx| document.nodes[idx].parent)
+                    .any(|idx| matches_in_scope(document, idx, ancestor, scope))
+        },
+        Selector::Child(parent, subject) => {
+            matches_in_scope(document, node_idx, subject, scope)
+                && node.parent.is_some_and(|idx| matches_in_scope(document, idx, parent, scope))
+        },
+        Selector::NextSibling(previous, subject) => {
+            matches_in_scope(document, node_idx, subject, scope)
+                && document.previous_element_sibling(node_idx).is_some_and(|idx| matches_in_scope(document, idx, previous, scope))
+        },
+        Selector::SubsequentSibling(earlier, subject) => {
+            matches_in_scope(document, node_idx, subject, scope)
+                && document.preceding_element_siblings(node_idx).any(|idx| matches_in_scope(document, idx, earlier, scope))
+        },
+        Selector::List(selectors) => selectors.iter().any(|selector| matches_in_scope(document, node_idx, selector, scope)),
+        Selector::Has(relative) => {
+            // Relative selectors reach the element's descendants, and its
+            // later siblings and theirs; each checks how it got there
+            let reachable = node.children.iter().copied().chain(document.following_element_siblings(node_idx));
+            let mut stack: Vec<usize> = reachable.collect();
+            while let Some(idx) = stack.pop() {
+                if relative.iter().any(|selector| matches_in_scope(document, idx, selector, Some(node_idx))) {
+                    return true;
+                }
+                stack.extend(&document.nodes[idx].children);
+            }
             false
-        }
+        },
+        Selector::Scope => scope == Some(node_idx),
+        Selector::Hover => document.is_hovered(node_idx),
     }
 }
 
-/// Find all elements matching a selector in the document
-pub fn query_selector_all(document: &Document, selector: &str) -> Result<Vec<usize>, String> {
+/// Find all elements matching a selector in the document, in document order
+pub fn query_selector_all(document: &Document, selector: &str) -> Result<Vec<usize>, SelectorError> {
     let parsed = parse_selector(selector)?;
     let mut results = Vec::new();
 
@@ -146,7 +525,7 @@ pub fn query_selector_all(document: &Document, selector: &str) -> Result<Vec<usi
 }
 
 /// Find the first element matching a selector
-pub fn query_selector(document: &Document, selector: &str) -> Result<Option<usize>, String> {
+pub fn query_selector(document: &Document, selector: &str) -> Result<Option<usize>, SelectorError> {
     let results = query_selector_all(document, selector)?;
     Ok(results.first().copied())
 }
@@ -167,14 +546,14 @@ mod tests {
     fn test_parse_element_selector() {
         let result = parse_selector("div");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Selector::Element("div".to_string()));
+        assert_eq!(result.unwrap(), Selector::Element(Atom::new("div")));
     }
 
     #[test]
     fn test_parse_element_selector_case_insensitive() {
         let result = parse_selector("DIV");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Selector::Element("DIV".to_string()));
+        assert_eq!(result.unwrap(), Selector::Element(Atom::new("div")));
     }
 
     #[test]
@@ -195,21 +574,21 @@ mod tests {
     fn test_parse_attribute_selector_with_value() {
         let result = parse_selector("[type=\"text\"]");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Selector::Attribute("type".to_string(), "text".to_string()));
+        assert_eq!(result.unwrap(), Selector::Attribute(Atom::new("type"), "text".to_string()));
     }
 
     #[test]
     fn test_parse_attribute_selector_with_single_quotes() {
         let result = parse_selector("[type='text']");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Selector::Attribute("type".to_string(), "text".to_string()));
+        assert_eq!(result.unwrap(), Selector::Attribute(Atom::new("type"), "text".to_string()));
     }
 
     #[test]
     fn test_parse_attribute_selector_exists() {
         let result = parse_selector("[disabled]");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Selector::AttributeExists("disabled".to_string()));
+        assert_eq!(result.unwrap(), Selector::AttributeExists(Atom::new("disabled")));
     }
 
     #[test]
@@ -222,7 +601,7 @@ mod tests {
     fn test_parse_selector_with_whitespace() {
         let result = parse_selector("  div  ");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Selector::Element("div".to_string()));
+        assert_eq!(result.unwrap(), Selector::Element(Atom::new("div")));
     }
 
     // ========================================================================
@@ -411,6 +790,26 @@ mod tests {
         assert_eq!(result.unwrap().len(), 0);
     }
 
+    #[test]
+    fn test_querying_unknown_names_keeps_no_atoms() {
+        // Given: A document
+        let mut doc = Document::new();
+        let html = doc.create_element("html");
+        doc.append_child(0, html);
+
+        // When: We query, then get and remove attributes, by names no node
+        // has
+        let result = query_selector_all(&doc, "x-query-only-tag, [data-query-only], [data-query-only-value=x]");
+        assert_eq!(doc.get_attribute(html, "data-get-only"), None);
+        crate::element::ElementRef::new(html).remove_attribute(&mut doc, "data-remove-only");
+
+        // Then: Nothing matched, and none of the names is still held
+        assert_eq!(result.unwrap(), Vec::<usize>::new());
+        for name in ["x-query-only-tag", "data-query-only", "data-query-only-value", "data-get-only", "data-remove-only"] {
+            assert_eq!(crate::atom::Atom::lookup(name), None, "{}", name);
+        }
+    }
+
     #[test]
     fn test_query_selector_all_case_insensitive_tags() {
         // Given: Elements with uppercase tags
@@ -561,4 +960,31 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap().len(), 1);
     }
+
+    #[test]
+    fn test_sibling_combinators_follow_dom_edits() {
+        // Given: A list of three items, with a text node between two
+        let mut doc = Document::new();
+        let list = doc.create_element("ul");
+        let items: Vec<usize> = (0..3).map(|_| doc.create_element("li")).collect();
+        let gap = doc.create_text_node(" ");
+        doc.append_child(0, list);
+        doc.append_child(list, items[0]);
+        doc.append_child(list, gap);
+        doc.append_child(list, items[1]);
+        doc.append_child(list, items[2]);
+        doc.set_attribute(items[2], "class", "last");
+
+        // When: The last item moves to the front and the first is removed
+        doc.insert_child(list, 0, items[2]);
+        doc.remove_child(items[0]);
+
+        // Then: Positions and sibling matches follow, skipping the text
+        assert_eq!(doc.child_index(items[1]), Some(2));
+        assert_eq!(doc.child_index(items[0]), None);
+        assert_eq!(doc.previous_element_sibling(items[1]), Some(items[2]));
+        assert_eq!(query_selector_all(&doc, ".last + li").unwrap(), [items[1]]);
+        assert_eq!(query_selector_all(&doc, "li ~ .last").unwrap(), Vec::<usize>::new());
+        assert_eq!(query_selector_all(&doc, "li:has(+ li)").unwrap(), [items[2]]);
+    }
 }
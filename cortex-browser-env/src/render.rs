@@ -1,6 +1,28 @@
-use raqote::{DrawTarget, Source, SolidSource, DrawOptions};
+use std::rc::Rc;
+
+use fontdue::Metrics;
+use raqote::{DrawTarget, Source, SolidSource, DrawOptions, Image, PathBuilder, StrokeStyle, Transform, Vector, Winding};
 use super::dom::{Document, Layout, NodeData, ElementData};
-use super::css::ComputedStyle;
+use super::css::{BoxShadow, ComputedStyle, TextDecoration, TextOverflow, TextTransform, WhiteSpace};
+use super::display_list::{DisplayList, GlyphStyle, PaintCommand};
+use super::emoji::{is_emoji, is_emoji_modifier};
+use super::fonts::{default_decoration_metrics, default_font, default_line_metrics, WebFont, WebFonts};
+use super::geometry::{EdgeSizes, Rect};
+use super::images::{image_source, DecodedImage, ImageCache};
+use super::inline::{self, TextFragment};
+use super::lists::{ListMarker, MarkerKind};
+use super::hit_test;
+use super::layout::ROOT_FONT_SIZE;
+use super::style;
+use super::svg::paint_svg;
+use super::text::{break_lines, WordBreaking, NO_BREAK_SPACE};
+use super::transform::{self, transform_rect};
+
+/// Largest blur radius and spread, in pixels, a box shadow is drawn with
+const MAX_SHADOW_EXTENT: f32 = 256.0;
+
+/// Bezier control point distance for approximating a quarter circle
+const KAPPA: f32 = 0.552_284_8;
 
 /// Render a document to a DrawTarget at the specified dimensions (headless)
 pub fn render_document(
@@ -8,8 +30,69 @@ pub fn render_document(
     width: i32,
     height: i32,
 ) -> DrawTarget {
-    let mut dt = DrawTarget::new(width, height);
-    let options = DrawOptions::new();
+    let default_styles = vec![ComputedStyle::default(); document.nodes.len()];
+    render_styled_document(document, &default_styles, &ImageCache::new(), width, height)
+}
+
+/// Render a document with computed styles and preloaded images
+///
+/// Images are drawn from `images` only; load them first with
+/// `images::load_document_images`. Missing images leave their box empty.
+pub fn render_styled_document(
+    document: &Document,
+    styles: &[ComputedStyle],
+    images: &ImageCache,
+    width: i32,
+    height: i32,
+) -> DrawTarget {
+    let viewport = Rect::new(0.0, 0.0, width as f32, height as f32);
+    render_styled_region(document, styles, images, viewport)
+}
+
+/// Render only part of a laid-out document (headless)
+pub fn render_region(document: &Document, region: Rect) -> DrawTarget {
+    let default_styles = vec![ComputedStyle::default(); document.nodes.len()];
+    render_styled_region(document, &default_styles, &ImageCache::new(), region)
+}
+
+/// Render the part of a laid-out document inside `region`
+///
+/// The target covers `region` rounded out to whole pixels, with the page
+/// translated so the region's origin lands at (0, 0). Pixels match the same
+/// area of a full-page render, but only the region is rasterized, which keeps
+/// element screenshots and scrolled captures of tall pages cheap.
+pub fn render_styled_region(
+    document: &Document,
+    styles: &[ComputedStyle],
+    images: &ImageCache,
+    region: Rect,
+) -> DrawTarget {
+    render_scaled_region(document, styles, images, &WebFonts::default(), region, 1.0)
+}
+
+/// Render the part of a laid-out document inside `region` at a device pixel
+/// ratio
+///
+/// Layout stays in CSS pixels; only rasterization is scaled, so at a ratio
+/// of 2 the target has twice the region's width and height in device pixels,
+/// like a screenshot on a retina display.
+pub fn render_scaled_region(
+    document: &Document,
+    styles: &[ComputedStyle],
+    images: &ImageCache,
+    fonts: &WebFonts,
+    region: Rect,
+    device_pixel_ratio: f32,
+) -> DrawTarget {
+    render_display_list(&build_display_list(document, styles, images, fonts), region, device_pixel_ratio)
+}
+
+/// Rasterize recorded paint commands over white, covering `region` at a
+/// device pixel ratio as `render_scaled_region` does
+pub fn render_display_list(list: &DisplayList, region: Rect, device_pixel_ratio: f32) -> DrawTarget {
+    let (_, _, width, height) = region.scale(device_pixel_ratio).round_out();
+    tracing::debug!(width, height, device_pixel_ratio, "Painting region");
+    let mut dt = DrawTarget::new(width.max(1), height.max(1));
 
     // Fill background with white
     dt.fill_rect(
@@ -18,94 +101,369 @@ pub fn render_document(
         width as f32,
         height as f32,
         &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
-        &options,
+        &DrawOptions::new(),
     );
+    dt.set_transform(&page_transform(region, device_pixel_ratio));
+    rasterize(&mut dt, list);
+    dt.set_transform(&Transform::identity());
+    dt
+}
 
-    // Render root element
-    if !document.nodes.is_empty() {
-        let root_idx = document.root;
-        let default_styles = vec![ComputedStyle::default(); document.nodes.len()];
-        render_node(&mut dt, document, root_idx, &default_styles);
+/// Map page coordinates to the pixels of a render of `region`
+fn page_transform(region: Rect, device_pixel_ratio: f32) -> Transform {
+    let (x, y, _, _) = region.scale(device_pixel_ratio).round_out();
+    Transform::scale(device_pixel_ratio, device_pixel_ratio).then_translate(Vector::new(-x as f32, -y as f32))
+}
+
+/// Re-rasterize only the damaged areas of a render of `region`
+///
+/// `dt` must hold a render of `region` at `device_pixel_ratio` whose
+/// pixels differ from a render of `list` only inside `damage` (see
+/// `DisplayList::damage_since`). Each damaged area is cleared to white and
+/// repainted with just the commands that reach it, leaving `dt` equal to a
+/// full render of `list`.
+pub fn repaint_damage(dt: &mut DrawTarget, list: &DisplayList, region: Rect, device_pixel_ratio: f32, damage: &[Rect]) {
+    let transform = page_transform(region, device_pixel_ratio);
+    let (origin_x, origin_y, _, _) = region.scale(device_pixel_ratio).round_out();
+    for area in damage {
+        // Whole device pixels, so the clip edge needs no anti-aliasing
+        let (x, y, width, height) = area.scale(device_pixel_ratio).round_out();
+        let pixels = Rect::new((x - origin_x) as f32, (y - origin_y) as f32, width as f32, height as f32);
+        let Some(pixels) = pixels.intersection(&Rect::new(0.0, 0.0, dt.width() as f32, dt.height() as f32)) else {
+            continue;
+        };
+
+        let mut pb = PathBuilder::new();
+        pb.rect(pixels.x, pixels.y, pixels.width, pixels.height);
+        dt.push_clip(&pb.finish());
+        dt.fill_rect(pixels.x, pixels.y, pixels.width, pixels.height, &solid_source(0xFFFFFFFF), &DrawOptions::new());
+        // Commands reaching any pixel of the rounded-out area repaint it
+        let reach = pixels.translate(origin_x as f32, origin_y as f32).scale(1.0 / device_pixel_ratio);
+        dt.set_transform(&transform);
+        rasterize_where(dt, list, |bounds| bounds.intersects(&reach));
+        dt.set_transform(&Transform::identity());
+        dt.pop_clip();
     }
+}
 
-    dt
+/// Keeps the last frame of a region and repaints only what changes
+///
+/// Each `update` diffs the new display list against the previous one, so a
+/// frame costs in proportion to the damage rather than the viewport size.
+pub struct IncrementalRenderer {
+    region: Rect,
+    device_pixel_ratio: f32,
+    list: DisplayList,
+    target: DrawTarget,
 }
 
-/// Recursively render a node and its children
-fn render_node(
-    dt: &mut DrawTarget,
+impl IncrementalRenderer {
+    /// Start from a blank white frame, as for an empty display list
+    pub fn new(region: Rect, device_pixel_ratio: f32) -> Self {
+        let (_, _, width, height) = region.scale(device_pixel_ratio).round_out();
+        let mut target = DrawTarget::new(width.max(1), height.max(1));
+        target.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        IncrementalRenderer { region, device_pixel_ratio, list: DisplayList::new(), target }
+    }
+
+    /// Areas in page coordinates that `list` would repaint
+    pub fn damage(&self, list: &DisplayList) -> Vec<Rect> {
+        list.damage_since(&self.list)
+            .into_iter()
+            .filter_map(|area| area.intersection(&self.region))
+            .collect()
+    }
+
+    /// Bring the frame up to date with `list`, returning the areas repainted
+    pub fn update(&mut self, list: DisplayList) -> Vec<Rect> {
+        let damage = self.damage(&list);
+        repaint_damage(&mut self.target, &list, self.region, self.device_pixel_ratio, &damage);
+        self.list = list;
+        damage
+    }
+
+    /// The current frame
+    pub fn target(&self) -> &DrawTarget {
+        &self.target
+    }
+}
+
+/// Record the paint commands for a laid-out document, in paint order
+///
+/// Images come from `images` only; missing ones paint nothing. Text whose
+/// `font-family` names a family in `fonts` is drawn with that font's glyphs.
+pub fn build_display_list(document: &Document, styles: &[ComputedStyle], images: &ImageCache, fonts: &WebFonts) -> DisplayList {
+    let mut list = DisplayList::new();
+    if !document.nodes.is_empty() {
+        paint_node(&mut list, document, document.root, styles, images, fonts);
+    }
+    list
+}
+
+/// Record a node and its children
+fn paint_node(
+    list: &mut DisplayList,
     document: &Document,
     node_idx: usize,
     styles: &[ComputedStyle],
+    images: &ImageCache,
+    fonts: &WebFonts,
 ) {
     let node = &document.nodes[node_idx];
-
-    if let Some(ref layout) = node.layout {
-        // Render background
+    let mut clip_pushed = false;
+    let mut transform_pushed = false;
+    // Hidden boxes keep their space and their children, which may be
+    // visible again, but paint nothing of their own
+    let visible = style::inherited_visibility(document, styles, node_idx).is_visible();
+
+    if let Some(layout) = document.layout(node_idx) {
+        // Paint background
         if let Some(style) = styles.get(node_idx) {
-            if let Some(ref bg_color) = style.background_color {
-                render_background(dt, layout, bg_color);
+            // A transform moves the box and everything inside it
+            if let Some(transform) = transform::element_matrix(style, layout.border_box()) {
+                list.push(PaintCommand::PushTransform { transform });
+                transform_pushed = true;
+            }
+
+            let radii = resolve_border_radii(style, layout);
+            let rounded = radii.iter().any(|r| *r > 0.0);
+
+            if visible {
+                // Outer shadows, last layer first so the first ends up on top
+                for shadow in style.box_shadows.iter().rev().filter(|s| !s.inset) {
+                    list.push(PaintCommand::BoxShadow { rect: layout.border_box(), radii, shadow: shadow.clone() });
+                }
+
+                if let Some(ref bg_color) = style.background_color {
+                    paint_background(list, layout, radii, bg_color);
+                }
+
+                // <img> content or background image
+                if let Some(image) = image_source(document, node_idx, Some(style)).and_then(|url| images.get(&url)) {
+                    paint_image(list, layout, radii, image);
+                }
+
+                // An iframe's frame, rendered by its own page
+                if let Some(frame) = document.frame_image(node_idx) {
+                    list.push(PaintCommand::Image { rect: layout.content_box(), image: Rc::new(frame.clone()) });
+                }
+
+                // Inset shadows, over the background and under the border
+                let padding_radii = inset_radii(radii, layout.border);
+                for shadow in style.box_shadows.iter().rev().filter(|s| s.inset) {
+                    list.push(PaintCommand::BoxShadow { rect: layout.padding_box(), radii: padding_radii, shadow: shadow.clone() });
+                }
+
+                if let Some(ref border_color) = style.border_color {
+                    paint_border(list, layout, radii, border_color);
+                }
+
+                // An outside marker hangs past the box, so it goes before the clip
+                if let Some(marker) = document.list_marker(node_idx) {
+                    paint_list_marker(list, marker, inherited_color(document, node_idx, styles));
+                }
             }
 
-            // Render border
-            if let Some(ref border_color) = style.border_color {
-                render_border(dt, layout, border_color);
+            // Children are clipped to the rounded content box, or to the
+            // padding box when overflow is not visible
+            if rounded {
+                list.push(PaintCommand::PushClip {
+                    rect: layout.content_box(),
+                    radii: inset_radii(radii, layout.border + layout.padding),
+                });
+                clip_pushed = true;
+            } else if style.overflow.is_some_and(|overflow| overflow.clips()) {
+                list.push(PaintCommand::PushClip { rect: layout.padding_box(), radii: [0.0; 4] });
+                clip_pushed = true;
             }
         }
 
-        // Render text content
+        // Paint text content
         if let Some(ref data) = node.data {
             if let NodeData::Text(text) = data {
-                // Check parent element tag for styling
-                render_text_with_styling(dt, layout, text, node_idx, document);
+                if visible {
+                    let text_style = resolve_text_style(document, node_idx, styles, fonts);
+                    match document.text_fragments(node_idx) {
+                        Some(fragments) => paint_text_fragments(list, document, node_idx, layout, fragments, &text_style),
+                        None => paint_body_text(list, layout, text, &text_style),
+                    }
+                }
             } else if let NodeData::Element(elem) = data {
-                // Render element attributes as text (label, placeholder, value, etc.)
-                render_element_text(dt, layout, elem);
+                if elem.tag_name == "svg" {
+                    // SVG children are shapes, not boxes, so the svg module
+                    // paints the whole subtree
+                    if visible {
+                        paint_svg(list, document, node_idx, layout);
+                    }
+                    if clip_pushed {
+                        list.push(PaintCommand::PopClip);
+                    }
+                    if transform_pushed {
+                        list.push(PaintCommand::PopTransform);
+                    }
+                    return;
+                }
+                // Element attributes as text (label, placeholder, value, etc.)
+                if visible {
+                    paint_element_text(list, layout, elem);
+                }
             }
         }
     }
 
-    // Recursively render children
-    let children = document.nodes[node_idx].children.clone();
-    for child_idx in children {
-        render_node(dt, document, child_idx, styles);
+    // Raised siblings paint later, so they end up on top
+    for child_idx in hit_test::paint_order(document, styles, node_idx) {
+        paint_node(list, document, child_idx, styles, images, fonts);
+    }
+
+    if clip_pushed {
+        list.push(PaintCommand::PopClip);
+    }
+    if transform_pushed {
+        list.push(PaintCommand::PopTransform);
     }
 }
 
-/// Render element background with solid color
-fn render_background(dt: &mut DrawTarget, layout: &Layout, color: &str) {
-    let argb = parse_color_to_argb(color);
-    let (a, r, g, b) = argb_to_components(argb);
-    let source = Source::Solid(SolidSource::from_unpremultiplied_argb(a, r, g, b));
-    let options = DrawOptions::new();
+/// Record an element background, rounded when any radius is set
+fn paint_background(list: &mut DisplayList, layout: &Layout, radii: [f32; 4], color: &str) {
+    list.push(PaintCommand::Rect { rect: layout.border_box(), radii, color: parse_color_to_argb(color) });
+}
 
-    dt.fill_rect(
-        layout.x,
-        layout.y,
-        layout.width,
-        layout.height,
-        &source,
-        &options,
-    );
+/// Record an image scaled to fill the padding box, clipped to rounded corners
+fn paint_image(list: &mut DisplayList, layout: &Layout, radii: [f32; 4], image: Rc<DecodedImage>) {
+    let target = layout.padding_box();
+    if target.is_empty() || image.width == 0 || image.height == 0 {
+        return;
+    }
+
+    let rounded = radii.iter().any(|r| *r > 0.0);
+    if rounded {
+        list.push(PaintCommand::PushClip { rect: target, radii: inset_radii(radii, layout.border) });
+    }
+    list.push(PaintCommand::Image { rect: target, image });
+    if rounded {
+        list.push(PaintCommand::PopClip);
+    }
 }
 
-/// Render element border
-fn render_border(dt: &mut DrawTarget, layout: &Layout, color: &str) {
-    if layout.border_width <= 0.0 {
+/// Record an element border
+fn paint_border(list: &mut DisplayList, layout: &Layout, radii: [f32; 4], color: &str) {
+    if layout.border.top <= 0.0 {
         return;
     }
+    list.push(PaintCommand::Border {
+        rect: layout.border_box(),
+        width: layout.border.top,
+        radii,
+        color: parse_color_to_argb(color),
+    });
+}
+
+/// Play paint commands onto a draw target, on top of what it holds
+///
+/// Commands are in page coordinates; set the target's transform first to
+/// paint a region or scale for a device pixel ratio.
+pub fn rasterize(dt: &mut DrawTarget, list: &DisplayList) {
+    rasterize_where(dt, list, |_| true);
+}
+
+/// Draw the commands whose bounds in page coordinates pass `keep`; clips
+/// and transforms always apply
+fn rasterize_where(dt: &mut DrawTarget, list: &DisplayList, keep: impl Fn(&Rect) -> bool) {
+    let options = DrawOptions::new();
+    // Target transforms to restore, and the pushed transforms combined
+    let mut saved: Vec<Transform> = Vec::new();
+    let mut pushed: Vec<Transform> = Vec::new();
+    for command in list {
+        let page_bounds = command.bounds().map(|bounds| match pushed.last() {
+            Some(transform) => transform_rect(transform, bounds),
+            None => bounds,
+        });
+        if page_bounds.is_some_and(|bounds| !keep(&bounds)) {
+            continue;
+        }
+        match command {
+            PaintCommand::Rect { rect, radii, color } => {
+                let source = solid_source(*color);
+                if radii.iter().any(|r| *r > 0.0) {
+                    let mut pb = PathBuilder::new();
+                    rounded_rect_path(&mut pb, *rect, *radii);
+                    dt.fill(&pb.finish(), &source, &options);
+                } else {
+                    dt.fill_rect(rect.x, rect.y, rect.width, rect.height, &source, &options);
+                }
+            }
+            PaintCommand::Border { rect, width, radii, color } => {
+                if radii.iter().any(|r| *r > 0.0) {
+                    draw_rounded_border(dt, *rect, *width, *radii, *color);
+                } else {
+                    draw_border(dt, *rect, *width, *color);
+                }
+            }
+            PaintCommand::BoxShadow { rect, radii, shadow } => draw_box_shadow(dt, *rect, *radii, shadow),
+            PaintCommand::Text { x, y, text, glyph, font } => {
+                let source = solid_source(glyph.color);
+                let mut x = *x;
+                // Emoji modifiers and joiners draw nothing of their own
+                for ch in text.chars().filter(|&ch| !is_emoji_modifier(ch)) {
+                    // No-break spaces look like regular spaces
+                    let shape = if ch == NO_BREAK_SPACE { ' ' } else { ch };
+                    // Synthetic bold: the same strokes again, a pixel over
+                    let offsets: &[f32] = if glyph.bold { &[0.0, 1.0] } else { &[0.0] };
+                    for offset in offsets {
+                        match font {
+                            Some(font) => draw_font_glyph(dt, font.rasterize(shape, glyph.height), x + offset, *y, glyph, &options),
+                            None => draw_builtin_char(dt, shape, x + offset, *y, glyph, &source, &options),
+                        }
+                    }
+                    x += glyph.advance(ch);
+                }
+            }
+            PaintCommand::Image { rect, image } => {
+                let source = Image { width: image.width as i32, height: image.height as i32, data: &image.pixels };
+                dt.draw_image_with_size_at(rect.width, rect.height, rect.x, rect.y, &source, &options);
+            }
+            PaintCommand::FillPath { path, color } => dt.fill(path, &solid_source(*color), &options),
+            PaintCommand::StrokePath { path, width, color } => {
+                let style = StrokeStyle { width: *width, ..Default::default() };
+                dt.stroke(path, &solid_source(*color), &style, &options);
+            }
+            PaintCommand::PushClip { rect, radii } => {
+                let mut pb = PathBuilder::new();
+                if radii.iter().any(|r| *r > 0.0) {
+                    rounded_rect_path(&mut pb, *rect, *radii);
+                } else {
+                    pb.rect(rect.x, rect.y, rect.width, rect.height);
+                }
+                dt.push_clip(&pb.finish());
+            }
+            PaintCommand::PopClip => dt.pop_clip(),
+            PaintCommand::PushTransform { transform } => {
+                let current = *dt.get_transform();
+                dt.set_transform(&transform.then(&current));
+                pushed.push(pushed.last().map_or(*transform, |outer| transform.then(outer)));
+                saved.push(current);
+            }
+            PaintCommand::PopTransform => {
+                if let Some(previous) = saved.pop() {
+                    dt.set_transform(&previous);
+                }
+                pushed.pop();
+            }
+        }
+    }
+}
 
-    let argb = parse_color_to_argb(color);
+fn solid_source(argb: u32) -> Source<'static> {
     let (a, r, g, b) = argb_to_components(argb);
-    let source = Source::Solid(SolidSource::from_unpremultiplied_argb(a, r, g, b));
+    Source::Solid(SolidSource::from_unpremultiplied_argb(a, r, g, b))
+}
 
-    // Draw border by drawing filled rectangles for each edge
+/// Draw a square border as one filled rectangle per edge
+fn draw_border(dt: &mut DrawTarget, rect: Rect, border_width: f32, color: u32) {
+    let source = solid_source(color);
     let options = DrawOptions::new();
-    let border_width = layout.border_width;
-    let x = layout.x;
-    let y = layout.y;
-    let w = layout.width;
-    let h = layout.height;
+    let Rect { x, y, width: w, height: h } = rect;
 
     // Top border
     dt.fill_rect(x, y, w, border_width, &source, &options);
@@ -120,113 +478,623 @@ fn render_border(dt: &mut DrawTarget, layout: &Layout, color: &str) {
     dt.fill_rect(x, y, border_width, h, &source, &options);
 }
 
-/// Render text with styling based on parent element
-fn render_text_with_styling(
-    dt: &mut DrawTarget,
-    layout: &Layout,
-    text: &str,
-    node_idx: usize,
-    document: &Document,
-) {
-    // Determine parent element type for styling
-    let mut parent_tag = "";
-    for (_i, node) in document.nodes.iter().enumerate() {
-        if node.children.contains(&node_idx) {
-            if let Some(NodeData::Element(elem)) = &node.data {
-                parent_tag = &elem.tag_name;
-            }
-            break;
+/// Resolve the per-corner border radii of a box in pixels
+///
+/// Corners are ordered top-left, top-right, bottom-right, bottom-left.
+/// Percentages resolve against the box width, and the radii are scaled down
+/// together when adjacent corners would overlap, as browsers do.
+fn resolve_border_radii(style: &ComputedStyle, layout: &Layout) -> [f32; 4] {
+    let resolve = |value: &Option<super::css::CSSValue>| {
+        value.as_ref().map(|v| v.as_pixels(layout.rect.width).max(0.0)).unwrap_or(0.0)
+    };
+    let radii = [
+        resolve(&style.border_top_left_radius),
+        resolve(&style.border_top_right_radius),
+        resolve(&style.border_bottom_right_radius),
+        resolve(&style.border_bottom_left_radius),
+    ];
+    clamp_radii(radii, layout.rect.width, layout.rect.height)
+}
+
+/// Scale radii so that adjacent corners never exceed the side they share
+fn clamp_radii(radii: [f32; 4], width: f32, height: f32) -> [f32; 4] {
+    let [tl, tr, br, bl] = radii;
+    let mut scale: f32 = 1.0;
+    for (side, sum) in [(width, tl + tr), (height, tr + br), (width, br + bl), (height, bl + tl)] {
+        if sum > 0.0 {
+            scale = scale.min(side.max(0.0) / sum);
         }
     }
+    radii.map(|r| r * scale)
+}
+
+/// Build a closed rounded-rectangle path with the given corner radii
+fn rounded_rect_path(pb: &mut PathBuilder, rect: Rect, radii: [f32; 4]) {
+    let Rect { x, y, width: w, height: h } = rect;
+    let [tl, tr, br, bl] = clamp_radii(radii, w, h);
+
+    pb.move_to(x + tl, y);
+    pb.line_to(x + w - tr, y);
+    pb.cubic_to(x + w - tr + tr * KAPPA, y, x + w, y + tr - tr * KAPPA, x + w, y + tr);
+    pb.line_to(x + w, y + h - br);
+    pb.cubic_to(x + w, y + h - br + br * KAPPA, x + w - br + br * KAPPA, y + h, x + w - br, y + h);
+    pb.line_to(x + bl, y + h);
+    pb.cubic_to(x + bl - bl * KAPPA, y + h, x, y + h - bl + bl * KAPPA, x, y + h - bl);
+    pb.line_to(x, y + tl);
+    pb.cubic_to(x, y + tl - tl * KAPPA, x + tl - tl * KAPPA, y, x + tl, y);
+    pb.close();
+}
+
+/// Shrink radii by the inset applied to each side, as for inner border edges
+fn inset_radii(radii: [f32; 4], inset: EdgeSizes) -> [f32; 4] {
+    let [tl, tr, br, bl] = radii;
+    let EdgeSizes { top, right, bottom, left } = inset;
+    [
+        (tl - top.max(left)).max(0.0),
+        (tr - top.max(right)).max(0.0),
+        (br - bottom.max(right)).max(0.0),
+        (bl - bottom.max(left)).max(0.0),
+    ]
+}
 
-    // Apply styling based on parent tag
-    match parent_tag {
-        "h1" => render_heading_text(dt, layout, text, 1.8),  // 80% larger
-        "h2" => render_heading_text(dt, layout, text, 1.6),  // 60% larger
-        "h3" => render_heading_text(dt, layout, text, 1.4),  // 40% larger
-        _ => render_text(dt, layout, text),                  // Normal text
+/// Draw a rounded border as the ring between the outer and inner rounded edges
+fn draw_rounded_border(dt: &mut DrawTarget, rect: Rect, border_width: f32, radii: [f32; 4], color: u32) {
+    let border = EdgeSizes::uniform(border_width);
+    let mut pb = PathBuilder::new();
+    rounded_rect_path(&mut pb, rect, radii);
+    rounded_rect_path(&mut pb, rect.inset(border), inset_radii(radii, border));
+    let mut path = pb.finish();
+    path.winding = Winding::EvenOdd;
+    dt.fill(&path, &solid_source(color), &DrawOptions::new());
+}
+
+/// Draw a box shadow: an outer one behind the border box `rect`, or an
+/// inset one inside the padding box `rect`
+///
+/// The shadow shape is rasterized into an alpha mask, blurred with three box
+/// blur passes (a close Gaussian approximation) and composited in the shadow
+/// color. Outer shadows are clipped so they never paint beneath the box,
+/// inset ones so they never paint outside it. Blur and spread are clamped
+/// to `MAX_SHADOW_EXTENT`, so a stray huge value can't allocate a huge
+/// mask.
+fn draw_box_shadow(dt: &mut DrawTarget, rect: Rect, radii: [f32; 4], shadow: &BoxShadow) {
+    let spread = shadow.spread_radius.clamp(-MAX_SHADOW_EXTENT, MAX_SHADOW_EXTENT);
+    let blur = shadow.blur_radius.min(MAX_SHADOW_EXTENT);
+    let margin = blur.ceil() as i32;
+    let fill = Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
+
+    let (mask_x, mask_y, mask_w, mask_h, alpha, clip) = if shadow.inset {
+        // The shadow is everything in the box outside the shrunk, offset
+        // hole; the mask reaches past the box so the blur fades in from
+        // solid shadow at the edges
+        if rect.is_empty() {
+            return;
+        }
+        let hole = rect.inset(EdgeSizes::uniform(spread)).translate(shadow.offset_x, shadow.offset_y);
+        let hole_radii = radii.map(|r| if r > 0.0 { (r - spread).max(0.0) } else { 0.0 });
+        let (x, y, w, h) = rect.round_out();
+        let (mask_x, mask_y) = (x - margin, y - margin);
+        let (mask_w, mask_h) = (w + 2 * margin, h + 2 * margin);
+
+        let mut shape = DrawTarget::new(mask_w, mask_h);
+        let mut pb = PathBuilder::new();
+        pb.rect(0.0, 0.0, mask_w as f32, mask_h as f32);
+        if !hole.is_empty() {
+            rounded_rect_path(&mut pb, hole.translate(-mask_x as f32, -mask_y as f32), hole_radii);
+        }
+        let mut path = pb.finish();
+        path.winding = Winding::EvenOdd;
+        shape.fill(&path, &fill, &DrawOptions::new());
+
+        let mut clip = PathBuilder::new();
+        rounded_rect_path(&mut clip, rect, radii);
+        (mask_x, mask_y, mask_w, mask_h, shape, clip.finish())
+    } else {
+        let shadow_box = rect.outset(EdgeSizes::uniform(spread)).translate(shadow.offset_x, shadow.offset_y);
+        if shadow_box.is_empty() {
+            return;
+        }
+        let shadow_radii = radii.map(|r| if r > 0.0 { (r + spread).max(0.0) } else { 0.0 });
+
+        // Rasterize the shadow shape with room for the blur to spread out
+        let (x, y, w, h) = shadow_box.round_out();
+        let (mask_x, mask_y) = (x - margin, y - margin);
+        let (mask_w, mask_h) = (w + 2 * margin, h + 2 * margin);
+
+        let mut shape = DrawTarget::new(mask_w, mask_h);
+        let mut pb = PathBuilder::new();
+        rounded_rect_path(&mut pb, shadow_box.translate(-mask_x as f32, -mask_y as f32), shadow_radii);
+        shape.fill(&pb.finish(), &fill, &DrawOptions::new());
+
+        // Clip out the border box itself
+        let mut clip = PathBuilder::new();
+        clip.rect(mask_x as f32, mask_y as f32, mask_w as f32, mask_h as f32);
+        rounded_rect_path(&mut clip, rect, radii);
+        let mut clip = clip.finish();
+        clip.winding = Winding::EvenOdd;
+        (mask_x, mask_y, mask_w, mask_h, shape, clip)
+    };
+
+    let mut alpha: Vec<u8> = alpha.get_data().iter().map(|p| (p >> 24) as u8).collect();
+    if blur > 0.0 {
+        // CSS defines the blur radius as twice the Gaussian standard deviation
+        gaussian_blur_alpha(&mut alpha, mask_w as usize, mask_h as usize, blur / 2.0);
     }
+
+    // Premultiply the shadow color by the blurred coverage
+    let (a, r, g, b) = argb_to_components(parse_color_to_argb(&shadow.color));
+    let pixels: Vec<u32> = alpha
+        .iter()
+        .map(|&coverage| {
+            let scale = |c: u8| (c as u32 * a as u32 * coverage as u32) / (255 * 255);
+            (scale(255) << 24) | (scale(r) << 16) | (scale(g) << 8) | scale(b)
+        })
+        .collect();
+    let image = Image { width: mask_w, height: mask_h, data: &pixels };
+
+    dt.push_clip(&clip);
+    dt.draw_image_at(mask_x as f32, mask_y as f32, &image, &DrawOptions::new());
+    dt.pop_clip();
 }
 
-/// Render heading text with larger font size
-fn render_heading_text(dt: &mut DrawTarget, layout: &Layout, text: &str, scale: f32) {
-    if text.is_empty() || layout.width <= 0.0 || layout.height <= 0.0 {
-        return;
+/// Approximate a Gaussian blur of an alpha mask with three box blur passes
+fn gaussian_blur_alpha(alpha: &mut [u8], width: usize, height: usize, sigma: f32) {
+    // Three passes of a box of width w have variance 3 * (w^2 - 1) / 12
+    let box_width = (4.0 * sigma * sigma + 1.0).sqrt();
+    let radius = ((box_width - 1.0) / 2.0).round().max(1.0) as usize;
+
+    let mut buffer: Vec<f32> = alpha.iter().map(|&a| a as f32).collect();
+    let mut scratch = vec![0.0; buffer.len()];
+    for _ in 0..3 {
+        box_blur_pass(&buffer, &mut scratch, width, height, radius, true);
+        box_blur_pass(&scratch, &mut buffer, width, height, radius, false);
     }
 
-    let char_width = 14.0 * scale;
-    let char_height = 22.0 * scale;
-    let line_height = char_height + 8.0;
+    for (out, value) in alpha.iter_mut().zip(buffer) {
+        *out = value.round().clamp(0.0, 255.0) as u8;
+    }
+}
 
-    let text_source = Source::Solid(SolidSource::from_unpremultiplied_argb(255, 40, 40, 40)); // Dark gray for headings
-    let text_options = DrawOptions::new();
+/// One horizontal or vertical box blur pass using a running sum
+fn box_blur_pass(src: &[f32], dst: &mut [f32], width: usize, height: usize, radius: usize, horizontal: bool) {
+    let (lines, len) = if horizontal { (height, width) } else { (width, height) };
+    let index = |line: usize, i: usize| if horizontal { line * width + i } else { i * width + line };
+    let window = (2 * radius + 1) as f32;
+
+    for line in 0..lines {
+        // Values outside the mask count as transparent
+        let mut sum: f32 = (0..=radius.min(len - 1)).map(|i| src[index(line, i)]).sum();
+        for i in 0..len {
+            dst[index(line, i)] = sum / window;
+            let entering = i + radius + 1;
+            if entering < len {
+                sum += src[index(line, entering)];
+            }
+            if i >= radius {
+                sum -= src[index(line, i - radius)];
+            }
+        }
+    }
+}
+
+/// Combine the text decorations of a node and its ancestors
+///
+/// Decorations propagate to all text inside a decorated element, so a link's
+/// underline still applies to text nested in a `<span>`.
+fn propagated_text_decoration(document: &Document, node_idx: usize, styles: &[ComputedStyle]) -> TextDecoration {
+    let mut decoration = TextDecoration::default();
+    let mut current = Some(node_idx);
+    while let Some(idx) = current {
+        if let Some(style) = styles.get(idx) {
+            decoration.underline |= style.text_decoration.underline;
+            decoration.overline |= style.text_decoration.overline;
+            decoration.line_through |= style.text_decoration.line_through;
+            // The innermost color wins
+            if decoration.color.is_none() {
+                decoration.color = style.text_decoration.color.clone();
+            }
+        }
+        current = document.nodes.get(idx).and_then(|n| n.parent);
+    }
+    decoration
+}
 
-    let mut x = layout.x + 8.0;
-    let mut y = layout.y + 8.0;
+/// Inherited text properties that affect how a run of text is painted
+#[derive(Debug, Clone, PartialEq)]
+struct TextStyle {
+    decoration: TextDecoration,
+    transform: TextTransform,
+    letter_spacing: f32,
+    word_spacing: f32,
+    white_space: WhiteSpace,
+    /// From `word-break` and `overflow-wrap`
+    breaking: WordBreaking,
+    /// Whether lines too wide for the box end in `…`: the containing
+    /// element clips its overflow with `text-overflow: ellipsis`
+    ellipsis: bool,
+    /// `font-weight` of 600 or more
+    bold: bool,
+    color: u32,
+    /// Fonts to draw characters in, first match wins; see
+    /// `WebFonts::fallback_chain`
+    fonts: Vec<Rc<WebFont>>,
+}
 
-    for ch in text.chars() {
-        if ch == '\n' {
-            x = layout.x + 8.0;
-            y += line_height;
-            continue;
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            decoration: TextDecoration::default(),
+            transform: TextTransform::default(),
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            white_space: WhiteSpace::default(),
+            breaking: WordBreaking::default(),
+            ellipsis: false,
+            bold: false,
+            color: 0xff000000,
+            fonts: Vec::new(),
         }
+    }
+}
 
-        if x + char_width > layout.x + layout.width - 4.0 {
-            x = layout.x + 8.0;
-            y += line_height;
+/// Resolve the text properties that apply to a node
+///
+/// `text-transform`, `letter-spacing`, `word-spacing`, `white-space`,
+/// `word-break`, `overflow-wrap`, `font-weight`, `font-family` and `color`
+/// are inherited, so the nearest node (self first) that sets each one wins.
+/// `text-overflow` and `overflow` are read from the parent element only.
+fn resolve_text_style(document: &Document, node_idx: usize, styles: &[ComputedStyle], fonts: &WebFonts) -> TextStyle {
+    let mut transform = None;
+    let mut letter_spacing = None;
+    let mut word_spacing = None;
+    let mut white_space = None;
+    let mut word_break = None;
+    let mut overflow_wrap = None;
+    let mut font_weight = None;
+    let mut font_family = None;
+    let mut current = Some(node_idx);
+    while let Some(idx) = current {
+        if let Some(style) = styles.get(idx) {
+            transform = transform.or(style.text_transform);
+            letter_spacing = letter_spacing.or_else(|| style.letter_spacing.as_ref().map(|v| v.as_pixels(0.0)));
+            word_spacing = word_spacing.or_else(|| style.word_spacing.as_ref().map(|v| v.as_pixels(0.0)));
+            white_space = white_space.or(style.white_space);
+            word_break = word_break.or(style.word_break);
+            overflow_wrap = overflow_wrap.or(style.overflow_wrap);
+            font_weight = font_weight.or(style.font_weight);
+            font_family = font_family.or(style.font_family.as_deref());
         }
+        current = document.nodes.get(idx).and_then(|n| n.parent);
+    }
+    let container = document.nodes.get(node_idx).and_then(|n| n.parent).and_then(|idx| styles.get(idx));
+
+    TextStyle {
+        decoration: propagated_text_decoration(document, node_idx, styles),
+        transform: transform.unwrap_or_default(),
+        letter_spacing: letter_spacing.unwrap_or(0.0),
+        word_spacing: word_spacing.unwrap_or(0.0),
+        white_space: white_space.unwrap_or_default(),
+        breaking: word_break.unwrap_or_default().breaking(overflow_wrap.unwrap_or_default()),
+        ellipsis: container.is_some_and(|style| {
+            style.text_overflow == Some(TextOverflow::Ellipsis) && style.overflow.is_some_and(|overflow| overflow.clips())
+        }),
+        bold: font_weight.is_some_and(|weight| weight >= 600),
+        color: inherited_color(document, node_idx, styles),
+        fonts: fonts.fallback_chain(font_family),
+    }
+}
 
-        if y + char_height > layout.y + layout.height - 2.0 {
-            break;
+/// The nearest `color` set on the node or an ancestor, or black
+fn inherited_color(document: &Document, node_idx: usize, styles: &[ComputedStyle]) -> u32 {
+    std::iter::successors(Some(node_idx), |&idx| document.nodes[idx].parent)
+        .find_map(|idx| styles.get(idx)?.color.as_deref())
+        .map_or(0xff000000, parse_color_to_argb)
+}
+
+/// Record a list item's bullet or counter in its marker box
+fn paint_list_marker(list: &mut DisplayList, marker: &ListMarker, color: u32) {
+    let rect = marker.rect;
+    let round = [rect.width / 2.0; 4];
+    match &marker.kind {
+        MarkerKind::Disc => list.push(PaintCommand::Rect { rect, radii: round, color }),
+        MarkerKind::Circle => list.push(PaintCommand::Border { rect, width: 1.0, radii: round, color }),
+        MarkerKind::Square => list.push(PaintCommand::Rect { rect, radii: [0.0; 4], color }),
+        MarkerKind::Text(text) => {
+            let glyph = GlyphStyle {
+                width: rect.width / text.chars().count().max(1) as f32,
+                height: rect.height,
+                letter_spacing: 0.0,
+                word_spacing: 0.0,
+                color,
+                bold: false,
+            };
+            list.push(PaintCommand::Text { x: rect.x, y: rect.y, text: text.clone(), glyph, font: None });
         }
+    }
+}
 
-        draw_simple_char(dt, ch, x, y, char_width, char_height, &text_source, &text_options);
+/// Record a text run at its box's font size
+///
+/// Glyphs are 14x22 with 6px insets at the 16px root size and scale with
+/// `font-size`; the weight and color come from the inherited style.
+fn paint_body_text(list: &mut DisplayList, layout: &Layout, text: &str, text_style: &TextStyle) {
+    let font_size = if layout.font_size > 0.0 { layout.font_size } else { ROOT_FONT_SIZE };
+    let scale = font_size / ROOT_FONT_SIZE;
+    let char_height = 22.0 * scale;
+    let paint = TextPaint {
+        char_width: 14.0 * scale,
+        char_height,
+        line_height: char_height + 6.0 * scale,
+        inset_x: 6.0,
+        inset_y: 6.0,
+        color: text_style.color,
+    };
+    paint_text(list, layout, text, &paint, text_style);
+}
+
+/// Record a text node's lines where inline layout put them
+///
+/// Glyphs stand on each fragment's baseline. With `text-overflow: ellipsis`
+/// a line running past the parent's content box is cut to end in `…`.
+fn paint_text_fragments(
+    list: &mut DisplayList,
+    document: &Document,
+    node_idx: usize,
+    layout: &Layout,
+    fragments: &[TextFragment],
+    text_style: &TextStyle,
+) {
+    let font_size = if layout.font_size > 0.0 { layout.font_size } else { ROOT_FONT_SIZE };
+    let glyph = GlyphStyle {
+        color: text_style.color,
+        bold: text_style.bold,
+        ..inline::glyph_style(font_size, text_style.letter_spacing, text_style.word_spacing)
+    };
+    let paint = TextPaint {
+        char_width: glyph.width,
+        char_height: glyph.height,
+        line_height: glyph.height,
+        inset_x: 0.0,
+        inset_y: 0.0,
+        color: text_style.color,
+    };
+    let clip_right = text_style
+        .ellipsis
+        .then(|| document.nodes[node_idx].parent.and_then(|parent| document.layout(parent)))
+        .flatten()
+        .map(|parent| parent.content_box().right());
+
+    for fragment in fragments {
+        let x = layout.rect.x + fragment.rect.x;
+        let y = layout.rect.y + fragment.rect.y + fragment.baseline - glyph.height;
+        // Layout already case-mapped the fragments
+        let mut text = fragment.text.clone();
+        if let Some(right) = clip_right {
+            truncate_with_ellipsis(&mut text, right - x, |ch| glyph.advance(ch));
+        }
 
-        x += char_width;
+        let mut line_end = x;
+        for (run, font) in font_runs(&text, &text_style.fonts) {
+            let run_end = run.chars().fold(line_end, |x, ch| x + glyph.advance(ch));
+            list.push(PaintCommand::Text { x: line_end, y, text: run, glyph, font });
+            line_end = run_end;
+        }
+        paint_decorations(list, x, line_end, y, &paint, &text_style.decoration);
     }
 }
 
-/// Render text content as visible readable characters
-fn render_text(dt: &mut DrawTarget, layout: &Layout, text: &str) {
-    if text.is_empty() || layout.width <= 0.0 || layout.height <= 0.0 {
+/// Glyph box, spacing and color used by the simple character renderer
+struct TextPaint {
+    char_width: f32,
+    char_height: f32,
+    line_height: f32,
+    inset_x: f32,
+    inset_y: f32,
+    color: u32,
+}
+
+/// Lay out text inside a box, wrapping at the right edge, and record one
+/// command per line
+///
+/// Whitespace is collapsed by `white-space` first, dropping spaces at the
+/// start and end of the run, and the text is case-mapped by `text-transform`.
+/// Every glyph advance is widened by `letter-spacing` (plus `word-spacing`
+/// for spaces). Lines break at spaces and soft hyphens via
+/// `text::break_lines`, or only at newlines for `pre` and `nowrap`, and
+/// inside words as `word-break` and `overflow-wrap` allow. With
+/// `text-overflow: ellipsis` a line still too wide is cut to end in `…`.
+/// Decoration lines are recorded per visual line, spanning the glyphs that
+/// ended up on that line.
+fn paint_text(
+    list: &mut DisplayList,
+    layout: &Layout,
+    text: &str,
+    paint: &TextPaint,
+    text_style: &TextStyle,
+) {
+    if text.is_empty() || layout.rect.is_empty() {
         return;
     }
 
-    let char_width = 14.0;  // MUCH LARGER for better readability
-    let char_height = 22.0;  // MUCH LARGER for better readability
-    let line_height = char_height + 6.0;
-
-    let text_source = Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)); // Black text
-    let text_options = DrawOptions::new();
+    let white_space = text_style.white_space;
+    let collapsed = white_space.apply(text);
+    let text = if white_space.collapses() { collapsed.trim_matches(' ') } else { &collapsed };
+    let text = text_style.transform.apply(text);
+    let glyph = GlyphStyle {
+        width: paint.char_width,
+        height: paint.char_height,
+        letter_spacing: text_style.letter_spacing,
+        word_spacing: text_style.word_spacing,
+        color: paint.color,
+        bold: text_style.bold,
+    };
+    let available = layout.rect.width - paint.inset_x - 4.0;
+    let max_width = if white_space.wraps() { available } else { f32::INFINITY };
+    let mut lines = break_lines(&text, max_width, text_style.breaking, |ch| glyph.advance(ch));
+    if text_style.ellipsis {
+        for line in &mut lines {
+            truncate_with_ellipsis(line, available, |ch| glyph.advance(ch));
+        }
+    }
 
-    let mut x = layout.x + 6.0;
-    let mut y = layout.y + 6.0;
+    let line_start = layout.rect.x + paint.inset_x;
+    let mut y = layout.rect.y + paint.inset_y;
+    for line in lines {
+        if y + paint.char_height > layout.rect.bottom() - 2.0 {
+            return;
+        }
 
-    // Simple bitmap-style text rendering with MUCH LARGER CHARACTERS
-    for ch in text.chars() {
-        if ch == '\n' {
-            x = layout.x + 6.0;
-            y += line_height;
-            continue;
+        let mut line_end = line_start;
+        for (run, font) in font_runs(&line, &text_style.fonts) {
+            let run_end = run.chars().fold(line_end, |x, ch| x + glyph.advance(ch));
+            list.push(PaintCommand::Text { x: line_end, y, text: run, glyph, font });
+            line_end = run_end;
         }
+        paint_decorations(list, line_start, line_end, y, paint, &text_style.decoration);
+        y += paint.line_height;
+    }
+}
+
+/// Cut a line wider than `max_width` so that it fits with `…` appended
+fn truncate_with_ellipsis(line: &mut String, max_width: f32, advance: impl Fn(char) -> f32) {
+    const ELLIPSIS: char = '…';
+    if line.chars().map(&advance).sum::<f32>() <= max_width {
+        return;
+    }
+    let mut width = advance(ELLIPSIS);
+    let fits = line
+        .char_indices()
+        .find(|&(_, ch)| {
+            width += advance(ch);
+            width > max_width
+        })
+        .map_or(line.len(), |(i, _)| i);
+    line.truncate(fits);
+    line.truncate(line.trim_end_matches(' ').len());
+    line.push(ELLIPSIS);
+}
 
-        if x + char_width > layout.x + layout.width - 4.0 {
-            x = layout.x + 6.0;
-            y += line_height;
+/// Split a line into runs drawn in one font each: per character, the first
+/// of `fonts` that has it, or `None` for the built-in glyphs
+///
+/// Emoji modifiers and joiners stay in the run of the emoji they modify.
+/// An empty line is one empty run.
+fn font_runs(line: &str, fonts: &[Rc<WebFont>]) -> Vec<(String, Option<Rc<WebFont>>)> {
+    if line.is_empty() {
+        return vec![(String::new(), None)];
+    }
+    let mut runs: Vec<(String, Option<Rc<WebFont>>)> = Vec::new();
+    for ch in line.chars() {
+        let font = if is_emoji_modifier(ch) {
+            runs.last().and_then(|(_, font)| font.clone())
+        } else {
+            fonts.iter().find(|font| font.has_glyph(ch)).cloned()
+        };
+        match runs.last_mut() {
+            Some((run, run_font)) if *run_font == font => run.push(ch),
+            _ => runs.push((ch.to_string(), font)),
         }
+    }
+    runs
+}
 
-        if y + char_height > layout.y + layout.height - 2.0 {
-            break;
+/// Record underline, overline and line-through for one visual line of text
+///
+/// The glyph box bottom is the baseline; line offsets and thicknesses come
+/// from the font's decoration metrics at the glyph size.
+fn paint_decorations(
+    list: &mut DisplayList,
+    start_x: f32,
+    end_x: f32,
+    line_y: f32,
+    paint: &TextPaint,
+    decoration: &TextDecoration,
+) {
+    if decoration.is_none() || end_x <= start_x {
+        return;
+    }
+
+    let metrics = default_decoration_metrics(paint.char_height);
+    let ascent = default_line_metrics(paint.char_height).ascent;
+    let baseline = line_y + paint.char_height;
+    let color = decoration.color.as_deref().map(parse_color_to_argb).unwrap_or(paint.color);
+
+    // Snap to whole pixels so decorations stay crisp
+    let snap_thickness = |t: f32| t.round().max(1.0);
+    let x = start_x.round();
+    let width = (end_x - start_x).round();
+    let mut line = |y: f32, thickness: f32| {
+        list.push(PaintCommand::Rect { rect: Rect::new(x, y, width, thickness), radii: [0.0; 4], color });
+    };
+    if decoration.underline {
+        line((baseline + metrics.underline_offset).round(), snap_thickness(metrics.underline_thickness));
+    }
+    if decoration.overline {
+        line((baseline - ascent).round(), snap_thickness(metrics.underline_thickness));
+    }
+    if decoration.line_through {
+        line((baseline - metrics.strikeout_offset).round(), snap_thickness(metrics.strikeout_thickness));
+    }
+}
+
+/// Draw a character no page font has: a built-in glyph, the default font's
+/// glyph, an outline for emoji, or else a missing-glyph box
+fn draw_builtin_char(dt: &mut DrawTarget, ch: char, x: f32, y: f32, glyph: &GlyphStyle, source: &Source, options: &DrawOptions) {
+    if draw_simple_char(dt, ch, x, y, glyph.width, glyph.height, source, options) {
+        return;
+    }
+    match default_font().filter(|font| font.lookup_glyph_index(ch) != 0) {
+        Some(font) => draw_font_glyph(dt, font.rasterize(ch, glyph.height), x, y, glyph, options),
+        None if is_emoji(ch) => draw_emoji_outline(dt, x, y, glyph, options),
+        None => {
+            // The box outline on the built-in glyphs' 12x18 grid
+            let (px, py) = (glyph.width / 12.0, glyph.height / 18.0);
+            dt.fill_rect(x + 2.0 * px, y, 4.0 * px, py, source, options);
+            dt.fill_rect(x + 2.0 * px, y + 11.0 * py, 4.0 * px, py, source, options);
+            dt.fill_rect(x + 2.0 * px, y, px, 12.0 * py, source, options);
+            dt.fill_rect(x + 5.0 * px, y, px, 12.0 * py, source, options);
         }
+    }
+}
 
-        // Draw simple character outlines (boxes with internal structure)
-        draw_simple_char(dt, ch, x, y, char_width, char_height, &text_source, &text_options);
+/// Draw a monochrome stand-in for an emoji no font has: a circle
+/// outline in the text color, centered in the glyph box
+fn draw_emoji_outline(dt: &mut DrawTarget, x: f32, y: f32, glyph: &GlyphStyle, options: &DrawOptions) {
+    let radius = glyph.width.min(glyph.height) * 0.4;
+    let mut pb = PathBuilder::new();
+    pb.arc(x + glyph.width / 2.0, y + glyph.height / 2.0, radius, 0.0, 2.0 * std::f32::consts::PI);
+    pb.close();
+    let stroke = StrokeStyle { width: (radius / 5.0).max(1.0), ..Default::default() };
+    dt.stroke(&pb.finish(), &solid_source(glyph.color), &stroke, options);
+}
 
-        x += char_width;
+/// Draw a glyph rasterized from a font in its glyph box
+///
+/// Like decorations, the glyph box height is the font size and its bottom
+/// is the baseline.
+fn draw_font_glyph(dt: &mut DrawTarget, (metrics, coverage): (Metrics, Vec<u8>), x: f32, y: f32, glyph: &GlyphStyle, options: &DrawOptions) {
+    if metrics.width == 0 || metrics.height == 0 {
+        return;
     }
+    // Coverage scales the text color's alpha, premultiplied as raqote wants
+    let (a, r, g, b) = argb_to_components(glyph.color);
+    let pixels: Vec<u32> = coverage
+        .iter()
+        .map(|&c| {
+            let alpha = c as u32 * a as u32 / 255;
+            let channel = |v: u8| v as u32 * alpha / 255;
+            (alpha << 24) | (channel(r) << 16) | (channel(g) << 8) | channel(b)
+        })
+        .collect();
+    let image = Image { width: metrics.width as i32, height: metrics.height as i32, data: &pixels };
+    let baseline = y + glyph.height;
+    dt.draw_image_at(x + metrics.xmin as f32, baseline - metrics.ymin as f32 - metrics.height as f32, &image, options);
 }
 
 /// Draw a character with actual readable bitmap patterns
+///
+/// Returns false, drawing nothing, for characters without a pattern.
+#[allow(clippy::too_many_arguments)]
 fn draw_simple_char(
     dt: &mut DrawTarget,
     ch: char,
@@ -236,7 +1104,7 @@ fn draw_simple_char(
     height: f32,
     source: &Source,
     options: &DrawOptions,
-) {
+) -> bool {
     let px = width / 12.0;  // LARGER GRID - 12x18 pixels instead of 8x12
     let py = height / 18.0;
 
@@ -425,17 +1293,14 @@ fn draw_simple_char(
             // Space - do nothing
         }
 
-        // Default: draw a box
-        _ => {
-            for row in 0..12 { draw_px!(2, row); draw_px!(5, row); }
-            for col in 2..=5 { draw_px!(col, 0); draw_px!(col, 11); }
-        }
+        _ => return false,
     }
+    true
 }
 
-/// Render element attributes as visible text (label, placeholder, value, etc.)
-fn render_element_text(dt: &mut DrawTarget, layout: &Layout, elem: &ElementData) {
-    if layout.width <= 0.0 || layout.height <= 0.0 {
+/// Record element attributes as visible text (label, placeholder, value, etc.)
+fn paint_element_text(list: &mut DisplayList, layout: &Layout, elem: &ElementData) {
+    if layout.rect.is_empty() {
         return;
     }
 
@@ -444,44 +1309,35 @@ fn render_element_text(dt: &mut DrawTarget, layout: &Layout, elem: &ElementData)
         let is_disabled = elem.attributes.contains_key("disabled");
 
         let border_color = if is_disabled {
-            Source::Solid(SolidSource::from_unpremultiplied_argb(255, 180, 180, 180)) // Lighter gray for disabled
+            0xffb4b4b4 // Lighter gray for disabled
         } else {
-            Source::Solid(SolidSource::from_unpremultiplied_argb(255, 100, 100, 100)) // Gray border
+            0xff646464 // Gray border
         };
 
         let bg_color = if is_disabled {
-            Source::Solid(SolidSource::from_unpremultiplied_argb(255, 230, 230, 230)) // Darker gray for disabled
+            0xffe6e6e6 // Darker gray for disabled
         } else {
-            Source::Solid(SolidSource::from_unpremultiplied_argb(255, 245, 245, 245)) // Very light gray for enabled
+            0xfff5f5f5 // Very light gray for enabled
         };
 
-        let options = DrawOptions::new();
-
         // Draw border
         let border_width = 2.0;
-        dt.fill_rect(layout.x, layout.y, layout.width, border_width, &border_color, &options);
-        dt.fill_rect(layout.x, layout.y + layout.height - border_width, layout.width, border_width, &border_color, &options);
-        dt.fill_rect(layout.x, layout.y, border_width, layout.height, &border_color, &options);
-        dt.fill_rect(layout.x + layout.width - border_width, layout.y, border_width, layout.height, &border_color, &options);
+        list.push(PaintCommand::Border { rect: layout.border_box(), width: border_width, radii: [0.0; 4], color: border_color });
 
         // Draw background
-        dt.fill_rect(layout.x + border_width, layout.y + border_width,
-                    layout.width - border_width * 2.0,
-                    layout.height - border_width * 2.0,
-                    &bg_color, &options);
+        list.push(PaintCommand::Rect {
+            rect: layout.border_box().inset(EdgeSizes::uniform(border_width)),
+            radii: [0.0; 4],
+            color: bg_color,
+        });
     }
 
-    let char_width = 14.0;  // MUCH LARGER
-    let char_height = 22.0;  // MUCH LARGER
-    let line_height = char_height + 6.0;
-
     let is_disabled_text = elem.attributes.contains_key("disabled");
-    let text_source = if is_disabled_text {
-        Source::Solid(SolidSource::from_unpremultiplied_argb(255, 150, 150, 150)) // Light gray for disabled text
+    let text_color = if is_disabled_text {
+        0xff969696 // Light gray for disabled text
     } else {
-        Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)) // Black text
+        0xff000000 // Black text
     };
-    let text_options = DrawOptions::new();
 
     // Prioritize rendering these attributes in order
     let text_attrs = vec!["label", "placeholder", "value", "text"];
@@ -503,34 +1359,21 @@ fn render_element_text(dt: &mut DrawTarget, layout: &Layout, elem: &ElementData)
         return;
     }
 
-    let mut x = layout.x + 8.0;
-    let mut y = layout.y + 6.0;
-
     // Draw text using simple character rendering with LARGER chars
-    for ch in rendered_text.chars() {
-        if ch == '\n' {
-            x = layout.x + 8.0;
-            y += line_height;
-            continue;
-        }
-
-        if x + char_width > layout.x + layout.width - 4.0 {
-            x = layout.x + 8.0;
-            y += line_height;
-        }
-
-        if y + char_height > layout.y + layout.height - 2.0 {
-            break;
-        }
-
-        draw_simple_char(dt, ch, x, y, char_width, char_height, &text_source, &text_options);
-
-        x += char_width;
-    }
+    let char_height = 22.0;  // MUCH LARGER
+    let paint = TextPaint {
+        char_width: 14.0,  // MUCH LARGER
+        char_height,
+        line_height: char_height + 6.0,
+        inset_x: 8.0,
+        inset_y: 6.0,
+        color: text_color,
+    };
+    paint_text(list, layout, &rendered_text, &paint, &TextStyle::default());
 }
 
 /// Convert ARGB u32 to (a, r, g, b) tuple for raqote
-fn argb_to_components(argb: u32) -> (u8, u8, u8, u8) {
+pub(crate) fn argb_to_components(argb: u32) -> (u8, u8, u8, u8) {
     let a = ((argb >> 24) & 0xff) as u8;
     let r = ((argb >> 16) & 0xff) as u8;
     let g = ((argb >> 8) & 0xff) as u8;
@@ -539,7 +1382,13 @@ fn argb_to_components(argb: u32) -> (u8, u8, u8, u8) {
 }
 
 /// Parse CSS color string to ARGB format
-fn parse_color_to_argb(color: &str) -> u32 {
+pub(crate) fn parse_color_to_argb(color: &str) -> u32 {
+    try_parse_color(color).unwrap_or(0xff000000) // Default to black
+}
+
+/// Parse CSS color string to ARGB format, or `None` if it is not a color
+/// this renderer knows
+pub(crate) fn try_parse_color(color: &str) -> Option<u32> {
     let color = color.trim().to_lowercase();
 
     // Handle rgb(r, g, b) format
@@ -552,7 +1401,24 @@ fn parse_color_to_argb(color: &str) -> u32 {
                 parts[1].trim().parse::<u8>(),
                 parts[2].trim().parse::<u8>(),
             ) {
-                return ((r as u32) << 16) | ((g as u32) << 8) | (b as u32) | 0xff000000;
+                return Some(((r as u32) << 16) | ((g as u32) << 8) | (b as u32) | 0xff000000);
+            }
+        }
+    }
+
+    // Handle rgba(r, g, b, a) format with alpha in 0.0..=1.0
+    if color.starts_with("rgba(") && color.ends_with(")") {
+        let inner = &color[5..color.len() - 1];
+        let parts: Vec<&str> = inner.split(',').collect();
+        if parts.len() == 4 {
+            if let (Ok(r), Ok(g), Ok(b), Ok(a)) = (
+                parts[0].trim().parse::<u8>(),
+                parts[1].trim().parse::<u8>(),
+                parts[2].trim().parse::<u8>(),
+                parts[3].trim().parse::<f32>(),
+            ) {
+                let a = (a.clamp(0.0, 1.0) * 255.0).round() as u32;
+                return Some((a << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32));
             }
         }
     }
@@ -564,22 +1430,22 @@ fn parse_color_to_argb(color: &str) -> u32 {
             let r = (hex >> 16) & 0xFF;
             let g = (hex >> 8) & 0xFF;
             let b = hex & 0xFF;
-            return 0xFF000000 | (r << 16) | (g << 8) | b;
+            return Some(0xFF000000 | (r << 16) | (g << 8) | b);
         }
     }
 
     // Named colors
     match color.as_str() {
-        "black" => 0xff000000,
-        "white" => 0xffffffff,
-        "red" => 0xffff0000,
-        "green" => 0xff008000,
-        "blue" => 0xff0000ff,
-        "yellow" => 0xffffff00,
-        "cyan" => 0xff00ffff,
-        "magenta" => 0xffff00ff,
-        "gray" | "grey" => 0xff808080,
-        _ => 0xff000000, // Default to black
+        "black" => Some(0xff000000),
+        "white" => Some(0xffffffff),
+        "red" => Some(0xffff0000),
+        "green" => Some(0xff008000),
+        "blue" => Some(0xff0000ff),
+        "yellow" => Some(0xffffff00),
+        "cyan" => Some(0xff00ffff),
+        "magenta" => Some(0xffff00ff),
+        "gray" | "grey" => Some(0xff808080),
+        _ => None,
     }
 }
 
@@ -590,6 +1456,8 @@ fn parse_color_to_argb(color: &str) -> u32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::css::CSSValue;
+    use crate::image_diff::{compare_with_file, DiffOptions, Image};
     use std::fs;
     use std::path::Path;
 
@@ -607,21 +1475,20 @@ mod tests {
         let mut doc = Document::new();
         let elem_idx = doc.create_element("div");
         doc.append_child(doc.root, elem_idx);
-        doc.nodes[elem_idx].layout = Some(Layout {
-            x: 10.0, y: 10.0, width: 100.0, height: 50.0,
+        doc.set_layout(elem_idx, Some(Layout {
+            rect: Rect::new(10.0, 10.0, 100.0, 50.0),
             ..Default::default()
-        });
+        }));
         let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
         styles[elem_idx].background_color = Some("red".to_string());
 
         // When: We render it
         let mut dt = DrawTarget::new(200, 100);
-        render_node(&mut dt, &doc, doc.root, &styles);
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
         dt.write_png(output_path.to_str().unwrap()).unwrap();
 
         // Then: The output should match the golden master
-        let (master_data, output_data) = load_or_create_golden_master(golden_master_path, &output_path);
-        assert_eq!(master_data, output_data, "Rendered output does not match the golden master.");
+        assert_matches_golden_master(golden_master_path, &output_path);
     }
 
     #[test]
@@ -651,23 +1518,54 @@ mod tests {
         // When: We calculate layout and render it
         super::super::layout::calculate_layout(&mut doc, 200.0, 100.0);
         let mut dt = DrawTarget::new(200, 100);
-        render_node(&mut dt, &doc, doc.root, &styles);
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
         dt.write_png(output_path.to_str().unwrap()).unwrap();
 
         // Then: The output should match the golden master
-        let (master_data, output_data) = load_or_create_golden_master(golden_master_path, &output_path);
-        assert_eq!(master_data, output_data, "Rendered flexbox output does not match the golden master.");
+        assert_matches_golden_master(golden_master_path, &output_path);
     }
 
-    fn load_or_create_golden_master(master_path: &str, current_output_path: &Path) -> (Vec<u8>, Vec<u8>) {
-        let output_data = fs::read(current_output_path).unwrap();
-        if let Ok(master_data) = fs::read(master_path) {
-            (master_data, output_data)
-        } else {
+    /// Record a subtree and play it straight onto a target
+    fn render_node(dt: &mut DrawTarget, document: &Document, node_idx: usize, styles: &[ComputedStyle], images: &ImageCache) {
+        let mut list = DisplayList::new();
+        paint_node(&mut list, document, node_idx, styles, images, &WebFonts::default());
+        rasterize(dt, &list);
+    }
+
+    fn render_background(dt: &mut DrawTarget, layout: &Layout, color: &str) {
+        let mut list = DisplayList::new();
+        paint_background(&mut list, layout, [0.0; 4], color);
+        rasterize(dt, &list);
+    }
+
+    fn render_border(dt: &mut DrawTarget, layout: &Layout, color: &str) {
+        let mut list = DisplayList::new();
+        paint_border(&mut list, layout, [0.0; 4], color);
+        rasterize(dt, &list);
+    }
+
+    fn render_text(dt: &mut DrawTarget, layout: &Layout, text: &str, text_style: &TextStyle) {
+        let mut list = DisplayList::new();
+        paint_body_text(&mut list, layout, text, text_style);
+        rasterize(dt, &list);
+    }
+
+    /// Compare rendered output with a golden master, creating it on first run
+    ///
+    /// Comparison is perceptual, so anti-aliasing and small colour drift
+    /// don't fail the test; on failure the diff images land next to the
+    /// rendered output.
+    fn assert_matches_golden_master(master_path: &str, current_output_path: &Path) {
+        let master_path = Path::new(master_path);
+        if !master_path.exists() {
             // Golden master doesn't exist, create it.
-            fs::create_dir_all(Path::new(master_path).parent().unwrap()).unwrap();
-            fs::write(master_path, &output_data).unwrap();
-            (output_data.clone(), output_data)
+            fs::create_dir_all(master_path.parent().unwrap()).unwrap();
+            fs::copy(current_output_path, master_path).unwrap();
+        }
+        let output = Image::load(current_output_path).unwrap();
+        let artifacts = current_output_path.parent().unwrap();
+        if let Err(e) = compare_with_file(&output, master_path, &DiffOptions::perceptual(), artifacts) {
+            panic!("Rendered output does not match the golden master {}: {}", master_path.display(), e);
         }
     }
 
@@ -728,6 +1626,77 @@ mod tests {
         assert_eq!(dt.height(), 2160);
     }
 
+    #[test]
+    fn test_render_region_matches_full_page() {
+        // Given: A tall page with a rounded, shadowed box far down
+        let mut doc = Document::new();
+        let elem_idx = doc.create_element("div");
+        doc.append_child(doc.root, elem_idx);
+        doc.set_layout(elem_idx, Some(Layout {
+            rect: Rect::new(30.0, 900.0, 60.0, 40.0),
+            border: EdgeSizes::uniform(2.0),
+            ..Default::default()
+        }));
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[elem_idx].background_color = Some("blue".to_string());
+        styles[elem_idx].border_color = Some("red".to_string());
+        styles[elem_idx].border_top_left_radius = Some(CSSValue::Pixels(8.0));
+        styles[elem_idx].border_bottom_right_radius = Some(CSSValue::Pixels(8.0));
+        styles[elem_idx].box_shadows = vec![BoxShadow {
+            offset_x: 4.0, offset_y: 4.0, blur_radius: 4.0, spread_radius: 0.0,
+            color: "black".to_string(), inset: false,
+        }];
+        let images = ImageCache::new();
+
+        // When: We render the whole page and just the area around the box
+        let full = render_styled_document(&doc, &styles, &images, 200, 1000);
+        let region = render_styled_region(&doc, &styles, &images, Rect::new(20.5, 890.0, 90.0, 70.0));
+
+        // Then: The region rounds out to whole pixels and matches the page
+        assert_eq!((region.width(), region.height()), (91, 70));
+        for y in 0..region.height() {
+            for x in 0..region.width() {
+                let page = full.get_data()[((890 + y) * full.width() + 20 + x) as usize];
+                assert_eq!(region.get_data()[(y * region.width() + x) as usize], page, "at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_region_outside_content_is_blank() {
+        let doc = Document::new();
+
+        let dt = render_region(&doc, Rect::new(5000.0, 5000.0, 10.0, 10.0));
+
+        assert_eq!((dt.width(), dt.height()), (10, 10));
+        assert!(dt.get_data().iter().all(|&p| p == 0xffffffff));
+    }
+
+    #[test]
+    fn test_render_at_device_pixel_ratio() {
+        // Given: A 20x10 box at (10, 5)
+        let mut doc = Document::new();
+        let elem_idx = doc.create_element("div");
+        doc.append_child(doc.root, elem_idx);
+        doc.set_layout(elem_idx, Some(Layout {
+            rect: Rect::new(10.0, 5.0, 20.0, 10.0),
+            ..Default::default()
+        }));
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[elem_idx].background_color = Some("blue".to_string());
+
+        // When: We render a 40x20 viewport at 2x
+        let dt = render_scaled_region(&doc, &styles, &ImageCache::new(), &WebFonts::default(), Rect::new(0.0, 0.0, 40.0, 20.0), 2.0);
+
+        // Then: The target is in device pixels and the box covers twice the area
+        assert_eq!((dt.width(), dt.height()), (80, 40));
+        let pixel = |x: i32, y: i32| dt.get_data()[(y * dt.width() + x) as usize];
+        assert_eq!(pixel(20, 10), 0xff0000ff, "box's top-left corner");
+        assert_eq!(pixel(59, 29), 0xff0000ff, "box's bottom-right corner");
+        assert_eq!(pixel(19, 10), 0xffffffff);
+        assert_eq!(pixel(60, 29), 0xffffffff);
+    }
+
     // ======================================================================== 
     // COLOR PARSING TESTS
     // ======================================================================== 
@@ -838,29 +1807,21 @@ mod tests {
         doc.append_child(doc.root, elem_idx);
 
         // Create layout for element
-        doc.nodes[elem_idx].layout = Some(Layout {
-            x: 10.0,
-            y: 10.0,
-            width: 100.0,
-            height: 100.0,
+        doc.set_layout(elem_idx, Some(Layout {
+            rect: Rect::new(10.0, 10.0, 100.0, 100.0),
             content_width: 100.0,
             content_height: 100.0,
-            padding_top: 0.0,
-            padding_right: 0.0,
-            padding_bottom: 0.0,
-            padding_left: 0.0,
-            margin_top: 0.0,
-            margin_right: 0.0,
-            margin_bottom: 0.0,
-            margin_left: 0.0,
-            border_width: 0.0,
+            padding: EdgeSizes::default(),
+            margin: EdgeSizes::default(),
+            border: EdgeSizes::default(),
             font_size: 16.0,
+            baseline: 0.0,
             display: super::super::dom::Display::Block,
-        });
+        }));
 
         // Manually render with background
         let mut dt = DrawTarget::new(200, 200);
-        let layout = doc.nodes[elem_idx].layout.as_ref().unwrap();
+        let layout = doc.layout(elem_idx).unwrap();
         render_background(&mut dt, layout, "red");
 
         // Then: Should complete without error
@@ -875,22 +1836,14 @@ mod tests {
     fn test_render_border_exists() {
         // Given: A layout with border
         let layout = Layout {
-            x: 50.0,
-            y: 50.0,
-            width: 100.0,
-            height: 100.0,
+            rect: Rect::new(50.0, 50.0, 100.0, 100.0),
             content_width: 100.0,
             content_height: 100.0,
-            padding_top: 0.0,
-            padding_right: 0.0,
-            padding_bottom: 0.0,
-            padding_left: 0.0,
-            margin_top: 0.0,
-            margin_right: 0.0,
-            margin_bottom: 0.0,
-            margin_left: 0.0,
-            border_width: 2.0,
+            padding: EdgeSizes::default(),
+            margin: EdgeSizes::default(),
+            border: EdgeSizes::uniform(2.0),
             font_size: 16.0,
+            baseline: 0.0,
             display: super::super::dom::Display::Block,
         };
 
@@ -906,22 +1859,14 @@ mod tests {
     fn test_render_border_zero_width_no_panic() {
         // Given: A layout with zero border width
         let layout = Layout {
-            x: 50.0,
-            y: 50.0,
-            width: 100.0,
-            height: 100.0,
+            rect: Rect::new(50.0, 50.0, 100.0, 100.0),
             content_width: 100.0,
             content_height: 100.0,
-            padding_top: 0.0,
-            padding_right: 0.0,
-            padding_bottom: 0.0,
-            padding_left: 0.0,
-            margin_top: 0.0,
-            margin_right: 0.0,
-            margin_bottom: 0.0,
-            margin_left: 0.0,
-            border_width: 0.0,
+            padding: EdgeSizes::default(),
+            margin: EdgeSizes::default(),
+            border: EdgeSizes::default(),
             font_size: 16.0,
+            baseline: 0.0,
             display: super::super::dom::Display::Block,
         };
 
@@ -941,28 +1886,20 @@ mod tests {
     fn test_render_text_with_layout() {
         // Given: A layout with font size
         let layout = Layout {
-            x: 10.0,
-            y: 10.0,
-            width: 100.0,
-            height: 50.0,
+            rect: Rect::new(10.0, 10.0, 100.0, 50.0),
             content_width: 100.0,
             content_height: 50.0,
-            padding_top: 0.0,
-            padding_right: 0.0,
-            padding_bottom: 0.0,
-            padding_left: 0.0,
-            margin_top: 0.0,
-            margin_right: 0.0,
-            margin_bottom: 0.0,
-            margin_left: 0.0,
-            border_width: 0.0,
+            padding: EdgeSizes::default(),
+            margin: EdgeSizes::default(),
+            border: EdgeSizes::default(),
             font_size: 16.0,
+            baseline: 0.0,
             display: super::super::dom::Display::Block,
         };
 
         // When: We render text
         let mut dt = DrawTarget::new(200, 200);
-        render_text(&mut dt, &layout, "Hello");
+        render_text(&mut dt, &layout, "Hello", &TextStyle::default());
 
         // Then: Should complete without error
         assert_eq!(dt.width(), 200);
@@ -972,28 +1909,20 @@ mod tests {
     fn test_render_text_empty_string_no_panic() {
         // Given: A layout with empty text
         let layout = Layout {
-            x: 10.0,
-            y: 10.0,
-            width: 100.0,
-            height: 50.0,
+            rect: Rect::new(10.0, 10.0, 100.0, 50.0),
             content_width: 100.0,
             content_height: 50.0,
-            padding_top: 0.0,
-            padding_right: 0.0,
-            padding_bottom: 0.0,
-            padding_left: 0.0,
-            margin_top: 0.0,
-            margin_right: 0.0,
-            margin_bottom: 0.0,
-            margin_left: 0.0,
-            border_width: 0.0,
+            padding: EdgeSizes::default(),
+            margin: EdgeSizes::default(),
+            border: EdgeSizes::default(),
             font_size: 16.0,
+            baseline: 0.0,
             display: super::super::dom::Display::Block,
         };
 
         // When: We render empty text
         let mut dt = DrawTarget::new(200, 200);
-        render_text(&mut dt, &layout, "");
+        render_text(&mut dt, &layout, "", &TextStyle::default());
 
         // Then: Should not panic
         assert_eq!(dt.width(), 200);
@@ -1003,30 +1932,882 @@ mod tests {
     fn test_render_text_zero_dimension_no_panic() {
         // Given: A layout with zero dimensions
         let layout = Layout {
-            x: 10.0,
-            y: 10.0,
-            width: 0.0,
-            height: 0.0,
+            rect: Rect::new(10.0, 10.0, 0.0, 0.0),
             content_width: 0.0,
             content_height: 0.0,
-            padding_top: 0.0,
-            padding_right: 0.0,
-            padding_bottom: 0.0,
-            padding_left: 0.0,
-            margin_top: 0.0,
-            margin_right: 0.0,
-            margin_bottom: 0.0,
-            margin_left: 0.0,
-            border_width: 0.0,
+            padding: EdgeSizes::default(),
+            margin: EdgeSizes::default(),
+            border: EdgeSizes::default(),
             font_size: 0.0,
+            baseline: 0.0,
             display: super::super::dom::Display::Block,
         };
 
         // When: We render text
         let mut dt = DrawTarget::new(200, 200);
-        render_text(&mut dt, &layout, "Text");
+        render_text(&mut dt, &layout, "Text", &TextStyle::default());
 
         // Then: Should not panic
         assert_eq!(dt.width(), 200);
     }
-}
\ No newline at end of file
+
+    // ======================================================================== 
+    // BORDER RADIUS TESTS
+    // ======================================================================== 
+
+    fn pixel(dt: &DrawTarget, x: i32, y: i32) -> u32 {
+        dt.get_data()[(y * dt.width() + x) as usize]
+    }
+
+    fn rounded_box_document(radius: f32) -> (Document, Vec<ComputedStyle>, usize) {
+        let mut doc = Document::new();
+        let elem_idx = doc.create_element("div");
+        doc.append_child(doc.root, elem_idx);
+        doc.set_layout(elem_idx, Some(Layout {
+            rect: Rect::new(10.0, 10.0, 100.0, 60.0),
+            ..Default::default()
+        }));
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[elem_idx].background_color = Some("red".to_string());
+        styles[elem_idx].border_top_left_radius = Some(super::super::css::CSSValue::Pixels(radius));
+        styles[elem_idx].border_top_right_radius = Some(super::super::css::CSSValue::Pixels(radius));
+        styles[elem_idx].border_bottom_right_radius = Some(super::super::css::CSSValue::Pixels(radius));
+        styles[elem_idx].border_bottom_left_radius = Some(super::super::css::CSSValue::Pixels(radius));
+        (doc, styles, elem_idx)
+    }
+
+    #[test]
+    fn test_clamp_radii_scales_overlapping_corners() {
+        // Two 80px radii on a 100px side must shrink proportionally
+        let radii = clamp_radii([80.0, 80.0, 0.0, 0.0], 100.0, 200.0);
+        assert_eq!(radii, [50.0, 50.0, 0.0, 0.0]);
+
+        // Radii that already fit are untouched
+        assert_eq!(clamp_radii([10.0, 20.0, 30.0, 40.0], 100.0, 100.0), [10.0, 20.0, 30.0, 40.0]);
+    }
+
+    #[test]
+    fn test_rounded_background_leaves_corners_unpainted() {
+        // Given: A red box with 20px rounded corners
+        let (doc, styles, _) = rounded_box_document(20.0);
+
+        // When: We render it on white
+        let mut dt = DrawTarget::new(120, 80);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: The box corner stays white while the middle is red
+        assert_eq!(pixel(&dt, 11, 11), 0xffffffff);
+        assert_eq!(pixel(&dt, 108, 68), 0xffffffff);
+        assert_eq!(pixel(&dt, 60, 40), 0xffff0000);
+        assert_eq!(pixel(&dt, 60, 11), 0xffff0000);
+    }
+
+    #[test]
+    fn test_rounded_border_draws_ring_only() {
+        // Given: A rounded box with a border and no background
+        let (mut doc, mut styles, elem_idx) = rounded_box_document(20.0);
+        styles[elem_idx].background_color = None;
+        styles[elem_idx].border_color = Some("blue".to_string());
+        doc.layout_mut(elem_idx).unwrap().border = EdgeSizes::uniform(4.0);
+
+        // When: We render it
+        let mut dt = DrawTarget::new(120, 80);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: The edge is blue, the interior and the outer corner are untouched
+        assert_eq!(pixel(&dt, 60, 11), 0xff0000ff);
+        assert_eq!(pixel(&dt, 60, 40), 0xffffffff);
+        assert_eq!(pixel(&dt, 11, 11), 0xffffffff);
+    }
+
+    #[test]
+    fn test_children_clipped_to_rounded_content_box() {
+        // Given: A rounded parent with a child covering the whole parent box
+        let (mut doc, mut styles, parent_idx) = rounded_box_document(30.0);
+        styles[parent_idx].background_color = None;
+        let child_idx = doc.create_element("div");
+        doc.append_child(parent_idx, child_idx);
+        doc.set_layout(child_idx, Some(Layout {
+            rect: Rect::new(10.0, 10.0, 100.0, 60.0),
+            ..Default::default()
+        }));
+        styles.push(ComputedStyle::default());
+        styles[child_idx].background_color = Some("green".to_string());
+
+        // When: We render it
+        let mut dt = DrawTarget::new(120, 80);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: The child's square corner is clipped away
+        assert_eq!(pixel(&dt, 11, 11), 0xffffffff);
+        assert_eq!(pixel(&dt, 60, 40), 0xff008000);
+    }
+
+    #[test]
+    fn test_transformed_box_and_children_paint_moved() {
+        // Given: A red box moved right and scaled down, with a green child
+        let (mut doc, mut styles, parent_idx) = rounded_box_document(0.0);
+        styles[parent_idx].transform = crate::transform::parse_transform("translateX(50px) scale(0.5)").unwrap();
+        let child_idx = doc.create_element("div");
+        doc.append_child(parent_idx, child_idx);
+        doc.set_layout(child_idx, Some(Layout { rect: Rect::new(10.0, 10.0, 50.0, 60.0), ..Default::default() }));
+        styles.push(ComputedStyle::default());
+        styles[child_idx].background_color = Some("green".to_string());
+
+        // When: We render it
+        let mut dt = DrawTarget::new(200, 80);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: Both paint inside the transformed box, from x 85 to 135
+        assert_eq!(pixel(&dt, 20, 40), 0xffffffff, "Nothing paints at the untransformed spot");
+        assert_eq!(pixel(&dt, 90, 40), 0xff008000);
+        assert_eq!(pixel(&dt, 125, 40), 0xffff0000);
+        assert_eq!(pixel(&dt, 140, 40), 0xffffffff);
+        assert_eq!(pixel(&dt, 90, 22), 0xffffffff, "The box is half as tall");
+    }
+
+    // ======================================================================== 
+    // BOX SHADOW AND TEXT DECORATION TESTS
+    // ======================================================================== 
+
+    #[test]
+    fn test_parse_color_rgba_format() {
+        assert_eq!(parse_color_to_argb("rgba(255, 0, 0, 0.5)"), 0x80ff0000);
+        assert_eq!(parse_color_to_argb("rgba(0, 0, 0, 1)"), 0xff000000);
+    }
+
+    #[test]
+    fn test_box_shadow_paints_outside_box_only() {
+        // Given: A box with a hard, offset blue shadow and no background
+        let (doc, mut styles, elem_idx) = rounded_box_document(0.0);
+        styles[elem_idx].background_color = None;
+        styles[elem_idx].box_shadows = vec![BoxShadow {
+            offset_x: 5.0,
+            offset_y: 5.0,
+            blur_radius: 0.0,
+            spread_radius: 0.0,
+            color: "blue".to_string(),
+            inset: false,
+        }];
+
+        // When: We render it
+        let mut dt = DrawTarget::new(130, 90);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: Only the offset strip outside the border box is shadowed
+        assert_eq!(pixel(&dt, 112, 40), 0xff0000ff);
+        assert_eq!(pixel(&dt, 60, 72), 0xff0000ff);
+        assert_eq!(pixel(&dt, 100, 60), 0xffffffff);
+        assert_eq!(pixel(&dt, 12, 72), 0xffffffff);
+    }
+
+    #[test]
+    fn test_box_shadow_blur_fades_out() {
+        // Given: A centered shadow with blur
+        let (doc, mut styles, elem_idx) = rounded_box_document(0.0);
+        styles[elem_idx].box_shadows = vec![BoxShadow {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            blur_radius: 8.0,
+            spread_radius: 0.0,
+            color: "black".to_string(),
+            inset: false,
+        }];
+
+        // When: We render it
+        let mut dt = DrawTarget::new(130, 90);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: The shadow darkens next to the edge and fades with distance
+        let red = |x: i32| (pixel(&dt, x, 40) >> 16) & 0xff;
+        assert!(red(111) < red(114));
+        assert!(red(114) < red(118));
+        assert_eq!(pixel(&dt, 125, 40), 0xffffffff);
+    }
+
+    #[test]
+    fn test_inset_box_shadow_paints_inside_box_only() {
+        // Given: A box with a hard blue inset shadow offset down and right
+        let (doc, mut styles, elem_idx) = rounded_box_document(0.0);
+        styles[elem_idx].box_shadows = vec![BoxShadow {
+            offset_x: 6.0,
+            offset_y: 6.0,
+            blur_radius: 0.0,
+            spread_radius: 0.0,
+            color: "blue".to_string(),
+            inset: true,
+        }];
+
+        // When: We render it
+        let mut dt = DrawTarget::new(130, 90);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: The top and left inner edges are shadowed over the
+        // background, and nothing is painted outside the box
+        assert_eq!(pixel(&dt, 60, 12), 0xff0000ff);
+        assert_eq!(pixel(&dt, 12, 40), 0xff0000ff);
+        assert_eq!(pixel(&dt, 60, 40), 0xffff0000);
+        assert_eq!(pixel(&dt, 106, 66), 0xffff0000);
+        assert_eq!(pixel(&dt, 115, 75), 0xffffffff);
+    }
+
+    #[test]
+    fn test_huge_box_shadow_blur_is_clamped() {
+        // Given: A shadow blurred far past any screen
+        let (doc, mut styles, elem_idx) = rounded_box_document(0.0);
+        styles[elem_idx].box_shadows = vec![BoxShadow {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            blur_radius: 100000.0,
+            spread_radius: 100000.0,
+            color: "black".to_string(),
+            inset: false,
+        }];
+
+        // When: We render it
+        let mut dt = DrawTarget::new(130, 90);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        render_node(&mut dt, &doc, doc.root, &styles, &ImageCache::new());
+
+        // Then: It paints with the clamped extent instead of allocating a
+        // mask for the full one
+        assert_ne!(pixel(&dt, 125, 85), 0xffffffff);
+        assert_eq!(pixel(&dt, 60, 40), 0xffff0000);
+    }
+
+    #[test]
+    fn test_gaussian_blur_preserves_mass() {
+        let mut alpha = vec![0u8; 21 * 21];
+        for y in 8..13 {
+            for x in 8..13 {
+                alpha[y * 21 + x] = 255;
+            }
+        }
+        let before: u32 = alpha.iter().map(|&a| a as u32).sum();
+
+        gaussian_blur_alpha(&mut alpha, 21, 21, 1.5);
+
+        let after: u32 = alpha.iter().map(|&a| a as u32).sum();
+        assert!(alpha[10 * 21 + 10] < 255);
+        assert!(alpha[10 * 21 + 6] > 0);
+        assert!((before as i64 - after as i64).abs() < 200);
+    }
+
+    #[test]
+    fn test_text_decoration_propagates_to_text() {
+        // Given: A link with underline containing a text node
+        let mut doc = Document::new();
+        let link_idx = doc.create_element("a");
+        let text_idx = doc.create_text_node("Go");
+        doc.append_child(doc.root, link_idx);
+        doc.append_child(link_idx, text_idx);
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[link_idx].text_decoration = TextDecoration { underline: true, ..Default::default() };
+
+        // Then: The text inherits the decoration
+        let decoration = propagated_text_decoration(&doc, text_idx, &styles);
+        assert!(decoration.underline);
+        assert!(!decoration.line_through);
+    }
+
+    /// Rows where render_text paints each decoration for the first line
+    /// (glyph box at y = 6, 22px tall)
+    fn decoration_rows() -> (i32, i32, i32) {
+        let metrics = default_decoration_metrics(22.0);
+        let baseline = 28.0;
+        let underline = (baseline + metrics.underline_offset).round() as i32;
+        let overline = (baseline - default_line_metrics(22.0).ascent).round() as i32;
+        let line_through = (baseline - metrics.strikeout_offset).round() as i32;
+        (underline, overline, line_through)
+    }
+
+    fn render_decorated(decoration: TextDecoration) -> DrawTarget {
+        let layout = Layout { rect: Rect::new(0.0, 0.0, 100.0, 50.0), ..Default::default() };
+        let mut dt = DrawTarget::new(100, 50);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        // Preserved spaces: decorated, with no glyphs in the way
+        render_text(&mut dt, &layout, "  ", &TextStyle { decoration, white_space: WhiteSpace::Pre, ..Default::default() });
+        dt
+    }
+
+    #[test]
+    fn test_decorations_placed_from_font_metrics() {
+        let (underline_y, overline_y, line_through_y) = decoration_rows();
+
+        // Underline just below the baseline, overline near the top of the
+        // glyph box, line-through in between
+        assert!((28..=31).contains(&underline_y));
+        assert!((6..=10).contains(&overline_y));
+        assert!(overline_y < line_through_y && line_through_y < 28);
+
+        let dt = render_decorated(TextDecoration { underline: true, ..Default::default() });
+        assert_eq!(pixel(&dt, 10, underline_y), 0xff000000);
+        assert_eq!(pixel(&dt, 10, line_through_y), 0xffffffff);
+
+        let dt = render_decorated(TextDecoration { line_through: true, ..Default::default() });
+        assert_eq!(pixel(&dt, 10, line_through_y), 0xff000000);
+        assert_eq!(pixel(&dt, 10, underline_y), 0xffffffff);
+
+        let dt = render_decorated(TextDecoration { overline: true, ..Default::default() });
+        assert_eq!(pixel(&dt, 10, overline_y), 0xff000000);
+        assert_eq!(pixel(&dt, 10, underline_y), 0xffffffff);
+    }
+
+    #[test]
+    fn test_decoration_color_overrides_text_color() {
+        let (underline_y, _, _) = decoration_rows();
+        let dt = render_decorated(TextDecoration {
+            underline: true,
+            color: Some("red".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(pixel(&dt, 10, underline_y), 0xffff0000);
+    }
+
+    #[test]
+    fn test_innermost_decoration_color_wins() {
+        // Given: A gray strikethrough price inside a red-underlined link
+        let mut doc = Document::new();
+        let link_idx = doc.create_element("a");
+        let price_idx = doc.create_element("s");
+        let text_idx = doc.create_text_node("$10");
+        doc.append_child(doc.root, link_idx);
+        doc.append_child(link_idx, price_idx);
+        doc.append_child(price_idx, text_idx);
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[link_idx].text_decoration = TextDecoration { underline: true, color: Some("red".to_string()), ..Default::default() };
+        styles[price_idx].text_decoration = TextDecoration { line_through: true, color: Some("gray".to_string()), ..Default::default() };
+
+        // Then: Lines combine and the nearest color applies
+        let decoration = propagated_text_decoration(&doc, text_idx, &styles);
+        assert!(decoration.underline && decoration.line_through);
+        assert_eq!(decoration.color, Some("gray".to_string()));
+    }
+
+    // ========================================================================
+    // TEXT SPACING AND TRANSFORM TESTS
+    // ========================================================================
+
+    #[test]
+    fn test_text_style_inherits_spacing_and_transform() {
+        // Given: A button label styled on the button, with the span overriding spacing
+        let mut doc = Document::new();
+        let button_idx = doc.create_element("button");
+        let span_idx = doc.create_element("span");
+        let text_idx = doc.create_text_node("buy");
+        doc.append_child(doc.root, button_idx);
+        doc.append_child(button_idx, span_idx);
+        doc.append_child(span_idx, text_idx);
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[button_idx].text_transform = Some(TextTransform::Uppercase);
+        styles[button_idx].letter_spacing = Some(CSSValue::Pixels(1.0));
+        styles[span_idx].letter_spacing = Some(CSSValue::Pixels(3.0));
+
+        // When: We resolve the text style
+        let text_style = resolve_text_style(&doc, text_idx, &styles, &WebFonts::default());
+
+        // Then: The nearest declaration wins for each property
+        assert_eq!(text_style.transform, TextTransform::Uppercase);
+        assert_eq!(text_style.letter_spacing, 3.0);
+        assert_eq!(text_style.word_spacing, 0.0);
+    }
+
+    #[test]
+    fn test_letter_and_word_spacing_widen_text() {
+        let layout = Layout { rect: Rect::new(0.0, 0.0, 200.0, 50.0), ..Default::default() };
+        let underline = TextDecoration { underline: true, ..Default::default() };
+        let (underline_y, _, _) = decoration_rows();
+
+        // The underline spans exactly the advanced width of the line
+        let underline_end = |text_style: &TextStyle| {
+            let mut dt = DrawTarget::new(200, 50);
+            dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+            render_text(&mut dt, &layout, "a b", text_style);
+            (0..200).rev().find(|&x| pixel(&dt, x, underline_y) == 0xff000000).unwrap()
+        };
+
+        let plain = underline_end(&TextStyle { decoration: underline.clone(), ..Default::default() });
+        let letter = underline_end(&TextStyle {
+            decoration: underline.clone(),
+            letter_spacing: 4.0,
+            ..Default::default()
+        });
+        let word = underline_end(&TextStyle {
+            decoration: underline,
+            word_spacing: 10.0,
+            ..Default::default()
+        });
+
+        assert_eq!(letter - plain, 12);
+        assert_eq!(word - plain, 10);
+    }
+
+    // ========================================================================
+    // IMAGE TESTS
+    // ========================================================================
+
+    #[test]
+    fn test_img_drawn_scaled_into_box() {
+        // Given: An <img> laid out at 10,10 sized 40x20 with a 2x1 image loaded
+        let mut doc = Document::new();
+        let img_idx = doc.create_element("img");
+        doc.append_child(doc.root, img_idx);
+        doc.set_layout(img_idx, Some(Layout { rect: Rect::new(10.0, 10.0, 40.0, 20.0), ..Default::default() }));
+        let styles = vec![ComputedStyle::default(); doc.nodes.len()];
+
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 0, 0, 255, 0, 0, 255, 255]).unwrap();
+        }
+        let network = crate::network::MockNetwork::new().with_response("/pair.png", bytes);
+        doc.set_attribute(img_idx, "src", "/pair.png");
+        let mut images = ImageCache::new();
+        crate::images::load_document_images(&doc, &styles, &network, &mut images);
+
+        // When: We render
+        let dt = render_styled_document(&doc, &styles, &images, 60, 40);
+
+        // Then: The left half is red, the right half blue, outside untouched
+        assert_eq!(pixel(&dt, 12, 20), 0xffff0000);
+        assert_eq!(pixel(&dt, 47, 20), 0xff0000ff);
+        assert_eq!(pixel(&dt, 5, 20), 0xffffffff);
+        assert_eq!(pixel(&dt, 55, 20), 0xffffffff);
+    }
+
+    #[test]
+    fn test_missing_image_leaves_box_empty() {
+        let mut doc = Document::new();
+        let div_idx = doc.create_element("div");
+        doc.append_child(doc.root, div_idx);
+        doc.set_layout(div_idx, Some(Layout { rect: Rect::new(0.0, 0.0, 20.0, 20.0), ..Default::default() }));
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        styles[div_idx].background_image = Some("/never-loaded.png".to_string());
+
+        let dt = render_styled_document(&doc, &styles, &ImageCache::new(), 20, 20);
+
+        assert_eq!(pixel(&dt, 10, 10), 0xffffffff);
+    }
+
+    // ========================================================================
+    // DISPLAY LIST TESTS
+    // ========================================================================
+
+    /// Command names in paint order, for asserting on the stream
+    fn command_names(list: &DisplayList) -> Vec<&'static str> {
+        list.iter()
+            .map(|command| match command {
+                PaintCommand::Rect { .. } => "Rect",
+                PaintCommand::Border { .. } => "Border",
+                PaintCommand::BoxShadow { .. } => "BoxShadow",
+                PaintCommand::Text { .. } => "Text",
+                PaintCommand::Image { .. } => "Image",
+                PaintCommand::FillPath { .. } => "FillPath",
+                PaintCommand::StrokePath { .. } => "StrokePath",
+                PaintCommand::PushClip { .. } => "PushClip",
+                PaintCommand::PopClip => "PopClip",
+                PaintCommand::PushTransform { .. } => "PushTransform",
+                PaintCommand::PopTransform => "PopTransform",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_display_list_paint_order() {
+        // Given: A rounded, bordered, shadowed box containing a child box
+        let (mut doc, mut styles, parent_idx) = rounded_box_document(10.0);
+        doc.layout_mut(parent_idx).unwrap().border = EdgeSizes::uniform(2.0);
+        styles[parent_idx].border_color = Some("blue".to_string());
+        styles[parent_idx].box_shadows = vec![BoxShadow {
+            offset_x: 2.0, offset_y: 2.0, blur_radius: 0.0, spread_radius: 0.0,
+            color: "black".to_string(), inset: false,
+        }];
+        let child_idx = doc.create_element("div");
+        doc.append_child(parent_idx, child_idx);
+        doc.set_layout(child_idx, Some(Layout { rect: Rect::new(20.0, 20.0, 10.0, 10.0), ..Default::default() }));
+        styles.push(ComputedStyle::default());
+        styles[child_idx].background_color = Some("green".to_string());
+
+        // When: We build the display list
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+
+        // Then: Shadow, background and border come first, then the child
+        // inside the parent's content clip
+        assert_eq!(command_names(&list), vec!["BoxShadow", "Rect", "Border", "PushClip", "Rect", "PopClip"]);
+        match &list.commands()[1] {
+            PaintCommand::Rect { rect, radii, color } => {
+                assert_eq!(*rect, Rect::new(10.0, 10.0, 100.0, 60.0));
+                assert_eq!(*radii, [10.0; 4]);
+                assert_eq!(*color, 0xffff0000);
+            }
+            other => panic!("expected the background, got {:?}", other),
+        }
+        match &list.commands()[3] {
+            PaintCommand::PushClip { rect, radii } => {
+                assert_eq!(*rect, Rect::new(12.0, 12.0, 96.0, 56.0));
+                assert_eq!(*radii, [8.0; 4]);
+            }
+            other => panic!("expected the content clip, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_display_list_text_lines_and_decorations() {
+        // Given: Underlined text that wraps onto two lines
+        let layout = Layout { rect: Rect::new(0.0, 0.0, 60.0, 100.0), ..Default::default() };
+        let text_style = TextStyle { decoration: TextDecoration { underline: true, ..Default::default() }, ..Default::default() };
+
+        // When: We record it
+        let mut list = DisplayList::new();
+        paint_body_text(&mut list, &layout, "ab cd", &text_style);
+
+        // Then: Each line is a text command followed by its underline
+        assert_eq!(command_names(&list), vec!["Text", "Rect", "Text", "Rect"]);
+        let lines: Vec<(f32, &str)> = list
+            .iter()
+            .filter_map(|command| match command {
+                PaintCommand::Text { y, text, .. } => Some((*y, text.trim_end())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lines, vec![(6.0, "ab"), (34.0, "cd")]);
+    }
+
+    #[test]
+    fn test_white_space_controls_collapsing_and_wrapping() {
+        // Given: Indented, multi-line text in a box two words wide
+        let layout = Layout { rect: Rect::new(0.0, 0.0, 100.0, 200.0), ..Default::default() };
+        let text = "\n  ab   cd\n  ef\n";
+        let lines = |white_space: WhiteSpace| {
+            let mut list = DisplayList::new();
+            paint_body_text(&mut list, &layout, text, &TextStyle { white_space, ..Default::default() });
+            list.iter()
+                .filter_map(|command| match command {
+                    PaintCommand::Text { text, .. } => Some(text.trim_end().to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Then: normal collapses and wraps, nowrap collapses onto one line,
+        // and pre keeps every space and newline
+        assert_eq!(lines(WhiteSpace::Normal), vec!["ab cd", "ef"]);
+        assert_eq!(lines(WhiteSpace::Nowrap), vec!["ab cd ef"]);
+        assert_eq!(lines(WhiteSpace::Pre), vec!["", "  ab   cd", "  ef"]);
+    }
+
+    #[test]
+    fn test_long_words_break_or_end_in_an_ellipsis() {
+        // Given: A box six glyphs wide and text with one long word
+        let layout = Layout { rect: Rect::new(0.0, 0.0, 100.0, 200.0), ..Default::default() };
+        let text = "ab cdefghij";
+        let lines = |text_style: TextStyle| {
+            let mut list = DisplayList::new();
+            paint_body_text(&mut list, &layout, text, &text_style);
+            list.iter()
+                .filter_map(|command| match command {
+                    PaintCommand::Text { text, .. } => Some(text.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Then: The word overflows unless word breaking allows a break in it
+        assert_eq!(lines(TextStyle::default()), vec!["ab", "cdefghij"]);
+        assert_eq!(lines(TextStyle { breaking: WordBreaking::Overlong, ..Default::default() }), vec!["ab", "cdefgh", "ij"]);
+        assert_eq!(lines(TextStyle { breaking: WordBreaking::Anywhere, ..Default::default() }), vec!["ab cde", "fghij"]);
+
+        // And an ellipsis cuts the one line nowrap leaves to fit the box
+        let ellipsis = TextStyle { white_space: WhiteSpace::Nowrap, ellipsis: true, ..Default::default() };
+        assert_eq!(lines(ellipsis), vec!["ab cd…"]);
+    }
+
+    #[test]
+    fn test_overflow_hidden_clips_children_and_enables_ellipsis() {
+        // Given: A box that hides its overflow with text-overflow: ellipsis
+        let mut doc = crate::parser::parse_html("<div>Overflowing</div>");
+        let css = "div { overflow: hidden; text-overflow: ellipsis; }";
+        let styles = crate::style::compute_styles(&doc, &crate::css::parse_css(css));
+        crate::layout::calculate_styled_layout(&mut doc, &styles, 100.0, 300.0);
+        let div_idx = (0..doc.nodes.len()).find(|&idx| styles[idx].overflow.is_some()).unwrap();
+
+        // When: We record the display list
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+
+        // Then: Its children are clipped to the padding box
+        let padding_box = doc.layout(div_idx).unwrap().padding_box();
+        assert!(list.iter().any(|command| matches!(command, PaintCommand::PushClip { rect, .. } if *rect == padding_box)));
+
+        // And its text ends lines that do not fit in an ellipsis
+        let text_idx = doc.nodes[div_idx].children[0];
+        assert!(resolve_text_style(&doc, text_idx, &styles, &WebFonts::default()).ellipsis);
+        assert!(!resolve_text_style(&doc, div_idx, &styles, &WebFonts::default()).ellipsis);
+    }
+
+    #[test]
+    fn test_text_is_painted_on_its_inline_layout_baseline() {
+        // Given: A paragraph whose text continues in a bold element
+        let mut doc = crate::parser::parse_html("<p>Hello <strong>world</strong></p>");
+        let styles = crate::style::compute_styles(&doc, &crate::css::StyleSheet::default());
+        crate::layout::calculate_styled_layout(&mut doc, &styles, 400.0, 300.0);
+
+        // When: We record the display list
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+        let texts: Vec<(f32, f32, String, bool)> = list.iter()
+            .filter_map(|command| match command {
+                PaintCommand::Text { x, y, text, glyph, .. } => Some((*x, *y, text.clone(), glyph.bold)),
+                _ => None,
+            })
+            .collect();
+
+        // Then: Each run starts at its box, its glyphs standing on the baseline
+        let paragraph = &doc.nodes[doc.nodes[doc.root].children[0]];
+        let (hello, world) = (paragraph.children[0], doc.nodes[paragraph.children[1]].children[0]);
+        let glyph_top = |idx: usize| {
+            let layout = doc.layout(idx).unwrap();
+            (layout.rect.x, layout.rect.y + layout.baseline - 16.0)
+        };
+        assert_eq!(texts, vec![
+            (glyph_top(hello).0, glyph_top(hello).1, "Hello ".to_string(), false),
+            (glyph_top(world).0, glyph_top(world).1, "world".to_string(), true),
+        ]);
+    }
+
+    #[test]
+    fn test_list_markers_are_painted_in_the_item_color() {
+        // Given: A red ordered list and an unordered one, laid out
+        let mut doc = crate::parser::parse_html("<ol><li>One</li></ol><ul><li>Dot</li></ul>");
+        let mut styles = vec![ComputedStyle::default(); doc.nodes.len()];
+        let ol_idx = doc.nodes[doc.root].children[0];
+        styles[ol_idx].color = Some("red".to_string());
+        crate::layout::calculate_layout(&mut doc, 400.0, 300.0);
+
+        // When: We record the display list
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+
+        // Then: The number is red text and the bullet a round black box,
+        // each in its marker box
+        let [number_item, bullet_item] = [doc.nodes[ol_idx].children[0], doc.nodes[doc.nodes[doc.root].children[1]].children[0]];
+        let number_rect = doc.list_marker(number_item).unwrap().rect;
+        let bullet_rect = doc.list_marker(bullet_item).unwrap().rect;
+        assert!(list.iter().any(|command| matches!(command,
+            PaintCommand::Text { x, y, text, glyph, .. } if text == "1." && *x == number_rect.x && *y == number_rect.y && glyph.color == 0xffff0000)));
+        assert!(list.iter().any(|command| matches!(command,
+            PaintCommand::Rect { rect, radii, color: 0xff000000 } if *rect == bullet_rect && radii[0] == rect.width / 2.0)));
+    }
+
+    #[test]
+    fn test_text_is_painted_from_the_user_agent_styles() {
+        // Given: A heading and a paragraph with a colored word, laid out
+        // under the user agent stylesheet
+        let mut doc = crate::parser::parse_html("<h1>Title</h1><p>Plain <em>blue</em></p>");
+        let styles = crate::style::compute_styles(&doc, &crate::css::parse_css("em { color: blue; }"));
+        crate::layout::calculate_styled_layout(&mut doc, &styles, 800.0, 600.0);
+
+        // When: We paint each text node in a box tall enough for its glyphs
+        let glyph = |word: &str| {
+            let idx = (0..doc.nodes.len()).find(|&idx| matches!(&doc.nodes[idx].data, Some(NodeData::Text(text)) if text.trim() == word)).unwrap();
+            let mut layout = doc.layout(idx).unwrap().clone();
+            layout.rect.height = 200.0;
+            let mut list = DisplayList::new();
+            paint_body_text(&mut list, &layout, word, &resolve_text_style(&doc, idx, &styles, &WebFonts::default()));
+            list.iter()
+                .find_map(|command| match command {
+                    PaintCommand::Text { glyph, .. } => Some(*glyph),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        // Then: The heading is bold at twice the body size, and the word
+        // takes its element's color
+        assert_eq!(glyph("Title").height, 2.0 * glyph("Plain").height);
+        assert!(glyph("Title").bold && !glyph("Plain").bold);
+        assert_eq!((glyph("Plain").color, glyph("blue").color), (0xff000000, 0xff0000ff));
+    }
+
+    #[test]
+    fn test_text_in_a_loaded_family_is_drawn_with_its_glyphs() {
+        // Given: A paragraph inheriting a family that has been loaded
+        let mut doc = crate::parser::parse_html("<body><p>Ag</p></body>");
+        let styles = crate::style::compute_styles(&doc, &crate::css::parse_css("body { font-family: 'Icons', serif; }"));
+        crate::layout::calculate_styled_layout(&mut doc, &styles, 400.0, 300.0);
+        let mut fonts = crate::fonts::FontManager::new().unwrap();
+        fonts.add_font("Icons", include_bytes!("../assets/DejaVuSansMono.ttf")).unwrap();
+
+        // When: We paint the text in a box tall enough for its glyphs
+        let idx = (0..doc.nodes.len()).find(|&idx| matches!(&doc.nodes[idx].data, Some(NodeData::Text(text)) if text == "Ag")).unwrap();
+        let mut layout = doc.layout(idx).unwrap().clone();
+        layout.rect.height = 200.0;
+        let mut list = DisplayList::new();
+        paint_body_text(&mut list, &layout, "Ag", &resolve_text_style(&doc, idx, &styles, fonts.web_fonts()));
+
+        // Then: The line carries the font, and rasterizes differently from
+        // the built-in glyphs
+        let command = list.iter().find(|command| matches!(command, PaintCommand::Text { .. })).unwrap().clone();
+        let PaintCommand::Text { x, y, text, glyph, font } = command.clone() else { unreachable!() };
+        assert_eq!(font.as_ref().map(|font| font.family.as_str()), Some("Icons"));
+        let draw = |command: PaintCommand| {
+            let mut dt = DrawTarget::new(100, 100);
+            let mut list = DisplayList::new();
+            list.push(command);
+            rasterize(&mut dt, &list);
+            dt.get_data().to_vec()
+        };
+        let web = draw(command);
+        assert!(web.iter().any(|&pixel| pixel != 0));
+        assert_ne!(web, draw(PaintCommand::Text { x, y, text, glyph, font: None }));
+    }
+
+    #[test]
+    fn test_characters_fall_back_through_the_font_chain() {
+        // Given: A family loaded from the default font, which has no emoji,
+        // ahead of a fallback font
+        let mut fonts = crate::fonts::FontManager::new().unwrap();
+        fonts.add_font("Brand", include_bytes!("../assets/DejaVuSansMono.ttf")).unwrap();
+        fonts.add_fallback_font(include_bytes!("../assets/DejaVuSansMono.ttf")).unwrap();
+        let chain = fonts.web_fonts().fallback_chain(Some("Brand"));
+
+        // When: We split text with an emoji and a skin tone into runs
+        let runs: Vec<(String, Option<String>)> = font_runs("Hi 👍🏽!", &chain)
+            .into_iter()
+            .map(|(run, font)| (run, font.map(|font| font.family.clone())))
+            .collect();
+
+        // Then: Characters take the first font that has them, the emoji
+        // keeps its modifier, and what no font has goes to the built-in glyphs
+        assert_eq!(chain.len(), 2);
+        assert_eq!(runs, vec![
+            ("Hi ".to_string(), Some("Brand".to_string())),
+            ("👍🏽".to_string(), None),
+            ("!".to_string(), Some("Brand".to_string())),
+        ]);
+        assert_eq!(font_runs("", &chain), vec![(String::new(), None)]);
+    }
+
+    #[test]
+    fn test_built_in_glyphs_fall_back_to_the_default_font_and_emoji_outlines() {
+        // Given: Built-in glyph boxes
+        let glyph = GlyphStyle { width: 14.0, height: 22.0, letter_spacing: 0.0, word_spacing: 0.0, color: 0xff000000, bold: false };
+        let draw = |text: &str| {
+            let mut dt = DrawTarget::new(40, 40);
+            let mut list = DisplayList::new();
+            list.push(PaintCommand::Text { x: 4.0, y: 4.0, text: text.to_string(), glyph, font: None });
+            rasterize(&mut dt, &list);
+            dt.get_data().to_vec()
+        };
+        let missing = draw("\u{E000}");
+
+        // Then: Characters without a pattern use the default font, emoji no
+        // font has get an outline, and only the rest are boxes
+        for text in ["é", "K", "😀"] {
+            let pixels = draw(text);
+            assert!(pixels.iter().any(|&pixel| pixel != 0), "{} paints", text);
+            assert_ne!(pixels, missing, "{} is not a missing-glyph box", text);
+        }
+        assert!(missing.iter().any(|&pixel| pixel != 0));
+
+        // And a skin tone modifies the emoji in place rather than adding a glyph
+        assert_eq!(draw("👍🏽"), draw("👍"));
+        assert_eq!(glyph.advance('\u{1F3FD}'), 0.0);
+    }
+
+    #[test]
+    fn test_visibility_hidden_paints_nothing_but_visible_children() {
+        // Given: A hidden red box holding a blue child that is visible again
+        let mut doc = crate::parser::parse_html("<div><p>x</p></div>");
+        let css = "div { visibility: hidden; background-color: red; } p { visibility: visible; background-color: blue; }";
+        let styles = crate::style::compute_styles(&doc, &crate::css::parse_css(css));
+        crate::layout::calculate_styled_layout(&mut doc, &styles, 400.0, 300.0);
+
+        // When: We record the display list
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+        let colors: Vec<u32> = list.iter()
+            .filter_map(|command| match command {
+                PaintCommand::Rect { color, .. } => Some(*color),
+                _ => None,
+            })
+            .collect();
+
+        // Then: Only the child's background is painted
+        assert_eq!(colors, vec![0xff0000ff]);
+    }
+
+    #[test]
+    fn test_display_list_rasterizes_like_direct_paint() {
+        // Given: The display list of a rounded box
+        let (doc, styles, _) = rounded_box_document(20.0);
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+
+        // When: We rasterize it twice onto fresh targets
+        let paint = || {
+            let mut dt = DrawTarget::new(120, 80);
+            dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+            rasterize(&mut dt, &list);
+            dt
+        };
+
+        // Then: The retained list replays to the same pixels as a full render
+        let expected = render_styled_document(&doc, &styles, &ImageCache::new(), 120, 80);
+        assert_eq!(paint().get_data(), expected.get_data());
+        assert_eq!(paint().get_data(), expected.get_data());
+    }
+
+    #[test]
+    fn test_display_list_empty_document() {
+        assert!(build_display_list(&Document::new(), &[], &ImageCache::new(), &WebFonts::default()).is_empty());
+    }
+
+    #[test]
+    fn test_incremental_update_matches_full_render() {
+        // Given: A frame of a red rounded box, kept at 2x
+        let (doc, mut styles, elem_idx) = rounded_box_document(20.0);
+        let region = Rect::new(0.0, 0.0, 120.0, 80.0);
+        let mut frame = IncrementalRenderer::new(region, 2.0);
+        frame.update(build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default()));
+
+        // When: The box turns blue and the frame is updated
+        styles[elem_idx].background_color = Some("blue".to_string());
+        let damage = frame.update(build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default()));
+
+        // Then: Only the box was repainted, and the frame matches a full
+        // render pixel for pixel
+        assert_eq!(damage, vec![Rect::new(9.0, 9.0, 102.0, 62.0)]);
+        let expected = render_scaled_region(&doc, &styles, &ImageCache::new(), &WebFonts::default(), region, 2.0);
+        assert_eq!(frame.target().get_data(), expected.get_data());
+
+        // And: An unchanged list damages nothing
+        assert!(frame.damage(&build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default())).is_empty());
+    }
+
+    #[test]
+    fn test_repaint_damage_leaves_other_pixels_alone() {
+        // Given: A frame scribbled on outside the damaged area
+        let (doc, styles, _) = rounded_box_document(0.0);
+        let list = build_display_list(&doc, &styles, &ImageCache::new(), &WebFonts::default());
+        let region = Rect::new(0.0, 0.0, 120.0, 80.0);
+        let mut dt = DrawTarget::new(120, 80);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
+
+        // When: Only the top-left corner of the box is repainted
+        repaint_damage(&mut dt, &list, region, 1.0, &[Rect::new(5.5, 5.5, 10.0, 10.0)]);
+
+        // Then: The rounded-out area is repainted and the rest is untouched
+        let pixel = |x: usize, y: usize| dt.get_data()[y * 120 + x];
+        assert_eq!(pixel(5, 5), 0xffffffff);
+        assert_eq!(pixel(12, 12), 0xffff0000);
+        assert_eq!(pixel(15, 15), 0xffff0000);
+        assert_eq!(pixel(16, 16), 0xff000000);
+        assert_eq!(pixel(4, 4), 0xff000000);
+    }
+}
@@ -0,0 +1,614 @@
+//! REPL
+//! An interactive prompt evaluating JavaScript and dot commands against a
+//! loaded page
+//!
+//! Entries run with `Page::run_script`; `.help` lists the commands. An entry
+//! with open brackets, strings or comments continues on the next line. On a
+//! terminal lines are edited in place, and piped input is read as is.
+
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::Path;
+
+use crate::browser::Page;
+use crate::dom::{Document, NodeData};
+use crate::error::BrowserError;
+use crate::serialize::{escape_attribute, escape_text};
+
+/// Prompt for a new entry
+pub const PROMPT: &str = "> ";
+/// Prompt for the next line of an unfinished entry
+pub const CONTINUATION_PROMPT: &str = "... ";
+
+const HELP: &str = "\
+.query <selector>    List the elements matching a CSS selector
+.layout              Lay out the page and print the layout tree
+.inspect [selector]  Print the body, or the first match, with attributes, style and boxes
+.screenshot [path]   Save the viewport (default: screenshot.png)
+.load <path>         Run a script file
+.help                Show this help
+.exit                Quit (or Ctrl-D)
+Anything else is evaluated as JavaScript against the page.";
+
+/// What entering a line did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// The entry continues on the next line
+    More,
+    /// The entry ran; what to print, if anything
+    Output(String),
+    Exit,
+}
+
+/// A REPL session on `page`, collecting lines into entries
+pub struct Repl<'a> {
+    page: &'a Page,
+    /// Lines of the unfinished entry
+    pending: String,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(page: &'a Page) -> Self {
+        Repl { page, pending: String::new() }
+    }
+
+    /// The prompt for the next line
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+
+    /// Discard the unfinished entry, as Ctrl-C does
+    pub fn interrupt(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Add `line` to the entry, running it once complete
+    pub fn enter(&mut self, line: &str) -> Step {
+        if self.pending.is_empty() && line.trim_start().starts_with('.') {
+            return self.command(line.trim());
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if !is_complete(&self.pending) {
+            return Step::More;
+        }
+        let source = std::mem::take(&mut self.pending);
+        if source.trim().is_empty() {
+            return Step::Output(String::new());
+        }
+        Step::Output(describe_result(self.page.run_script_named(&source, "<repl>")))
+    }
+
+    fn command(&mut self, line: &str) -> Step {
+        let (name, argument) = line.split_once(char::is_whitespace).map_or((line, ""), |(name, argument)| (name, argument.trim()));
+        let output = match (name, argument) {
+            (".exit", _) => return Step::Exit,
+            (".help", _) => HELP.to_string(),
+            (".query", "") => "Usage: .query <selector>".to_string(),
+            (".query", selector) => self.query(selector),
+            (".layout", _) => self.page.layout_tree().trim_end().to_string(),
+            (".inspect", selector) => match self.page.inspect(Some(selector).filter(|selector| !selector.is_empty())) {
+                Ok(tree) => tree.to_string().trim_end().to_string(),
+                Err(e) => e.to_string(),
+            },
+            (".screenshot", path) => {
+                let path = if path.is_empty() { "screenshot.png" } else { path };
+                match self.page.screenshot(Path::new(path)) {
+                    Ok(path) => format!("Saved screenshot to {}", path.display()),
+                    Err(e) => e.to_string(),
+                }
+            }
+            (".load", "") => "Usage: .load <path>".to_string(),
+            (".load", path) => match std::fs::read_to_string(path) {
+                Ok(source) => describe_result(self.page.run_script_file(&source, Path::new(path))),
+                Err(e) => format!("Cannot read '{}': {}", path, e),
+            },
+            _ => format!("Unknown command '{}'; type .help for the commands", name),
+        };
+        Step::Output(output)
+    }
+
+    /// The matching elements, one per line as `[index] <start tag>`
+    fn query(&self, selector: &str) -> String {
+        match self.page.query_all(selector) {
+            Ok(matches) if matches.is_empty() => format!("No elements match '{}'", selector),
+            Ok(matches) => {
+                let document = self.page.document();
+                matches.iter().map(|&idx| format!("[{}] {}", idx, start_tag(&document, idx))).collect::<Vec<_>>().join("\n")
+            }
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+/// A script's value, or its error as browsers' consoles show uncaught ones
+fn describe_result(result: Result<String, BrowserError>) -> String {
+    match result {
+        Ok(value) => value,
+        Err(BrowserError::JavaScriptError(message, Some(stack))) => format!("Uncaught {}\n{}", message, stack.trim_end()),
+        Err(BrowserError::JavaScriptError(message, None)) => format!("Uncaught {}", message),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// `<tag name="value">` for an element, its quoted text for a text node
+fn start_tag(document: &Document, idx: usize) -> String {
+    match &document.nodes[idx].data {
+        Some(NodeData::Element(element)) => {
+            let attributes: String =
+                element.attributes.iter().map(|(name, value)| format!(" {}=\"{}\"", name, escape_attribute(value))).collect();
+            format!("<{}{}>", element.tag_name, attributes)
+        }
+        Some(NodeData::Text(text)) => format!("\"{}\"", escape_text(text)),
+        _ => format!("{:?}", document.nodes[idx].node_type),
+    }
+}
+
+/// Whether `source` closes every bracket, block comment and template
+/// literal it opens
+///
+/// A stray closing bracket or a string left open at the end of a line is
+/// a syntax error more lines cannot fix, so it counts as complete for the
+/// engine to report.
+pub fn is_complete(source: &str) -> bool {
+    #[derive(PartialEq)]
+    enum Open {
+        Bracket(char),
+        Template,
+        /// `${...}` inside a template literal
+        Substitution,
+    }
+    let mut open = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if open.last() == Some(&Open::Template) {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '`' => {
+                    open.pop();
+                }
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    open.push(Open::Substitution);
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                if !chars.by_ref().any(|c| std::mem::replace(&mut previous, c) == '*' && c == '/') {
+                    return false;
+                }
+            }
+            '"' | '\'' => {
+                let quote = c;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '\n' => return true,
+                        c if c == quote => break,
+                        _ => {}
+                    }
+                }
+            }
+            '`' => open.push(Open::Template),
+            '(' => open.push(Open::Bracket(')')),
+            '[' => open.push(Open::Bracket(']')),
+            '{' => open.push(Open::Bracket('}')),
+            '}' if open.last() == Some(&Open::Substitution) => {
+                open.pop();
+            }
+            ')' | ']' | '}' if open.pop() != Some(Open::Bracket(c)) => return true,
+            _ => {}
+        }
+    }
+    open.is_empty()
+}
+
+// ============================================================================
+// Line editing
+// ============================================================================
+
+/// A key the line editor acts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    /// Ctrl-U
+    KillToStart,
+    /// Ctrl-K
+    KillToEnd,
+    /// Ctrl-C
+    Interrupt,
+    /// Ctrl-D
+    EndOfInput,
+}
+
+/// The keys in `bytes` read from a terminal in raw mode; other control
+/// characters and escape sequences are dropped
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x01' => Key::Home,
+            '\x05' => Key::End,
+            '\x02' => Key::Left,
+            '\x06' => Key::Right,
+            '\x10' => Key::Up,
+            '\x0e' => Key::Down,
+            '\x15' => Key::KillToStart,
+            '\x0b' => Key::KillToEnd,
+            '\x03' => Key::Interrupt,
+            '\x04' => Key::EndOfInput,
+            '\x1b' if matches!(chars.peek(), Some('[' | 'O')) => {
+                chars.next();
+                let mut parameter = String::new();
+                while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit() || **c == ';') {
+                    parameter.push(digit);
+                    chars.next();
+                }
+                match (chars.next(), parameter.as_str()) {
+                    (Some('A'), _) => Key::Up,
+                    (Some('B'), _) => Key::Down,
+                    (Some('C'), _) => Key::Right,
+                    (Some('D'), _) => Key::Left,
+                    (Some('H'), _) | (Some('~'), "1" | "7") => Key::Home,
+                    (Some('F'), _) | (Some('~'), "4" | "8") => Key::End,
+                    (Some('~'), "3") => Key::Delete,
+                    _ => continue,
+                }
+            }
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// A line read from the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Line(String),
+    /// Ctrl-C
+    Interrupted,
+    /// Ctrl-D on an empty line, or the end of piped input
+    EndOfInput,
+}
+
+/// The line being edited, and the lines entered before
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    /// Index of the history entry shown, and the line it replaced
+    browsing: Option<(usize, Vec<char>)>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    /// The cursor's position, in characters
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Apply `key`, returning the input once a line is finished
+    pub fn key(&mut self, key: Key) -> Option<Input> {
+        match key {
+            Key::Char(c) => {
+                self.line.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Enter => {
+                let line = self.line();
+                if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.clear();
+                return Some(Input::Line(line));
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.line.len(),
+            Key::Up => self.browse_back(),
+            Key::Down => self.browse_forward(),
+            Key::KillToStart => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::KillToEnd => self.line.truncate(self.cursor),
+            Key::Interrupt => {
+                self.clear();
+                return Some(Input::Interrupted);
+            }
+            Key::EndOfInput if self.line.is_empty() => return Some(Input::EndOfInput),
+            Key::EndOfInput => return self.key(Key::Delete),
+            Key::Backspace | Key::Delete => {}
+        }
+        None
+    }
+
+    fn clear(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.browsing = None;
+    }
+
+    fn browse_back(&mut self) {
+        let index = match &self.browsing {
+            Some((0, _)) => return,
+            Some((index, _)) => index - 1,
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        let draft = self.browsing.take().map_or_else(|| self.line.clone(), |(_, draft)| draft);
+        self.show(self.history[index].chars().collect());
+        self.browsing = Some((index, draft));
+    }
+
+    fn browse_forward(&mut self) {
+        let Some((index, draft)) = self.browsing.take() else { return };
+        if index + 1 < self.history.len() {
+            self.show(self.history[index + 1].chars().collect());
+            self.browsing = Some((index + 1, draft));
+        } else {
+            self.show(draft);
+        }
+    }
+
+    fn show(&mut self, line: Vec<char>) {
+        self.cursor = line.len();
+        self.line = line;
+    }
+
+    /// Read a line from stdin, editing it in place on a terminal
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+        #[cfg(unix)]
+        if io::stdin().is_terminal() {
+            return self.read_terminal_line(prompt);
+        }
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(Input::EndOfInput);
+        }
+        Ok(Input::Line(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    #[cfg(unix)]
+    fn read_terminal_line(&mut self, prompt: &str) -> io::Result<Input> {
+        let _raw = RawMode::enable()?;
+        let mut stdout = io::stdout();
+        self.redraw(&mut stdout, prompt)?;
+        let mut buffer = [0; 64];
+        loop {
+            let read = io::stdin().read(&mut buffer)?;
+            if read == 0 {
+                return Ok(Input::EndOfInput);
+            }
+            for key in parse_keys(&buffer[..read]) {
+                let Some(input) = self.key(key) else { continue };
+                let end = if input == Input::Interrupted { "^C\r\n" } else { "\r\n" };
+                stdout.write_all(end.as_bytes())?;
+                stdout.flush()?;
+                return Ok(input);
+            }
+            self.redraw(&mut stdout, prompt)?;
+        }
+    }
+
+    /// Rewrite the prompt and line, leaving the terminal's cursor at ours
+    fn redraw(&self, out: &mut impl Write, prompt: &str) -> io::Result<()> {
+        write!(out, "\r{}{}\x1b[K", prompt, self.line())?;
+        let behind = self.line.len() - self.cursor;
+        if behind > 0 {
+            write!(out, "\x1b[{}D", behind)?;
+        }
+        out.flush()
+    }
+}
+
+/// The terminal reading keys as they are typed, without echoing them,
+/// until dropped
+#[cfg(unix)]
+struct RawMode(libc::termios);
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // SAFETY: `termios` is plain data tcgetattr fills in
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = termios;
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        termios.c_iflag &= !(libc::IXON | libc::ICRNL);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode(original))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.0) };
+    }
+}
+
+/// Prompt for entries on stdin and print what they return until `.exit`
+/// or the end of input
+pub fn run(page: &Page) -> io::Result<()> {
+    let terminal = io::stdin().is_terminal();
+    if terminal {
+        println!("Type .help for commands, .exit or Ctrl-D to quit");
+    }
+    let mut editor = LineEditor::new();
+    let mut repl = Repl::new(page);
+    loop {
+        let prompt = if terminal { repl.prompt() } else { "" };
+        match editor.read_line(prompt)? {
+            Input::EndOfInput => return Ok(()),
+            Input::Interrupted => repl.interrupt(),
+            Input::Line(line) => match repl.enter(&line) {
+                Step::More => {}
+                Step::Output(output) if output.is_empty() => {}
+                Step::Output(output) => println!("{}", output),
+                Step::Exit => return Ok(()),
+            },
+        }
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::PageBuilder;
+
+    #[test]
+    fn test_entries_run_against_the_live_page() {
+        // Given: A REPL on a page with a list
+        let page = PageBuilder::new().with_seed(1).build().unwrap();
+        page.load_html(r#"<html><body><ul><li id="a" class="item">A</li><li class="item">B</li></ul></body></html>"#);
+        let mut repl = Repl::new(&page);
+
+        // When: A function is entered over several lines, then called
+        assert_eq!(repl.enter("function count(selector) {"), Step::More);
+        assert_eq!(repl.prompt(), CONTINUATION_PROMPT);
+        assert_eq!(repl.enter("  return `${selector}: ${getAttribute(getByText('A'), 'id')}`;"), Step::More);
+        assert_eq!(repl.enter("}"), Step::Output("undefined".to_string()));
+
+        // Then: Later entries see it, and errors are reported
+        assert_eq!(repl.prompt(), PROMPT);
+        assert_eq!(repl.enter("count('li')"), Step::Output("li: a".to_string()));
+        assert!(matches!(repl.enter("missing()"), Step::Output(output) if output.starts_with("Uncaught ReferenceError")));
+        assert_eq!(repl.enter("  "), Step::Output(String::new()));
+
+        // And: Commands query the document and lay it out
+        assert_eq!(repl.enter(".query li.item"), Step::Output(format!(
+            "[{}] <li class=\"item\" id=\"a\">\n[{}] <li class=\"item\">",
+            page.query("#a").unwrap().unwrap(),
+            page.query_all("li").unwrap()[1]
+        )));
+        assert_eq!(repl.enter(".query table"), Step::Output("No elements match 'table'".to_string()));
+        assert!(matches!(repl.enter(".layout"), Step::Output(tree) if tree.contains("li")));
+        assert!(matches!(repl.enter(".inspect li.item"), Step::Output(tree) if tree.starts_with(r#"<li id="a" class="item">"#)));
+        assert_eq!(repl.enter(".frobnicate"), Step::Output("Unknown command '.frobnicate'; type .help for the commands".to_string()));
+        assert_eq!(repl.enter(".exit"), Step::Exit);
+    }
+
+    #[test]
+    fn test_screenshot_and_interrupt() {
+        // Given: A REPL on a blank page, with an entry left open
+        let dir = tempfile::tempdir().unwrap();
+        let page = PageBuilder::new().with_seed(1).with_viewport(32, 32).build().unwrap();
+        let mut repl = Repl::new(&page);
+        assert_eq!(repl.enter("[1,"), Step::More);
+
+        // When: The entry is interrupted and a screenshot saved
+        repl.interrupt();
+        let path = dir.path().join("page.png");
+        let step = repl.enter(&format!(".screenshot {}", path.display()));
+
+        // Then: The next line starts a new entry and the file exists
+        assert_eq!(step, Step::Output(format!("Saved screenshot to {}", path.display())));
+        assert!(path.exists());
+        assert_eq!(repl.enter("1 + 1"), Step::Output("2".to_string()));
+    }
+
+    #[test]
+    fn test_entries_are_complete_once_everything_closes() {
+        assert!(is_complete("1 + 1"));
+        assert!(!is_complete("if (x) {"));
+        assert!(!is_complete("[1, (2"));
+        assert!(is_complete("'{' + \"(\" // ["));
+        assert!(!is_complete("/* still"));
+        assert!(is_complete("/* done */ f()"));
+        assert!(!is_complete("`line ${ {a: 1}.a"));
+        assert!(is_complete("`line ${ {a: 1}.a } and \\` }`"));
+        assert!(is_complete("f())"), "A stray bracket is the engine's error to report");
+        assert!(is_complete("'open\n"), "So is a string left open at the end of a line");
+    }
+
+    #[test]
+    fn test_line_editing_and_history() {
+        // Given: An editor with a line typed and edited in the middle
+        let mut editor = LineEditor::new();
+        let keys = parse_keys(b"dox.title\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x7fcument\x1b[F!");
+        assert!(keys.iter().all(|&key| editor.key(key).is_none()));
+        assert_eq!((editor.line(), editor.cursor()), ("document.title!".to_string(), 15));
+
+        // When: It is finished, and another line is typed and killed
+        assert_eq!(editor.key(Key::Backspace), None);
+        assert_eq!(editor.key(Key::Enter), Some(Input::Line("document.title".to_string())));
+        for key in parse_keys(b"1 + 2\x01\x0b") {
+            editor.key(key);
+        }
+        assert_eq!(editor.line(), "");
+
+        // Then: Up and Down browse the history, back to the draft
+        editor.key(Key::Char('x'));
+        editor.key(Key::Up);
+        assert_eq!(editor.line(), "document.title");
+        editor.key(Key::Up);
+        assert_eq!(editor.line(), "document.title");
+        editor.key(Key::Down);
+        assert_eq!(editor.line(), "x");
+        assert_eq!(editor.key(Key::Interrupt), Some(Input::Interrupted));
+        assert_eq!(editor.key(Key::EndOfInput), Some(Input::EndOfInput));
+        assert_eq!(editor.history(), ["document.title"]);
+        assert_eq!(parse_keys("é\x1b[3~\x1b[1~\r".as_bytes()), [Key::Char('é'), Key::Delete, Key::Home, Key::Enter]);
+    }
+}
@@ -0,0 +1,601 @@
+//! Test Reporters
+//! Machine-readable renderings of a `TestSummary` (JSON, JUnit XML, TAP) for
+//! CI, and a self-contained HTML page for people, selected with
+//! `--reporter` and written to stdout or a file
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::console::ConsoleLevel;
+use crate::error::{BrowserError, TestResult, TestSummary};
+use crate::network::encode_data_uri;
+
+/// Output format of a test run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReporterKind {
+    /// `TestSummary::format_summary`
+    Human,
+    Json,
+    Junit,
+    Tap,
+    /// `format_html`
+    Html,
+}
+
+impl ReporterKind {
+    pub fn parse(name: &str) -> Result<ReporterKind, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "human" | "default" => Ok(ReporterKind::Human),
+            "json" => Ok(ReporterKind::Json),
+            "junit" | "xml" => Ok(ReporterKind::Junit),
+            "tap" => Ok(ReporterKind::Tap),
+            "html" => Ok(ReporterKind::Html),
+            other => Err(format!("Unknown reporter '{}': expected human, json, junit, tap or html", other)),
+        }
+    }
+}
+
+impl fmt::Display for ReporterKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ReporterKind::Human => "human",
+            ReporterKind::Json => "json",
+            ReporterKind::Junit => "junit",
+            ReporterKind::Tap => "tap",
+            ReporterKind::Html => "html",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which reporter to use and where its output goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReporterOptions {
+    pub kind: ReporterKind,
+    /// Write to this file instead of stdout
+    pub output: Option<PathBuf>,
+}
+
+impl Default for ReporterOptions {
+    fn default() -> Self {
+        ReporterOptions { kind: ReporterKind::Human, output: None }
+    }
+}
+
+/// Find `--reporter <kind>` and `--reporter-output <path>` (or their `=`
+/// forms) among command-line arguments
+pub fn parse_reporter_args(args: &[String]) -> Result<ReporterOptions, String> {
+    let mut options = ReporterOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if let Some(value) = arg.strip_prefix("--reporter=") {
+            options.kind = ReporterKind::parse(value)?;
+        } else if arg == "--reporter" {
+            i += 1;
+            options.kind = ReporterKind::parse(args.get(i).ok_or("--reporter requires a value")?)?;
+        } else if let Some(value) = arg.strip_prefix("--reporter-output=") {
+            options.output = Some(PathBuf::from(value));
+        } else if arg == "--reporter-output" {
+            i += 1;
+            options.output = Some(PathBuf::from(args.get(i).ok_or("--reporter-output requires a path")?));
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+/// Remove the reporter flags and their values from command-line arguments
+pub fn strip_reporter_args(args: &[String]) -> Vec<String> {
+    let mut rest = Vec::new();
+    let mut skip_next = false;
+    for arg in args {
+        if skip_next {
+            skip_next = false;
+        } else if arg == "--reporter" || arg == "--reporter-output" {
+            skip_next = true;
+        } else if !arg.starts_with("--reporter=") && !arg.starts_with("--reporter-output=") {
+            rest.push(arg.clone());
+        }
+    }
+    rest
+}
+
+/// Render a summary in the given format
+pub fn format_report(summary: &TestSummary, kind: ReporterKind) -> String {
+    match kind {
+        ReporterKind::Human => summary.format_summary(),
+        ReporterKind::Json => format_json(summary),
+        ReporterKind::Junit => format_junit(summary),
+        ReporterKind::Tap => format_tap(summary),
+        ReporterKind::Html => format_html(summary),
+    }
+}
+
+/// Write the report to the configured file, or stdout
+pub fn write_report(summary: &TestSummary, options: &ReporterOptions) -> Result<(), BrowserError> {
+    let report = format_report(summary, options.kind);
+    match &options.output {
+        Some(path) => fs::write(path, report).map_err(|e| {
+            BrowserError::InvalidOperationError(format!("Failed to write report to {}: {}", path.display(), e))
+        }),
+        None => {
+            print!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+// ============================================================================
+// ERROR DETAILS
+// ============================================================================
+
+/// Variant name of an error, e.g. `JavaScriptError`
+pub fn error_kind(error: &BrowserError) -> &'static str {
+    match error {
+        BrowserError::ParseError(_) => "ParseError",
+        BrowserError::LayoutError(_) => "LayoutError",
+        BrowserError::RenderError(_) => "RenderError",
+        BrowserError::ScreenshotError(_) => "ScreenshotError",
+        BrowserError::DOMError(_) => "DOMError",
+        BrowserError::QueryError(_) => "QueryError",
+        BrowserError::ElementError(_) => "ElementError",
+        BrowserError::JavaScriptError(_, _) => "JavaScriptError",
+        BrowserError::InvalidOperationError(_) => "InvalidOperationError",
+        BrowserError::NotFoundError(_) => "NotFoundError",
+        BrowserError::LimitExceeded(..) => "LimitExceeded",
+    }
+}
+
+fn error_stack(error: &BrowserError) -> Option<&str> {
+    match error {
+        BrowserError::JavaScriptError(_, stack) => stack.as_deref(),
+        _ => None,
+    }
+}
+
+fn duration_ms(duration: Option<Duration>) -> f64 {
+    duration.map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
+
+fn total_duration(summary: &TestSummary) -> Duration {
+    summary.results.iter().filter_map(|r| r.duration).sum()
+}
+
+// ============================================================================
+// JSON
+// ============================================================================
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_optional_string(text: Option<&str>) -> String {
+    text.map_or_else(|| "null".to_string(), json_string)
+}
+
+fn json_test(result: &TestResult) -> String {
+    let error = match &result.error {
+        Some(error) if !result.passed => format!(
+            "{{\"kind\":{},\"message\":{},\"stack\":{}}}",
+            json_string(error_kind(error)),
+            json_string(&error.to_string()),
+            json_optional_string(error_stack(error))
+        ),
+        _ => "null".to_string(),
+    };
+    let path = |p: &Option<PathBuf>| json_optional_string(p.as_ref().map(|p| p.display().to_string()).as_deref());
+    format!(
+        "{{\"name\":{},\"status\":\"{}\",\"duration_ms\":{:.3},\"message\":{},\"error\":{},\"screenshot\":{},\"dom_snapshot\":{}}}",
+        json_string(&result.name),
+        if result.passed { "passed" } else { "failed" },
+        duration_ms(result.duration),
+        json_string(&result.message),
+        error,
+        path(&result.screenshot_path),
+        path(&result.dom_snapshot_path)
+    )
+}
+
+/// One JSON object with run totals and a `tests` array
+pub fn format_json(summary: &TestSummary) -> String {
+    let tests: Vec<String> = summary.results.iter().map(|r| format!("    {}", json_test(r))).collect();
+    let tests = if tests.is_empty() { "[]".to_string() } else { format!("[\n{}\n  ]", tests.join(",\n")) };
+    format!(
+        "{{\n  \"total\": {},\n  \"passed\": {},\n  \"failed\": {},\n  \"seed\": {},\n  \"duration_ms\": {:.3},\n  \"tests\": {}\n}}\n",
+        summary.total,
+        summary.passed,
+        summary.failed,
+        summary.seed.map_or_else(|| "null".to_string(), |s| s.to_string()),
+        duration_ms(Some(total_duration(summary))),
+        tests
+    )
+}
+
+// ============================================================================
+// JUNIT XML
+// ============================================================================
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Split `Suite > Nested > test` into the JUnit class name and test name
+fn junit_names(name: &str) -> (&str, &str) {
+    match name.rsplit_once(" > ") {
+        Some((suite, test)) => (suite, test),
+        None => ("cortex", name),
+    }
+}
+
+/// JUnit XML as read by Jenkins, GitLab and GitHub Actions test reporters
+pub fn format_junit(summary: &TestSummary) -> String {
+    let time = total_duration(summary).as_secs_f64();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        summary.total, summary.failed, time
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"cortex\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"0\" time=\"{:.3}\">\n",
+        summary.total, summary.failed, time
+    ));
+    if let Some(seed) = summary.seed {
+        xml.push_str(&format!(
+            "    <properties>\n      <property name=\"seed\" value=\"{}\"/>\n    </properties>\n",
+            seed
+        ));
+    }
+    for result in &summary.results {
+        let (classname, name) = junit_names(&result.name);
+        let open = format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            xml_escape(classname),
+            xml_escape(name),
+            duration_ms(result.duration) / 1000.0
+        );
+        if result.passed {
+            xml.push_str(&open);
+            xml.push_str("/>\n");
+            continue;
+        }
+        let kind = result.error.as_ref().map_or("AssertionError", error_kind);
+        let details = match &result.error {
+            Some(error) => match error_stack(error) {
+                Some(stack) => format!("{}\n{}", error, stack),
+                None => error.to_string(),
+            },
+            None => result.message.clone(),
+        };
+        xml.push_str(&open);
+        xml.push_str(">\n");
+        xml.push_str(&format!(
+            "      <failure message=\"{}\" type=\"{}\">{}</failure>\n",
+            xml_escape(&result.message),
+            kind,
+            xml_escape(&details)
+        ));
+        if let Some(ref path) = result.screenshot_path {
+            xml.push_str(&format!(
+                "      <system-out>[[ATTACHMENT|{}]]</system-out>\n",
+                xml_escape(&path.display().to_string())
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+// ============================================================================
+// TAP
+// ============================================================================
+
+fn yaml_block(key: &str, text: &str) -> String {
+    let mut out = format!("  {}: |-\n", key);
+    for line in text.lines() {
+        out.push_str(&format!("    {}\n", line.trim_start()));
+    }
+    out
+}
+
+/// TAP version 13, with a YAML diagnostic block under each failure
+pub fn format_tap(summary: &TestSummary) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", summary.total);
+    if let Some(seed) = summary.seed {
+        tap.push_str(&format!("# Seed: {}\n", seed));
+    }
+    for (i, result) in summary.results.iter().enumerate() {
+        let name = result.name.replace('#', "\\#");
+        if result.passed {
+            tap.push_str(&format!("ok {} - {}\n", i + 1, name));
+            continue;
+        }
+        tap.push_str(&format!("not ok {} - {}\n", i + 1, name));
+        tap.push_str("  ---\n");
+        tap.push_str(&format!("  message: {}\n", json_string(&result.message)));
+        tap.push_str("  severity: fail\n");
+        tap.push_str(&format!("  duration_ms: {:.3}\n", duration_ms(result.duration)));
+        if let Some(ref error) = result.error {
+            tap.push_str(&format!("  error: {}\n", error_kind(error)));
+            if let Some(stack) = error_stack(error) {
+                tap.push_str(&yaml_block("stack", stack));
+            }
+        }
+        if let Some(ref path) = result.screenshot_path {
+            tap.push_str(&format!("  screenshot: {}\n", json_string(&path.display().to_string())));
+        }
+        tap.push_str("  ...\n");
+    }
+    tap.push_str(&format!("# pass {}\n# fail {}\n", summary.passed, summary.failed));
+    tap
+}
+
+// ============================================================================
+// HTML
+// ============================================================================
+
+const HTML_STYLE: &str = "\
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2em auto; max-width: 72em; color: #222; }
+.totals span { margin-right: 1.5em; }
+.passed { color: #1a7f37; } .failed { color: #cf222e; }
+details { border: 1px solid #ddd; border-radius: 4px; margin: 0.5em 0; padding: 0.5em 1em; }
+summary { cursor: pointer; font-weight: 600; }
+summary .duration { color: #777; font-weight: normal; margin-left: 1em; }
+pre { background: #f6f8fa; padding: 0.75em; overflow-x: auto; white-space: pre-wrap; }
+.console .error { color: #cf222e; } .console .warn { color: #9a6700; }
+figure { display: inline-block; margin: 0.5em 1em 0.5em 0; vertical-align: top; }
+figure img { max-width: 100%; border: 1px solid #ddd; image-rendering: pixelated; }
+";
+
+/// An image file as a `data:` URI, so the report stands alone
+fn image_data_uri(path: &Path) -> Option<String> {
+    let media_type = match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        _ => return None,
+    };
+    fs::read(path).ok().map(|bytes| encode_data_uri(media_type, &bytes))
+}
+
+/// Diff and side-by-side images a failure message points at, e.g. from a
+/// golden master mismatch, that exist on disk
+fn mentioned_images(text: &str) -> Vec<PathBuf> {
+    let pattern = Regex::new(r"[^\s,()]+\.png").expect("valid image path pattern");
+    let mut paths: Vec<PathBuf> = pattern.find_iter(text).map(|m| PathBuf::from(m.as_str())).filter(|p| p.is_file()).collect();
+    paths.dedup();
+    paths
+}
+
+fn html_figure(path: &Path, caption: &str) -> String {
+    match image_data_uri(path) {
+        Some(uri) => format!(
+            "<figure><img src=\"{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>",
+            uri,
+            xml_escape(caption),
+            xml_escape(caption)
+        ),
+        None => format!("<p>{}: {} (not readable)</p>", xml_escape(caption), xml_escape(&path.display().to_string())),
+    }
+}
+
+fn html_test(result: &TestResult) -> String {
+    let status = if result.passed { "passed" } else { "failed" };
+    let mut html = format!(
+        "<details class=\"test {}\"{}><summary><span class=\"{}\">{}</span> {}<span class=\"duration\">{:.1} ms</span></summary>\n",
+        status,
+        if result.passed { "" } else { " open" },
+        status,
+        if result.passed { "&#10003;" } else { "&#10007;" },
+        xml_escape(&result.name),
+        duration_ms(result.duration)
+    );
+    if !result.passed {
+        html.push_str(&format!("<pre class=\"message\">{}</pre>\n", xml_escape(&result.message)));
+        if let Some(error) = &result.error {
+            html.push_str(&format!("<p>{}</p>\n", error_kind(error)));
+            if let Some(stack) = error_stack(error) {
+                html.push_str(&format!("<pre class=\"stack\">{}</pre>\n", xml_escape(stack)));
+            }
+        }
+    }
+    if !result.console.is_empty() {
+        html.push_str("<h4>Console</h4>\n<pre class=\"console\">");
+        for entry in &result.console {
+            let level = match entry.level {
+                ConsoleLevel::Error => "error",
+                ConsoleLevel::Warn => "warn",
+                _ => "log",
+            };
+            html.push_str(&format!("<span class=\"{}\">{}</span>\n", level, xml_escape(&entry.message)));
+        }
+        html.push_str("</pre>\n");
+    }
+    let mut images: Vec<(PathBuf, String)> = Vec::new();
+    if let Some(path) = &result.screenshot_path {
+        images.push((path.clone(), "Screenshot at failure".to_string()));
+    }
+    if !result.passed {
+        let text = result.error.as_ref().map_or_else(|| result.message.clone(), |e| format!("{}\n{}", result.message, e));
+        for path in mentioned_images(&text) {
+            let caption = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            images.push((path, caption));
+        }
+    }
+    for (path, caption) in images {
+        html.push_str(&html_figure(&path, &caption));
+        html.push('\n');
+    }
+    html.push_str("</details>\n");
+    html
+}
+
+/// A self-contained HTML page: totals, then each test with its status,
+/// duration, console output and, for failures, the error, stack and
+/// screenshots and diff images inlined as `data:` URIs
+pub fn format_html(summary: &TestSummary) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Test report</title>\n");
+    html.push_str(&format!("<style>\n{}</style></head>\n<body>\n<h1>Test report</h1>\n", HTML_STYLE));
+    html.push_str(&format!(
+        "<p class=\"totals\"><span>{} tests</span><span class=\"passed\">{} passed</span><span class=\"failed\">{} failed</span><span>{:.1} ms</span>",
+        summary.total,
+        summary.passed,
+        summary.failed,
+        duration_ms(Some(total_duration(summary)))
+    ));
+    if let Some(seed) = summary.seed {
+        html.push_str(&format!("<span>seed {}</span>", seed));
+    }
+    html.push_str("</p>\n");
+    for result in &summary.results {
+        html.push_str(&html_test(result));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn sample_summary() -> TestSummary {
+        let mut summary = TestSummary::new().with_seed(42);
+        summary.add_result(TestResult::success("Form > renders", "passed").with_duration(Duration::from_millis(12)));
+        summary.add_result(
+            TestResult::failure(
+                "Form > submits <data>",
+                "expected \"Save\"",
+                BrowserError::JavaScriptError("expected \"Save\"".to_string(), Some("at submit (test.js:3)".to_string())),
+            )
+            .with_duration(Duration::from_millis(3)),
+        );
+        summary
+    }
+
+    #[test]
+    fn test_reporter_arguments() {
+        let parsed = parse_reporter_args(&args(&["prog", "--reporter", "junit", "--reporter-output=out.xml", "code"]));
+
+        assert_eq!(
+            parsed,
+            Ok(ReporterOptions { kind: ReporterKind::Junit, output: Some(PathBuf::from("out.xml")) })
+        );
+        assert_eq!(parse_reporter_args(&args(&["prog"])), Ok(ReporterOptions::default()));
+        assert!(parse_reporter_args(&args(&["prog", "--reporter=yaml"])).is_err());
+        assert!(parse_reporter_args(&args(&["prog", "--reporter"])).is_err());
+        assert_eq!(
+            strip_reporter_args(&args(&["prog", "--reporter", "tap", "code", "--reporter-output=x"])),
+            args(&["prog", "code"])
+        );
+    }
+
+    #[test]
+    fn test_json_report() {
+        // Given: A summary with a pass and a failure with a stack trace
+        let summary = sample_summary();
+
+        // When: We render JSON
+        let json = format_json(&summary);
+
+        // Then: Totals, durations and escaped error details are present
+        assert!(json.contains("\"total\": 2"));
+        assert!(json.contains("\"seed\": 42"));
+        assert!(json.contains("\"duration_ms\": 15.000"));
+        assert!(json.contains(r#""name":"Form > renders","status":"passed","duration_ms":12.000"#));
+        assert!(json.contains(r#""message":"expected \"Save\"""#));
+        assert!(json.contains(r#""error":{"kind":"JavaScriptError","message":"JavaScript Error: expected \"Save\"","stack":"at submit (test.js:3)"}"#));
+        assert_eq!(format_json(&TestSummary::new()).matches("[]").count(), 1);
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let xml = format_junit(&sample_summary());
+
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains("<testsuite name=\"cortex\" tests=\"2\" failures=\"1\" errors=\"0\" skipped=\"0\" time=\"0.015\">"));
+        assert!(xml.contains("<property name=\"seed\" value=\"42\"/>"));
+        assert!(xml.contains("<testcase classname=\"Form\" name=\"renders\" time=\"0.012\"/>"));
+        assert!(xml.contains("<testcase classname=\"Form\" name=\"submits &lt;data&gt;\" time=\"0.003\">"));
+        assert!(xml.contains(
+            "<failure message=\"expected &quot;Save&quot;\" type=\"JavaScriptError\">JavaScript Error: expected &quot;Save&quot;\nat submit (test.js:3)</failure>"
+        ));
+    }
+
+    #[test]
+    fn test_tap_report() {
+        let tap = format_tap(&sample_summary());
+
+        assert_eq!(
+            tap,
+            "TAP version 13\n1..2\n# Seed: 42\nok 1 - Form > renders\nnot ok 2 - Form > submits <data>\n  ---\n  \
+             message: \"expected \\\"Save\\\"\"\n  severity: fail\n  duration_ms: 3.000\n  error: JavaScriptError\n  \
+             stack: |-\n    at submit (test.js:3)\n  ...\n# pass 1\n# fail 1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_report_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.tap");
+        let options = ReporterOptions { kind: ReporterKind::Tap, output: Some(path.clone()) };
+
+        write_report(&sample_summary(), &options).unwrap();
+
+        assert!(fs::read_to_string(path).unwrap().starts_with("TAP version 13\n1..2\n"));
+    }
+
+    #[test]
+    fn test_html_report_inlines_images() {
+        // Given: A failed golden master check whose diff image is on disk,
+        // and a test that logged
+        let dir = tempfile::tempdir().unwrap();
+        let diff = dir.path().join("card.diff.png");
+        fs::write(&diff, [137, 80, 78, 71]).unwrap();
+        let mut summary = sample_summary();
+        summary.results[0].console = vec![crate::console::ConsoleEntry { level: ConsoleLevel::Warn, message: "slow <render>".to_string() }];
+        summary.add_result(TestResult::failure_string("Card > looks right", &format!("1.00% of pixels differ; diff: {}, side by side: /missing.png", diff.display())));
+
+        // When: We render HTML
+        let html = format_html(&summary);
+
+        // Then: Totals, escaped names, stacks and console output are there,
+        // and the diff is embedded, not linked
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<span>3 tests</span><span class=\"passed\">1 passed</span><span class=\"failed\">2 failed</span>"));
+        assert!(html.contains("Form &gt; submits &lt;data&gt;"));
+        assert!(html.contains("<pre class=\"stack\">at submit (test.js:3)</pre>"));
+        assert!(html.contains("<span class=\"warn\">slow &lt;render&gt;</span>"));
+        assert!(html.contains("<img src=\"data:image/png;base64,iVBORw==\" alt=\"card.diff.png\">"));
+        assert!(!html.contains("missing.png\" alt"));
+        assert_eq!(ReporterKind::parse("HTML"), Ok(ReporterKind::Html));
+    }
+}
@@ -0,0 +1,400 @@
+//! Rule Map
+//! The stylesheet's rules compiled for matching against many nodes
+//!
+//! Rules are filed under the rightmost compound's id, class or tag, so a
+//! node only tries those that could match it, and a Bloom filter of each
+//! node's ancestors rejects descendant selectors early. Selectors come
+//! from `query::parse_style_selector`.
+
+use std::collections::HashMap;
+
+use crate::atom::Atom;
+use crate::css::{Rule, StyleSheet};
+use crate::dom::{Document, Node, NodeData};
+use crate::query::{self, Selector};
+
+/// One compound selector, like `div.card:hover`, with the names it is
+/// filed and filtered under
+#[derive(Debug, Clone, PartialEq)]
+struct Compound {
+    selector: Selector,
+    tag: Option<Atom>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+/// A parsed selector: the compound the node itself must match, then the
+/// ones to its left, nearest first, each with the combinator joining it to
+/// the compound after it
+#[derive(Debug, Clone, PartialEq)]
+struct ComplexSelector {
+    subject: Compound,
+    leftward: Vec<(Combinator, Compound)>,
+    /// Identifier hashes the ancestors must include, for the Bloom filter
+    ancestor_hashes: Vec<u32>,
+}
+
+struct Entry {
+    rule: usize,
+    /// Position in cascade order
+    rank: usize,
+    selector: ComplexSelector,
+}
+
+/// A stylesheet's active rules, bucketed by the rightmost compound, plus
+/// every node's ancestor filter
+pub struct RuleMap<'a> {
+    rules: &'a [Rule],
+    by_id: HashMap<String, Vec<Entry>>,
+    by_class: HashMap<String, Vec<Entry>>,
+    by_tag: HashMap<Atom, Vec<Entry>>,
+    universal: Vec<Entry>,
+    filters: Vec<AncestorFilter>,
+}
+
+impl<'a> RuleMap<'a> {
+    pub fn new(document: &Document, stylesheet: &'a StyleSheet) -> Self {
+        Self::build(document, stylesheet, true)
+    }
+
+    /// Like `new`, but later rules in the sheet win, for a stylesheet
+    /// written in cascade order such as the user agent's
+    pub fn in_source_order(document: &Document, stylesheet: &'a StyleSheet) -> Self {
+        Self::build(document, stylesheet, false)
+    }
+
+    fn build(document: &Document, stylesheet: &'a StyleSheet, by_selector_text: bool) -> Self {
+        let mut map = RuleMap {
+            rules: &stylesheet.rules,
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: Vec::new(),
+            filters: ancestor_filters(document),
+        };
+
+        // Later rules win, ordered by their selector text; a stable sort
+        // keeps source order among equal ones
+        let mut active: Vec<(usize, &Rule)> = stylesheet
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.media.as_ref().is_none_or(|query| stylesheet.media.matches(query)))
+            .collect();
+        if by_selector_text {
+            active.sort_by_cached_key(|(_, rule)| rule.selectors.join(","));
+        }
+
+        for (rank, (rule, declared)) in active.into_iter().enumerate() {
+            let parsed = declared.selectors.iter().filter_map(|text| query::parse_style_selector(text).ok());
+            let listed = parsed.flat_map(|selector| match selector {
+                Selector::List(selectors) => selectors,
+                selector => vec![selector],
+            });
+            for selector in listed.map(ComplexSelector::new) {
+                let entry = Entry { rule, rank, selector };
+                let subject = &entry.selector.subject;
+                if let Some(id) = &subject.id {
+                    map.by_id.entry(id.clone()).or_default().push(entry);
+                } else if let Some(class) = subject.classes.first() {
+                    map.by_class.entry(class.clone()).or_default().push(entry);
+                } else if let Some(tag) = &subject.tag {
+                    map.by_tag.entry(tag.clone()).or_default().push(entry);
+                } else {
+                    map.universal.push(entry);
+                }
+            }
+        }
+        map
+    }
+
+    /// The rules matching node `idx`, in cascade order: later rules win
+    pub fn matching(&self, document: &Document, idx: usize) -> Vec<&'a Rule> {
+        let Some(node) = document.nodes.get(idx) else { return Vec::new() };
+        let Some(NodeData::Element(elem)) = &node.data else { return Vec::new() };
+
+        let mut candidates: Vec<&Entry> = self.universal.iter().collect();
+        if let Some(entries) = self.by_tag.get(&elem.tag_name) {
+            candidates.extend(entries);
+        }
+        if let Some(entries) = elem.attributes.get("id").and_then(|id| self.by_id.get(id)) {
+            candidates.extend(entries);
+        }
+        if let Some(class) = elem.attributes.get("class") {
+            for name in class.split_whitespace() {
+                candidates.extend(self.by_class.get(name).into_iter().flatten());
+            }
+        }
+
+        let filter = &self.filters[idx];
+        let mut matched: Vec<&Entry> = candidates.into_iter().filter(|entry| entry.selector.matches(document, idx, filter)).collect();
+        matched.sort_by_key(|entry| entry.rank);
+        matched.dedup_by_key(|entry| entry.rank);
+        matched.into_iter().map(|entry| &self.rules[entry.rule]).collect()
+    }
+}
+
+impl Compound {
+    fn new(selector: Selector) -> Self {
+        let mut compound = Compound { selector, tag: None, id: None, classes: Vec::new() };
+        let parts = match &compound.selector {
+            Selector::Compound(parts) => parts.as_slice(),
+            simple => std::slice::from_ref(simple),
+        };
+        for part in parts {
+            match part {
+                Selector::Element(tag) => compound.tag = Some(tag.clone()),
+                Selector::Id(id) => compound.id = Some(id.clone()),
+                Selector::Class(class) => compound.classes.push(class.clone()),
+                _ => {}
+            }
+        }
+        compound
+    }
+
+    fn matches(&self, document: &Document, idx: usize) -> bool {
+        query::matches_selector(document, idx, &self.selector)
+    }
+
+    /// Hashes of the identifiers an element must have to match
+    fn hashes(&self) -> impl Iterator<Item = u32> + '_ {
+        let tag = self.tag.as_ref().map(|tag| identifier_hash(b't', tag));
+        let id = self.id.as_ref().map(|id| identifier_hash(b'#', id));
+        tag.into_iter().chain(id).chain(self.classes.iter().map(|class| identifier_hash(b'.', class)))
+    }
+}
+
+impl ComplexSelector {
+    /// Split a selector `query` parsed into its subject and the compounds
+    /// to its left
+    fn new(selector: Selector) -> Self {
+        // `query` nests combinators leftward: `a > b c` is
+        // `Descendant(Child(a, b), c)`
+        let mut compounds = Vec::new();
+        let mut rest = selector;
+        let leftmost = loop {
+            let (combinator, left, right) = match rest {
+                Selector::Descendant(left, right) => (Combinator::Descendant, left, right),
+                Selector::Child(left, right) => (Combinator::Child, left, right),
+                Selector::NextSibling(left, right) => (Combinator::NextSibling, left, right),
+                Selector::SubsequentSibling(left, right) => (Combinator::SubsequentSibling, left, right),
+                compound => break compound,
+            };
+            compounds.push((combinator, Compound::new(*right)));
+            rest = *left;
+        };
+
+        // `compounds[i]` is joined to the compound left of it by its combinator
+        let mut leftward = Vec::new();
+        let mut right = Compound::new(leftmost);
+        for (combinator, compound) in compounds.into_iter().rev() {
+            leftward.push((combinator, right));
+            right = compound;
+        }
+        leftward.reverse();
+        let subject = right;
+
+        // A compound is an ancestor once a descendant or child combinator lies
+        // between it and the subject; before that it is a sibling
+        let ancestor_hashes = leftward
+            .iter()
+            .skip_while(|(combinator, _)| matches!(combinator, Combinator::NextSibling | Combinator::SubsequentSibling))
+            .flat_map(|(_, compound)| compound.hashes())
+            .collect();
+        ComplexSelector { subject, leftward, ancestor_hashes }
+    }
+
+    fn matches(&self, document: &Document, idx: usize, filter: &AncestorFilter) -> bool {
+        if !self.subject.matches(document, idx) {
+            return false;
+        }
+        if self.ancestor_hashes.iter().any(|&hash| !filter.might_contain(hash)) {
+            return false;
+        }
+        matches_leftward(document, idx, &self.leftward)
+    }
+}
+
+/// Whether the nodes around `idx` satisfy `leftward`, nearest first;
+/// backtracks over the choices descendant and subsequent-sibling
+/// combinators leave
+fn matches_leftward(document: &Document, idx: usize, leftward: &[(Combinator, Compound)]) -> bool {
+    let Some(((combinator, compound), rest)) = leftward.split_first() else { return true };
+    let matches = |candidate: usize| compound.matches(document, candidate) && matches_leftward(document, candidate, rest);
+    match combinator {
+        Combinator::Descendant => std::iter::successors(document.nodes[idx].parent, |&idx| document.nodes[idx].parent).any(matches),
+        Combinator::Child => document.nodes[idx].parent.is_some_and(matches),
+        Combinator::NextSibling => document.previous_element_sibling(idx).is_some_and(matches),
+        Combinator::SubsequentSibling => document.preceding_element_siblings(idx).any(matches),
+    }
+}
+
+/// A 256-bit Bloom filter of the tags, ids and classes of a node's
+/// ancestors: a miss means no ancestor has the identifier, a hit means one
+/// may
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AncestorFilter {
+    bits: [u64; 4],
+}
+
+impl AncestorFilter {
+    fn insert(&mut self, hash: u32) {
+        for bit in [hash & 0xff, (hash >> 8) & 0xff] {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    fn might_contain(&self, hash: u32) -> bool {
+        [hash & 0xff, (hash >> 8) & 0xff].iter().all(|&bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// This filter plus the identifiers of `node`, for its children
+    fn with_node(mut self, node: &Node) -> Self {
+        if let Some(NodeData::Element(elem)) = &node.data {
+            self.insert(identifier_hash(b't', &elem.tag_name));
+            if let Some(id) = elem.attributes.get("id") {
+                self.insert(identifier_hash(b'#', id));
+            }
+            for class in elem.attributes.get("class").into_iter().flat_map(|class| class.split_whitespace()) {
+                self.insert(identifier_hash(b'.', class));
+            }
+        }
+        self
+    }
+}
+
+/// FNV-1a of an identifier, with its kind mixed in so `.a` and `#a` differ
+fn identifier_hash(kind: u8, name: &str) -> u32 {
+    std::iter::once(kind).chain(name.bytes()).fold(0x811c_9dc5, |hash: u32, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// Every node's ancestor filter, indexed by node
+fn ancestor_filters(document: &Document) -> Vec<AncestorFilter> {
+    let mut filters = vec![AncestorFilter::default(); document.nodes.len()];
+    let mut stack: Vec<usize> = (0..document.nodes.len()).filter(|&idx| document.nodes[idx].parent.is_none()).collect();
+    while let Some(idx) = stack.pop() {
+        let inherited = filters[idx].with_node(&document.nodes[idx]);
+        for &child in &document.nodes[idx].children {
+            filters[child] = inherited;
+            stack.push(child);
+        }
+    }
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::parse_css;
+    use crate::parser::parse_html;
+    use crate::query::query_selector;
+
+    fn matching_selectors(document: &Document, stylesheet: &StyleSheet, selector: &str) -> Vec<String> {
+        let idx = query_selector(document, selector).unwrap().unwrap();
+        RuleMap::new(document, stylesheet).matching(document, idx).iter().map(|rule| rule.selectors.join(",")).collect()
+    }
+
+    #[test]
+    fn test_descendant_and_child_combinators() {
+        // Given: A link inside a nav list, and rules reaching it different ways
+        let document = parse_html(r#"<html><body><nav id="top"><ul class="menu"><li><a class="item">Home</a></li></ul></nav></body></html>"#);
+        let stylesheet = parse_css(
+            "nav a { color: red; } #top > a { color: blue; } .menu > li > a { color: green; } body > a { color: gray; } nav li.item { color: pink; } ul *.item:hover { color: teal; }",
+        );
+
+        // Then: Only selectors whose ancestors are all there match
+        assert_eq!(matching_selectors(&document, &stylesheet, ".item"), [".menu > li > a", "nav a"]);
+    }
+
+    #[test]
+    fn test_sibling_combinators_and_has() {
+        // Given: A card with a heading, a paragraph after it and an image,
+        // and rules reaching the paragraph through siblings and `:has()`
+        let document = parse_html(r#"<html><body><section class="card"><h2>Title</h2><p>Text</p><img src="a.png"></section></body></html>"#);
+        let stylesheet = parse_css(
+            "h2 + p { color: red; } h2 ~ img { color: blue; } .card h2 + p { color: green; } .sidebar h2 + p { color: gray; } p ~ h2 { color: pink; } section:has(> img) p { color: teal; } section:has(+ aside) p { color: olive; }",
+        );
+
+        // Then: Only selectors whose siblings and relatives are there match
+        assert_eq!(matching_selectors(&document, &stylesheet, "p"), [".card h2 + p", "h2 + p", "section:has(> img) p"]);
+        assert_eq!(matching_selectors(&document, &stylesheet, "img"), ["h2 ~ img"]);
+    }
+
+    #[test]
+    fn test_rules_keep_cascade_order_across_buckets() {
+        // Given: Rules matching one element through its id, class and tag
+        let document = parse_html(r#"<html><body><p id="intro" class="lead">Hi</p></body></html>"#);
+        let stylesheet = parse_css("p { color: red; } .lead { color: blue; } #intro { color: green; } * { margin: 0; } p.lead, .lead { color: gray; } a { color: pink; }");
+
+        // Then: Each matching rule comes back once, in the cascade's order
+        assert_eq!(matching_selectors(&document, &stylesheet, "p"), ["#intro", "*", ".lead", "p", "p.lead,.lead"]);
+    }
+
+    #[test]
+    fn test_attribute_selectors_and_source_order() {
+        // Given: A hidden paragraph, and rules whose text sorts against
+        // their order in the sheet
+        let document = parse_html(r#"<html><body><p hidden id="x">Hi</p><p>There</p></body></html>"#);
+        let stylesheet = parse_css("p { display: block; } [hidden] { display: none; }");
+
+        // Then: `[hidden]` matches only the element with the attribute, and
+        // keeps its place in the sheet when asked to
+        assert_eq!(matching_selectors(&document, &stylesheet, "#x"), ["[hidden]", "p"]);
+        let hidden = query_selector(&document, "#x").unwrap().unwrap();
+        let in_order: Vec<String> = RuleMap::in_source_order(&document, &stylesheet).matching(&document, hidden).iter().map(|rule| rule.selectors.join(",")).collect();
+        assert_eq!(in_order, ["p", "[hidden]"]);
+        let shown = document.nodes[document.nodes[hidden].parent.unwrap()].children[1];
+        assert_eq!(RuleMap::new(&document, &stylesheet).matching(&document, shown).len(), 1);
+    }
+
+    #[test]
+    fn test_attribute_values_and_operators() {
+        // Given: Inputs and links, and rules comparing attribute values
+        let document = parse_html(r#"<html><body><form><input type="text" id="name"><input type="checkbox" id="agree"></form><a id="docs" href="https://docs.example" lang="en-GB">Docs</a></body></html>"#);
+        let stylesheet = parse_css(
+            "input[type=text] { width: 10em; } input[type=\"checkbox\"] { margin: 0; } a[href^=https] { color: green; } a[href$=\".pdf\"] { color: red; } [lang|=en] { quotes: none; } form > input[type=text]:hover { outline: none; }",
+        );
+
+        // Then: Each element matches only the rules its values satisfy
+        assert_eq!(matching_selectors(&document, &stylesheet, "#name"), ["input[type=text]"]);
+        assert_eq!(matching_selectors(&document, &stylesheet, "#agree"), ["input[type=\"checkbox\"]"]);
+        assert_eq!(matching_selectors(&document, &stylesheet, "#docs"), ["[lang|=en]", "a[href^=https]"]);
+    }
+
+    #[test]
+    fn test_names_no_node_has_are_freed_with_the_map() {
+        // Given: Rules naming a tag and an attribute no node has
+        let document = parse_html(r#"<html><body><p>Hi</p></body></html>"#);
+        let stylesheet = parse_css("x-rule-only-tag { color: red; } [data-rule-only] { color: blue; } p { color: green; }");
+
+        // When: The sheet is matched against the document
+        let matched = matching_selectors(&document, &stylesheet, "p");
+
+        // Then: Only `p` matched, and the other names were freed with the map
+        assert_eq!(matched, ["p"]);
+        assert_eq!(Atom::lookup("x-rule-only-tag"), None);
+        assert_eq!(Atom::lookup("data-rule-only"), None);
+    }
+
+    #[test]
+    fn test_ancestor_filter_rejects_missing_identifiers() {
+        // Given: The filter of a node under `section.card`
+        let document = parse_html(r#"<html><body><section class="card"><p>Text</p></section></body></html>"#);
+        let paragraph = query_selector(&document, "p").unwrap().unwrap();
+        let filter = ancestor_filters(&document)[paragraph];
+
+        // Then: Its ancestors' identifiers are present, others are not
+        assert!(filter.might_contain(identifier_hash(b't', "section")));
+        assert!(filter.might_contain(identifier_hash(b'.', "card")));
+        assert!(!filter.might_contain(identifier_hash(b'.', "sidebar")));
+        assert!(!filter.might_contain(identifier_hash(b't', "p")));
+    }
+}
@@ -3,9 +3,69 @@ use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::Write;
 
+use crate::dom::Document;
+use crate::geometry::Rect;
+use crate::render::render_region;
+
+/// Screenshot encodings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    /// Lossy; transparency is dropped
+    Jpeg,
+    /// Lossless WebP; quality is ignored
+    WebP,
+    /// Raw 8-bit RGBA pixels, row by row, with no header
+    RawRgba,
+}
+
+/// JPEG quality used unless one is given
+pub const DEFAULT_QUALITY: u8 = 90;
+
+impl ImageFormat {
+    pub fn parse(name: &str) -> Result<ImageFormat, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+            "webp" => Ok(ImageFormat::WebP),
+            "rgba" | "raw" => Ok(ImageFormat::RawRgba),
+            _ => Err(format!("Unknown image format '{}': expected png, jpeg, webp or rgba", name)),
+        }
+    }
+
+    /// The format a file extension names, e.g. `shot.jpg`
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        path.extension().and_then(|ext| ImageFormat::parse(&ext.to_string_lossy()).ok())
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::WebP => "webp",
+            ImageFormat::RawRgba => "rgba",
+        }
+    }
+}
+
 /// Save a DrawTarget as a PNG file to the specified path (headless)
 /// Creates parent directories if they don't exist
 pub fn save_screenshot(draw_target: &DrawTarget, path: &Path) -> Result<PathBuf, ScreenshotError> {
+    save_screenshot_with_format(draw_target, path, ImageFormat::Png, DEFAULT_QUALITY)
+}
+
+/// Save a DrawTarget in the given format; `quality` (1-100) applies to JPEG
+/// Creates parent directories if they don't exist
+pub fn save_screenshot_with_format(
+    draw_target: &DrawTarget,
+    path: &Path,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<PathBuf, ScreenshotError> {
+    // Encode first, so a failed encode leaves no partial file behind
+    let data = encode_to_vec(draw_target, format, quality)?;
+
     // Create parent directories if needed
     if let Some(parent) = path.parent() {
         if !parent.as_os_str().is_empty() {
@@ -14,27 +74,95 @@ pub fn save_screenshot(draw_target: &DrawTarget, path: &Path) -> Result<PathBuf,
         }
     }
 
-    // Get pixel data from DrawTarget
-    let width = draw_target.width() as u32;
-    let height = draw_target.height() as u32;
-    let data = draw_target.get_data();
-
-    // Convert from raqote's format to PNG format
-    let png_data = encode_png(data, width, height)
-        .map_err(|e| ScreenshotError::EncodingError(e))?;
-
     // Write to file
     let mut file = fs::File::create(path)
         .map_err(|e| ScreenshotError::IoError(format!("Failed to create file: {}", e)))?;
 
-    file.write_all(&png_data)
+    file.write_all(&data)
         .map_err(|e| ScreenshotError::IoError(format!("Failed to write file: {}", e)))?;
 
     Ok(path.to_path_buf())
 }
 
+/// Encode a DrawTarget in memory, e.g. to stream it without touching the
+/// file system; `quality` (1-100) applies to JPEG
+pub fn encode_to_vec(draw_target: &DrawTarget, format: ImageFormat, quality: u8) -> Result<Vec<u8>, ScreenshotError> {
+    let width = draw_target.width() as u32;
+    let height = draw_target.height() as u32;
+    let data = draw_target.get_data();
+
+    match format {
+        ImageFormat::Png => encode_png(data, width, height).map_err(ScreenshotError::EncodingError),
+        ImageFormat::Jpeg => encode_jpeg(&argb_to_rgba(data), width, height, quality),
+        ImageFormat::WebP => encode_webp(&argb_to_rgba(data), width, height),
+        ImageFormat::RawRgba => Ok(argb_to_rgba(data)),
+    }
+}
+
+fn encode_jpeg(rgba_data: &[u8], width: u32, height: u32, quality: u8) -> Result<Vec<u8>, ScreenshotError> {
+    let too_large = || ScreenshotError::EncodingError(format!("{}x{} is too large for JPEG", width, height));
+    let width = u16::try_from(width).map_err(|_| too_large())?;
+    let height = u16::try_from(height).map_err(|_| too_large())?;
+
+    let mut jpeg_buffer = Vec::new();
+    jpeg_encoder::Encoder::new(&mut jpeg_buffer, quality.clamp(1, 100))
+        .encode(rgba_data, width, height, jpeg_encoder::ColorType::Rgba)
+        .map_err(|e| ScreenshotError::EncodingError(format!("JPEG write error: {}", e)))?;
+    Ok(jpeg_buffer)
+}
+
+fn encode_webp(rgba_data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, ScreenshotError> {
+    let mut webp_buffer = Vec::new();
+    image_webp::WebPEncoder::new(&mut webp_buffer)
+        .encode(rgba_data, width, height, image_webp::ColorType::Rgba8)
+        .map_err(|e| ScreenshotError::EncodingError(format!("WebP write error: {}", e)))?;
+    Ok(webp_buffer)
+}
+
+/// Render just an element's border box and save it as a PNG
+///
+/// The document must already be laid out. Boxes are painted with default
+/// styles; `Page::screenshot_element` uses the page's stylesheet.
+pub fn screenshot_element(document: &Document, element_idx: usize, path: &Path) -> Result<PathBuf, ScreenshotError> {
+    let region = element_region(document, element_idx)?;
+    save_screenshot(&render_region(document, region), path)
+}
+
+/// Render a region of a laid-out document and save it as a PNG
+pub fn screenshot_region(document: &Document, region: Rect, path: &Path) -> Result<PathBuf, ScreenshotError> {
+    save_screenshot(&render_region(document, check_region(region)?), path)
+}
+
+/// The border box of a laid-out element, for cropping screenshots to it
+pub fn element_region(document: &Document, element_idx: usize) -> Result<Rect, ScreenshotError> {
+    if element_idx >= document.nodes.len() {
+        return Err(ScreenshotError::RegionError(format!("No element {}", element_idx)));
+    }
+    let layout = document
+        .layout(element_idx)
+        .ok_or_else(|| ScreenshotError::RegionError(format!("Element {} has not been laid out", element_idx)))?;
+    check_region(layout.border_box())
+}
+
+/// Reject regions with nothing to capture
+pub fn check_region(region: Rect) -> Result<Rect, ScreenshotError> {
+    if region.width <= 0.0 || region.height <= 0.0 {
+        return Err(ScreenshotError::RegionError(format!(
+            "Region {}x{} at ({}, {}) is empty",
+            region.width, region.height, region.x, region.y
+        )));
+    }
+    Ok(region)
+}
+
 /// Encode pixel data to PNG format
 fn encode_png(data: &[u32], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    // Convert raqote's ARGB format to PNG RGBA format
+    encode_rgba_png(&argb_to_rgba(data), width, height)
+}
+
+/// Encode 8-bit RGBA pixels as a PNG
+pub fn encode_rgba_png(rgba_data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
     use png::Encoder;
 
     // Create a buffer to write PNG data
@@ -49,34 +177,73 @@ fn encode_png(data: &[u32], width: u32, height: u32) -> Result<Vec<u8>, String>
             .write_header()
             .map_err(|e| format!("PNG header error: {}", e))?;
 
-        // Convert raqote's ARGB format to PNG RGBA format
-        let mut rgba_data = Vec::with_capacity((width * height * 4) as usize);
-
-        for &pixel in data {
-            let a = ((pixel >> 24) & 0xFF) as u8;
-            let r = ((pixel >> 16) & 0xFF) as u8;
-            let g = ((pixel >> 8) & 0xFF) as u8;
-            let b = (pixel & 0xFF) as u8;
-
-            rgba_data.push(r);
-            rgba_data.push(g);
-            rgba_data.push(b);
-            rgba_data.push(a);
-        }
-
         encoder
-            .write_image_data(&rgba_data)
+            .write_image_data(rgba_data)
             .map_err(|e| format!("PNG write error: {}", e))?;
     }
 
     Ok(png_buffer)
 }
 
+/// Convert raqote's packed ARGB pixels to a byte buffer in RGBA order
+pub fn argb_to_rgba(data: &[u32]) -> Vec<u8> {
+    let mut rgba_data = Vec::with_capacity(data.len() * 4);
+
+    for &pixel in data {
+        let a = ((pixel >> 24) & 0xFF) as u8;
+        let r = ((pixel >> 16) & 0xFF) as u8;
+        let g = ((pixel >> 8) & 0xFF) as u8;
+        let b = (pixel & 0xFF) as u8;
+
+        rgba_data.push(r);
+        rgba_data.push(g);
+        rgba_data.push(b);
+        rgba_data.push(a);
+    }
+
+    rgba_data
+}
+
+/// Decode PNG bytes into `(width, height, rgba_pixels)`
+///
+/// Grayscale, palette and RGB images are expanded to 8-bit RGBA so callers
+/// can compare them byte-for-byte with rendered output.
+pub fn decode_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), ScreenshotError> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| ScreenshotError::EncodingError(format!("PNG header error: {}", e)))?;
+
+    let buffer_size = reader
+        .output_buffer_size()
+        .ok_or_else(|| ScreenshotError::EncodingError("PNG image too large".to_string()))?;
+    let mut buf = vec![0; buffer_size];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| ScreenshotError::EncodingError(format!("PNG read error: {}", e)))?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(ScreenshotError::EncodingError("Unexpanded palette PNG".to_string()))
+        }
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
 /// Error types for screenshot operations
 #[derive(Debug)]
 pub enum ScreenshotError {
     IoError(String),
     EncodingError(String),
+    /// The element or clip region cannot be captured
+    RegionError(String),
 }
 
 impl std::fmt::Display for ScreenshotError {
@@ -84,6 +251,7 @@ impl std::fmt::Display for ScreenshotError {
         match self {
             ScreenshotError::IoError(e) => write!(f, "IO Error: {}", e),
             ScreenshotError::EncodingError(e) => write!(f, "Encoding Error: {}", e),
+            ScreenshotError::RegionError(e) => write!(f, "Region Error: {}", e),
         }
     }
 }
@@ -109,10 +277,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("test.png");
 
-        let mut dt = DrawTarget::new(100, 100);
+        let dt = DrawTarget::new(100, 100);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: File should be created successfully
         assert!(result.is_ok());
@@ -128,10 +296,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("screenshot.png");
 
-        let mut dt = DrawTarget::new(50, 50);
+        let dt = DrawTarget::new(50, 50);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Returned path should match input path
         assert!(result.is_ok());
@@ -144,10 +312,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let nested_path = temp_dir.path().join("subdir").join("nested").join("screenshot.png");
 
-        let mut dt = DrawTarget::new(100, 100);
+        let dt = DrawTarget::new(100, 100);
 
         // When: We save a screenshot with nested path
-        let result = save_screenshot(&mut dt, &nested_path);
+        let result = save_screenshot(&dt, &nested_path);
 
         // Then: All parent directories should be created
         assert!(result.is_ok());
@@ -161,10 +329,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("content.png");
 
-        let mut dt = DrawTarget::new(100, 100);
+        let dt = DrawTarget::new(100, 100);
 
         // When: We save a screenshot
-        save_screenshot(&mut dt, &file_path).unwrap();
+        save_screenshot(&dt, &file_path).unwrap();
 
         // Then: File should contain PNG data (not empty)
         let metadata = fs::metadata(&file_path).unwrap();
@@ -181,10 +349,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("small.png");
 
-        let mut dt = DrawTarget::new(1, 1);
+        let dt = DrawTarget::new(1, 1);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should succeed without error
         assert!(result.is_ok());
@@ -197,10 +365,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("large.png");
 
-        let mut dt = DrawTarget::new(2048, 1536);
+        let dt = DrawTarget::new(2048, 1536);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should succeed without error
         assert!(result.is_ok());
@@ -218,13 +386,13 @@ mod tests {
         let file_path = temp_dir.path().join("overwrite.png");
 
         // Create initial file
-        let mut dt1 = DrawTarget::new(50, 50);
-        save_screenshot(&mut dt1, &file_path).unwrap();
+        let dt1 = DrawTarget::new(50, 50);
+        save_screenshot(&dt1, &file_path).unwrap();
         let first_size = fs::metadata(&file_path).unwrap().len();
 
         // When: We save a different screenshot to same path
-        let mut dt2 = DrawTarget::new(100, 100);
-        let result = save_screenshot(&mut dt2, &file_path);
+        let dt2 = DrawTarget::new(100, 100);
+        let result = save_screenshot(&dt2, &file_path);
 
         // Then: File should be overwritten
         assert!(result.is_ok());
@@ -244,8 +412,8 @@ mod tests {
         // When: We save multiple screenshots
         for i in 0..5 {
             let file_path = temp_dir.path().join(format!("screenshot_{}.png", i));
-            let mut dt = DrawTarget::new(100 + (i as i32 * 10), 100);
-            let result = save_screenshot(&mut dt, &file_path);
+            let dt = DrawTarget::new(100 + (i * 10), 100);
+            let result = save_screenshot(&dt, &file_path);
             assert!(result.is_ok());
         }
 
@@ -266,10 +434,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("header.png");
 
-        let mut dt = DrawTarget::new(10, 10);
+        let dt = DrawTarget::new(10, 10);
 
         // When: We save a screenshot
-        save_screenshot(&mut dt, &file_path).unwrap();
+        save_screenshot(&dt, &file_path).unwrap();
 
         // Then: PNG magic number should be present
         let file_data = fs::read(&file_path).unwrap();
@@ -286,10 +454,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("square.png");
 
-        let mut dt = DrawTarget::new(256, 256);
+        let dt = DrawTarget::new(256, 256);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should create valid square PNG
         assert!(result.is_ok());
@@ -304,10 +472,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("wide.png");
 
-        let mut dt = DrawTarget::new(1024, 256);
+        let dt = DrawTarget::new(1024, 256);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should create valid wide PNG
         assert!(result.is_ok());
@@ -320,10 +488,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("tall.png");
 
-        let mut dt = DrawTarget::new(256, 1024);
+        let dt = DrawTarget::new(256, 1024);
 
         // When: We save a screenshot
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should create valid tall PNG
         assert!(result.is_ok());
@@ -340,10 +508,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("a").join("b").join("c").join("d").join("screenshot.png");
 
-        let mut dt = DrawTarget::new(50, 50);
+        let dt = DrawTarget::new(50, 50);
 
         // When: We try to save with missing directories
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should succeed by creating directories
         assert!(result.is_ok());
@@ -356,10 +524,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("");
 
-        let mut dt = DrawTarget::new(50, 50);
+        let dt = DrawTarget::new(50, 50);
 
         // When: We try to save with empty filename
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should fail gracefully
         // This test verifies error handling, not that it succeeds
@@ -377,10 +545,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("test-screenshot_2024.png");
 
-        let mut dt = DrawTarget::new(50, 50);
+        let dt = DrawTarget::new(50, 50);
 
         // When: We save with special characters
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should succeed
         assert!(result.is_ok());
@@ -393,10 +561,10 @@ mod tests {
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("12345.png");
 
-        let mut dt = DrawTarget::new(50, 50);
+        let dt = DrawTarget::new(50, 50);
 
         // When: We save with numeric name
-        let result = save_screenshot(&mut dt, &file_path);
+        let result = save_screenshot(&dt, &file_path);
 
         // Then: Should succeed
         assert!(result.is_ok());
@@ -414,16 +582,167 @@ mod tests {
         let path1 = temp_dir.path().join("output1.png");
         let path2 = temp_dir.path().join("output2.png");
 
-        let mut dt1 = DrawTarget::new(100, 100);
-        let mut dt2 = DrawTarget::new(100, 100);
+        let dt1 = DrawTarget::new(100, 100);
+        let dt2 = DrawTarget::new(100, 100);
 
         // When: We save both
-        save_screenshot(&mut dt1, &path1).unwrap();
-        save_screenshot(&mut dt2, &path2).unwrap();
+        save_screenshot(&dt1, &path1).unwrap();
+        save_screenshot(&dt2, &path2).unwrap();
 
         // Then: Files should have same size (identical content)
         let size1 = fs::metadata(&path1).unwrap().len();
         let size2 = fs::metadata(&path2).unwrap().len();
         assert_eq!(size1, size2);
     }
+
+    #[test]
+    fn test_decode_png_round_trip() {
+        // Given: A saved screenshot with a known pixel
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("roundtrip.png");
+        let mut dt = DrawTarget::new(4, 3);
+        dt.clear(raqote::SolidSource::from_unpremultiplied_argb(255, 10, 20, 30));
+        save_screenshot(&dt, &file_path).unwrap();
+
+        // When: We decode it again
+        let (width, height, rgba) = decode_png(&fs::read(&file_path).unwrap()).unwrap();
+
+        // Then: Dimensions and pixels survive the round trip
+        assert_eq!((width, height), (4, 3));
+        assert_eq!(rgba, argb_to_rgba(dt.get_data()));
+        assert_eq!(&rgba[0..4], &[10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_decode_png_rejects_garbage() {
+        assert!(decode_png(b"not a png").is_err());
+    }
+
+    // ========================================================================
+    // ELEMENT AND REGION SCREENSHOTS
+    // ========================================================================
+
+    fn document_with_box() -> (Document, usize) {
+        let mut doc = Document::new();
+        let elem_idx = doc.create_element("div");
+        doc.append_child(doc.root, elem_idx);
+        doc.set_layout(elem_idx, Some(crate::dom::Layout {
+            rect: Rect::new(10.5, 20.0, 40.0, 30.0),
+            border: crate::geometry::EdgeSizes::uniform(2.0),
+            ..Default::default()
+        }));
+        (doc, elem_idx)
+    }
+
+    #[test]
+    fn test_screenshot_element_crops_to_border_box() {
+        // Given: A laid-out element
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("element.png");
+        let (doc, elem_idx) = document_with_box();
+
+        // When: We screenshot just that element
+        let result = screenshot_element(&doc, elem_idx, &file_path);
+
+        // Then: The image covers its border box, rounded out to whole pixels
+        assert_eq!(result.unwrap(), file_path);
+        let (width, height, _) = decode_png(&fs::read(&file_path).unwrap()).unwrap();
+        assert_eq!((width, height), (41, 30));
+        assert_eq!(element_region(&doc, elem_idx).unwrap(), Rect::new(10.5, 20.0, 40.0, 30.0));
+    }
+
+    #[test]
+    fn test_screenshot_region() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("clip.png");
+        let (doc, _) = document_with_box();
+
+        screenshot_region(&doc, Rect::new(0.0, 0.0, 16.0, 8.0), &file_path).unwrap();
+
+        let (width, height, _) = decode_png(&fs::read(&file_path).unwrap()).unwrap();
+        assert_eq!((width, height), (16, 8));
+    }
+
+    #[test]
+    fn test_screenshot_element_errors() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("never.png");
+        let mut doc = Document::new();
+        let unlaid = doc.create_element("span");
+
+        let not_laid_out = screenshot_element(&doc, unlaid, &file_path).unwrap_err();
+        let missing = screenshot_element(&doc, 999, &file_path).unwrap_err();
+        let empty = screenshot_region(&doc, Rect::new(0.0, 0.0, 0.0, 10.0), &file_path).unwrap_err();
+
+        assert!(not_laid_out.to_string().contains("has not been laid out"), "{}", not_laid_out);
+        assert_eq!(missing.to_string(), "Region Error: No element 999");
+        assert!(empty.to_string().contains("is empty"), "{}", empty);
+        assert!(!file_path.exists());
+    }
+
+    // ========================================================================
+    // OTHER FORMATS
+    // ========================================================================
+
+    fn red_square() -> DrawTarget {
+        let mut dt = DrawTarget::new(8, 6);
+        dt.fill_rect(
+            0.0, 0.0, 8.0, 6.0,
+            &raqote::Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(255, 255, 0, 0)),
+            &raqote::DrawOptions::new(),
+        );
+        dt
+    }
+
+    #[test]
+    fn test_encode_to_vec_formats() {
+        // Given: A red image
+        let dt = red_square();
+
+        // When: We encode it in every format
+        let png = encode_to_vec(&dt, ImageFormat::Png, DEFAULT_QUALITY).unwrap();
+        let jpeg = encode_to_vec(&dt, ImageFormat::Jpeg, 80).unwrap();
+        let webp = encode_to_vec(&dt, ImageFormat::WebP, DEFAULT_QUALITY).unwrap();
+        let raw = encode_to_vec(&dt, ImageFormat::RawRgba, DEFAULT_QUALITY).unwrap();
+
+        // Then: Each has its format's signature, and raw data is bare pixels
+        assert_eq!(&png[0..4], &[137, 80, 78, 71]);
+        assert_eq!(&jpeg[0..3], &[0xFF, 0xD8, 0xFF]);
+        assert_eq!((&webp[0..4], &webp[8..12]), (&b"RIFF"[..], &b"WEBP"[..]));
+        assert_eq!(raw.len(), 8 * 6 * 4);
+        assert_eq!(&raw[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_jpeg_decodes_close_to_the_original() {
+        let jpeg = encode_to_vec(&red_square(), ImageFormat::Jpeg, 95).unwrap();
+
+        let mut decoder = jpeg_decoder::Decoder::new(std::io::Cursor::new(jpeg));
+        let pixels = decoder.decode().unwrap();
+
+        let info = decoder.info().unwrap();
+        assert_eq!((info.width, info.height), (8, 6));
+        assert!(pixels[0] > 240 && pixels[1] < 16 && pixels[2] < 16, "{:?}", &pixels[0..3]);
+    }
+
+    #[test]
+    fn test_save_screenshot_with_format() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("shots/red.jpg");
+
+        let result = save_screenshot_with_format(&red_square(), &file_path, ImageFormat::Jpeg, 70);
+
+        assert_eq!(result.unwrap(), file_path);
+        assert_eq!(&fs::read(&file_path).unwrap()[0..2], &[0xFF, 0xD8]);
+    }
+
+    #[test]
+    fn test_image_format_names() {
+        assert_eq!(ImageFormat::from_path(Path::new("a/shot.JPG")), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_path(Path::new("shot.webp")), Some(ImageFormat::WebP));
+        assert_eq!(ImageFormat::from_path(Path::new("shot.rgba")), Some(ImageFormat::RawRgba));
+        assert_eq!(ImageFormat::from_path(Path::new("shot")), None);
+        assert_eq!(ImageFormat::parse("jpeg").unwrap().extension(), "jpg");
+        assert!(ImageFormat::parse("gif").is_err());
+    }
 }
@@ -0,0 +1,277 @@
+//! Script Elements
+//! Running a page's `<script>` elements, inline or with `src`, and
+//! `document.write` and `document.currentScript` for the scripts themselves
+//!
+//! Scripts run as tasks in document order, ordinary ones first, then
+//! `defer` and modules, then `async`. A script that throws is logged and
+//! recorded in its `ScriptLoad` without stopping the others.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Module, Object, Value};
+
+use crate::browser::{eval_named, pending_exception, SCRIPT_FILE_NAME};
+use crate::console::{ConsoleBuffer, ConsoleEntry, ConsoleLevel};
+use crate::dom::Document;
+use crate::error::BrowserError;
+use crate::event_loop::TaskSource;
+use crate::forms;
+use crate::head;
+use crate::network::{self, ResourceLoader};
+use crate::parser;
+use crate::selection::child_index;
+
+/// How a script element's text is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+    Classic,
+    Module,
+}
+
+/// When a script runs relative to the others; earlier variants go first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScriptTiming {
+    Blocking,
+    Deferred,
+    Async,
+}
+
+/// Outcome of running one script element
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLoad {
+    pub node_idx: usize,
+    pub src: Option<String>,
+    /// Why fetching or running the script failed
+    pub result: Result<(), String>,
+}
+
+/// What `idx` runs as, if it is a script element scripts can run: data
+/// blocks such as `type="application/json"`, `nomodule` fallbacks and
+/// scripts in a `<template>` are inert
+pub fn script_kind(document: &Document, idx: usize) -> Option<ScriptKind> {
+    if forms::tag_name(document, idx) != Some("script") {
+        return None;
+    }
+    let mut ancestor = document.nodes[idx].parent;
+    while let Some(parent) = ancestor {
+        if forms::tag_name(document, parent) == Some("template") {
+            return None;
+        }
+        ancestor = document.nodes[parent].parent;
+    }
+    let script_type = document.get_attribute(idx, "type").map(|t| t.trim().to_ascii_lowercase()).unwrap_or_default();
+    match script_type.as_str() {
+        "module" => Some(ScriptKind::Module),
+        _ if forms::has_attribute(document, idx, "nomodule") => None,
+        "" | "text/javascript" | "application/javascript" | "text/ecmascript" | "application/ecmascript" => Some(ScriptKind::Classic),
+        _ => None,
+    }
+}
+
+/// When the script element `idx` of `kind` runs; `defer` and `async` only
+/// apply to classic scripts with a `src`
+pub fn script_timing(document: &Document, idx: usize, kind: ScriptKind) -> ScriptTiming {
+    let external = forms::has_attribute(document, idx, "src");
+    if forms::has_attribute(document, idx, "async") && (external || kind == ScriptKind::Module) {
+        ScriptTiming::Async
+    } else if kind == ScriptKind::Module || (external && forms::has_attribute(document, idx, "defer")) {
+        ScriptTiming::Deferred
+    } else {
+        ScriptTiming::Blocking
+    }
+}
+
+/// The script element to run next among those not in `started`
+pub fn next_script(document: &Document, started: &HashSet<usize>) -> Option<(usize, ScriptKind)> {
+    if document.nodes.is_empty() {
+        return None;
+    }
+    forms::descendants(document, document.root)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, idx)| !started.contains(idx))
+        .filter_map(|(position, idx)| script_kind(document, idx).map(|kind| (script_timing(document, idx, kind), position, idx, kind)))
+        .min_by_key(|&(timing, position, _, _)| (timing, position))
+        .map(|(_, _, idx, kind)| (idx, kind))
+}
+
+#[derive(Debug, Default)]
+struct ScriptState {
+    started: HashSet<usize>,
+    /// The script running now, `document.currentScript`
+    current: Option<usize>,
+    loads: Vec<ScriptLoad>,
+}
+
+/// A page's script elements: which have run, and the one running
+#[derive(Clone)]
+pub struct PageScripts {
+    document: Rc<RefCell<Document>>,
+    loader: Rc<dyn ResourceLoader>,
+    console: ConsoleBuffer,
+    state: Rc<RefCell<ScriptState>>,
+}
+
+impl PageScripts {
+    pub fn new(document: Rc<RefCell<Document>>, loader: Rc<dyn ResourceLoader>, console: ConsoleBuffer) -> Self {
+        PageScripts { document, loader, console, state: Rc::default() }
+    }
+
+    /// Forget which scripts ran, for a new document
+    pub fn reset(&self) {
+        *self.state.borrow_mut() = ScriptState::default();
+    }
+
+    /// The outcomes of the scripts run since the last call
+    pub fn take_loads(&self) -> Vec<ScriptLoad> {
+        std::mem::take(&mut self.state.borrow_mut().loads)
+    }
+
+    /// The script element running now
+    pub fn current(&self) -> Option<usize> {
+        self.state.borrow().current
+    }
+
+    /// Insert `html` after the running script, or at the end of the body
+    pub fn write(&self, html: &str) {
+        let mut document = self.document.borrow_mut();
+        let nodes = parser::parse_fragment(&mut document, html);
+        let current = self.current().filter(|&idx| document.nodes[idx].parent.is_some());
+        let (parent, mut index) = match current {
+            Some(script) => (document.nodes[script].parent.unwrap(), child_index(&document, script) + 1),
+            None => {
+                let parent = head::body(&document).unwrap_or(document.root);
+                (parent, document.nodes[parent].children.len())
+            }
+        };
+        for idx in nodes {
+            document.insert_child(parent, index, idx);
+            index += 1;
+        }
+    }
+
+    /// The script's source, fetched if it has a `src`
+    fn source(&self, idx: usize) -> (Option<String>, Result<String, String>) {
+        let document = self.document.borrow();
+        match document.get_attribute(idx, "src") {
+            Some(src) => {
+                let source = network::fetch(&*self.loader, src).map(|bytes| String::from_utf8_lossy(&bytes).into_owned()).map_err(|e| e.to_string());
+                (Some(src.clone()), source)
+            }
+            None => (None, Ok(document.text_content(idx))),
+        }
+    }
+
+    fn run<'js>(&self, ctx: &Ctx<'js>, idx: usize, kind: ScriptKind) -> ScriptLoad {
+        let (src, source) = self.source(idx);
+        let name = src.clone().unwrap_or_else(|| SCRIPT_FILE_NAME.to_string());
+        self.state.borrow_mut().current = Some(idx);
+        let result = source.and_then(|source| {
+            let ran = match kind {
+                ScriptKind::Classic => eval_named(ctx, &source, &name).map(|_| ()),
+                ScriptKind::Module => Module::evaluate(ctx.clone(), name.as_str(), source).map(|_| ()).map_err(|_| pending_exception(ctx)),
+            };
+            ran.map_err(|e| match e {
+                BrowserError::JavaScriptError(message, _) => message,
+                other => other.to_string(),
+            })
+        });
+        self.state.borrow_mut().current = None;
+        if let Err(message) = &result {
+            self.console.borrow_mut().push(ConsoleEntry { level: ConsoleLevel::Error, message: format!("Uncaught {}", message) });
+        }
+        ScriptLoad { node_idx: idx, src, result }
+    }
+}
+
+impl TaskSource for PageScripts {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let next = next_script(&self.document.borrow(), &self.state.borrow().started);
+        let Some((idx, kind)) = next else { return Ok(false) };
+        self.state.borrow_mut().started.insert(idx);
+        let load = self.run(ctx, idx, kind);
+        self.state.borrow_mut().loads.push(load);
+        Ok(true)
+    }
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// Expose `document.write`, `document.writeln` and `document.currentScript`,
+/// the element as a node index
+pub fn install_scripts<'js>(ctx: &Ctx<'js>, scripts: PageScripts) -> rquickjs::Result<()> {
+    if ctx.globals().get::<_, Object>("document").is_err() {
+        return Ok(());
+    }
+
+    let native = Object::new(ctx.clone())?;
+    let shared = scripts.clone();
+    native.set("write", Function::new(ctx.clone(), move |html: String| shared.write(&html))?)?;
+    native.set(
+        "current",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>| -> Value<'js> {
+            match scripts.current() {
+                Some(idx) => Value::new_number(ctx, idx as f64),
+                None => Value::new_null(ctx),
+            }
+        })?,
+    )?;
+
+    ctx.globals().set("__cortexScripts", native)?;
+    ctx.eval::<(), _>(SCRIPTS_PRELUDE)
+}
+
+const SCRIPTS_PRELUDE: &str = r#"
+(function() {
+    const native = globalThis.__cortexScripts;
+    document.write = (...markup) => native.write(markup.join(''));
+    document.writeln = (...markup) => native.write(markup.join('') + '\n');
+    Object.defineProperty(document, 'currentScript', { get() { return native.current(); }, configurable: true });
+})();
+"#;
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    fn run_order(html: &str) -> Vec<String> {
+        let document = parse_html(html);
+        let mut started = HashSet::new();
+        let mut order = Vec::new();
+        while let Some((idx, _)) = next_script(&document, &started) {
+            started.insert(idx);
+            order.push(document.get_attribute(idx, "id").cloned().unwrap_or_default());
+        }
+        order
+    }
+
+    #[test]
+    fn test_scripts_run_blocking_then_deferred_then_async() {
+        let order = run_order(concat!(
+            r#"<html><head><script id="a" src="a.js" async=""></script><script id="d" src="d.js" defer=""></script>"#,
+            r#"<script id="m" type="module">1</script><script id="i" defer="">1</script></head>"#,
+            r#"<body><script id="b" src="b.js"></script><script id="j" type="application/json">{}</script>"#,
+            r#"<template><script id="t">1</script></template><script id="n" nomodule="">1</script></body></html>"#
+        ));
+        // `defer` on an inline script is ignored; data blocks, template
+        // contents and nomodule fallbacks never run
+        assert_eq!(order, ["i", "b", "d", "m", "a"]);
+    }
+
+    #[test]
+    fn test_script_text_is_raw() {
+        let document = parse_html("<html><body><script>if (1 < 2 && '</p>') x = '<b>';</script><p>After</p></body></html>");
+        let script = (0..document.nodes.len()).find(|&idx| script_kind(&document, idx).is_some()).unwrap();
+        assert_eq!(document.text_content(script), "if (1 < 2 && '</p>') x = '<b>';");
+        assert_eq!(document.nodes[document.nodes[script].parent.unwrap()].children.len(), 2);
+    }
+}
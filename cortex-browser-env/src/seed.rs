@@ -0,0 +1,303 @@
+//! Run Seed
+//! One seed per test run drives every source of randomness, so a failing run
+//! can be replayed exactly with `--seed`
+//!
+//! Deterministic mode (`--deterministic`) goes further for golden masters:
+//! runs without a seed use `DETERMINISTIC_SEED` rather than the clock, and
+//! `Date` is frozen at `DEFAULT_FROZEN_TIME`, or the `--freeze-time` given,
+//! so pages that print dates render the same on every run. Attributes and
+//! declarations are kept in name order, so nothing iterates a hash map in
+//! an order that changes between runs.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rquickjs::{Ctx, Function, Object};
+
+/// Environment variable consulted when `--seed` is not given
+pub const SEED_ENV_VAR: &str = "CORTEX_SEED";
+
+/// Seed deterministic mode uses when none is given
+pub const DETERMINISTIC_SEED: u64 = 0;
+
+/// When deterministic mode freezes `Date` unless told otherwise:
+/// 2020-01-01T00:00:00Z, in milliseconds since the Unix epoch
+pub const DEFAULT_FROZEN_TIME: f64 = 1_577_836_800_000.0;
+
+/// Deterministic pseudo-random generator (SplitMix64)
+///
+/// Not cryptographically secure; fast, tiny and identical on every platform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `[0, 1)`, like `Math.random()`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in `[low, high)`; returns `low` for an empty range
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        if high <= low {
+            return low;
+        }
+        low + self.next_u64() % (high - low)
+    }
+
+    /// Uniformly pick an element
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.range(0, items.len() as u64) as usize)
+    }
+
+    /// Shuffle in place (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.range(0, i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// The seed of one test run
+///
+/// Each consumer (JS `Math.random`, data factories, schedulers) draws from
+/// its own labelled stream, so adding randomness in one place does not shift
+/// the values another place sees for the same seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSeed(pub u64);
+
+impl RunSeed {
+    /// Seed from `--seed <n>` / `--seed=<n>`, then `CORTEX_SEED`, then the clock
+    pub fn resolve(args: &[String]) -> Result<RunSeed, String> {
+        Self::resolve_with(parse_seed_arg(args)?)
+    }
+
+    /// Use an explicit seed if given, else `CORTEX_SEED`, else the clock
+    pub fn resolve_with(seed: Option<u64>) -> Result<RunSeed, String> {
+        if let Some(seed) = seed {
+            return Ok(RunSeed(seed));
+        }
+        if let Ok(value) = std::env::var(SEED_ENV_VAR) {
+            return parse_seed(&value).map(RunSeed);
+        }
+        Ok(RunSeed::from_clock())
+    }
+
+    /// A fresh seed from the system clock
+    pub fn from_clock() -> RunSeed {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        // Scramble so consecutive runs get unrelated seeds
+        RunSeed(Rng::new(nanos).next_u64() % 1_000_000_000)
+    }
+
+    /// Generator for one consumer, identified by `label`
+    pub fn rng(&self, label: &str) -> Rng {
+        // FNV-1a of the label, mixed into the run seed
+        let hash = label
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+        Rng::new(Rng::new(self.0 ^ hash).next_u64())
+    }
+
+    /// How to replay this run
+    pub fn reproduce_hint(&self) -> String {
+        format!("Re-run with --seed {} to reproduce", self.0)
+    }
+}
+
+impl fmt::Display for RunSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parse a seed value, e.g. from `--seed` or `CORTEX_SEED`
+pub fn parse_seed(value: &str) -> Result<u64, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid seed '{}': expected a non-negative integer", value.trim()))
+}
+
+/// Find `--seed <n>` or `--seed=<n>` among command-line arguments
+pub fn parse_seed_arg(args: &[String]) -> Result<Option<u64>, String> {
+    for (i, arg) in args.iter().enumerate() {
+        if let Some(value) = arg.strip_prefix("--seed=") {
+            return parse_seed(value).map(Some);
+        }
+        if arg == "--seed" {
+            let value = args.get(i + 1).ok_or("--seed requires a value")?;
+            return parse_seed(value).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Remove `--seed` and its value from command-line arguments
+pub fn strip_seed_args(args: &[String]) -> Vec<String> {
+    let mut rest = Vec::new();
+    let mut skip_next = false;
+    for arg in args {
+        if skip_next {
+            skip_next = false;
+        } else if arg == "--seed" {
+            skip_next = true;
+        } else if !arg.starts_with("--seed=") {
+            rest.push(arg.clone());
+        }
+    }
+    rest
+}
+
+/// Replace JavaScript's `Math.random` with the run's `js` stream
+pub fn install_seeded_math_random(ctx: &Ctx<'_>, seed: RunSeed) -> rquickjs::Result<()> {
+    let rng = Rc::new(RefCell::new(seed.rng("js")));
+    let math: Object = ctx.globals().get("Math")?;
+    math.set("random", Function::new(ctx.clone(), move || rng.borrow_mut().next_f64())?)?;
+    Ok(())
+}
+
+/// `Date` with the clock stopped at `now`, over the real one
+const FROZEN_DATE: &str = r#"
+(now => {
+    const RealDate = globalThis.Date;
+    const FrozenDate = function Date(...args) {
+        if (!new.target) return new RealDate(now).toString();
+        return Reflect.construct(RealDate, args.length > 0 ? args : [now], new.target);
+    };
+    Object.setPrototypeOf(FrozenDate, RealDate);
+    FrozenDate.prototype = RealDate.prototype;
+    FrozenDate.prototype.constructor = FrozenDate;
+    FrozenDate.now = () => now;
+    globalThis.Date = FrozenDate;
+})
+"#;
+
+/// Freeze JavaScript's clock at `now`, in milliseconds since the Unix
+/// epoch: `Date.now()` and `new Date()` report it and `Date()` formats it;
+/// dates built from arguments are unaffected
+pub fn install_frozen_date(ctx: &Ctx<'_>, now: f64) -> rquickjs::Result<()> {
+    let freeze: Function = ctx.eval(FROZEN_DATE)?;
+    freeze.call((now,))
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        let first: Vec<u64> = (0..5).map(|_| a.next_u64()).collect();
+        assert_eq!(first, (0..5).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(first, (0..5).map(|_| c.next_u64()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_rng_ranges() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let f = rng.next_f64();
+            assert!((0.0..1.0).contains(&f));
+            assert!((10..20).contains(&rng.range(10, 20)));
+        }
+        assert_eq!(rng.range(5, 5), 5);
+        assert_eq!(rng.choose::<u8>(&[]), None);
+
+        let mut items: Vec<u32> = (0..10).collect();
+        rng.shuffle(&mut items);
+        items.sort();
+        assert_eq!(items, (0..10).collect::<Vec<_>>(), "Shuffle is a permutation");
+    }
+
+    #[test]
+    fn test_labelled_streams_are_independent() {
+        let seed = RunSeed(1234);
+
+        assert_eq!(seed.rng("factories").next_u64(), seed.rng("factories").next_u64());
+        assert_ne!(seed.rng("factories").next_u64(), seed.rng("js").next_u64());
+        assert_ne!(seed.rng("js").next_u64(), RunSeed(1235).rng("js").next_u64());
+    }
+
+    #[test]
+    fn test_seed_arguments() {
+        assert_eq!(parse_seed_arg(&args(&["prog", "--seed", "99", "code"])), Ok(Some(99)));
+        assert_eq!(parse_seed_arg(&args(&["prog", "--seed=5"])), Ok(Some(5)));
+        assert_eq!(parse_seed_arg(&args(&["prog", "code"])), Ok(None));
+        assert!(parse_seed_arg(&args(&["prog", "--seed"])).is_err());
+        assert!(parse_seed_arg(&args(&["prog", "--seed", "abc"])).is_err());
+
+        assert_eq!(strip_seed_args(&args(&["prog", "--seed", "99", "code", "--seed=1"])), args(&["prog", "code"]));
+        assert_eq!(RunSeed::resolve(&args(&["prog", "--seed", "8"])), Ok(RunSeed(8)));
+    }
+
+    #[test]
+    fn test_seeded_math_random_replays() {
+        let run = |seed: u64| -> String {
+            let runtime = rquickjs::Runtime::new().unwrap();
+            let context = rquickjs::Context::full(&runtime).unwrap();
+            context.with(|ctx| {
+                install_seeded_math_random(&ctx, RunSeed(seed)).unwrap();
+                ctx.eval("[Math.random(), Math.random()].join(',')").unwrap()
+            })
+        };
+
+        assert_eq!(run(31), run(31));
+        assert_ne!(run(31), run(32));
+    }
+
+    #[test]
+    fn test_frozen_date_stops_the_clock() {
+        let runtime = rquickjs::Runtime::new().unwrap();
+        let context = rquickjs::Context::full(&runtime).unwrap();
+        let dates: Vec<String> = context.with(|ctx| {
+            install_frozen_date(&ctx, DEFAULT_FROZEN_TIME).unwrap();
+            ctx.eval(
+                r#"[
+                    Date.now(),
+                    new Date().toISOString(),
+                    new Date(0).toISOString(),
+                    Date.UTC(2021, 0, 1),
+                    Date() === new Date().toString(),
+                    new Date() instanceof Date,
+                ].map(String)"#,
+            )
+            .unwrap()
+        });
+
+        assert_eq!(dates, ["1577836800000", "2020-01-01T00:00:00.000Z", "1970-01-01T00:00:00.000Z", "1609459200000", "true", "true"]);
+    }
+}
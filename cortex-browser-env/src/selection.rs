@@ -0,0 +1,563 @@
+//! Selection and Range
+//! Boundary points in the tree, the ranges between them, and the page's
+//! selection, with `Range`, `document.createRange()` and `getSelection()`
+//! for scripts
+//!
+//! A boundary point is a node and an offset into it: characters for a text
+//! node, children for any other. The selection keeps its anchor and focus
+//! on the Rust side, so the page and scripts see the same one. Changing it
+//! queues a `selectionchange` at the document, fired as a task, and several
+//! changes in a row fire one event, as in browsers.
+//!
+//! Ranges are not live: removing nodes does not move the boundary points
+//! into them.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object, Value};
+
+use crate::dom::{Document, NodeData};
+use crate::event_loop::TaskSource;
+use crate::events;
+use crate::handles::JsNode;
+
+/// A position in the tree: before the `offset`th character of a text node,
+/// or the `offset`th child of another node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundaryPoint {
+    pub node: usize,
+    pub offset: usize,
+}
+
+impl BoundaryPoint {
+    pub fn new(node: usize, offset: usize) -> Self {
+        BoundaryPoint { node, offset }
+    }
+}
+
+/// The part of the tree between two boundary points, `start` never after
+/// `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: BoundaryPoint,
+    pub end: BoundaryPoint,
+}
+
+impl Range {
+    /// The range from `a` to `b`, whichever comes first
+    pub fn between(document: &Document, a: BoundaryPoint, b: BoundaryPoint) -> Self {
+        match compare_points(document, a, b) {
+            Ordering::Greater => Range { start: b, end: a },
+            _ => Range { start: a, end: b },
+        }
+    }
+
+    /// The range covering `node`'s contents
+    pub fn node_contents(document: &Document, node: usize) -> Self {
+        Range { start: BoundaryPoint::new(node, 0), end: BoundaryPoint::new(node, node_length(document, node)) }
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The text the range covers, as `Range.toString()` gives it
+    pub fn text(&self, document: &Document) -> String {
+        let mut text = String::new();
+        for idx in tree_order(document, document.root) {
+            let Some(NodeData::Text(content)) = &document.nodes[idx].data else { continue };
+            let length = content.chars().count();
+            let from = if idx == self.start.node {
+                self.start.offset
+            } else if compare_points(document, BoundaryPoint::new(idx, 0), self.start) == Ordering::Less {
+                continue;
+            } else {
+                0
+            };
+            let to = if idx == self.end.node {
+                self.end.offset
+            } else if compare_points(document, BoundaryPoint::new(idx, length), self.end) == Ordering::Greater {
+                continue;
+            } else {
+                length
+            };
+            text.extend(content.chars().skip(from).take(to.saturating_sub(from)));
+        }
+        text
+    }
+}
+
+/// `node` and its descendants in tree order
+pub(crate) fn tree_order(document: &Document, node: usize) -> Vec<usize> {
+    let mut order = Vec::new();
+    let mut stack = vec![node];
+    while let Some(current) = stack.pop() {
+        order.push(current);
+        stack.extend(document.nodes[current].children.iter().rev());
+    }
+    order
+}
+
+/// How many offsets `node` has: characters of text, else children
+pub fn node_length(document: &Document, node: usize) -> usize {
+    match &document.nodes[node].data {
+        Some(NodeData::Text(content)) => content.chars().count(),
+        _ => document.nodes[node].children.len(),
+    }
+}
+
+/// `node`'s index among its parent's children
+pub fn child_index(document: &Document, node: usize) -> usize {
+    document.nodes[node]
+        .parent
+        .and_then(|parent| document.nodes[parent].children.iter().position(|&child| child == node))
+        .unwrap_or(0)
+}
+
+/// Child indices leading from the root to `node`
+fn tree_position(document: &Document, node: usize) -> Vec<usize> {
+    let mut position: Vec<usize> = events::event_path(document, node).iter().map(|&idx| child_index(document, idx)).collect();
+    // The root's own index means nothing
+    position.pop();
+    position.reverse();
+    position
+}
+
+/// Where boundary point `a` lies relative to `b` in tree order
+pub fn compare_points(document: &Document, a: BoundaryPoint, b: BoundaryPoint) -> Ordering {
+    if a.node == b.node {
+        return a.offset.cmp(&b.offset);
+    }
+    let (position_a, position_b) = (tree_position(document, a.node), tree_position(document, b.node));
+    if position_b.starts_with(&position_a) {
+        // b is inside a's node, under the child at this index
+        return if position_b[position_a.len()] < a.offset { Ordering::Greater } else { Ordering::Less };
+    }
+    if position_a.starts_with(&position_b) {
+        return compare_points(document, b, a).reverse();
+    }
+    position_a.cmp(&position_b)
+}
+
+/// The deepest node holding both `a` and `b`
+pub fn common_ancestor(document: &Document, a: usize, b: usize) -> usize {
+    let ancestors_b = events::event_path(document, b);
+    events::event_path(document, a).into_iter().find(|node| ancestors_b.contains(node)).unwrap_or(document.root)
+}
+
+// ============================================================================
+// SELECTION
+// ============================================================================
+
+/// Where a selection started and where it was extended to; the focus may
+/// come before the anchor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Option<BoundaryPoint>,
+    pub focus: Option<BoundaryPoint>,
+}
+
+impl Selection {
+    /// The selected range, `None` when nothing is selected
+    pub fn range(&self, document: &Document) -> Option<Range> {
+        Some(Range::between(document, self.anchor?, self.focus?))
+    }
+
+    /// The selected text
+    pub fn text(&self, document: &Document) -> String {
+        self.range(document).map(|range| range.text(document)).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct SelectionState {
+    selection: Selection,
+    change_pending: bool,
+}
+
+/// A page's selection, shared with its scripts and polled by its event
+/// loop for `selectionchange`
+#[derive(Clone)]
+pub struct PageSelection {
+    document: Rc<RefCell<Document>>,
+    state: Rc<RefCell<SelectionState>>,
+}
+
+impl PageSelection {
+    pub fn new(document: Rc<RefCell<Document>>) -> Self {
+        PageSelection { document, state: Rc::new(RefCell::new(SelectionState::default())) }
+    }
+
+    pub fn get(&self) -> Selection {
+        self.state.borrow().selection
+    }
+
+    /// Select from `anchor` to `focus`, queuing `selectionchange` if that
+    /// changes the selection
+    pub fn set(&self, anchor: BoundaryPoint, focus: BoundaryPoint) {
+        self.replace(Selection { anchor: Some(anchor), focus: Some(focus) });
+    }
+
+    /// Select nothing, queuing `selectionchange` if something was selected
+    pub fn clear(&self) {
+        self.replace(Selection::default());
+    }
+
+    /// Forget the selection without an event, as loading a new document does
+    pub fn reset(&self) {
+        *self.state.borrow_mut() = SelectionState::default();
+    }
+
+    fn replace(&self, selection: Selection) {
+        let mut state = self.state.borrow_mut();
+        if state.selection != selection {
+            state.selection = selection;
+            state.change_pending = true;
+        }
+    }
+}
+
+impl TaskSource for PageSelection {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        if !std::mem::take(&mut self.state.borrow_mut().change_pending) {
+            return Ok(false);
+        }
+        let root = self.document.borrow().root;
+        let event = events::create_event(ctx, "Event", "selectionchange", Object::new(ctx.clone())?)?;
+        event.set("isTrusted", true)?;
+        events::dispatch_event(ctx, &self.document, root, event)?;
+        Ok(true)
+    }
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// `Range`, `document.createRange()` and the `Selection` `getSelection()`
+/// returns, over the natives of `install_selection`
+const SELECTION_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexSelection;
+    delete globalThis.__cortexSelection;
+    const indexSizeError = () => new RangeError("IndexSizeError: The offset is larger than the node's length.");
+    // The Range the selection handed out, kept while it still matches
+    let selected = null;
+
+    class Range {
+        #start;
+        #end;
+        constructor() {
+            this.#start = this.#end = [native.root(), 0];
+        }
+        get startContainer() { return this.#start[0]; }
+        get startOffset() { return this.#start[1]; }
+        get endContainer() { return this.#end[0]; }
+        get endOffset() { return this.#end[1]; }
+        get collapsed() { return this.#start[0] === this.#end[0] && this.#start[1] === this.#end[1]; }
+        get commonAncestorContainer() { return native.common(this.#start[0], this.#end[0]); }
+        setStart(node, offset) {
+            if (offset > native.length(node)) throw indexSizeError();
+            this.#start = [node, offset];
+            if (native.compare(node, offset, ...this.#end) > 0) this.#end = this.#start;
+            this.#changed();
+        }
+        setEnd(node, offset) {
+            if (offset > native.length(node)) throw indexSizeError();
+            this.#end = [node, offset];
+            if (native.compare(...this.#start, node, offset) > 0) this.#start = this.#end;
+            this.#changed();
+        }
+        setStartBefore(node) { this.setStart(native.parent(node), native.index(node)); }
+        setStartAfter(node) { this.setStart(native.parent(node), native.index(node) + 1); }
+        setEndBefore(node) { this.setEnd(native.parent(node), native.index(node)); }
+        setEndAfter(node) { this.setEnd(native.parent(node), native.index(node) + 1); }
+        collapse(toStart = false) {
+            if (toStart) this.#end = this.#start;
+            else this.#start = this.#end;
+            this.#changed();
+        }
+        selectNode(node) {
+            const [parent, index] = [native.parent(node), native.index(node)];
+            this.#start = [parent, index];
+            this.#end = [parent, index + 1];
+            this.#changed();
+        }
+        selectNodeContents(node) {
+            this.#start = [node, 0];
+            this.#end = [node, native.length(node)];
+            this.#changed();
+        }
+        cloneRange() {
+            const range = new Range();
+            range.#start = this.#start;
+            range.#end = this.#end;
+            return range;
+        }
+        detach() {}
+        toString() { return native.text(...this.#start, ...this.#end); }
+        #changed() {
+            if (selected !== this) return;
+            // The selection follows its range, keeping its direction
+            if (state()?.backward) native.set(...this.#end, ...this.#start);
+            else native.set(...this.#start, ...this.#end);
+        }
+        static fromPoints(start, end) {
+            const range = new Range();
+            range.#start = start;
+            range.#end = end;
+            return range;
+        }
+        static pointsOf(range) { return [range.#start, range.#end]; }
+    }
+
+    const samePoint = (a, b) => a[0] === b[0] && a[1] === b[1];
+    const state = () => {
+        const points = native.get();
+        if (!points) return null;
+        const [anchor, focus] = [points.slice(0, 2), points.slice(2, 4)];
+        const backward = native.compare(...anchor, ...focus) > 0;
+        return { anchor, focus, backward, start: backward ? focus : anchor, end: backward ? anchor : focus };
+    };
+
+    class Selection {
+        get anchorNode() { return state()?.anchor[0] ?? null; }
+        get anchorOffset() { return state()?.anchor[1] ?? 0; }
+        get focusNode() { return state()?.focus[0] ?? null; }
+        get focusOffset() { return state()?.focus[1] ?? 0; }
+        get isCollapsed() {
+            const current = state();
+            return !current || samePoint(current.anchor, current.focus);
+        }
+        get rangeCount() { return state() ? 1 : 0; }
+        get type() {
+            if (!state()) return "None";
+            return this.isCollapsed ? "Caret" : "Range";
+        }
+        get direction() {
+            const current = state();
+            if (!current || this.isCollapsed) return "none";
+            return current.backward ? "backward" : "forward";
+        }
+        getRangeAt(index) {
+            const current = state();
+            if (!current || index !== 0) throw indexSizeError();
+            if (selected) {
+                const [start, end] = Range.pointsOf(selected);
+                if (samePoint(start, current.start) && samePoint(end, current.end)) return selected;
+            }
+            selected = Range.fromPoints(current.start, current.end);
+            return selected;
+        }
+        addRange(range) {
+            if (state()) return;
+            const [start, end] = Range.pointsOf(range);
+            selected = range;
+            native.set(...start, ...end);
+        }
+        removeRange(range) {
+            if (range === selected) this.removeAllRanges();
+        }
+        removeAllRanges() {
+            selected = null;
+            native.clear();
+        }
+        empty() { this.removeAllRanges(); }
+        collapse(node, offset = 0) {
+            if (node === null) return this.removeAllRanges();
+            if (offset > native.length(node)) throw indexSizeError();
+            selected = null;
+            native.set(node, offset, node, offset);
+        }
+        collapseToStart() {
+            const current = state();
+            if (current) this.collapse(...current.start);
+        }
+        collapseToEnd() {
+            const current = state();
+            if (current) this.collapse(...current.end);
+        }
+        extend(node, offset = 0) {
+            const current = state();
+            if (!current) throw new Error("InvalidStateError: There is no selection to extend.");
+            if (offset > native.length(node)) throw indexSizeError();
+            selected = null;
+            native.set(...current.anchor, node, offset);
+        }
+        setBaseAndExtent(anchorNode, anchorOffset, focusNode, focusOffset) {
+            if (anchorOffset > native.length(anchorNode) || focusOffset > native.length(focusNode)) throw indexSizeError();
+            selected = null;
+            native.set(anchorNode, anchorOffset, focusNode, focusOffset);
+        }
+        selectAllChildren(node) {
+            selected = null;
+            native.set(node, 0, node, native.length(node));
+        }
+        containsNode(node, allowPartialContainment = false) {
+            const current = state();
+            if (!current) return false;
+            const [parent, index] = [native.parent(node), native.index(node)];
+            if (parent === null) return false;
+            const before = native.compare(...current.start, parent, index) <= 0;
+            const after = native.compare(parent, index + 1, ...current.end) <= 0;
+            if (before && after) return true;
+            if (!allowPartialContainment) return false;
+            return native.compare(...current.start, parent, index + 1) < 0 && native.compare(parent, index, ...current.end) < 0;
+        }
+        toString() {
+            const current = state();
+            return current ? native.text(...current.start, ...current.end) : "";
+        }
+    }
+
+    const selection = new Selection();
+    globalThis.Range = Range;
+    globalThis.Selection = Selection;
+    globalThis.getSelection = () => selection;
+    if (globalThis.document) {
+        document.createRange = () => new Range();
+        document.getSelection = () => selection;
+        // `selectionchange` fires at the document node
+        document.addEventListener ??= (type, listener) => addEventListener(native.root(), type, listener);
+        document.removeEventListener ??= (type, listener) => removeEventListener(native.root(), type, listener);
+    }
+})();
+"#;
+
+/// Install `Range`, `Selection`, `getSelection()` and
+/// `document.createRange()` over `selection`
+///
+/// Needs the `events` bindings installed first, and `document` for its
+/// methods.
+pub fn install_selection<'js>(ctx: &Ctx<'js>, selection: PageSelection) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let document = selection.document.clone();
+
+    let doc = document.clone();
+    native.set("root", Function::new(ctx.clone(), move || doc.borrow().root)?)?;
+    let doc = document.clone();
+    native.set(
+        "length",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<usize> {
+            let document = doc.borrow();
+            Ok(node_length(&document, node.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "parent",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Value<'js>> {
+            let document = doc.borrow();
+            Ok(match document.nodes[node.live(&ctx, &document)?].parent {
+                Some(parent) => Value::new_number(ctx, parent as f64),
+                None => Value::new_null(ctx),
+            })
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "index",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<usize> {
+            let document = doc.borrow();
+            Ok(child_index(&document, node.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "common",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, a: JsNode, b: JsNode| -> rquickjs::Result<usize> {
+            let document = doc.borrow();
+            Ok(common_ancestor(&document, a.live(&ctx, &document)?, b.live(&ctx, &document)?))
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "compare",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, a: JsNode, a_offset: usize, b: JsNode, b_offset: usize| -> rquickjs::Result<i32> {
+            let document = doc.borrow();
+            let (a, b) = (BoundaryPoint::new(a.live(&ctx, &document)?, a_offset), BoundaryPoint::new(b.live(&ctx, &document)?, b_offset));
+            Ok(compare_points(&document, a, b) as i32)
+        })?,
+    )?;
+    let doc = document.clone();
+    native.set(
+        "text",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, start: JsNode, start_offset: usize, end: JsNode, end_offset: usize| -> rquickjs::Result<String> {
+            let document = doc.borrow();
+            let start = BoundaryPoint::new(start.live(&ctx, &document)?, start_offset);
+            let end = BoundaryPoint::new(end.live(&ctx, &document)?, end_offset);
+            Ok(Range { start, end }.text(&document))
+        })?,
+    )?;
+
+    let shared = selection.clone();
+    native.set(
+        "get",
+        Function::new(ctx.clone(), move || -> Option<Vec<usize>> {
+            let Selection { anchor, focus } = shared.get();
+            let (anchor, focus) = (anchor?, focus?);
+            Some(vec![anchor.node, anchor.offset, focus.node, focus.offset])
+        })?,
+    )?;
+    let shared = selection.clone();
+    native.set(
+        "set",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, anchor: JsNode, anchor_offset: usize, focus: JsNode, focus_offset: usize| -> rquickjs::Result<()> {
+            let (anchor, focus) = {
+                let document = document.borrow();
+                (anchor.live(&ctx, &document)?, focus.live(&ctx, &document)?)
+            };
+            shared.set(BoundaryPoint::new(anchor, anchor_offset), BoundaryPoint::new(focus, focus_offset));
+            Ok(())
+        })?,
+    )?;
+    native.set("clear", Function::new(ctx.clone(), move || selection.clear())?)?;
+
+    ctx.globals().set("__cortexSelection", native)?;
+    ctx.eval::<(), _>(SELECTION_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    fn text_nodes(document: &Document) -> Vec<usize> {
+        (0..document.nodes.len()).filter(|&i| matches!(document.nodes[i].data, Some(NodeData::Text(_)))).collect()
+    }
+
+    #[test]
+    fn test_compare_points_in_tree_order() {
+        let document = parse_html("<html><body><p>Hello <b>bold</b> world</p></body></html>");
+        let [hello, bold, world] = text_nodes(&document)[..] else { panic!("expected three text nodes") };
+        let b = document.nodes[bold].parent.unwrap();
+        let p = document.nodes[b].parent.unwrap();
+
+        assert_eq!(compare_points(&document, BoundaryPoint::new(hello, 3), BoundaryPoint::new(world, 0)), Ordering::Less);
+        // (p, 1) sits between "Hello " and <b>, so before the bold text
+        assert_eq!(compare_points(&document, BoundaryPoint::new(p, 1), BoundaryPoint::new(bold, 0)), Ordering::Less);
+        assert_eq!(compare_points(&document, BoundaryPoint::new(p, 2), BoundaryPoint::new(bold, 4)), Ordering::Greater);
+        assert_eq!(compare_points(&document, BoundaryPoint::new(bold, 2), BoundaryPoint::new(p, 1)), Ordering::Greater);
+        assert_eq!(common_ancestor(&document, hello, bold), p);
+    }
+
+    #[test]
+    fn test_range_text_spans_nodes() {
+        let document = parse_html("<html><body><p>Hello <b>bold</b> world</p></body></html>");
+        let [hello, bold, world] = text_nodes(&document)[..] else { panic!("expected three text nodes") };
+        let p = document.nodes[hello].parent.unwrap();
+
+        let range = Range::between(&document, BoundaryPoint::new(world, 3), BoundaryPoint::new(hello, 2));
+        assert_eq!(range.start, BoundaryPoint::new(hello, 2));
+        assert_eq!(range.text(&document), "llo bold wo");
+        assert_eq!(Range { start: BoundaryPoint::new(bold, 1), end: BoundaryPoint::new(bold, 3) }.text(&document), "ol");
+        assert_eq!(Range::node_contents(&document, p).text(&document), "Hello bold world");
+        assert!(Range { start: BoundaryPoint::new(p, 1), end: BoundaryPoint::new(p, 1) }.is_collapsed());
+    }
+}
@@ -0,0 +1,321 @@
+//! Selector Conformance
+//! A corpus of valid and invalid selectors, checked against the parser and
+//! matcher of `query`
+//!
+//! ```text
+//! | <html><body><p id="intro" class="lead"></p></body></html>
+//! match  p.lead        =>  intro
+//! error  p[class^=]    =>  9: an attribute value
+//! ```
+
+use std::fmt;
+
+use crate::dom::{Document, NodeData};
+use crate::parser::parse_html;
+use crate::query::{parse_selector, query_selector_all};
+
+/// The selector engine's own corpus
+pub const CORPUS: &str = r#"
+# Fixture: every case runs against this document
+| <html><body>
+| <nav id="top" class="menu main" lang="en-US">
+|   <ul class="links">
+|     <li id="l1" class="item first" data-kind="home page"><a id="a1" class="foo" href="/home">Home</a></li>
+|     <li id="l2" class="item"><a id="a2" href="https://example.com/docs.pdf" target="_blank">Docs</a></li>
+|   </ul>
+| </nav>
+| <main id="content" class="foo">
+|   <bar id="b1" attr="xyz"></bar>
+|   <div id="d1" class="foo"><bar id="b2" attr="abc"></bar><span id="s1"><bar id="b3" attr="x"></bar></span></div>
+|   <input id="i1" type="text" disabled/>
+|   <input id="i2" type="checkbox" name="agree"/>
+|   <i id="x.y"></i>
+| </main>
+| </body></html>
+
+# Type, universal, id and class selectors
+match  li                    =>  l1 l2
+match  LI                    =>  l1 l2
+match  *                     =>  html body top ul l1 a1 l2 a2 content b1 d1 b2 s1 b3 i1 i2 x.y
+match  #content              =>  content
+match  .foo                  =>  a1 content d1
+match  .item.first           =>  l1
+match  li.item#l2            =>  l2
+match  *.menu                =>  top
+match  .missing              =>
+match  #x\.y                 =>  x.y
+match  #x.y                  =>
+
+# Attribute selectors
+match  [disabled]            =>  i1
+match  [type=text]           =>  i1
+match  [type="checkbox"]     =>  i2
+match  [ type = 'text' ]     =>  i1
+match  [attr^=x]             =>  b1 b3
+match  [attr$=z]             =>  b1
+match  [attr*=b]             =>  b2
+match  [data-kind~=page]     =>  l1
+match  [data-kind~="home page"]  =>
+match  [lang|=en]            =>  top
+match  [lang|=en-US]         =>  top
+match  [lang|=e]             =>
+match  [href$=".pdf"]        =>  a2
+match  [attr^=""]            =>
+match  [attr="a\"bc"]        =>
+
+# Combinators and lists
+match  nav a                 =>  a1 a2
+match  ul > li               =>  l1 l2
+match  nav > li              =>
+match  nav>ul>li>a           =>  a1 a2
+match  .foo >bar[attr^=x]    =>  b1
+match  .foo bar[attr^=x]     =>  b1 b3
+match  main div > span bar   =>  b3
+match  body > * > *          =>  ul b1 d1 i1 i2 x.y
+match  #top a, input         =>  a1 a2 i1 i2
+match  input, #top a         =>  a1 a2 i1 i2
+match  a , a                 =>  a1 a2
+match    li  .foo            =>  a1
+
+# Sibling combinators
+match  li + li               =>  l2
+match  li ~ li               =>  l2
+match  bar + div             =>  d1
+match  bar ~ input           =>  i1 i2
+match  #b1 ~ *               =>  d1 i1 i2 x.y
+match  input + input         =>  i2
+match  bar+div>span          =>  s1
+match  #i1 + bar             =>
+match  #b2 + span bar        =>  b3
+
+# Relational :has()
+match  li:has(> a.foo)       =>  l1
+match  :has(> bar)           =>  content d1 s1
+match  main:has(span bar)    =>  content
+match  li:has(+ li)          =>  l1
+match  bar:has(~ input[disabled])  =>  b1
+match  div:has(bar, input)   =>  d1
+match  nav:has(> li)         =>
+match  *:has(#b3)            =>  html body content d1 s1
+match  bar:has(+ div) ~ i    =>  x.y
+match  :has(:has(#a1))       =>  html body top ul
+
+# Parse errors, at the character the parser stopped on
+error  ""                    =>  0: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  "   "                 =>  3: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  .foo >                =>  6: a selector after '>'
+error  .foo >bar[attr^=]     =>  16: an attribute value
+error  .foo >bar[attr^x]     =>  15: '=' to complete the operator
+error  .foo >bar[attr^=x     =>  17: ']'
+error  [attr="x]             =>  9: a closing "
+error  [attr x]              =>  6: ']' or an operator (=, ~=, |=, ^=, $=, *=)
+error  [=x]                  =>  1: an attribute name
+error  [attr=1x]             =>  6: an attribute value
+error  #                     =>  1: an identifier after '#'
+error  .1st                  =>  1: a class name after '.'
+error  div..foo              =>  4: a class name after '.'
+error  > li                  =>  0: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  ul > > li             =>  5: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  a,                    =>  2: a selector after ','
+error  a,,b                  =>  2: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  div*                  =>  3: a combinator, ',' or end of selector
+error  a:hover               =>  1: a combinator, ',' or end of selector
+error  ul :first-child       =>  3: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  a\                    =>  2: a character after '\'
+error  li +                  =>  4: a selector after '+'
+error  li ~, a               =>  4: a selector after '~'
+error  li:has()              =>  7: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  li:has(> a            =>  10: ')' to close ':has('
+error  li:has(> > a)         =>  9: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  :hover                =>  0: a tag, '*', '#id', '.class', '[attribute]' or ':has()'
+error  a:has(b):hover        =>  8: a combinator, ',' or end of selector
+"#;
+
+/// What a case requires of its selector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expectation {
+    /// Ids (or tag names) of the elements it matches, in document order
+    Matches(Vec<String>),
+    /// The parse error's position and expectation
+    Error { position: usize, expected: String },
+}
+
+/// One line of a corpus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceCase {
+    /// Line number in the corpus, from 1
+    pub line: usize,
+    pub selector: String,
+    pub expectation: Expectation,
+}
+
+/// A corpus: its fixture document's HTML and its cases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corpus {
+    pub fixture: String,
+    pub cases: Vec<ConformanceCase>,
+}
+
+impl Corpus {
+    /// Parse a corpus: `| html` fixture lines, `match` and `error` cases
+    /// and `#` comments
+    pub fn parse(text: &str) -> Result<Corpus, String> {
+        let mut fixture = String::new();
+        let mut cases = Vec::new();
+        for (i, raw) in text.lines().enumerate() {
+            let line = i + 1;
+            let trimmed = raw.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if let Some(html) = trimmed.strip_prefix('|') {
+                fixture.push_str(html.strip_prefix(' ').unwrap_or(html));
+                fixture.push('\n');
+                continue;
+            }
+            let (kind, rest) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+            let (selector, expected) = rest
+                .rsplit_once("=>")
+                .ok_or_else(|| format!("Line {}: expected '<selector> => <expectation>'", line))?;
+            let selector = unquote(selector.trim());
+            let expected = expected.trim();
+            let expectation = match kind {
+                "match" => Expectation::Matches(expected.split_whitespace().map(String::from).collect()),
+                "error" => {
+                    let (position, expected) =
+                        expected.split_once(':').ok_or_else(|| format!("Line {}: expected '<position>: <expectation>'", line))?;
+                    let position = position.trim().parse().map_err(|_| format!("Line {}: invalid position '{}'", line, position.trim()))?;
+                    Expectation::Error { position, expected: expected.trim().to_string() }
+                }
+                other => return Err(format!("Line {}: unknown case kind '{}': expected match or error", line, other)),
+            };
+            cases.push(ConformanceCase { line, selector, expectation });
+        }
+        Ok(Corpus { fixture, cases })
+    }
+
+    /// Check every case, collecting the ones that fail
+    pub fn run(&self) -> ConformanceReport {
+        let document = parse_html(&self.fixture);
+        let mut report = ConformanceReport::default();
+        for case in &self.cases {
+            let actual = match &case.expectation {
+                Expectation::Matches(_) => match query_selector_all(&document, &case.selector) {
+                    Ok(nodes) => Expectation::Matches(nodes.iter().map(|&idx| label(&document, idx)).collect()),
+                    Err(e) => Expectation::Error { position: e.position, expected: e.expected },
+                },
+                Expectation::Error { .. } => match parse_selector(&case.selector) {
+                    Ok(selector) => Expectation::Matches(vec![format!("{:?}", selector)]),
+                    Err(e) => Expectation::Error { position: e.position, expected: e.expected },
+                },
+            };
+            if actual == case.expectation {
+                report.passed += 1;
+            } else {
+                report.failures.push(CaseFailure { case: case.clone(), actual });
+            }
+        }
+        report
+    }
+}
+
+/// A selector in a case, with surrounding `"` removed so empty and
+/// whitespace-only selectors can be written
+fn unquote(selector: &str) -> String {
+    match selector.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) if !inner.contains('"') => inner.to_string(),
+        _ => selector.to_string(),
+    }
+}
+
+/// How a case failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseFailure {
+    pub case: ConformanceCase,
+    /// What the engine did instead; a selector that parsed where an error
+    /// was expected shows as its parsed form
+    pub actual: Expectation,
+}
+
+/// Cases passed and failed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    pub passed: usize,
+    pub failures: Vec<CaseFailure>,
+}
+
+impl ConformanceReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Selector conformance: {} passed, {} failed", self.passed, self.failures.len())?;
+        for failure in &self.failures {
+            writeln!(f, "  line {}: '{}'", failure.case.line, failure.case.selector)?;
+            writeln!(f, "    expected {}", describe(&failure.case.expectation))?;
+            writeln!(f, "    actual   {}", describe(&failure.actual))?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(expectation: &Expectation) -> String {
+    match expectation {
+        Expectation::Matches(labels) if labels.is_empty() => "no matches".to_string(),
+        Expectation::Matches(labels) => format!("matches {}", labels.join(" ")),
+        Expectation::Error { position, expected } => format!("error at {}: expected {}", position, expected),
+    }
+}
+
+/// An element's id, or its tag name when it has none
+fn label(document: &Document, idx: usize) -> String {
+    match &document.nodes[idx].data {
+        Some(NodeData::Element(elem)) => elem.attributes.get("id").cloned().unwrap_or_else(|| elem.tag_name.to_string()),
+        _ => format!("#{}", idx),
+    }
+}
+
+/// Parse and run `text`
+pub fn run_corpus(text: &str) -> Result<ConformanceReport, String> {
+    Corpus::parse(text).map(|corpus| corpus.run())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selector_engine_passes_its_corpus() {
+        let report = run_corpus(CORPUS).unwrap();
+        assert!(report.is_success(), "{}", report);
+        assert!(report.passed >= 60, "{}", report);
+    }
+
+    #[test]
+    fn test_harness_reports_each_failing_case() {
+        // Given: A corpus with one right and two wrong expectations
+        let corpus = "
+            | <p id=\"a\" class=\"x\"></p><p id=\"b\"></p>
+            match  p.x   =>  a
+            match  p     =>  a
+            error  p[    =>  5: ']'
+        ";
+
+        // When: It is run
+        let report = run_corpus(corpus).unwrap();
+
+        // Then: Both failures are reported with what happened instead
+        assert_eq!(report.passed, 1);
+        let text = report.to_string();
+        assert!(text.contains("line 4: 'p'\n    expected matches a\n    actual   matches a b"), "{}", text);
+        assert!(text.contains("line 5: 'p['\n    expected error at 5: expected ']'\n    actual   error at 2: expected an attribute name"), "{}", text);
+        assert!(run_corpus("find p => a").unwrap_err().starts_with("Line 1: unknown case kind 'find'"));
+    }
+}
@@ -0,0 +1,116 @@
+//! HTML Serialization
+//! Turns a document subtree back into markup, for DOM snapshots and debugging
+
+use crate::dom::{Document, NodeData};
+
+/// Elements that never have children or an end tag
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Serialize a node and its subtree, like `outerHTML`
+///
+/// For the document node this is the markup of all its children. Attributes
+/// are written in name order so snapshots of the same DOM are identical.
+pub fn to_html(document: &Document, idx: usize) -> String {
+    let mut out = String::new();
+    write_node(document, idx, &mut out);
+    out
+}
+
+/// Serialize only the children of a node, like `innerHTML`
+pub fn inner_html(document: &Document, idx: usize) -> String {
+    let mut out = String::new();
+    for &child in &document.nodes[idx].children {
+        write_node(document, child, &mut out);
+    }
+    out
+}
+
+fn write_node(document: &Document, idx: usize, out: &mut String) {
+    let node = &document.nodes[idx];
+    match &node.data {
+        Some(NodeData::Text(text)) if in_raw_text_element(document, idx) => out.push_str(text),
+        Some(NodeData::Text(text)) => out.push_str(&escape_text(text)),
+        Some(NodeData::Element(elem)) => {
+            out.push('<');
+            out.push_str(&elem.tag_name);
+            for (name, value) in &elem.attributes {
+                out.push_str(&format!(" {}=\"{}\"", name, escape_attribute(value)));
+            }
+            out.push('>');
+            if VOID_ELEMENTS.contains(&elem.tag_name.as_str()) {
+                return;
+            }
+            out.push_str(&inner_html(document, idx));
+            out.push_str(&format!("</{}>", elem.tag_name));
+        }
+        None => out.push_str(&inner_html(document, idx)),
+    }
+}
+
+/// Whether a text node is the content of a `<script>` or `<style>`, which
+/// is written as is: the parser does not decode references there
+fn in_raw_text_element(document: &Document, idx: usize) -> bool {
+    let parent = document.nodes[idx].parent.and_then(|parent| document.nodes[parent].data.as_ref());
+    matches!(parent, Some(NodeData::Element(elem)) if elem.tag_name == "script" || elem.tag_name == "style")
+}
+
+/// Escape text content: `&`, `<`, `>` and no-break spaces
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('\u{A0}', "&nbsp;")
+}
+
+/// Escape a double-quoted attribute value: `&`, `"` and no-break spaces
+pub fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('\u{A0}', "&nbsp;")
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    #[test]
+    fn test_round_trips_markup() {
+        let html = r#"<div class="card" id="c"><p>Hello <b>world</b></p><img src="a.png"></div>"#;
+        let doc = parse_html(html);
+
+        assert_eq!(to_html(&doc, doc.root), html);
+    }
+
+    #[test]
+    fn test_escapes_text_and_attributes() {
+        // Given: Text and an attribute containing markup characters
+        let mut doc = Document::new();
+        let p = doc.create_element("p");
+        doc.set_attribute(p, "title", "say \"hi\" & go");
+        let text = doc.create_text_node("1 < 2 & 3 > 2");
+        doc.append_child(p, text);
+        doc.append_child(doc.root, p);
+
+        // Then: Both are escaped
+        assert_eq!(
+            to_html(&doc, p),
+            r#"<p title="say &quot;hi&quot; &amp; go">1 &lt; 2 &amp; 3 &gt; 2</p>"#
+        );
+        assert_eq!(inner_html(&doc, p), "1 &lt; 2 &amp; 3 &gt; 2");
+    }
+
+    #[test]
+    fn test_decoded_references_round_trip() {
+        // Given: Markup written with character references
+        let html = r#"<p title="a&amp;b&quot;">Fish&nbsp;&amp; chips &lt;3</p><script>if (a < b && c) {}</script>"#;
+        let doc = parse_html(html);
+
+        // Then: The DOM holds the characters and serializing escapes them
+        // again, leaving script text alone
+        assert_eq!(doc.text_content(doc.nodes[doc.root].children[0]), "Fish\u{A0}& chips <3");
+        assert_eq!(to_html(&doc, doc.root), html);
+        assert_eq!(to_html(&parse_html(&to_html(&doc, doc.root)), doc.root), html);
+    }
+}
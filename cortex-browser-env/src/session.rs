@@ -0,0 +1,282 @@
+//! Session
+//! Successive pages in one tab: navigating, reloading and going back and
+//! forward through the history
+//!
+//! Every page load opens a fresh `Page` from the session's `PageBuilder`, so
+//! a page's scripts, listeners and timers never leak into the next one, as
+//! in a browser. Documents named by URL are fetched through the builder's
+//! network mode, and the URL becomes the page's base URL; HTML given
+//! directly keeps the builder's. The page being left receives `unload`, and
+//! the new one `DOMContentLoaded` and `load`; see `Page::load_html`.
+//!
+//! Nothing is cached between entries: going back reloads the document.
+
+use crate::browser::{Page, PageBuilder, PageLoad, BLANK_PAGE};
+use crate::encoding;
+use crate::error::BrowserError;
+use crate::navigation::NavigationRequest;
+use crate::network::{self, resolve_url, NetworkError};
+
+/// A document in the session history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEntry {
+    /// A document fetched from this absolute or base-relative URL
+    Url(String),
+    /// HTML given directly, with the base URL it loaded under
+    Html { html: String, base_url: Option<String> },
+}
+
+impl SessionEntry {
+    /// The URL the document loaded from or under, if any
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            SessionEntry::Url(url) => Some(url),
+            SessionEntry::Html { base_url, .. } => base_url.as_deref(),
+        }
+    }
+}
+
+/// A tab loading one page after another
+pub struct Session {
+    builder: PageBuilder,
+    page: Page,
+    history: Vec<SessionEntry>,
+    /// The history entry shown; `None` for the blank page the session
+    /// opened with
+    current: Option<usize>,
+}
+
+impl Session {
+    /// Open a session showing `BLANK_PAGE`
+    pub fn new(builder: PageBuilder) -> Result<Session, BrowserError> {
+        let page = builder.clone().build()?;
+        Ok(Session { builder, page, history: Vec::new(), current: None })
+    }
+
+    /// The page shown
+    pub fn page(&self) -> &Page {
+        &self.page
+    }
+
+    /// The URL of the page shown, if it has one
+    pub fn url(&self) -> Option<&str> {
+        self.current_entry().and_then(SessionEntry::url).or(self.builder.base_url.as_deref())
+    }
+
+    /// Documents visited, oldest first; entries after the current one were
+    /// gone back from
+    pub fn history(&self) -> &[SessionEntry] {
+        &self.history
+    }
+
+    pub fn current_entry(&self) -> Option<&SessionEntry> {
+        self.current.map(|index| &self.history[index])
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.current.is_some_and(|index| index > 0)
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.current.map_or(0, |index| index + 1) < self.history.len()
+    }
+
+    /// Navigate to `target`: HTML when it starts with `<`, otherwise a URL
+    /// resolved against the current page's
+    ///
+    /// Entries after the current one are dropped, as following a link after
+    /// going back does in a browser. A document that fails to load leaves
+    /// the session where it was.
+    pub fn navigate(&mut self, target: &str) -> Result<PageLoad, BrowserError> {
+        if target.trim_start().starts_with('<') {
+            return self.load_html(target);
+        }
+        let url = match self.url() {
+            Some(base) => resolve_url(base, target),
+            None => target.trim().to_string(),
+        };
+        self.push(SessionEntry::Url(url))
+    }
+
+    /// Navigate to a document given as HTML, under the builder's base URL
+    pub fn load_html(&mut self, html: &str) -> Result<PageLoad, BrowserError> {
+        self.push(SessionEntry::Html { html: html.to_string(), base_url: self.builder.base_url.clone() })
+    }
+
+    fn push(&mut self, entry: SessionEntry) -> Result<PageLoad, BrowserError> {
+        let load = self.open(&entry)?;
+        let index = self.current.map_or(0, |index| index + 1);
+        self.history.truncate(index);
+        self.history.push(entry);
+        self.current = Some(index);
+        Ok(load)
+    }
+
+    /// Follow a link the page's user clicked, unless it opens another
+    /// window, returning `None` then
+    pub fn follow(&mut self, request: &NavigationRequest) -> Result<Option<PageLoad>, BrowserError> {
+        if request.opens_new_window() {
+            return Ok(None);
+        }
+        self.navigate(&request.url).map(Some)
+    }
+
+    /// Load the current entry again in a fresh page
+    pub fn reload(&mut self) -> Result<PageLoad, BrowserError> {
+        match self.current_entry().cloned() {
+            Some(entry) => self.open(&entry),
+            None => self.open(&SessionEntry::Html { html: BLANK_PAGE.to_string(), base_url: self.builder.base_url.clone() }),
+        }
+    }
+
+    /// Load the previous entry, returning `None` at the start of the
+    /// history
+    pub fn go_back(&mut self) -> Result<Option<PageLoad>, BrowserError> {
+        if !self.can_go_back() {
+            return Ok(None);
+        }
+        self.traverse(self.current.unwrap_or(0) - 1).map(Some)
+    }
+
+    /// Load the next entry, returning `None` at the end of the history
+    pub fn go_forward(&mut self) -> Result<Option<PageLoad>, BrowserError> {
+        if !self.can_go_forward() {
+            return Ok(None);
+        }
+        self.traverse(self.current.map_or(0, |index| index + 1)).map(Some)
+    }
+
+    fn traverse(&mut self, index: usize) -> Result<PageLoad, BrowserError> {
+        let load = self.open(&self.history[index].clone())?;
+        self.current = Some(index);
+        Ok(load)
+    }
+
+    /// Fetch `entry`'s document, unload the current page and show the
+    /// document in a new one
+    fn open(&mut self, entry: &SessionEntry) -> Result<PageLoad, BrowserError> {
+        let mut builder = self.builder.clone();
+        builder.base_url = entry.url().map(String::from).or(builder.base_url);
+        let html = match entry {
+            SessionEntry::Url(url) => {
+                let bytes = network::fetch(&*self.builder.network.loader(), url).map_err(|e| load_error(url, e))?;
+                encoding::decode(&bytes, None).0
+            }
+            SessionEntry::Html { html, .. } => html.clone(),
+        };
+        let page = builder.build()?;
+        self.page.unload();
+        self.page = page;
+        Ok(self.page.load_html(&html))
+    }
+}
+
+fn load_error(url: &str, error: NetworkError) -> BrowserError {
+    match error {
+        NetworkError::NotFound(_) => BrowserError::NotFoundError(format!("Cannot load page '{}': {}", url, error)),
+        error => BrowserError::InvalidOperationError(format!("Cannot load page '{}': {}", url, error)),
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::NetworkMode;
+    use crate::network::MockNetwork;
+    use std::rc::Rc;
+
+    fn shop() -> Session {
+        let network = MockNetwork::new()
+            .with_response("https://shop.test/index.html", "<html><body><a href=\"cart.html\">Cart</a></body></html>")
+            .with_response(
+            "https://shop.test/cart.html",
+            r#"<html><body><p id="count">0</p><script>
+                globalThis.lifecycle = [document.readyState];
+                addEventListener(0, "DOMContentLoaded", () => lifecycle.push("DOMContentLoaded " + document.readyState));
+                addEventListener(0, "load", () => lifecycle.push("load " + document.readyState));
+            </script></body></html>"#,
+            );
+        let builder = PageBuilder::new().with_base_url("https://shop.test/").with_network(NetworkMode::Custom(Rc::new(network)));
+        Session::new(builder).unwrap()
+    }
+
+    #[test]
+    fn test_navigating_fetches_pages_and_fires_lifecycle_events() {
+        // Given: A session on a shop whose cart page logs its lifecycle
+        let mut session = shop();
+
+        // When: We open the index, then the cart relative to it
+        session.navigate("index.html").unwrap();
+        session.page().run_script("globalThis.leftover = true").unwrap();
+        session.navigate("cart.html").unwrap();
+
+        // Then: The cart loaded in a fresh context and saw each stage
+        assert_eq!(session.url(), Some("https://shop.test/cart.html"));
+        assert_eq!(session.page().base_url(), Some("https://shop.test/cart.html"));
+        assert_eq!(session.page().run_script("typeof leftover").unwrap(), "undefined");
+        assert_eq!(session.page().run_script("lifecycle.join()").unwrap(), "loading,DOMContentLoaded interactive,load complete");
+        assert_eq!(session.history().len(), 2);
+    }
+
+    #[test]
+    fn test_history_goes_back_forward_and_reloads() {
+        // Given: A session that visited the index, the cart and some HTML
+        let mut session = shop();
+        session.navigate("https://shop.test/index.html").unwrap();
+        session.navigate("cart.html").unwrap();
+        session.navigate("<p>Thanks</p>").unwrap();
+
+        // When: We go back twice, then forward
+        session.go_back().unwrap();
+        session.page().run_script("globalThis.stale = true").unwrap();
+        session.go_back().unwrap();
+        assert!(session.go_back().unwrap().is_none());
+        session.go_forward().unwrap();
+
+        // Then: Each entry loads again from scratch
+        assert_eq!(session.current_entry(), Some(&SessionEntry::Url("https://shop.test/cart.html".to_string())));
+        assert_eq!(session.page().run_script("typeof stale").unwrap(), "undefined");
+        assert!(session.can_go_back() && session.can_go_forward());
+
+        // When: We reload, then navigate from the middle of the history
+        session.page().run_script("lifecycle.push('edited')").unwrap();
+        session.reload().unwrap();
+        assert_eq!(session.page().run_script("lifecycle.length").unwrap(), "3");
+        session.navigate("index.html").unwrap();
+
+        // Then: The entries ahead are dropped
+        assert_eq!(session.history().len(), 3);
+        assert!(!session.can_go_forward());
+    }
+
+    #[test]
+    fn test_failed_loads_and_followed_links() {
+        // Given: A session on the index
+        let mut session = shop();
+        session.navigate("index.html").unwrap();
+        session.page().run_script("addEventListener(0, 'unload', () => { throw new Error('unload ran'); })").unwrap();
+
+        // When: A missing page is requested
+        let missing = session.navigate("gone.html").unwrap_err();
+
+        // Then: It fails and the index stays, not unloaded
+        assert!(matches!(missing, BrowserError::NotFoundError(_)), "{:?}", missing);
+        assert_eq!(session.url(), Some("https://shop.test/index.html"));
+        assert!(session.page().console_messages(crate::console::ConsoleLevel::Error).is_empty());
+
+        // When: The cart link is clicked and followed
+        let rect = session.page().bounding_client_rect(session.page().query("a").unwrap().unwrap()).unwrap();
+        session.page().click_at(rect.x + 2.0, rect.y + 2.0).unwrap();
+        let request = session.page().navigation_requests().remove(0);
+        session.follow(&request).unwrap();
+
+        // Then: The cart is shown
+        assert_eq!(session.url(), Some("https://shop.test/cart.html"));
+        let popup = NavigationRequest { target: Some("_blank".to_string()), ..request };
+        assert_eq!(session.follow(&popup).unwrap(), None);
+    }
+}
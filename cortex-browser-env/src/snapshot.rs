@@ -0,0 +1,119 @@
+//! DOM Snapshots
+//! A stable, pretty-printed form of a subtree for structural assertions,
+//! stored next to the golden master screenshots
+//!
+//! Each element is written on its own line, indented two spaces per level,
+//! with its attributes sorted by name. Text is whitespace-collapsed and
+//! trimmed, so reformatting markup or reordering attributes does not change
+//! a snapshot, and whitespace-only text is left out. Open shadow roots are
+//! written as a `#shadow-root` child of their host.
+
+use crate::dom::{Document, NodeData, ShadowRootMode};
+use crate::serialize::{escape_attribute, escape_text, VOID_ELEMENTS};
+
+/// The snapshot of `idx` and its subtree, ending with a newline
+pub fn dom_snapshot(document: &Document, idx: usize) -> String {
+    let mut out = String::new();
+    write_node(document, idx, 0, &mut out);
+    out
+}
+
+fn write_node(document: &Document, idx: usize, depth: usize, out: &mut String) {
+    let node = &document.nodes[idx];
+    let indent = "  ".repeat(depth);
+    match &node.data {
+        Some(NodeData::Text(text)) => {
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !text.is_empty() {
+                out.push_str(&format!("{}{}\n", indent, escape_text(&text)));
+            }
+        }
+        Some(NodeData::Element(elem)) => {
+            let attributes: String = elem.attributes.iter().map(|(name, value)| format!(" {}=\"{}\"", name, escape_attribute(value))).collect();
+            out.push_str(&format!("{}<{}{}>", indent, elem.tag_name, attributes));
+            if VOID_ELEMENTS.contains(&elem.tag_name.as_str()) {
+                out.push('\n');
+                return;
+            }
+            let shadow = document.shadow_root(idx).filter(|root| root.mode == ShadowRootMode::Open);
+            if node.children.is_empty() && shadow.is_none() {
+                out.push_str(&format!("</{}>\n", elem.tag_name));
+                return;
+            }
+            out.push('\n');
+            if let Some(root) = shadow {
+                out.push_str(&format!("{}  #shadow-root\n", indent));
+                for &child in &root.children {
+                    write_node(document, child, depth + 2, out);
+                }
+            }
+            for &child in &node.children {
+                write_node(document, child, depth + 1, out);
+            }
+            out.push_str(&format!("{}</{}>\n", indent, elem.tag_name));
+        }
+        None => {
+            for &child in &node.children {
+                write_node(document, child, depth, out);
+            }
+        }
+    }
+}
+
+/// The lines that differ between two snapshots, `-` for expected and `+`
+/// for actual, each with its line number
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    // Longest common subsequence lengths of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("-{:>4} {}\n", i + 1, old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{:>4} {}\n", j + 1, new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    #[test]
+    fn test_snapshot_is_canonical() {
+        // Given: The same card written two ways
+        let a = parse_html("<div class=\"card\" id=\"c\"><h2>  Hello\n   world </h2><br/><span></span></div>");
+        let b = parse_html("<div id=\"c\" class=\"card\"><h2>Hello world</h2><br/><span></span></div>");
+
+        // Then: Both snapshot the same, one element per line
+        let snapshot = dom_snapshot(&a, a.root);
+        assert_eq!(snapshot, dom_snapshot(&b, b.root));
+        assert_eq!(snapshot, "<div class=\"card\" id=\"c\">\n  <h2>\n    Hello world\n  </h2>\n  <br>\n  <span></span>\n</div>\n");
+    }
+
+    #[test]
+    fn test_line_diff_shows_changed_lines() {
+        let diff = line_diff("<ul>\n  <li>\n    One\n  </li>\n</ul>\n", "<ul>\n  <li>\n    Uno\n  </li>\n</ul>\n");
+        assert_eq!(diff, "-   3     One\n+   3     Uno\n");
+        assert_eq!(line_diff("same\n", "same\n"), "");
+    }
+}
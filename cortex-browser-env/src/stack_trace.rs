@@ -0,0 +1,363 @@
+//! JavaScript Stack Traces
+//! Parses QuickJS stack frames and maps them through source maps, so errors
+//! in bundled test files point at the original sources
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::BrowserError;
+use crate::json::Json;
+use crate::network::{decode_data_uri, is_data_uri};
+
+/// One `at function (file:line:column)` line of a stack trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    /// `None` for anonymous code
+    pub function: Option<String>,
+    pub file: String,
+    /// 1-based
+    pub line: u32,
+    /// 1-based, when the engine reports it
+    pub column: Option<u32>,
+}
+
+impl StackFrame {
+    /// Parse `at f (file.js:3)` or `at f (file.js:3:7)`; native frames and
+    /// other text give `None`
+    pub fn parse(line: &str) -> Option<StackFrame> {
+        let rest = line.trim().strip_prefix("at ")?;
+        let (function, location) = match rest.strip_suffix(')').and_then(|r| r.rsplit_once(" (")) {
+            Some((function, location)) => (Some(function), location),
+            None => (None, rest),
+        };
+
+        let (file, numbers) = split_location(location)?;
+        let function = function.filter(|f| !f.is_empty() && *f != "<anonymous>" && *f != "<eval>");
+        Some(StackFrame { function: function.map(str::to_string), file: file.to_string(), line: numbers.0, column: numbers.1 })
+    }
+}
+
+/// Split `file:line[:column]`, allowing colons in the file name
+fn split_location(location: &str) -> Option<(&str, (u32, Option<u32>))> {
+    let (head, last) = location.rsplit_once(':')?;
+    let last: u32 = last.parse().ok()?;
+    match head.rsplit_once(':').and_then(|(file, line)| Some((file, line.parse::<u32>().ok()?))) {
+        Some((file, line)) => Some((file, (line, Some(last)))),
+        None => Some((head, (last, None))),
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "    at {} ({}:{}", self.function.as_deref().unwrap_or("<anonymous>"), self.file, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The frames of a stack trace that name a location, innermost first
+pub fn parse_stack(stack: &str) -> Vec<StackFrame> {
+    stack.lines().filter_map(StackFrame::parse).collect()
+}
+
+// ============================================================================
+// SOURCE MAPS
+// ============================================================================
+
+/// A position in an original source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct OriginalPosition {
+    pub source: String,
+    /// 1-based
+    pub line: u32,
+    /// 1-based
+    pub column: u32,
+}
+
+/// One mapped span of a generated line, 0-based as in the mappings
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    generated_column: u32,
+    source: usize,
+    line: u32,
+    column: u32,
+}
+
+/// A version 3 source map
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    /// Segments of each generated line, by generated column
+    lines: Vec<Vec<Segment>>,
+}
+
+impl SourceMap {
+    /// Parse the JSON of a version 3 source map
+    ///
+    /// Only `sources`, `sourceRoot` and `mappings` are read; index maps
+    /// with `sections` are not supported.
+    pub fn parse(json: &str) -> Result<SourceMap, BrowserError> {
+        let invalid = |reason: &str| BrowserError::ParseError(format!("Invalid source map: {}", reason));
+        let map = Json::parse(json).ok_or_else(|| invalid("malformed JSON"))?;
+        if map.get("version").and_then(Json::as_number) != Some(3.0) {
+            return Err(invalid("expected version 3"));
+        }
+        let root = map.get("sourceRoot").and_then(Json::as_str).unwrap_or("");
+        let sources = match map.get("sources") {
+            Some(Json::Array(items)) => items
+                .iter()
+                .map(|item| item.as_str().map(|source| join_source_root(root, source)))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid("sources must be strings"))?,
+            _ => return Err(invalid("missing sources")),
+        };
+        let mappings = map.get("mappings").and_then(Json::as_str).ok_or_else(|| invalid("missing mappings"))?;
+        let lines = decode_mappings(mappings, sources.len()).ok_or_else(|| invalid("malformed mappings"))?;
+        Ok(SourceMap { sources, lines })
+    }
+
+    /// Where a generated position came from
+    ///
+    /// Without a column, the first mapped span of the line is used.
+    pub fn lookup(&self, line: u32, column: Option<u32>) -> Option<OriginalPosition> {
+        let segments = self.lines.get(line.checked_sub(1)? as usize)?;
+        let segment = match column {
+            Some(column) => segments.iter().rev().find(|s| s.generated_column < column)?,
+            None => segments.first()?,
+        };
+        Some(OriginalPosition {
+            source: self.sources[segment.source].clone(),
+            line: segment.line + 1,
+            column: segment.column + 1,
+        })
+    }
+}
+
+fn join_source_root(root: &str, source: &str) -> String {
+    if root.is_empty() || source.contains("://") || source.starts_with('/') {
+        source.to_string()
+    } else {
+        format!("{}/{}", root.trim_end_matches('/'), source)
+    }
+}
+
+/// Decode the base64 VLQ `mappings` of a source map
+///
+/// Fields after the generated column are relative to the previous segment
+/// across the whole map; the generated column restarts on each line.
+fn decode_mappings(mappings: &str, source_count: usize) -> Option<Vec<Vec<Segment>>> {
+    let (mut source, mut line, mut column) = (0i64, 0i64, 0i64);
+    let mut lines = Vec::new();
+    for text in mappings.split(';') {
+        let mut generated_column = 0i64;
+        let mut segments = Vec::new();
+        for field in text.split(',').filter(|field| !field.is_empty()) {
+            let values = decode_vlq(field)?;
+            generated_column += values[0];
+            // One-field segments map to nothing
+            if values.len() < 4 {
+                continue;
+            }
+            source += values[1];
+            line += values[2];
+            column += values[3];
+            if source < 0 || source as usize >= source_count || line < 0 || column < 0 || generated_column < 0 {
+                return None;
+            }
+            segments.push(Segment {
+                generated_column: generated_column as u32,
+                source: source as usize,
+                line: line as u32,
+                column: column as u32,
+            });
+        }
+        segments.sort_by_key(|s| s.generated_column);
+        lines.push(segments);
+    }
+    Some(lines)
+}
+
+/// Decode one segment's base64 VLQ numbers
+fn decode_vlq(field: &str) -> Option<Vec<i64>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut values = Vec::new();
+    let (mut value, mut shift) = (0i64, 0);
+    for byte in field.bytes() {
+        let digit = ALPHABET.iter().position(|&c| c == byte)? as i64;
+        value += (digit & 31) << shift;
+        if digit & 32 != 0 {
+            shift += 5;
+            if shift > 60 {
+                return None;
+            }
+            continue;
+        }
+        // The lowest bit is the sign
+        values.push(if value & 1 == 1 { -(value >> 1) } else { value >> 1 });
+        value = 0;
+        shift = 0;
+    }
+    (shift == 0 && !values.is_empty()).then_some(values)
+}
+
+/// The URL in a script's last `//# sourceMappingURL=` comment
+pub fn source_map_url(source: &str) -> Option<&str> {
+    source
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix("//# sourceMappingURL=").or_else(|| line.strip_prefix("//@ sourceMappingURL=")))
+        .map(str::trim)
+}
+
+/// The inline source map a script's `//# sourceMappingURL=data:...`
+/// comment carries
+pub fn inline_source_map(source: &str) -> Option<String> {
+    let url = source_map_url(source).filter(|url| is_data_uri(url))?;
+    let (_, body) = decode_data_uri(url).ok()?;
+    String::from_utf8(body).ok()
+}
+
+/// Source maps by the file name scripts were run as
+#[derive(Debug, Clone, Default)]
+pub struct SourceMaps {
+    maps: HashMap<String, SourceMap>,
+}
+
+impl SourceMaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, file: &str, map: SourceMap) {
+        self.maps.insert(file.to_string(), map);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    /// The frame at its original position, when its file has a map
+    /// covering it
+    pub fn map_frame(&self, frame: &StackFrame) -> Option<StackFrame> {
+        let original = self.maps.get(&frame.file)?.lookup(frame.line, frame.column)?;
+        Some(StackFrame {
+            function: frame.function.clone(),
+            file: original.source,
+            line: original.line,
+            column: Some(original.column),
+        })
+    }
+
+    /// Rewrite the mapped frames of a stack trace, keeping other lines
+    pub fn map_stack(&self, stack: &str) -> String {
+        let lines: Vec<String> = stack
+            .lines()
+            .map(|line| match StackFrame::parse(line).and_then(|frame| self.map_frame(&frame)) {
+                Some(frame) => frame.to_string(),
+                None => line.to_string(),
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Map the stack of a `JavaScriptError`; other errors pass through
+    pub fn map_error(&self, error: BrowserError) -> BrowserError {
+        match error {
+            BrowserError::JavaScriptError(message, Some(stack)) if !self.is_empty() => {
+                BrowserError::JavaScriptError(message, Some(self.map_stack(&stack)))
+            }
+            error => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stack_frames() {
+        let stack = "    at assertSaved (app.js:12)\n    at <anonymous> (tests.js:3:9)\n    at <eval> (native)";
+        let frames = parse_stack(stack);
+        assert_eq!(
+            frames,
+            vec![
+                StackFrame { function: Some("assertSaved".to_string()), file: "app.js".to_string(), line: 12, column: None },
+                StackFrame { function: None, file: "tests.js".to_string(), line: 3, column: Some(9) },
+            ]
+        );
+        assert_eq!(frames[1].to_string(), "    at <anonymous> (tests.js:3:9)");
+        assert_eq!(StackFrame::parse("at C:\\tests\\a.js:4").unwrap().file, "C:\\tests\\a.js");
+    }
+
+    #[test]
+    fn test_decode_vlq() {
+        assert_eq!(decode_vlq("AAAA"), Some(vec![0, 0, 0, 0]));
+        assert_eq!(decode_vlq("CADF"), Some(vec![1, 0, -1, -2]));
+        assert_eq!(decode_vlq("gB"), Some(vec![16]));
+        assert_eq!(decode_vlq("g"), None, "Unfinished continuation");
+        assert_eq!(decode_vlq("*"), None);
+    }
+
+    /// `bundle.js` line 1 is `src/a.ts` line 1; line 2 is `src/b.ts` line 5,
+    /// column 3 from generated column 4 on
+    const MAP: &str = r#"{
+        "version": 3,
+        "file": "bundle.js",
+        "sourceRoot": "src",
+        "sources": ["a.ts", "b.ts"],
+        "names": [],
+        "mappings": "AAAA;ACIA,IAAE"
+    }"#;
+
+    #[test]
+    fn test_source_map_lookup() {
+        let map = SourceMap::parse(MAP).unwrap();
+
+        let at = |source: &str, line, column| Some(OriginalPosition { source: source.to_string(), line, column });
+        assert_eq!(map.lookup(1, None), at("src/a.ts", 1, 1));
+        assert_eq!(map.lookup(2, None), at("src/b.ts", 5, 1));
+        assert_eq!(map.lookup(2, Some(6)), at("src/b.ts", 5, 3));
+        assert_eq!(map.lookup(3, None), None);
+        assert_eq!(map.lookup(0, None), None);
+    }
+
+    #[test]
+    fn test_source_map_rejects_bad_input() {
+        assert!(SourceMap::parse("{").is_err());
+        assert!(SourceMap::parse(r#"{"version": 2, "sources": [], "mappings": ""}"#).is_err());
+        assert!(SourceMap::parse(r#"{"version": 3, "sources": ["a.js"], "mappings": "AEAA"}"#).is_err());
+    }
+
+    #[test]
+    fn test_map_stack_rewrites_mapped_frames() {
+        let mut maps = SourceMaps::new();
+        maps.insert("bundle.js", SourceMap::parse(MAP).unwrap());
+
+        let stack = "    at save (bundle.js:2)\n    at other.js:7\n    at <eval> (native)";
+        assert_eq!(
+            maps.map_stack(stack),
+            "    at save (src/b.ts:5:1)\n    at other.js:7\n    at <eval> (native)"
+        );
+
+        let error = BrowserError::JavaScriptError("Error: x".to_string(), Some("    at bundle.js:1".to_string()));
+        assert_eq!(
+            maps.map_error(error),
+            BrowserError::JavaScriptError("Error: x".to_string(), Some("    at <anonymous> (src/a.ts:1:1)".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_inline_source_map() {
+        let script = format!(
+            "throw new Error('x');\n//# sourceMappingURL={}\n",
+            crate::network::encode_data_uri("application/json", MAP.as_bytes())
+        );
+        assert_eq!(inline_source_map(&script).as_deref(), Some(MAP));
+        assert_eq!(inline_source_map("//# sourceMappingURL=bundle.js.map"), None);
+        assert_eq!(source_map_url("f();\n//# sourceMappingURL=bundle.js.map\n"), Some("bundle.js.map"));
+    }
+}
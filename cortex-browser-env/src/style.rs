@@ -1,6 +1,15 @@
-use crate::css::{CSSValue, ComputedStyle, StyleSheet, Rule};
-use crate::dom::{Document, Node, NodeData, NodeType};
+use crate::css::{
+    parse_background_image, parse_border_radius, parse_box_shadow, parse_display, parse_edges, parse_font_size, parse_font_weight,
+    parse_list_style, parse_spacing, parse_text_decoration, CSSValue, ComputedStyle, ListStylePosition, ListStyleType, Overflow, OverflowWrap, PointerEvents, StyleSheet, TextOverflow, TextTransform, Visibility,
+    WhiteSpace, WordBreak,
+};
 use std::collections::HashMap;
+use crate::dom::{Document, Node, NodeType};
+use crate::parallel;
+use crate::rule_map::RuleMap;
+use crate::transform;
+use crate::user_agent;
+use rayon::prelude::*;
 
 #[derive(Debug, PartialEq)]
 pub struct StyledNode<'a> {
@@ -9,67 +18,223 @@ pub struct StyledNode<'a> {
     pub children: Vec<StyledNode<'a>>,
 }
 
-// Returns true if a node matches a simple selector.
-fn matches(node: &Node, selector: &str) -> bool {
-    if let Some(NodeData::Element(element_data)) = &node.data {
-        // Check for tag name match
-        if element_data.tag_name == selector {
-            return true;
-        }
-        // Check for class match
-        if let Some(class_attr) = element_data.attributes.get("class") {
-            if class_attr.split_whitespace().any(|c| format!(".{}", c) == selector) {
-                return true;
-            }
-        }
-        // Check for ID match
-        if let Some(id_attr) = element_data.attributes.get("id") {
-            if format!("#{}", id_attr) == selector {
-                return true;
-            }
+/// The page's rules, with the user agent's under them
+struct Cascade<'a> {
+    user_agent: RuleMap<'static>,
+    author: RuleMap<'a>,
+}
+
+impl<'a> Cascade<'a> {
+    fn new(document: &Document, stylesheet: &'a StyleSheet) -> Self {
+        Cascade {
+            user_agent: RuleMap::in_source_order(document, user_agent::stylesheet()),
+            author: RuleMap::new(document, stylesheet),
         }
     }
-    false
 }
 
-// Apply styles to a single node.
-fn specified_values(node: &Node, stylesheet: &StyleSheet) -> ComputedStyle {
+// Apply the user agent's styles to a single node, then the page's, then
+// the values animations give it.
+fn specified_values(document: &Document, idx: usize, cascade: &Cascade) -> ComputedStyle {
     let mut style = ComputedStyle::default();
-    let mut matched_rules = Vec::new();
 
-    for rule in &stylesheet.rules {
-        for selector in &rule.selectors {
-            if matches(node, selector) {
-                matched_rules.push(rule);
-                break; // Move to next rule once one selector matches
+    let rules = cascade.user_agent.matching(document, idx).into_iter().chain(cascade.author.matching(document, idx));
+    for rule in rules {
+        // In source order, so a shorthand resets the longhands before it
+        for (property, value) in &rule.declarations {
+            apply_declaration(&mut style, property, value);
+        }
+    }
+
+    // Running transitions and animations override the cascade
+    let mut animated: Vec<_> = document.animated_style(idx).into_iter().flatten().collect();
+    animated.sort();
+    for (property, value) in animated {
+        apply_declaration(&mut style, property, value);
+    }
+
+    style
+}
+
+fn apply_declaration(style: &mut ComputedStyle, property: &str, value: &str) {
+    match property {
+        "color" => style.color = Some(value.to_string()),
+        "background-color" => style.background_color = Some(value.trim().to_string()),
+        "border-radius" => {
+            if let Some([tl, tr, br, bl]) = parse_border_radius(value) {
+                style.border_top_left_radius = Some(tl);
+                style.border_top_right_radius = Some(tr);
+                style.border_bottom_right_radius = Some(br);
+                style.border_bottom_left_radius = Some(bl);
+            }
+        }
+        "border-top-left-radius" => style.border_top_left_radius = CSSValue::parse(value),
+        "border-top-right-radius" => style.border_top_right_radius = CSSValue::parse(value),
+        "border-bottom-right-radius" => style.border_bottom_right_radius = CSSValue::parse(value),
+        "border-bottom-left-radius" => style.border_bottom_left_radius = CSSValue::parse(value),
+        "box-shadow" => {
+            if let Some(shadows) = parse_box_shadow(value) {
+                style.box_shadows = shadows;
             }
         }
+        "text-decoration" => style.text_decoration = parse_text_decoration(value),
+        "text-decoration-line" => {
+            let lines = parse_text_decoration(value);
+            style.text_decoration.underline = lines.underline;
+            style.text_decoration.overline = lines.overline;
+            style.text_decoration.line_through = lines.line_through;
+        }
+        "text-decoration-color" => style.text_decoration.color = Some(value.trim().to_string()),
+        "background-image" => style.background_image = parse_background_image(value),
+        "text-transform" => style.text_transform = TextTransform::parse(value),
+        "letter-spacing" => style.letter_spacing = parse_spacing(value),
+        "word-spacing" => style.word_spacing = parse_spacing(value),
+        "white-space" => style.white_space = WhiteSpace::parse(value),
+        "overflow-wrap" | "word-wrap" => style.overflow_wrap = OverflowWrap::parse(value),
+        "word-break" => style.word_break = WordBreak::parse(value),
+        "overflow" => style.overflow = Overflow::parse(value),
+        "text-overflow" => style.text_overflow = TextOverflow::parse(value),
+        "list-style" => {
+            // Like any shorthand, omitted parts reset to their initial values
+            let (style_type, position) = parse_list_style(value);
+            style.list_style_type = Some(style_type.unwrap_or(ListStyleType::Disc));
+            style.list_style_position = Some(position.unwrap_or_default());
+        }
+        "list-style-type" => style.list_style_type = ListStyleType::parse(value),
+        "list-style-position" => style.list_style_position = ListStylePosition::parse(value),
+        "transform" => {
+            if let Some(functions) = transform::parse_transform(value) {
+                style.transform = functions;
+            }
+        }
+        "transform-origin" => style.transform_origin = transform::parse_transform_origin(value),
+        "z-index" => style.z_index = value.trim().parse().ok(),
+        "display" => {
+            if let Some(display) = parse_display(value) {
+                style.display = display;
+            }
+        }
+        "font-size" => style.font_size = parse_font_size(value),
+        "font-weight" => style.font_weight = parse_font_weight(value),
+        "font-family" => style.font_family = Some(value.trim().to_string()).filter(|family| !family.is_empty()),
+        "margin" => {
+            if let Some([top, right, bottom, left]) = parse_edges(value) {
+                (style.margin_top, style.margin_right, style.margin_bottom, style.margin_left) = (Some(top), Some(right), Some(bottom), Some(left));
+            }
+        }
+        "margin-top" => style.margin_top = CSSValue::parse(value),
+        "margin-right" => style.margin_right = CSSValue::parse(value),
+        "margin-bottom" => style.margin_bottom = CSSValue::parse(value),
+        "margin-left" => style.margin_left = CSSValue::parse(value),
+        "padding" => {
+            if let Some([top, right, bottom, left]) = parse_edges(value) {
+                (style.padding_top, style.padding_right, style.padding_bottom, style.padding_left) = (Some(top), Some(right), Some(bottom), Some(left));
+            }
+        }
+        "padding-top" => style.padding_top = CSSValue::parse(value),
+        "padding-right" => style.padding_right = CSSValue::parse(value),
+        "padding-bottom" => style.padding_bottom = CSSValue::parse(value),
+        "padding-left" => style.padding_left = CSSValue::parse(value),
+        "pointer-events" => style.pointer_events = PointerEvents::parse(value),
+        "visibility" => style.visibility = Visibility::parse(value),
+        // Add other property handlers here...
+        _ => ()
     }
+}
 
-    // Simple specificity: last rule wins.
-    matched_rules.sort_by_key(|r| r.selectors.join(",")); // Not a real specificity sort, but stable
-    for rule in matched_rules {
+/// The value each property of a node is declared with by the page's
+/// rules, after the cascade and before transitions and animations
+pub fn declared_values(document: &Document, idx: usize, rules: &RuleMap) -> HashMap<String, String> {
+    let mut declared = HashMap::new();
+    for rule in rules.matching(document, idx) {
         for (property, value) in &rule.declarations {
-            match property.as_str() {
-                "color" => style.color = Some(value.clone()),
-                // Add other property handlers here...
-                _ => ()
-            }
+            declared.insert(property.clone(), value.clone());
         }
     }
+    declared
+}
 
-    style
+/// Compute the specified style of every node, indexed by node index
+///
+/// The result lines up with `document.nodes`, which is the shape the layout
+/// and render passes expect for their `styles` argument. Large documents
+/// are styled across the `parallel` pool; each node's style depends only
+/// on the node, so the result is the same either way. `em` and `rem`
+/// lengths come out in pixels, relative to the sheet's initial font size.
+pub fn compute_styles(document: &Document, stylesheet: &StyleSheet) -> Vec<ComputedStyle> {
+    compute_styles_with_threads(document, stylesheet, 0)
+}
+
+/// `compute_styles` splitting large documents over `threads` threads, 0
+/// for one per core; see `parallel`
+pub fn compute_styles_with_threads(document: &Document, stylesheet: &StyleSheet, threads: usize) -> Vec<ComputedStyle> {
+    tracing::debug!(nodes = document.nodes.len(), rules = stylesheet.rules.len(), "Computing styles");
+    let cascade = Cascade::new(document, stylesheet);
+    let style = |idx: usize| specified_values(document, idx, &cascade);
+    let mut styles: Vec<ComputedStyle> = if parallel::worth_splitting(document.nodes.len(), threads) {
+        parallel::install(threads, || (0..document.nodes.len()).into_par_iter().map(style).collect())
+    } else {
+        (0..document.nodes.len()).map(style).collect()
+    };
+    resolve_font_relative_lengths(document, &mut styles, stylesheet.media.font_size);
+    styles
 }
 
+/// Turn `em` and `rem` into pixels, walking down from the document so
+/// each node knows its parent's font size
+///
+/// The document node gets `initial_font_size` unless something set one,
+/// so the root element inherits it; `rem` is the root element's size.
+fn resolve_font_relative_lengths(document: &Document, styles: &mut [ComputedStyle], initial_font_size: f32) {
+    let root = document.root;
+    if root >= styles.len() {
+        return;
+    }
+    let font_size = |value: Option<&CSSValue>, parent_font_size: f32, root_font_size: f32| match value {
+        None | Some(CSSValue::Inherit | CSSValue::Auto) => parent_font_size,
+        Some(value) => value.resolve_font_relative(parent_font_size, root_font_size).as_pixels(parent_font_size),
+    };
+    styles[root].font_size.get_or_insert(CSSValue::Pixels(initial_font_size));
+    let root_font_size = document.nodes[root]
+        .children
+        .iter()
+        .find(|&&child| document.nodes[child].node_type == NodeType::Element)
+        .map_or(initial_font_size, |&element| font_size(styles[element].font_size.as_ref(), initial_font_size, initial_font_size));
+
+    let mut stack = vec![(root, initial_font_size)];
+    while let Some((idx, parent_font_size)) = stack.pop() {
+        let style = &mut styles[idx];
+        let own_font_size = font_size(style.font_size.as_ref(), parent_font_size, root_font_size);
+        style.font_size = style
+            .font_size
+            .take()
+            .filter(|value| !matches!(value, CSSValue::Inherit | CSSValue::Auto))
+            .map(|value| value.resolve_font_relative(parent_font_size, root_font_size));
+        style.resolve_font_relative_lengths(own_font_size, root_font_size);
+        stack.extend(document.nodes[idx].children.iter().map(|&child| (child, own_font_size)));
+    }
+}
+
+/// The `visibility` of `node`: the nearest one set on it or an ancestor,
+/// so a visible child of a hidden element still shows
+pub fn inherited_visibility(document: &Document, styles: &[ComputedStyle], node: usize) -> Visibility {
+    std::iter::successors(Some(node), |&idx| document.nodes[idx].parent)
+        .find_map(|idx| styles.get(idx)?.visibility)
+        .unwrap_or_default()
+}
 
 pub fn style_tree<'a>(
     document: &'a Document,
     node_idx: usize,
     stylesheet: &'a StyleSheet,
 ) -> StyledNode<'a> {
+    style_subtree(document, node_idx, &Cascade::new(document, stylesheet))
+}
+
+fn style_subtree<'a>(document: &'a Document, node_idx: usize, cascade: &Cascade) -> StyledNode<'a> {
     let node = document.get_node(node_idx).unwrap();
-    let specified = specified_values(node, stylesheet);
-    let children = node.children.iter().map(|child_idx| style_tree(document, *child_idx, stylesheet)).collect();
+    let specified = specified_values(document, node_idx, cascade);
+    let children = node.children.iter().map(|&child_idx| style_subtree(document, child_idx, cascade)).collect();
 
     StyledNode {
         node,
@@ -83,6 +248,7 @@ mod tests {
     use super::*;
     use crate::parser::{parse_html};
     use crate::css::{parse_css};
+    use crate::dom::NodeData;
 
     #[test]
     fn test_style_simple_tree() {
@@ -105,4 +271,83 @@ mod tests {
 
         assert_eq!(p_node_styled.specified_values.color, Some("red".to_string()));
     }
+
+    #[test]
+    fn test_border_radius_shorthand_and_longhand() {
+        let document = parse_html("<html><body><button class=\"btn\">Go</button></body></html>");
+        let stylesheet = parse_css(".btn { border-radius: 4px 8px; } .btn { border-bottom-left-radius: 50%; }");
+
+        let styles = compute_styles(&document, &stylesheet);
+        let button = crate::query::query_selector(&document, ".btn").unwrap().unwrap();
+        let style = &styles[button];
+
+        assert_eq!(styles.len(), document.nodes.len());
+        assert_eq!(style.border_top_left_radius, Some(CSSValue::Pixels(4.0)));
+        assert_eq!(style.border_top_right_radius, Some(CSSValue::Pixels(8.0)));
+        assert_eq!(style.border_bottom_right_radius, Some(CSSValue::Pixels(4.0)));
+        assert_eq!(style.border_bottom_left_radius, Some(CSSValue::Percentage(50.0)));
+    }
+
+    #[test]
+    fn test_declarations_apply_in_source_order() {
+        let document = parse_html("<html><body><p class=\"a\">A</p><p class=\"b\">B</p></body></html>");
+        let stylesheet = parse_css(".a { margin-top: 10px; margin: 0; padding-left: 7px; padding: 1px; } .b { margin: 0; margin-top: 10px; color: red; color: blue; }");
+
+        let styles = compute_styles(&document, &stylesheet);
+        let (a, b) = (crate::query::query_selector(&document, ".a").unwrap().unwrap(), crate::query::query_selector(&document, ".b").unwrap().unwrap());
+
+        assert_eq!(styles[a].margin_top, Some(CSSValue::Pixels(0.0)));
+        assert_eq!(styles[a].padding_left, Some(CSSValue::Pixels(1.0)));
+        assert_eq!(styles[b].margin_top, Some(CSSValue::Pixels(10.0)));
+        assert_eq!(styles[b].color, Some("blue".to_string()));
+    }
+
+    #[test]
+    fn test_text_spacing_and_transform_properties() {
+        let document = parse_html("<html><body><button class=\"cta\">Go</button></body></html>");
+        let stylesheet = parse_css(".cta { text-transform: uppercase; } .cta { letter-spacing: 0.5px; } .cta { word-spacing: normal; }");
+
+        let styles = compute_styles(&document, &stylesheet);
+        let button = crate::query::query_selector(&document, ".cta").unwrap().unwrap();
+        let style = &styles[button];
+
+        assert_eq!(style.text_transform, Some(TextTransform::Uppercase));
+        assert_eq!(style.letter_spacing, Some(CSSValue::Pixels(0.5)));
+        assert_eq!(style.word_spacing, Some(CSSValue::Pixels(0.0)));
+    }
+
+    #[test]
+    fn test_text_decoration_longhands() {
+        let document = parse_html("<html><body><a class=\"price\">$10</a></body></html>");
+        let stylesheet = parse_css(".price { text-decoration-color: gray; } .price { text-decoration-line: line-through; }");
+
+        let styles = compute_styles(&document, &stylesheet);
+        let link = crate::query::query_selector(&document, ".price").unwrap().unwrap();
+        let decoration = &styles[link].text_decoration;
+
+        assert!(decoration.line_through && !decoration.underline);
+        assert_eq!(decoration.color, Some("gray".to_string()));
+    }
+
+    #[test]
+    fn test_em_and_rem_resolve_against_font_sizes() {
+        // Given: A page whose initial font size is 20px, with a root that
+        // doubles it and nested em sizes
+        let document = parse_html(r#"<html><body><section><p>Text</p></section></body></html>"#);
+        let mut stylesheet = parse_css("html { font-size: 2em; } section { font-size: 0.5em; padding: 1em; } p { margin: 1rem 2em; }");
+        stylesheet.media.font_size = 20.0;
+
+        // When: Styles are computed
+        let styles = compute_styles(&document, &stylesheet);
+        let style = |selector: &str| &styles[crate::query::query_selector(&document, selector).unwrap().unwrap()];
+
+        // Then: Font sizes use the parent's, other lengths the element's
+        // own, and rem the root element's
+        assert_eq!(style("html").font_size, Some(CSSValue::Pixels(40.0)));
+        assert_eq!(style("section").font_size, Some(CSSValue::Pixels(20.0)));
+        assert_eq!(style("section").padding_left, Some(CSSValue::Pixels(20.0)));
+        assert_eq!(style("p").margin_top, Some(CSSValue::Pixels(40.0)));
+        assert_eq!(style("p").margin_left, Some(CSSValue::Pixels(40.0)));
+        assert_eq!(styles[document.root].font_size, Some(CSSValue::Pixels(20.0)));
+    }
 }
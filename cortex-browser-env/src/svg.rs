@@ -0,0 +1,496 @@
+//! Inline SVG Rendering
+//! Paints the basic shapes of an inline `<svg>` element (rect, circle,
+//! ellipse, line, polyline, polygon and path) into its layout box
+
+use raqote::PathBuilder;
+
+use crate::display_list::{DisplayList, PaintCommand};
+use crate::dom::{Document, ElementData, Layout, NodeData};
+use crate::geometry::Rect;
+use crate::render::parse_color_to_argb;
+
+/// Maps user-space coordinates into device pixels
+///
+/// Implements the default `preserveAspectRatio="xMidYMid meet"`: the viewBox
+/// is scaled uniformly to fit the viewport and centered in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportTransform {
+    pub scale: f32,
+    pub translate_x: f32,
+    pub translate_y: f32,
+}
+
+impl ViewportTransform {
+    /// Build the transform mapping the viewBox onto a viewport rectangle
+    pub fn new(view_box: Option<[f32; 4]>, viewport: Rect) -> Self {
+        let Rect { x, y, width, height } = viewport;
+        match view_box {
+            Some([min_x, min_y, vb_width, vb_height]) if vb_width > 0.0 && vb_height > 0.0 => {
+                let scale = (width / vb_width).min(height / vb_height);
+                ViewportTransform {
+                    scale,
+                    translate_x: x + (width - vb_width * scale) / 2.0 - min_x * scale,
+                    translate_y: y + (height - vb_height * scale) / 2.0 - min_y * scale,
+                }
+            }
+            _ => ViewportTransform { scale: 1.0, translate_x: x, translate_y: y },
+        }
+    }
+
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale + self.translate_x, y * self.scale + self.translate_y)
+    }
+}
+
+/// Paint attributes inherited down the SVG tree
+#[derive(Debug, Clone, PartialEq)]
+struct Paint {
+    fill: Option<String>,
+    stroke: Option<String>,
+    stroke_width: f32,
+}
+
+impl Default for Paint {
+    fn default() -> Self {
+        Paint { fill: Some("black".to_string()), stroke: None, stroke_width: 1.0 }
+    }
+}
+
+impl Paint {
+    /// Apply an element's presentation attributes on top of inherited paint
+    fn inherit(&self, elem: &ElementData) -> Paint {
+        let paint_attr = |name: &str, inherited: &Option<String>| match elem.attributes.get(name) {
+            Some(value) if value.trim().eq_ignore_ascii_case("none") => None,
+            Some(value) => Some(value.trim().to_string()),
+            None => inherited.clone(),
+        };
+        Paint {
+            fill: paint_attr("fill", &self.fill),
+            stroke: paint_attr("stroke", &self.stroke),
+            stroke_width: number_attr(elem, "stroke-width").unwrap_or(self.stroke_width),
+        }
+    }
+}
+
+/// Parse a `viewBox` attribute into `[min-x, min-y, width, height]`
+pub fn parse_view_box(value: &str) -> Option<[f32; 4]> {
+    let numbers = parse_numbers(value)?;
+    match numbers.as_slice() {
+        [min_x, min_y, width, height] => Some([*min_x, *min_y, *width, *height]),
+        _ => None,
+    }
+}
+
+/// Record an `<svg>` element's shapes, clipped to its content box
+pub fn paint_svg(list: &mut DisplayList, document: &Document, svg_idx: usize, layout: &Layout) {
+    let Some(NodeData::Element(elem)) = &document.nodes[svg_idx].data else {
+        return;
+    };
+
+    let viewport = layout.content_box();
+    if viewport.is_empty() {
+        return;
+    }
+
+    // Attribute names are interned lowercase, so `viewBox` is `viewbox`
+    let view_box = elem.attributes.get("viewbox").and_then(|v| parse_view_box(v));
+    let transform = ViewportTransform::new(view_box, viewport);
+    let paint = Paint::default().inherit(elem);
+
+    // Shapes never paint outside the viewport
+    list.push(PaintCommand::PushClip { rect: viewport, radii: [0.0; 4] });
+    paint_children(list, document, svg_idx, &transform, &paint);
+    list.push(PaintCommand::PopClip);
+}
+
+fn paint_children(
+    list: &mut DisplayList,
+    document: &Document,
+    parent_idx: usize,
+    transform: &ViewportTransform,
+    paint: &Paint,
+) {
+    for &child_idx in &document.nodes[parent_idx].children {
+        let Some(NodeData::Element(elem)) = &document.nodes[child_idx].data else {
+            continue;
+        };
+        let paint = paint.inherit(elem);
+        if elem.tag_name == "g" {
+            paint_children(list, document, child_idx, transform, &paint);
+        } else if let Some(path) = shape_path(elem, transform) {
+            paint_shape(list, path, &paint, transform.scale);
+        }
+    }
+}
+
+/// Fill then stroke a shape, as SVG paints them
+///
+/// Open shapes are filled as if closed; a `<line>` encloses no area so only
+/// its stroke shows.
+fn paint_shape(list: &mut DisplayList, path: raqote::Path, paint: &Paint, scale: f32) {
+    if let Some(fill) = &paint.fill {
+        list.push(PaintCommand::FillPath { path: path.clone(), color: parse_color_to_argb(fill) });
+    }
+    if let Some(stroke) = &paint.stroke {
+        let width = paint.stroke_width * scale;
+        if width > 0.0 {
+            list.push(PaintCommand::StrokePath { path, width, color: parse_color_to_argb(stroke) });
+        }
+    }
+}
+
+/// Build the device-space outline of a basic shape element
+fn shape_path(elem: &ElementData, t: &ViewportTransform) -> Option<raqote::Path> {
+    let attr = |name: &str| number_attr(elem, name).unwrap_or(0.0);
+    let mut pb = PathBuilder::new();
+
+    match elem.tag_name.as_str() {
+        "rect" => {
+            let (x, y) = t.apply(attr("x"), attr("y"));
+            let (width, height) = (attr("width") * t.scale, attr("height") * t.scale);
+            if width <= 0.0 || height <= 0.0 {
+                return None;
+            }
+            pb.rect(x, y, width, height);
+        }
+        "circle" => {
+            let (cx, cy) = t.apply(attr("cx"), attr("cy"));
+            let r = attr("r") * t.scale;
+            if r <= 0.0 {
+                return None;
+            }
+            ellipse_path(&mut pb, cx, cy, r, r);
+        }
+        "ellipse" => {
+            let (cx, cy) = t.apply(attr("cx"), attr("cy"));
+            let (rx, ry) = (attr("rx") * t.scale, attr("ry") * t.scale);
+            if rx <= 0.0 || ry <= 0.0 {
+                return None;
+            }
+            ellipse_path(&mut pb, cx, cy, rx, ry);
+        }
+        "line" => {
+            let (x1, y1) = t.apply(attr("x1"), attr("y1"));
+            let (x2, y2) = t.apply(attr("x2"), attr("y2"));
+            pb.move_to(x1, y1);
+            pb.line_to(x2, y2);
+        }
+        "polyline" | "polygon" => {
+            let numbers = parse_numbers(elem.attributes.get("points")?)?;
+            let mut points = numbers.chunks_exact(2).map(|p| t.apply(p[0], p[1]));
+            let (x, y) = points.next()?;
+            pb.move_to(x, y);
+            for (x, y) in points {
+                pb.line_to(x, y);
+            }
+            if elem.tag_name == "polygon" {
+                pb.close();
+            }
+        }
+        "path" => build_path_data(&mut pb, elem.attributes.get("d")?, t)?,
+        _ => return None,
+    }
+
+    Some(pb.finish())
+}
+
+/// Approximate an ellipse with four cubic Bézier arcs
+fn ellipse_path(pb: &mut PathBuilder, cx: f32, cy: f32, rx: f32, ry: f32) {
+    const KAPPA: f32 = 0.552_284_8;
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    pb.move_to(cx + rx, cy);
+    pb.cubic_to(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry);
+    pb.cubic_to(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy);
+    pb.cubic_to(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry);
+    pb.cubic_to(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy);
+    pb.close();
+}
+
+/// Translate path data (`M`, `L`, `H`, `V`, `C`, `Z`, absolute and relative)
+/// into device-space path commands
+///
+/// Returns `None` for unsupported commands or malformed data, in which case
+/// the path is not painted at all.
+fn build_path_data(pb: &mut PathBuilder, d: &str, t: &ViewportTransform) -> Option<()> {
+    let tokens = tokenize_path_data(d)?;
+    let mut i = 0;
+    let mut command = None;
+    let (mut x, mut y) = (0.0f32, 0.0f32);
+    let (mut start_x, mut start_y) = (0.0f32, 0.0f32);
+
+    let number = |i: &mut usize| -> Option<f32> {
+        match tokens.get(*i) {
+            Some(PathToken::Number(n)) => {
+                *i += 1;
+                Some(*n)
+            }
+            _ => None,
+        }
+    };
+
+    while i < tokens.len() {
+        if let PathToken::Command(c) = tokens[i] {
+            command = Some(c);
+            i += 1;
+            if c.eq_ignore_ascii_case(&'z') {
+                pb.close();
+                x = start_x;
+                y = start_y;
+                continue;
+            }
+        }
+
+        let c = command?;
+        let relative = c.is_ascii_lowercase();
+        let (ox, oy) = if relative { (x, y) } else { (0.0, 0.0) };
+        match c.to_ascii_uppercase() {
+            'M' => {
+                x = ox + number(&mut i)?;
+                y = oy + number(&mut i)?;
+                let (dx, dy) = t.apply(x, y);
+                pb.move_to(dx, dy);
+                start_x = x;
+                start_y = y;
+                // Extra coordinate pairs after a moveto are implicit linetos
+                command = Some(if relative { 'l' } else { 'L' });
+            }
+            'L' => {
+                x = ox + number(&mut i)?;
+                y = oy + number(&mut i)?;
+                let (dx, dy) = t.apply(x, y);
+                pb.line_to(dx, dy);
+            }
+            'H' => {
+                x = ox + number(&mut i)?;
+                let (dx, dy) = t.apply(x, y);
+                pb.line_to(dx, dy);
+            }
+            'V' => {
+                y = oy + number(&mut i)?;
+                let (dx, dy) = t.apply(x, y);
+                pb.line_to(dx, dy);
+            }
+            'C' => {
+                let (x1, y1) = t.apply(ox + number(&mut i)?, oy + number(&mut i)?);
+                let (x2, y2) = t.apply(ox + number(&mut i)?, oy + number(&mut i)?);
+                x = ox + number(&mut i)?;
+                y = oy + number(&mut i)?;
+                let (dx, dy) = t.apply(x, y);
+                pb.cubic_to(x1, y1, x2, y2, dx, dy);
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PathToken {
+    Command(char),
+    Number(f32),
+}
+
+/// Split path data into commands and numbers
+///
+/// Handles the compact forms browsers accept, such as `M10-5` and `.5.5`.
+fn tokenize_path_data(d: &str) -> Option<Vec<PathToken>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = d.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ',' {
+            i += 1;
+        } else if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            tokens.push(PathToken::Command(c));
+            i += 1;
+        } else {
+            let start = i;
+            let mut seen_dot = false;
+            if chars[i] == '-' || chars[i] == '+' {
+                i += 1;
+            }
+            while i < chars.len() {
+                match chars[i] {
+                    '0'..='9' => i += 1,
+                    '.' if !seen_dot => {
+                        seen_dot = true;
+                        i += 1;
+                    }
+                    'e' | 'E' => {
+                        i += 1;
+                        if i < chars.len() && (chars[i] == '-' || chars[i] == '+') {
+                            i += 1;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(PathToken::Number(text.parse().ok()?));
+        }
+    }
+    Some(tokens)
+}
+
+/// Parse a whitespace/comma separated list of numbers
+fn parse_numbers(value: &str) -> Option<Vec<f32>> {
+    tokenize_path_data(value)?
+        .into_iter()
+        .map(|token| match token {
+            PathToken::Number(n) => Some(n),
+            PathToken::Command(_) => None,
+        })
+        .collect()
+}
+
+/// Read a numeric attribute, accepting an optional `px` suffix
+fn number_attr(elem: &ElementData, name: &str) -> Option<f32> {
+    let value = elem.attributes.get(name)?.trim();
+    value.strip_suffix("px").unwrap_or(value).trim().parse().ok()
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use crate::render::rasterize;
+    use raqote::{DrawTarget, SolidSource};
+
+    fn pixel(dt: &DrawTarget, x: i32, y: i32) -> u32 {
+        dt.get_data()[(y * dt.width() + x) as usize]
+    }
+
+    /// Parse markup containing one `<svg>` and render it into a box
+    fn render_markup(html: &str, layout: Layout) -> DrawTarget {
+        let document = parse_html(html);
+        let svg_idx = crate::query::query_selector(&document, "svg").unwrap().unwrap();
+        let mut dt = DrawTarget::new(100, 100);
+        dt.clear(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255));
+        let mut list = DisplayList::new();
+        paint_svg(&mut list, &document, svg_idx, &layout);
+        rasterize(&mut dt, &list);
+        dt
+    }
+
+    fn box_at(x: f32, y: f32, size: f32) -> Layout {
+        Layout { rect: Rect::new(x, y, size, size), ..Default::default() }
+    }
+
+    #[test]
+    fn test_tokenize_compact_path_data() {
+        let tokens = tokenize_path_data("M10-5L.5.5z").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                PathToken::Command('M'),
+                PathToken::Number(10.0),
+                PathToken::Number(-5.0),
+                PathToken::Command('L'),
+                PathToken::Number(0.5),
+                PathToken::Number(0.5),
+                PathToken::Command('z'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_view_box_meet_centers_content() {
+        // Given: A 2:1 viewBox in a square viewport
+        let t = ViewportTransform::new(Some([0.0, 0.0, 20.0, 10.0]), Rect::new(0.0, 0.0, 40.0, 40.0));
+
+        // Then: It scales to the width and is centered vertically
+        assert_eq!(t.scale, 2.0);
+        assert_eq!(t.apply(0.0, 0.0), (0.0, 10.0));
+        assert_eq!(t.apply(20.0, 10.0), (40.0, 30.0));
+    }
+
+    #[test]
+    fn test_rect_scaled_by_view_box() {
+        // Given: A 24-unit icon drawn into a 48px box
+        let html = r#"<svg viewBox="0 0 24 24"><rect x="12" y="0" width="12" height="12" fill="red" /></svg>"#;
+
+        // When: We render it at 10,10
+        let dt = render_markup(html, box_at(10.0, 10.0, 48.0));
+
+        // Then: The rect covers the top-right quadrant only
+        assert_eq!(pixel(&dt, 50, 20), 0xffff0000);
+        assert_eq!(pixel(&dt, 20, 20), 0xffffffff);
+        assert_eq!(pixel(&dt, 50, 50), 0xffffffff);
+    }
+
+    #[test]
+    fn test_circle_fill_and_stroke() {
+        let html = r#"<svg viewBox="0 0 10 10"><circle cx="5" cy="5" r="4" fill="blue" stroke="red" stroke-width="1" /></svg>"#;
+        let dt = render_markup(html, box_at(0.0, 0.0, 100.0));
+
+        assert_eq!(pixel(&dt, 50, 50), 0xff0000ff);
+        assert_eq!(pixel(&dt, 90, 50), 0xffff0000);
+        assert_eq!(pixel(&dt, 2, 2), 0xffffffff);
+    }
+
+    #[test]
+    fn test_path_and_group_paint_inheritance() {
+        // Given: A triangle path inside a group that sets the fill
+        let html = r#"<svg viewBox="0 0 100 100"><g fill="green"><path d="M0 0 L100 0 L0 100 Z" /></g></svg>"#;
+        let dt = render_markup(html, box_at(0.0, 0.0, 100.0));
+
+        // Then: Only the upper-left triangle is painted with the group fill
+        assert_eq!(pixel(&dt, 10, 10), 0xff008000);
+        assert_eq!(pixel(&dt, 90, 90), 0xffffffff);
+    }
+
+    #[test]
+    fn test_line_and_polyline_are_stroked_only() {
+        let html = r#"<svg viewBox="0 0 100 100"><line x1="0" y1="10" x2="100" y2="10" stroke="black" stroke-width="4" /><polyline points="0,50 50,50 50,100" fill="none" stroke="blue" stroke-width="4" /></svg>"#;
+        let dt = render_markup(html, box_at(0.0, 0.0, 100.0));
+
+        assert_eq!(pixel(&dt, 50, 10), 0xff000000);
+        assert_eq!(pixel(&dt, 25, 50), 0xff0000ff);
+        assert_eq!(pixel(&dt, 25, 75), 0xffffffff);
+    }
+
+    #[test]
+    fn test_cubic_path_relative_commands() {
+        // Given: A closed shape built from relative commands
+        let html = r#"<svg viewBox="0 0 100 100"><path d="m10 10 h80 v80 c0 0 -80 0 -80 0 z" fill="red" /></svg>"#;
+        let dt = render_markup(html, box_at(0.0, 0.0, 100.0));
+
+        assert_eq!(pixel(&dt, 50, 50), 0xffff0000);
+        assert_eq!(pixel(&dt, 5, 5), 0xffffffff);
+    }
+
+    #[test]
+    fn test_malformed_path_is_skipped() {
+        let html = r#"<svg viewBox="0 0 10 10"><path d="M0 0 Q 5 5 10 0" fill="red" /></svg>"#;
+        let dt = render_markup(html, box_at(0.0, 0.0, 100.0));
+
+        assert_eq!(pixel(&dt, 50, 10), 0xffffffff);
+    }
+
+    #[test]
+    fn test_shapes_recorded_inside_viewport_clip() {
+        // Given: A filled and stroked rect and a stroke-only line
+        let html = r#"<svg viewBox="0 0 10 10"><rect width="5" height="5" fill="red" stroke="blue" /><line x2="10" y2="10" stroke="black" /></svg>"#;
+        let document = parse_html(html);
+        let svg_idx = crate::query::query_selector(&document, "svg").unwrap().unwrap();
+
+        // When: The svg is recorded into a 100px box
+        let mut list = DisplayList::new();
+        paint_svg(&mut list, &document, svg_idx, &box_at(0.0, 0.0, 100.0));
+
+        // Then: Fills precede strokes, widths are scaled, all inside the clip
+        let commands = list.commands();
+        assert_eq!(commands.len(), 6);
+        assert!(matches!(commands[0], PaintCommand::PushClip { rect, .. } if rect == Rect::new(0.0, 0.0, 100.0, 100.0)));
+        assert!(matches!(commands[1], PaintCommand::FillPath { color: 0xffff0000, .. }));
+        assert!(matches!(commands[2], PaintCommand::StrokePath { width, color: 0xff0000ff, .. } if width == 10.0));
+        assert!(matches!(commands[3], PaintCommand::FillPath { .. }), "Lines are filled as if closed");
+        assert!(matches!(commands[4], PaintCommand::StrokePath { color: 0xff000000, .. }));
+        assert!(matches!(commands[5], PaintCommand::PopClip));
+    }
+}
@@ -0,0 +1,771 @@
+//! Test Runner
+//! `describe` / `it` / `beforeEach` / `afterEach` for JavaScript tests,
+//! aggregated into a `TestSummary`
+//!
+//! Each test starts from the same document and realm, with the globals,
+//! tasks and timers a test leaves behind cleared after it. Timers run on
+//! the page's clock, so `await sleep(1000)` settles at once.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rquickjs::{AsyncContext, AsyncRuntime, CatchResultExt, Ctx, Object, Value};
+
+use crate::console::ConsoleBuffer;
+use crate::css::StyleSheet;
+use crate::dom::Document;
+use crate::error::{BrowserError, TestResult, TestSummary};
+use crate::event_loop::{block_on_until, poll_once, ready, EventLoop, HOST_TIMEOUT};
+use crate::failure_capture::{capture_on_failure, FailureCaptureConfig};
+use crate::parser::parse_html;
+use crate::style::compute_styles;
+use crate::trace::{TraceStage, Tracer};
+
+/// Timeout applied to tests that do not pass their own to `it`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the DOM is reset between tests
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomIsolation {
+    /// Snapshot the document before each test and restore it afterwards
+    SnapshotRestore,
+    /// Replace the document with a freshly parsed copy of this HTML before
+    /// each test
+    FreshDocument(String),
+    /// Tests share one document and see each other's changes
+    Shared,
+}
+
+/// How globals and queued tasks are reset between tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalIsolation {
+    /// Undo each test's changes to globals and drop the tasks it left queued
+    Restore,
+    /// Tests share globals and tasks
+    Shared,
+}
+
+/// Runner configuration
+#[derive(Debug, Clone)]
+pub struct TestRunnerConfig {
+    pub timeout: Duration,
+    pub isolation: DomIsolation,
+    pub globals: GlobalIsolation,
+    /// Fail passing tests that leave new globals or queued tasks behind
+    pub detect_leaks: bool,
+    pub failure_capture: FailureCaptureConfig,
+    /// Stylesheet used to render failure screenshots; defaults apply if unset
+    pub stylesheet: Option<Rc<StyleSheet>>,
+    pub seed: Option<u64>,
+    /// Tasks async tests may wait on, e.g. WebSocket messages
+    pub event_loop: EventLoop,
+    /// Where scripts log, so each result carries what its test logged
+    pub console: Option<ConsoleBuffer>,
+    /// Records a span per test
+    pub tracer: Tracer,
+}
+
+impl TestRunnerConfig {
+    pub fn new() -> Self {
+        TestRunnerConfig {
+            timeout: DEFAULT_TIMEOUT,
+            isolation: DomIsolation::SnapshotRestore,
+            globals: GlobalIsolation::Restore,
+            detect_leaks: false,
+            failure_capture: FailureCaptureConfig::disabled(),
+            stylesheet: None,
+            seed: None,
+            event_loop: EventLoop::new(),
+            console: None,
+            tracer: Tracer::disabled(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_isolation(mut self, isolation: DomIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    pub fn with_global_isolation(mut self, globals: GlobalIsolation) -> Self {
+        self.globals = globals;
+        self
+    }
+
+    pub fn with_leak_detection(mut self, detect_leaks: bool) -> Self {
+        self.detect_leaks = detect_leaks;
+        self
+    }
+
+    pub fn with_failure_capture(mut self, config: FailureCaptureConfig) -> Self {
+        self.failure_capture = config;
+        self
+    }
+
+    pub fn with_stylesheet(mut self, stylesheet: Rc<StyleSheet>) -> Self {
+        self.stylesheet = Some(stylesheet);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_event_loop(mut self, event_loop: EventLoop) -> Self {
+        self.event_loop = event_loop;
+        self
+    }
+
+    pub fn with_console(mut self, console: ConsoleBuffer) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = tracer;
+        self
+    }
+}
+
+impl Default for TestRunnerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// JavaScript side of the runner: collects the suite tree while the test file
+/// is evaluated and runs one test (hooks included) on request
+///
+/// A test's outcome is written to `__cortexTests.outcomes[i]` once its promise
+/// settles, so the Rust side only has to pump jobs and poll. Outcomes are
+/// kept per test so a timed-out test settling late cannot overwrite the
+/// outcome of the test running after it.
+const RUNNER_PRELUDE: &str = r#"
+(function () {
+    const root = { name: "", parent: null, beforeEach: [], afterEach: [] };
+    const tests = [];
+    let current = root;
+
+    function describeError(e) {
+        if (e instanceof Error) {
+            return e.name && e.name !== "Error" ? e.name + ": " + e.message : e.message;
+        }
+        return String(e);
+    }
+
+    function stackOf(e) {
+        return e instanceof Error && typeof e.stack === "string" && e.stack ? e.stack : undefined;
+    }
+
+    function chainOf(suite) {
+        const chain = [];
+        for (let s = suite; s; s = s.parent) chain.unshift(s);
+        return chain;
+    }
+
+    globalThis.describe = function (name, fn) {
+        const suite = { name: String(name), parent: current, beforeEach: [], afterEach: [] };
+        const previous = current;
+        current = suite;
+        try {
+            fn();
+        } finally {
+            current = previous;
+        }
+    };
+    globalThis.it = globalThis.test = function (name, fn, timeout) {
+        tests.push({ suite: current, name: String(name), fn: fn, timeout: timeout });
+    };
+    globalThis.beforeEach = function (fn) { current.beforeEach.push(fn); };
+    globalThis.afterEach = function (fn) { current.afterEach.push(fn); };
+
+    globalThis.__cortexTests = {
+        outcomes: [],
+        count: function () { return tests.length; },
+        name: function (i) {
+            const names = chainOf(tests[i].suite).map(s => s.name).filter(n => n);
+            names.push(tests[i].name);
+            return names.join(" > ");
+        },
+        timeout: function (i) {
+            const timeout = tests[i].timeout;
+            return typeof timeout === "number" && timeout > 0 ? timeout : -1;
+        },
+        // Globals as the running test started
+        globals: new Map(),
+        // Names of the globals added since the test started, deleting them
+        // and putting back replaced or deleted ones when `restore`
+        leaks: function (restore) {
+            const saved = this.globals;
+            const leaked = Reflect.ownKeys(globalThis).filter(key => !saved.has(key));
+            if (restore) {
+                for (const key of leaked) delete globalThis[key];
+                for (const [key, descriptor] of saved) {
+                    const current = Object.getOwnPropertyDescriptor(globalThis, key);
+                    if (current && Object.is(current.value, descriptor.value) && current.get === descriptor.get && current.set === descriptor.set) continue;
+                    try { Object.defineProperty(globalThis, key, descriptor); } catch (e) {}
+                }
+            }
+            return leaked.map(String);
+        },
+        run: function (i) {
+            const state = this;
+            const test = tests[i];
+            const chain = chainOf(test.suite);
+            state.globals = new Map(Reflect.ownKeys(globalThis).map(key => [key, Object.getOwnPropertyDescriptor(globalThis, key)]));
+            // Console assertions only see what this test logs
+            if (globalThis.__cortexConsole) globalThis.__cortexConsole.startTest();
+            (async function () {
+                let error;
+                try {
+                    for (const suite of chain) for (const hook of suite.beforeEach) await hook();
+                    await test.fn();
+                } catch (e) {
+                    error = e;
+                }
+                for (const suite of chain.slice().reverse()) {
+                    for (const hook of suite.afterEach) {
+                        try {
+                            await hook();
+                        } catch (e) {
+                            if (error === undefined) error = e;
+                        }
+                    }
+                }
+                if (error !== undefined) throw error;
+            })().then(
+                () => { state.outcomes[i] = { passed: true, message: "" }; },
+                e => { state.outcomes[i] = { passed: false, message: describeError(e), stack: stackOf(e) }; }
+            );
+        },
+    };
+})();
+"#;
+
+/// Define `describe`, `it`/`test`, `beforeEach` and `afterEach`
+///
+/// Call before evaluating test files; tests are only collected, and run later
+/// by `run_tests`.
+pub fn install_test_runner(ctx: &Ctx<'_>) -> rquickjs::Result<()> {
+    ctx.eval::<(), _>(RUNNER_PRELUDE)
+}
+
+/// Number of tests registered so far
+pub fn registered_test_count(ctx: &Ctx<'_>) -> rquickjs::Result<usize> {
+    let tests: Object = ctx.globals().get("__cortexTests")?;
+    let count: rquickjs::Function = tests.get("count")?;
+    count.call::<_, usize>(())
+}
+
+/// Run every registered test in order
+///
+/// Must be called outside `AsyncContext::with`: pending jobs are executed on the
+/// runtime between polls. Each test gets its own deadline; a test that
+/// overruns it (including a synchronous infinite loop, which is interrupted)
+/// fails with a timeout, and one whose promise is left pending with nothing
+/// left to run fails at once rather than waiting out its deadline.
+pub fn run_tests(
+    runtime: &AsyncRuntime,
+    context: &AsyncContext,
+    document: Rc<RefCell<Document>>,
+    config: &TestRunnerConfig,
+) -> TestSummary {
+    let mut summary = TestSummary::new();
+    if let Some(seed) = config.seed {
+        summary = summary.with_seed(seed);
+    }
+
+    let deadline: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+    let interrupt_deadline = deadline.clone();
+    ready(runtime.set_interrupt_handler(Some(Box::new(move || {
+        interrupt_deadline.get().is_some_and(|d| Instant::now() > d)
+    }))));
+
+    let count = ready(context.with(|ctx| registered_test_count(&ctx))).unwrap_or(0);
+    for i in 0..count {
+        let (name, timeout) = ready(context.with(|ctx| test_info(&ctx, i, config.timeout)));
+
+        let snapshot = match &config.isolation {
+            DomIsolation::SnapshotRestore => Some(document.borrow().clone()),
+            DomIsolation::FreshDocument(html) => {
+                *document.borrow_mut() = parse_html(html);
+                None
+            }
+            DomIsolation::Shared => None,
+        };
+
+        let logged_before = config.console.as_ref().map_or(0, |console| console.borrow().len());
+        let started = Instant::now();
+        deadline.set(Some(started + timeout));
+        let outcome = config.tracer.span(TraceStage::Test, &name, || run_one(runtime, context, &config.event_loop, i, &deadline));
+        deadline.set(None);
+        let elapsed = started.elapsed();
+        let restore = config.globals == GlobalIsolation::Restore;
+        let leaked_globals = ready(context.with(|ctx| take_leaks(&ctx, restore))).unwrap_or_default();
+        let pending_tasks = config.event_loop.pending_tasks();
+        let pending_timers = config.event_loop.pending_timers();
+        if restore {
+            config.event_loop.clear_tasks();
+        }
+
+        let result = match outcome {
+            Outcome::Passed => TestResult::success(&name, "passed"),
+            Outcome::Failed(message, stack) => {
+                TestResult::failure(&name, &message, BrowserError::JavaScriptError(message.clone(), stack))
+            }
+            Outcome::TimedOut => {
+                let message = format!("Timed out after {}ms", timeout.as_millis());
+                TestResult::failure(&name, &message, BrowserError::JavaScriptError(message.clone(), None))
+            }
+        }
+        .with_duration(elapsed);
+        let result = match describe_leaks(&leaked_globals, pending_tasks, pending_timers) {
+            Some(message) if config.detect_leaks && result.passed => {
+                TestResult::failure(&name, &message, BrowserError::JavaScriptError(message.clone(), None)).with_duration(elapsed)
+            }
+            _ => result,
+        };
+        // `console.clear()` only hides entries from matchers, so the buffer
+        // only grows
+        let logged = config.console.as_ref().map(|console| console.borrow().get(logged_before..).unwrap_or_default().to_vec());
+        let result = result.with_console(logged.unwrap_or_default());
+        let result = {
+            let doc = document.borrow();
+            let styles = match &config.stylesheet {
+                Some(stylesheet) if !result.passed && config.failure_capture.enabled => {
+                    compute_styles(&doc, stylesheet)
+                }
+                _ => Vec::new(),
+            };
+            capture_on_failure(result, &doc, &styles, &config.failure_capture)
+        };
+        summary.add_result(result);
+
+        if let Some(snapshot) = snapshot {
+            document.borrow_mut().restore(snapshot);
+        }
+    }
+
+    ready(runtime.set_interrupt_handler(None));
+    summary
+}
+
+enum Outcome {
+    Passed,
+    /// Message and, for thrown `Error`s, the JavaScript stack
+    Failed(String, Option<String>),
+    TimedOut,
+}
+
+fn test_info(ctx: &Ctx<'_>, i: usize, default_timeout: Duration) -> (String, Duration) {
+    let info = || -> rquickjs::Result<(String, f64)> {
+        let tests: Object = ctx.globals().get("__cortexTests")?;
+        let name: rquickjs::Function = tests.get("name")?;
+        let timeout: rquickjs::Function = tests.get("timeout")?;
+        Ok((name.call((i,))?, timeout.call((i,))?))
+    };
+    match info() {
+        Ok((name, ms)) if ms > 0.0 => (name, Duration::from_millis(ms as u64)),
+        Ok((name, _)) => (name, default_timeout),
+        Err(_) => (format!("test #{}", i + 1), default_timeout),
+    }
+}
+
+/// The globals the running test added, deleted with its other changes to
+/// globals undone when `restore`
+fn take_leaks(ctx: &Ctx<'_>, restore: bool) -> rquickjs::Result<Vec<String>> {
+    let tests: Object = ctx.globals().get("__cortexTests")?;
+    let leaks: rquickjs::Function = tests.get("leaks")?;
+    leaks.call((rquickjs::function::This(tests.clone()), restore))
+}
+
+/// What a test left behind, e.g. `Leaked globals: cache, token; 1 pending
+/// task; 2 timers`, or `None` when nothing
+fn describe_leaks(globals: &[String], pending_tasks: usize, pending_timers: usize) -> Option<String> {
+    let mut leaks = Vec::new();
+    if !globals.is_empty() {
+        leaks.push(format!("globals: {}", globals.join(", ")));
+    }
+    if pending_tasks > 0 {
+        leaks.push(format!("{} pending task{}", pending_tasks, if pending_tasks == 1 { "" } else { "s" }));
+    }
+    if pending_timers > 0 {
+        leaks.push(format!("{} timer{}", pending_timers, if pending_timers == 1 { "" } else { "s" }));
+    }
+    (!leaks.is_empty()).then(|| format!("Leaked {}", leaks.join("; ")))
+}
+
+fn run_one(runtime: &AsyncRuntime, context: &AsyncContext, event_loop: &EventLoop, i: usize, deadline: &Cell<Option<Instant>>) -> Outcome {
+    let timed_out = || deadline.get().is_some_and(|d| Instant::now() > d);
+
+    let started = ready(context.with(|ctx| {
+        let run = || -> rquickjs::Result<()> {
+            let tests: Object = ctx.globals().get("__cortexTests")?;
+            let run: rquickjs::Function = tests.get("run")?;
+            run.call((rquickjs::function::This(tests.clone()), i))
+        };
+        run().catch(&ctx).map_err(|e| e.to_string())
+    }));
+    if let Err(message) = started {
+        return if timed_out() { Outcome::TimedOut } else { Outcome::Failed(message, None) };
+    }
+
+    loop {
+        if let Some(outcome) = ready(context.with(|ctx| read_outcome(&ctx, i))) {
+            return outcome;
+        }
+        if timed_out() {
+            return Outcome::TimedOut;
+        }
+        // `None` when only host functions still running are left
+        let job = poll_once(runtime.execute_pending_job());
+        match job {
+            Some(Ok(true)) => {}
+            Some(Ok(false)) | None => match event_loop.run_next_task(context) {
+                Ok(true) => {}
+                // Only timers left: skip the wait to the next one
+                Ok(false) if event_loop.advance_to_next_due() => {}
+                // Host functions still running: wait for one, up to the deadline
+                Ok(false) if job.is_none() => {
+                    let until = deadline.get().unwrap_or_else(|| Instant::now() + HOST_TIMEOUT);
+                    if let Some(Err(e)) = block_on_until(runtime.execute_pending_job(), until) {
+                        return if timed_out() { Outcome::TimedOut } else { Outcome::Failed(e.to_string(), None) };
+                    }
+                }
+                // Nothing left to run and the test has not settled: it never will
+                Ok(false) => return Outcome::Failed("Test never settled: nothing left to run".to_string(), None),
+                Err(_) if timed_out() => return Outcome::TimedOut,
+                Err(e) => return Outcome::Failed(e.to_string(), None),
+            },
+            Some(Err(_)) if timed_out() => return Outcome::TimedOut,
+            Some(Err(e)) => return Outcome::Failed(e.to_string(), None),
+        }
+    }
+}
+
+fn read_outcome(ctx: &Ctx<'_>, i: usize) -> Option<Outcome> {
+    let tests: Object = ctx.globals().get("__cortexTests").ok()?;
+    let outcomes: Object = tests.get("outcomes").ok()?;
+    let outcome: Value = outcomes.get(i as u32).ok()?;
+    let outcome = outcome.into_object()?;
+    let passed: bool = outcome.get("passed").ok()?;
+    if passed {
+        Some(Outcome::Passed)
+    } else {
+        Some(Outcome::Failed(
+            outcome.get("message").unwrap_or_default(),
+            outcome.get::<_, Option<String>>("stack").ok().flatten(),
+        ))
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_loop::{Clock, TaskSource};
+    use crate::timers::{install_timers, PageTimers};
+
+    fn run_js(source: &str, config: &TestRunnerConfig) -> (TestSummary, Rc<RefCell<Document>>) {
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let document = Rc::new(RefCell::new(parse_html("<div id=\"app\"></div>")));
+        let doc = document.clone();
+        ready(context.with(|ctx| {
+            install_test_runner(&ctx).unwrap();
+            let append = rquickjs::Function::new(ctx.clone(), move |tag: String| {
+                let mut doc = doc.borrow_mut();
+                let idx = doc.create_element(&tag);
+                let root = doc.root;
+                doc.append_child(root, idx);
+            })
+            .unwrap();
+            ctx.globals().set("appendElement", append).unwrap();
+            let doc = document.clone();
+            let count = rquickjs::Function::new(ctx.clone(), move || doc.borrow().nodes.len()).unwrap();
+            ctx.globals().set("nodeCount", count).unwrap();
+            ctx.eval::<(), _>(source).unwrap();
+        }));
+        let summary = run_tests(&runtime, &context, document.clone(), config);
+        (summary, document)
+    }
+
+    // ========================================================================
+    // Collection and hooks
+    // ========================================================================
+
+    #[test]
+    fn test_describe_it_and_hooks() {
+        // Given: Nested suites with hooks that record the call order
+        let source = r#"
+            globalThis.log = [];
+            describe("outer", () => {
+                beforeEach(() => log.push("before outer"));
+                afterEach(() => log.push("after outer"));
+                describe("inner", () => {
+                    beforeEach(() => log.push("before inner"));
+                    afterEach(() => log.push("after inner"));
+                    it("runs", () => log.push("test"));
+                });
+                it("fails", () => { throw new Error("expected 'Save'"); });
+            });
+            test("top level", () => {
+                if (log.join(",") !== "before outer,before inner,test,after inner,after outer,before outer,after outer") {
+                    throw new Error("bad order: " + log.join(","));
+                }
+            });
+        "#;
+
+        // When: We run the tests
+        let (summary, _) = run_js(source, &TestRunnerConfig::new());
+
+        // Then: Names include the suite path and failures carry the message
+        let names: Vec<&str> = summary.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["outer > inner > runs", "outer > fails", "top level"]);
+        assert_eq!((summary.passed, summary.failed), (2, 1));
+        assert_eq!(summary.results[1].message, "expected 'Save'");
+        assert_eq!(summary.exit_code(), 1);
+    }
+
+    #[test]
+    fn test_error_types_and_thrown_values() {
+        let source = r#"
+            it("type error", () => { null.x; });
+            it("string", () => { throw "plain"; });
+            it("after each failure", () => {});
+            afterEach(() => { throw new RangeError("cleanup"); });
+        "#;
+
+        let (summary, _) = run_js(source, &TestRunnerConfig::new());
+
+        assert!(summary.results[0].message.starts_with("TypeError: "));
+        match &summary.results[0].error {
+            Some(BrowserError::JavaScriptError(_, stack)) => assert!(stack.is_some(), "Errors keep their stack"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(summary.results.iter().all(|r| r.duration.is_some()));
+        assert_eq!(summary.results[1].message, "plain", "The test's own error wins over afterEach");
+        assert_eq!(summary.results[2].message, "RangeError: cleanup");
+    }
+
+    // ========================================================================
+    // Async tests and timeouts
+    // ========================================================================
+
+    #[test]
+    fn test_async_tests_are_awaited() {
+        // Given: Async tests and hooks that resolve through the job queue
+        let source = r#"
+            let ready = false;
+            beforeEach(async () => { await Promise.resolve(); ready = true; });
+            it("sees async setup", async () => {
+                await null;
+                if (!ready) throw new Error("not ready");
+            });
+            it("rejects", async () => {
+                await Promise.resolve();
+                throw new Error("async failure");
+            });
+        "#;
+
+        let (summary, _) = run_js(source, &TestRunnerConfig::new());
+
+        assert!(summary.results[0].passed, "{}", summary.results[0].message);
+        assert_eq!(summary.results[1].message, "async failure");
+    }
+
+    #[test]
+    fn test_timeouts() {
+        // Given: A test that never settles and one that loops forever
+        let source = r#"
+            it("never settles", () => new Promise(() => {}));
+            it("loops", () => { while (true) {} }, 50);
+            it("still runs", () => {});
+        "#;
+
+        // When: We run with a short default timeout
+        let config = TestRunnerConfig::new().with_timeout(Duration::from_millis(100));
+        let (summary, _) = run_js(source, &config);
+
+        // Then: The stuck test fails at once, the loop times out, and later
+        // tests are unaffected
+        assert_eq!(summary.results[0].message, "Test never settled: nothing left to run");
+        assert_eq!(summary.results[1].message, "Timed out after 50ms");
+        assert!(summary.results[2].passed, "{}", summary.results[2].message);
+    }
+
+    // ========================================================================
+    // DOM isolation
+    // ========================================================================
+
+    #[test]
+    fn test_snapshot_restore_isolates_tests() {
+        // Given: Two tests that add an element and check the node count
+        let source = r#"
+            let initial;
+            beforeEach(() => { if (initial === undefined) initial = nodeCount(); });
+            it("adds", () => { appendElement("p"); });
+            it("starts clean", () => {
+                if (nodeCount() !== initial) throw new Error("leaked " + (nodeCount() - initial));
+            });
+        "#;
+
+        // When: Tests run with the default snapshot/restore isolation
+        let (summary, document) = run_js(source, &TestRunnerConfig::new());
+
+        // Then: The second test sees the original DOM, and so does the caller
+        assert_eq!(summary.failed, 0, "{}", summary.format_summary());
+        let (_, shared_document) = run_js(source, &TestRunnerConfig::new().with_isolation(DomIsolation::Shared));
+        assert_eq!(document.borrow().nodes.len() + 1, shared_document.borrow().nodes.len());
+    }
+
+    #[test]
+    fn test_fresh_document_per_test() {
+        let source = r#"
+            let initial;
+            it("one", () => { initial = nodeCount(); appendElement("p"); });
+            it("two", () => { if (nodeCount() !== initial) throw new Error("leaked"); });
+        "#;
+
+        let config = TestRunnerConfig::new().with_isolation(DomIsolation::FreshDocument("<main></main>".to_string()));
+        let (summary, _) = run_js(source, &config);
+
+        assert_eq!(summary.failed, 0, "{}", summary.format_summary());
+    }
+
+    // ========================================================================
+    // Global isolation and leaks
+    // ========================================================================
+
+    /// Counts tasks `queueTask()` queues, running none
+    #[derive(Default)]
+    struct Queued(Cell<usize>);
+
+    impl TaskSource for Queued {
+        fn run_next_task(&self, _: &Ctx<'_>) -> rquickjs::Result<bool> {
+            Ok(false)
+        }
+
+        fn pending_tasks(&self) -> usize {
+            self.0.get()
+        }
+
+        fn clear_tasks(&self) {
+            self.0.set(0);
+        }
+    }
+
+    fn run_with_tasks(source: &str, config: TestRunnerConfig) -> (TestSummary, Rc<Queued>) {
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let queued = Rc::new(Queued::default());
+        let tasks = queued.clone();
+        ready(context.with(|ctx| {
+            install_test_runner(&ctx).unwrap();
+            let queue = rquickjs::Function::new(ctx.clone(), move || tasks.0.set(tasks.0.get() + 1)).unwrap();
+            ctx.globals().set("queueTask", queue).unwrap();
+            ctx.eval::<(), _>(source).unwrap();
+        }));
+        let document = Rc::new(RefCell::new(parse_html("<main></main>")));
+        let config = config.with_event_loop(EventLoop::new().with_source(queued.clone()));
+        (run_tests(&runtime, &context, document, &config), queued)
+    }
+
+    #[test]
+    fn test_globals_are_restored_and_leaks_reported() {
+        // Given: A file defining a global, a test that adds one, replaces the
+        // file's and queues a task, and a test expecting neither change
+        let source = r#"
+            globalThis.shared = "file";
+            it("pollutes", () => { globalThis.cache = {}; shared = "changed"; queueTask(); });
+            it("starts clean", () => {
+                if (typeof cache !== "undefined") throw new Error("cache leaked");
+                if (shared !== "file") throw new Error("shared is " + shared);
+            });
+        "#;
+
+        // When: The tests run with leak detection
+        let (summary, queued) = run_with_tasks(source, TestRunnerConfig::new().with_leak_detection(true));
+
+        // Then: The polluting test fails naming its leaks, which the next
+        // test does not see
+        assert_eq!(summary.results[0].message, "Leaked globals: cache; 1 pending task");
+        assert!(summary.results[1].passed, "{}", summary.results[1].message);
+        assert_eq!(queued.pending_tasks(), 0);
+
+        // When: Globals are shared, without leak detection
+        let (summary, queued) = run_with_tasks(source, TestRunnerConfig::new().with_global_isolation(GlobalIsolation::Shared));
+
+        // Then: The first test passes and its changes carry over
+        assert!(summary.results[0].passed, "{}", summary.results[0].message);
+        assert_eq!(summary.results[1].message, "cache leaked");
+        assert_eq!(queued.pending_tasks(), 1);
+        assert_eq!(describe_leaks(&[], 2, 0), Some("Leaked 2 pending tasks".to_string()));
+        assert_eq!(describe_leaks(&[], 0, 0), None);
+    }
+
+    #[test]
+    fn test_timers_skip_ahead_and_leaks_are_reported() {
+        // Given: A test awaiting a long timeout, one leaving an interval and
+        // one expecting no timer from it
+        let source = r#"
+            const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+            let ticks = 0;
+            it("sleeps", async () => { await sleep(60000); });
+            it("leaves an interval", () => { setInterval(() => ticks++, 10); });
+            it("starts clean", async () => {
+                await sleep(100);
+                if (ticks !== 0) throw new Error("interval ran " + ticks + " times");
+            });
+        "#;
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let clock = Clock::new();
+        let timers = PageTimers::new(clock.clone());
+        ready(context.with(|ctx| {
+            install_test_runner(&ctx).unwrap();
+            install_timers(&ctx, &timers).unwrap();
+            ctx.eval::<(), _>(source).unwrap();
+        }));
+
+        // When: The tests run with leak detection, against a real timeout
+        // far shorter than the sleep
+        let document = Rc::new(RefCell::new(parse_html("<main></main>")));
+        let config = TestRunnerConfig::new()
+            .with_timeout(Duration::from_secs(1))
+            .with_leak_detection(true)
+            .with_event_loop(EventLoop::new().with_clock(clock.clone()).with_source(Rc::new(timers.clone())));
+        let summary = run_tests(&runtime, &context, document, &config);
+
+        // Then: The sleep settles on the page clock, and the interval fails
+        // its test and is gone before the next
+        assert!(summary.results[0].passed, "{}", summary.results[0].message);
+        assert!(clock.now_ms() >= 60000.0);
+        assert_eq!(summary.results[1].message, "Leaked 1 timer");
+        assert!(summary.results[2].passed, "{}", summary.results[2].message);
+        assert_eq!(timers.active(), 0);
+        assert_eq!(describe_leaks(&[], 1, 2), Some("Leaked 1 pending task; 2 timers".to_string()));
+    }
+
+    #[test]
+    fn test_seed_and_failure_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TestRunnerConfig::new()
+            .with_seed(7)
+            .with_failure_capture(FailureCaptureConfig::new().with_output_dir(dir.path()).with_viewport(32, 32));
+
+        let (summary, _) = run_js(r#"describe("form", () => it("breaks", () => { throw new Error("x"); }));"#, &config);
+
+        assert_eq!(summary.seed, Some(7));
+        assert_eq!(summary.results[0].screenshot_path, Some(dir.path().join("form-breaks.png")));
+    }
+}
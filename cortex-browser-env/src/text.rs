@@ -0,0 +1,190 @@
+//! Text Line Breaking
+//! Splits text into visual lines at the break opportunities browsers use
+
+/// No-break space: renders as a space but never allows a line break
+pub const NO_BREAK_SPACE: char = '\u{00A0}';
+
+/// Soft hyphen: invisible unless a line breaks at it, then shown as `-`
+pub const SOFT_HYPHEN: char = '\u{00AD}';
+
+/// Where words may break when no space or soft hyphen fits the line, from
+/// `word-break` and `overflow-wrap`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WordBreaking {
+    /// Never; an overlong word overflows the line
+    #[default]
+    Normal,
+    /// Between characters of a word too long for a line of its own
+    Overlong,
+    /// Between any two characters, filling every line
+    Anywhere,
+}
+
+/// A run of text between two break opportunities
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    /// Visible text, without soft hyphens
+    text: String,
+    /// Whether the break after this segment is a soft hyphen
+    hyphenated: bool,
+    /// Whether the text ends with a forced line break (`\n`)
+    forced_break: bool,
+}
+
+/// Split text into segments ending at spaces, soft hyphens or newlines
+fn segments(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    for ch in text.chars() {
+        match ch {
+            SOFT_HYPHEN => {
+                segments.push(Segment { text: std::mem::take(&mut current), hyphenated: true, forced_break: false });
+            }
+            '\n' => {
+                segments.push(Segment { text: std::mem::take(&mut current), hyphenated: false, forced_break: true });
+            }
+            ' ' => {
+                current.push(ch);
+                segments.push(Segment { text: std::mem::take(&mut current), hyphenated: false, forced_break: false });
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.is_empty() {
+        segments.push(Segment { text: current, hyphenated: false, forced_break: false });
+    }
+    segments
+}
+
+/// Break text into lines no wider than `max_width`
+///
+/// Lines break after regular spaces, at soft hyphens (which then render as a
+/// trailing `-`) and at newlines. No-break spaces keep their neighbours
+/// together. Spaces at a wrapped line end hang past the edge and are dropped.
+/// `breaking` decides whether words also break between characters.
+///
+/// `advance` gives the horizontal advance of each character, including any
+/// letter and word spacing.
+pub fn break_lines(text: &str, max_width: f32, breaking: WordBreaking, advance: impl Fn(char) -> f32) -> Vec<String> {
+    let width_of = |s: &str| s.chars().map(&advance).sum::<f32>();
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0.0;
+    let mut pending_hyphen = false;
+
+    for segment in segments(text) {
+        let content = segment.text.trim_end_matches(' ');
+        let content_width = width_of(content);
+
+        let anywhere = breaking == WordBreaking::Anywhere;
+        if !anywhere && !line.is_empty() && line_width + content_width > max_width {
+            // Wrap before this segment
+            let mut finished = std::mem::take(&mut line);
+            finished.truncate(finished.trim_end_matches(' ').len());
+            if pending_hyphen {
+                finished.push('-');
+            }
+            lines.push(finished);
+            line_width = 0.0;
+        }
+
+        let overlong = breaking == WordBreaking::Overlong && line.is_empty() && content_width > max_width;
+        if anywhere || overlong {
+            // Break between characters, inside the word
+            for ch in segment.text.chars() {
+                let w = advance(ch);
+                if !line.is_empty() && line_width + w > max_width && ch != ' ' {
+                    let mut finished = std::mem::take(&mut line);
+                    finished.truncate(finished.trim_end_matches(' ').len());
+                    lines.push(finished);
+                    line_width = 0.0;
+                }
+                line.push(ch);
+                line_width += w;
+            }
+        } else {
+            line.push_str(&segment.text);
+            line_width += width_of(&segment.text);
+        }
+
+        pending_hyphen = segment.hyphenated;
+        if segment.forced_break {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0.0;
+            pending_hyphen = false;
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every character is one unit wide
+    fn unit(_: char) -> f32 {
+        1.0
+    }
+
+    #[test]
+    fn test_breaks_at_spaces() {
+        // Given: Three words in a line 8 units wide
+        let lines = break_lines("one two three", 8.0, WordBreaking::Normal, unit);
+
+        // Then: Lines wrap between words and drop the hanging space
+        assert_eq!(lines, vec!["one two", "three"]);
+    }
+
+    #[test]
+    fn test_no_break_space_keeps_words_together() {
+        let text = format!("Total 10{}USD", NO_BREAK_SPACE);
+        let lines = break_lines(&text, 10.0, WordBreaking::Normal, unit);
+
+        assert_eq!(lines, vec!["Total".to_string(), format!("10{}USD", NO_BREAK_SPACE)]);
+    }
+
+    #[test]
+    fn test_soft_hyphen_used_only_when_breaking() {
+        let text = format!("extra{}ordinary", SOFT_HYPHEN);
+
+        // Fits: the soft hyphen stays invisible
+        assert_eq!(break_lines(&text, 20.0, WordBreaking::Normal, unit), vec!["extraordinary"]);
+
+        // Too long: breaks at the soft hyphen and shows a hyphen
+        assert_eq!(break_lines(&text, 9.0, WordBreaking::Normal, unit), vec!["extra-", "ordinary"]);
+    }
+
+    #[test]
+    fn test_forced_breaks_and_overlong_words() {
+        assert_eq!(break_lines("a\nb", 10.0, WordBreaking::Normal, unit), vec!["a", "b"]);
+        assert_eq!(break_lines("abcdef", 4.0, WordBreaking::Overlong, unit), vec!["abcd", "ef"]);
+    }
+
+    #[test]
+    fn test_word_breaking_modes() {
+        // Given: A short word and a long one in a line 4 units wide
+        let text = "ab cdefgh";
+
+        // When/Then: Normal lets the long word overflow, overlong breaks
+        // only that word and anywhere fills every line
+        assert_eq!(break_lines(text, 4.0, WordBreaking::Normal, unit), vec!["ab", "cdefgh"]);
+        assert_eq!(break_lines(text, 4.0, WordBreaking::Overlong, unit), vec!["ab", "cdef", "gh"]);
+        assert_eq!(break_lines(text, 4.0, WordBreaking::Anywhere, unit), vec!["ab c", "defg", "h"]);
+    }
+
+    #[test]
+    fn test_advance_includes_spacing() {
+        // Spaces twice as wide push the second word onto its own line
+        let wide_space = |ch: char| if ch == ' ' { 3.0 } else { 1.0 };
+        assert_eq!(break_lines("ab cd", 6.0, WordBreaking::Normal, unit), vec!["ab cd"]);
+        assert_eq!(break_lines("ab cd", 6.0, WordBreaking::Normal, wide_space), vec!["ab", "cd"]);
+    }
+}
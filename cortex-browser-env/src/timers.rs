@@ -0,0 +1,290 @@
+//! Timers
+//! `setTimeout`, `setInterval`, `clearTimeout` and `clearInterval` on the
+//! page's clock
+//!
+//! A timer is a task of the page's event loop that becomes ready once the
+//! page's `Clock` reaches its time, so `setTimeout(f, 1000)` runs `f` after
+//! `Page::advance_time` of a second, however long the test really took. A
+//! timer without a delay runs as soon as the page is idle. As in browsers,
+//! timers nested more than five deep wait at least 4ms, so a timer that
+//! re-arms itself cannot keep `run_until_idle` busy forever.
+//!
+//! Callbacks throwing are reported with `console.error` and do not stop
+//! the loop, like event listeners.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function, Object};
+
+use crate::event_loop::{Clock, TaskSource, TimerQueue};
+
+/// Nesting level past which timers wait at least `MIN_NESTED_DELAY_MS`
+const MAX_NESTING: u32 = 5;
+
+const MIN_NESTED_DELAY_MS: f64 = 4.0;
+
+struct Timer {
+    /// Delay between runs of a `setInterval`, `None` for a `setTimeout`
+    interval: Option<f64>,
+    /// How many timers deep it was set
+    nesting: u32,
+}
+
+struct TimerState {
+    queue: TimerQueue<u32>,
+    timers: HashMap<u32, Timer>,
+    next_id: u32,
+    /// Nesting level of the timer running now, 0 outside timers
+    nesting: u32,
+}
+
+/// A page's timers
+///
+/// Clones share timers, so the page's event loop runs the ones its
+/// scripts set.
+#[derive(Clone)]
+pub struct PageTimers {
+    state: Rc<RefCell<TimerState>>,
+}
+
+impl PageTimers {
+    pub fn new(clock: Clock) -> Self {
+        let state = TimerState { queue: TimerQueue::new(clock), timers: HashMap::new(), next_id: 0, nesting: 0 };
+        PageTimers { state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// Set a timer running after `delay_ms`, then every `delay_ms` if it
+    /// `repeats`, returning its id
+    fn set(&self, delay_ms: f64, repeats: bool) -> u32 {
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        let id = state.next_id;
+        let nesting = state.nesting + 1;
+        let delay_ms = clamp_delay(delay_ms, nesting);
+        state.timers.insert(id, Timer { interval: repeats.then_some(delay_ms), nesting });
+        state.queue.schedule(delay_ms, id);
+        id
+    }
+
+    /// Cancel timer `id`; unknown ids are ignored
+    fn clear(&self, id: u32) {
+        let mut state = self.state.borrow_mut();
+        if state.timers.remove(&id).is_some() {
+            state.queue.retain(|&waiting| waiting != id);
+        }
+    }
+
+    /// Timers set and not yet run or cleared, intervals included
+    pub fn active(&self) -> usize {
+        self.state.borrow().timers.len()
+    }
+}
+
+impl fmt::Debug for PageTimers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PageTimers({} active)", self.active())
+    }
+}
+
+/// `delay_ms` as browsers apply it: not negative, and at least 4ms past
+/// the fifth nested timer
+fn clamp_delay(delay_ms: f64, nesting: u32) -> f64 {
+    let delay_ms = if delay_ms > 0.0 { delay_ms } else { 0.0 };
+    if nesting > MAX_NESTING {
+        delay_ms.max(MIN_NESTED_DELAY_MS)
+    } else {
+        delay_ms
+    }
+}
+
+impl TaskSource for PageTimers {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let (id, repeats) = {
+            let mut state = self.state.borrow_mut();
+            let Some(id) = state.queue.take_due() else {
+                return Ok(false);
+            };
+            let Some(timer) = state.timers.get_mut(&id) else {
+                return Ok(true);
+            };
+            let (interval, nesting) = (timer.interval, timer.nesting);
+            match interval {
+                Some(interval) => {
+                    // Each run of an interval counts as one more level
+                    timer.nesting += 1;
+                    let delay_ms = clamp_delay(interval, timer.nesting);
+                    state.queue.schedule(delay_ms, id);
+                }
+                None => {
+                    state.timers.remove(&id);
+                }
+            }
+            state.nesting = nesting;
+            (id, interval.is_some())
+        };
+        let native: Object = ctx.globals().get("__cortexTimers")?;
+        let fire: Function = native.get("fire")?;
+        let fired = fire.call::<_, ()>((id, repeats));
+        self.state.borrow_mut().nesting = 0;
+        fired.map(|_| true)
+    }
+
+    fn pending_timers(&self) -> usize {
+        self.active()
+    }
+
+    fn clear_tasks(&self) {
+        let mut state = self.state.borrow_mut();
+        state.timers.clear();
+        state.queue.clear();
+    }
+
+    fn next_due_ms(&self) -> Option<f64> {
+        self.state.borrow().queue.next_due_ms()
+    }
+}
+
+/// The timer globals, over the natives of `install_timers`
+const TIMERS_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexTimers;
+    const callbacks = new Map();
+
+    function set(repeats, handler, delay, args) {
+        if (typeof handler !== "function") {
+            throw new TypeError("Timer handlers must be functions");
+        }
+        const id = native.set(Number(delay) || 0, repeats);
+        callbacks.set(id, { handler, args });
+        return id;
+    }
+    function clear(id) {
+        id = Number(id);
+        callbacks.delete(id);
+        native.clear(id);
+    }
+
+    native.fire = (id, repeats) => {
+        const callback = callbacks.get(id);
+        if (!callback) return;
+        if (!repeats) callbacks.delete(id);
+        try {
+            callback.handler.apply(globalThis, callback.args);
+        } catch (error) {
+            console.error("Uncaught", error);
+        }
+    };
+
+    globalThis.setTimeout = (handler, delay, ...args) => set(false, handler, delay, args);
+    globalThis.setInterval = (handler, delay, ...args) => set(true, handler, delay, args);
+    globalThis.clearTimeout = clear;
+    globalThis.clearInterval = clear;
+})();
+"#;
+
+/// Install `setTimeout`, `setInterval`, `clearTimeout` and `clearInterval`
+/// on `timers`
+///
+/// Callbacks only run when `timers`, as a `TaskSource` of the page's event
+/// loop, runs them.
+pub fn install_timers<'js>(ctx: &Ctx<'js>, timers: &PageTimers) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let page = timers.clone();
+    native.set("set", Function::new(ctx.clone(), move |delay_ms: f64, repeats: bool| page.set(delay_ms, repeats))?)?;
+    let page = timers.clone();
+    native.set("clear", Function::new(ctx.clone(), move |id: f64| page.clear(id as u32))?)?;
+    ctx.globals().set("__cortexTimers", native)?;
+    ctx.eval::<(), _>(TIMERS_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_loop::{ready, EventLoop};
+    use rquickjs::{AsyncContext, AsyncRuntime};
+
+    /// A context with timers on a fresh clock, its event loop and the clock
+    fn setup() -> (AsyncRuntime, AsyncContext, EventLoop, Clock, PageTimers) {
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let clock = Clock::new();
+        let timers = PageTimers::new(clock.clone());
+        ready(context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            install_timers(&ctx, &timers).unwrap();
+        }));
+        let event_loop = EventLoop::new().with_clock(clock.clone()).with_source(Rc::new(timers.clone()));
+        (runtime, context, event_loop, clock, timers)
+    }
+
+    fn log(context: &AsyncContext) -> String {
+        ready(context.with(|ctx| ctx.eval("log.join(' ')").unwrap()))
+    }
+
+    #[test]
+    fn test_timers_run_when_the_clock_reaches_them() {
+        // Given: Timeouts, one canceled, and an interval
+        let (runtime, context, event_loop, clock, timers) = setup();
+        ready(context.with(|ctx| {
+            ctx.eval::<(), _>(
+                r#"
+                globalThis.log = [];
+                setTimeout((a, b) => log.push(`late ${a}${b}`), 100, 1, 2);
+                setTimeout(() => log.push("now"));
+                clearTimeout(setTimeout(() => log.push("canceled"), 10));
+                globalThis.tick = setInterval(() => log.push("tick"), 40);
+                "#,
+            )
+            .unwrap()
+        }));
+
+        // When: The page idles, then its clock moves 100ms
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        assert_eq!(log(&context), "now");
+        clock.advance(100.0);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        // Then: Everything due ran, once per due time, with its arguments,
+        // leaving only the interval set
+        assert_eq!(log(&context), "now tick late 12");
+        assert_eq!(timers.active(), 1);
+        ready(context.with(|ctx| ctx.eval::<(), _>("clearInterval(tick)").unwrap()));
+        assert_eq!(timers.active(), 0);
+    }
+
+    #[test]
+    fn test_nested_timers_are_clamped_and_errors_reported() {
+        // Given: A timer re-arming itself with no delay, and one throwing
+        let (runtime, context, event_loop, clock, _) = setup();
+        ready(context.with(|ctx| {
+            ctx.eval::<(), _>(
+                r#"
+                globalThis.log = [];
+                const again = () => { log.push("run"); setTimeout(again); };
+                setTimeout(again);
+                setTimeout(() => { throw new Error("boom"); });
+                "#,
+            )
+            .unwrap()
+        }));
+
+        // When: The page idles without its clock moving
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        // Then: Five levels ran, the sixth waits 4ms, and the throw did
+        // not stop the loop
+        let runs = || ready(context.with(|ctx| ctx.eval::<usize, _>("log.length").unwrap()));
+        assert_eq!(runs(), 5);
+        clock.advance(4.0);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        assert_eq!(runs(), 6);
+        assert_eq!(event_loop.next_due_ms(), Some(8.0));
+    }
+}
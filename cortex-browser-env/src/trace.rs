@@ -0,0 +1,212 @@
+//! Performance Tracing
+//! How long each pipeline stage took, per run and per test, written as
+//! Chrome trace events for about:tracing or Perfetto
+//!
+//! A `Tracer` records a span around each stage: parsing, style, layout,
+//! paint, JavaScript and screenshot encoding, plus one span per test. Spans
+//! nest as the calls do, so a test's span contains the layouts and scripts
+//! it ran. Clones share their events, so a browser's pages all record into
+//! the tracer it was given. A disabled tracer, the default, records
+//! nothing and only runs what it is handed.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::reporters::json_string;
+
+/// A stage of loading, running or rendering a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TraceStage {
+    Parse,
+    Style,
+    Layout,
+    Paint,
+    Js,
+    Encode,
+    Test,
+}
+
+impl TraceStage {
+    pub const ALL: [TraceStage; 7] =
+        [TraceStage::Parse, TraceStage::Style, TraceStage::Layout, TraceStage::Paint, TraceStage::Js, TraceStage::Encode, TraceStage::Test];
+
+    /// The event category trace viewers group and filter by
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraceStage::Parse => "parse",
+            TraceStage::Style => "style",
+            TraceStage::Layout => "layout",
+            TraceStage::Paint => "paint",
+            TraceStage::Js => "js",
+            TraceStage::Encode => "encode",
+            TraceStage::Test => "test",
+        }
+    }
+}
+
+impl fmt::Display for TraceStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One finished span
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    pub stage: TraceStage,
+    pub name: String,
+    /// When the span began, from the tracer's creation
+    pub start: Duration,
+    pub duration: Duration,
+    /// Whether it ran inside another span of the same stage, whose
+    /// duration already includes it
+    pub nested: bool,
+}
+
+#[derive(Debug)]
+struct TraceLog {
+    origin: Instant,
+    events: Vec<TraceEvent>,
+    /// Stages of the spans open now, innermost last
+    open: Vec<TraceStage>,
+}
+
+/// Records pipeline spans; see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    log: Option<Rc<RefCell<TraceLog>>>,
+}
+
+impl Tracer {
+    /// A tracer that records, timing spans from now
+    pub fn new() -> Self {
+        Tracer { log: Some(Rc::new(RefCell::new(TraceLog { origin: Instant::now(), events: Vec::new(), open: Vec::new() }))) }
+    }
+
+    /// A tracer that records nothing
+    pub fn disabled() -> Self {
+        Tracer::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.log.is_some()
+    }
+
+    /// Run `f` as a span of `stage` called `name`
+    pub fn span<T>(&self, stage: TraceStage, name: &str, f: impl FnOnce() -> T) -> T {
+        let Some(log) = &self.log else { return f() };
+        let (start, nested) = {
+            let mut log = log.borrow_mut();
+            let nested = log.open.contains(&stage);
+            log.open.push(stage);
+            (log.origin.elapsed(), nested)
+        };
+        let value = f();
+        let mut log = log.borrow_mut();
+        let duration = log.origin.elapsed().saturating_sub(start);
+        log.open.pop();
+        log.events.push(TraceEvent { stage, name: name.to_string(), start, duration, nested });
+        value
+    }
+
+    /// The spans recorded so far, in the order they finished
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.log.as_ref().map(|log| log.borrow().events.clone()).unwrap_or_default()
+    }
+
+    /// Total time spent in each stage that ran, counting nested spans of a
+    /// stage once
+    pub fn breakdown(&self) -> Vec<(TraceStage, Duration)> {
+        let events = self.events();
+        TraceStage::ALL
+            .into_iter()
+            .filter_map(|stage| {
+                let spans: Vec<_> = events.iter().filter(|e| e.stage == stage && !e.nested).collect();
+                (!spans.is_empty()).then(|| (stage, spans.iter().map(|e| e.duration).sum()))
+            })
+            .collect()
+    }
+
+    /// The breakdown as text, one stage per line
+    pub fn format_breakdown(&self) -> String {
+        self.breakdown().iter().map(|(stage, duration)| format!("{:<8}{:>10.3}ms\n", stage.as_str(), duration.as_secs_f64() * 1000.0)).collect()
+    }
+
+    /// The spans in the Chrome trace-event format, as complete (`"X"`)
+    /// events with microsecond times
+    pub fn to_chrome_json(&self) -> String {
+        let events: Vec<String> = self
+            .events()
+            .iter()
+            .map(|event| {
+                format!(
+                    "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1}}",
+                    json_string(&event.name),
+                    event.stage.as_str(),
+                    event.start.as_secs_f64() * 1e6,
+                    event.duration.as_secs_f64() * 1e6
+                )
+            })
+            .collect();
+        format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n", events.join(",\n"))
+    }
+
+    /// Write `to_chrome_json` to `path`
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_chrome_json())
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Json;
+
+    #[test]
+    fn test_spans_nest_and_sum_once() {
+        // Given: A layout run inside another, inside a test
+        let tracer = Tracer::new();
+        let value = tracer.span(TraceStage::Test, "Form > \"submits\"", || {
+            tracer.span(TraceStage::Layout, "Layout", || tracer.span(TraceStage::Layout, "Layout", || std::thread::sleep(Duration::from_millis(2))));
+            tracer.span(TraceStage::Js, "main.js", || 42)
+        });
+        assert_eq!(value, 42);
+
+        // Then: Events finish innermost first and the inner layout counts once
+        let events = tracer.events();
+        let names: Vec<_> = events.iter().map(|e| (e.stage, e.nested)).collect();
+        assert_eq!(names, [(TraceStage::Layout, true), (TraceStage::Layout, false), (TraceStage::Js, false), (TraceStage::Test, false)]);
+        let breakdown = tracer.breakdown();
+        assert_eq!(breakdown.iter().map(|(stage, _)| *stage).collect::<Vec<_>>(), [TraceStage::Layout, TraceStage::Js, TraceStage::Test]);
+        assert_eq!(breakdown[0].1, events[1].duration);
+        assert!(events[3].start <= events[1].start && events[1].duration <= events[3].duration);
+    }
+
+    #[test]
+    fn test_chrome_json_is_valid() {
+        let tracer = Tracer::new();
+        tracer.span(TraceStage::Parse, "Parse \"page\"", || ());
+        let json = Json::parse(&tracer.to_chrome_json()).expect("valid JSON");
+        let Some(Json::Array(events)) = json.get("traceEvents") else { panic!("no traceEvents") };
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].get("name").and_then(Json::as_str), Some("Parse \"page\""));
+        assert_eq!(events[0].get("cat").and_then(Json::as_str), Some("parse"));
+        assert_eq!(events[0].get("ph").and_then(Json::as_str), Some("X"));
+        assert!(events[0].get("dur").and_then(Json::as_number).is_some());
+    }
+
+    #[test]
+    fn test_disabled_tracer_records_nothing() {
+        let tracer = Tracer::disabled();
+        assert_eq!(tracer.span(TraceStage::Paint, "Paint", || 7), 7);
+        assert!(tracer.events().is_empty());
+        assert_eq!(tracer.to_chrome_json(), "{\"traceEvents\":[\n\n],\"displayTimeUnit\":\"ms\"}\n");
+    }
+}
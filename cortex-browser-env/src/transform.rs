@@ -0,0 +1,235 @@
+//! CSS Transforms
+//! Parsing `transform` and `transform-origin`, and the matrices they give
+//! laid-out boxes
+//!
+//! A transform moves how a box and its descendants paint without changing
+//! layout, so the renderer wraps their paint commands in a
+//! `PaintCommand::PushTransform`, and geometry that scripts and hit tests
+//! see goes through `bounding_client_rect`. Matrices map page coordinates
+//! before the transform to page coordinates after it; an element's matrix
+//! already includes its `transform-origin`.
+
+use raqote::{Point, Transform};
+
+use crate::css::{CSSValue, ComputedStyle};
+use crate::dom::Document;
+use crate::geometry::Rect;
+
+/// One function of a `transform` list
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformFunction {
+    /// `matrix(a, b, c, d, e, f)`
+    Matrix([f32; 6]),
+    /// Percentages are of the border box
+    Translate(CSSValue, CSSValue),
+    Scale(f32, f32),
+    /// Clockwise, in radians
+    Rotate(f32),
+    /// Angles in radians
+    Skew(f32, f32),
+}
+
+impl TransformFunction {
+    /// The function's matrix for a box of `size`, about the origin
+    fn to_matrix(&self, width: f32, height: f32) -> Transform {
+        match self {
+            TransformFunction::Matrix([a, b, c, d, e, f]) => Transform::new(*a, *b, *c, *d, *e, *f),
+            TransformFunction::Translate(x, y) => Transform::translation(x.as_pixels(width), y.as_pixels(height)),
+            TransformFunction::Scale(x, y) => Transform::scale(*x, *y),
+            TransformFunction::Rotate(angle) => {
+                let (sin, cos) = angle.sin_cos();
+                Transform::new(cos, sin, -sin, cos, 0.0, 0.0)
+            }
+            TransformFunction::Skew(x, y) => Transform::new(1.0, y.tan(), x.tan(), 1.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Parse a `transform` list; `none` gives an empty list
+pub fn parse_transform(value: &str) -> Option<Vec<TransformFunction>> {
+    let value = value.trim().to_ascii_lowercase();
+    if value == "none" {
+        return Some(Vec::new());
+    }
+    let mut functions = Vec::new();
+    let mut rest = value.as_str();
+    while !rest.is_empty() {
+        let open = rest.find('(')?;
+        let close = open + rest[open..].find(')')?;
+        let name = rest[..open].trim();
+        let args: Vec<&str> = rest[open + 1..close].split(',').map(str::trim).collect();
+        functions.push(parse_function(name, &args)?);
+        rest = rest[close + 1..].trim_start();
+    }
+    (!functions.is_empty()).then_some(functions)
+}
+
+fn parse_function(name: &str, args: &[&str]) -> Option<TransformFunction> {
+    let number = |arg: &str| arg.parse::<f32>().ok();
+    let length = |arg: &str| CSSValue::parse(arg).filter(|v| matches!(v, CSSValue::Pixels(_) | CSSValue::Percentage(_)));
+    let zero = CSSValue::Pixels(0.0);
+    let function = match (name, args) {
+        ("matrix", [a, b, c, d, e, f]) => {
+            TransformFunction::Matrix([number(a)?, number(b)?, number(c)?, number(d)?, number(e)?, number(f)?])
+        }
+        ("translate", [x]) | ("translatex", [x]) => TransformFunction::Translate(length(x)?, zero),
+        ("translate", [x, y]) => TransformFunction::Translate(length(x)?, length(y)?),
+        ("translatey", [y]) => TransformFunction::Translate(zero, length(y)?),
+        ("scale", [s]) => TransformFunction::Scale(number(s)?, number(s)?),
+        ("scale", [x, y]) => TransformFunction::Scale(number(x)?, number(y)?),
+        ("scalex", [x]) => TransformFunction::Scale(number(x)?, 1.0),
+        ("scaley", [y]) => TransformFunction::Scale(1.0, number(y)?),
+        ("rotate", [angle]) => TransformFunction::Rotate(parse_angle(angle)?),
+        ("skew", [x]) | ("skewx", [x]) => TransformFunction::Skew(parse_angle(x)?, 0.0),
+        ("skew", [x, y]) => TransformFunction::Skew(parse_angle(x)?, parse_angle(y)?),
+        ("skewy", [y]) => TransformFunction::Skew(0.0, parse_angle(y)?),
+        _ => return None,
+    };
+    Some(function)
+}
+
+/// An angle in radians: `deg`, `rad`, `grad`, `turn`, or a bare 0
+pub fn parse_angle(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = |unit: &str| value.strip_suffix(unit).and_then(|n| n.trim().parse::<f32>().ok());
+    number("deg")
+        .map(f32::to_radians)
+        .or_else(|| number("grad").map(|g| g * std::f32::consts::PI / 200.0))
+        .or_else(|| number("rad"))
+        .or_else(|| number("turn").map(|t| t * std::f32::consts::TAU))
+        .or_else(|| (value.parse::<f32>().ok() == Some(0.0)).then_some(0.0))
+}
+
+/// Parse `transform-origin` as (x, y) offsets into the border box;
+/// keywords map to percentages and a missing y is `center`
+pub fn parse_transform_origin(value: &str) -> Option<[CSSValue; 2]> {
+    let keyword = |token: &str| match token {
+        "left" | "top" => Some(CSSValue::Percentage(0.0)),
+        "center" => Some(CSSValue::Percentage(50.0)),
+        "right" | "bottom" => Some(CSSValue::Percentage(100.0)),
+        _ => None,
+    };
+    let tokens: Vec<String> = value.split_whitespace().map(str::to_ascii_lowercase).collect();
+    let center = CSSValue::Percentage(50.0);
+    match tokens.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [single] => match *single {
+            "top" | "bottom" => Some([center, keyword(single)?]),
+            _ => Some([keyword(single).or_else(|| CSSValue::parse(single))?, center]),
+        },
+        // The third value is a z offset, which flat rendering ignores
+        [first, second] | [first, second, _] => {
+            // Vertical keywords first, as in `top left`, swap the pair
+            let (x, y) = if matches!(*first, "top" | "bottom") || matches!(*second, "left" | "right") {
+                (second, first)
+            } else {
+                (first, second)
+            };
+            Some([keyword(x).or_else(|| CSSValue::parse(x))?, keyword(y).or_else(|| CSSValue::parse(y))?])
+        }
+        _ => None,
+    }
+}
+
+/// The matrix `style` transforms the box `border_box` by, about its
+/// `transform-origin`, or `None` when it has no transform
+pub fn element_matrix(style: &ComputedStyle, border_box: Rect) -> Option<Transform> {
+    if style.transform.is_empty() {
+        return None;
+    }
+    let [origin_x, origin_y] = style.transform_origin.clone().unwrap_or([CSSValue::Percentage(50.0), CSSValue::Percentage(50.0)]);
+    let origin_x = border_box.x + origin_x.as_pixels(border_box.width);
+    let origin_y = border_box.y + origin_y.as_pixels(border_box.height);
+    // The last function applies first, so compose from the right
+    let matrix = style
+        .transform
+        .iter()
+        .fold(Transform::identity(), |matrix, function| function.to_matrix(border_box.width, border_box.height).then(&matrix));
+    Some(Transform::translation(-origin_x, -origin_y).then(&matrix).then(&Transform::translation(origin_x, origin_y)))
+}
+
+/// The matrix a node paints with: its own transform followed by each
+/// ancestor's
+pub fn accumulated_matrix(document: &Document, styles: &[ComputedStyle], node: usize) -> Transform {
+    let mut matrix = Transform::identity();
+    let mut current = Some(node);
+    while let Some(idx) = current {
+        let own = document.layout(idx).zip(styles.get(idx)).and_then(|(layout, style)| element_matrix(style, layout.border_box()));
+        if let Some(own) = own {
+            matrix = matrix.then(&own);
+        }
+        current = document.nodes[idx].parent;
+    }
+    matrix
+}
+
+/// The smallest rectangle holding `rect` after `matrix`
+pub fn transform_rect(matrix: &Transform, rect: Rect) -> Rect {
+    let corners = [(rect.x, rect.y), (rect.right(), rect.y), (rect.x, rect.bottom()), (rect.right(), rect.bottom())]
+        .map(|(x, y)| matrix.transform_point(Point::new(x, y)));
+    let (min_x, max_x) = corners.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| (lo.min(p.x), hi.max(p.x)));
+    let (min_y, max_y) = corners.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| (lo.min(p.y), hi.max(p.y)));
+    Rect::new(min_x, min_y, max_x - min_x, max_y - min_y)
+}
+
+/// A laid-out node's border box as it appears on screen, as
+/// `getBoundingClientRect` reports it: transformed by the node and its
+/// ancestors
+pub fn bounding_client_rect(document: &Document, styles: &[ComputedStyle], node: usize) -> Option<Rect> {
+    let border_box = document.layout(node)?.border_box();
+    Some(transform_rect(&accumulated_matrix(document, styles, node), border_box))
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(transform: &str, origin: Option<&str>) -> ComputedStyle {
+        ComputedStyle {
+            transform: parse_transform(transform).unwrap(),
+            transform_origin: origin.and_then(parse_transform_origin),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_transform_functions() {
+        assert_eq!(parse_transform("none"), Some(vec![]));
+        assert_eq!(
+            parse_transform("translate(-50%, 10px) rotate(0.25turn) scaleY(2)"),
+            Some(vec![
+                TransformFunction::Translate(CSSValue::Percentage(-50.0), CSSValue::Pixels(10.0)),
+                TransformFunction::Rotate(std::f32::consts::FRAC_PI_2),
+                TransformFunction::Scale(1.0, 2.0),
+            ])
+        );
+        assert_eq!(parse_transform("rotate(90)"), None, "Angles need a unit");
+        assert_eq!(parse_transform("wobble(1)"), None);
+        assert_eq!(parse_transform_origin("top left"), Some([CSSValue::Percentage(0.0), CSSValue::Percentage(0.0)]));
+        assert_eq!(parse_transform_origin("10px bottom"), Some([CSSValue::Pixels(10.0), CSSValue::Percentage(100.0)]));
+    }
+
+    #[test]
+    fn test_element_matrix_uses_origin() {
+        let border_box = Rect::new(100.0, 100.0, 100.0, 50.0);
+
+        // Centered by default, so scaling grows the box evenly
+        let scaled = element_matrix(&style("scale(2)", None), border_box).unwrap();
+        assert!(transform_rect(&scaled, border_box).approx_eq(&Rect::new(50.0, 75.0, 200.0, 100.0), 0.001));
+
+        // Rotating a quarter turn about the top-left corner swings the box left
+        let rotated = element_matrix(&style("rotate(90deg)", Some("left top")), border_box).unwrap();
+        assert!(transform_rect(&rotated, border_box).approx_eq(&Rect::new(50.0, 100.0, 50.0, 100.0), 0.001));
+
+        // Functions apply right to left: scale, then move by the border box
+        let moved = element_matrix(&style("translateX(100%) scale(0.5)", None), border_box).unwrap();
+        assert!(transform_rect(&moved, border_box).approx_eq(&Rect::new(225.0, 112.5, 50.0, 25.0), 0.001));
+
+        let matrix = element_matrix(&style("matrix(1, 0, 0, 1, 5, -5)", None), border_box).unwrap();
+        assert_eq!(transform_rect(&matrix, border_box), Rect::new(105.0, 95.0, 100.0, 50.0));
+        assert_eq!(element_matrix(&style("none", None), border_box), None);
+    }
+}
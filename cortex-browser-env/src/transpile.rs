@@ -0,0 +1,1807 @@
+//! TypeScript and JSX Transpilation
+//! Strips TypeScript syntax and compiles JSX to factory calls so `.ts`,
+//! `.tsx` and `.jsx` files run without an external build
+//!
+//! This is a stripper, not a type checker: annotations, generics, `as`
+//! casts, `this` parameters, interfaces, type aliases, overloads and
+//! type-only imports are removed; enums and constructor parameter
+//! properties are compiled. Output keeps every line where it was, so stack
+//! traces point at the original source. Namespaces, decorators and
+//! `<T>expr` casts are not supported.
+
+use std::mem;
+use std::path::Path;
+
+use crate::error::BrowserError;
+
+/// What a source file needs compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    TypeScript,
+    Tsx,
+    Jsx,
+}
+
+impl SourceKind {
+    /// `.ts`, `.mts`, `.tsx` or `.jsx`; plain JavaScript gives `None`
+    pub fn from_path(path: &Path) -> Option<SourceKind> {
+        match path.extension()?.to_str()? {
+            "ts" | "mts" | "cts" => Some(SourceKind::TypeScript),
+            "tsx" => Some(SourceKind::Tsx),
+            "jsx" => Some(SourceKind::Jsx),
+            _ => None,
+        }
+    }
+
+    fn typescript(&self) -> bool {
+        matches!(self, SourceKind::TypeScript | SourceKind::Tsx)
+    }
+
+    fn jsx(&self) -> bool {
+        matches!(self, SourceKind::Tsx | SourceKind::Jsx)
+    }
+}
+
+/// How JSX compiles; a file's `/** @jsx h */` and `/** @jsxFrag Fragment */`
+/// pragmas override these
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranspileOptions {
+    /// Called as `factory(type, props, ...children)`
+    pub jsx_factory: String,
+    /// Type of `<>...</>` fragments
+    pub jsx_fragment: String,
+}
+
+impl Default for TranspileOptions {
+    fn default() -> Self {
+        TranspileOptions { jsx_factory: "React.createElement".to_string(), jsx_fragment: "React.Fragment".to_string() }
+    }
+}
+
+impl TranspileOptions {
+    pub fn with_jsx_factory(mut self, factory: &str, fragment: &str) -> Self {
+        self.jsx_factory = factory.to_string();
+        self.jsx_fragment = fragment.to_string();
+        self
+    }
+}
+
+/// Compile `source` as the file at `path` says; other files pass through
+pub fn transpile_file(source: &str, path: &Path, options: &TranspileOptions) -> Result<String, BrowserError> {
+    match SourceKind::from_path(path) {
+        Some(kind) => transpile(source, kind, options).map_err(|e| BrowserError::ParseError(format!("{}: {}", path.display(), e))),
+        None => Ok(source.to_string()),
+    }
+}
+
+/// Compile TypeScript and JSX down to JavaScript
+///
+/// Errors name the line they were found on.
+pub fn transpile(source: &str, kind: SourceKind, options: &TranspileOptions) -> Result<String, String> {
+    let mut options = options.clone();
+    if let Some(factory) = pragma(source, "@jsx") {
+        options.jsx_factory = factory;
+    }
+    if let Some(fragment) = pragma(source, "@jsxFrag") {
+        options.jsx_fragment = fragment;
+    }
+    let mut transpiler = Transpiler {
+        src: source.chars().collect(),
+        pos: 0,
+        out: String::with_capacity(source.len()),
+        kind,
+        options,
+        frames: vec![Frame::new(FrameKind::Top)],
+        prev: Tok::None,
+        newline_before: false,
+        classes: Vec::new(),
+        pending_class_body: false,
+        pending_props: None,
+        function_start: None,
+        export_start: None,
+    };
+    transpiler.code()?;
+    Ok(transpiler.out)
+}
+
+/// The value of a `@jsx`-style pragma in a block comment
+fn pragma(source: &str, name: &str) -> Option<String> {
+    let mut rest = source;
+    while let Some(start) = rest.find("/*") {
+        let end = rest[start..].find("*/").map_or(rest.len(), |end| start + end);
+        let comment = &rest[start + 2..end];
+        let mut words = comment.split(|c: char| c.is_whitespace() || c == '*').filter(|w| !w.is_empty());
+        while let Some(word) = words.next() {
+            if word == name {
+                return words.next().map(str::to_string);
+            }
+        }
+        rest = &rest[end.min(rest.len())..];
+        if rest.len() < 2 {
+            break;
+        }
+        rest = &rest[2..];
+    }
+    None
+}
+
+/// The last significant token, which decides what `/`, `<`, `!` and `(`
+/// mean
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    None,
+    Ident(String),
+    Keyword(String),
+    Literal,
+    Punct(String),
+    /// `?` of an optional parameter or member, already stripped
+    Optional,
+}
+
+/// Words after which an expression starts rather than ends
+const KEYWORDS: [&str; 25] = [
+    "break", "case", "catch", "continue", "default", "delete", "do", "else", "export", "extends", "for", "function",
+    "if", "import", "in", "instanceof", "new", "of", "return", "switch", "throw", "typeof", "void", "while", "yield",
+];
+
+/// Words that precede a parenthesized condition, not parameters
+const CONTROL: [&str; 5] = ["if", "for", "while", "switch", "with"];
+
+const MODIFIERS: [&str; 6] = ["public", "private", "protected", "readonly", "override", "declare"];
+
+impl Tok {
+    /// Whether an expression just ended, so `/` divides, `<` compares and
+    /// `!` asserts non-null
+    fn ends_expression(&self) -> bool {
+        match self {
+            Tok::Ident(_) | Tok::Literal => true,
+            Tok::Punct(p) => matches!(p.as_str(), ")" | "]"),
+            _ => false,
+        }
+    }
+
+    fn is_punct(&self, text: &str) -> bool {
+        matches!(self, Tok::Punct(p) if p == text)
+    }
+
+    fn is_keyword(&self, word: &str) -> bool {
+        matches!(self, Tok::Keyword(k) if k == word)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Top,
+    /// Parameter list
+    Params,
+    /// Any other parentheses
+    Paren,
+    Bracket,
+    Brace,
+    ClassBody,
+    /// `{` of `import { ... }` or `export { ... }`
+    Specifiers,
+    /// `${` of a template literal, or `{` of a JSX expression
+    Interpolation,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    kind: FrameKind,
+    /// Open `?` of conditional expressions
+    ternary: u32,
+    /// Inside `let`/`const`/`var` bindings
+    declaration: bool,
+    /// Inside an initializer, where `:` is not an annotation
+    initializer: bool,
+    /// After `case`/`default`, until its `:`
+    case_label: bool,
+    /// Output position of the current class member
+    member_start: usize,
+    /// A method's parameter list (or the function declared at
+    /// `function_start`), so a missing body makes it an overload signature
+    signature_start: Option<usize>,
+    /// A constructor's parameter list, where modifiers declare properties
+    constructor: bool,
+    /// Constructor parameters that declare properties
+    param_props: Vec<String>,
+    /// `this.x = x` assignments waiting for `super(...)` to return
+    inject_after_super: Option<String>,
+    /// This class extends another
+    extends: bool,
+    /// The arguments of `super(...)`
+    super_call: bool,
+}
+
+impl Frame {
+    fn new(kind: FrameKind) -> Self {
+        Frame {
+            kind,
+            ternary: 0,
+            declaration: false,
+            initializer: false,
+            case_label: false,
+            member_start: 0,
+            signature_start: None,
+            constructor: false,
+            param_props: Vec::new(),
+            inject_after_super: None,
+            extends: false,
+            super_call: false,
+        }
+    }
+}
+
+/// Where a type being skipped appears, which decides where it ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TypeContext {
+    /// `x: T` of a parameter, binding or class field
+    Annotation,
+    /// `): T` before a body or `=>`
+    Return,
+    /// `x as T`, `x satisfies T`
+    Cast,
+    /// `type X = T`
+    Alias,
+}
+
+struct Transpiler {
+    src: Vec<char>,
+    pos: usize,
+    out: String,
+    kind: SourceKind,
+    options: TranspileOptions,
+    frames: Vec<Frame>,
+    prev: Tok,
+    newline_before: bool,
+    /// Classes whose body has not opened yet: whether each extends another
+    classes: Vec<bool>,
+    pending_class_body: bool,
+    /// Parameter properties of a constructor whose body opens next
+    pending_props: Option<Vec<String>>,
+    /// Output position of a `function` declaration, for dropping overloads
+    function_start: Option<usize>,
+    /// Output position of the `export` keyword just written
+    export_start: Option<usize>,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_ident_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+impl Transpiler {
+    // ------------------------------------------------------------------
+    // Scanning
+    // ------------------------------------------------------------------
+
+    fn peek(&self) -> Option<char> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.src.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn line(&self) -> usize {
+        self.src[..self.pos.min(self.src.len())].iter().filter(|&&c| c == '\n').count() + 1
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} on line {}", message, self.line())
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("the top frame is never popped")
+    }
+
+    fn frame_kind(&self) -> FrameKind {
+        self.frames.last().map_or(FrameKind::Top, |f| f.kind)
+    }
+
+    /// Position after whitespace and comments from `pos`
+    fn skip_trivia_from(&self, mut pos: usize) -> usize {
+        loop {
+            match self.src.get(pos) {
+                Some(c) if c.is_whitespace() => pos += 1,
+                Some('/') if self.src.get(pos + 1) == Some(&'/') => {
+                    while self.src.get(pos).is_some_and(|&c| c != '\n') {
+                        pos += 1;
+                    }
+                }
+                Some('/') if self.src.get(pos + 1) == Some(&'*') => {
+                    pos += 2;
+                    while pos < self.src.len() && !(self.src[pos] == '*' && self.src.get(pos + 1) == Some(&'/')) {
+                        pos += 1;
+                    }
+                    pos = (pos + 2).min(self.src.len());
+                }
+                _ => return pos,
+            }
+        }
+    }
+
+    fn next_significant(&self) -> Option<char> {
+        self.src.get(self.skip_trivia_from(self.pos)).copied()
+    }
+
+    /// The word starting at `pos`, if any
+    fn word_at(&self, pos: usize) -> Option<String> {
+        if !self.src.get(pos).is_some_and(|&c| is_ident_start(c)) {
+            return None;
+        }
+        let end = (pos..self.src.len()).find(|&i| !is_ident_part(self.src[i])).unwrap_or(self.src.len());
+        Some(self.src[pos..end].iter().collect())
+    }
+
+    /// Position just past the bracket matching the one at `open`
+    fn matching_close(&self, open: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut pos = open;
+        while pos < self.src.len() {
+            pos = self.skip_trivia_from(pos);
+            match self.src.get(pos)? {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(pos + 1);
+                    }
+                }
+                quote @ ('"' | '\'' | '`') => {
+                    let quote = *quote;
+                    pos += 1;
+                    while pos < self.src.len() && self.src[pos] != quote {
+                        if self.src[pos] == '\\' {
+                            pos += 1;
+                        }
+                        pos += 1;
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        None
+    }
+
+    /// Copy whitespace and comments, noting whether a line ended
+    fn trivia(&mut self) {
+        let end = self.skip_trivia_from(self.pos);
+        self.newline_before = self.src[self.pos..end].contains(&'\n');
+        self.out.extend(&self.src[self.pos..end]);
+        self.pos = end;
+    }
+
+    /// Consume up to `end`, writing only its line breaks
+    fn strip_to(&mut self, end: usize) {
+        let newlines = self.src[self.pos..end].iter().filter(|&&c| c == '\n').count();
+        self.out.extend(std::iter::repeat_n('\n', newlines));
+        self.pos = end;
+    }
+
+    /// Drop output written since `start`, keeping its line breaks
+    fn truncate_output(&mut self, start: usize) {
+        let newlines = self.out[start..].matches('\n').count();
+        self.out.truncate(start);
+        self.out.extend(std::iter::repeat_n('\n', newlines));
+    }
+
+    fn copy(&mut self, len: usize) -> String {
+        let text: String = self.src[self.pos..self.pos + len].iter().collect();
+        self.out.push_str(&text);
+        self.pos += len;
+        text
+    }
+
+    fn at_statement_start(&self) -> bool {
+        self.newline_before
+            || matches!(&self.prev, Tok::None)
+            || [";", "{", "}"].iter().any(|p| self.prev.is_punct(p))
+            || self.prev.is_keyword("export")
+            || self.prev.is_keyword("default")
+    }
+
+    // ------------------------------------------------------------------
+    // Code
+    // ------------------------------------------------------------------
+
+    /// Translate until the frame this call started in closes, or the end
+    fn code(&mut self) -> Result<(), String> {
+        let depth = self.frames.len();
+        loop {
+            self.trivia();
+            let Some(c) = self.peek() else {
+                return if depth == 1 { Ok(()) } else { Err(self.error("Unexpected end of file")) };
+            };
+
+            // An identifier after a line break starts a new class member
+            if self.newline_before && self.frame_kind() == FrameKind::ClassBody && self.prev.ends_expression() {
+                let start = self.out.len();
+                let frame = self.frame();
+                frame.initializer = false;
+                frame.member_start = start;
+            }
+
+            match c {
+                '"' | '\'' => self.string(),
+                '`' => self.template()?,
+                '0'..='9' => self.number(),
+                '.' if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) => self.number(),
+                c if is_ident_start(c) => self.identifier()?,
+                '#' if self.peek_at(1).is_some_and(is_ident_start) => {
+                    self.copy(1);
+                    let word = self.word_at(self.pos).unwrap_or_default();
+                    self.copy(word.chars().count());
+                    self.prev = Tok::Ident(word);
+                }
+                '/' if !self.prev.ends_expression() => self.regex(),
+                '<' => self.less_than()?,
+                '(' | '[' | '{' => self.open(c)?,
+                ')' | ']' | '}' => {
+                    if self.frames.len() == depth && depth > 1 {
+                        // The caller consumes the closing bracket
+                        return Ok(());
+                    }
+                    self.close(c)?;
+                }
+                ':' => self.colon()?,
+                '?' => self.question()?,
+                '!' if self.kind.typescript()
+                    && self.prev.ends_expression()
+                    && !self.newline_before
+                    && self.out.ends_with(|c: char| !c.is_whitespace())
+                    && self.peek_at(1) != Some('=') =>
+                {
+                    // Non-null assertion
+                    self.pos += 1;
+                }
+                '=' => self.equals()?,
+                ',' => {
+                    self.copy(1);
+                    let frame = self.frame();
+                    if frame.declaration {
+                        frame.initializer = false;
+                    }
+                    self.prev = Tok::Punct(",".to_string());
+                }
+                ';' => {
+                    self.copy(1);
+                    let start = self.out.len();
+                    let frame = self.frame();
+                    frame.declaration = false;
+                    frame.initializer = false;
+                    frame.member_start = start;
+                    self.prev = Tok::Punct(";".to_string());
+                }
+                _ => self.punctuation(),
+            }
+        }
+    }
+
+    fn string(&mut self) {
+        let quote = self.peek().unwrap_or('"');
+        let start = self.pos;
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == '\\' {
+                self.pos += 1;
+            } else if c == quote || c == '\n' {
+                break;
+            }
+        }
+        self.pos = self.pos.min(self.src.len());
+        self.out.extend(&self.src[start..self.pos]);
+        self.prev = Tok::Literal;
+    }
+
+    fn template(&mut self) -> Result<(), String> {
+        self.copy(1);
+        loop {
+            match self.peek() {
+                None => return Err(self.error("Unterminated template literal")),
+                Some('`') => {
+                    self.copy(1);
+                    break;
+                }
+                Some('\\') => {
+                    let len = if self.peek_at(1).is_some() { 2 } else { 1 };
+                    self.copy(len);
+                }
+                Some('$') if self.peek_at(1) == Some('{') => {
+                    self.copy(2);
+                    self.frames.push(Frame::new(FrameKind::Interpolation));
+                    self.prev = Tok::Punct("${".to_string());
+                    self.code()?;
+                    self.frames.pop();
+                    self.copy(1);
+                }
+                Some(_) => {
+                    self.copy(1);
+                }
+            }
+        }
+        self.prev = Tok::Literal;
+        Ok(())
+    }
+
+    fn number(&mut self) {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '.' || c == '_') {
+            self.pos += 1;
+        }
+        self.out.extend(&self.src[start..self.pos]);
+        self.prev = Tok::Literal;
+    }
+
+    fn regex(&mut self) {
+        let start = self.pos;
+        self.pos += 1;
+        let mut in_class = false;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                '[' => in_class = true,
+                ']' => in_class = false,
+                '/' if !in_class => break,
+                '\n' => break,
+                _ => {}
+            }
+        }
+        while self.peek().is_some_and(is_ident_part) {
+            self.pos += 1;
+        }
+        self.pos = self.pos.min(self.src.len());
+        self.out.extend(&self.src[start..self.pos]);
+        self.prev = Tok::Literal;
+    }
+
+    fn punctuation(&mut self) {
+        const OPERATORS: [&str; 24] = [
+            ">>>=", "...", "===", "!==", "**=", "<<=", ">>=", ">>>", "&&=", "||=", "??=", "=>", "==", "!=", "<=",
+            ">=", "&&", "||", "??", "++", "--", "+=", "-=", "**",
+        ];
+        let len = OPERATORS.iter().find(|op| self.starts_with(op)).map_or(1, |op| op.len());
+        let text = self.copy(len);
+        self.prev = Tok::Punct(text);
+    }
+
+    fn equals(&mut self) -> Result<(), String> {
+        if self.starts_with("=>") || self.starts_with("==") {
+            self.punctuation();
+            return Ok(());
+        }
+        self.copy(1);
+        let frame = self.frame();
+        if frame.declaration || frame.kind == FrameKind::ClassBody {
+            frame.initializer = true;
+        }
+        self.prev = Tok::Punct("=".to_string());
+        Ok(())
+    }
+
+    fn question(&mut self) -> Result<(), String> {
+        if self.starts_with("?.") && !self.peek_at(2).is_some_and(|c| c.is_ascii_digit()) || self.starts_with("??") {
+            self.punctuation();
+            return Ok(());
+        }
+        // `x?: T`, `x?)` and `method?()` mark optional parameters and members
+        let next = self.src.get(self.skip_trivia_from(self.pos + 1)).copied();
+        let optional_spot = matches!(self.frame_kind(), FrameKind::Params | FrameKind::ClassBody)
+            || self.frame().declaration && !self.frame().initializer;
+        if self.kind.typescript()
+            && optional_spot
+            && self.frame().ternary == 0
+            && matches!(next, Some(':' | ',' | ')' | '=' | '(' | ';'))
+            && matches!(self.prev, Tok::Ident(_) | Tok::Punct(_))
+        {
+            self.pos += 1;
+            self.prev = Tok::Optional;
+            return Ok(());
+        }
+        self.copy(1);
+        self.frame().ternary += 1;
+        self.prev = Tok::Punct("?".to_string());
+        Ok(())
+    }
+
+    fn colon(&mut self) -> Result<(), String> {
+        let frame = self.frames.last().expect("frame");
+        let annotated = matches!(self.prev, Tok::Ident(_) | Tok::Optional)
+            || self.prev.is_punct("]")
+            || self.prev.is_punct("}");
+        let is_type = self.kind.typescript()
+            && frame.ternary == 0
+            && !frame.case_label
+            && annotated
+            && match frame.kind {
+                FrameKind::Params => true,
+                FrameKind::ClassBody => !frame.initializer,
+                _ => frame.declaration && !frame.initializer,
+            };
+        if is_type {
+            self.pos += 1;
+            self.skip_type(TypeContext::Annotation)?;
+            return Ok(());
+        }
+        let frame = self.frame();
+        if frame.ternary > 0 {
+            frame.ternary -= 1;
+        } else if frame.case_label {
+            frame.case_label = false;
+        }
+        self.copy(1);
+        self.prev = Tok::Punct(":".to_string());
+        Ok(())
+    }
+
+    /// `<`: JSX, generic parameters or arguments to strip, or less-than
+    fn less_than(&mut self) -> Result<(), String> {
+        let ts = self.kind.typescript();
+        if ts && self.prev.ends_expression() {
+            if let Some(end) = self.type_arguments_end(self.pos) {
+                let after = self.src.get(self.skip_trivia_from(end)).copied();
+                let after_word = self.word_at(self.skip_trivia_from(end));
+                // Generic declarations and calls: `f<T>(`, `class A<T> {`,
+                // `new Map<K, V>()`, `class A<T> extends B`
+                if matches!(after, Some('(' | '{' | '`')) || matches!(after_word.as_deref(), Some("extends" | "implements")) {
+                    self.strip_to(end);
+                    return Ok(());
+                }
+            }
+        }
+        if !self.prev.ends_expression() {
+            let next = self.peek_at(1);
+            let generic_arrow = ts && next.is_some_and(is_ident_start) && {
+                let word = self.word_at(self.pos + 1).unwrap_or_default();
+                let after = self.src.get(self.skip_trivia_from(self.pos + 1 + word.chars().count())).copied();
+                !self.kind.jsx() || after == Some(',') || self.word_at(self.skip_trivia_from(self.pos + 1 + word.chars().count())).as_deref() == Some("extends")
+            };
+            if generic_arrow {
+                // `<T>(x: T) => x`, or `<T,>` in TSX
+                if let Some(end) = self.type_arguments_end(self.pos) {
+                    self.strip_to(end);
+                    return Ok(());
+                }
+            }
+            if self.kind.jsx() && next.is_some_and(|c| is_ident_start(c) || c == '>') {
+                let element = self.jsx_element()?;
+                self.out.push_str(&element);
+                self.prev = Tok::Literal;
+                return Ok(());
+            }
+        }
+        self.punctuation();
+        Ok(())
+    }
+
+    /// Position past the `>` closing type arguments at `open`, if the
+    /// brackets hold nothing but types
+    fn type_arguments_end(&self, open: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut pos = open;
+        while pos < self.src.len() {
+            pos = self.skip_trivia_from(pos);
+            let c = *self.src.get(pos)?;
+            match c {
+                '<' | '(' | '[' | '{' => depth += 1,
+                '>' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(pos + 1);
+                    }
+                }
+                ')' | ']' | '}' => depth = depth.checked_sub(1).filter(|&d| d > 0)?,
+                // `=>` of function types, `=` of defaults like `<T = string>`
+                '=' if self.src.get(pos + 1) == Some(&'>') => pos += 1,
+                '=' => {}
+                '&' | '|' if self.src.get(pos + 1) == Some(&c) => return None,
+                '"' | '\'' => {
+                    pos += 1;
+                    while self.src.get(pos).is_some_and(|&q| q != c) {
+                        pos += 1;
+                    }
+                }
+                c if is_ident_part(c) || ",.|&:;?-".contains(c) => {}
+                _ => return None,
+            }
+            pos += 1;
+        }
+        None
+    }
+
+    fn open(&mut self, c: char) -> Result<(), String> {
+        let mut frame = match c {
+            '(' => Frame::new(if self.is_parameter_list() { FrameKind::Params } else { FrameKind::Paren }),
+            '[' => {
+                // Index signatures in classes are types only
+                if self.frame_kind() == FrameKind::ClassBody && self.kind.typescript() && self.is_index_signature() {
+                    let start = self.frames.last().map_or(self.out.len(), |f| f.member_start);
+                    self.skip_member()?;
+                    self.truncate_output(start);
+                    return Ok(());
+                }
+                Frame::new(FrameKind::Bracket)
+            }
+            _ if self.pending_class_body => {
+                self.pending_class_body = false;
+                let mut frame = Frame::new(FrameKind::ClassBody);
+                frame.extends = self.classes.pop().unwrap_or(false);
+                frame
+            }
+            _ if self.prev.is_keyword("import") || self.prev.is_keyword("export") || self.import_default_then_braces() => {
+                Frame::new(FrameKind::Specifiers)
+            }
+            _ => Frame::new(FrameKind::Brace),
+        };
+        frame.super_call = c == '(' && self.prev == Tok::Ident("super".to_string());
+        if c == '(' && frame.kind == FrameKind::Params {
+            let parent = self.frames.last().expect("frame");
+            if parent.kind == FrameKind::ClassBody && matches!(self.prev, Tok::Ident(_) | Tok::Keyword(_) | Tok::Optional) {
+                frame.signature_start = Some(parent.member_start);
+            } else if let Some(start) = self.function_start.take() {
+                frame.signature_start = Some(start);
+            }
+            frame.constructor = parent.kind == FrameKind::ClassBody && self.prev == Tok::Ident("constructor".to_string());
+        }
+        self.copy(1);
+        let start = self.out.len();
+        frame.member_start = start;
+        if c == '{' {
+            if let Some(props) = self.pending_props.take() {
+                let assignments: String = props.iter().map(|p| format!(" this.{0} = {0};", p)).collect();
+                let extends = self.frames.iter().rev().find(|f| f.kind == FrameKind::ClassBody).is_some_and(|f| f.extends);
+                if extends {
+                    frame.inject_after_super = Some(assignments);
+                } else {
+                    self.out.push_str(&assignments);
+                }
+            }
+        }
+        self.frames.push(frame);
+        self.prev = Tok::Punct(c.to_string());
+        Ok(())
+    }
+
+    /// `import x, { ... }`: the `{` after a default import
+    fn import_default_then_braces(&self) -> bool {
+        self.prev.is_punct(",") && self.frame_kind() == FrameKind::Top && self.out.trim_end().trim_end_matches(',').trim_end().rsplit(char::is_whitespace).nth(1) == Some("import")
+    }
+
+    fn close(&mut self, c: char) -> Result<(), String> {
+        let frame = match self.frames.len() {
+            1 => return Err(self.error(&format!("Unmatched '{}'", c))),
+            _ => self.frames.pop().expect("frame"),
+        };
+        self.copy(1);
+        self.prev = Tok::Punct(c.to_string());
+
+        match frame.kind {
+            FrameKind::Params => self.after_parameters(frame)?,
+            FrameKind::Paren if frame.super_call => {
+                // `super(...)` returned: assign parameter properties now
+                let body = self.frames.iter_mut().rev().find(|f| f.inject_after_super.is_some());
+                if let Some(assignments) = body.and_then(|f| f.inject_after_super.take()) {
+                    self.out.push(';');
+                    self.out.push_str(assignments.trim_end_matches(';'));
+                }
+            }
+            FrameKind::Brace | FrameKind::ClassBody => {
+                let start = self.out.len();
+                let parent = self.frame();
+                if parent.kind == FrameKind::ClassBody {
+                    parent.member_start = start;
+                    parent.initializer = false;
+                }
+                // A closed block ends any declaration it was part of
+                if frame.kind == FrameKind::ClassBody || !parent.declaration {
+                    self.prev = Tok::Punct("}".to_string());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// After a parameter list: strip the return type, and drop overload
+    /// and abstract signatures that have no body
+    fn after_parameters(&mut self, frame: Frame) -> Result<(), String> {
+        if self.kind.typescript() && self.next_significant() == Some(':') {
+            self.trivia();
+            self.pos += 1;
+            self.skip_type(TypeContext::Return)?;
+        }
+        if !frame.param_props.is_empty() {
+            self.pending_props = Some(frame.param_props);
+        }
+        let Some(start) = frame.signature_start else {
+            return Ok(());
+        };
+        if !self.kind.typescript() {
+            return Ok(());
+        }
+        let next = self.next_significant();
+        if next == Some('{') || self.starts_with_arrow_after_trivia() {
+            return Ok(());
+        }
+        // No body: a signature, e.g. `function f(a: string): void;`
+        let end = self.skip_trivia_from(self.pos);
+        if next == Some(';') {
+            self.strip_to(end + 1);
+        }
+        self.truncate_output(start);
+        self.prev = Tok::Punct(";".to_string());
+        Ok(())
+    }
+
+    fn starts_with_arrow_after_trivia(&self) -> bool {
+        let pos = self.skip_trivia_from(self.pos);
+        self.src.get(pos) == Some(&'=') && self.src.get(pos + 1) == Some(&'>')
+    }
+
+    /// Whether the `(` at the current position opens parameters
+    fn is_parameter_list(&self) -> bool {
+        let frame = self.frames.last().expect("frame");
+        if frame.case_label {
+            return false;
+        }
+        let Some(end) = self.matching_close(self.pos) else {
+            return false;
+        };
+        let after = self.skip_trivia_from(end);
+        let next = self.src.get(after).copied();
+        if next == Some('=') && self.src.get(after + 1) == Some(&'>') {
+            return true;
+        }
+        let callable_head = match &self.prev {
+            Tok::Ident(_) | Tok::Optional => true,
+            Tok::Keyword(word) => word == "function",
+            _ => false,
+        };
+        if next == Some('{') {
+            return callable_head || self.frame_kind() == FrameKind::ClassBody;
+        }
+        if next == Some(':') && self.kind.typescript() && frame.ternary == 0 {
+            let expression_start = match &self.prev {
+                Tok::None | Tok::Punct(_) => !self.prev.ends_expression(),
+                Tok::Keyword(word) => !CONTROL.contains(&word.as_str()),
+                _ => false,
+            };
+            return callable_head || expression_start;
+        }
+        // Signatures without bodies
+        self.kind.typescript()
+            && (frame.kind == FrameKind::ClassBody && matches!(self.prev, Tok::Ident(_) | Tok::Optional)
+                || self.function_start.is_some() && callable_head)
+    }
+
+    fn is_index_signature(&self) -> bool {
+        let Some(word) = self.word_at(self.skip_trivia_from(self.pos + 1)) else {
+            return false;
+        };
+        let start = self.skip_trivia_from(self.pos + 1);
+        self.src.get(self.skip_trivia_from(start + word.chars().count())) == Some(&':')
+    }
+
+    /// Consume a class member up to and including its `;`, or its line
+    fn skip_member(&mut self) -> Result<(), String> {
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                '(' | '[' | '{' | '<' => depth += 1,
+                ')' | ']' | '>' => depth = depth.saturating_sub(1),
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                ';' if depth == 0 => {
+                    self.strip_to(self.pos + 1);
+                    return Ok(());
+                }
+                '\n' if depth == 0 => break,
+                _ => {}
+            }
+            self.strip_to(self.pos + 1);
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------
+    // Identifiers and declarations
+    // ------------------------------------------------------------------
+
+    fn identifier(&mut self) -> Result<(), String> {
+        let word = self.word_at(self.pos).unwrap_or_default();
+        let len = word.chars().count();
+        let ts = self.kind.typescript();
+        let statement_start = self.at_statement_start();
+        // Modifiers may follow `static` or each other
+        let member_start = self.frame_kind() == FrameKind::ClassBody
+            && (self.prev.is_punct("{")
+                || self.prev.is_punct(";")
+                || self.prev.is_punct("}")
+                || self.newline_before
+                || matches!(&self.prev, Tok::Ident(w) if w == "static"))
+            && !self.frames.last().is_some_and(|f| f.initializer);
+        let after = |t: &Transpiler| {
+            let pos = t.skip_trivia_from(t.pos + len);
+            (t.word_at(pos), t.src.get(pos).copied())
+        };
+
+        if ts {
+            let (next_word, next_char) = after(self);
+            match word.as_str() {
+                "interface" if statement_start && next_word.is_some() => return self.strip_interface(),
+                "type" if statement_start && next_word.is_some() && self.frame_kind() != FrameKind::Specifiers => {
+                    let pos = self.skip_trivia_from(self.pos + len);
+                    let name = next_word.clone().unwrap_or_default();
+                    let after_name = self.src.get(self.skip_trivia_from(pos + name.chars().count())).copied();
+                    if matches!(after_name, Some('=' | '<')) {
+                        return self.strip_type_alias();
+                    }
+                }
+                "type" if next_char == Some('{') && (self.prev.is_keyword("export") || self.prev.is_keyword("import")) => {
+                    return self.strip_type_only_statement();
+                }
+                "type" if self.prev.is_keyword("import") && next_word.as_deref().is_some_and(|w| w != "from") => {
+                    return self.strip_type_only_statement();
+                }
+                "type" if self.frame_kind() == FrameKind::Specifiers
+                    && next_word.as_deref().is_some_and(|w| w != "as")
+                    && !matches!(next_char, Some(',' | '}')) =>
+                {
+                    return self.strip_type_specifier();
+                }
+                "declare" if statement_start && next_word.is_some() => return self.strip_statement(),
+                "abstract" if next_word.as_deref() == Some("class") || member_start => {
+                    self.strip_to(self.skip_trivia_from(self.pos + len));
+                    return Ok(());
+                }
+                "this" if self.in_parameter_start() && next_char == Some(':') => {
+                    // A `this` parameter only types the receiver: drop it
+                    // with its annotation and the comma after it
+                    self.strip_to(self.skip_trivia_from(self.pos + len) + 1);
+                    self.skip_type(TypeContext::Annotation)?;
+                    let end = self.skip_trivia_from(self.pos);
+                    if self.src.get(end) == Some(&',') {
+                        self.strip_to(self.skip_trivia_from(end + 1));
+                    }
+                    return Ok(());
+                }
+                "enum" if statement_start && next_word.is_some() => return self.enum_declaration(),
+                "const" if statement_start && next_word.as_deref() == Some("enum") => {
+                    self.strip_to(self.skip_trivia_from(self.pos + len));
+                    return self.enum_declaration();
+                }
+                "namespace" | "module" if statement_start && self.word_after_next_is_brace(len) => {
+                    return Err(self.error("TypeScript namespaces are not supported"));
+                }
+                "implements" if self.pending_class_body => {
+                    let brace = (self.pos..self.src.len()).find(|&i| self.src[i] == '{').unwrap_or(self.src.len());
+                    self.strip_to(brace);
+                    return Ok(());
+                }
+                "as" | "satisfies"
+                    if (self.prev.ends_expression() || self.prev.is_punct("}") && !self.newline_before)
+                        && self.frame_kind() != FrameKind::Specifiers
+                        && !self.prev.is_punct("*")
+                        && next_char.is_some_and(|c| is_ident_start(c) || "{[(\"'`".contains(c)) =>
+                {
+                    self.pos += len;
+                    let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+                    self.out.truncate(trimmed);
+                    self.skip_type(TypeContext::Cast)?;
+                    return Ok(());
+                }
+                w if MODIFIERS.contains(&w)
+                    && (member_start || self.in_parameter_start())
+                    && (next_word.is_some() || matches!(next_char, Some('[' | '#'))) =>
+                {
+                    if self.frame_kind() == FrameKind::Params && w != "declare" {
+                        if let (Some(frame), Some(name)) = (self.frames.last_mut(), next_word.clone()) {
+                            if frame.constructor && !MODIFIERS.contains(&name.as_str()) {
+                                frame.param_props.push(name);
+                            }
+                        }
+                    }
+                    self.strip_to(self.skip_trivia_from(self.pos + len));
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        if word == "export" {
+            self.export_start = Some(self.out.len());
+        } else if !matches!(word.as_str(), "async" | "default") {
+            // `export` only matters to the declaration right after it
+            if !self.prev.is_keyword("export") && !self.prev.is_keyword("default") && !self.prev.is_keyword("async") {
+                self.export_start = None;
+            }
+        }
+        if word == "function" && statement_start {
+            self.function_start = Some(self.export_start.unwrap_or(self.out.len()));
+        } else if word == "function" {
+            self.function_start = None;
+        }
+        if word == "class" && !self.prev.is_punct(".") && !matches!(self.next_significant_after(len), Some(':' | ',' | '=' | ')' | '}')) {
+            self.classes.push(false);
+            self.pending_class_body = true;
+        }
+        if word == "extends" && self.pending_class_body {
+            if let Some(last) = self.classes.last_mut() {
+                *last = true;
+            }
+        }
+        if matches!(word.as_str(), "let" | "const" | "var") && !self.prev.is_punct(".") {
+            let frame = self.frame();
+            frame.declaration = true;
+            frame.initializer = false;
+        }
+        let label = word == "case" || word == "default" && self.next_significant_after(len) == Some(':');
+        if label && !self.prev.is_punct(".") && self.frame_kind() != FrameKind::Specifiers {
+            self.frame().case_label = true;
+        }
+
+        self.copy(len);
+        self.prev = if KEYWORDS.contains(&word.as_str()) && !self.prev.is_punct(".") {
+            Tok::Keyword(word)
+        } else {
+            Tok::Ident(word)
+        };
+        Ok(())
+    }
+
+    fn next_significant_after(&self, len: usize) -> Option<char> {
+        self.src.get(self.skip_trivia_from(self.pos + len)).copied()
+    }
+
+    fn word_after_next_is_brace(&self, len: usize) -> bool {
+        let pos = self.skip_trivia_from(self.pos + len);
+        let name = self.word_at(pos).unwrap_or_default();
+        self.src.get(self.skip_trivia_from(pos + name.chars().count())) == Some(&'{')
+    }
+
+    /// At the start of a parameter: right after `(` or `,` in a parameter
+    /// list
+    fn in_parameter_start(&self) -> bool {
+        self.frame_kind() == FrameKind::Params && (self.prev.is_punct("(") || self.prev.is_punct(","))
+    }
+
+    /// Drop output back to a preceding `export`
+    fn drop_export(&mut self) {
+        if self.prev.is_keyword("export") {
+            if let Some(start) = self.export_start.take() {
+                self.truncate_output(start);
+            }
+        }
+    }
+
+    /// `interface X<T> extends Y { ... }`
+    fn strip_interface(&mut self) -> Result<(), String> {
+        self.drop_export();
+        let brace = (self.pos..self.src.len()).find(|&i| self.src[i] == '{').ok_or_else(|| self.error("Expected '{'"))?;
+        let end = self.matching_close(brace).ok_or_else(|| self.error("Unterminated interface"))?;
+        self.strip_to(end);
+        self.prev = Tok::Punct("}".to_string());
+        Ok(())
+    }
+
+    /// `type X<T> = ...;`
+    fn strip_type_alias(&mut self) -> Result<(), String> {
+        self.drop_export();
+        let equals = (self.pos..self.src.len()).find(|&i| self.src[i] == '=').ok_or_else(|| self.error("Expected '='"))?;
+        self.strip_to(equals + 1);
+        self.skip_type(TypeContext::Alias)?;
+        let end = self.skip_trivia_from(self.pos);
+        if self.src.get(end) == Some(&';') && !self.src[self.pos..end].contains(&'\n') {
+            self.strip_to(end + 1);
+        }
+        self.prev = Tok::Punct(";".to_string());
+        Ok(())
+    }
+
+    /// `import type ...;` and `export type { ... }`
+    fn strip_type_only_statement(&mut self) -> Result<(), String> {
+        let start = if self.prev.is_keyword("import") {
+            self.out.rfind("import").unwrap_or(self.out.len())
+        } else {
+            self.export_start.take().unwrap_or(self.out.len())
+        };
+        self.truncate_output(start);
+        self.skip_to_statement_end(false);
+        self.prev = Tok::Punct(";".to_string());
+        Ok(())
+    }
+
+    /// `type X` inside import or export braces, with its comma
+    fn strip_type_specifier(&mut self) -> Result<(), String> {
+        let mut end = self.pos;
+        let mut words = 0;
+        loop {
+            end = self.skip_trivia_from(end);
+            match self.src.get(end) {
+                Some(',') => {
+                    end += 1;
+                    break;
+                }
+                Some('}') | None => break,
+                Some(&c) if is_ident_start(c) => {
+                    let word = self.word_at(end).unwrap_or_default();
+                    end += word.chars().count();
+                    words += 1;
+                }
+                Some(_) => return Err(self.error("Malformed type-only import")),
+            }
+        }
+        debug_assert!(words > 0);
+        self.strip_to(end);
+        Ok(())
+    }
+
+    /// `declare ...`: up to `;`, the end of its braces, or its line
+    fn strip_statement(&mut self) -> Result<(), String> {
+        self.drop_export();
+        self.skip_to_statement_end(true);
+        self.prev = Tok::Punct(";".to_string());
+        Ok(())
+    }
+
+    /// Strip to the end of the statement; with `brace_ends`, a closing
+    /// brace at the top ends it, as in `declare module "x" { ... }`
+    fn skip_to_statement_end(&mut self, brace_ends: bool) {
+        let mut depth = 0usize;
+        let mut pos = self.pos;
+        while pos < self.src.len() {
+            match self.src[pos] {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' => depth = depth.saturating_sub(1),
+                '}' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 && brace_ends {
+                        pos += 1;
+                        break;
+                    }
+                }
+                ';' if depth == 0 => {
+                    pos += 1;
+                    break;
+                }
+                '\n' if depth == 0 => {
+                    // Lines ending in an operator continue the statement
+                    let before = self.src[self.pos..pos].iter().rev().find(|c| !c.is_whitespace());
+                    if !matches!(before, Some(',' | '|' | '&' | '=' | ':' | '{' | '(')) {
+                        break;
+                    }
+                }
+                '"' | '\'' | '`' => {
+                    let quote = self.src[pos];
+                    pos += 1;
+                    while pos < self.src.len() && self.src[pos] != quote {
+                        pos += 1;
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        self.strip_to(pos.min(self.src.len()));
+    }
+
+    /// `enum E { A, B = 5, C = "c" }` as the object TypeScript emits; a
+    /// `const enum` compiles the same way
+    fn enum_declaration(&mut self) -> Result<(), String> {
+        let start = self.pos;
+        self.pos += 4;
+        let name_pos = self.skip_trivia_from(self.pos);
+        let name = self.word_at(name_pos).ok_or_else(|| self.error("Expected an enum name"))?;
+        let brace = self.skip_trivia_from(name_pos + name.chars().count());
+        if self.src.get(brace) != Some(&'{') {
+            return Err(self.error("Expected '{' after the enum name"));
+        }
+        let end = self.matching_close(brace).ok_or_else(|| self.error("Unterminated enum"))?;
+        let body: String = self.src[brace + 1..end - 1].iter().collect();
+
+        let mut members = Vec::new();
+        let mut next: Option<String> = Some("0".to_string());
+        for member in split_top_level(&body) {
+            let member = strip_comments(&member);
+            let member = member.trim();
+            if member.is_empty() {
+                continue;
+            }
+            let (key, init) = match member.split_once('=') {
+                Some((key, init)) => (key.trim(), Some(init.trim().to_string())),
+                None => (member, None),
+            };
+            let key = key.trim_matches(|c| c == '"' || c == '\'');
+            let value = match init.or_else(|| next.clone()) {
+                Some(value) => value,
+                None => return Err(self.error(&format!("Enum member '{}' needs an initializer", key))),
+            };
+            if value.starts_with('"') || value.starts_with('\'') || value.starts_with('`') {
+                members.push(format!("{0}[\"{1}\"] = {2};", name, key, value));
+                next = None;
+            } else {
+                members.push(format!("{0}[{0}[\"{1}\"] = {2}] = \"{1}\";", name, key, value));
+                next = Some(match value.parse::<f64>() {
+                    Ok(n) => format!("{}", n + 1.0),
+                    Err(_) => format!("{}[\"{}\"] + 1", name, key),
+                });
+            }
+        }
+        let newlines = self.src[start..end].iter().filter(|&&c| c == '\n').count();
+        self.out.push_str(&format!(
+            "var {0}; (function ({0}) {{ {1} }})({0} || ({0} = {{}}));",
+            name,
+            members.join(" ")
+        ));
+        self.out.extend(std::iter::repeat_n('\n', newlines));
+        self.pos = end;
+        self.prev = Tok::Punct(";".to_string());
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------
+    // Types
+    // ------------------------------------------------------------------
+
+    /// Consume a type, writing only its line breaks
+    fn skip_type(&mut self, context: TypeContext) -> Result<(), String> {
+        let mut depth: Vec<char> = Vec::new();
+        let mut expecting = true;
+        // A parenthesized group opened where a type was expected: a function
+        // type's parameters, so `=>` continues the type
+        let mut function_params = false;
+        // Whether each open group was opened where a type was expected
+        let mut group_stack: Vec<bool> = Vec::new();
+        loop {
+            let before = self.pos;
+            let next = self.skip_trivia_from(self.pos);
+            let newline = self.src[self.pos..next].contains(&'\n');
+            let Some(c) = self.src.get(next).copied() else {
+                self.strip_to(next);
+                return Ok(());
+            };
+            let stop = |t: &mut Transpiler| {
+                t.pos = before;
+                Ok(())
+            };
+            if depth.is_empty() && !expecting && newline {
+                let continues = matches!(c, '|' | '&' | '.' | '[')
+                    || context == TypeContext::Alias && matches!(c, '?' | ':')
+                    || self.word_at(next).as_deref() == Some("extends") && context == TypeContext::Alias;
+                if !continues {
+                    return stop(self);
+                }
+            }
+            let two = (c, self.src.get(next + 1).copied());
+            match c {
+                c if is_ident_start(c) => {
+                    let word = self.word_at(next).unwrap_or_default();
+                    if depth.is_empty() && !expecting {
+                        let continues = matches!(word.as_str(), "extends" | "is") && (context == TypeContext::Alias || word == "is");
+                        if !continues {
+                            return stop(self);
+                        }
+                    }
+                    expecting = matches!(word.as_str(), "keyof" | "typeof" | "infer" | "readonly" | "unique" | "extends" | "is" | "asserts" | "new");
+                    self.strip_to(next + word.chars().count());
+                    function_params = false;
+                    continue;
+                }
+                '"' | '\'' | '`' => {
+                    let mut end = next + 1;
+                    while end < self.src.len() && self.src[end] != c {
+                        if self.src[end] == '\\' {
+                            end += 1;
+                        }
+                        end += 1;
+                    }
+                    self.strip_to((end + 1).min(self.src.len()));
+                    expecting = false;
+                    function_params = false;
+                    continue;
+                }
+                '0'..='9' | '-' => {
+                    let mut end = next + 1;
+                    while self.src.get(end).is_some_and(|c| c.is_alphanumeric() || *c == '.') {
+                        end += 1;
+                    }
+                    self.strip_to(end);
+                    expecting = false;
+                    continue;
+                }
+                '(' | '[' | '{' | '<' => {
+                    if depth.is_empty() && !expecting {
+                        // `T[]`, `T[K]` and `Array<T>` continue a type
+                        if !matches!(c, '[' | '<') {
+                            return stop(self);
+                        }
+                    }
+                    group_stack.push(expecting);
+                    depth.push(c);
+                    expecting = true;
+                    self.strip_to(next + 1);
+                    continue;
+                }
+                ')' | ']' | '}' | '>' => {
+                    if two == ('>', Some('=')) && depth.is_empty() {
+                        return stop(self);
+                    }
+                    if depth.is_empty() {
+                        return stop(self);
+                    }
+                    depth.pop();
+                    let opened_expecting = group_stack.pop().unwrap_or(false);
+                    function_params = opened_expecting && c == ')';
+                    // A generic function type's `<T>`: its parameters follow
+                    expecting = opened_expecting && c == '>';
+                    self.strip_to(next + 1);
+                    continue;
+                }
+                '=' if two.1 == Some('>') => {
+                    if depth.is_empty() && !function_params {
+                        return stop(self);
+                    }
+                    expecting = true;
+                    self.strip_to(next + 2);
+                    continue;
+                }
+                '|' | '&' if two.1 == Some(c) => {
+                    if depth.is_empty() {
+                        return stop(self);
+                    }
+                }
+                '|' | '&' => expecting = true,
+                ',' | ';' | '=' => {
+                    if depth.is_empty() {
+                        return stop(self);
+                    }
+                    expecting = true;
+                }
+                ':' | '?' => {
+                    if depth.is_empty() && context != TypeContext::Alias {
+                        return stop(self);
+                    }
+                    expecting = true;
+                }
+                '.' => {
+                    expecting = true;
+                    let len = if self.src[next..].starts_with(&['.', '.', '.']) { 3 } else { 1 };
+                    self.strip_to(next + len);
+                    continue;
+                }
+                _ => {
+                    if depth.is_empty() {
+                        return stop(self);
+                    }
+                }
+            }
+            function_params = false;
+            self.strip_to(next + 1);
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // JSX
+    // ------------------------------------------------------------------
+
+    /// Translate the JSX element at `<` into a factory call
+    fn jsx_element(&mut self) -> Result<String, String> {
+        let line = self.line();
+        self.pos += 1;
+        let mut newlines = 0;
+        let name = self.jsx_name();
+        let tag = if name.is_empty() {
+            self.options.jsx_fragment.clone()
+        } else if name.chars().next().is_some_and(|c| c.is_ascii_lowercase()) && !name.contains('.') {
+            format!("\"{}\"", name)
+        } else {
+            name.clone()
+        };
+
+        // Attributes
+        let mut props: Vec<String> = Vec::new();
+        loop {
+            newlines += self.jsx_whitespace();
+            match self.peek() {
+                None => return Err(format!("Unterminated JSX element <{}> from line {}", name, line)),
+                Some('/') if self.peek_at(1) == Some('>') => {
+                    self.pos += 2;
+                    return Ok(self.jsx_call(&tag, &props, &[], newlines));
+                }
+                Some('>') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    let expression = self.jsx_expression()?;
+                    let spread = expression.trim_start().strip_prefix("...").unwrap_or(&expression).to_string();
+                    props.push(format!("...{}", spread.trim()));
+                }
+                Some(c) if is_ident_start(c) => {
+                    let attribute = self.jsx_name();
+                    let key = if attribute.chars().all(is_ident_part) { attribute.clone() } else { format!("\"{}\"", attribute) };
+                    newlines += self.jsx_whitespace();
+                    if self.peek() != Some('=') {
+                        props.push(format!("{}: true", key));
+                        continue;
+                    }
+                    self.pos += 1;
+                    newlines += self.jsx_whitespace();
+                    let value = match self.peek() {
+                        Some(quote @ ('"' | '\'')) => {
+                            self.pos += 1;
+                            let start = self.pos;
+                            while self.peek().is_some_and(|c| c != quote) {
+                                self.pos += 1;
+                            }
+                            let text: String = self.src[start..self.pos].iter().collect();
+                            newlines += text.matches('\n').count();
+                            self.pos += 1;
+                            js_string(&decode_entities(&text))
+                        }
+                        Some('{') => {
+                            self.pos += 1;
+                            self.jsx_expression()?
+                        }
+                        Some('<') => self.jsx_element()?,
+                        _ => return Err(self.error(&format!("Expected a value for JSX attribute '{}'", attribute))),
+                    };
+                    props.push(format!("{}: {}", key, value.trim()));
+                }
+                Some(c) => return Err(self.error(&format!("Unexpected '{}' in JSX element <{}>", c, name))),
+            }
+        }
+
+        // Children
+        let mut children: Vec<String> = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(format!("Unterminated JSX element <{}> from line {}", name, line)),
+                Some('<') if self.peek_at(1) == Some('/') => {
+                    self.pos += 2;
+                    self.jsx_whitespace();
+                    let closing = self.jsx_name();
+                    self.jsx_whitespace();
+                    if closing != name || self.peek() != Some('>') {
+                        return Err(self.error(&format!("Expected </{}> to close the JSX element from line {}", name, line)));
+                    }
+                    self.pos += 1;
+                    break;
+                }
+                Some('<') => {
+                    let child = self.jsx_element()?;
+                    children.push(child);
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    let expression = self.jsx_expression()?;
+                    if strip_comments(&expression).trim().is_empty() {
+                        newlines += expression.matches('\n').count();
+                    } else {
+                        children.push(expression);
+                    }
+                }
+                Some(_) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c != '<' && c != '{') {
+                        self.pos += 1;
+                    }
+                    let raw: String = self.src[start..self.pos].iter().collect();
+                    newlines += raw.matches('\n').count();
+                    if let Some(text) = jsx_text(&raw) {
+                        children.push(js_string(&decode_entities(&text)));
+                    }
+                }
+            }
+        }
+        Ok(self.jsx_call(&tag, &props, &children, newlines))
+    }
+
+    fn jsx_call(&self, tag: &str, props: &[String], children: &[String], newlines: usize) -> String {
+        let props = if props.is_empty() { "null".to_string() } else { format!("{{ {} }}", props.join(", ")) };
+        let mut call = format!("{}({}, {}", self.options.jsx_factory, tag, props);
+        for child in children {
+            call.push_str(", ");
+            call.push_str(child);
+        }
+        call.push(')');
+        // Keep the element's line breaks so later lines stay in place
+        call.extend(std::iter::repeat_n('\n', newlines));
+        call
+    }
+
+    /// Tag or attribute name, e.g. `div`, `Foo.Bar`, `data-id`, `xlink:href`
+    fn jsx_name(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| is_ident_part(c) || c == '.' || c == '-' || c == ':') {
+            self.pos += 1;
+        }
+        self.src[start..self.pos].iter().collect()
+    }
+
+    /// Skip whitespace and comments inside a tag, counting line breaks
+    fn jsx_whitespace(&mut self) -> usize {
+        let end = self.skip_trivia_from(self.pos);
+        let newlines = self.src[self.pos..end].iter().filter(|&&c| c == '\n').count();
+        self.pos = end;
+        newlines
+    }
+
+    /// Translate a `{...}` expression whose `{` was consumed, returning it
+    fn jsx_expression(&mut self) -> Result<String, String> {
+        let saved_out = mem::take(&mut self.out);
+        let saved_prev = mem::replace(&mut self.prev, Tok::Punct("{".to_string()));
+        self.frames.push(Frame::new(FrameKind::Interpolation));
+        let result = self.code();
+        self.frames.pop();
+        let expression = mem::replace(&mut self.out, saved_out);
+        self.prev = saved_prev;
+        result?;
+        if self.peek() != Some('}') {
+            return Err(self.error("Expected '}' to close the JSX expression"));
+        }
+        self.pos += 1;
+        Ok(expression)
+    }
+}
+
+/// Text of a JSX text child: lines trimmed, blank lines dropped and the
+/// rest joined with spaces, as React does
+fn jsx_text(raw: &str) -> Option<String> {
+    let lines: Vec<&str> = raw.split('\n').collect();
+    let last = lines.len() - 1;
+    let parts: Vec<String> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = line.replace('\t', " ");
+            let line = if i > 0 { line.trim_start().to_string() } else { line };
+            if i < last { line.trim_end().to_string() } else { line }
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+    let text = parts.join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Decode the HTML entities JSX text and attribute strings may use
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                "copy" => Some('©'),
+                "hellip" => Some('…'),
+                "mdash" => Some('—'),
+                _ => match entity.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32),
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            ch.map(|ch| (ch, end))
+        });
+        match decoded {
+            Some((ch, end)) => {
+                out.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A double-quoted JavaScript string literal
+fn js_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Split on commas outside brackets and strings
+fn split_top_level(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    for ch in text.chars() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'' | '`') => quote = Some(ch),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().expect("parts").push(ch);
+    }
+    parts
+}
+
+/// Remove `//` and `/* */` comments from a short snippet
+fn strip_comments(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    loop {
+        let line = rest.find("//");
+        let block = rest.find("/*");
+        match (line, block) {
+            (Some(l), b) if b.is_none_or(|b| l < b) => {
+                out.push_str(&rest[..l]);
+                rest = rest[l..].find('\n').map_or("", |end| &rest[l + end..]);
+            }
+            (_, Some(b)) => {
+                out.push_str(&rest[..b]);
+                rest = rest[b..].find("*/").map_or("", |end| &rest[b + end + 2..]);
+            }
+            _ => {
+                out.push_str(rest);
+                return out;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(source: &str) -> String {
+        transpile(source, SourceKind::TypeScript, &TranspileOptions::default()).unwrap()
+    }
+
+    fn tsx(source: &str) -> String {
+        transpile(source, SourceKind::Tsx, &TranspileOptions::default().with_jsx_factory("h", "Fragment")).unwrap()
+    }
+
+    fn squash(text: &str) -> String {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    // ==================== TypeScript ====================
+
+    #[test]
+    fn test_annotations_are_stripped() {
+        assert_eq!(ts("let x: number = 1;"), "let x = 1;");
+        assert_eq!(ts("const a: string[] = [], b: Map<string, number> = new Map<string, number>();"), "const a = [], b = new Map();");
+        assert_eq!(ts("function add(a: number, b?: number): number { return a + (b ?? 0); }"), "function add(a, b) { return a + (b ?? 0); }");
+        assert_eq!(ts("const f = (x: { a: string }, cb: (n: number) => void): void => cb(1);"), "const f = (x, cb) => cb(1);");
+        assert_eq!(ts("const g = async <T,>(items: T[] = []): Promise<T[]> => items;"), "const g = async (items = []) => items;");
+        assert_eq!(ts("function first<T extends object>({ a, b }: T, ...rest: T[]) {}"), "function first({ a, b }, ...rest) {}");
+        assert_eq!(ts("const v = cond ? a : b;"), "const v = cond ? a : b;");
+        assert_eq!(ts("const o = { a: 1, b: c ? (d) : e };"), "const o = { a: 1, b: c ? (d) : e };");
+        assert_eq!(ts("switch (x) { case (1): break; default: y(); }"), "switch (x) { case (1): break; default: y(); }");
+        assert_eq!(ts("const isStr = (v: unknown): v is string => typeof v === 'string';"), "const isStr = (v) => typeof v === 'string';");
+        assert_eq!(ts("function h(this: Window, ...rest: any[]) {}"), "function h(...rest) {}");
+        assert_eq!(ts("function onClick(this: HTMLElement): void { this.focus(); }"), "function onClick() { this.focus(); }");
+        assert_eq!(ts("const o = { m(this: { n: number }, a: number) { return this.n + a; } };"), "const o = { m(a) { return this.n + a; } };");
+    }
+
+    #[test]
+    fn test_generic_function_types_are_stripped() {
+        // Given: Annotations that are generic function types
+        let source = "let fn: <T>(x: T) => T = x => x;\nconst pick: <K extends string, V>(m: Record<K, V>, k: K) => V | undefined = (m, k) => m[k];\nlet on: Map<string, <E>(e: E) => void> = new Map();";
+
+        // When: They are transpiled
+        let output = ts(source);
+
+        // Then: The whole type goes, type parameters, parameters and return type
+        assert_eq!(output, "let fn = x => x;\nconst pick = (m, k) => m[k];\nlet on = new Map();");
+    }
+
+    #[test]
+    fn test_type_declarations_are_removed() {
+        let source = "import type { A } from './a';\nimport { type B, c } from './b';\nexport interface Props {\n  name: string;\n}\ntype Id = string | number;\nexport type Pair<T> = [T, T];\ndeclare const VERSION: string;\nexport { c };\n";
+        let output = ts(source);
+        assert_eq!(output.lines().count(), source.lines().count(), "line numbers are kept: {}", output);
+        assert_eq!(squash(&output), "import { c } from './b'; export { c };");
+    }
+
+    #[test]
+    fn test_casts_and_non_null_assertions() {
+        assert_eq!(ts("const el = document.querySelector('p')! as HTMLElement;"), "const el = document.querySelector('p');");
+        assert_eq!(ts("const n = (value as unknown as number) + map.get(k)!.size;"), "const n = (value) + map.get(k).size;");
+        assert_eq!(ts("const cfg = { a: 1 } satisfies Config;\nlet ok = !done && a != b;"), "const cfg = { a: 1 };\nlet ok = !done && a != b;");
+        assert_eq!(ts("import * as path from 'path';\nexport { a as b };"), "import * as path from 'path';\nexport { a as b };");
+    }
+
+    #[test]
+    fn test_classes_enums_and_overloads() {
+        let source = "abstract class Base<T> implements Shape {\n  private readonly items: T[] = [];\n  static count?: number;\n  [key: string]: unknown;\n  abstract area(): number;\n  get size(): number { return this.items.length; }\n}\nclass Point extends Base<number> {\n  constructor(public x: number, private y = 0) {\n    super();\n  }\n}\nfunction parse(v: string): number;\nfunction parse(v: any) { return +v; }\nenum Color { Red, Green = 5, Blue }\n";
+        let output = ts(source);
+        assert_eq!(output.lines().count(), source.lines().count(), "line numbers are kept: {}", output);
+        assert_eq!(
+            squash(&output),
+            squash(
+                "class Base { items = []; static count; get size() { return this.items.length; } } \
+                 class Point extends Base { constructor(x, y = 0) { super(); this.x = x; this.y = y; } } \
+                 function parse(v) { return +v; } \
+                 var Color; (function (Color) { Color[Color[\"Red\"] = 0] = \"Red\"; Color[Color[\"Green\"] = 5] = \"Green\"; Color[Color[\"Blue\"] = 6] = \"Blue\"; })(Color || (Color = {}));"
+            )
+        );
+
+        let plain = ts("class Box { constructor(readonly value: string) {} }");
+        assert_eq!(plain, "class Box { constructor(value) { this.value = value;} }");
+    }
+
+    #[test]
+    fn test_namespaces_are_rejected() {
+        let error = transpile("let a = 1;\nnamespace Util { }", SourceKind::TypeScript, &TranspileOptions::default()).unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+    }
+
+    // ==================== JSX ====================
+
+    #[test]
+    fn test_jsx_elements_compile_to_factory_calls() {
+        assert_eq!(tsx("const a = <div className=\"x\" hidden />;"), "const a = h(\"div\", { className: \"x\", hidden: true });");
+        assert_eq!(
+            tsx("const b = <List items={xs.map((x: Item) => <li key={x.id}>{x.name}!</li>)} {...rest} />;"),
+            "const b = h(List, { items: xs.map((x) => h(\"li\", { key: x.id }, x.name, \"!\")), ...rest });"
+        );
+        assert_eq!(tsx("const c = <><b>bold &amp; brave</b> text</>;"), "const c = h(Fragment, null, h(\"b\", null, \"bold & brave\"), \" text\");");
+        assert_eq!(tsx("if (a < b && c > d) {}"), "if (a < b && c > d) {}");
+        assert_eq!(tsx("const id = <T,>(v: T) => v;"), "const id = (v) => v;");
+    }
+
+    #[test]
+    fn test_jsx_keeps_lines_and_honours_pragmas() {
+        let source = "/** @jsx el */\nconst view = (\n  <section data-id=\"main\">\n    {/* comment */}\n    Hello\n    world\n  </section>\n);\nthrow new Error('here');";
+        let output = transpile(source, SourceKind::Jsx, &TranspileOptions::default()).unwrap();
+        assert!(output.contains("el(\"section\", { \"data-id\": \"main\" }, \"Hello world\")"), "{}", output);
+        assert_eq!(output.lines().position(|l| l.contains("throw")), Some(8));
+
+        let error = transpile("const a = <div><span></div>;", SourceKind::Jsx, &TranspileOptions::default()).unwrap_err();
+        assert!(error.contains("</span>"), "{}", error);
+    }
+
+    #[test]
+    fn test_transpile_file_by_extension() {
+        let options = TranspileOptions::default();
+        assert_eq!(transpile_file("let a: number = 1;", Path::new("a.ts"), &options).unwrap(), "let a = 1;");
+        assert_eq!(transpile_file("let a = <p/>;", Path::new("a.jsx"), &options).unwrap(), "let a = React.createElement(\"p\", null);");
+        assert_eq!(transpile_file("let a = b < c;", Path::new("a.js"), &options).unwrap(), "let a = b < c;");
+        let error = transpile_file("let a = <p>;", Path::new("view.tsx"), &options).unwrap_err();
+        assert!(error.to_string().contains("view.tsx"), "{}", error);
+    }
+}
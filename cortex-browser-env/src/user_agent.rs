@@ -0,0 +1,92 @@
+//! User Agent Stylesheet
+//! The default styles every page starts from, below its own stylesheets
+//!
+//! Headings are larger and bold, `strong` and `b` are bold, phrasing
+//! elements are inline, form controls are inline blocks, lists are
+//! indented, `pre` keeps its whitespace and the head, scripts and anything
+//! `hidden` are `display: none`. Sizes follow the common browser defaults
+//! for a 16px root. The rules are applied in the order written, before
+//! any page rule.
+
+use std::sync::OnceLock;
+
+use crate::css::{parse_css, StyleSheet};
+
+/// The user agent stylesheet, in cascade order: later rules win
+pub const USER_AGENT_CSS: &str = r#"
+html, body, address, article, aside, blockquote, details, dialog, dd, div, dl, dt, fieldset, figcaption, figure,
+footer, form, h1, h2, h3, h4, h5, h6, header, hgroup, hr, legend, li, main, menu, nav, ol, p, pre, section, summary,
+table, ul { display: block; }
+
+a, abbr, b, bdi, bdo, br, cite, code, data, dfn, em, i, kbd, label, mark, q, s, samp, small, span, strong, sub, sup,
+time, u, var { display: inline; }
+
+img, input, button, select, textarea, meter, progress, video, canvas, iframe, svg { display: inline-block; }
+
+head, script, style, title, meta, link, base, template, noscript { display: none; }
+
+body { margin: 8px; }
+
+h1 { font-size: 32px; font-weight: bold; margin: 21px 0; }
+h2 { font-size: 24px; font-weight: bold; margin: 20px 0; }
+h3 { font-size: 19px; font-weight: bold; margin: 19px 0; }
+h4 { font-size: 16px; font-weight: bold; margin: 21px 0; }
+h5 { font-size: 13px; font-weight: bold; margin: 22px 0; }
+h6 { font-size: 11px; font-weight: bold; margin: 25px 0; }
+
+p, dl, pre, ul, ol, menu { margin: 16px 0; }
+blockquote, figure { margin: 16px 40px; }
+ul, ol, menu { padding-left: 40px; }
+dd { margin-left: 40px; }
+
+b, strong, th { font-weight: bold; }
+small { font-size: 13px; }
+pre, textarea { white-space: pre; }
+
+input, select, button { padding: 1px 2px; }
+textarea { padding: 2px; }
+
+[hidden] { display: none; }
+"#;
+
+/// The parsed user agent stylesheet, shared by every page
+pub fn stylesheet() -> &'static StyleSheet {
+    static STYLESHEET: OnceLock<StyleSheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| parse_css(USER_AGENT_CSS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::Display;
+    use crate::parser::parse_html;
+    use crate::query::query_selector;
+    use crate::style::compute_styles;
+
+    #[test]
+    fn test_user_agent_defaults_under_page_rules() {
+        // Given: A page with a heading, inline text, a hidden block and one
+        // rule of its own
+        let document = parse_html(r#"<html><head><title>T</title></head><body><h1>Title</h1><p>Some <strong>bold</strong> text</p><div hidden>Secret</div></body></html>"#);
+        let styles = compute_styles(&document, &parse_css("h1 { font-size: 40px; } p { margin: 0; }"));
+        let style = |selector: &str| &styles[query_selector(&document, selector).unwrap().unwrap()];
+
+        // Then: The defaults apply, and the page's rules win over them
+        assert_eq!(style("h1").font_size, Some(crate::css::CSSValue::Pixels(40.0)));
+        assert_eq!(style("h1").font_weight, Some(700));
+        assert_eq!(style("strong").display, Display::Inline);
+        assert_eq!(style("strong").font_weight, Some(700));
+        assert_eq!(style("p").margin_top, Some(crate::css::CSSValue::Pixels(0.0)));
+        assert_eq!(style("body").margin_left, Some(crate::css::CSSValue::Pixels(8.0)));
+        assert_eq!(style("div").display, Display::None);
+        assert_eq!(style("title").display, Display::None);
+    }
+
+    #[test]
+    fn test_user_agent_stylesheet_parses_every_rule() {
+        // Every rule has declarations and selectors the rule map understands
+        let sheet = stylesheet();
+        assert!(sheet.rules.len() >= 20);
+        assert!(sheet.rules.iter().all(|rule| !rule.declarations.is_empty() && !rule.selectors.is_empty()));
+    }
+}
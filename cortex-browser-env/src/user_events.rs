@@ -0,0 +1,930 @@
+//! User Events
+//! Input events as a user's pointer and keyboard produce them
+//!
+//! Pointer events go to whatever `hit_test` finds under the point, so an
+//! overlay covering a button receives the click, and `MouseState` moves the
+//! `:hover` chain as the pointer moves. Key events go to the focused
+//! element in a browser's order; canceling one skips the steps it leads to.
+
+use std::cell::RefCell;
+
+use rquickjs::{Ctx, Object};
+
+use crate::dom::Document;
+use crate::editing::{self, Edit};
+use crate::events;
+use crate::forms;
+use crate::geometry::Point;
+use crate::head;
+use crate::navigation;
+use crate::selection::{PageSelection, Range};
+
+// ============================================================================
+// MOUSE
+// ============================================================================
+
+/// `MouseEvent.buttons` bit of the primary button
+pub const PRIMARY_BUTTON: u16 = 1;
+
+/// Where the pointer is and what it presses
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MouseState {
+    /// `None` until the pointer first moves over the page
+    pub position: Option<Point>,
+    /// The element under the pointer, the bottom of the `:hover` chain
+    pub target: Option<usize>,
+    /// Buttons held down, as `MouseEvent.buttons` reports them
+    pub buttons: u16,
+}
+
+/// What a mouse event reports besides its type and target
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MouseEventInit {
+    pub point: Point,
+    /// The button that changed: 0 for primary
+    pub button: i16,
+    pub buttons: u16,
+    /// The element the pointer left for `mouseover`, or entered for
+    /// `mouseout`
+    pub related_target: Option<usize>,
+    /// Click count for `click`, `mousedown` and `mouseup`
+    pub detail: i32,
+}
+
+impl MouseEventInit {
+    pub fn at(point: Point) -> Self {
+        MouseEventInit { point, ..Default::default() }
+    }
+}
+
+/// Whether a mouse event of `event_type` bubbles and can be canceled;
+/// `mouseenter` and `mouseleave` do neither
+pub fn mouse_event_bubbles(event_type: &str) -> bool {
+    !matches!(event_type, "mouseenter" | "mouseleave")
+}
+
+/// Dispatch a trusted `MouseEvent` of `event_type` at `target`, returning
+/// `false` if a listener canceled it
+pub fn dispatch_mouse_event<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    target: usize,
+    event_type: &str,
+    init: &MouseEventInit,
+) -> rquickjs::Result<bool> {
+    let event = create_mouse_event(ctx, "MouseEvent", event_type, init)?;
+    events::dispatch_event(ctx, document, target, event)
+}
+
+fn create_mouse_event<'js>(
+    ctx: &Ctx<'js>,
+    constructor: &str,
+    event_type: &str,
+    init: &MouseEventInit,
+) -> rquickjs::Result<Object<'js>> {
+    let object = Object::new(ctx.clone())?;
+    let bubbles = mouse_event_bubbles(event_type);
+    object.set("bubbles", bubbles)?;
+    object.set("cancelable", bubbles)?;
+    object.set("composed", true)?;
+    for name in ["clientX", "pageX", "screenX"] {
+        object.set(name, init.point.x)?;
+    }
+    for name in ["clientY", "pageY", "screenY"] {
+        object.set(name, init.point.y)?;
+    }
+    object.set("button", init.button)?;
+    object.set("buttons", init.buttons)?;
+    object.set("detail", init.detail)?;
+    if let Some(related) = init.related_target {
+        object.set("relatedTarget", related)?;
+    }
+    let event = events::create_event(ctx, constructor, event_type, object)?;
+    event.set("isTrusted", true)?;
+    Ok(event)
+}
+
+/// Move the `:hover` chain to `target` and its ancestors
+pub fn set_hover(document: &mut Document, target: Option<usize>) {
+    let chain = target.map(|target| events::event_path(document, target)).unwrap_or_default();
+    document.set_hovered(chain);
+}
+
+/// Move the pointer to `point`, over `target`, firing the boundary events
+/// when it changes elements and then `mousemove`
+pub fn move_to<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    state: &mut MouseState,
+    point: Point,
+    target: Option<usize>,
+) -> rquickjs::Result<()> {
+    let previous = state.target;
+    *state = MouseState { position: Some(point), target, ..*state };
+    let init = MouseEventInit { buttons: state.buttons, ..MouseEventInit::at(point) };
+    if previous != target {
+        set_hover(&mut document.borrow_mut(), target);
+        let (old_path, new_path) = {
+            let document = document.borrow();
+            let path = |node: Option<usize>| node.map(|node| events::event_path(&document, node)).unwrap_or_default();
+            (path(previous), path(target))
+        };
+        if let Some(previous) = previous {
+            dispatch_mouse_event(ctx, document, previous, "mouseout", &MouseEventInit { related_target: target, ..init })?;
+            // Leaving runs from the element outwards
+            for &node in old_path.iter().filter(|node| !new_path.contains(node)) {
+                dispatch_mouse_event(ctx, document, node, "mouseleave", &MouseEventInit { related_target: target, ..init })?;
+            }
+        }
+        if let Some(target) = target {
+            dispatch_mouse_event(ctx, document, target, "mouseover", &MouseEventInit { related_target: previous, ..init })?;
+            // Entering runs from the outermost element inwards
+            for &node in new_path.iter().rev().filter(|node| !old_path.contains(node)) {
+                dispatch_mouse_event(ctx, document, node, "mouseenter", &MouseEventInit { related_target: previous, ..init })?;
+            }
+        }
+    }
+    if let Some(target) = target {
+        dispatch_mouse_event(ctx, document, target, "mousemove", &init)?;
+    }
+    Ok(())
+}
+
+/// Press the primary button over `target`, returning `false` if a
+/// listener canceled `mousedown`
+pub fn press<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, state: &mut MouseState, target: usize) -> rquickjs::Result<bool> {
+    state.buttons |= PRIMARY_BUTTON;
+    let init = MouseEventInit { buttons: state.buttons, detail: 1, ..MouseEventInit::at(state.position.unwrap_or_default()) };
+    dispatch_mouse_event(ctx, document, target, "mousedown", &init)
+}
+
+/// Release the primary button over `target`, firing `mouseup` and then
+/// `click` at the innermost element holding both where the button went
+/// down and `target`, and returning what the click activated
+pub fn release<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    state: &mut MouseState,
+    pressed: usize,
+    target: usize,
+) -> rquickjs::Result<Option<Activation>> {
+    state.buttons &= !PRIMARY_BUTTON;
+    let init = MouseEventInit { buttons: state.buttons, detail: 1, ..MouseEventInit::at(state.position.unwrap_or_default()) };
+    dispatch_mouse_event(ctx, document, target, "mouseup", &init)?;
+    let clicked = {
+        let document = document.borrow();
+        let pressed_path = events::event_path(&document, pressed);
+        events::event_path(&document, target).into_iter().find(|node| pressed_path.contains(node))
+    };
+    match clicked {
+        Some(clicked) => click(ctx, document, clicked, &init),
+        None => Ok(None),
+    }
+}
+
+/// What a click did once no listener canceled it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Activation {
+    /// A submit button submitted its form
+    Submitted(FormSubmission),
+    /// A link was followed; see `navigation`
+    FollowedLink(usize),
+    /// A checkbox or radio button changed its checkedness, firing `input`
+    /// and `change`
+    Toggled(usize),
+}
+
+/// Dispatch `click` at `target` and run the activation behavior of the
+/// innermost submit button, link, checkbox or radio button on its path
+///
+/// Checkboxes and radios change before `click` is dispatched and change
+/// back if a listener cancels it. The others act only once no listener
+/// canceled it: a submit button submits its form, and a link is followed.
+pub fn click<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    target: usize,
+    init: &MouseEventInit,
+) -> rquickjs::Result<Option<Activation>> {
+    let activated = {
+        let document = document.borrow();
+        events::event_path(&document, target).into_iter().find(|&node| {
+            is_submit_button(&document, node) || navigation::is_link(&document, node) || forms::is_checkable(&document, node)
+        })
+    };
+    let pre_activation = activated.and_then(|node| forms::pre_activate(&mut document.borrow_mut(), node));
+    if !dispatch_mouse_event(ctx, document, target, "click", init)? {
+        if let Some(pre_activation) = pre_activation {
+            forms::cancel_activation(&mut document.borrow_mut(), pre_activation);
+        }
+        return Ok(None);
+    }
+    if let (Some(node), Some(pre_activation)) = (activated, pre_activation) {
+        let toggled = forms::finish_activation(ctx, document, pre_activation)?;
+        return Ok(toggled.then_some(Activation::Toggled(node)));
+    }
+    match activated {
+        Some(link) if navigation::is_link(&document.borrow(), link) => Ok(Some(Activation::FollowedLink(link))),
+        Some(button) if is_submit_button(&document.borrow(), button) && !forms::has_attribute(&document.borrow(), button, "disabled") => {
+            let form = forms::form_owner(&document.borrow(), button);
+            match form {
+                Some(form) => Ok(submit(ctx, document, form, Some(button))?.map(Activation::Submitted)),
+                None => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Fire a `wheel` event at `target` scrolling `delta_y` pixels down,
+/// returning `false` if a listener canceled it
+pub fn wheel<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, state: &MouseState, target: usize, delta_y: f64) -> rquickjs::Result<bool> {
+    let init = MouseEventInit { buttons: state.buttons, ..MouseEventInit::at(state.position.unwrap_or_default()) };
+    let event = create_mouse_event(ctx, "WheelEvent", "wheel", &init)?;
+    event.set("deltaY", delta_y)?;
+    events::dispatch_event(ctx, document, target, event)
+}
+
+// ============================================================================
+// FOCUS
+// ============================================================================
+
+/// Which element has focus, and the keys held down
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyboardState {
+    /// `None` when the body has focus
+    pub focused: Option<usize>,
+    /// The focused text field's value when it gained focus; `change` fires
+    /// on blur if it differs
+    pub value_at_focus: Option<String>,
+    /// Keys down, by `key`; pressing one again is a repeat
+    pub held: Vec<String>,
+    /// Forms submitted, in order
+    pub submissions: Vec<FormSubmission>,
+}
+
+impl KeyboardState {
+    /// The modifier keys held down
+    pub fn modifiers(&self) -> Modifiers {
+        let held = |key: &str| self.held.iter().any(|k| k == key);
+        Modifiers { alt: held("Alt"), ctrl: held("Control"), meta: held("Meta"), shift: held("Shift") }
+    }
+}
+
+/// Whether `idx` can take focus: a link with an `href`, an enabled form
+/// control, an editing host, or an element with a `tabindex`
+pub fn is_focusable(document: &Document, idx: usize) -> bool {
+    match forms::tag_name(document, idx) {
+        Some("a") => forms::has_attribute(document, idx, "href") || tab_index(document, idx).is_some(),
+        Some("input") if forms::input_type(document, idx) == "hidden" => false,
+        Some("button" | "input" | "select" | "textarea") => !forms::has_attribute(document, idx, "disabled"),
+        Some(_) => tab_index(document, idx).is_some() || editing::is_editing_host(document, idx),
+        None => false,
+    }
+}
+
+fn tab_index(document: &Document, idx: usize) -> Option<i32> {
+    document.get_attribute(idx, "tabindex").and_then(|value| value.trim().parse().ok())
+}
+
+/// The focusable elements Tab visits, in order: positive `tabindex`es
+/// ascending, then the rest in tree order; a negative `tabindex` opts out
+pub fn tab_order(document: &Document) -> Vec<usize> {
+    let mut order: Vec<(i32, usize)> = forms::descendants(document, document.root)
+        .into_iter()
+        .filter(|&idx| is_focusable(document, idx))
+        .map(|idx| (tab_index(document, idx).unwrap_or(0), idx))
+        .filter(|&(index, _)| index >= 0)
+        .collect();
+    // Zero sorts after every positive index; the sort is stable, keeping
+    // tree order among equals
+    order.sort_by_key(|&(index, _)| if index == 0 { i32::MAX } else { index });
+    order.into_iter().map(|(_, idx)| idx).collect()
+}
+
+/// The element Tab (or Shift+Tab, when `backwards`) moves focus to from
+/// `from`, wrapping around the document
+pub fn next_in_tab_order(document: &Document, from: Option<usize>, backwards: bool) -> Option<usize> {
+    let order = tab_order(document);
+    let position = from.and_then(|from| order.iter().position(|&idx| idx == from));
+    let next = match (position, backwards) {
+        (None, false) => 0,
+        (None, true) => order.len().checked_sub(1)?,
+        (Some(position), false) => (position + 1) % order.len(),
+        (Some(position), true) => (position + order.len() - 1) % order.len(),
+    };
+    order.get(next).copied()
+}
+
+/// The innermost focusable element holding `idx`, which a click focuses
+pub fn focusable_ancestor(document: &Document, idx: usize) -> Option<usize> {
+    events::event_path(document, idx).into_iter().find(|&node| is_focusable(document, node))
+}
+
+/// Move focus to `target`, or to the body for `None`: `blur` and
+/// `focusout` at the old element, with `change` first if its text was
+/// edited, then `focus` and `focusin` at the new one
+///
+/// Focusing an editable element puts the caret at its end, unless the
+/// selection is already inside it.
+pub fn focus<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    selection: &PageSelection,
+    state: &mut KeyboardState,
+    target: Option<usize>,
+) -> rquickjs::Result<()> {
+    let previous = state.focused;
+    if previous == target {
+        return Ok(());
+    }
+    state.focused = target;
+    if let Some(previous) = previous {
+        let edited = state.value_at_focus.take().is_some_and(|value| value != forms::value(&document.borrow(), previous));
+        if edited {
+            dispatch_simple_event(ctx, document, previous, "change", true, false)?;
+        }
+        dispatch_focus_event(ctx, document, previous, "blur", target)?;
+        dispatch_focus_event(ctx, document, previous, "focusout", target)?;
+    }
+    if let Some(target) = target {
+        state.value_at_focus = {
+            let document = document.borrow();
+            if editing::is_editing_host(&document, target) {
+                let caret = caret_range(&document, selection, target);
+                selection.set(caret.start, caret.end);
+            }
+            forms::is_text_field(&document, target).then(|| forms::value(&document, target))
+        };
+        dispatch_focus_event(ctx, document, target, "focus", previous)?;
+        dispatch_focus_event(ctx, document, target, "focusin", previous)?;
+    }
+    Ok(())
+}
+
+fn dispatch_focus_event<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    target: usize,
+    event_type: &str,
+    related_target: Option<usize>,
+) -> rquickjs::Result<bool> {
+    let init = Object::new(ctx.clone())?;
+    // `focus` and `blur` stay on their target; `focusin` and `focusout` bubble
+    init.set("bubbles", matches!(event_type, "focusin" | "focusout"))?;
+    init.set("composed", true)?;
+    if let Some(related) = related_target {
+        init.set("relatedTarget", related)?;
+    }
+    let event = events::create_event(ctx, "FocusEvent", event_type, init)?;
+    event.set("isTrusted", true)?;
+    events::dispatch_event(ctx, document, target, event)
+}
+
+fn dispatch_simple_event<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    target: usize,
+    event_type: &str,
+    bubbles: bool,
+    cancelable: bool,
+) -> rquickjs::Result<bool> {
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", bubbles)?;
+    init.set("cancelable", cancelable)?;
+    let event = events::create_event(ctx, "Event", event_type, init)?;
+    event.set("isTrusted", true)?;
+    events::dispatch_event(ctx, document, target, event)
+}
+
+// ============================================================================
+// KEYBOARD
+// ============================================================================
+
+/// Modifier keys, as keyboard and mouse events report them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub alt: bool,
+    pub ctrl: bool,
+    pub meta: bool,
+    pub shift: bool,
+}
+
+/// What a keyboard event reports about its key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    /// `KeyboardEvent.key`: the character typed, or the key's name
+    pub key: String,
+    /// `KeyboardEvent.code`: the physical key, e.g. `KeyA` or `ShiftLeft`
+    pub code: String,
+    /// Legacy `keyCode`; 0 when unknown
+    pub key_code: u32,
+    /// 1 for the left-hand modifier keys, otherwise 0
+    pub location: u32,
+}
+
+impl Key {
+    /// The key named `name`, e.g. `a`, `Enter` or `Shift`; with Shift held a
+    /// lowercase letter becomes uppercase
+    pub fn new(name: &str, shift: bool) -> Self {
+        let name = match name {
+            "Space" => " ",
+            "Ctrl" => "Control",
+            "Esc" => "Escape",
+            "Cmd" => "Meta",
+            _ => name,
+        };
+        let mut chars = name.chars();
+        let single = chars.next().filter(|_| chars.next().is_none());
+        let key = match single {
+            Some(c) if shift && c.is_ascii_lowercase() => c.to_ascii_uppercase().to_string(),
+            _ => name.to_string(),
+        };
+        let code = match single {
+            Some(c) if c.is_ascii_alphabetic() => format!("Key{}", c.to_ascii_uppercase()),
+            Some(c) if c.is_ascii_digit() => format!("Digit{}", c),
+            Some(c) => match c {
+                ' ' => "Space",
+                '-' => "Minus",
+                '=' => "Equal",
+                ',' => "Comma",
+                '.' => "Period",
+                '/' => "Slash",
+                ';' => "Semicolon",
+                '\'' => "Quote",
+                '[' => "BracketLeft",
+                ']' => "BracketRight",
+                '\\' => "Backslash",
+                '`' => "Backquote",
+                _ => "",
+            }
+            .to_string(),
+            None if is_modifier(name) => format!("{}Left", name),
+            None => name.to_string(),
+        };
+        let key_code = match (single, name) {
+            (Some(c), _) if c.is_ascii_alphanumeric() || c == ' ' => c.to_ascii_uppercase() as u32,
+            (_, "Backspace") => 8,
+            (_, "Tab") => 9,
+            (_, "Enter") => 13,
+            (_, "Shift") => 16,
+            (_, "Control") => 17,
+            (_, "Alt") => 18,
+            (_, "Escape") => 27,
+            (_, "ArrowLeft") => 37,
+            (_, "ArrowUp") => 38,
+            (_, "ArrowRight") => 39,
+            (_, "ArrowDown") => 40,
+            (_, "Delete") => 46,
+            (_, "Meta") => 91,
+            _ => 0,
+        };
+        Key { key, code, key_code, location: is_modifier(name) as u32 }
+    }
+
+    /// The text the key types, for keys that type any
+    pub fn text(&self) -> Option<&str> {
+        (self.key.chars().count() == 1).then_some(self.key.as_str())
+    }
+}
+
+/// Whether `key` is Shift, Control, Alt or Meta
+pub fn is_modifier(key: &str) -> bool {
+    matches!(key, "Shift" | "Control" | "Alt" | "Meta")
+}
+
+/// Split a combination like `Control+Shift+z` into its modifiers and key;
+/// `+` alone is the plus key
+pub fn parse_key_combo(combo: &str) -> (Vec<String>, String) {
+    if combo.len() <= 1 {
+        return (Vec::new(), combo.to_string());
+    }
+    let (modifiers, key) = match combo.strip_suffix("++") {
+        Some(modifiers) => (modifiers, "+"),
+        None => combo.rsplit_once('+').unwrap_or(("", combo)),
+    };
+    let modifiers = modifiers.split('+').filter(|m| !m.is_empty()).map(|m| Key::new(m, false).key).collect();
+    (modifiers, key.to_string())
+}
+
+/// Where keyboard events go: the focused element, else the body
+pub fn keyboard_target(document: &Document, state: &KeyboardState) -> Option<usize> {
+    state.focused.filter(|&idx| idx < document.nodes.len()).or_else(|| head::body(document))
+}
+
+fn dispatch_key_event<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    target: usize,
+    event_type: &str,
+    key: &Key,
+    modifiers: Modifiers,
+    repeat: bool,
+) -> rquickjs::Result<bool> {
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", true)?;
+    init.set("cancelable", true)?;
+    init.set("composed", true)?;
+    init.set("key", key.key.as_str())?;
+    init.set("code", key.code.as_str())?;
+    init.set("location", key.location)?;
+    init.set("repeat", repeat)?;
+    init.set("altKey", modifiers.alt)?;
+    init.set("ctrlKey", modifiers.ctrl)?;
+    init.set("metaKey", modifiers.meta)?;
+    init.set("shiftKey", modifiers.shift)?;
+    // Legacy codes: `keypress` reports the character, the others the key
+    let char_code = key.text().and_then(|text| text.chars().next()).map_or(13, |c| c as u32);
+    let legacy = if event_type == "keypress" { char_code } else { key.key_code };
+    init.set("keyCode", legacy)?;
+    init.set("which", legacy)?;
+    init.set("charCode", if event_type == "keypress" { char_code } else { 0 })?;
+    let event = events::create_event(ctx, "KeyboardEvent", event_type, init)?;
+    event.set("isTrusted", true)?;
+    events::dispatch_event(ctx, document, target, event)
+}
+
+/// Press `name` down, firing `keydown` (a repeat if it is already down) and
+/// running what the key does unless a listener cancels it; returns `false`
+/// if one did
+pub fn key_down<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    selection: &PageSelection,
+    state: &mut KeyboardState,
+    name: &str,
+) -> rquickjs::Result<bool> {
+    let key = Key::new(name, state.modifiers().shift);
+    let repeat = state.held.contains(&key.key);
+    if !repeat {
+        state.held.push(key.key.clone());
+    }
+    let modifiers = state.modifiers();
+    let Some(target) = keyboard_target(&document.borrow(), state) else {
+        return Ok(true);
+    };
+    if !dispatch_key_event(ctx, document, target, "keydown", &key, modifiers, repeat)? {
+        return Ok(false);
+    }
+    let typing = !modifiers.ctrl && !modifiers.meta && !modifiers.alt;
+    match key.key.as_str() {
+        "Tab" if !modifiers.ctrl && !modifiers.alt => {
+            let next = next_in_tab_order(&document.borrow(), state.focused, modifiers.shift);
+            if next.is_some() {
+                focus(ctx, document, selection, state, next)?;
+            }
+        }
+        "Enter" if typing => {
+            if dispatch_key_event(ctx, document, target, "keypress", &key, modifiers, repeat)? {
+                enter(ctx, document, selection, state, target)?;
+            }
+        }
+        "Backspace" if typing => {
+            edit_text(ctx, document, selection, target, Edit::DeleteBackward)?;
+        }
+        _ => {
+            if let Some(text) = key.text().filter(|_| typing) {
+                if dispatch_key_event(ctx, document, target, "keypress", &key, modifiers, repeat)? {
+                    edit_text(ctx, document, selection, target, Edit::InsertText(text))?;
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Release `name`, firing `keyup` at whatever has focus now
+pub fn key_up<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, state: &mut KeyboardState, name: &str) -> rquickjs::Result<bool> {
+    let key = Key::new(name, state.modifiers().shift);
+    state.held.retain(|held| !held.eq_ignore_ascii_case(&key.key));
+    let Some(target) = keyboard_target(&document.borrow(), state) else {
+        return Ok(true);
+    };
+    dispatch_key_event(ctx, document, target, "keyup", &key, state.modifiers(), false)
+}
+
+/// Enter in a textarea or an editable element starts a new line; in
+/// another text field it submits the field's form
+fn enter<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    selection: &PageSelection,
+    state: &mut KeyboardState,
+    target: usize,
+) -> rquickjs::Result<()> {
+    if forms::tag_name(&document.borrow(), target) == Some("textarea") {
+        return edit_text(ctx, document, selection, target, Edit::InsertLineBreak).map(|_| ());
+    }
+    if editing::editing_host(&document.borrow(), target).is_some() {
+        return edit_text(ctx, document, selection, target, Edit::InsertParagraph).map(|_| ());
+    }
+    let form = {
+        let document = document.borrow();
+        forms::is_text_field(&document, target).then(|| forms::form_owner(&document, target)).flatten()
+    };
+    if let Some(form) = form {
+        if let Some(submission) = submit_implicitly(ctx, document, form)? {
+            state.submissions.push(submission);
+        }
+    }
+    Ok(())
+}
+
+/// Edit the focused text field or editable element as typing does: a
+/// cancelable `beforeinput`, the change, then `input`; returns whether
+/// anything changed
+///
+/// Text fields are edited at the end of their value. Editable elements are
+/// edited at the selection, which is left collapsed after the edit.
+pub fn edit_text<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    selection: &PageSelection,
+    target: usize,
+    edit: Edit,
+) -> rquickjs::Result<bool> {
+    let host = editing::editing_host(&document.borrow(), target);
+    let editable = host.is_some() || forms::is_editable_text_field(&document.borrow(), target);
+    if !editable {
+        return Ok(false);
+    }
+    if !editing::dispatch_input_event(ctx, document, target, "beforeinput", edit.input_type(), edit.data())? {
+        return Ok(false);
+    }
+    let changed = match host {
+        Some(host) => {
+            let caret = {
+                let mut document = document.borrow_mut();
+                let range = caret_range(&document, selection, host);
+                editing::apply_edit(&mut document, host, range, edit)
+            };
+            caret.inspect(|&caret| selection.set(caret, caret)).is_some()
+        }
+        None => {
+            let before = forms::value(&document.borrow(), target);
+            let mut value = before.clone();
+            match edit {
+                Edit::InsertText(text) => value.push_str(text),
+                Edit::InsertLineBreak | Edit::InsertParagraph => value.push('\n'),
+                Edit::DeleteBackward => {
+                    value.pop();
+                }
+            }
+            forms::set_value(&mut document.borrow_mut(), target, &value);
+            value != before
+        }
+    };
+    if changed {
+        editing::dispatch_input_event(ctx, document, target, "input", edit.input_type(), edit.data())?;
+    }
+    Ok(changed)
+}
+
+/// The selection inside editing host `host`, or a caret at its end
+fn caret_range(document: &Document, selection: &PageSelection, host: usize) -> Range {
+    selection.get().range(document).and_then(|range| editing::range_in(document, host, range)).unwrap_or_else(|| {
+        let caret = editing::end_of(document, host);
+        Range { start: caret, end: caret }
+    })
+}
+
+// ============================================================================
+// FORM SUBMISSION
+// ============================================================================
+
+/// A form submitted by the user, with what it would have sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormSubmission {
+    pub form: usize,
+    /// The button that submitted it, if any
+    pub submitter: Option<usize>,
+    /// Name/value pairs, as `forms::serialize_form` gives them
+    pub entries: Vec<(String, String)>,
+}
+
+fn is_submit_button(document: &Document, idx: usize) -> bool {
+    match forms::tag_name(document, idx) {
+        Some("button") => document.get_attribute(idx, "type").is_none_or(|kind| kind.trim().eq_ignore_ascii_case("submit")),
+        Some("input") => matches!(forms::input_type(document, idx).as_str(), "submit" | "image"),
+        _ => false,
+    }
+}
+
+/// The form's default button: its first submit button in tree order
+pub fn default_button(document: &Document, form: usize) -> Option<usize> {
+    forms::descendants(document, form).into_iter().find(|&idx| is_submit_button(document, idx))
+}
+
+/// Submit `form` as Enter in one of its fields does: by clicking its
+/// default button, or directly when it has none; a disabled default
+/// button blocks it
+pub fn submit_implicitly<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, form: usize) -> rquickjs::Result<Option<FormSubmission>> {
+    let button = default_button(&document.borrow(), form);
+    match button {
+        Some(button) if forms::has_attribute(&document.borrow(), button, "disabled") => Ok(None),
+        Some(button) => match click(ctx, document, button, &MouseEventInit::default())? {
+            Some(Activation::Submitted(submission)) => Ok(Some(submission)),
+            _ => Ok(None),
+        },
+        None => submit(ctx, document, form, None),
+    }
+}
+
+/// Fire a cancelable `submit` at `form` and, unless a listener canceled it,
+/// return what it submits
+pub fn submit<'js>(
+    ctx: &Ctx<'js>,
+    document: &RefCell<Document>,
+    form: usize,
+    submitter: Option<usize>,
+) -> rquickjs::Result<Option<FormSubmission>> {
+    let init = Object::new(ctx.clone())?;
+    init.set("bubbles", true)?;
+    init.set("cancelable", true)?;
+    if let Some(submitter) = submitter {
+        init.set("submitter", submitter)?;
+    }
+    let event = events::create_event(ctx, "SubmitEvent", "submit", init)?;
+    event.set("isTrusted", true)?;
+    if !events::dispatch_event(ctx, document, form, event)? {
+        return Ok(None);
+    }
+    let entries = forms::serialize_form(&document.borrow(), form);
+    Ok(Some(FormSubmission { form, submitter, entries }))
+}
+
+/// `UIEvent`, `MouseEvent`, `WheelEvent`, `FocusEvent`, `KeyboardEvent`,
+/// `InputEvent` and `SubmitEvent`, over the `Event` of the `events`
+/// bindings
+const USER_EVENTS_PRELUDE: &str = r#"
+(() => {
+    class UIEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.detail = Number(init.detail ?? 0);
+            this.view = init.view ?? null;
+        }
+    }
+    class MouseEvent extends UIEvent {
+        constructor(type, init = {}) {
+            super(type, init);
+            for (const name of ["clientX", "clientY", "pageX", "pageY", "screenX", "screenY", "button", "buttons"]) {
+                this[name] = Number(init[name] ?? 0);
+            }
+            this.x = this.clientX;
+            this.y = this.clientY;
+            for (const name of ["altKey", "ctrlKey", "metaKey", "shiftKey"]) {
+                this[name] = Boolean(init[name]);
+            }
+            this.relatedTarget = init.relatedTarget ?? null;
+        }
+        getModifierState(key) {
+            return { Alt: this.altKey, Control: this.ctrlKey, Meta: this.metaKey, Shift: this.shiftKey }[key] ?? false;
+        }
+    }
+    class WheelEvent extends MouseEvent {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.deltaX = Number(init.deltaX ?? 0);
+            this.deltaY = Number(init.deltaY ?? 0);
+            this.deltaZ = Number(init.deltaZ ?? 0);
+            this.deltaMode = Number(init.deltaMode ?? WheelEvent.DOM_DELTA_PIXEL);
+        }
+    }
+    Object.assign(WheelEvent, { DOM_DELTA_PIXEL: 0, DOM_DELTA_LINE: 1, DOM_DELTA_PAGE: 2 });
+    class FocusEvent extends UIEvent {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.relatedTarget = init.relatedTarget ?? null;
+        }
+    }
+    class KeyboardEvent extends UIEvent {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.key = String(init.key ?? "");
+            this.code = String(init.code ?? "");
+            this.location = Number(init.location ?? 0);
+            this.repeat = Boolean(init.repeat);
+            this.isComposing = Boolean(init.isComposing);
+            for (const name of ["altKey", "ctrlKey", "metaKey", "shiftKey"]) {
+                this[name] = Boolean(init[name]);
+            }
+            for (const name of ["keyCode", "charCode", "which"]) {
+                this[name] = Number(init[name] ?? 0);
+            }
+        }
+        getModifierState(key) {
+            return MouseEvent.prototype.getModifierState.call(this, key);
+        }
+    }
+    class InputEvent extends UIEvent {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.data = init.data ?? null;
+            this.inputType = String(init.inputType ?? "");
+            this.isComposing = Boolean(init.isComposing);
+        }
+    }
+    class SubmitEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.submitter = init.submitter ?? null;
+        }
+    }
+
+    globalThis.UIEvent = UIEvent;
+    globalThis.MouseEvent = MouseEvent;
+    globalThis.WheelEvent = WheelEvent;
+    globalThis.FocusEvent = FocusEvent;
+    globalThis.KeyboardEvent = KeyboardEvent;
+    globalThis.InputEvent = InputEvent;
+    globalThis.SubmitEvent = SubmitEvent;
+})();
+"#;
+
+/// Install the user event classes
+///
+/// Needs the `events` bindings installed first.
+pub fn install_user_events(ctx: &Ctx<'_>) -> rquickjs::Result<()> {
+    ctx.eval::<(), _>(USER_EVENTS_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+    use rquickjs::{Context, Runtime};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_mouse_events_carry_the_point() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        let document = Rc::new(RefCell::new(parse_html("<html><body><button>Buy</button></body></html>")));
+        let button = {
+            let doc = document.borrow();
+            (0..doc.nodes.len()).find(|&i| crate::forms::tag_name(&doc, i) == Some("button")).unwrap()
+        };
+        let body = document.borrow().nodes[button].parent.unwrap();
+
+        context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            events::install_events(&ctx, document.clone()).unwrap();
+            install_user_events(&ctx).unwrap();
+            ctx.globals().set("body", body).unwrap();
+            ctx.eval::<(), _>(
+                r#"
+                globalThis.seen = [];
+                addEventListener(body, "click", e => seen.push([e instanceof MouseEvent, e.isTrusted, e.clientX, e.y, e.target].join()));
+                addEventListener(body, "mouseenter", () => seen.push("never: mouseenter does not bubble"));
+                "#,
+            )
+            .unwrap();
+
+            let init = MouseEventInit::at(Point::new(12.5, 40.0));
+            assert!(dispatch_mouse_event(&ctx, &document, button, "click", &init).unwrap());
+            dispatch_mouse_event(&ctx, &document, button, "mouseenter", &init).unwrap();
+            let seen: Vec<String> = ctx.eval("seen").unwrap();
+            assert_eq!(seen, [format!("true,true,12.5,40,{}", button)]);
+            let manual: bool = ctx.eval("new MouseEvent('click', { shiftKey: true }).getModifierState('Shift')").unwrap();
+            assert!(manual);
+        });
+    }
+
+    #[test]
+    fn test_keys_and_combos() {
+        assert_eq!(Key::new("a", true), Key { key: "A".into(), code: "KeyA".into(), key_code: 65, location: 0 });
+        assert_eq!(Key::new("Space", false).code, "Space");
+        assert_eq!(Key::new("Ctrl", false), Key { key: "Control".into(), code: "ControlLeft".into(), key_code: 17, location: 1 });
+        assert_eq!(Key::new("Enter", false).text(), None);
+        assert_eq!(parse_key_combo("Ctrl+Shift+z"), (vec!["Control".to_string(), "Shift".to_string()], "z".to_string()));
+        assert_eq!(parse_key_combo("Shift++"), (vec!["Shift".to_string()], "+".to_string()));
+        assert_eq!(parse_key_combo("+"), (vec![], "+".to_string()));
+    }
+
+    #[test]
+    fn test_tab_order_follows_tabindex() {
+        // Given: Controls in tree order, two with positive tabindexes, one
+        // opted out and one disabled
+        let document = parse_html(
+            r#"<html><body><input id="a"/><button id="b" tabindex="2">B</button><a id="c" href="/">C</a>
+            <div id="d" tabindex="1">D</div><span id="e" tabindex="-1">E</span><select id="f" disabled=""></select></body></html>"#,
+        );
+        let by_id = |id: &str| {
+            (0..document.nodes.len()).find(|&i| document.get_attribute(i, "id").map(String::as_str) == Some(id)).unwrap()
+        };
+        let [a, b, c, d, e] = ["a", "b", "c", "d", "e"].map(by_id);
+
+        // Then: Positive indexes come first, and Tab wraps in both directions
+        assert_eq!(tab_order(&document), [d, b, a, c]);
+        assert!(is_focusable(&document, e));
+        assert_eq!(next_in_tab_order(&document, None, false), Some(d));
+        assert_eq!(next_in_tab_order(&document, Some(c), false), Some(d));
+        assert_eq!(next_in_tab_order(&document, Some(d), true), Some(c));
+        assert_eq!(next_in_tab_order(&document, Some(e), true), Some(c));
+    }
+}
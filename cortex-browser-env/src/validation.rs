@@ -0,0 +1,494 @@
+//! Constraint Validation
+//! `required`, length, range, step, pattern and type checks for form
+//! controls, with `checkValidity()` and `invalid` events
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use regex::Regex;
+use rquickjs::{Ctx, Function, Object};
+
+use crate::dom::Document;
+use crate::events;
+use crate::handles::JsNode;
+use crate::forms::{
+    descendants, has_attribute, input_type, is_checkable, is_checked, is_radio, options, radio_group,
+    selected_options, tag_name, value,
+};
+
+/// Which constraints a control fails, mirroring the DOM `ValidityState`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidityState {
+    pub value_missing: bool,
+    pub type_mismatch: bool,
+    pub pattern_mismatch: bool,
+    pub too_long: bool,
+    pub too_short: bool,
+    pub range_underflow: bool,
+    pub range_overflow: bool,
+    pub step_mismatch: bool,
+    pub bad_input: bool,
+}
+
+impl ValidityState {
+    /// True when no constraint fails
+    pub fn valid(&self) -> bool {
+        *self == ValidityState::default()
+    }
+}
+
+/// Input types whose value is free text that `pattern` and lengths apply to
+const TEXT_TYPES: &[&str] = &["text", "search", "url", "tel", "email", "password"];
+
+/// Check whether a control takes part in constraint validation
+///
+/// Disabled controls, read-only text fields and hidden, reset and button
+/// inputs are barred.
+pub fn will_validate(document: &Document, idx: usize) -> bool {
+    let Some(tag) = tag_name(document, idx) else {
+        return false;
+    };
+    if !matches!(tag, "input" | "select" | "textarea") || has_attribute(document, idx, "disabled") {
+        return false;
+    }
+    if tag != "select" && has_attribute(document, idx, "readonly") {
+        return false;
+    }
+    !(tag == "input" && matches!(input_type(document, idx).as_str(), "hidden" | "reset" | "button"))
+}
+
+/// Compute the validity flags of a control
+///
+/// Controls that do not validate are always valid.
+pub fn validity(document: &Document, idx: usize) -> ValidityState {
+    let mut state = ValidityState::default();
+    if !will_validate(document, idx) {
+        return state;
+    }
+
+    match tag_name(document, idx) {
+        Some("select") => state.value_missing = select_value_missing(document, idx),
+        Some("textarea") => {
+            let text = value(document, idx);
+            state.value_missing = has_attribute(document, idx, "required") && text.is_empty();
+            check_lengths(document, idx, &text, &mut state);
+        }
+        _ => check_input(document, idx, &mut state),
+    }
+    state
+}
+
+fn check_input(document: &Document, idx: usize, state: &mut ValidityState) {
+    let kind = input_type(document, idx);
+    let required = has_attribute(document, idx, "required");
+
+    if is_radio(document, idx) {
+        let group = radio_group(document, idx);
+        state.value_missing = group.iter().any(|&r| has_attribute(document, r, "required"))
+            && !group.iter().any(|&r| is_checked(document, r));
+        return;
+    }
+    if is_checkable(document, idx) {
+        state.value_missing = required && !is_checked(document, idx);
+        return;
+    }
+
+    if kind == "file" {
+        state.value_missing = required && document.form_state(idx).files.is_empty();
+        return;
+    }
+
+    let text = value(document, idx);
+    state.value_missing = required && text.is_empty();
+    if text.is_empty() {
+        return;
+    }
+
+    match kind.as_str() {
+        "email" => {
+            let multiple = has_attribute(document, idx, "multiple");
+            let addresses: Vec<&str> = if multiple { text.split(',').map(str::trim).collect() } else { vec![&text] };
+            state.type_mismatch = !addresses.iter().all(|a| is_valid_email(a));
+        }
+        "url" => state.type_mismatch = !is_absolute_url(&text),
+        "number" | "range" => {
+            check_number(document, idx, &text, state);
+            return;
+        }
+        _ => {}
+    }
+
+    if TEXT_TYPES.contains(&kind.as_str()) {
+        check_lengths(document, idx, &text, state);
+        state.pattern_mismatch = pattern_mismatch(document, idx, &text, &kind);
+    }
+}
+
+/// `minlength`/`maxlength`, which only apply once the user has edited the value
+fn check_lengths(document: &Document, idx: usize, text: &str, state: &mut ValidityState) {
+    if document.form_state(idx).value.is_none() || text.is_empty() {
+        return;
+    }
+    // Lengths are counted in UTF-16 code units, as in JavaScript
+    let length = text.encode_utf16().count();
+    if let Some(max) = length_attr(document, idx, "maxlength") {
+        state.too_long = length > max;
+    }
+    if let Some(min) = length_attr(document, idx, "minlength") {
+        state.too_short = length < min;
+    }
+}
+
+fn length_attr(document: &Document, idx: usize, name: &str) -> Option<usize> {
+    document.get_attribute(idx, name)?.trim().parse().ok()
+}
+
+fn number_attr(document: &Document, idx: usize, name: &str) -> Option<f64> {
+    parse_number(document.get_attribute(idx, name)?)
+}
+
+/// Parse an HTML floating-point number: finite, no leading `+`
+fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.starts_with('+') {
+        return None;
+    }
+    text.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+fn check_number(document: &Document, idx: usize, text: &str, state: &mut ValidityState) {
+    let Some(number) = parse_number(text) else {
+        state.bad_input = true;
+        return;
+    };
+    let min = number_attr(document, idx, "min");
+    if let Some(min) = min {
+        state.range_underflow = number < min;
+    }
+    if let Some(max) = number_attr(document, idx, "max") {
+        state.range_overflow = number > max;
+    }
+
+    let step = match document.get_attribute(idx, "step") {
+        Some(step) if step.trim().eq_ignore_ascii_case("any") => None,
+        Some(step) => Some(parse_number(step).filter(|s| *s > 0.0).unwrap_or(1.0)),
+        None => Some(1.0),
+    };
+    if let Some(step) = step {
+        let steps = (number - min.unwrap_or(0.0)) / step;
+        state.step_mismatch = (steps - steps.round()).abs() > 1e-9;
+    }
+}
+
+/// Check the `pattern` attribute, which must match the whole value
+///
+/// Invalid patterns are ignored, as in browsers.
+fn pattern_mismatch(document: &Document, idx: usize, text: &str, kind: &str) -> bool {
+    let Some(pattern) = document.get_attribute(idx, "pattern") else {
+        return false;
+    };
+    let Ok(regex) = Regex::new(&format!("^(?:{})$", pattern)) else {
+        return false;
+    };
+    if kind == "email" && has_attribute(document, idx, "multiple") {
+        return !text.split(',').all(|a| regex.is_match(a.trim()));
+    }
+    !regex.is_match(text)
+}
+
+fn is_valid_email(address: &str) -> bool {
+    // The HTML specification's deliberately simple address grammar
+    let email = Regex::new(
+        r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$",
+    )
+    .expect("valid email regex");
+    email.is_match(address)
+}
+
+fn is_absolute_url(text: &str) -> bool {
+    let url = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:\S+$").expect("valid URL regex");
+    url.is_match(text)
+}
+
+/// A required select is missing a value when nothing is selected, or when a
+/// single-select still shows its placeholder: a first option with an empty
+/// value directly inside the select
+fn select_value_missing(document: &Document, idx: usize) -> bool {
+    if !has_attribute(document, idx, "required") {
+        return false;
+    }
+    let selected = selected_options(document, idx);
+    let Some(&first_selected) = selected.first() else {
+        return true;
+    };
+    if has_attribute(document, idx, "multiple") {
+        return false;
+    }
+    options(document, idx).first() == Some(&first_selected)
+        && document.nodes[first_selected].parent == Some(idx)
+        && value(document, first_selected).is_empty()
+}
+
+/// Fire a trusted, cancelable `invalid` at control `idx`, returning `false`
+/// if a listener canceled it
+fn fire_invalid<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, idx: usize) -> rquickjs::Result<bool> {
+    let init = Object::new(ctx.clone())?;
+    init.set("cancelable", true)?;
+    let event = events::create_event(ctx, "Event", "invalid", init)?;
+    event.set("isTrusted", true)?;
+    events::dispatch_event(ctx, document, idx, event)
+}
+
+/// `element.checkValidity()`: fires `invalid` on the control when it fails
+pub fn check_validity<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, idx: usize) -> rquickjs::Result<bool> {
+    if tag_name(&document.borrow(), idx) == Some("form") {
+        return check_form_validity(ctx, document, idx);
+    }
+    let valid = validity(&document.borrow(), idx).valid();
+    if !valid {
+        fire_invalid(ctx, document, idx)?;
+    }
+    Ok(valid)
+}
+
+/// `form.checkValidity()`: fires `invalid` on every failing control in tree
+/// order and returns whether all of them passed
+pub fn check_form_validity<'js>(ctx: &Ctx<'js>, document: &RefCell<Document>, form_idx: usize) -> rquickjs::Result<bool> {
+    let failing: Vec<usize> = {
+        let document = document.borrow();
+        descendants(&document, form_idx)
+            .into_iter()
+            .filter(|&idx| will_validate(&document, idx) && !validity(&document, idx).valid())
+            .collect()
+    };
+    for &idx in &failing {
+        fire_invalid(ctx, document, idx)?;
+    }
+    Ok(failing.is_empty())
+}
+
+// ============================================================================
+// JAVASCRIPT BINDINGS
+// ============================================================================
+
+/// Expose constraint validation to JavaScript as index-based globals
+///
+/// - `checkValidity(idx)` for a control or a whole form
+/// - `getValidity(idx)`, returning an object shaped like `ValidityState`
+pub fn install_validation_bindings<'js>(ctx: &Ctx<'js>, document: Rc<RefCell<Document>>) -> rquickjs::Result<()> {
+    let globals = ctx.globals();
+
+    let doc = document.clone();
+    globals.set(
+        "checkValidity",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<bool> {
+            let idx = node.live(&ctx, &doc.borrow())?;
+            check_validity(&ctx, &doc, idx)
+        })?,
+    )?;
+
+    let doc = document;
+    globals.set(
+        "getValidity",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, node: JsNode| -> rquickjs::Result<Object<'js>> {
+            let state = {
+                let document = doc.borrow();
+                validity(&document, node.live(&ctx, &document)?)
+            };
+            let obj = Object::new(ctx)?;
+            obj.set("valueMissing", state.value_missing)?;
+            obj.set("typeMismatch", state.type_mismatch)?;
+            obj.set("patternMismatch", state.pattern_mismatch)?;
+            obj.set("tooLong", state.too_long)?;
+            obj.set("tooShort", state.too_short)?;
+            obj.set("rangeUnderflow", state.range_underflow)?;
+            obj.set("rangeOverflow", state.range_overflow)?;
+            obj.set("stepMismatch", state.step_mismatch)?;
+            obj.set("badInput", state.bad_input)?;
+            obj.set("valid", state.valid())?;
+            Ok(obj)
+        })?,
+    )?;
+
+    Ok(())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forms::{set_checked, set_value};
+
+    fn add(doc: &mut Document, parent: usize, tag: &str, attrs: &[(&str, &str)]) -> usize {
+        let idx = doc.create_element(tag);
+        for (name, value) in attrs {
+            doc.set_attribute(idx, name, value);
+        }
+        doc.append_child(parent, idx);
+        idx
+    }
+
+    #[test]
+    fn test_required_text_and_checkbox() {
+        // Given: A required text field and a required checkbox, both empty
+        let mut doc = Document::new();
+        let root = doc.root;
+        let name = add(&mut doc, root, "input", &[("required", "")]);
+        let terms = add(&mut doc, root, "input", &[("type", "checkbox"), ("required", "")]);
+
+        assert!(validity(&doc, name).value_missing);
+        assert!(validity(&doc, terms).value_missing);
+
+        // When: The user fills them in
+        set_value(&mut doc, name, "Ada");
+        set_checked(&mut doc, terms, true);
+
+        // Then: Both are valid
+        assert!(validity(&doc, name).valid());
+        assert!(validity(&doc, terms).valid());
+    }
+
+    #[test]
+    fn test_required_radio_group() {
+        let mut doc = Document::new();
+        let root = doc.root;
+        let yes = add(&mut doc, root, "input", &[("type", "radio"), ("name", "rsvp"), ("required", "")]);
+        let no = add(&mut doc, root, "input", &[("type", "radio"), ("name", "rsvp")]);
+
+        // Every member of the group reports the missing value
+        assert!(validity(&doc, no).value_missing);
+
+        set_checked(&mut doc, no, true);
+        assert!(validity(&doc, yes).valid());
+    }
+
+    #[test]
+    fn test_length_limits_apply_after_editing() {
+        // Given: A field whose default value already breaks minlength
+        let mut doc = Document::new();
+        let root = doc.root;
+        let code = add(&mut doc, root, "input", &[("value", "ab"), ("minlength", "3"), ("maxlength", "5")]);
+        assert!(validity(&doc, code).valid(), "Default values are not length-checked");
+
+        // When: The user types too little, then too much
+        set_value(&mut doc, code, "x");
+        assert!(validity(&doc, code).too_short);
+        set_value(&mut doc, code, "abcdef");
+        assert!(validity(&doc, code).too_long);
+
+        // Then: A length in range is valid
+        set_value(&mut doc, code, "abcd");
+        assert!(validity(&doc, code).valid());
+    }
+
+    #[test]
+    fn test_number_range_step_and_bad_input() {
+        let mut doc = Document::new();
+        let root = doc.root;
+        let qty = add(&mut doc, root, "input", &[("type", "number"), ("min", "1"), ("max", "10"), ("step", "0.5")]);
+
+        let check = |doc: &mut Document, text: &str| {
+            set_value(doc, qty, text);
+            validity(doc, qty)
+        };
+
+        assert!(check(&mut doc, "0").range_underflow);
+        assert!(check(&mut doc, "11").range_overflow);
+        assert!(check(&mut doc, "2.25").step_mismatch);
+        assert!(check(&mut doc, "two").bad_input);
+        assert!(check(&mut doc, "2.5").valid());
+        assert!(check(&mut doc, "").valid(), "Empty optional number is valid");
+    }
+
+    #[test]
+    fn test_email_url_and_pattern() {
+        let mut doc = Document::new();
+        let root = doc.root;
+        let email = add(&mut doc, root, "input", &[("type", "email"), ("value", "ada@example")]);
+        let emails = add(&mut doc, root, "input", &[("type", "email"), ("multiple", ""), ("value", "a@x.io, b@")]);
+        let site = add(&mut doc, root, "input", &[("type", "url"), ("value", "example.com")]);
+        let zip = add(&mut doc, root, "input", &[("pattern", "[0-9]{5}"), ("value", "123456")]);
+
+        assert!(validity(&doc, email).valid(), "Dotless domains are allowed");
+        assert!(validity(&doc, emails).type_mismatch);
+        assert!(validity(&doc, site).type_mismatch);
+        assert!(validity(&doc, zip).pattern_mismatch, "Pattern must match the whole value");
+
+        set_value(&mut doc, email, "not an email");
+        set_value(&mut doc, zip, "12345");
+        assert!(validity(&doc, email).type_mismatch);
+        assert!(validity(&doc, zip).valid());
+    }
+
+    #[test]
+    fn test_required_select_placeholder() {
+        // Given: A required select whose first option is an empty placeholder
+        let mut doc = Document::new();
+        let root = doc.root;
+        let select = add(&mut doc, root, "select", &[("required", "")]);
+        add(&mut doc, select, "option", &[("value", "")]);
+        add(&mut doc, select, "option", &[("value", "cat")]);
+
+        assert!(validity(&doc, select).value_missing);
+
+        crate::forms::set_selected_index(&mut doc, select, 1);
+        assert!(validity(&doc, select).valid());
+    }
+
+    #[test]
+    fn test_barred_controls_are_always_valid() {
+        let mut doc = Document::new();
+        let root = doc.root;
+        let disabled = add(&mut doc, root, "input", &[("required", ""), ("disabled", "")]);
+        let readonly = add(&mut doc, root, "input", &[("required", ""), ("readonly", "")]);
+        let hidden = add(&mut doc, root, "input", &[("type", "hidden"), ("required", "")]);
+
+        for idx in [disabled, readonly, hidden] {
+            assert!(!will_validate(&doc, idx));
+            assert!(validity(&doc, idx).valid());
+        }
+    }
+
+    #[test]
+    fn test_form_check_validity_and_js_bindings() {
+        // Given: A form with one valid and one invalid control, and a
+        // listener that cancels `invalid`
+        let mut doc = Document::new();
+        let root = doc.root;
+        let form = add(&mut doc, root, "form", &[]);
+        add(&mut doc, form, "input", &[("name", "a"), ("value", "ok"), ("required", "")]);
+        let missing = add(&mut doc, form, "input", &[("name", "b"), ("required", "")]);
+        let document = Rc::new(RefCell::new(doc));
+
+        let runtime = rquickjs::Runtime::new().unwrap();
+        let context = rquickjs::Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            events::install_events(&ctx, document.clone()).unwrap();
+            install_validation_bindings(&ctx, document.clone()).unwrap();
+            let script = format!(
+                "globalThis.seen = []; \
+                 addEventListener({m}, 'invalid', e => {{ e.preventDefault(); seen.push([e.target, e.isTrusted, e.defaultPrevented].join(':')); }}); \
+                 addEventListener({f}, 'invalid', () => seen.push('form'));",
+                m = missing, f = form
+            );
+            ctx.eval::<(), _>(script).unwrap();
+
+            // When: Script checks the form and inspects the failing field
+            let script = format!(
+                "const v = getValidity({m}); [checkValidity({f}), v.valid, v.valueMissing, v.tooLong].join(',')",
+                m = missing, f = form
+            );
+            let result: String = ctx.eval(script).unwrap();
+
+            // Then: The form is invalid because of the empty required field,
+            // whose listener saw a trusted, cancelable `invalid` that did
+            // not bubble to the form
+            assert_eq!(result, "false,false,true,false");
+            let seen: String = ctx.eval("seen.join(' ')").unwrap();
+            assert_eq!(seen, format!("{}:true:true", missing));
+        });
+    }
+}
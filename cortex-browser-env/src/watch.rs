@@ -0,0 +1,400 @@
+//! Watch Mode
+//! Polls the files a test run reads and re-runs the tests a change affects,
+//! printing a short summary after each re-run
+//!
+//! Each script runs in its own page, so a changed script or a module it
+//! imported re-runs alone, while a changed page or stylesheet re-runs every
+//! script. The modules a script imports are learned from each run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::browser::Browser;
+use crate::cli::{is_module_path, Cli, InputSource};
+use crate::encoding;
+use crate::error::{TestResult, TestSummary};
+
+/// What a file looked like when last polled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> Option<FileStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileStamp { modified: metadata.modified().ok(), len: metadata.len() })
+    }
+}
+
+/// Detects changes to a set of files by polling their modification time and
+/// size
+#[derive(Debug, Clone)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<FileStamp>)>,
+}
+
+impl FileWatcher {
+    /// Watch `paths`, taking their current state as unchanged
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut files: Vec<(PathBuf, Option<FileStamp>)> = Vec::new();
+        for path in paths {
+            if !files.iter().any(|(watched, _)| *watched == path) {
+                let stamp = FileStamp::read(&path);
+                files.push((path, stamp));
+            }
+        }
+        FileWatcher { files }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+
+    /// Watch exactly `paths` from now on, keeping what was last seen of
+    /// files already watched
+    pub fn update(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        let mut updated = FileWatcher::new(Vec::new());
+        for path in paths {
+            if updated.files.iter().any(|(watched, _)| *watched == path) {
+                continue;
+            }
+            let stamp = match self.files.iter().find(|(watched, _)| *watched == path) {
+                Some((_, stamp)) => *stamp,
+                None => FileStamp::read(&path),
+            };
+            updated.files.push((path, stamp));
+        }
+        *self = updated;
+    }
+
+    /// Files modified, created or deleted since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, stamp) in &mut self.files {
+            let current = FileStamp::read(path);
+            if current != *stamp {
+                *stamp = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+/// The files a watched test run reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchSet {
+    pub html: Option<PathBuf>,
+    pub css: Option<PathBuf>,
+    pub scripts: Vec<PathBuf>,
+    /// Run every script as an ES module, not just `.mjs` files
+    pub modules: bool,
+    /// The module files each script imported on its latest run, by script
+    pub imports: Vec<Vec<PathBuf>>,
+}
+
+impl WatchSet {
+    /// The files named on a `watch` command line; stdin cannot be watched
+    pub fn from_cli(cli: &Cli) -> Result<WatchSet, String> {
+        let file = |source: &InputSource| match source {
+            InputSource::File(path) => Ok(path.clone()),
+            InputSource::Stdin => Err("Cannot watch stdin".to_string()),
+        };
+        Ok(WatchSet {
+            html: cli.html.as_ref().map(file).transpose()?,
+            css: cli.css.as_ref().map(file).transpose()?,
+            scripts: cli.scripts.iter().map(file).collect::<Result<_, _>>()?,
+            modules: cli.modules,
+            imports: Vec::new(),
+        })
+    }
+
+    /// The named files, then every module the scripts imported
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.html.iter().chain(&self.css).chain(&self.scripts).chain(self.imports.iter().flatten()).cloned().collect()
+    }
+
+    /// Indices of the scripts whose tests a change to `changed` affects
+    pub fn affected_scripts(&self, changed: &[PathBuf]) -> Vec<usize> {
+        let page_changed = self.html.iter().chain(&self.css).any(|path| changed.contains(path));
+        let imported = |i: usize| self.imports.get(i).is_some_and(|files| files.iter().any(|path| changed.contains(path)));
+        (0..self.scripts.len())
+            .filter(|&i| page_changed || changed.contains(&self.scripts[i]) || imported(i))
+            .collect()
+    }
+}
+
+/// The latest results of every watched script
+pub struct WatchSession {
+    browser: Browser,
+    files: WatchSet,
+    summaries: Vec<TestSummary>,
+}
+
+impl WatchSession {
+    pub fn new(browser: Browser, mut files: WatchSet) -> Self {
+        let summaries = vec![TestSummary::new(); files.scripts.len()];
+        files.imports.resize(files.scripts.len(), Vec::new());
+        WatchSession { browser, files, summaries }
+    }
+
+    pub fn files(&self) -> &WatchSet {
+        &self.files
+    }
+
+    /// Re-run the given scripts' tests, noting the modules each imported,
+    /// and describe the outcome
+    pub fn run(&mut self, scripts: &[usize]) -> String {
+        for &i in scripts {
+            (self.summaries[i], self.files.imports[i]) = self.run_script(i);
+        }
+        self.format_update(scripts)
+    }
+
+    /// Whether every script's latest run passed
+    pub fn passed(&self) -> bool {
+        self.summaries.iter().all(|summary| summary.failed == 0)
+    }
+
+    /// Run one script in a fresh page, returning its tests' results and the
+    /// module files it imported; unreadable files and uncaught script errors
+    /// count as a failed test named after the script
+    fn run_script(&self, i: usize) -> (TestSummary, Vec<PathBuf>) {
+        let script = &self.files.scripts[i];
+        let name = script.display().to_string();
+        let read = |path: &Path| {
+            fs::read(path).map(|bytes| encoding::decode(&bytes, None).0).map_err(|e| format!("Cannot read '{}': {}", path.display(), e))
+        };
+
+        let mut summary = TestSummary::new();
+        let page = match self.browser.new_page() {
+            Ok(page) => page,
+            Err(e) => {
+                summary.add_result(TestResult::failure(&name, "Could not open page", e));
+                return (summary, Vec::new());
+            }
+        };
+        let setup = || -> Result<String, String> {
+            if let Some(html) = &self.files.html {
+                page.load_html(&read(html)?);
+            }
+            if let Some(css) = &self.files.css {
+                page.add_style(&read(css)?);
+            }
+            read(script)
+        };
+        match setup() {
+            Ok(source) => {
+                let result = if self.files.modules || is_module_path(script) {
+                    page.run_module_file(&source, script)
+                } else {
+                    page.run_script_file(&source, script).map(|_| ())
+                };
+                if let Err(e) = result {
+                    summary.add_result(TestResult::failure(&name, "Script threw an uncaught error", e));
+                }
+            }
+            Err(e) => summary.add_result(TestResult::failure_string(&name, &e)),
+        }
+        for result in page.run_tests().results {
+            summary.add_result(result);
+        }
+        summary.seed = Some(page.seed().0);
+        (summary, page.module_files())
+    }
+
+    /// One line per re-run script with its failures, then totals across all
+    /// scripts
+    fn format_update(&self, scripts: &[usize]) -> String {
+        let mut output = String::new();
+        for &i in scripts {
+            let summary = &self.summaries[i];
+            output.push_str(&format!(
+                "{}: {}/{} passed\n",
+                self.files.scripts[i].display(),
+                summary.passed,
+                summary.total
+            ));
+            for result in summary.failed_tests() {
+                output.push_str(&format!("  ❌ {}: {}\n", result.name, result.message));
+                if let Some(ref error) = result.error {
+                    output.push_str(&format!("     {}\n", error));
+                }
+            }
+        }
+
+        let passed: usize = self.summaries.iter().map(|summary| summary.passed).sum();
+        let total: usize = self.summaries.iter().map(|summary| summary.total).sum();
+        output.push_str(&format!(
+            "Total: {}/{} passed, {} failed (re-ran {} of {} files)\n",
+            passed,
+            total,
+            total - passed,
+            scripts.len(),
+            self.summaries.len()
+        ));
+        if let Some(seed) = self.browser.seed {
+            output.push_str(&format!("Seed: {} (re-run with --seed {} to reproduce)\n", seed, seed));
+        }
+        output
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::ModuleConfig;
+
+    fn write(path: &Path, text: &str) {
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_file_watcher_reports_changes_once() {
+        // Given: A watched file and a file that does not exist yet
+        let dir = tempfile::tempdir().unwrap();
+        let spec = dir.path().join("spec.js");
+        let later = dir.path().join("later.js");
+        write(&spec, "1");
+        let mut watcher = FileWatcher::new(vec![spec.clone(), later.clone(), spec.clone()]);
+        assert_eq!(watcher.paths().count(), 2, "Duplicates are watched once");
+
+        // When: Nothing changes
+        // Then: Polling reports nothing
+        assert!(watcher.poll().is_empty());
+
+        // When: One file changes size and the other appears
+        write(&spec, "12");
+        write(&later, "");
+
+        // Then: Both are reported, and only once
+        assert_eq!(watcher.poll(), vec![spec.clone(), later]);
+        assert!(watcher.poll().is_empty());
+
+        // When: A file is deleted
+        fs::remove_file(&spec).unwrap();
+
+        // Then: That counts as a change too
+        assert_eq!(watcher.poll(), vec![spec]);
+    }
+
+    #[test]
+    fn test_file_watcher_update_keeps_known_files() {
+        // Given: A watcher that has seen one file change
+        let dir = tempfile::tempdir().unwrap();
+        let (spec, util, gone) = (dir.path().join("spec.js"), dir.path().join("util.js"), dir.path().join("gone.js"));
+        write(&spec, "1");
+        write(&util, "1");
+        let mut watcher = FileWatcher::new(vec![spec.clone(), gone.clone()]);
+        write(&spec, "12");
+
+        // When: The watched set gains a file and loses another
+        watcher.update(vec![spec.clone(), util.clone()]);
+
+        // Then: The pending change is still reported, the new file is only
+        // reported once it changes, and the dropped one is not polled
+        assert_eq!(watcher.paths().collect::<Vec<_>>(), vec![spec.as_path(), util.as_path()]);
+        assert_eq!(watcher.poll(), vec![spec]);
+        write(&util, "12");
+        assert_eq!(watcher.poll(), vec![util]);
+    }
+
+    #[test]
+    fn test_affected_scripts() {
+        let files = WatchSet {
+            html: Some(PathBuf::from("page.html")),
+            css: None,
+            scripts: vec![PathBuf::from("a.js"), PathBuf::from("b.js")],
+            modules: false,
+            imports: vec![vec![PathBuf::from("lib/util.js")], Vec::new()],
+        };
+
+        assert_eq!(files.affected_scripts(&[PathBuf::from("b.js")]), vec![1]);
+        assert_eq!(files.affected_scripts(&[PathBuf::from("lib/util.js")]), vec![0]);
+        assert_eq!(files.affected_scripts(&[PathBuf::from("page.html")]), vec![0, 1]);
+        assert!(files.affected_scripts(&[PathBuf::from("other.js")]).is_empty());
+    }
+
+    #[test]
+    fn test_session_reruns_and_summarizes() {
+        // Given: A page and two spec files, one failing
+        let dir = tempfile::tempdir().unwrap();
+        let html = dir.path().join("page.html");
+        let a = dir.path().join("a.js");
+        let b = dir.path().join("b.js");
+        write(&html, r#"<button class="primary">Save</button>"#);
+        write(&a, r#"it("has a button", () => { getByText("Save"); });"#);
+        write(&b, r#"it("is broken", () => { throw new Error("nope"); });"#);
+        let files = WatchSet { html: Some(html), css: None, scripts: vec![a, b.clone()], modules: false, imports: Vec::new() };
+        let mut session = WatchSession::new(Browser::new().with_seed(3), files);
+
+        // When: Everything runs
+        let first = session.run(&[0, 1]);
+
+        // Then: Each file is summarized, with totals
+        assert!(first.contains("a.js: 1/1 passed"), "{}", first);
+        assert!(first.contains("b.js: 0/1 passed"), "{}", first);
+        assert!(first.contains("❌ is broken: "), "{}", first);
+        assert!(first.contains("Total: 1/2 passed, 1 failed (re-ran 2 of 2 files)"), "{}", first);
+        assert!(first.contains("Seed: 3"), "{}", first);
+        assert!(!session.passed());
+
+        // When: The failing file is fixed and only it re-runs
+        write(&b, r#"it("is fixed", () => {});"#);
+        let second = session.run(&session.files().affected_scripts(&[b]));
+
+        // Then: Totals include the earlier file's results
+        assert!(!second.contains("a.js"), "{}", second);
+        assert!(second.contains("Total: 2/2 passed, 0 failed (re-ran 1 of 2 files)"), "{}", second);
+        assert!(session.passed());
+    }
+
+    #[test]
+    fn test_session_watches_the_modules_scripts_import() {
+        // Given: Two module specs, one importing a helper
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (a, b, util) = (root.join("a.mjs"), root.join("b.mjs"), root.join("util.js"));
+        write(&util, "export const answer = 42;");
+        write(&a, r#"import { answer } from './util.js'; it("answers", () => { expect(answer).toBe(42); });"#);
+        write(&b, r#"it("stands alone", () => {});"#);
+        let files = WatchSet { html: None, css: None, scripts: vec![a, b], modules: false, imports: Vec::new() };
+        let browser = Browser::new().with_seed(1).with_modules(ModuleConfig::new(&root));
+        let mut session = WatchSession::new(browser, files);
+
+        // When: Both run
+        session.run(&[0, 1]);
+
+        // Then: The helper is watched, and changing it re-runs only its importer
+        assert!(session.files().paths().contains(&util), "{:?}", session.files().paths());
+        write(&util, "export const answer = 41;");
+        let affected = session.files().affected_scripts(&[util]);
+        assert_eq!(affected, vec![0]);
+        let output = session.run(&affected);
+        assert!(output.contains("a.mjs: 0/1 passed"), "{}", output);
+    }
+
+    #[test]
+    fn test_session_reports_unreadable_and_throwing_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let throws = dir.path().join("throws.js");
+        write(&throws, "it('runs', () => {}); null.x;");
+        let missing = dir.path().join("missing.js");
+        let files = WatchSet { html: None, css: None, scripts: vec![throws, missing], modules: false, imports: Vec::new() };
+        let mut session = WatchSession::new(Browser::new().with_seed(1), files);
+
+        let output = session.run(&[0, 1]);
+
+        assert!(output.contains("throws.js: 1/2 passed"), "{}", output);
+        assert!(output.contains("Script threw an uncaught error"), "{}", output);
+        assert!(output.contains("missing.js: 0/1 passed"), "{}", output);
+        assert!(output.contains("Cannot read '"), "{}", output);
+    }
+}
@@ -0,0 +1,654 @@
+//! WebDriver
+//! A W3C WebDriver HTTP endpoint, so test clients in any language can drive
+//! pages
+//!
+//! Each session is a `Session` tab, and element references are `NodeId`s,
+//! so a stale one answers `stale element reference`. The endpoint listens
+//! on loopback only and refuses requests naming another `Host`, which
+//! keeps web pages from reaching it through DNS rebinding.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use crate::browser::{Page, PageBuilder};
+use crate::dom::NodeId;
+use crate::error::BrowserError;
+use crate::geometry::Point;
+use crate::json::Json;
+use crate::network::{encode_base64, has_scheme};
+use crate::reporters::json_string;
+use crate::screenshot::{ImageFormat, DEFAULT_QUALITY};
+use crate::session::Session;
+
+/// Port `webdriver` listens on unless `--port` says otherwise, as for
+/// other drivers
+pub const DEFAULT_PORT: u16 = 4444;
+
+/// The key of a web element reference object in the spec
+pub const ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f735466cecf";
+
+/// URL reported for a session that has not navigated
+const BLANK_URL: &str = "about:blank";
+
+/// Largest request body accepted; screenshots go out, not in, so commands
+/// stay far below this
+pub const MAX_BODY: usize = 4 * 1024 * 1024;
+
+/// Largest request line plus headers accepted
+pub const MAX_HEADER: usize = 16 * 1024;
+
+/// How long a client may take to send a whole request before it is
+/// dropped, so one slow connection can't block every other session
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An HTTP request to the endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Path, without any query string
+    pub path: String,
+    pub body: String,
+}
+
+/// An HTTP response with a JSON body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    /// A command's result, wrapped as `{"value": ...}`
+    fn success(value: String) -> Response {
+        Response { status: 200, body: format!("{{\"value\":{}}}", value) }
+    }
+}
+
+/// A command failure: an error code from the spec and a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebDriverError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl WebDriverError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        WebDriverError { code, message: message.into() }
+    }
+
+    /// The HTTP status the spec gives the error code
+    pub fn status(&self) -> u16 {
+        match self.code {
+            "invalid argument" | "invalid selector" | "element not interactable" | "element click intercepted" => 400,
+            "invalid session id" | "no such element" | "stale element reference" | "unknown command" => 404,
+            _ => 500,
+        }
+    }
+
+    fn response(&self) -> Response {
+        Response {
+            status: self.status(),
+            body: format!(
+                "{{\"value\":{{\"error\":{},\"message\":{},\"stacktrace\":\"\"}}}}",
+                json_string(self.code),
+                json_string(&self.message)
+            ),
+        }
+    }
+}
+
+impl From<BrowserError> for WebDriverError {
+    fn from(error: BrowserError) -> Self {
+        let code = match error {
+            BrowserError::QueryError(_) => "invalid selector",
+            BrowserError::JavaScriptError(..) => "javascript error",
+            _ => "unknown error",
+        };
+        WebDriverError::new(code, error.to_string())
+    }
+}
+
+/// A tab and the elements handed out to the client, referenced by their
+/// position
+struct DriverSession {
+    session: Session,
+    elements: Vec<NodeId>,
+}
+
+/// The endpoint's sessions
+pub struct WebDriver {
+    builder: PageBuilder,
+    sessions: HashMap<String, DriverSession>,
+    next_session: u64,
+}
+
+impl WebDriver {
+    /// An endpoint opening every session's pages from `builder`
+    pub fn new(builder: PageBuilder) -> Self {
+        WebDriver { builder, sessions: HashMap::new(), next_session: 1 }
+    }
+
+    /// Answer requests from `listener`; a connection that can't be
+    /// accepted, or a client that goes away mid-response, is logged and
+    /// skipped
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::warn!(error = %e, "Cannot accept WebDriver client");
+                    continue;
+                }
+            };
+            let deadline = Instant::now() + REQUEST_TIMEOUT;
+            let response = match read_request(&mut Deadline { stream: &stream, deadline }) {
+                Ok(request) => {
+                    tracing::debug!(method = %request.method, path = %request.path, "WebDriver command");
+                    self.handle(&request)
+                }
+                Err(e) => WebDriverError::new("invalid argument", format!("Malformed request: {}", e)).response(),
+            };
+            if let Err(e) = write_response(&mut stream, &response) {
+                tracing::warn!(error = %e, "Cannot answer WebDriver client");
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one command
+    pub fn handle(&mut self, request: &Request) -> Response {
+        match self.route(request) {
+            Ok(value) => Response::success(value),
+            Err(e) => e.response(),
+        }
+    }
+
+    fn route(&mut self, request: &Request) -> Result<String, WebDriverError> {
+        let segments: Vec<&str> = request.path.split('/').filter(|segment| !segment.is_empty()).collect();
+        let body = || parse_body(&request.body);
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["status"]) => Ok(format!(
+                "{{\"ready\":true,\"message\":{}}}",
+                json_string(&format!("{} sessions open", self.sessions.len()))
+            )),
+            ("POST", ["session"]) => {
+                body()?;
+                self.new_session()
+            }
+            ("DELETE", ["session", id]) => match self.sessions.remove(*id) {
+                Some(_) => Ok("null".to_string()),
+                None => Err(invalid_session(id)),
+            },
+            (method, ["session", id, command @ ..]) => {
+                let session = self.sessions.get_mut(*id).ok_or_else(|| invalid_session(id))?;
+                session.run(method, command, body)
+            }
+            (method, _) => Err(WebDriverError::new("unknown command", format!("No command {} {}", method, request.path))),
+        }
+    }
+
+    fn new_session(&mut self) -> Result<String, WebDriverError> {
+        let id = format!("cortex-{}", self.next_session);
+        let session = Session::new(self.builder.clone()).map_err(|e| WebDriverError::new("session not created", e.to_string()))?;
+        self.next_session += 1;
+        self.sessions.insert(id.clone(), DriverSession { session, elements: Vec::new() });
+        Ok(format!(
+            "{{\"sessionId\":{},\"capabilities\":{{\"browserName\":\"cortex-browser-env\",\"browserVersion\":{},\"acceptInsecureCerts\":false,\"pageLoadStrategy\":\"normal\"}}}}",
+            json_string(&id),
+            json_string(env!("CARGO_PKG_VERSION"))
+        ))
+    }
+}
+
+impl DriverSession {
+    fn page(&self) -> &Page {
+        self.session.page()
+    }
+
+    fn run(&mut self, method: &str, command: &[&str], body: impl Fn() -> Result<Json, WebDriverError>) -> Result<String, WebDriverError> {
+        match (method, command) {
+            ("POST", ["url"]) => {
+                let body = body()?;
+                let url = string_field(&body, "url")?;
+                if !has_scheme(url.trim()) {
+                    return Err(WebDriverError::new("invalid argument", format!("Expected an absolute URL, got '{}'", url)));
+                }
+                self.session.navigate(url)?;
+                Ok("null".to_string())
+            }
+            ("GET", ["url"]) => Ok(json_string(self.session.url().unwrap_or(BLANK_URL))),
+            ("GET", ["title"]) => Ok(json_string(&self.page().title())),
+            ("POST", ["element"]) => {
+                let found = self.find(&body()?)?;
+                let first = found.first().ok_or_else(|| WebDriverError::new("no such element", "No element matches the locator"))?;
+                self.reference(*first)
+            }
+            ("POST", ["elements"]) => {
+                let found = self.find(&body()?)?;
+                let references = found.into_iter().map(|idx| self.reference(idx)).collect::<Result<Vec<_>, _>>()?;
+                Ok(format!("[{}]", references.join(",")))
+            }
+            ("GET", ["element", id, "text"]) => {
+                let idx = self.resolve(id)?;
+                let text = self.page().document().text_content(idx);
+                Ok(json_string(&text.split_whitespace().collect::<Vec<_>>().join(" ")))
+            }
+            ("POST", ["element", id, "click"]) => {
+                let idx = self.resolve(id)?;
+                self.click(idx)?;
+                Ok("null".to_string())
+            }
+            ("POST", ["element", id, "value"]) => {
+                let idx = self.resolve(id)?;
+                let body = body()?;
+                self.send_keys(idx, string_field(&body, "text")?)?;
+                Ok("null".to_string())
+            }
+            ("GET", ["screenshot"]) => Ok(json_string(&encode_base64(&self.page().encode_screenshot(ImageFormat::Png, DEFAULT_QUALITY)?))),
+            _ => Err(WebDriverError::new("unknown command", format!("No command {} {}", method, command.join("/")))),
+        }
+    }
+
+    /// Elements matching a `{"using", "value"}` locator, in document order
+    fn find(&self, locator: &Json) -> Result<Vec<usize>, WebDriverError> {
+        let using = string_field(locator, "using")?;
+        let value = string_field(locator, "value")?;
+        let page = self.page();
+        match using {
+            "css selector" | "tag name" => Ok(page.query_all(value)?),
+            "link text" | "partial link text" => {
+                let document = page.document();
+                let links = page.query_all("a[href]")?;
+                let text = |idx: usize| document.text_content(idx).split_whitespace().collect::<Vec<_>>().join(" ");
+                Ok(links
+                    .into_iter()
+                    .filter(|&idx| if using == "link text" { text(idx) == value.trim() } else { text(idx).contains(value) })
+                    .collect())
+            }
+            other => Err(WebDriverError::new("invalid argument", format!("Unsupported locator strategy '{}'", other))),
+        }
+    }
+
+    /// The web element reference object for `idx`
+    fn reference(&mut self, idx: usize) -> Result<String, WebDriverError> {
+        let id = self.page().node_id(idx)?;
+        let position = match self.elements.iter().position(|known| *known == id) {
+            Some(position) => position,
+            None => {
+                self.elements.push(id);
+                self.elements.len() - 1
+            }
+        };
+        Ok(format!("{{{}:\"node-{}\"}}", json_string(ELEMENT_KEY), position))
+    }
+
+    /// The node a reference handed out earlier names, if it is still live
+    fn resolve(&self, reference: &str) -> Result<usize, WebDriverError> {
+        let id = reference
+            .strip_prefix("node-")
+            .and_then(|position| position.parse::<usize>().ok())
+            .and_then(|position| self.elements.get(position))
+            .ok_or_else(|| WebDriverError::new("no such element", format!("No element with reference '{}'", reference)))?;
+        self.page().document().resolve(*id).map_err(|e| WebDriverError::new("stale element reference", e.to_string()))
+    }
+
+    /// Click the center of `idx`'s box, following a link the click asks
+    /// to navigate to
+    fn click(&mut self, idx: usize) -> Result<(), WebDriverError> {
+        let page = self.page();
+        let rect = page
+            .bounding_client_rect(idx)
+            .filter(|rect| !rect.is_empty())
+            .ok_or_else(|| WebDriverError::new("element not interactable", format!("Element {} has no box", idx)))?;
+        let (x, y) = (rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+        if !page.viewport_region().contains(Point::new(x, y)) {
+            return Err(WebDriverError::new("element not interactable", format!("Element {} is outside the viewport", idx)));
+        }
+        match page.element_from_point(x, y) {
+            Some(hit) if is_inclusive_ancestor(page, idx, hit) => {}
+            Some(hit) => {
+                return Err(WebDriverError::new("element click intercepted", format!("Element {} would receive the click", hit)));
+            }
+            None => return Err(WebDriverError::new("element not interactable", format!("Element {} cannot be hit", idx))),
+        }
+
+        let navigations = page.navigation_requests().len();
+        page.click_at(x, y)?;
+        if let Some(request) = page.navigation_requests().get(navigations).cloned() {
+            self.session.follow(&request)?;
+        }
+        Ok(())
+    }
+
+    /// Focus `idx` and type `text`, pressing the keys the spec's private
+    /// use characters name
+    fn send_keys(&self, idx: usize, text: &str) -> Result<(), WebDriverError> {
+        let page = self.page();
+        page.focus(Some(idx))?;
+        let mut run = String::new();
+        for c in text.chars() {
+            match special_key(c) {
+                Some(key) => {
+                    page.type_text(&std::mem::take(&mut run))?;
+                    page.press_key(key)?;
+                }
+                None => run.push(c),
+            }
+        }
+        page.type_text(&run)?;
+        Ok(())
+    }
+}
+
+fn invalid_session(id: &str) -> WebDriverError {
+    WebDriverError::new("invalid session id", format!("No session '{}'", id))
+}
+
+/// A request body as JSON; an empty body is an empty object
+fn parse_body(body: &str) -> Result<Json, WebDriverError> {
+    if body.trim().is_empty() {
+        return Ok(Json::Object(Vec::new()));
+    }
+    match Json::parse(body) {
+        Some(json @ Json::Object(_)) => Ok(json),
+        _ => Err(WebDriverError::new("invalid argument", "Request body must be a JSON object")),
+    }
+}
+
+fn string_field<'a>(body: &'a Json, name: &str) -> Result<&'a str, WebDriverError> {
+    body.get(name)
+        .and_then(Json::as_str)
+        .ok_or_else(|| WebDriverError::new("invalid argument", format!("Expected a string '{}'", name)))
+}
+
+/// Whether `ancestor` is `node` or contains it
+fn is_inclusive_ancestor(page: &Page, ancestor: usize, node: usize) -> bool {
+    let document = page.document();
+    std::iter::successors(Some(node), |&current| document.nodes[current].parent).any(|current| current == ancestor)
+}
+
+/// The key a WebDriver private use character stands for
+fn special_key(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{E003}' => "Backspace",
+        '\u{E004}' => "Tab",
+        '\u{E006}' | '\u{E007}' => "Enter",
+        '\u{E00C}' => "Escape",
+        '\u{E00D}' => " ",
+        '\u{E010}' => "End",
+        '\u{E011}' => "Home",
+        '\u{E012}' => "ArrowLeft",
+        '\u{E013}' => "ArrowUp",
+        '\u{E014}' => "ArrowRight",
+        '\u{E015}' => "ArrowDown",
+        '\u{E017}' => "Delete",
+        _ => return None,
+    })
+}
+
+/// A connection's reads, failing once `deadline` has passed however the
+/// client spreads its bytes out
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.checked_duration_since(Instant::now()).filter(|left| !left.is_zero());
+        let too_long = || io::Error::new(io::ErrorKind::TimedOut, "request took too long");
+        let Some(left) = left else { return Err(too_long()) };
+        self.stream.set_read_timeout(Some(left))?;
+        // Timed-out socket reads fail as `WouldBlock` on Unix
+        self.stream.read(buf).map_err(|e| if e.kind() == io::ErrorKind::WouldBlock { too_long() } else { e })
+    }
+}
+
+/// Whether a `Host` header names this machine: `localhost` or a loopback
+/// address, with any port
+fn is_local_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, port)| if port.bytes().all(|b| b.is_ascii_digit()) { name } else { host }),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Read one HTTP/1.1 request: the request line, headers of at most
+/// [`MAX_HEADER`] bytes with a local `Host`, and a body of `Content-Length`
+/// bytes, refused past [`MAX_BODY`] before any is read
+pub fn read_request(stream: &mut impl Read) -> io::Result<Request> {
+    let malformed = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(stream);
+    let mut header = (&mut reader).take(MAX_HEADER as u64);
+    let mut line = String::new();
+    let mut read_line = |line: &mut String| -> io::Result<usize> {
+        line.clear();
+        let read = header.read_line(line)?;
+        if read > 0 && !line.ends_with('\n') && header.limit() == 0 {
+            return Err(malformed(&format!("headers exceed {} bytes", MAX_HEADER)));
+        }
+        Ok(read)
+    };
+    read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(malformed("no request line"));
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let method = method.to_string();
+
+    let mut length = 0;
+    let mut host = None;
+    while read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| malformed("bad Content-Length"))?;
+            } else if name.trim().eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            }
+        }
+    }
+    match host {
+        Some(host) if is_local_host(&host) => {}
+        Some(host) => return Err(malformed(&format!("Host '{}' is not this machine", host))),
+        None => return Err(malformed("no Host header")),
+    }
+    if length > MAX_BODY {
+        return Err(malformed(&format!("body of {} bytes exceeds {} bytes", length, MAX_BODY)));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| malformed("body is not UTF-8"))?;
+    Ok(Request { method, path, body })
+}
+
+/// Write `response` as HTTP/1.1 and close the connection
+pub fn write_response(stream: &mut impl Write, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(driver: &mut WebDriver, method: &str, path: &str, body: &str) -> (u16, Json) {
+        let response = driver.handle(&Request { method: method.to_string(), path: path.to_string(), body: body.to_string() });
+        let json = Json::parse(&response.body).unwrap_or_else(|| panic!("not JSON: {}", response.body));
+        (response.status, json.get("value").cloned().unwrap())
+    }
+
+    fn start(driver: &mut WebDriver) -> String {
+        let (_, value) = send(driver, "POST", "/session", r#"{"capabilities":{}}"#);
+        value.get("sessionId").and_then(Json::as_str).unwrap().to_string()
+    }
+
+    fn element(value: &Json) -> String {
+        value.get(ELEMENT_KEY).and_then(Json::as_str).unwrap().to_string()
+    }
+
+    const FORM: &str = "data:text/html,<title>Form</title><input id='name'><button id='go' onclick='this.setAttribute(\"data-clicked\", \"yes\")'>Go</button><a href='data:text/html,<title>Next</title>'>Next page</a>";
+
+    #[test]
+    fn test_session_navigates_finds_clicks_and_types() {
+        // Given: A session on a page with an input, a button and a link
+        let mut driver = WebDriver::new(PageBuilder::new().with_seed(1).with_viewport(400, 300));
+        let session = start(&mut driver);
+        let (status, _) = send(&mut driver, "POST", &format!("/session/{}/url", session), &format!("{{\"url\":{}}}", json_string(FORM)));
+        assert_eq!(status, 200);
+        assert_eq!(send(&mut driver, "GET", &format!("/session/{}/title", session), "").1, Json::String("Form".to_string()));
+
+        // When: The input is found and typed into, with Backspace as a
+        // private use character, and the button is clicked
+        let (_, input) = send(&mut driver, "POST", &format!("/session/{}/element", session), r##"{"using":"css selector","value":"#name"}"##);
+        let input = element(&input);
+        let keys = format!("{{\"text\":{}}}", json_string("Adaa\u{E003}"));
+        assert_eq!(send(&mut driver, "POST", &format!("/session/{}/element/{}/value", session, input), &keys).0, 200);
+        let (_, button) = send(&mut driver, "POST", &format!("/session/{}/element", session), r#"{"using":"tag name","value":"button"}"#);
+        assert_eq!(send(&mut driver, "POST", &format!("/session/{}/element/{}/click", session, element(&button)), "{}").0, 200);
+
+        // Then: The page saw the keys and the click, and a screenshot is a
+        // base64 PNG
+        let page = driver.sessions[&session].page();
+        let idx = page.query("#name").unwrap().unwrap();
+        assert_eq!(crate::forms::value(&page.document(), idx), "Ada");
+        let button = page.query("#go").unwrap().unwrap();
+        assert_eq!(page.document().get_attribute(button, "data-clicked").map(String::as_str), Some("yes"));
+        let (_, shot) = send(&mut driver, "GET", &format!("/session/{}/screenshot", session), "");
+        assert!(shot.as_str().unwrap().starts_with("iVBORw0KGgo"));
+
+        // When: The link is found by its text and clicked
+        let (_, links) = send(&mut driver, "POST", &format!("/session/{}/elements", session), r#"{"using":"partial link text","value":"Next"}"#);
+        let Json::Array(links) = links else { panic!("expected an array") };
+        assert_eq!(links.len(), 1);
+        send(&mut driver, "POST", &format!("/session/{}/element/{}/click", session, element(&links[0])), "{}");
+
+        // Then: The tab follows it, and old references are stale
+        assert_eq!(send(&mut driver, "GET", &format!("/session/{}/title", session), "").1, Json::String("Next".to_string()));
+        let (status, error) = send(&mut driver, "POST", &format!("/session/{}/element/{}/click", session, input), "{}");
+        assert_eq!((status, error.get("error").and_then(Json::as_str)), (404, Some("stale element reference")));
+        assert_eq!(send(&mut driver, "DELETE", &format!("/session/{}", session), "").0, 200);
+    }
+
+    #[test]
+    fn test_errors_use_spec_codes() {
+        // Given: A session on an empty page
+        let mut driver = WebDriver::new(PageBuilder::new().with_seed(1));
+        let session = start(&mut driver);
+        let error = |driver: &mut WebDriver, method: &str, path: &str, body: &str| {
+            let (status, value) = send(driver, method, path, body);
+            (status, value.get("error").and_then(Json::as_str).unwrap().to_string())
+        };
+
+        // When/Then: Each failure answers its status and error code
+        let find = format!("/session/{}/element", session);
+        assert_eq!(error(&mut driver, "POST", &find, r##"{"using":"css selector","value":"#missing"}"##), (404, "no such element".to_string()));
+        assert_eq!(error(&mut driver, "POST", &find, r#"{"using":"css selector","value":"[["}"#), (400, "invalid selector".to_string()));
+        assert_eq!(error(&mut driver, "POST", &find, r#"{"using":"xpath","value":"//a"}"#), (400, "invalid argument".to_string()));
+        assert_eq!(error(&mut driver, "POST", &find, "not json"), (400, "invalid argument".to_string()));
+        assert_eq!(error(&mut driver, "POST", &format!("/session/{}/element/node-9/click", session), "{}"), (404, "no such element".to_string()));
+        assert_eq!(error(&mut driver, "GET", "/session/nope/url", ""), (404, "invalid session id".to_string()));
+        assert_eq!(error(&mut driver, "POST", &format!("/session/{}/frame", session), "{}"), (404, "unknown command".to_string()));
+        let navigate = format!("/session/{}/url", session);
+        assert_eq!(error(&mut driver, "POST", &navigate, r#"{"url":"page.html"}"#), (400, "invalid argument".to_string()));
+        assert_eq!(send(&mut driver, "GET", &navigate, "").1, Json::String(BLANK_URL.to_string()));
+    }
+
+    #[test]
+    fn test_http_framing() {
+        // Given: A raw request with a query string and a body
+        let raw = "POST /session/a/url?x=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 13\r\n\r\n{\"url\":\"/a\"}\n";
+
+        // When: It is read and a response written
+        let request = read_request(&mut raw.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        write_response(&mut out, &Response::success("null".to_string())).unwrap();
+
+        // Then: The parts are split out, and the response is framed
+        assert_eq!(request, Request { method: "POST".to_string(), path: "/session/a/url".to_string(), body: "{\"url\":\"/a\"}\n".to_string() });
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200 OK\r\n") && out.contains("Content-Length: 14\r\n") && out.ends_with("\r\n\r\n{\"value\":null}"), "{}", out);
+        assert!(read_request(&mut "\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_oversized_body_is_refused() {
+        // Given: A request claiming a body far past the limit
+        let raw = format!("POST /session HTTP/1.1\r\nHost: 127.0.0.1:4444\r\nContent-Length: {}\r\n\r\n{{}}", usize::MAX);
+
+        // When: It is read
+        let error = read_request(&mut raw.as_bytes()).unwrap_err();
+
+        // Then: It is refused as malformed without allocating the body
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("exceeds"), "{}", error);
+    }
+
+    #[test]
+    fn test_oversized_headers_and_foreign_hosts_are_refused() {
+        // Given: Requests with endless headers, another site's Host, and none
+        let endless = format!("GET /status HTTP/1.1\r\nHost: localhost\r\nX-Pad: {}\r\n\r\n", "a".repeat(MAX_HEADER));
+        let rebound = "GET /status HTTP/1.1\r\nHost: attacker.example:4444\r\n\r\n";
+        let hostless = "GET /status HTTP/1.1\r\n\r\n";
+
+        // Then: Each is refused as malformed
+        for (raw, reason) in [(endless.as_str(), "exceed"), (rebound, "attacker.example"), (hostless, "no Host")] {
+            let error = read_request(&mut raw.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains(reason), "{}", error);
+        }
+
+        // And: Loopback hosts are accepted with or without a port
+        for host in ["localhost", "LOCALHOST:4444", "127.0.0.1:4444", "[::1]:4444"] {
+            assert!(is_local_host(host), "{}", host);
+        }
+        assert!(!is_local_host("127.0.0.1.attacker.example"));
+    }
+
+    #[test]
+    fn test_slow_clients_are_dropped_at_the_request_deadline() {
+        // Given: A client that trickles a header byte at a time
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            for byte in b"GET /status HTTP/1.1\r\nHost: localhost\r\n".iter().cycle().take(400) {
+                if stream.write_all(&[*byte]).is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        // When: The request is read against a deadline shorter than the trickle
+        let (stream, _) = listener.accept().unwrap();
+        let started = Instant::now();
+        let error = read_request(&mut Deadline { stream: &stream, deadline: started + Duration::from_millis(200) }).unwrap_err();
+
+        // Then: The read gives up at the deadline, though bytes kept coming
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        drop(stream);
+        client.join().unwrap();
+    }
+}
@@ -0,0 +1,821 @@
+//! WebSocket
+//! `new WebSocket(url)` connected to an in-process mock peer that tests
+//! control: push messages, read what the page sent, and close or fail the
+//! connection
+//!
+//! Nothing goes over the network. Every connection a page opens reaches its
+//! `MockWebSocketServer`, which accepts any URL it was not told to refuse.
+//! What the server does reaches the page as tasks: `open`, `message`,
+//! `error` and `close` events fire when the event loop runs, after the
+//! current script and its promise jobs, never inside the call that caused
+//! them. Scripts drive the same server through the `mockWebSocket` global.
+//! Binary messages arrive as `ArrayBuffer`s whatever `binaryType` says.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+use rquickjs::{Array, ArrayBuffer, Ctx, Function, Object, TypedArray, Value};
+
+use crate::event_loop::TaskSource;
+
+/// Close code for a connection that ended without a close frame
+pub const ABNORMAL_CLOSURE: u16 = 1006;
+
+/// Close code the page reports when `close()` was given none
+pub const NO_STATUS_RECEIVED: u16 = 1005;
+
+/// `WebSocket.readyState`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadyState {
+    Connecting,
+    Open,
+    Closing,
+    Closed,
+}
+
+impl ReadyState {
+    /// The number scripts see, e.g. `WebSocket.OPEN` is 1
+    pub fn as_number(&self) -> u8 {
+        match self {
+            ReadyState::Connecting => 0,
+            ReadyState::Open => 1,
+            ReadyState::Closing => 2,
+            ReadyState::Closed => 3,
+        }
+    }
+}
+
+/// A text or binary message in either direction
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl SocketMessage {
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            SocketMessage::Text(text) => Some(text),
+            SocketMessage::Binary(_) => None,
+        }
+    }
+}
+
+/// Status code and reason of a close
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Something that happened to a connection, waiting to reach its page
+#[derive(Debug, Clone, PartialEq)]
+enum SocketEvent {
+    Open { protocol: String },
+    Message(SocketMessage),
+    Error,
+    Close { frame: CloseFrame, clean: bool },
+}
+
+#[derive(Debug)]
+struct Connection {
+    url: String,
+    protocols: Vec<String>,
+    /// Page that opened it, from `MockWebSocketServer::attach`
+    page: usize,
+    /// As the page last saw it, or closing once either side started to
+    state: ReadyState,
+    /// Messages the page sent
+    received: Vec<SocketMessage>,
+    /// How the page closed it, if it did
+    closed_by_page: Option<CloseFrame>,
+}
+
+type MessageHandler = Rc<dyn Fn(&MockSocket, &SocketMessage)>;
+
+#[derive(Default)]
+struct ServerState {
+    refused: Vec<String>,
+    connections: Vec<Connection>,
+    /// Events in the order they happened, by connection
+    events: VecDeque<(usize, SocketEvent)>,
+    pages: usize,
+    handler: Option<MessageHandler>,
+}
+
+/// The peer every WebSocket of a page connects to
+///
+/// Clones share connections, so a test keeps one clone while the page uses
+/// another; a `Browser` shares its server with all its pages.
+#[derive(Clone, Default)]
+pub struct MockWebSocketServer {
+    state: Rc<RefCell<ServerState>>,
+}
+
+impl MockWebSocketServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail connections to `url` with an `error` and a 1006 `close`
+    pub fn with_refused(self, url: &str) -> Self {
+        self.refuse(url);
+        self
+    }
+
+    /// Answer every message a page sends, e.g. to echo it back
+    pub fn with_handler(self, handler: impl Fn(&MockSocket, &SocketMessage) + 'static) -> Self {
+        self.state.borrow_mut().handler = Some(Rc::new(handler));
+        self
+    }
+
+    pub fn refuse(&self, url: &str) {
+        self.state.borrow_mut().refused.push(url.to_string());
+    }
+
+    /// Every connection opened so far, oldest first
+    pub fn connections(&self) -> Vec<MockSocket> {
+        (0..self.state.borrow().connections.len()).map(|id| self.socket(id)).collect()
+    }
+
+    /// The latest connection to `url`
+    pub fn connection(&self, url: &str) -> Option<MockSocket> {
+        let state = self.state.borrow();
+        let id = state.connections.iter().rposition(|c| c.url == url)?;
+        Some(self.socket(id))
+    }
+
+    /// Connections for a new page, whose events its event loop runs
+    pub fn attach(&self) -> PageSockets {
+        let mut state = self.state.borrow_mut();
+        state.pages += 1;
+        PageSockets { server: self.clone(), page: state.pages }
+    }
+
+    fn socket(&self, id: usize) -> MockSocket {
+        MockSocket { server: self.clone(), id }
+    }
+
+    fn queue(&self, id: usize, event: SocketEvent) {
+        self.state.borrow_mut().events.push_back((id, event));
+    }
+
+    /// Start closing `id` from the server's side, unless it already is
+    fn begin_close(&self, id: usize) -> bool {
+        let mut state = self.state.borrow_mut();
+        let connection = &mut state.connections[id];
+        let open = matches!(connection.state, ReadyState::Connecting | ReadyState::Open);
+        if open {
+            connection.state = ReadyState::Closing;
+        }
+        open
+    }
+}
+
+impl fmt::Debug for MockWebSocketServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MockWebSocketServer({} connections)", self.state.borrow().connections.len())
+    }
+}
+
+/// The server's end of one connection
+#[derive(Clone)]
+pub struct MockSocket {
+    server: MockWebSocketServer,
+    id: usize,
+}
+
+impl MockSocket {
+    fn with<T>(&self, f: impl FnOnce(&Connection) -> T) -> T {
+        f(&self.server.state.borrow().connections[self.id])
+    }
+
+    pub fn url(&self) -> String {
+        self.with(|c| c.url.clone())
+    }
+
+    /// Subprotocols the page asked for; the first is accepted
+    pub fn protocols(&self) -> Vec<String> {
+        self.with(|c| c.protocols.clone())
+    }
+
+    pub fn ready_state(&self) -> ReadyState {
+        self.with(|c| c.state)
+    }
+
+    /// Messages the page sent, oldest first
+    pub fn messages(&self) -> Vec<SocketMessage> {
+        self.with(|c| c.received.clone())
+    }
+
+    /// Text messages the page sent, oldest first
+    pub fn text_messages(&self) -> Vec<String> {
+        self.with(|c| c.received.iter().filter_map(|m| m.as_text().map(str::to_string)).collect())
+    }
+
+    /// The code and reason the page's `close()` sent
+    pub fn closed_by_page(&self) -> Option<CloseFrame> {
+        self.with(|c| c.closed_by_page.clone())
+    }
+
+    /// Send the page a text message; ignored once the connection is closing
+    pub fn send(&self, text: &str) {
+        self.push(SocketMessage::Text(text.to_string()));
+    }
+
+    /// Send the page a binary message; ignored once the connection is
+    /// closing
+    pub fn send_binary(&self, bytes: &[u8]) {
+        self.push(SocketMessage::Binary(bytes.to_vec()));
+    }
+
+    fn push(&self, message: SocketMessage) {
+        if matches!(self.ready_state(), ReadyState::Connecting | ReadyState::Open) {
+            self.server.queue(self.id, SocketEvent::Message(message));
+        }
+    }
+
+    /// Close the connection cleanly with `code` and `reason`
+    pub fn close(&self, code: u16, reason: &str) {
+        if self.server.begin_close(self.id) {
+            let frame = CloseFrame { code, reason: reason.to_string() };
+            self.server.queue(self.id, SocketEvent::Close { frame, clean: true });
+        }
+    }
+
+    /// Drop the connection: the page sees `error`, then a 1006 `close`
+    pub fn fail(&self) {
+        if self.server.begin_close(self.id) {
+            self.server.queue(self.id, SocketEvent::Error);
+            let frame = CloseFrame { code: ABNORMAL_CLOSURE, reason: String::new() };
+            self.server.queue(self.id, SocketEvent::Close { frame, clean: false });
+        }
+    }
+}
+
+impl fmt::Debug for MockSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MockSocket({}, {:?})", self.url(), self.ready_state())
+    }
+}
+
+/// One page's side of a `MockWebSocketServer`
+#[derive(Debug, Clone)]
+pub struct PageSockets {
+    server: MockWebSocketServer,
+    page: usize,
+}
+
+impl PageSockets {
+    pub fn server(&self) -> &MockWebSocketServer {
+        &self.server
+    }
+
+    /// Open a connection as `new WebSocket(url, protocols)` does
+    fn connect(&self, url: &str, protocols: Vec<String>) -> usize {
+        let mut state = self.server.state.borrow_mut();
+        let refused = state.refused.iter().any(|r| r == url);
+        let id = state.connections.len();
+        state.connections.push(Connection {
+            url: url.to_string(),
+            protocols: protocols.clone(),
+            page: self.page,
+            state: if refused { ReadyState::Closing } else { ReadyState::Connecting },
+            received: Vec::new(),
+            closed_by_page: None,
+        });
+        if refused {
+            state.events.push_back((id, SocketEvent::Error));
+            let frame = CloseFrame { code: ABNORMAL_CLOSURE, reason: String::new() };
+            state.events.push_back((id, SocketEvent::Close { frame, clean: false }));
+        } else {
+            let protocol = protocols.into_iter().next().unwrap_or_default();
+            state.events.push_back((id, SocketEvent::Open { protocol }));
+        }
+        id
+    }
+
+    /// Record a message the page sent and let the handler answer it
+    fn receive(&self, id: usize, message: SocketMessage) {
+        let handler = {
+            let mut state = self.server.state.borrow_mut();
+            let connection = &mut state.connections[id];
+            if connection.state != ReadyState::Open {
+                return;
+            }
+            connection.received.push(message.clone());
+            state.handler.clone()
+        };
+        if let Some(handler) = handler {
+            handler(&self.server.socket(id), &message);
+        }
+    }
+
+    /// The page's `close()`; closing before the connection opened fails it
+    fn close(&self, id: usize, frame: CloseFrame) {
+        let mut state = self.server.state.borrow_mut();
+        let connection = &mut state.connections[id];
+        match connection.state {
+            ReadyState::Connecting => {
+                connection.state = ReadyState::Closing;
+                state.events.retain(|(event_id, event)| !(*event_id == id && matches!(event, SocketEvent::Open { .. })));
+                state.events.push_back((id, SocketEvent::Error));
+                let frame = CloseFrame { code: ABNORMAL_CLOSURE, reason: String::new() };
+                state.events.push_back((id, SocketEvent::Close { frame, clean: false }));
+            }
+            ReadyState::Open => {
+                connection.state = ReadyState::Closing;
+                connection.closed_by_page = Some(frame.clone());
+                state.events.push_back((id, SocketEvent::Close { frame, clean: true }));
+            }
+            ReadyState::Closing | ReadyState::Closed => {}
+        }
+    }
+
+    fn owns(&self, id: usize) -> bool {
+        self.server.state.borrow().connections.get(id).is_some_and(|c| c.page == self.page)
+    }
+
+    /// The next event for this page's connections, applied to their state
+    fn take_event(&self) -> Option<(usize, SocketEvent)> {
+        let mut state = self.server.state.borrow_mut();
+        let ServerState { connections, events, .. } = &mut *state;
+        let index = events.iter().position(|(id, _)| connections[*id].page == self.page)?;
+        let (id, event) = events.remove(index)?;
+        let connection = &mut connections[id];
+        match &event {
+            SocketEvent::Open { .. } if connection.state == ReadyState::Connecting => connection.state = ReadyState::Open,
+            SocketEvent::Close { .. } => connection.state = ReadyState::Closed,
+            _ => {}
+        }
+        Some((id, event))
+    }
+}
+
+impl TaskSource for PageSockets {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let Some((id, event)) = self.take_event() else {
+            return Ok(false);
+        };
+        let native: Object = ctx.globals().get("__cortexWebSocket")?;
+        let deliver: Function = native.get("deliver")?;
+        match event {
+            SocketEvent::Open { protocol } => deliver.call::<_, ()>((id, "open", protocol))?,
+            SocketEvent::Message(message) => deliver.call::<_, ()>((id, "message", message_value(ctx, message)?))?,
+            SocketEvent::Error => deliver.call::<_, ()>((id, "error"))?,
+            SocketEvent::Close { frame, clean } => deliver.call::<_, ()>((id, "close", frame.code, frame.reason, clean))?,
+        }
+        Ok(true)
+    }
+}
+
+/// A string, or an `ArrayBuffer` for binary messages
+fn message_value<'js>(ctx: &Ctx<'js>, message: SocketMessage) -> rquickjs::Result<Value<'js>> {
+    match message {
+        SocketMessage::Text(text) => rquickjs::String::from_str(ctx.clone(), &text).map(|s| s.into_value()),
+        SocketMessage::Binary(bytes) => ArrayBuffer::new(ctx.clone(), bytes).map(|b| b.into_value()),
+    }
+}
+
+/// `WebSocket`, its events and the `mockWebSocket` controls, over the
+/// natives of `install_websocket`
+const WEBSOCKET_PRELUDE: &str = r##"
+(() => {
+    const native = globalThis.__cortexWebSocket;
+    const [CONNECTING, OPEN, CLOSING, CLOSED] = [0, 1, 2, 3];
+    const sockets = new Map();
+    const mockConnections = new Map();
+
+    function domError(name, message) {
+        const error = new Error(message);
+        error.name = name;
+        return error;
+    }
+
+    function report(error) {
+        console.error("Uncaught", error);
+    }
+
+    function bytesOf(data) {
+        if (data instanceof ArrayBuffer) return new Uint8Array(data.slice(0));
+        if (ArrayBuffer.isView(data)) return new Uint8Array(data.buffer.slice(data.byteOffset, data.byteOffset + data.byteLength));
+        return null;
+    }
+
+    function utf8Length(text) {
+        let length = 0;
+        for (const ch of text) {
+            const code = ch.codePointAt(0);
+            length += code < 0x80 ? 1 : code < 0x800 ? 2 : code < 0x10000 ? 3 : 4;
+        }
+        return length;
+    }
+
+    class WebSocket {
+        #id;
+        #listeners = new Map();
+
+        constructor(url, protocols = []) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to construct 'WebSocket': 1 argument required, but only 0 present.");
+            }
+            url = String(url).replace(/^http(s?):/i, "ws$1:");
+            if (!/^wss?:\/\/[^/?#]/i.test(url)) {
+                throw domError("SyntaxError", `Failed to construct 'WebSocket': The URL '${url}' is invalid.`);
+            }
+            if (url.includes("#")) {
+                throw domError("SyntaxError", `Failed to construct 'WebSocket': The URL contains a fragment identifier ('${url}').`);
+            }
+            protocols = typeof protocols === "string" ? [protocols] : Array.from(protocols, String);
+            const duplicate = protocols.find((p, i) => protocols.indexOf(p) !== i);
+            if (duplicate !== undefined) {
+                throw domError("SyntaxError", `Failed to construct 'WebSocket': The subprotocol '${duplicate}' is duplicated.`);
+            }
+            Object.defineProperty(this, "url", { value: url, enumerable: true });
+            this.readyState = CONNECTING;
+            this.protocol = "";
+            this.extensions = "";
+            this.bufferedAmount = 0;
+            this.binaryType = "blob";
+            this.onopen = null;
+            this.onmessage = null;
+            this.onerror = null;
+            this.onclose = null;
+            this.#id = native.connect(url, protocols);
+            sockets.set(this.#id, this);
+            const connection = mockConnection(this.#id);
+            if (native.info(this.#id).readyState === CONNECTING && typeof mockWebSocket.onconnection === "function") {
+                try { mockWebSocket.onconnection(connection); } catch (error) { report(error); }
+            }
+        }
+
+        send(data) {
+            if (this.readyState === CONNECTING) {
+                throw domError("InvalidStateError", "Failed to execute 'send' on 'WebSocket': Still in CONNECTING state.");
+            }
+            if (this.readyState !== OPEN) return;
+            const bytes = bytesOf(data);
+            if (bytes) native.sendBinary(this.#id, bytes);
+            else native.send(this.#id, String(data));
+            const connection = mockConnections.get(this.#id);
+            if (connection && typeof connection.onmessage === "function") {
+                const message = bytes ? bytes.buffer : String(data);
+                try { connection.onmessage(message); } catch (error) { report(error); }
+            }
+        }
+
+        close(code, reason = "") {
+            if (code !== undefined && code !== 1000 && !(code >= 3000 && code <= 4999)) {
+                throw domError("InvalidAccessError", `Failed to execute 'close' on 'WebSocket': The close code must be either 1000, or between 3000 and 4999. ${code} is neither.`);
+            }
+            reason = String(reason);
+            if (utf8Length(reason) > 123) {
+                throw domError("SyntaxError", "Failed to execute 'close' on 'WebSocket': The close reason must not be greater than 123 UTF-8 bytes.");
+            }
+            if (this.readyState === CLOSING || this.readyState === CLOSED) return;
+            this.readyState = CLOSING;
+            native.close(this.#id, code === undefined ? 1005 : code, reason);
+        }
+
+        addEventListener(type, listener) {
+            if (typeof listener !== "function" && !(listener && typeof listener.handleEvent === "function")) return;
+            const listeners = this.#listeners.get(type) || [];
+            if (!listeners.includes(listener)) listeners.push(listener);
+            this.#listeners.set(type, listeners);
+        }
+
+        removeEventListener(type, listener) {
+            const listeners = this.#listeners.get(type) || [];
+            this.#listeners.set(type, listeners.filter(l => l !== listener));
+        }
+
+        dispatchEvent(event) {
+            event.target = this;
+            event.currentTarget = this;
+            const handler = this["on" + event.type];
+            const listeners = [...(typeof handler === "function" ? [handler] : []), ...(this.#listeners.get(event.type) || [])];
+            for (const listener of listeners) {
+                try {
+                    if (typeof listener === "function") listener.call(this, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    report(error);
+                }
+            }
+            return true;
+        }
+    }
+    for (const [name, value] of Object.entries({ CONNECTING, OPEN, CLOSING, CLOSED })) {
+        Object.defineProperty(WebSocket, name, { value, enumerable: true });
+        Object.defineProperty(WebSocket.prototype, name, { value, enumerable: true });
+    }
+
+    native.deliver = (id, type, a, b, c) => {
+        const socket = sockets.get(id);
+        if (!socket) return;
+        const event = { type, target: socket, currentTarget: socket };
+        if (type === "open") {
+            socket.readyState = OPEN;
+            socket.protocol = a;
+        } else if (type === "message") {
+            event.data = a;
+            event.origin = socket.url.match(/^wss?:\/\/[^/?#]+/i)[0];
+            event.lastEventId = "";
+        } else if (type === "close") {
+            socket.readyState = CLOSED;
+            sockets.delete(id);
+            Object.assign(event, { code: a, reason: b, wasClean: c });
+        }
+        socket.dispatchEvent(event);
+    };
+
+    class MockConnection {
+        #id;
+        constructor(id) {
+            this.#id = id;
+            this.onmessage = null;
+        }
+        get url() { return native.info(this.#id).url; }
+        get protocols() { return native.info(this.#id).protocols; }
+        get readyState() { return native.info(this.#id).readyState; }
+        /** Messages the page sent: strings, or ArrayBuffers for binary */
+        get messages() { return native.received(this.#id); }
+        send(data) {
+            const bytes = bytesOf(data);
+            if (bytes) native.pushBinary(this.#id, bytes);
+            else native.push(this.#id, String(data));
+        }
+        close(code = 1000, reason = "") { native.serverClose(this.#id, code, String(reason)); }
+        error() { native.fail(this.#id); }
+    }
+
+    function mockConnection(id) {
+        if (!mockConnections.has(id)) mockConnections.set(id, new MockConnection(id));
+        return mockConnections.get(id);
+    }
+
+    const mockWebSocket = {
+        /** Called with each new connection's MockConnection */
+        onconnection: null,
+        connections() { return native.connections().map(mockConnection); },
+        last(url) {
+            const matching = this.connections().filter(c => url === undefined || c.url === url);
+            return matching.length ? matching[matching.length - 1] : null;
+        },
+        refuse(url) { native.refuse(String(url)); },
+    };
+
+    globalThis.WebSocket = WebSocket;
+    globalThis.mockWebSocket = mockWebSocket;
+})();
+"##;
+
+/// Install `WebSocket` and `mockWebSocket`, connected through `sockets`
+///
+/// Events only fire when `sockets`, as a `TaskSource` of the page's event
+/// loop, runs them.
+pub fn install_websocket<'js>(ctx: &Ctx<'js>, sockets: &PageSockets) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+
+    // The page's side
+    let page = sockets.clone();
+    native.set(
+        "connect",
+        Function::new(ctx.clone(), move |url: String, protocols: Vec<String>| page.connect(&url, protocols))?,
+    )?;
+    let page = sockets.clone();
+    native.set(
+        "send",
+        Function::new(ctx.clone(), move |id: usize, text: String| page.receive(id, SocketMessage::Text(text)))?,
+    )?;
+    let page = sockets.clone();
+    native.set(
+        "sendBinary",
+        Function::new(ctx.clone(), move |id: usize, bytes: TypedArray<'js, u8>| {
+            let bytes = bytes.as_bytes().unwrap_or_default().to_vec();
+            page.receive(id, SocketMessage::Binary(bytes))
+        })?,
+    )?;
+    let page = sockets.clone();
+    native.set(
+        "close",
+        Function::new(ctx.clone(), move |id: usize, code: u16, reason: String| page.close(id, CloseFrame { code, reason }))?,
+    )?;
+
+    // The mock server's side, limited to this page's connections
+    let page = sockets.clone();
+    native.set(
+        "connections",
+        Function::new(ctx.clone(), move || -> Vec<usize> {
+            let count = page.server.state.borrow().connections.len();
+            (0..count).filter(|&id| page.owns(id)).collect()
+        })?,
+    )?;
+    let page = sockets.clone();
+    native.set(
+        "info",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, id: usize| -> rquickjs::Result<Object<'js>> {
+            let socket = page.server.socket(id);
+            let info = Object::new(ctx)?;
+            info.set("url", socket.url())?;
+            info.set("protocols", socket.protocols())?;
+            info.set("readyState", socket.ready_state().as_number())?;
+            Ok(info)
+        })?,
+    )?;
+    let page = sockets.clone();
+    native.set(
+        "received",
+        Function::new(ctx.clone(), move |ctx: Ctx<'js>, id: usize| -> rquickjs::Result<Array<'js>> {
+            let messages = Array::new(ctx.clone())?;
+            for (i, message) in page.server.socket(id).messages().into_iter().enumerate() {
+                messages.set(i, message_value(&ctx, message)?)?;
+            }
+            Ok(messages)
+        })?,
+    )?;
+    let page = sockets.clone();
+    native.set("push", Function::new(ctx.clone(), move |id: usize, text: String| page.server.socket(id).send(&text))?)?;
+    let page = sockets.clone();
+    native.set(
+        "pushBinary",
+        Function::new(ctx.clone(), move |id: usize, bytes: TypedArray<'js, u8>| {
+            page.server.socket(id).send_binary(bytes.as_bytes().unwrap_or_default())
+        })?,
+    )?;
+    let page = sockets.clone();
+    native.set(
+        "serverClose",
+        Function::new(ctx.clone(), move |id: usize, code: u16, reason: String| page.server.socket(id).close(code, &reason))?,
+    )?;
+    let page = sockets.clone();
+    native.set("fail", Function::new(ctx.clone(), move |id: usize| page.server.socket(id).fail())?)?;
+    let page = sockets.clone();
+    native.set("refuse", Function::new(ctx.clone(), move |url: String| page.server.refuse(&url))?)?;
+
+    ctx.globals().set("__cortexWebSocket", native)?;
+    ctx.eval::<(), _>(WEBSOCKET_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_loop::{ready, EventLoop};
+    use rquickjs::{AsyncContext, AsyncRuntime};
+
+    /// A context with WebSockets on `server`, and its event loop
+    fn setup(server: &MockWebSocketServer) -> (AsyncRuntime, AsyncContext, EventLoop) {
+        let runtime = AsyncRuntime::new().unwrap();
+        let context = ready(AsyncContext::full(&runtime)).unwrap();
+        let sockets = server.attach();
+        ready(context.with(|ctx| {
+            crate::console::install_console(&ctx, Default::default(), false).unwrap();
+            install_websocket(&ctx, &sockets).unwrap();
+        }));
+        (runtime, context, EventLoop::new().with_source(Rc::new(sockets)))
+    }
+
+    fn eval<T: for<'js> rquickjs::FromJs<'js>>(context: &AsyncContext, source: &str) -> T {
+        ready(context.with(|ctx| ctx.eval(source).unwrap()))
+    }
+
+    #[test]
+    fn test_messages_in_both_directions() {
+        let server = MockWebSocketServer::new();
+        let (runtime, context, event_loop) = setup(&server);
+        eval::<()>(
+            &context,
+            r#"
+            globalThis.log = [];
+            const ws = new WebSocket("wss://chat.test/room", ["v2", "v1"]);
+            ws.binaryType = "arraybuffer";
+            ws.onopen = () => { log.push("open " + ws.protocol); ws.send("hello"); ws.send(new Uint8Array([1, 2])); };
+            ws.addEventListener("message", e => log.push(typeof e.data === "string" ? e.data : [...new Uint8Array(e.data)].join()));
+            log.push("state " + ws.readyState);
+            "#,
+        );
+        let socket = server.connection("wss://chat.test/room").unwrap();
+        assert_eq!(socket.ready_state(), ReadyState::Connecting, "Nothing happens until the event loop runs");
+
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        assert_eq!(socket.ready_state(), ReadyState::Open);
+        assert_eq!(socket.protocols(), ["v2", "v1"]);
+        assert_eq!(socket.messages(), [SocketMessage::Text("hello".to_string()), SocketMessage::Binary(vec![1, 2])]);
+
+        socket.send("welcome");
+        socket.send_binary(&[7, 8]);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        let log: Vec<String> = eval(&context, "log");
+        assert_eq!(log, ["state 0", "open v2", "welcome", "7,8"]);
+    }
+
+    #[test]
+    fn test_close_and_error_events() {
+        let server = MockWebSocketServer::new().with_refused("ws://down.test/");
+        let (runtime, context, event_loop) = setup(&server);
+        eval::<()>(
+            &context,
+            r#"
+            globalThis.log = [];
+            const record = name => e => log.push(name + (e.type === "close" ? ` ${e.code} ${e.reason} ${e.wasClean}` : ""));
+            for (const url of ["ws://down.test/", "ws://a.test/", "ws://b.test/", "ws://c.test/"]) {
+                const ws = new WebSocket(url);
+                ws.onerror = record(url + " error");
+                ws.onclose = record(url + " close");
+            }
+            "#,
+        );
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        server.connection("ws://a.test/").unwrap().close(4001, "kicked");
+        server.connection("ws://b.test/").unwrap().fail();
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        let log: Vec<String> = eval(&context, "log");
+        assert_eq!(
+            log,
+            [
+                "ws://down.test/ error",
+                "ws://down.test/ close 1006  false",
+                "ws://a.test/ close 4001 kicked true",
+                "ws://b.test/ error",
+                "ws://b.test/ close 1006  false",
+            ]
+        );
+        assert_eq!(server.connection("ws://b.test/").unwrap().ready_state(), ReadyState::Closed);
+        assert_eq!(server.connection("ws://c.test/").unwrap().ready_state(), ReadyState::Open);
+    }
+
+    #[test]
+    fn test_page_close_and_validation() {
+        let server = MockWebSocketServer::new();
+        let (runtime, context, event_loop) = setup(&server);
+        let errors: Vec<String> = eval(
+            &context,
+            r#"
+            const attempt = f => { try { f(); return "no error"; } catch (e) { return e.name + ": " + e.message; } };
+            globalThis.ws = new WebSocket("ws://app.test/live");
+            [
+                attempt(() => new WebSocket("http://app.test/x").url),
+                attempt(() => new WebSocket("ftp://app.test/")),
+                attempt(() => new WebSocket("ws://app.test/", ["a", "a"])),
+                attempt(() => ws.send("too early")),
+                attempt(() => ws.close(1001)),
+            ]
+            "#,
+        );
+        assert_eq!(errors[0], "no error");
+        assert_eq!(errors[1], "SyntaxError: Failed to construct 'WebSocket': The URL 'ftp://app.test/' is invalid.");
+        assert_eq!(errors[2], "SyntaxError: Failed to construct 'WebSocket': The subprotocol 'a' is duplicated.");
+        assert_eq!(errors[3], "InvalidStateError: Failed to execute 'send' on 'WebSocket': Still in CONNECTING state.");
+        assert!(errors[4].starts_with("InvalidAccessError"), "{}", errors[4]);
+
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+        eval::<()>(&context, "ws.onclose = e => globalThis.closed = [e.code, e.reason, ws.readyState]; ws.close(4000, 'bye'); ws.send('dropped');");
+        assert_eq!(eval::<u8>(&context, "ws.readyState"), 2);
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        let socket = server.connection("ws://app.test/live").unwrap();
+        assert_eq!(socket.closed_by_page(), Some(CloseFrame { code: 4000, reason: "bye".to_string() }));
+        assert!(socket.messages().is_empty(), "Sends after close() are dropped");
+        assert_eq!(eval::<Vec<String>>(&context, "closed.map(String)"), ["4000", "bye", "3"]);
+    }
+
+    #[test]
+    fn test_scripted_servers() {
+        // Rust handlers answer every page; `onconnection` handlers only their
+        // own page
+        let server = MockWebSocketServer::new().with_handler(|socket, message| {
+            if let Some(text) = message.as_text() {
+                socket.send(&text.to_uppercase());
+            }
+        });
+        let (runtime, context, event_loop) = setup(&server);
+        eval::<()>(
+            &context,
+            r#"
+            globalThis.replies = [];
+            mockWebSocket.onconnection = conn => {
+                conn.send("hi " + conn.url);
+                conn.onmessage = data => { if (data === "bye") conn.close(1000, "done"); };
+            };
+            const ws = new WebSocket("ws://echo.test/");
+            ws.onopen = () => ws.send("ping");
+            ws.onmessage = e => { replies.push(e.data); if (e.data === "PING") ws.send("bye"); };
+            ws.onclose = e => replies.push("closed " + e.reason);
+            "#,
+        );
+        event_loop.run_until_idle(&runtime, &context).unwrap();
+
+        let replies: Vec<String> = eval(&context, "replies");
+        assert_eq!(replies, ["hi ws://echo.test/", "PING", "BYE", "closed done"]);
+        let sent: Vec<String> = eval(&context, "mockWebSocket.last().messages");
+        assert_eq!(sent, ["ping", "bye"]);
+        assert_eq!(eval::<u8>(&context, "mockWebSocket.last('ws://echo.test/').readyState"), 3);
+    }
+}
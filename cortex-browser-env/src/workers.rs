@@ -0,0 +1,697 @@
+//! Workers
+//! `new Worker(url)`: a script running in a runtime of its own on another
+//! thread, talking to the page through `postMessage`
+//!
+//! Workers share the page's seed, frozen time and `Limits`. Their replies
+//! run as tasks on the page's event loop, and driving the page waits for a
+//! busy worker up to `WORKER_TIMEOUT`.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rquickjs::{CatchResultExt, Context, Ctx, Function, Object, Runtime};
+
+use crate::console::{self, ConsoleBuffer, ConsoleEntry, ConsoleLevel};
+use crate::encoding;
+use crate::error::BrowserError;
+use crate::event_loop::TaskSource;
+use crate::limits::{LimitKind, Limits, Watchdog};
+use crate::messaging;
+use crate::network::{self, resolve_url, ResourceLoader};
+use crate::seed::{self, RunSeed};
+
+/// How long driving the page waits for a busy worker before reporting it
+/// and moving on
+pub const WORKER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a worker sends its page
+#[derive(Debug, Clone, PartialEq)]
+enum WorkerEvent {
+    /// A serialized message
+    Message(String),
+    /// An uncaught exception, or a script that failed to load
+    Error(String),
+    Console(ConsoleEntry),
+    /// A limit the worker ran into, which stopped it
+    Limit(LimitKind, String),
+}
+
+/// The page's end of a worker thread
+struct WorkerThread {
+    url: String,
+    inbox: Option<Sender<String>>,
+    outbox: Receiver<WorkerEvent>,
+    /// The script and messages the worker has yet to finish
+    busy: Arc<AtomicUsize>,
+    /// Set to stop the worker, interrupting any running script
+    stopped: Arc<AtomicBool>,
+    /// Timed out while busy; not waited for again until it reports back
+    stalled: bool,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WorkerThread {
+    fn is_busy(&self) -> bool {
+        !self.stalled && !self.stopped.load(Ordering::SeqCst) && self.busy.load(Ordering::SeqCst) > 0
+    }
+}
+
+impl Drop for WorkerThread {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.inbox.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A page's workers, by id
+///
+/// As a task source it delivers what each worker sent, waiting for busy
+/// workers as described in the module docs.
+#[derive(Clone)]
+pub struct PageWorkers {
+    workers: Rc<RefCell<Vec<Option<WorkerThread>>>>,
+    loader: Rc<dyn ResourceLoader>,
+    base_url: Option<String>,
+    console: ConsoleBuffer,
+    /// The page's seed, which each worker's own seed is drawn from
+    seed: RunSeed,
+    /// The time the page's `Date` is frozen at, if it is
+    frozen_time: Option<f64>,
+    limits: Limits,
+    /// The limit a worker ran into since the page last asked
+    exceeded: Rc<RefCell<Option<BrowserError>>>,
+}
+
+impl PageWorkers {
+    pub fn new(loader: Rc<dyn ResourceLoader>, base_url: Option<String>, console: ConsoleBuffer) -> Self {
+        PageWorkers { workers: Rc::new(RefCell::new(Vec::new())), loader, base_url, console, seed: RunSeed(0), frozen_time: None, limits: Limits::default(), exceeded: Rc::default() }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The limit a worker ran into since the last call, if any
+    pub fn take_limit_error(&self) -> Option<BrowserError> {
+        self.exceeded.borrow_mut().take()
+    }
+
+    pub fn with_seed(mut self, seed: RunSeed) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_frozen_time(mut self, time: f64) -> Self {
+        self.frozen_time = Some(time);
+        self
+    }
+
+    /// Fetch the script at `url` and start a worker running it; a script
+    /// that fails to load becomes the worker's error
+    fn spawn(&self, url: &str, name: String) -> usize {
+        let url = self.base_url.as_deref().map_or_else(|| url.trim().to_string(), |base| resolve_url(base, url));
+        let (inbox, inbox_receiver) = mpsc::channel();
+        let (outbox_sender, outbox) = mpsc::channel();
+        let busy = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicBool::new(false));
+        // Workers are numbered in the order they start, so each draws the
+        // same stream on every run with the page's seed
+        let seed = RunSeed(self.seed.rng(&format!("worker {}", self.workers.borrow().len())).next_u64());
+        let thread = match network::fetch(&*self.loader, &url) {
+            Ok(bytes) => {
+                busy.store(1, Ordering::SeqCst);
+                let script = encoding::decode(&bytes, None).0;
+                let scope = WorkerScope {
+                    name,
+                    url: url.clone(),
+                    outbox: outbox_sender,
+                    busy: busy.clone(),
+                    stopped: stopped.clone(),
+                    seed,
+                    frozen_time: self.frozen_time,
+                    limits: self.limits,
+                };
+                thread::Builder::new().name(format!("worker {}", url)).spawn(move || scope.run(&script, inbox_receiver)).ok()
+            }
+            Err(e) => {
+                let _ = outbox_sender.send(WorkerEvent::Error(format!("Failed to load worker script '{}': {}", url, e)));
+                None
+            }
+        };
+        let mut workers = self.workers.borrow_mut();
+        workers.push(Some(WorkerThread { url, inbox: Some(inbox), outbox, busy, stopped, stalled: false, thread }));
+        workers.len() - 1
+    }
+
+    /// Send the serialized `data` to worker `id`, unless it stopped
+    fn post(&self, id: usize, data: String) {
+        let workers = self.workers.borrow();
+        let Some(worker) = workers.get(id).and_then(Option::as_ref) else {
+            return;
+        };
+        if worker.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        worker.busy.fetch_add(1, Ordering::SeqCst);
+        if let Some(inbox) = &worker.inbox {
+            let _ = inbox.send(data);
+        }
+    }
+
+    /// Stop worker `id` and discard what it sent but was not delivered
+    fn terminate(&self, id: usize) {
+        // Joined outside the borrow, as the thread may take a moment to stop
+        let worker = self.workers.borrow_mut().get_mut(id).and_then(Option::take);
+        drop(worker);
+    }
+
+    /// The next event a worker sent, waiting while any is busy
+    fn next_event(&self) -> Option<(usize, WorkerEvent)> {
+        let deadline = Instant::now() + WORKER_TIMEOUT;
+        loop {
+            let mut workers = self.workers.borrow_mut();
+            let busy: Vec<usize> = workers.iter().enumerate().filter(|(_, w)| w.as_ref().is_some_and(WorkerThread::is_busy)).map(|(id, _)| id).collect();
+            for (id, worker) in workers.iter_mut().enumerate() {
+                let Some(worker) = worker.as_mut() else {
+                    continue;
+                };
+                if let Ok(event) = worker.outbox.try_recv() {
+                    worker.stalled = false;
+                    return Some((id, event));
+                }
+            }
+            let &first = busy.first()?;
+            if Instant::now() >= deadline {
+                for &id in &busy {
+                    let worker = workers[id].as_mut()?;
+                    worker.stalled = true;
+                    let message = format!("Worker '{}' did not respond within {}s", worker.url, WORKER_TIMEOUT.as_secs());
+                    console::record(&self.console, ConsoleEntry { level: ConsoleLevel::Error, message }, true);
+                }
+                return None;
+            }
+            match workers[first].as_ref()?.outbox.recv_timeout(Duration::from_millis(1)) {
+                Ok(event) => return Some((first, event)),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {}
+            }
+        }
+    }
+}
+
+impl TaskSource for PageWorkers {
+    fn run_next_task(&self, ctx: &Ctx<'_>) -> rquickjs::Result<bool> {
+        let Some((id, event)) = self.next_event() else {
+            return Ok(false);
+        };
+        let native: Object = ctx.globals().get("__cortexWorkers")?;
+        let deliver: Function = native.get("deliver")?;
+        match event {
+            WorkerEvent::Message(data) => deliver.call::<_, bool>((id, "message", data)).map(|_| ())?,
+            WorkerEvent::Error(message) => {
+                if deliver.call::<_, bool>((id, "error", message.clone()))? {
+                    console::record(&self.console, ConsoleEntry { level: ConsoleLevel::Error, message: format!("Uncaught {}", message) }, true);
+                }
+            }
+            WorkerEvent::Console(entry) => console::record(&self.console, entry, true),
+            WorkerEvent::Limit(kind, message) => {
+                let url = self.workers.borrow().get(id).and_then(Option::as_ref).map(|worker| worker.url.clone()).unwrap_or_default();
+                self.terminate(id);
+                *self.exceeded.borrow_mut() = Some(BrowserError::LimitExceeded(kind, format!("Worker '{}': {}", url, message)));
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// A worker's side of its thread
+struct WorkerScope {
+    name: String,
+    url: String,
+    outbox: Sender<WorkerEvent>,
+    busy: Arc<AtomicUsize>,
+    stopped: Arc<AtomicBool>,
+    seed: RunSeed,
+    frozen_time: Option<f64>,
+    limits: Limits,
+}
+
+impl WorkerScope {
+    /// Run `script`, then each message from `inbox`, until the worker is
+    /// stopped or closes itself
+    fn run(self, script: &str, inbox: Receiver<String>) {
+        let started = Runtime::new().and_then(|runtime| Context::full(&runtime).map(|context| (runtime, context)));
+        let Ok((runtime, context)) = started else {
+            let _ = self.outbox.send(WorkerEvent::Error("Failed to start the worker's runtime".to_string()));
+            self.finish();
+            return;
+        };
+        if let Some(bytes) = self.limits.memory {
+            runtime.set_memory_limit(bytes);
+        }
+        let watchdog = Watchdog::new();
+        let (stopped, timer) = (self.stopped.clone(), watchdog.clone());
+        runtime.set_interrupt_handler(Some(Box::new(move || stopped.load(Ordering::SeqCst) || timer.expired())));
+        let console = ConsoleBuffer::default();
+        let closing = Rc::new(Cell::new(false));
+        let reported = Rc::new(RefCell::new(Vec::new()));
+
+        let installed = context.with(|ctx| {
+            console::install_console(&ctx, console.clone(), false)?;
+            seed::install_seeded_math_random(&ctx, self.seed)?;
+            if let Some(time) = self.frozen_time {
+                seed::install_frozen_date(&ctx, time)?;
+            }
+            self.install(&ctx, closing.clone(), reported.clone())
+        });
+        if installed.is_err() {
+            let _ = self.outbox.send(WorkerEvent::Error("Failed to set up the worker's global scope".to_string()));
+            self.finish();
+            return;
+        }
+
+        // Run a task and the jobs it queued under the limits, returning
+        // whether it ran into one
+        let turn = |task: &dyn Fn(&Ctx<'_>) -> Result<(), String>| {
+            let timed = self.limits.script_timeout.is_some_and(|timeout| watchdog.arm(timeout));
+            let mut errors = Vec::new();
+            if let Err(message) = context.with(|ctx| task(&ctx)) {
+                errors.push(message);
+            }
+            loop {
+                match runtime.execute_pending_job() {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => errors.push(e.to_string()),
+                }
+            }
+            for entry in console.borrow_mut().drain(..) {
+                let _ = self.outbox.send(WorkerEvent::Console(entry));
+            }
+            errors.append(&mut reported.borrow_mut());
+            let limit = if timed && watchdog.disarm() {
+                Some(self.limits.timeout_error())
+            } else {
+                errors.iter().map(|message| self.limits.classify_js_error(BrowserError::JavaScriptError(message.clone(), None))).find(|e| matches!(e, BrowserError::LimitExceeded(..)))
+            };
+            let limited = limit.is_some();
+            match limit {
+                Some(BrowserError::LimitExceeded(kind, message)) => {
+                    let _ = self.outbox.send(WorkerEvent::Limit(kind, message));
+                }
+                _ => errors.into_iter().for_each(|message| {
+                    let _ = self.outbox.send(WorkerEvent::Error(message));
+                }),
+            }
+            self.busy.fetch_sub(1, Ordering::SeqCst);
+            limited
+        };
+
+        let mut limited = turn(&|ctx| ctx.eval::<(), _>(script).catch(ctx).map_err(|e| e.to_string()));
+        while !limited && !closing.get() && !self.stopped.load(Ordering::SeqCst) {
+            let Ok(data) = inbox.recv() else {
+                break;
+            };
+            limited = turn(&|ctx| {
+                let native: Object = ctx.globals().get("__cortexWorker").map_err(|e| e.to_string())?;
+                let deliver: Function = native.get("deliver").map_err(|e| e.to_string())?;
+                deliver.call::<_, ()>((data.clone(),)).catch(ctx).map_err(|e| e.to_string())
+            });
+        }
+        self.finish();
+    }
+
+    /// Mark the worker stopped with nothing left to wait for
+    fn finish(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.busy.store(0, Ordering::SeqCst);
+    }
+
+    /// Install the global scope; errors scripts report land in `reported`
+    /// for the turn to send
+    fn install<'js>(&self, ctx: &Ctx<'js>, closing: Rc<Cell<bool>>, reported: Rc<RefCell<Vec<String>>>) -> rquickjs::Result<()> {
+        let native = Object::new(ctx.clone())?;
+        native.set("name", self.name.as_str())?;
+        native.set("url", self.url.as_str())?;
+        native.set("cloning", messaging::structured_clone(ctx)?)?;
+        let outbox = self.outbox.clone();
+        native.set(
+            "post",
+            Function::new(ctx.clone(), move |data: String| {
+                let _ = outbox.send(WorkerEvent::Message(data));
+            })?,
+        )?;
+        native.set("error", Function::new(ctx.clone(), move |message: String| reported.borrow_mut().push(message))?)?;
+        native.set("close", Function::new(ctx.clone(), move || closing.set(true))?)?;
+        ctx.globals().set("__cortexWorker", native)?;
+        ctx.eval::<(), _>(WORKER_SCOPE_PRELUDE)
+    }
+}
+
+/// The worker's global scope, over the natives of `WorkerScope::install`
+const WORKER_SCOPE_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexWorker;
+    const { serialize, deserialize } = native.cloning({ isPort: () => false });
+    const listeners = new Map();
+
+    function domError(name, message) {
+        const error = new Error(message);
+        error.name = name;
+        return error;
+    }
+
+    function describe(error) {
+        return error instanceof Error ? `${error.name}: ${error.message}` : String(error);
+    }
+
+    class MessageEvent {
+        constructor(type, init = {}) {
+            this.type = String(type);
+            this.data = init.data === undefined ? null : init.data;
+            this.origin = "";
+            this.lastEventId = "";
+            this.source = null;
+            this.ports = Object.freeze([]);
+            this.target = globalThis;
+            this.currentTarget = globalThis;
+            this.defaultPrevented = false;
+            this.isTrusted = true;
+        }
+        preventDefault() { this.defaultPrevented = true; }
+        stopPropagation() {}
+        stopImmediatePropagation() {}
+    }
+
+    globalThis.self = globalThis;
+    globalThis.name = native.name;
+    globalThis.location = { href: native.url };
+    globalThis.MessageEvent = MessageEvent;
+    globalThis.onmessage = null;
+    globalThis.postMessage = function postMessage(message, transfer) {
+        if (arguments.length === 0) {
+            throw new TypeError("Failed to execute 'postMessage' on 'DedicatedWorkerGlobalScope': 1 argument required, but only 0 present.");
+        }
+        const list = transfer !== null && typeof transfer === "object" && !(Symbol.iterator in transfer) ? transfer.transfer ?? [] : transfer ?? [];
+        native.post(serialize(message, list).data);
+    };
+    globalThis.close = () => native.close();
+    globalThis.importScripts = () => {
+        throw domError("NotSupportedError", "Failed to execute 'importScripts' on 'WorkerGlobalScope': scripts cannot be imported in this environment.");
+    };
+    globalThis.addEventListener = (type, listener) => {
+        if (typeof listener !== "function" && !(listener && typeof listener.handleEvent === "function")) return;
+        const registered = listeners.get(type) || [];
+        if (!registered.includes(listener)) listeners.set(type, [...registered, listener]);
+    };
+    globalThis.removeEventListener = (type, listener) => {
+        listeners.set(type, (listeners.get(type) || []).filter(l => l !== listener));
+    };
+
+    native.deliver = data => {
+        const event = new MessageEvent("message", { data: deserialize(data, []) });
+        const handler = globalThis.onmessage;
+        for (const listener of [...(typeof handler === "function" ? [handler] : []), ...(listeners.get("message") || [])]) {
+            try {
+                if (typeof listener === "function") listener.call(globalThis, event);
+                else listener.handleEvent(event);
+            } catch (error) {
+                native.error(describe(error));
+            }
+        }
+    };
+})();
+"#;
+
+/// `Worker`, over the natives of `install_workers`
+const WORKERS_PRELUDE: &str = r#"
+(() => {
+    const native = globalThis.__cortexWorkers;
+    const { serialize, deserialize } = globalThis.__cortexMessaging;
+    const workers = new Map();
+
+    function domError(name, message) {
+        const error = new Error(message);
+        error.name = name;
+        return error;
+    }
+
+    class Worker {
+        #id;
+        #listeners = new Map();
+
+        constructor(url, options = {}) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to construct 'Worker': 1 argument required, but only 0 present.");
+            }
+            if (options?.type === "module") {
+                throw domError("NotSupportedError", "Failed to construct 'Worker': module workers are not supported.");
+            }
+            this.onmessage = null;
+            this.onmessageerror = null;
+            this.onerror = null;
+            this.#id = native.spawn(String(url), String(options?.name ?? ""));
+            workers.set(this.#id, this);
+        }
+
+        postMessage(message, transfer) {
+            if (arguments.length === 0) {
+                throw new TypeError("Failed to execute 'postMessage' on 'Worker': 1 argument required, but only 0 present.");
+            }
+            const list = [...(transfer !== null && typeof transfer === "object" && !(Symbol.iterator in transfer) ? transfer.transfer ?? [] : transfer ?? [])];
+            if (list.some(item => item instanceof MessagePort)) {
+                throw domError("DataCloneError", "Failed to execute 'postMessage' on 'Worker': MessagePorts cannot be transferred to workers.");
+            }
+            native.post(this.#id, serialize(message, list).data);
+        }
+
+        terminate() {
+            workers.delete(this.#id);
+            native.terminate(this.#id);
+        }
+
+        addEventListener(type, listener) {
+            if (typeof listener !== "function" && !(listener && typeof listener.handleEvent === "function")) return;
+            const listeners = this.#listeners.get(type) || [];
+            if (!listeners.includes(listener)) listeners.push(listener);
+            this.#listeners.set(type, listeners);
+        }
+
+        removeEventListener(type, listener) {
+            const listeners = this.#listeners.get(type) || [];
+            this.#listeners.set(type, listeners.filter(l => l !== listener));
+        }
+
+        dispatchEvent(event) {
+            event.target = this;
+            event.currentTarget = this;
+            const handler = this["on" + event.type];
+            const listeners = [...(typeof handler === "function" ? [handler] : []), ...(this.#listeners.get(event.type) || [])];
+            for (const listener of listeners) {
+                try {
+                    if (typeof listener === "function") listener.call(this, event);
+                    else listener.handleEvent(event);
+                } catch (error) {
+                    console.error("Uncaught", error);
+                }
+            }
+            return !event.defaultPrevented;
+        }
+    }
+
+    /** Dispatch what worker `id` sent; `false` when an error was canceled */
+    native.deliver = (id, type, payload) => {
+        const worker = workers.get(id);
+        if (!worker) return true;
+        let event;
+        if (type === "message") {
+            event = new MessageEvent("message", { data: deserialize(payload, []) });
+        } else {
+            event = new Event("error", { cancelable: true });
+            Object.assign(event, { message: payload, filename: "", lineno: 0, colno: 0, error: null });
+        }
+        event.isTrusted = true;
+        return worker.dispatchEvent(event);
+    };
+
+    globalThis.Worker = Worker;
+})();
+"#;
+
+/// Install `Worker`, whose threads `workers` runs
+pub fn install_workers<'js>(ctx: &Ctx<'js>, workers: PageWorkers) -> rquickjs::Result<()> {
+    let native = Object::new(ctx.clone())?;
+    let spawner = workers.clone();
+    native.set("spawn", Function::new(ctx.clone(), move |url: String, name: String| spawner.spawn(&url, name))?)?;
+    let poster = workers.clone();
+    native.set("post", Function::new(ctx.clone(), move |id: usize, data: String| poster.post(id, data))?)?;
+    native.set("terminate", Function::new(ctx.clone(), move |id: usize| workers.terminate(id))?)?;
+    ctx.globals().set("__cortexWorkers", native)?;
+    ctx.eval::<(), _>(WORKERS_PRELUDE)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::browser::{NetworkMode, Page, PageBuilder};
+    use crate::console::ConsoleLevel;
+    use crate::error::BrowserError;
+    use crate::limits::{LimitKind, Limits};
+    use crate::network::MockNetwork;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    fn page(scripts: &[(&str, &str)]) -> Page {
+        let network = scripts.iter().fold(MockNetwork::new(), |network, &(url, script)| network.with_response(url, script));
+        PageBuilder::new().with_base_url("https://shop.test/").with_network(NetworkMode::Custom(Rc::new(network))).build().unwrap()
+    }
+
+    #[test]
+    fn test_workers_compute_off_the_page_and_reply() {
+        // Given: A worker totalling the carts it is sent
+        let page = page(&[(
+            "https://shop.test/total.js",
+            r#"
+            console.log(`worker ${name} at ${location.href}`);
+            onmessage = e => postMessage({ id: e.data.id, total: e.data.prices.reduce((a, b) => a + b, 0n), self: self === globalThis });
+            "#,
+        )]);
+
+        // When: The page posts two carts and runs until idle
+        page.run_script(
+            r#"
+            globalThis.totals = [];
+            const worker = new Worker("total.js", { name: "totals" });
+            worker.onmessage = e => totals.push(`${e.data.id}=${e.data.total} ${e.data.self} ${e.isTrusted}`);
+            worker.postMessage({ id: "a", prices: [1n, 2n] });
+            worker.postMessage({ id: "b", prices: [10n ** 20n] });
+            "#,
+        )
+        .unwrap();
+        page.run_until_idle().unwrap();
+
+        // Then: Both replies arrived in order, and the worker's log is the page's
+        assert_eq!(page.run_script("totals.join('; ')").unwrap(), "a=3 true true; b=100000000000000000000 true true");
+        let logs = page.console_messages(ConsoleLevel::Log);
+        assert!(logs.iter().any(|log| log == "worker totals at https://shop.test/total.js"), "{:?}", logs);
+    }
+
+    #[test]
+    fn test_workers_replay_the_page_seed_and_frozen_time() {
+        // Given: Pages with the same seed and frozen time, each starting two
+        // workers that report random numbers and the time
+        let run = |seed: u64| {
+            let network = MockNetwork::new().with_response("https://shop.test/dice.js", "postMessage([Math.random(), Math.random(), Date.now()].join());");
+            let page = PageBuilder::new()
+                .with_base_url("https://shop.test/")
+                .with_network(NetworkMode::Custom(Rc::new(network)))
+                .with_seed(seed)
+                .with_frozen_time(1_000.0)
+                .build()
+                .unwrap();
+            page.run_script("globalThis.rolls = []; for (const i of [0, 1]) new Worker('dice.js').onmessage = e => rolls[i] = e.data;").unwrap();
+            page.run_until_idle().unwrap();
+            page.run_script("rolls.join(' / ')").unwrap()
+        };
+
+        // When: Each runs twice, and once with another seed
+        let (first, again, other) = (run(7), run(7), run(8));
+
+        // Then: The same seed replays the same values, distinct per worker,
+        // at the frozen time
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        let workers: Vec<&str> = first.split(" / ").collect();
+        assert_ne!(workers[0], workers[1]);
+        assert!(workers.iter().all(|rolls| rolls.ends_with(",1000")), "{}", first);
+    }
+
+    #[test]
+    fn test_workers_run_under_the_page_limits() {
+        // Given: A page with a script timeout and memory limit, and workers
+        // that spin forever or allocate without end
+        let network = MockNetwork::new()
+            .with_response("https://shop.test/spin.js", "while (true) {}")
+            .with_response("https://shop.test/hoard.js", "onmessage = () => { const hoard = []; for (;;) hoard.push(new Array(1e5).fill(1)); };");
+        let limits = Limits::new().with_script_timeout(Duration::from_millis(200)).with_memory(32 * 1024 * 1024);
+        let page = PageBuilder::new()
+            .with_base_url("https://shop.test/")
+            .with_network(NetworkMode::Custom(Rc::new(network)))
+            .with_limits(limits)
+            .build()
+            .unwrap();
+
+        // When: The page starts the spinning worker
+        let started = Instant::now();
+        let spun = page.run_script("new Worker('spin.js');");
+
+        // Then: The page's script fails on the timeout well before the
+        // worker timeout
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+        assert_eq!(spun, Err(BrowserError::LimitExceeded(LimitKind::ScriptTimeout, "Worker 'https://shop.test/spin.js': Script ran for more than 200ms".to_string())));
+
+        // When: The hoarding worker is sent a message
+        let hoarded = page.run_script("new Worker('hoard.js').postMessage(1);");
+
+        // Then: It fails on the memory limit, and the page still runs scripts
+        assert!(matches!(hoarded, Err(BrowserError::LimitExceeded(LimitKind::Memory, _))), "{:?}", hoarded);
+        assert_eq!(page.run_script("1 + 1").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_worker_errors_and_termination() {
+        // Given: A worker that fails on bad input, one that closes itself,
+        // a missing script and one spinning forever
+        let page = page(&[
+            ("https://shop.test/strict.js", "onmessage = e => { if (!e.data) throw new TypeError('empty cart'); postMessage('ok'); };"),
+            ("https://shop.test/once.js", "onmessage = e => { postMessage('first'); close(); };"),
+            ("https://shop.test/spin.js", "while (true) {}"),
+        ]);
+
+        // When: Each is used, the spinning one terminated right away
+        let started = Instant::now();
+        page.run_script(
+            r#"
+            globalThis.log = [];
+            const strict = new Worker("strict.js");
+            strict.onerror = e => { log.push("error " + e.message); e.preventDefault(); };
+            strict.onmessage = e => log.push(e.data);
+            strict.postMessage(null);
+            strict.postMessage(1);
+            const once = new Worker("once.js");
+            once.onmessage = e => log.push(e.data);
+            once.postMessage(1);
+            once.postMessage(2);
+            new Worker("missing.js").addEventListener("error", e => log.push("missing"));
+            new Worker("spin.js").terminate();
+            try { strict.postMessage(() => {}); } catch (e) { log.push(e.name); }
+            "#,
+        )
+        .unwrap();
+        page.run_until_idle().unwrap();
+
+        // Then: Errors reach onerror, close stops replies and terminate
+        // interrupts the loop
+        assert!(started.elapsed() < Duration::from_secs(4));
+        let log = page.run_script("log.join('; ')").unwrap();
+        assert!(log.starts_with("DataCloneError; "), "{}", log);
+        for expected in ["error TypeError: empty cart", "ok", "first", "missing"] {
+            assert!(log.contains(expected), "{}", log);
+        }
+        assert_eq!(log.matches("first").count(), 1);
+        let errors = page.console_messages(ConsoleLevel::Error);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with("Uncaught Failed to load worker script 'https://shop.test/missing.js'"), "{:?}", errors);
+    }
+}